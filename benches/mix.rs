@@ -0,0 +1,59 @@
+//! ProcessOutput's stereo mix into a slot ring, against the per-frame loop it replaced.
+//! Run with `cargo bench --bench mix`; prints nanoseconds per frame for common IO sizes.
+
+use prism::dsp::mix_stereo_into;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const RUN_TIME: Duration = Duration::from_millis(300);
+
+// The loop ProcessOutput ran before mix_stereo_into.
+unsafe fn mix_per_frame(src: *const f32, dst: *mut f32, frames: usize, mix: [f32; 4]) {
+    for i in 0..frames {
+        let l = *src.add(i * 2);
+        let r = *src.add(i * 2 + 1);
+        dst.add(i * 2).write(mix[0] * l + mix[1] * r);
+        dst.add(i * 2 + 1).write(mix[2] * l + mix[3] * r);
+    }
+}
+
+// Nanoseconds per frame of `body`, repeated for RUN_TIME.
+fn time_per_frame(frames: usize, mut body: impl FnMut()) -> f64 {
+    let start = Instant::now();
+    let mut runs = 0u64;
+    while start.elapsed() < RUN_TIME {
+        body();
+        runs += 1;
+    }
+    start.elapsed().as_nanos() as f64 / (runs as f64 * frames as f64)
+}
+
+fn main() {
+    let mixes = [
+        ("unity", [1.0, 0.0, 0.0, 1.0]),
+        ("pan", [0.8, 0.0, 0.0, 0.6]),
+        ("mono", [0.5, 0.5, 0.5, 0.5]),
+    ];
+    println!(
+        "{:<6} {:>6} {:>12} {:>12}",
+        "mix", "frames", "helper ns", "per-frame ns"
+    );
+    for (name, mix) in mixes {
+        for frames in [64, 512, 4096] {
+            let src: Vec<f32> = (0..frames * 2).map(|n| (n as f32).sin()).collect();
+            let mut dst = vec![0.0f32; frames * 2];
+            let helper = time_per_frame(frames, || unsafe {
+                mix_stereo_into(black_box(src.as_ptr()), dst.as_mut_ptr(), frames, mix);
+                black_box(dst.as_ptr());
+            });
+            let per_frame = time_per_frame(frames, || unsafe {
+                mix_per_frame(black_box(src.as_ptr()), dst.as_mut_ptr(), frames, mix);
+                black_box(dst.as_ptr());
+            });
+            println!(
+                "{:<6} {:>6} {:>12.3} {:>12.3}",
+                name, frames, helper, per_frame
+            );
+        }
+    }
+}
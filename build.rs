@@ -1,4 +1,17 @@
+use std::process::Command;
+
 fn main() {
     println!("cargo:rustc-link-lib=framework=CoreFoundation");
     println!("cargo:rustc-link-lib=framework=CoreServices");
+
+    // Reported by the plug-in's 'pinf' property; absent outside a git checkout
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    if let Some(hash) = hash {
+        println!("cargo:rustc-env=PRISM_GIT_HASH={}", hash.trim());
+    }
 }
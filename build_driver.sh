@@ -1,8 +1,8 @@
 #!/bin/bash
 set -e
 
-# Build the rust project
-cargo build --release
+# Build the rust project (extra arguments go to cargo, e.g. --features trace-io)
+cargo build --release "$@"
 
 # Fix libiconv dependency to use system library instead of Nix store library
 NIX_ICONV=$(otool -L target/release/libprism.dylib | grep libiconv | awk '{print $1}')
@@ -0,0 +1,183 @@
+//! Slow automatic gain control toward a target loudness.
+//!
+//! Runs in prismd on top of the per-bus RMS levels the driver publishes. Loudness
+//! follows the BS.1770 channel sum (-0.691 + 10·log10 Σ mean square) without the
+//! K-weighting pre-filter, which the driver does not apply; it tracks LUFS closely
+//! for broadband program material.
+
+pub const AGC_DEFAULT_RANGE_DB: f32 = 12.0;
+pub const AGC_MAX_RANGE_DB: f32 = 24.0;
+/// Fastest the gain may move, in dB per second.
+pub const AGC_MAX_SPEED_DB: f32 = 1.0;
+/// Blocks quieter than this are treated as silence and hold the gain.
+pub const AGC_GATE_LUFS: f32 = -60.0;
+
+// Time constant of the loudness average.
+const AGC_INTEGRATION_SECS: f32 = 3.0;
+// Smallest gain change worth sending to the driver.
+const AGC_MIN_UPDATE_DB: f32 = 0.1;
+
+/// Loudness of a stereo block from its per-channel RMS levels.
+pub fn loudness_lufs(rms: [f32; 2]) -> f32 {
+    -0.691 + 10.0 * (rms[0] * rms[0] + rms[1] * rms[1]).log10()
+}
+
+#[derive(Debug, Clone)]
+pub struct AgcController {
+    pub target_lufs: f32,
+    pub range_db: f32,
+    /// Gain currently applied, in dB relative to unity.
+    pub gain_db: f32,
+    // Averaged channel-summed mean square, measured after the applied gain
+    mean_square: Option<f32>,
+    // Gain last sent to the driver
+    sent_gain_db: f32,
+}
+
+impl AgcController {
+    pub fn new(target_lufs: f32, range_db: f32) -> Self {
+        Self {
+            target_lufs,
+            range_db: range_db.clamp(0.0, AGC_MAX_RANGE_DB),
+            gain_db: 0.0,
+            mean_square: None,
+            sent_gain_db: 0.0,
+        }
+    }
+
+    /// Averaged loudness, once any non-silent audio has been measured.
+    pub fn loudness(&self) -> Option<f32> {
+        self.mean_square
+            .map(|ms| -0.691 + 10.0 * ms.log10())
+            .filter(|lufs| lufs.is_finite())
+    }
+
+    /// Fold in one post-gain measurement taken `dt` seconds after the previous one.
+    /// Returns the new gain in dB when it moved far enough to be worth applying.
+    pub fn update(&mut self, rms: [f32; 2], dt: f32) -> Option<f32> {
+        if loudness_lufs(rms) < AGC_GATE_LUFS {
+            return None;
+        }
+
+        let block = rms[0] * rms[0] + rms[1] * rms[1];
+        let weight = (dt / AGC_INTEGRATION_SECS).clamp(0.0, 1.0);
+        let ms = match self.mean_square {
+            Some(ms) => ms + (block - ms) * weight,
+            None => block,
+        };
+        self.mean_square = Some(ms);
+
+        let error = self.target_lufs - self.loudness()?;
+        let max_step = AGC_MAX_SPEED_DB * dt;
+        let gain_db =
+            (self.gain_db + error.clamp(-max_step, max_step)).clamp(-self.range_db, self.range_db);
+        // The average was measured at the old gain; rescale it so the next error is fair
+        let delta = gain_db - self.gain_db;
+        self.mean_square = Some(ms * 10f32.powf(delta / 10.0));
+        self.gain_db = gain_db;
+
+        if (gain_db - self.sent_gain_db).abs() >= AGC_MIN_UPDATE_DB {
+            self.sent_gain_db = gain_db;
+            Some(gain_db)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 0.25;
+
+    // Equal per-channel RMS of a block at `lufs`.
+    fn rms_at(lufs: f32) -> [f32; 2] {
+        let rms = (10f32.powf((lufs + 0.691) / 10.0) / 2.0).sqrt();
+        [rms, rms]
+    }
+
+    // Feed `secs` of a steady input at `input_lufs`, measured after the gain the
+    // controller applied, as the driver's meters would report it.
+    fn run(controller: &mut AgcController, input_lufs: f32, secs: f32) {
+        for _ in 0..(secs / DT) as usize {
+            let rms = rms_at(input_lufs + controller.gain_db);
+            controller.update(rms, DT);
+        }
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 0.01,
+            "{} is not close to {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn loudness_sums_both_channels() {
+        assert_close(loudness_lufs(rms_at(-23.0)), -23.0);
+        let [rms, _] = rms_at(-23.0);
+        // One silent channel halves the energy
+        assert_close(loudness_lufs([rms, 0.0]), -23.0 - 10.0 * 2f32.log10());
+    }
+
+    #[test]
+    fn attack_lowers_the_gain_at_most_one_db_per_second() {
+        let mut controller = AgcController::new(-20.0, AGC_DEFAULT_RANGE_DB);
+        let first = controller.update(rms_at(-10.0), DT).unwrap();
+        assert_close(first, -AGC_MAX_SPEED_DB * DT);
+        run(&mut controller, -10.0, 1.0 - DT);
+        assert_close(controller.gain_db, -AGC_MAX_SPEED_DB);
+    }
+
+    #[test]
+    fn release_raises_the_gain_at_most_one_db_per_second() {
+        let mut controller = AgcController::new(-20.0, AGC_DEFAULT_RANGE_DB);
+        let first = controller.update(rms_at(-30.0), DT).unwrap();
+        assert_close(first, AGC_MAX_SPEED_DB * DT);
+        run(&mut controller, -30.0, 1.0 - DT);
+        assert_close(controller.gain_db, AGC_MAX_SPEED_DB);
+    }
+
+    #[test]
+    fn settles_on_the_target() {
+        let mut controller = AgcController::new(-20.0, AGC_DEFAULT_RANGE_DB);
+        run(&mut controller, -26.0, 30.0);
+        assert_close(controller.gain_db, 6.0);
+        assert_close(controller.loudness().unwrap(), -20.0);
+        // On target there is nothing left worth sending
+        assert_eq!(controller.update(rms_at(-20.0), DT), None);
+    }
+
+    #[test]
+    fn gain_stays_within_the_range() {
+        let mut controller = AgcController::new(-20.0, 12.0);
+        run(&mut controller, -40.0, 60.0);
+        assert_close(controller.gain_db, 12.0);
+
+        let mut controller = AgcController::new(-20.0, 12.0);
+        run(&mut controller, 0.0, 60.0);
+        assert_close(controller.gain_db, -12.0);
+
+        assert_eq!(AgcController::new(-20.0, 60.0).range_db, AGC_MAX_RANGE_DB);
+        assert_eq!(AgcController::new(-20.0, -3.0).range_db, 0.0);
+    }
+
+    #[test]
+    fn small_moves_are_applied_but_not_sent() {
+        let mut controller = AgcController::new(-20.0, AGC_DEFAULT_RANGE_DB);
+        assert_eq!(controller.update(rms_at(-20.05), DT), None);
+        assert_close(controller.gain_db, 0.05);
+    }
+
+    #[test]
+    fn silence_holds_the_gain() {
+        let mut controller = AgcController::new(-20.0, AGC_DEFAULT_RANGE_DB);
+        assert_eq!(controller.update([0.0, 0.0], DT), None);
+        assert_eq!(controller.update(rms_at(AGC_GATE_LUFS - 1.0), DT), None);
+        assert_eq!(controller.loudness(), None);
+        assert_eq!(controller.gain_db, 0.0);
+    }
+}
@@ -0,0 +1,108 @@
+//! The LaunchAgent that starts prismd at login and brings it back if it crashes.
+//!
+//! The agent plist lives in ~/Library/LaunchAgents and is loaded into the user's GUI
+//! launchd domain. launchd keeps prismd in the foreground, so its output goes to a log
+//! file (~/Library/Logs/Prism/prismd.log unless told otherwise).
+
+use plist::{Dictionary, Value};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// launchd label of the agent, also the plist's file name.
+pub const AGENT_LABEL: &str = "dev.ichigo.prism.prismd";
+
+pub fn agent_plist_path() -> Result<PathBuf, String> {
+    Ok(home()?
+        .join("Library/LaunchAgents")
+        .join(format!("{}.plist", AGENT_LABEL)))
+}
+
+pub fn default_log_path() -> Result<PathBuf, String> {
+    Ok(home()?.join("Library/Logs/Prism/prismd.log"))
+}
+
+/// Write the agent plist, which runs `program` as `arg0` with `args`, and load it.
+/// An agent already loaded is replaced, so new arguments take effect. Returns the
+/// plist's path.
+pub fn install(program: &Path, arg0: &str, args: &[String], log: &Path) -> Result<PathBuf, String> {
+    let path = agent_plist_path()?;
+    // launchd does not create the directories it is pointed at
+    for dir in [path.parent(), log.parent()].into_iter().flatten() {
+        fs::create_dir_all(dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+    }
+
+    let mut arguments = vec![Value::from(arg0)];
+    arguments.extend(args.iter().map(|arg| Value::from(arg.as_str())));
+    // Restart after a crash, but leave a daemon stopped on purpose alone
+    let mut keep_alive = Dictionary::new();
+    keep_alive.insert("SuccessfulExit".to_string(), Value::from(false));
+
+    let mut agent = Dictionary::new();
+    agent.insert("Label".to_string(), Value::from(AGENT_LABEL));
+    agent.insert(
+        "Program".to_string(),
+        Value::from(program.display().to_string()),
+    );
+    agent.insert("ProgramArguments".to_string(), Value::Array(arguments));
+    agent.insert("RunAtLoad".to_string(), Value::from(true));
+    agent.insert("KeepAlive".to_string(), Value::Dictionary(keep_alive));
+    agent.insert(
+        "StandardOutPath".to_string(),
+        Value::from(log.display().to_string()),
+    );
+    agent.insert(
+        "StandardErrorPath".to_string(),
+        Value::from(log.display().to_string()),
+    );
+
+    // Not loaded yet is fine
+    let _ = launchctl(&["bootout", &service_target()]);
+    Value::Dictionary(agent)
+        .to_file_xml(&path)
+        .map_err(|err| format!("{}: {}", path.display(), err))?;
+    launchctl(&["bootstrap", &domain_target(), &path.display().to_string()])?;
+    Ok(path)
+}
+
+/// Unload the agent and remove its plist. Returns false when there was no agent.
+pub fn uninstall() -> Result<bool, String> {
+    let path = agent_plist_path()?;
+    let loaded = launchctl(&["bootout", &service_target()]).is_ok();
+    match fs::remove_file(&path) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(loaded),
+        Err(err) => Err(format!("{}: {}", path.display(), err)),
+    }
+}
+
+fn home() -> Result<PathBuf, String> {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| "HOME is not set".to_string())
+}
+
+fn domain_target() -> String {
+    format!("gui/{}", unsafe { libc::getuid() })
+}
+
+fn service_target() -> String {
+    format!("{}/{}", domain_target(), AGENT_LABEL)
+}
+
+fn launchctl(args: &[&str]) -> Result<(), String> {
+    let output = Command::new("/bin/launchctl")
+        .args(args)
+        .output()
+        .map_err(|err| format!("launchctl: {}", err))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(format!(
+        "launchctl {} failed: {}",
+        args[0],
+        String::from_utf8_lossy(&output.stderr).trim()
+    ))
+}
@@ -0,0 +1,704 @@
+//! Shadow host for the Prism driver.
+//!
+//! Loads the driver in-process through `AudioServerPlugInMain`, hands it a fake
+//! host interface and lets you query or set properties from a REPL. Property
+//! notifications and configuration-change requests are printed as they happen.
+//! Nothing here touches coreaudiod, so non-IO features can be tried with a plain
+//! `cargo run --bin prism-shadow` instead of installing the bundle.
+
+#![allow(non_upper_case_globals)]
+
+use clap::Parser;
+use core_foundation::base::TCFType;
+use core_foundation::data::{CFData, CFDataRef};
+use core_foundation::string::{CFString, CFStringRef};
+use coreaudio_sys::*;
+use plist::Value;
+use std::ffi::c_void;
+use std::io::{self, BufRead, Cursor, Write};
+use std::ptr;
+use std::sync::Mutex;
+
+const DEVICE_ID: AudioObjectID = 2;
+const INPUT_STREAM_ID: AudioObjectID = 3;
+const OUTPUT_STREAM_ID: AudioObjectID = 4;
+
+#[derive(Parser)]
+#[command(
+    name = "prism-shadow",
+    about = "Load the Prism driver in-process and poke at its properties"
+)]
+struct Cli {
+    /// Run a command and exit (may be repeated)
+    #[arg(short = 'c', long = "command", value_name = "CMD")]
+    commands: Vec<String>,
+}
+
+// Mirrors the driver's view of AudioServerPlugInClientInfo, which carries the bundle ID.
+#[repr(C)]
+#[allow(non_snake_case)]
+struct ShadowClientInfo {
+    mClientID: UInt32,
+    mProcessID: pid_t,
+    mIsNativeEndian: Boolean,
+    mBundleID: CFStringRef,
+}
+
+// Configuration changes the driver asked for; performed after the current command,
+// the way the HAL defers them off the requesting thread.
+static PENDING_CHANGES: Mutex<Vec<(AudioObjectID, u64, usize)>> = Mutex::new(Vec::new());
+
+static HOST: AudioServerPlugInHostInterface = AudioServerPlugInHostInterface {
+    PropertiesChanged: Some(host_properties_changed),
+    CopyFromStorage: Some(host_copy_from_storage),
+    WriteToStorage: Some(host_write_to_storage),
+    DeleteFromStorage: Some(host_delete_from_storage),
+    RequestDeviceConfigurationChange: Some(host_request_configuration_change),
+};
+
+unsafe extern "C" fn host_properties_changed(
+    _host: AudioServerPlugInHostRef,
+    object_id: AudioObjectID,
+    count: UInt32,
+    addresses: *const AudioObjectPropertyAddress,
+) -> OSStatus {
+    if addresses.is_null() {
+        return 0;
+    }
+    for address in std::slice::from_raw_parts(addresses, count as usize) {
+        println!(
+            "~ changed object={} '{}' scope='{}' element={}",
+            object_id,
+            fourcc(address.mSelector),
+            fourcc(address.mScope),
+            address.mElement
+        );
+    }
+    0
+}
+
+unsafe extern "C" fn host_copy_from_storage(
+    _host: AudioServerPlugInHostRef,
+    _key: CFStringRef,
+    out_data: *mut CFPropertyListRef,
+) -> OSStatus {
+    if !out_data.is_null() {
+        *out_data = ptr::null();
+    }
+    kAudioHardwareUnknownPropertyError as OSStatus
+}
+
+unsafe extern "C" fn host_write_to_storage(
+    _host: AudioServerPlugInHostRef,
+    _key: CFStringRef,
+    _data: CFPropertyListRef,
+) -> OSStatus {
+    0
+}
+
+unsafe extern "C" fn host_delete_from_storage(
+    _host: AudioServerPlugInHostRef,
+    _key: CFStringRef,
+) -> OSStatus {
+    0
+}
+
+unsafe extern "C" fn host_request_configuration_change(
+    _host: AudioServerPlugInHostRef,
+    device_id: AudioObjectID,
+    action: UInt64,
+    info: *mut c_void,
+) -> OSStatus {
+    println!(
+        "~ configuration change requested: device={} action={}",
+        device_id, action
+    );
+    if let Ok(mut pending) = PENDING_CHANGES.lock() {
+        pending.push((device_id, action, info as usize));
+    }
+    0
+}
+
+struct Shadow {
+    driver: AudioServerPlugInDriverRef,
+}
+
+impl Shadow {
+    fn load() -> Result<Self, String> {
+        let driver =
+            prism::AudioServerPlugInMain(ptr::null(), ptr::null()) as AudioServerPlugInDriverRef;
+        if driver.is_null() {
+            return Err("AudioServerPlugInMain returned NULL".to_string());
+        }
+        let shadow = Shadow { driver };
+        let initialize = shadow.vtable().Initialize.ok_or("Initialize missing")?;
+        check(unsafe { initialize(driver, &HOST) }, "Initialize")?;
+        Ok(shadow)
+    }
+
+    fn vtable(&self) -> &AudioServerPlugInDriverInterface {
+        unsafe { &**self.driver }
+    }
+
+    /// Drop the reference and load a fresh instance, as coreaudiod does on reload.
+    fn reset(&mut self) -> Result<(), String> {
+        if let Some(release) = self.vtable().Release {
+            let remaining = unsafe { release(self.driver as *mut c_void) };
+            println!("released driver ({} references left)", remaining);
+        }
+        *self = Shadow::load()?;
+        Ok(())
+    }
+
+    fn has(&self, object_id: AudioObjectID, address: &AudioObjectPropertyAddress) -> bool {
+        let has_property = self.vtable().HasProperty.expect("HasProperty missing");
+        unsafe { has_property(self.driver, object_id, 0, address) != 0 }
+    }
+
+    fn settable(
+        &self,
+        object_id: AudioObjectID,
+        address: &AudioObjectPropertyAddress,
+    ) -> Result<bool, String> {
+        let is_settable = self
+            .vtable()
+            .IsPropertySettable
+            .ok_or("IsPropertySettable missing")?;
+        let mut settable: Boolean = 0;
+        check(
+            unsafe { is_settable(self.driver, object_id, 0, address, &mut settable) },
+            "IsPropertySettable",
+        )?;
+        Ok(settable != 0)
+    }
+
+    fn get(
+        &self,
+        object_id: AudioObjectID,
+        address: &AudioObjectPropertyAddress,
+    ) -> Result<Vec<u8>, String> {
+        let vtable = self.vtable();
+        let get_size = vtable
+            .GetPropertyDataSize
+            .ok_or("GetPropertyDataSize missing")?;
+        let get_data = vtable.GetPropertyData.ok_or("GetPropertyData missing")?;
+
+        let mut size: UInt32 = 0;
+        check(
+            unsafe {
+                get_size(
+                    self.driver,
+                    object_id,
+                    0,
+                    address,
+                    0,
+                    ptr::null(),
+                    &mut size,
+                )
+            },
+            "GetPropertyDataSize",
+        )?;
+        let mut buf = vec![0u8; size as usize];
+        let mut used: UInt32 = 0;
+        check(
+            unsafe {
+                get_data(
+                    self.driver,
+                    object_id,
+                    0,
+                    address,
+                    0,
+                    ptr::null(),
+                    size,
+                    &mut used,
+                    buf.as_mut_ptr() as *mut c_void,
+                )
+            },
+            "GetPropertyData",
+        )?;
+        buf.truncate(used as usize);
+        Ok(buf)
+    }
+
+    fn set(
+        &self,
+        object_id: AudioObjectID,
+        address: &AudioObjectPropertyAddress,
+        data: &[u8],
+    ) -> Result<(), String> {
+        let set_data = self
+            .vtable()
+            .SetPropertyData
+            .ok_or("SetPropertyData missing")?;
+        check(
+            unsafe {
+                set_data(
+                    self.driver,
+                    object_id,
+                    0,
+                    address,
+                    0,
+                    ptr::null(),
+                    data.len() as UInt32,
+                    data.as_ptr() as *const c_void,
+                )
+            },
+            "SetPropertyData",
+        )
+    }
+
+    fn client(&self, add: bool, client_id: u32, pid: i32, bundle_id: &str) -> Result<(), String> {
+        let bundle = CFString::new(bundle_id);
+        let info = ShadowClientInfo {
+            mClientID: client_id,
+            mProcessID: pid,
+            mIsNativeEndian: 1,
+            mBundleID: if bundle_id.is_empty() {
+                ptr::null()
+            } else {
+                bundle.as_concrete_TypeRef()
+            },
+        };
+        let info = &info as *const ShadowClientInfo as *const AudioServerPlugInClientInfo;
+        let vtable = self.vtable();
+        let status = if add {
+            let add_client = vtable.AddDeviceClient.ok_or("AddDeviceClient missing")?;
+            unsafe { add_client(self.driver, DEVICE_ID, info) }
+        } else {
+            let remove_client = vtable
+                .RemoveDeviceClient
+                .ok_or("RemoveDeviceClient missing")?;
+            unsafe { remove_client(self.driver, DEVICE_ID, info) }
+        };
+        check(
+            status,
+            if add {
+                "AddDeviceClient"
+            } else {
+                "RemoveDeviceClient"
+            },
+        )
+    }
+
+    fn perform_pending_changes(&self) {
+        let pending: Vec<_> = match PENDING_CHANGES.lock() {
+            Ok(mut pending) => pending.drain(..).collect(),
+            Err(_) => return,
+        };
+        let Some(perform) = self.vtable().PerformDeviceConfigurationChange else {
+            return;
+        };
+        for (device_id, action, info) in pending {
+            let status = unsafe { perform(self.driver, device_id, action, info as *mut c_void) };
+            println!(
+                "~ performed configuration change action={} -> {}",
+                action,
+                status_text(status)
+            );
+        }
+    }
+}
+
+fn check(status: OSStatus, call: &str) -> Result<(), String> {
+    if status == 0 {
+        Ok(())
+    } else {
+        Err(format!("{} failed: {}", call, status_text(status)))
+    }
+}
+
+fn status_text(status: OSStatus) -> String {
+    let code = fourcc(status as u32);
+    if code.chars().all(|c| c.is_ascii_graphic() || c == ' ') {
+        format!("'{}' ({})", code, status)
+    } else {
+        status.to_string()
+    }
+}
+
+fn fourcc(value: u32) -> String {
+    value
+        .to_be_bytes()
+        .iter()
+        .map(|b| {
+            let c = *b as char;
+            if c.is_ascii_graphic() || c == ' ' {
+                c
+            } else {
+                '?'
+            }
+        })
+        .collect()
+}
+
+/// Accepts a four-char code (`stat`, `'uid '`), hex (`0x73746174`) or decimal.
+fn parse_code(text: &str) -> Result<u32, String> {
+    let trimmed = text.trim_matches('\'');
+    if let Some(hex) = trimmed
+        .strip_prefix("0x")
+        .or_else(|| trimmed.strip_prefix("0X"))
+    {
+        return u32::from_str_radix(hex, 16).map_err(|err| format!("{}: {}", text, err));
+    }
+    if let Ok(value) = trimmed.parse::<u32>() {
+        return Ok(value);
+    }
+    if !trimmed.is_ascii() || trimmed.is_empty() || trimmed.len() > 4 {
+        return Err(format!("'{}' is not a four-char code", text));
+    }
+    let mut bytes = [b' '; 4];
+    bytes[..trimmed.len()].copy_from_slice(trimmed.as_bytes());
+    Ok(u32::from_be_bytes(bytes))
+}
+
+fn parse_object(text: &str) -> Result<AudioObjectID, String> {
+    match text {
+        "plugin" => Ok(kAudioObjectPlugInObject),
+        "device" => Ok(DEVICE_ID),
+        "input" => Ok(INPUT_STREAM_ID),
+        "output" => Ok(OUTPUT_STREAM_ID),
+        _ => text
+            .parse::<AudioObjectID>()
+            .map_err(|_| format!("Unknown object '{}'", text)),
+    }
+}
+
+/// `SEL[:SCOPE[:ELEMENT]]`, e.g. `stat`, `vold:inpt:1`.
+fn parse_address(text: &str) -> Result<AudioObjectPropertyAddress, String> {
+    let mut parts = text.split(':');
+    let selector = parse_code(parts.next().unwrap_or_default())?;
+    let scope = match parts.next() {
+        Some(scope) => parse_code(scope)?,
+        None => kAudioObjectPropertyScopeGlobal,
+    };
+    let element = match parts.next() {
+        Some(element) => element
+            .parse::<u32>()
+            .map_err(|_| format!("Invalid element '{}'", element))?,
+        None => kAudioObjectPropertyElementMaster,
+    };
+    Ok(AudioObjectPropertyAddress {
+        mSelector: selector,
+        mScope: scope,
+        mElement: element,
+    })
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    U32,
+    F32,
+    F64,
+    Str,
+    Plist,
+    Ids,
+    Custom,
+    Hex,
+}
+
+impl Kind {
+    fn parse(text: &str) -> Result<Kind, String> {
+        match text {
+            "u32" => Ok(Kind::U32),
+            "f32" => Ok(Kind::F32),
+            "f64" => Ok(Kind::F64),
+            "str" => Ok(Kind::Str),
+            "plist" => Ok(Kind::Plist),
+            "ids" => Ok(Kind::Ids),
+            "cust" => Ok(Kind::Custom),
+            "hex" | "data" => Ok(Kind::Hex),
+            _ => Err(format!("Unknown kind '{}'", text)),
+        }
+    }
+
+    // Best guess for selectors whose shape is known; everything else is sized up.
+    fn guess(selector: u32, size: usize) -> Kind {
+        match &fourcc(selector)[..] {
+            "lnam" | "lmak" | "uid " | "muid" | "tlmy" => Kind::Str,
+            "clnt" | "stat" | "metr" | "cact" | "rsyn" | "evnt" | "logl" | "clr " | "rate"
+            | "cnfg" | "pinf" => Kind::Plist,
+            "nsrt" => Kind::F64,
+            "lcsv" | "lcdv" | "vols" | "vold" => Kind::F32,
+            "owno" | "stm#" | "ctrl" | "dev#" => Kind::Ids,
+            "cust" => Kind::Custom,
+            _ if size == 4 => Kind::U32,
+            _ => Kind::Hex,
+        }
+    }
+}
+
+fn print_value(data: &[u8], kind: Kind) -> Result<(), String> {
+    let word = |chunk: &[u8]| u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    match kind {
+        Kind::U32 if data.len() == 4 => {
+            let value = word(data);
+            println!("{} ('{}', 0x{:08X})", value, fourcc(value), value);
+        }
+        Kind::F32 if data.len() == 4 => {
+            println!("{}", f32::from_ne_bytes(data.try_into().unwrap()))
+        }
+        Kind::F64 if data.len() == 8 => {
+            println!("{}", f64::from_ne_bytes(data.try_into().unwrap()))
+        }
+        Kind::Ids => {
+            let ids: Vec<String> = data.chunks_exact(4).map(|c| word(c).to_string()).collect();
+            println!("[{}]", ids.join(", "));
+        }
+        Kind::Custom => {
+            for entry in data.chunks_exact(12) {
+                println!(
+                    "'{}' type='{}' qualifier='{}'",
+                    fourcc(word(&entry[0..4])),
+                    fourcc(word(&entry[4..8])),
+                    fourcc(word(&entry[8..12]))
+                );
+            }
+        }
+        Kind::Str | Kind::Plist if data.len() == std::mem::size_of::<usize>() => {
+            let cf_ref = usize::from_ne_bytes(data.try_into().unwrap()) as *const c_void;
+            if cf_ref.is_null() {
+                println!("(null)");
+            } else if kind == Kind::Str {
+                let string = unsafe { CFString::wrap_under_create_rule(cf_ref as CFStringRef) };
+                println!("\"{}\"", string);
+            } else {
+                let cfdata = unsafe { CFData::wrap_under_create_rule(cf_ref as CFDataRef) };
+                let value = Value::from_reader(Cursor::new(cfdata.bytes().to_vec()))
+                    .map_err(|err| format!("Failed to decode plist: {}", err))?;
+                value
+                    .to_writer_xml(io::stdout())
+                    .map_err(|err| err.to_string())?;
+                println!();
+            }
+        }
+        _ => {
+            let hex: Vec<String> = data.iter().map(|b| format!("{:02x}", b)).collect();
+            println!("{} bytes: {}", data.len(), hex.join(" "));
+        }
+    }
+    Ok(())
+}
+
+// Holds any CF object backing an encoded value until SetPropertyData returns.
+enum Encoded {
+    Bytes(Vec<u8>),
+    Data(CFData),
+    String(CFString),
+}
+
+impl Encoded {
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            Encoded::Bytes(bytes) => bytes.clone(),
+            Encoded::Data(data) => (data.as_concrete_TypeRef() as usize).to_ne_bytes().to_vec(),
+            Encoded::String(string) => (string.as_concrete_TypeRef() as usize)
+                .to_ne_bytes()
+                .to_vec(),
+        }
+    }
+}
+
+fn encode_value(kind: Kind, args: &[&str]) -> Result<Encoded, String> {
+    let single = || -> Result<&str, String> {
+        match args {
+            [value] => Ok(value),
+            _ => Err("Expected exactly one value".to_string()),
+        }
+    };
+    let parse_err = |err: &dyn std::fmt::Display| err.to_string();
+    match kind {
+        Kind::U32 => Ok(Encoded::Bytes(
+            parse_code(single()?)?.to_ne_bytes().to_vec(),
+        )),
+        Kind::F32 => Ok(Encoded::Bytes(
+            single()?
+                .parse::<f32>()
+                .map_err(|err| parse_err(&err))?
+                .to_ne_bytes()
+                .to_vec(),
+        )),
+        Kind::F64 => Ok(Encoded::Bytes(
+            single()?
+                .parse::<f64>()
+                .map_err(|err| parse_err(&err))?
+                .to_ne_bytes()
+                .to_vec(),
+        )),
+        Kind::Str => Ok(Encoded::String(CFString::new(&args.join(" ")))),
+        Kind::Hex => {
+            let digits: String = args.concat();
+            if digits.len() % 2 != 0 {
+                return Err("Hex data needs an even number of digits".to_string());
+            }
+            let bytes = (0..digits.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|err| parse_err(&err)))
+                .collect::<Result<Vec<u8>, String>>()?;
+            Ok(Encoded::Data(CFData::from_buffer(&bytes)))
+        }
+        Kind::Plist => {
+            let mut dict = plist::Dictionary::new();
+            for pair in args {
+                let (key, value) = pair
+                    .split_once('=')
+                    .ok_or_else(|| format!("Expected KEY=VALUE, got '{}'", pair))?;
+                let value = match value.parse::<i64>() {
+                    Ok(number) => Value::from(number),
+                    Err(_) => Value::from(value),
+                };
+                dict.insert(key.to_string(), value);
+            }
+            let mut buf = Vec::new();
+            plist::to_writer_binary(&mut buf, &Value::Dictionary(dict))
+                .map_err(|err| format!("Failed to encode plist: {}", err))?;
+            Ok(Encoded::Data(CFData::from_buffer(&buf)))
+        }
+        Kind::Ids | Kind::Custom => Err("That kind cannot be set".to_string()),
+    }
+}
+
+const HELP: &str = "\
+Objects: plugin (1), device (2), input (3), output (4), or any numeric ID.
+Addresses: SEL[:SCOPE[:ELEMENT]], e.g. stat, vold:inpt:1, 0x73746174.
+
+  has OBJ ADDR                      HasProperty / IsPropertySettable
+  get OBJ ADDR [KIND]               read and decode (KIND: u32 f32 f64 str plist ids cust hex)
+  set OBJ ADDR KIND VALUE...        write; plist takes KEY=VALUE pairs, hex takes bytes
+  route PID OFFSET [GAIN [PAN [WIDTH]]]
+                                    set 'rout' for a client PID (linear gain, NaN keeps)
+  client add ID PID [BUNDLE]        simulate AddDeviceClient
+  client remove ID PID              simulate RemoveDeviceClient
+  reset                             release the driver and load a fresh instance
+  help | quit";
+
+fn run_command(shadow: &mut Shadow, line: &str) -> Result<bool, String> {
+    let args: Vec<&str> = line.split_whitespace().collect();
+    match args.as_slice() {
+        [] => {}
+        ["quit"] | ["exit"] => return Ok(false),
+        ["help"] | ["?"] => println!("{}", HELP),
+        ["reset"] => shadow.reset()?,
+        ["has", object, address] => {
+            let object_id = parse_object(object)?;
+            let address = parse_address(address)?;
+            if shadow.has(object_id, &address) {
+                let settable = shadow.settable(object_id, &address)?;
+                println!("yes ({})", if settable { "settable" } else { "read-only" });
+            } else {
+                println!("no");
+            }
+        }
+        ["get", object, address, rest @ ..] => {
+            let address = parse_address(address)?;
+            let data = shadow.get(parse_object(object)?, &address)?;
+            let kind = match rest {
+                [] => Kind::guess(address.mSelector, data.len()),
+                [kind] => Kind::parse(kind)?,
+                _ => return Err("Usage: get OBJ ADDR [KIND]".to_string()),
+            };
+            print_value(&data, kind)?;
+        }
+        ["set", object, address, kind, values @ ..] if !values.is_empty() => {
+            let encoded = encode_value(Kind::parse(kind)?, values)?;
+            shadow.set(
+                parse_object(object)?,
+                &parse_address(address)?,
+                &encoded.bytes(),
+            )?;
+            println!("ok");
+        }
+        ["route", pid, offset, mix @ ..] if mix.len() <= 3 => {
+            let pid = pid
+                .parse::<i32>()
+                .map_err(|_| format!("Invalid PID '{}'", pid))?;
+            let offset = offset
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid offset '{}'", offset))?;
+            let mut payload = Vec::new();
+            payload.extend_from_slice(&pid.to_le_bytes());
+            payload.extend_from_slice(&offset.to_le_bytes());
+            // Gain and pan travel together; width is an optional third field
+            let mut values = mix
+                .iter()
+                .map(|value| value.parse::<f32>().map_err(|err| err.to_string()))
+                .collect::<Result<Vec<f32>, String>>()?;
+            if values.len() == 1 {
+                values.push(f32::NAN);
+            }
+            for value in values {
+                payload.extend_from_slice(&value.to_le_bytes());
+            }
+            let encoded = Encoded::Data(CFData::from_buffer(&payload));
+            shadow.set(DEVICE_ID, &parse_address("rout")?, &encoded.bytes())?;
+            println!("ok");
+        }
+        ["client", action @ ("add" | "remove"), id, pid, bundle @ ..] if bundle.len() <= 1 => {
+            let client_id = id
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid client ID '{}'", id))?;
+            let pid = pid
+                .parse::<i32>()
+                .map_err(|_| format!("Invalid PID '{}'", pid))?;
+            let bundle_id = bundle.first().copied().unwrap_or_default();
+            shadow.client(*action == "add", client_id, pid, bundle_id)?;
+            println!("ok");
+        }
+        _ => {
+            return Err(format!(
+                "Unrecognised command '{}' (try 'help')",
+                line.trim()
+            ))
+        }
+    }
+    shadow.perform_pending_changes();
+    Ok(true)
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let mut shadow = match Shadow::load() {
+        Ok(shadow) => shadow,
+        Err(err) => {
+            eprintln!("Failed to load driver: {}", err);
+            std::process::exit(1);
+        }
+    };
+    shadow.perform_pending_changes();
+
+    if !cli.commands.is_empty() {
+        let mut failed = false;
+        for command in &cli.commands {
+            match run_command(&mut shadow, command) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(err) => {
+                    eprintln!("error: {}", err);
+                    failed = true;
+                }
+            }
+        }
+        std::process::exit(if failed { 1 } else { 0 });
+    }
+
+    let interactive = unsafe { libc::isatty(libc::STDIN_FILENO) } == 1;
+    if interactive {
+        println!("Prism shadow host. Type 'help' for commands.");
+    }
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        if interactive {
+            print!("prism> ");
+            let _ = io::stdout().flush();
+        }
+        let Some(Ok(line)) = lines.next() else {
+            break;
+        };
+        match run_command(&mut shadow, &line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => eprintln!("error: {}", err),
+        }
+    }
+}
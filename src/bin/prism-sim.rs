@@ -0,0 +1,495 @@
+//! Virtual client simulator for the Prism driver.
+//!
+//! Starts N worker processes, each its own HAL client of the Prism device. Every worker
+//! plays a sine tone at a frequency of its own and captures the input stream, then
+//! reports how strongly its tone came back on each channel pair and how regular its IO
+//! cycles were. With `--route` client i is routed to channels 3-4 + 2i (through the
+//! driver's 'rout' property, so prismd need not run) and the simulator checks that each
+//! tone arrived on its pair at the expected level and nowhere else on the bus. The exit
+//! status says whether every check passed, so it can run unattended on a machine with
+//! the driver installed.
+
+#![allow(non_upper_case_globals)]
+
+use clap::Parser;
+use coreaudio_sys::*;
+use prism::host::{self, locate_channel};
+use prism::ipc::db_to_linear;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::f64::consts::TAU;
+use std::ffi::c_void;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+const TONE_DB: f32 = -12.0; // Level every client plays its tone at
+const TONE_TOLERANCE_DB: f32 = 1.5; // How far a routed tone may land from its expected level
+const LEAK_DB: f32 = -60.0; // A tone below this on a pair counts as absent
+const LATE_CYCLE_RATIO: f64 = 1.5; // A cycle later than this many buffers counts as a gap
+const MAX_LATE_FRACTION: f64 = 0.01; // Share of late cycles a client may see and still pass
+
+#[derive(Parser)]
+#[command(
+    name = "prism-sim",
+    about = "Play test tones into Prism from several clients and check where they land"
+)]
+struct Cli {
+    /// Number of simulated clients, each a separate process
+    #[arg(short = 'n', long, default_value_t = 4)]
+    clients: usize,
+    /// How long each client plays, in seconds
+    #[arg(short = 't', long, default_value_t = 3.0)]
+    seconds: f64,
+    /// Capture ignored at the start of each client's run, while routes settle
+    #[arg(long, value_name = "MS", default_value_t = 500)]
+    settle_ms: u64,
+    /// Tone of the first client in Hz
+    #[arg(long, value_name = "HZ", default_value_t = 440.0)]
+    base_hz: f64,
+    /// Spacing between the clients' tones in Hz
+    #[arg(long, value_name = "HZ", default_value_t = 110.0)]
+    step_hz: f64,
+    /// Route client i to channels 3-4 + 2i and check each tone arrives there
+    #[arg(long)]
+    route: bool,
+    /// Gain applied with --route, in dB
+    #[arg(
+        long,
+        value_name = "DB",
+        default_value_t = 0.0,
+        allow_hyphen_values = true
+    )]
+    gain_db: f32,
+    /// Print the reports as JSON
+    #[arg(long)]
+    json: bool,
+    /// Run as one simulated client playing HZ (used by the simulator itself)
+    #[arg(long, value_name = "HZ", hide = true)]
+    worker: Option<f64>,
+}
+
+/// What one client heard, printed by the worker as a JSON line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WorkerReport {
+    pid: u32,
+    freq_hz: f64,
+    cycles: u64,
+    late_cycles: u64,
+    max_interval_ms: f64,
+    analyzed_frames: u64,
+    pairs: Vec<PairReport>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PairReport {
+    channel_offset: u32,
+    /// Level of the client's own tone on the pair, the louder channel.
+    tone_db: f32,
+    /// Overall RMS level of the pair.
+    level_db: f32,
+}
+
+/// A worker's report with the verdict of the parent's checks.
+#[derive(Debug, Clone, Serialize)]
+struct ClientResult {
+    index: usize,
+    expected_offset: Option<u32>,
+    /// Bus pair (from 3-4 up) the tone was loudest on, if it reached the bus at all.
+    landed_offset: Option<u32>,
+    report: Option<WorkerReport>,
+    failures: Vec<String>,
+}
+
+// State the IOProc owns while it runs. The main thread only locks it after
+// AudioDeviceStop, so the IOProc's try_lock never contends.
+struct Analysis {
+    sample_rate: f64,
+    settle_frames: f64,
+    freq_hz: f64,
+    amplitude: f32,
+    start: Option<f64>,
+    last_input_time: Option<f64>,
+    cycles: u64,
+    late_cycles: u64,
+    max_interval_frames: f64,
+    frames: u64,
+    // Per input channel: correlation with the tone (in phase, quadrature) and energy
+    in_phase: Vec<f64>,
+    quadrature: Vec<f64>,
+    energy: Vec<f64>,
+}
+
+unsafe extern "C" fn sim_proc(
+    _device: AudioObjectID,
+    _now: *const AudioTimeStamp,
+    input: *const AudioBufferList,
+    input_time: *const AudioTimeStamp,
+    output: *mut AudioBufferList,
+    output_time: *const AudioTimeStamp,
+    client_data: *mut c_void,
+) -> OSStatus {
+    let state = &*(client_data as *const Mutex<Analysis>);
+    let Ok(mut analysis) = state.try_lock() else {
+        return 0;
+    };
+    let omega = TAU * analysis.freq_hz / analysis.sample_rate;
+
+    // The tone follows the output sample time, so it stays continuous across cycles
+    if !output.is_null() && !output_time.is_null() {
+        let start = (*output_time).mSampleTime;
+        for channel in 0..2 {
+            let Some((data, index, stride, frames)) = locate_channel(output, channel) else {
+                continue;
+            };
+            for f in 0..frames {
+                let phase = (omega * (start + f as f64)) % TAU;
+                *data.add(f * stride + index) = analysis.amplitude * phase.sin() as f32;
+            }
+        }
+    }
+
+    if input.is_null() || input_time.is_null() {
+        return 0;
+    }
+    let Some((_, _, _, frames)) = locate_channel(input, 0) else {
+        return 0;
+    };
+    let now = (*input_time).mSampleTime;
+    analysis.cycles += 1;
+    let start = *analysis.start.get_or_insert(now);
+    if let Some(last) = analysis.last_input_time.replace(now) {
+        let gap = now - last;
+        if gap > frames as f64 * LATE_CYCLE_RATIO {
+            analysis.late_cycles += 1;
+        }
+        analysis.max_interval_frames = analysis.max_interval_frames.max(gap);
+    }
+    if now - start < analysis.settle_frames {
+        return 0;
+    }
+
+    // Correlate every channel with the tone at the input sample time. Other clients'
+    // tones and any phase offset average out over the run
+    let channels = analysis.energy.len();
+    for channel in 0..channels {
+        let Some((data, index, stride, count)) = locate_channel(input, channel as u32) else {
+            continue;
+        };
+        let (mut i_sum, mut q_sum, mut e_sum) = (0.0, 0.0, 0.0);
+        for f in 0..count.min(frames) {
+            let x = f64::from(*data.add(f * stride + index));
+            let phase = (omega * (now + f as f64)) % TAU;
+            i_sum += x * phase.cos();
+            q_sum += x * phase.sin();
+            e_sum += x * x;
+        }
+        analysis.in_phase[channel] += i_sum;
+        analysis.quadrature[channel] += q_sum;
+        analysis.energy[channel] += e_sum;
+    }
+    analysis.frames += frames as u64;
+    0
+}
+
+fn to_db(level: f64) -> f32 {
+    if level > 0.0 {
+        (20.0 * level.log10()) as f32
+    } else {
+        f32::NEG_INFINITY
+    }
+}
+
+// Play `freq_hz` on the Prism device for the run, capturing all the while, and print
+// the report. "ready" goes out first, once IO runs, so the parent can route the client.
+fn run_worker(freq_hz: f64, seconds: f64, settle_ms: u64) -> Result<(), String> {
+    let device_id = host::find_prism_device()?;
+    let channels = host::channel_count(device_id, kAudioObjectPropertyScopeInput)? as usize;
+    let sample_rate = host::device_sample_rate(device_id).unwrap_or(48_000.0);
+
+    let state = Box::new(Mutex::new(Analysis {
+        sample_rate,
+        settle_frames: settle_ms as f64 * sample_rate / 1000.0,
+        freq_hz,
+        amplitude: db_to_linear(TONE_DB),
+        start: None,
+        last_input_time: None,
+        cycles: 0,
+        late_cycles: 0,
+        max_interval_frames: 0.0,
+        frames: 0,
+        in_phase: vec![0.0; channels],
+        quadrature: vec![0.0; channels],
+        energy: vec![0.0; channels],
+    }));
+    let client_data = &*state as *const Mutex<Analysis> as *mut c_void;
+    let mut proc_id: AudioDeviceIOProcID = None;
+    let status =
+        unsafe { AudioDeviceCreateIOProcID(device_id, Some(sim_proc), client_data, &mut proc_id) };
+    if status != 0 {
+        return Err(format!(
+            "AudioDeviceCreateIOProcID failed with status {}",
+            status
+        ));
+    }
+    let status = unsafe { AudioDeviceStart(device_id, proc_id) };
+    if status != 0 {
+        unsafe { AudioDeviceDestroyIOProcID(device_id, proc_id) };
+        return Err(format!("AudioDeviceStart failed with status {}", status));
+    }
+    println!("ready");
+    let _ = std::io::stdout().flush();
+
+    thread::sleep(Duration::from_secs_f64(seconds));
+    // Destroying the IOProc waits for a cycle in flight, so `state` outlives it
+    unsafe {
+        AudioDeviceStop(device_id, proc_id);
+        AudioDeviceDestroyIOProcID(device_id, proc_id);
+    }
+
+    let analysis = state
+        .lock()
+        .map_err(|_| "analysis state poisoned".to_string())?;
+    let frames = analysis.frames.max(1) as f64;
+    let pairs = (0..channels / 2)
+        .map(|pair| {
+            let tone = (pair * 2..pair * 2 + 2)
+                .map(|ch| 2.0 * analysis.in_phase[ch].hypot(analysis.quadrature[ch]) / frames)
+                .fold(0.0, f64::max);
+            let energy = analysis.energy[pair * 2] + analysis.energy[pair * 2 + 1];
+            PairReport {
+                channel_offset: (pair * 2) as u32,
+                tone_db: to_db(tone),
+                level_db: to_db((energy / (2.0 * frames)).sqrt()),
+            }
+        })
+        .collect();
+    let report = WorkerReport {
+        pid: std::process::id(),
+        freq_hz,
+        cycles: analysis.cycles,
+        late_cycles: analysis.late_cycles,
+        max_interval_ms: analysis.max_interval_frames * 1000.0 / sample_rate,
+        analyzed_frames: analysis.frames,
+        pairs,
+    };
+    println!(
+        "{}",
+        serde_json::to_string(&report).map_err(|err| err.to_string())?
+    );
+    Ok(())
+}
+
+fn spawn_worker(
+    cli: &Cli,
+    freq_hz: f64,
+) -> Result<(Child, BufReader<std::process::ChildStdout>), String> {
+    let exe = env::current_exe().map_err(|err| format!("cannot locate prism-sim: {}", err))?;
+    let mut child = Command::new(exe)
+        .arg("--worker")
+        .arg(freq_hz.to_string())
+        .arg("--seconds")
+        .arg(cli.seconds.to_string())
+        .arg("--settle-ms")
+        .arg(cli.settle_ms.to_string())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("cannot start a client: {}", err))?;
+    let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+    let mut line = String::new();
+    if stdout.read_line(&mut line).is_err() || line.trim() != "ready" {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(format!("client playing {} Hz did not start", freq_hz));
+    }
+    Ok((child, stdout))
+}
+
+// Check a report against what the run expected of it.
+fn check(result: &mut ClientResult, cli: &Cli) {
+    let Some(report) = result.report.clone() else {
+        result.failures.push("no report".to_string());
+        return;
+    };
+    if report.analyzed_frames == 0 {
+        result
+            .failures
+            .push("captured nothing after the settle time".to_string());
+        return;
+    }
+    if report.late_cycles as f64 > report.cycles as f64 * MAX_LATE_FRACTION {
+        result.failures.push(format!(
+            "{} of {} IO cycles late (longest interval {:.1} ms)",
+            report.late_cycles, report.cycles, report.max_interval_ms
+        ));
+    }
+
+    let bus = || report.pairs.iter().filter(|pair| pair.channel_offset >= 2);
+    result.landed_offset = bus()
+        .filter(|pair| pair.tone_db > LEAK_DB)
+        .max_by(|a, b| a.tone_db.total_cmp(&b.tone_db))
+        .map(|pair| pair.channel_offset);
+    let Some(expected) = result.expected_offset else {
+        return;
+    };
+    let want = TONE_DB + cli.gain_db;
+    match report
+        .pairs
+        .iter()
+        .find(|pair| pair.channel_offset == expected)
+    {
+        Some(pair) if (pair.tone_db - want).abs() <= TONE_TOLERANCE_DB => {}
+        Some(pair) => result.failures.push(format!(
+            "tone at {:.1} dB on channels {}-{}, expected {:.1} dB",
+            pair.tone_db,
+            expected + 1,
+            expected + 2,
+            want
+        )),
+        None => result.failures.push(format!(
+            "channels {}-{} are not on the device",
+            expected + 1,
+            expected + 2
+        )),
+    }
+    for pair in bus().filter(|pair| pair.channel_offset != expected && pair.tone_db > LEAK_DB) {
+        result.failures.push(format!(
+            "tone leaks onto channels {}-{} at {:.1} dB",
+            pair.channel_offset + 1,
+            pair.channel_offset + 2,
+            pair.tone_db
+        ));
+    }
+}
+
+fn run(cli: &Cli) -> Result<bool, String> {
+    if cli.clients == 0 {
+        return Err("--clients must be at least 1".to_string());
+    }
+    if cli.seconds * 1000.0 <= cli.settle_ms as f64 {
+        return Err("--seconds must be longer than the settle time".to_string());
+    }
+    let device_id = host::find_prism_device()?;
+    let pairs = host::channel_count(device_id, kAudioObjectPropertyScopeInput)? / 2;
+    if cli.route && cli.clients as u32 >= pairs {
+        return Err(format!(
+            "--route needs a bus pair per client; the device has {} besides 1-2",
+            pairs.saturating_sub(1)
+        ));
+    }
+
+    // Start every client before routing any, so they all play for the whole run
+    let mut workers = Vec::new();
+    for index in 0..cli.clients {
+        let freq_hz = cli.base_hz + cli.step_hz * index as f64;
+        match spawn_worker(cli, freq_hz) {
+            Ok(worker) => workers.push(worker),
+            Err(err) => {
+                for (mut child, _) in workers {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                }
+                return Err(err);
+            }
+        }
+    }
+    let mut results: Vec<ClientResult> = (0..cli.clients)
+        .map(|index| ClientResult {
+            index,
+            expected_offset: cli.route.then_some(2 + 2 * index as u32),
+            landed_offset: None,
+            report: None,
+            failures: Vec::new(),
+        })
+        .collect();
+    if cli.route {
+        let gain = db_to_linear(cli.gain_db);
+        for (result, (child, _)) in results.iter_mut().zip(&workers) {
+            let offset = result.expected_offset.unwrap_or(0);
+            if let Err(err) =
+                host::send_rout_update(device_id, child.id() as i32, offset, Some(gain), None, None)
+            {
+                result.failures.push(format!("routing failed: {}", err));
+            }
+        }
+    }
+
+    for (result, (mut child, stdout)) in results.iter_mut().zip(workers) {
+        let report = stdout
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str::<WorkerReport>(&line).ok())
+            .last();
+        let _ = child.wait();
+        result.report = report;
+        check(result, cli);
+    }
+
+    if cli.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&results).map_err(|err| err.to_string())?
+        );
+    } else {
+        print_results(&results);
+    }
+    Ok(results.iter().all(|result| result.failures.is_empty()))
+}
+
+fn print_results(results: &[ClientResult]) {
+    let pair = |offset: Option<u32>| {
+        offset.map_or("-".to_string(), |offset| {
+            format!("{}-{}", offset + 1, offset + 2)
+        })
+    };
+    println!(
+        "{:<7} {:>8} {:>8} {:>8} {:>9} {:>8} {:>12}  RESULT",
+        "CLIENT", "PID", "TONE", "LANDED", "EXPECTED", "CYCLES", "MAX CYCLE"
+    );
+    for result in results {
+        let (pid, freq, cycles, gap) = match &result.report {
+            Some(report) => (
+                report.pid.to_string(),
+                format!("{:.0} Hz", report.freq_hz),
+                report.cycles.to_string(),
+                format!("{:.1} ms", report.max_interval_ms),
+            ),
+            None => ("-".into(), "-".into(), "-".into(), "-".into()),
+        };
+        println!(
+            "{:<7} {:>8} {:>8} {:>8} {:>9} {:>8} {:>12}  {}",
+            result.index + 1,
+            pid,
+            freq,
+            pair(result.landed_offset),
+            pair(result.expected_offset),
+            cycles,
+            gap,
+            if result.failures.is_empty() {
+                "ok"
+            } else {
+                "FAIL"
+            }
+        );
+        for failure in &result.failures {
+            println!("        {}", failure);
+        }
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let outcome = match cli.worker {
+        Some(freq_hz) => run_worker(freq_hz, cli.seconds, cli.settle_ms).map(|()| true),
+        None => run(&cli),
+    };
+    match outcome {
+        Ok(passed) => std::process::exit(if passed { 0 } else { 1 }),
+        Err(err) => {
+            eprintln!("prism-sim: {}", err);
+            std::process::exit(2);
+        }
+    }
+}
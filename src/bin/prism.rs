@@ -1,35 +1,89 @@
-#[path = "../socket.rs"]
-mod socket;
+#[path = "../mixer.rs"]
+mod mixer;
 
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::engine::{ArgValueCandidates, CompletionCandidate};
+use clap_complete::CompleteEnv;
+use prism::daemon;
 use prism::ipc::{
-    ClientInfoPayload, CommandRequest, CustomPropertyPayload, HelpEntry, RoutingUpdateAck,
-    RpcResponse,
+    linear_to_db, AgcPayload, AssignPolicy, AutoAssignPayload, BridgePayload, BufferRegionPayload,
+    BusLabelPayload, BusLevelPayload, BusPayload, CaptureListPayload, CaptureMaskPayload,
+    CaptureRulePayload, ChannelTrimPayload, ClassificationPayload, ClientInfoPayload,
+    ClientMatrixPayload, ClockDomainPayload, ClockRatePayload, CommandRequest, CompletionKind,
+    CompletionPayload, ConflictPolicy, ContentType, CustomPropertyPayload, DiagPayload,
+    DriverInfoPayload, GapMode, GapPolicyPayload, HealthPayload, HelpEntry, IoStatsPayload,
+    LatencyPayload, LogLevelPayload, MatrixRoutePayload, MeterHistoryPayload, MonitorPayload,
+    MonitorSendPayload, MonoFold, OutputModePayload, PairPolicyPayload, PresetPayload,
+    ReservationPayload, ResyncPayload, RoutingUpdateAck, RpcResponse, RulesPayload, SoloPayload,
+    StatusPayload, SystemMixPayload, IDEMPOTENCY_KEY_FIELD, NULL_BUS_OFFSET,
 };
+use prism::presetfmt;
+use prism::socket;
 use serde::de::DeserializeOwned;
 use serde_json::{self};
 use std::collections::BTreeMap;
-// std::env not required here (clap handles args)
-use std::io::{BufReader, Read, Write};
+use std::env;
+use std::ffi::OsString;
+use std::io::{Read, Write};
 use std::net::Shutdown;
 use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 #[derive(Parser)]
 #[command(name = "prism", about = "Prism control CLI")]
 struct Cli {
+    /// prismd socket to use (default: $PRISM_SOCKET, else your per-user socket, else
+    /// /tmp/prismd.sock)
+    #[arg(long, global = true, value_name = "PATH")]
+    socket: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+// --socket, set once before any request is sent
+static SOCKET_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
 #[derive(Subcommand)]
 enum Commands {
     /// Send routing update to a PID
-    #[command(about = "Send routing update to a PID")]
+    #[command(
+        about = "Send routing update to a PID",
+        long_about = "Routes the PID's clients to a pair, given as OFFSET or CH1-CH2. `null` \
+                      routes them to the null bus: they keep playing but no capture hears them."
+    )]
     Set {
         #[arg(value_name = "PID")]
         pid: i32,
-        #[arg(value_name = "OFFSET|CH1-CH2")]
+        #[arg(value_name = "OFFSET|CH1-CH2|null", add = ArgValueCandidates::new(complete_pairs))]
         offset: String,
+        /// Gain in decibels (e.g. -6dB)
+        #[arg(long, value_name = "GAIN", allow_hyphen_values = true)]
+        gain: Option<String>,
+        /// Pan from -1.0 (left) to 1.0 (right)
+        #[arg(long, value_name = "PAN", allow_hyphen_values = true)]
+        pan: Option<f32>,
+        /// With PID -1, leave this pid or bundle ID where it is (repeatable)
+        #[arg(long, value_name = "PID|BUNDLE_ID")]
+        exclude: Vec<String>,
+        /// Route as OWNER, so a pair OWNER reserved accepts the route
+        #[arg(long = "as", value_name = "OWNER")]
+        owner: Option<String>,
+    },
+    /// Return a PID's clients to the unassigned state
+    #[command(
+        about = "Return a PID's clients to the unassigned state",
+        long_about = "Moves the clients back to 1-2ch with unity gain, centered pan, normal \
+                      width and no matrix, as when they first connected, and clears the pair \
+                      they leave. PID -1 unroutes every client."
+    )]
+    Unset {
+        #[arg(value_name = "PID", allow_negative_numbers = true)]
+        pid: i32,
     },
     /// List driver custom properties
     #[command(about = "List driver custom properties")]
@@ -40,25 +94,707 @@ enum Commands {
     /// List apps grouped by responsible process
     #[command(about = "List apps grouped by responsible process")]
     Apps,
+    /// Show driver IO statistics
+    #[command(about = "Show driver IO statistics")]
+    Stats,
+    /// Show which capture clients are reading the input stream and where they are
+    #[command(
+        about = "Show which capture clients are reading the input stream and where they are"
+    )]
+    Diag,
+    /// Measure prismd request latency with a burst of read-only requests
+    #[command(about = "Measure prismd request latency with a burst of read-only requests")]
+    Benchmark {
+        /// Number of requests to send
+        #[arg(short = 'n', long, default_value_t = 200)]
+        requests: usize,
+        /// Connections sending requests at the same time
+        #[arg(short = 'j', long, default_value_t = 1)]
+        concurrency: usize,
+        /// Request to send: clients, apps, list, stats or meters
+        #[arg(long, value_name = "REQUEST", default_value = "clients")]
+        request: String,
+    },
+    /// Check routing, gain and IO timing end to end with simulated clients
+    #[command(
+        about = "Check routing, gain and IO timing end to end with simulated clients",
+        long_about = "Runs prism-sim (installed next to prism) with --route: CLIENTS processes \
+                      each play a test tone into Prism, are routed to their own pair from 3-4 \
+                      up and capture the input stream, and the run fails unless every tone \
+                      arrived on its pair at the expected level and nowhere else. Routes are \
+                      set on the driver directly, so prismd need not run."
+    )]
+    Selftest {
+        /// Number of simulated clients
+        #[arg(short = 'n', long, default_value_t = 4)]
+        clients: usize,
+        /// How long each client plays, in seconds
+        #[arg(short = 't', long, default_value_t = 3.0)]
+        seconds: f64,
+    },
+    /// Show peak/RMS levels per bus
+    #[command(about = "Show peak/RMS levels per bus")]
+    Meters {
+        /// Keep refreshing until interrupted
+        #[arg(short, long)]
+        watch: bool,
+        /// Show a peak sparkline per app over the last DURATION (e.g. 5m, 90s, 1h)
+        #[arg(long, value_name = "DURATION", conflicts_with = "watch")]
+        history: Option<String>,
+    },
+    /// Show which bus pairs are free, who plays on each and when it last had audio
+    #[command(
+        about = "Show which bus pairs are free, who plays on each and when it last had audio",
+        long_about = "Lists every pair from 3-4 up: free (no app plays on it and nobody reserved \
+                      it), in use with the apps writing to it, or reserved and by whom, with \
+                      how long ago the pair last carried audio. Use it to pick a bus for a new \
+                      route."
+    )]
+    Buses,
+    /// Name a bus pair, or drop its name
+    #[command(
+        about = "Name a bus pair, or drop its name",
+        long_about = "Gives the pair a name such as \"Music\" or \"Stream Mix\", shown by \
+                      `prism buses` and used for the device's channel names in recording apps \
+                      (\"Music L\", \"Music R\"). Without NAME, the pair goes back to its \
+                      number. prismd saves the names and restores them after coreaudiod \
+                      restarts."
+    )]
+    BusLabel {
+        #[arg(value_name = "CH1-CH2", add = ArgValueCandidates::new(complete_pairs))]
+        pair: String,
+        /// Words of the name, joined by spaces
+        #[arg(value_name = "NAME")]
+        name: Vec<String>,
+    },
+    /// Restrict who may capture a channel pair
+    #[command(
+        about = "Restrict who may capture a channel pair",
+        long_about = "Only capture clients of BUNDLE_ID read audio on the pair; others get silence. \
+                      Without arguments, lists the current restrictions."
+    )]
+    CaptureAllow {
+        #[arg(value_name = "OFFSET|CH1-CH2", add = ArgValueCandidates::new(complete_pairs))]
+        offset: Option<String>,
+        #[arg(value_name = "BUNDLE_ID")]
+        bundle_id: Option<String>,
+        /// Lift the restriction on the pair
+        #[arg(long, conflicts_with = "bundle_id")]
+        clear: bool,
+    },
+    /// Limit which pairs one capture process reads
+    #[command(
+        about = "Limit which pairs one capture process reads",
+        long_about = "Capture clients of PID read only the listed pairs; every other channel is \
+                      silence for them, while other processes still read everything. `all` \
+                      lifts the mask. Without arguments, lists the masked processes."
+    )]
+    CaptureMask {
+        #[arg(value_name = "PID")]
+        pid: Option<i32>,
+        #[arg(value_name = "CH1-CH2|all", add = ArgValueCandidates::new(complete_pairs))]
+        pairs: Vec<String>,
+    },
+    /// Show the apps the driver never lets into the loopback
+    #[command(
+        about = "Show the apps the driver never lets into the loopback",
+        long_about = "Bundle IDs listed under capture_exclude in the driver's Driver.plist \
+                      (conferencing or banking apps, say) keep playing, but their output is \
+                      dropped before it reaches any channel, however they are routed. The list \
+                      is read when coreaudiod loads the driver; prism cannot change it."
+    )]
+    CaptureExclusions,
+    /// Limit who may capture the input stream at all
+    #[command(
+        about = "Limit who may capture the input stream at all",
+        long_about = "While the list is on, only the listed processes read the Prism input; \
+                      every other capture client gets silence on all channels. Each ENTRY is \
+                      a bundle ID (which also covers the app's helpers) or a PID added to the \
+                      list; ENTRY@off removes it. prismd stays on the list for its bridges. \
+                      Without arguments, shows the list."
+    )]
+    CaptureList {
+        /// on enforces the list, off lets every client capture again
+        #[arg(value_name = "on|off")]
+        state: Option<String>,
+        #[arg(value_name = "BUNDLE_ID|PID[@off]")]
+        entries: Vec<String>,
+    },
+    /// Choose what happens when an app is routed to a pair another app uses
+    #[command(
+        about = "Choose what happens when an app is routed to a pair another app uses",
+        long_about = "POLICY is one of: reject (refuse the new route), mix (play both; the default), \
+                      replace (move the apps already there to the system mix) or duck (keep them, \
+                      12 dB lower). Without arguments, lists the pairs with a non-default policy."
+    )]
+    PairPolicy {
+        #[arg(value_name = "OFFSET|CH1-CH2", add = ArgValueCandidates::new(complete_pairs))]
+        offset: Option<String>,
+        #[arg(value_name = "POLICY")]
+        policy: Option<String>,
+    },
+    /// Choose what a pair plays when an app on it misses a cycle
+    #[command(
+        about = "Choose what a pair plays when an app on it misses a cycle",
+        long_about = "zero-fill (the default) plays silence for audio an app has not written in \
+                      time, which music capture expects. hold-last repeats the app's newest \
+                      audio over gaps up to --max-hold milliseconds (20 by default, at most \
+                      200) and falls back to silence beyond that; voice chat and voice activity \
+                      detection cope better with a held buffer than with a dropout. Without \
+                      arguments, lists the pairs that hold."
+    )]
+    GapPolicy {
+        #[arg(value_name = "OFFSET|CH1-CH2", add = ArgValueCandidates::new(complete_pairs))]
+        offset: Option<String>,
+        #[arg(value_name = "zero-fill|hold-last")]
+        mode: Option<String>,
+        /// Longest gap hold-last covers, in milliseconds
+        #[arg(long = "max-hold", value_name = "MS")]
+        max_hold: Option<u32>,
+    },
+    /// Claim a pair so other tools cannot route apps onto it
+    #[command(
+        about = "Claim a pair so other tools cannot route apps onto it",
+        long_about = "Reserves the pair for OWNER in the driver: routing writes that do not name \
+                      OWNER are refused for it, including `prism set` and presets unless they \
+                      pass --as OWNER, while apps already there stay. --release gives the pair up again; only OWNER can. \
+                      Without arguments, lists the reserved pairs."
+    )]
+    Reserve {
+        #[arg(value_name = "OFFSET|CH1-CH2", add = ArgValueCandidates::new(complete_pairs))]
+        offset: Option<String>,
+        #[arg(value_name = "OWNER")]
+        owner: Option<String>,
+        /// Give the pair up instead of claiming it
+        #[arg(long)]
+        release: bool,
+    },
+    /// Trim input channels (same as the channel sliders in Audio MIDI Setup)
+    #[command(about = "Trim input channels (same as the channel sliders in Audio MIDI Setup)")]
+    Trim {
+        #[arg(value_name = "CH|CH1-CH2")]
+        channels: Option<String>,
+        /// Trim in decibels (e.g. -3dB)
+        #[arg(value_name = "GAIN", allow_hyphen_values = true)]
+        gain: Option<String>,
+    },
+    /// Re-anchor the driver timeline and clear its buffers
+    #[command(about = "Re-anchor the driver timeline and clear its buffers")]
+    Resync,
+    /// Check that prismd is up and attached to the Prism device (exit status 1 if not)
+    #[command(
+        about = "Check that prismd is up and attached to the Prism device (exit status 1 if not)"
+    )]
+    Health,
+    /// Show what prismd keeps track of and its last routing drift check
+    #[command(
+        about = "Show what prismd keeps track of and its last routing drift check",
+        long_about = "prismd remembers where it routed each client and periodically checks \
+                      that the driver still routes them there, routing back any that drifted \
+                      (after a missed property write or a driver restart)."
+    )]
+    Status,
+    /// Show the loaded driver's version, build and devices
+    #[command(about = "Show the loaded driver's version, build and devices")]
+    DriverInfo,
+    /// Zero a pair's loopback ring (or all of them) to stop stuck or looping audio
+    #[command(
+        about = "Zero a pair's loopback ring (or all of them) to stop stuck or looping audio",
+        long_about = "Without arguments, lists the allocated rings with their peak level and \
+                      how many clients are routed to each."
+    )]
+    Clear {
+        #[arg(value_name = "OFFSET|CH1-CH2|all", add = ArgValueCandidates::new(complete_pairs))]
+        target: Option<String>,
+    },
+    /// Route common apps to their own pairs with a built-in preset
+    #[command(
+        about = "Route common apps to their own pairs with a built-in preset",
+        long_about = "Applies a preset to the apps playing now and keeps it active, so apps \
+                      that start later are routed too. Apps you reroute yourself are left \
+                      alone. Without arguments, lists the presets; `off` stops routing new \
+                      apps. Preset files in ~/Library/Application Support/Prism/presets are \
+                      offered too."
+    )]
+    Quickstart {
+        #[arg(value_name = "PRESET|off", add = ArgValueCandidates::new(complete_presets))]
+        preset: Option<String>,
+        /// Route as OWNER, so pairs OWNER reserved accept the preset's routes
+        #[arg(long = "as", value_name = "OWNER")]
+        owner: Option<String>,
+    },
+    /// Check a preset file without applying it
+    #[command(
+        about = "Check a preset file without applying it",
+        long_about = "Validates a preset file against the schema prismd reads (version 1) and \
+                      points at the first mistake. Runs locally; prismd is not needed."
+    )]
+    PresetCheck {
+        #[arg(value_name = "FILE")]
+        path: PathBuf,
+    },
+    /// Show the routing rules prismd applies as apps appear
+    #[command(
+        about = "Show the routing rules prismd applies as apps appear",
+        long_about = "Lists the rules from ~/Library/Application Support/Prism/rules.json (or \
+                      prismd --rules-file). Each app a rule matches is routed once as it \
+                      appears; routes set by hand afterwards stick. --reload rereads the file \
+                      and applies it to the apps playing now."
+    )]
+    Rules {
+        /// Reread the rules file
+        #[arg(long)]
+        reload: bool,
+    },
+    /// Show or set how much the driver logs (error, info or debug)
+    #[command(
+        about = "Show or set how much the driver logs (error, info or debug)",
+        long_about = "The driver logs to the unified log under the dev.ichigo.prism subsystem. \
+                      info (the default) covers lifecycle and routing; debug adds property \
+                      traffic. View with: log stream --level debug --predicate \
+                      'subsystem == \"dev.ichigo.prism\"'"
+    )]
+    LogLevel {
+        #[arg(value_name = "LEVEL")]
+        level: Option<String>,
+    },
+    /// Show or set the latency and safety offset the driver reports per direction
+    #[command(
+        about = "Show or set the latency and safety offset the driver reports per direction",
+        long_about = "Values are frames at 48 kHz (0 to 16384). A larger safety offset gives \
+                      apps more headroom against dropouts; latency tells them how late the \
+                      audio really is, so recordings line up. Changes last until coreaudiod \
+                      restarts; set the same keys in Driver.plist to keep them."
+    )]
+    Latency {
+        /// Input (capture) latency in frames
+        #[arg(long = "input", value_name = "FRAMES")]
+        input_latency: Option<u32>,
+        /// Output (playback) latency in frames
+        #[arg(long = "output", value_name = "FRAMES")]
+        output_latency: Option<u32>,
+        /// Input safety offset in frames
+        #[arg(long = "input-safety", value_name = "FRAMES")]
+        input_safety_offset: Option<u32>,
+        /// Output safety offset in frames
+        #[arg(long = "output-safety", value_name = "FRAMES")]
+        output_safety_offset: Option<u32>,
+    },
+    /// Forward a pair into another device, e.g. per-app buses into a master mix device
+    #[command(
+        about = "Forward a pair into another device, e.g. per-app buses into a master mix device",
+        long_about = "prismd copies channels CH1-CH2 of the source's input (Prism unless --from \
+                      names another device) into the target device's output, at --to (1-2 by \
+                      default). Chain bridges to build tiers. Both devices must run at the same \
+                      sample rate. Without arguments, lists the bridges with the latency each \
+                      hop adds."
+    )]
+    Bridge {
+        #[arg(value_name = "OFFSET|CH1-CH2")]
+        offset: Option<String>,
+        /// Device to play into, by name or UID
+        #[arg(value_name = "TARGET")]
+        target: Option<String>,
+        /// Pair of the target to write (default 1-2)
+        #[arg(long = "to", value_name = "OFFSET|CH1-CH2")]
+        to: Option<String>,
+        /// Device to read from, by name or UID (default Prism)
+        #[arg(long = "from", value_name = "DEVICE")]
+        from: Option<String>,
+        /// Stop a bridge
+        #[arg(long, value_name = "ID", conflicts_with_all = ["offset", "target", "to", "from"])]
+        remove: Option<u32>,
+    },
+    /// Show or set the clock domain Prism shares with a hardware device
+    #[command(
+        about = "Show or set the clock domain Prism shares with a hardware device",
+        long_about = "Devices in the same clock domain run off one clock, so macOS does not \
+                      resample between them in an aggregate device. Name the interface Prism \
+                      is aggregated with (or its UID) to take its domain, give a number to set \
+                      one directly, or 0 to claim none. Without arguments, shows Prism's \
+                      domain and every device's. Set clock_domain in Driver.plist to keep it."
+    )]
+    ClockDomain {
+        #[arg(value_name = "DEVICE|DOMAIN")]
+        target: Option<String>,
+    },
+    /// Show or set whether the output stream spans the whole bus
+    #[command(
+        about = "Show or set whether the output stream spans the whole bus",
+        long_about = "stereo (the default): apps play stereo and prism routes each app to its \
+                      pair. bus: the output stream is as wide as the bus, so a DAW or other \
+                      multichannel player writes bus channels 1:1 and per-app routing no \
+                      longer applies. Set full_bus_output in Driver.plist to start in bus mode."
+    )]
+    OutputMode {
+        #[arg(value_name = "stereo|bus")]
+        mode: Option<String>,
+    },
+    /// Show, move or switch off the system mix
+    #[command(
+        about = "Show, move or switch off the system mix",
+        long_about = "The system mix is everything apps play that is not routed to a pair of \
+                      its own; it lands on 1-2 unless moved. Moving it to, say, 63-64 keeps \
+                      1-2 free for a monitor fold or a single app. New apps are never \
+                      auto-assigned the system mix pair. off drops the mix in the driver, so \
+                      only explicitly routed apps reach the bus; on brings it back. Set \
+                      system_mix_offset and system_mix_enabled in Driver.plist to start that way."
+    )]
+    SystemMix {
+        #[arg(value_name = "CH1-CH2|on|off", add = ArgValueCandidates::new(complete_pairs))]
+        pair: Option<String>,
+    },
+    /// Show or set how new apps get a pair before they are routed
+    #[command(
+        about = "Show or set how new apps get a pair before they are routed",
+        long_about = "first-free (the default) puts each new app on the lowest free pair from \
+                      3-4 up. round-robin takes the next free pair after the one handed out \
+                      last, so a pair just vacated is not reused at once. sticky-bundle puts an \
+                      app back on the pair it last played on, which suits streaming setups \
+                      where each source keeps its bus. disabled (or off) leaves new apps on \
+                      1-2 for passthrough. Reserved pairs are never handed out. prismd keeps \
+                      the policy and sets it again after coreaudiod restarts."
+    )]
+    AutoAssign {
+        #[arg(value_name = "disabled|first-free|round-robin|sticky-bundle")]
+        policy: Option<String>,
+    },
+    /// Show or set the driver clock rate offset in ppm
+    #[command(
+        about = "Show or set the driver clock rate offset in ppm",
+        long_about = "Prism's timestamps follow the host clock. In an aggregate device with \
+                      hardware that runs slightly fast or slow, set the offset (up to ±1000 \
+                      ppm) so the HAL's drift compensation has less to correct. The driver \
+                      eases into the new rate instead of jumping."
+    )]
+    ClockRate {
+        #[arg(value_name = "PPM", allow_negative_numbers = true)]
+        ppm: Option<f64>,
+    },
+    /// Interactive mixer with per-app meters, faders, mute/solo and channel pairs
+    #[command(
+        about = "Interactive mixer with per-app meters, faders, mute/solo and channel pairs"
+    )]
+    Mixer,
+    /// Set the stereo width of an app (0 = mono, 1 = unchanged, 2 = widest)
+    #[command(about = "Set the stereo width of an app (0 = mono, 1 = unchanged, 2 = widest)")]
+    Width {
+        #[arg(value_name = "APP_NAME", add = ArgValueCandidates::new(complete_apps))]
+        app_name: String,
+        #[arg(value_name = "WIDTH")]
+        width: f32,
+    },
+    /// Set an app's left/right balance, or swap its channels
+    #[command(
+        about = "Set an app's left/right balance, or swap its channels",
+        long_about = "BALANCE runs from -1.0 (left only) through 0 (centered) to 1.0 (right \
+                      only); the side it leans away from is attenuated. --swap exchanges the \
+                      app's left and right before anything else, for apps that play them \
+                      reversed, and --no-swap undoes it."
+    )]
+    Balance {
+        #[arg(value_name = "APP_NAME", add = ArgValueCandidates::new(complete_apps))]
+        app_name: String,
+        #[arg(value_name = "BALANCE", allow_negative_numbers = true)]
+        balance: Option<f32>,
+        /// Exchange the app's left and right channels
+        #[arg(long, conflicts_with = "no_swap")]
+        swap: bool,
+        /// Play the app's channels the right way round again
+        #[arg(long)]
+        no_swap: bool,
+    },
+    /// Fold an app down to mono on one channel of its pair, or back to stereo
+    #[command(
+        about = "Fold an app down to mono on one channel of its pair, or back to stereo",
+        long_about = "Sums the app's left and right at -3 dB into the left or right channel of \
+                      its pair and leaves the other channel silent, so a second mono source \
+                      can use it. `off` returns the app to stereo."
+    )]
+    Mono {
+        #[arg(value_name = "APP_NAME", add = ArgValueCandidates::new(complete_apps))]
+        app_name: String,
+        #[arg(value_name = "left|right|off")]
+        fold: String,
+    },
+    /// Also play an app on a second pair, or stop
+    #[command(
+        about = "Also play an app on a second pair, or stop",
+        long_about = "Duplicates the app's output onto a second bus pair, so one feed can be \
+                      recorded clean while the other goes into a stream mix. The copy carries \
+                      the app's gain, balance and width, and follows it if it is rerouted. \
+                      `off` stops mirroring."
+    )]
+    Mirror {
+        #[arg(value_name = "APP_NAME", add = ArgValueCandidates::new(complete_apps))]
+        app_name: String,
+        #[arg(value_name = "CH1-CH2|off")]
+        pair: String,
+    },
+    /// Route each side of an app to any bus channels instead of one pair
+    #[command(
+        about = "Route each side of an app to any bus channels instead of one pair",
+        long_about = "Each ROUTE sends the app's left (L) or right (R) output to a 1-based bus \
+                      channel, optionally at a gain: L:5 R:12 sends left to channel 5 and right \
+                      to channel 12, L:7@-6dB adds left to channel 7 as well, 6 dB down. Up to \
+                      8 routes. --clear returns the app to its pair. Without arguments, lists \
+                      the apps routed by a matrix."
+    )]
+    Matrix {
+        #[arg(value_name = "APP_NAME", add = ArgValueCandidates::new(complete_apps))]
+        app_name: Option<String>,
+        #[arg(value_name = "L|R:CH[@GAIN]", allow_hyphen_values = true)]
+        routes: Vec<String>,
+        /// Drop the matrix and route the app to its pair again
+        #[arg(long, conflicts_with = "routes")]
+        clear: bool,
+    },
+    /// Hear routed pairs on 1-2 without an external mixer
+    #[command(
+        about = "Hear routed pairs on 1-2 without an external mixer",
+        long_about = "While on, bridges from Prism's 1-2 (see `prism bridge`) add every pair with \
+                      a monitor send to what they play; capture clients still read each pair on \
+                      its own. Each SEND names a pair and optionally a gain: 3-4 sends 3-4 at \
+                      0 dB, 5-6@-6dB sends 5-6 6 dB down, 5-6@off drops the send. \
+                      Without arguments, shows the monitor state."
+    )]
+    Monitor {
+        /// on or off; sends can be changed either way
+        #[arg(value_name = "on|off")]
+        state: Option<String>,
+        #[arg(value_name = "CH1-CH2[@GAIN|@off]", allow_hyphen_values = true)]
+        sends: Vec<String>,
+    },
+    /// Isolate pairs for every capture without changing any routing
+    #[command(
+        about = "Isolate pairs for every capture without changing any routing",
+        long_about = "While any pair is soloed, the driver silences every other pair for all \
+                      capture clients. Each PAIR is added to the solo; 5-6@off releases it, and \
+                      `off` releases every solo first. Without arguments, shows the soloed pairs."
+    )]
+    Solo {
+        /// off releases every solo
+        #[arg(value_name = "off")]
+        state: Option<String>,
+        #[arg(value_name = "CH1-CH2[@off]", add = ArgValueCandidates::new(complete_pairs))]
+        pairs: Vec<String>,
+    },
+    /// Keep an app near a target loudness by adjusting its gain
+    #[command(about = "Keep an app near a target loudness by adjusting its gain")]
+    Agc {
+        #[arg(value_name = "APP_NAME", add = ArgValueCandidates::new(complete_apps))]
+        app_name: Option<String>,
+        /// Target loudness (e.g. -18LUFS)
+        #[arg(long, value_name = "LOUDNESS", allow_hyphen_values = true)]
+        target: Option<String>,
+        /// Maximum boost or cut (e.g. 12dB)
+        #[arg(long, value_name = "GAIN")]
+        range: Option<String>,
+        /// Turn AGC off and restore unity gain
+        #[arg(long, conflicts_with_all = ["target", "range"])]
+        off: bool,
+    },
     /// Set channel offset for all clients of an app
     #[command(about = "Set channel offset for all clients of an app")]
     SetApp {
-        #[arg(value_name = "APP_NAME")]
+        /// App display name (as shown by `prism apps`), bundle ID, or type:<content type>
+        /// for every app of that type (e.g. type:voice)
+        #[arg(value_name = "APP_NAME|BUNDLE_ID", add = ArgValueCandidates::new(complete_apps))]
         app_name: String,
-        #[arg(value_name = "OFFSET|CH1-CH2")]
+        #[arg(value_name = "OFFSET|CH1-CH2|null", add = ArgValueCandidates::new(complete_pairs))]
         offset: String,
+        /// Gain in decibels (e.g. -6dB)
+        #[arg(long, value_name = "GAIN", allow_hyphen_values = true)]
+        gain: Option<String>,
+        /// Pan from -1.0 (left) to 1.0 (right)
+        #[arg(long, value_name = "PAN", allow_hyphen_values = true)]
+        pan: Option<f32>,
+        /// Route as OWNER, so a pair OWNER reserved accepts the route
+        #[arg(long = "as", value_name = "OWNER")]
+        owner: Option<String>,
+    },
+    /// Show or set the content type of an app (music, voice, game or system)
+    #[command(
+        about = "Show or set the content type of an app (music, voice, game or system)",
+        long_about = "Apps are classified by the category their bundle declares, and anything \
+                      running from the OS counts as system. Set a type for a bundle ID (its \
+                      helpers included) when that guess is wrong or missing; prismd saves it. \
+                      Presets route by type, and `prism set-app type:voice 5-6` routes every \
+                      voice app. Without arguments, lists the apps playing and their types."
+    )]
+    Classify {
+        #[arg(value_name = "BUNDLE_ID")]
+        bundle_id: Option<String>,
+        #[arg(value_name = "music|voice|game|system")]
+        content_type: Option<String>,
+        /// Drop the override and classify the app from its bundle again
+        #[arg(long, conflicts_with = "content_type")]
+        clear: bool,
+    },
+    /// Run the prismd daemon from this binary
+    #[command(about = "Run the prismd daemon from this binary")]
+    Daemon {
+        #[command(subcommand)]
+        command: DaemonCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum DaemonCommand {
+    /// Start the daemon; takes the same options as prismd (e.g. --daemonize)
+    #[command(
+        about = "Start the daemon; takes the same options as prismd (e.g. --daemonize)",
+        disable_help_flag = true
+    )]
+    Run {
+        #[arg(
+            value_name = "PRISMD_ARGS",
+            trailing_var_arg = true,
+            allow_hyphen_values = true
+        )]
+        args: Vec<OsString>,
     },
 }
 
 fn main() {
+    // Multicall: started through the prismd link, the binary is the daemon
+    if daemon::invoked_as_daemon() {
+        daemon::run(env::args_os());
+        return;
+    }
+
+    // Answers the shell when it runs us for completions (COMPLETE=<shell>), then exits
+    CompleteEnv::with_factory(Cli::command).complete();
+
     let cli = Cli::parse();
+    if let Some(path) = cli.socket {
+        let _ = SOCKET_OVERRIDE.set(path);
+    }
 
     let res = match cli.command {
-        Commands::Set { pid, offset } => handle_set(vec![pid.to_string(), offset]),
+        Commands::Set {
+            pid,
+            offset,
+            gain,
+            pan,
+            exclude,
+            owner,
+        } => handle_set(vec![pid.to_string(), offset], gain, pan, exclude, owner),
+        Commands::Unset { pid } => handle_unset(pid),
         Commands::List => handle_list(),
         Commands::Clients => handle_clients(),
         Commands::Apps => handle_apps(Vec::new()),
-        Commands::SetApp { app_name, offset } => handle_set_app(vec![app_name, offset]),
+        Commands::Stats => handle_stats(),
+        Commands::Diag => handle_diag(),
+        Commands::Benchmark {
+            requests,
+            concurrency,
+            request,
+        } => handle_benchmark(requests, concurrency, &request),
+        Commands::Selftest { clients, seconds } => handle_selftest(clients, seconds),
+        Commands::Meters { watch, history } => handle_meters(watch, history),
+        Commands::Buses => handle_buses(),
+        Commands::BusLabel { pair, name } => handle_bus_label(&pair, &name),
+        Commands::Trim { channels, gain } => handle_trim(channels, gain),
+        Commands::Resync => handle_resync(),
+        Commands::LogLevel { level } => handle_log_level(level),
+        Commands::ClockRate { ppm } => handle_clock_rate(ppm),
+        Commands::OutputMode { mode } => handle_output_mode(mode),
+        Commands::SystemMix { pair } => handle_system_mix(pair),
+        Commands::AutoAssign { policy } => handle_auto_assign(policy),
+        Commands::ClockDomain { target } => handle_clock_domain(target),
+        Commands::Bridge {
+            offset,
+            target,
+            to,
+            from,
+            remove,
+        } => handle_bridge(offset, target, to, from, remove),
+        Commands::Latency {
+            input_latency,
+            output_latency,
+            input_safety_offset,
+            output_safety_offset,
+        } => handle_latency(CommandRequest::Latency {
+            input_latency,
+            output_latency,
+            input_safety_offset,
+            output_safety_offset,
+        }),
+        Commands::Clear { target } => handle_clear(target),
+        Commands::Quickstart { preset, owner } => handle_quickstart(preset, owner),
+        Commands::PresetCheck { path } => handle_preset_check(&path),
+        Commands::Rules { reload } => handle_rules(reload),
+        Commands::Health => handle_health(),
+        Commands::Status => handle_status(),
+        Commands::Classify {
+            bundle_id,
+            content_type,
+            clear,
+        } => handle_classify(bundle_id, content_type, clear),
+        Commands::Daemon {
+            command: DaemonCommand::Run { args },
+        } => {
+            let mut argv = vec![OsString::from(daemon::DAEMON_NAME)];
+            // --socket is global to prism, so clap claims it before the daemon sees it
+            if let Some(path) = SOCKET_OVERRIDE.get() {
+                argv.push("--socket".into());
+                argv.push(path.into());
+            }
+            argv.extend(args);
+            daemon::run(argv);
+            Ok(())
+        }
+        Commands::DriverInfo => handle_driver_info(),
+        Commands::Mixer => mixer::run(),
+        Commands::Width { app_name, width } => handle_width(app_name, width),
+        Commands::Mono { app_name, fold } => handle_mono(app_name, &fold),
+        Commands::Mirror { app_name, pair } => handle_mirror(app_name, &pair),
+        Commands::Balance {
+            app_name,
+            balance,
+            swap,
+            no_swap,
+        } => handle_balance(app_name, balance, (swap || no_swap).then_some(swap)),
+        Commands::Matrix {
+            app_name,
+            routes,
+            clear,
+        } => handle_matrix(app_name, routes, clear),
+        Commands::Monitor { state, sends } => handle_monitor(state, sends),
+        Commands::Solo { state, pairs } => handle_solo(state, pairs),
+        Commands::CaptureList { state, entries } => handle_capture_list(state, entries),
+        Commands::Agc {
+            app_name,
+            target,
+            range,
+            off,
+        } => handle_agc(app_name, target, range, off),
+        Commands::CaptureAllow {
+            offset,
+            bundle_id,
+            clear,
+        } => handle_capture_allow(offset, bundle_id, clear),
+        Commands::CaptureMask { pid, pairs } => handle_capture_mask(pid, pairs),
+        Commands::CaptureExclusions => execute_capture_exclusions(),
+        Commands::PairPolicy { offset, policy } => handle_pair_policy(offset, policy),
+        Commands::GapPolicy {
+            offset,
+            mode,
+            max_hold,
+        } => handle_gap_policy(offset, mode, max_hold),
+        Commands::Reserve {
+            offset,
+            owner,
+            release,
+        } => handle_reserve(offset, owner, release),
+        Commands::SetApp {
+            app_name,
+            offset,
+            gain,
+            pan,
+            owner,
+        } => handle_set_app(vec![app_name, offset], gain, pan, owner),
     };
 
     if let Err(err) = res {
@@ -114,7 +850,10 @@ fn handle_apps(_args: Vec<String>) -> Result<(), String> {
         offsets.dedup();
         let offset_str = offsets
             .iter()
-            .map(|o| {
+            .map(|&o| {
+                if o == NULL_BUS_OFFSET {
+                    return "null".to_string();
+                }
                 let ch1 = o + 1;
                 let ch2 = o + 2;
                 format!("{}-{}ch", ch1, ch2)
@@ -135,7 +874,10 @@ fn handle_apps(_args: Vec<String>) -> Result<(), String> {
         offsets.dedup();
         let offset_str = offsets
             .iter()
-            .map(|o| {
+            .map(|&o| {
+                if o == NULL_BUS_OFFSET {
+                    return "null".to_string();
+                }
                 let ch1 = o * 2;
                 let ch2 = o * 2 + 1;
                 format!("{}-{}ch", ch1, ch2)
@@ -149,98 +891,2043 @@ fn handle_apps(_args: Vec<String>) -> Result<(), String> {
             width = max_name_len
         );
     }
+    if clients.iter().any(|client| client.identity_pending) {
+        println!("(Some clients are still being identified; run again to group them.)");
+    }
+    Ok(())
+}
+
+fn handle_set_app(
+    args: Vec<String>,
+    gain: Option<String>,
+    pan: Option<f32>,
+    owner: Option<String>,
+) -> Result<(), String> {
+    // set-app <APP_NAME> <OFFSET|CH1-CH2>
+    // Accept app name containing spaces by treating the last arg as the offset
+    if args.len() < 2 {
+        return Err("Usage: prism set-app <APP_NAME> <OFFSET|CH1-CH2>".to_string());
+    }
+    let offset_arg = args.last().unwrap().to_string();
+    let app_name = args[..args.len() - 1].join(" ");
+    // Accept either numeric offset or channel range like "1-2", or the null bus
+    let offset: u32 = if offset_arg.eq_ignore_ascii_case("null") {
+        NULL_BUS_OFFSET
+    } else if let Some((ch1, ch2)) = parse_channel_range(&offset_arg) {
+        if ch2 != ch1 + 1 {
+            return Err("Channel range must be consecutive (e.g. 1-2, 3-4)".to_string());
+        }
+        if ch1 < 1 {
+            return Err("Channel numbers must be >= 1".to_string());
+        }
+        ch1 - 1
+    } else {
+        offset_arg.parse().map_err(|_| {
+            "OFFSET must be a non-negative integer or channel range (e.g. 1-2)".to_string()
+        })?
+    };
+    let (gain_db, pan) = parse_mix_args(gain.as_deref(), pan)?;
+    // Delegate the app-level update to prismd (daemon) and display its result.
+    let response = send_request(&CommandRequest::SetApp {
+        app_name: app_name.clone(),
+        offset,
+        gain_db,
+        pan,
+        owner,
+    })?;
+    let parsed: RpcResponse<Vec<RoutingUpdateAck>> = parse_response(&response)?;
+    let (_message, results): (Option<String>, Vec<RoutingUpdateAck>) = extract_success(parsed)?;
+
+    if results.is_empty() {
+        println!("No clients found for app '{}'.", app_name);
+    } else {
+        let pids: Vec<String> = results.iter().map(|ack| ack.pid.to_string()).collect();
+        println!(
+            "Set offset={}{} for app '{}' (pids: {})",
+            format_offset(offset),
+            format_mix(gain_db, pan),
+            app_name,
+            pids.join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn handle_set(
+    args: Vec<String>,
+    gain: Option<String>,
+    pan: Option<f32>,
+    exclude: Vec<String>,
+    owner: Option<String>,
+) -> Result<(), String> {
+    if args.len() < 2 {
+        return Err("Usage: prism set <PID> <OFFSET|CH1-CH2>".to_string());
+    }
+
+    let pid: i32 = args[0]
+        .parse()
+        .map_err(|_| "PID must be an integer".to_string())?;
+    if pid != -1 && !exclude.is_empty() {
+        return Err("--exclude only applies to a broadcast (PID -1)".to_string());
+    }
+
+    // Accept either offset or CH1-CH2 format, or the null bus
+    let offset: u32 = if args[1].eq_ignore_ascii_case("null") {
+        NULL_BUS_OFFSET
+    } else if let Some((ch1, ch2)) = parse_channel_range(&args[1]) {
+        // offset = ch1 - 1
+        if ch2 != ch1 + 1 {
+            return Err("Channel range must be consecutive (e.g. 1-2, 2-3)".to_string());
+        }
+        if ch1 < 1 {
+            return Err("Channel numbers must be >= 1".to_string());
+        }
+        ch1 - 1
+    } else {
+        args[1].parse().map_err(|_| {
+            "OFFSET must be a non-negative integer or channel range (e.g. 1-2)".to_string()
+        })?
+    };
+    let (gain_db, pan) = parse_mix_args(gain.as_deref(), pan)?;
+    execute_set(pid, offset, gain_db, pan, exclude, owner)
+}
+
+fn handle_unset(pid: i32) -> Result<(), String> {
+    let response = send_request(&CommandRequest::Unset { pid })?;
+    let parsed: RpcResponse<RoutingUpdateAck> = parse_response(&response)?;
+    let (message, ack): (Option<String>, RoutingUpdateAck) = extract_success(parsed)?;
+    println!(
+        "{} (pid={})",
+        message.unwrap_or_else(|| "client unrouted".to_string()),
+        ack.pid
+    );
+    Ok(())
+}
+
+fn handle_list() -> Result<(), String> {
+    execute_list()
+}
+
+fn handle_clients() -> Result<(), String> {
+    execute_clients()
+}
+
+fn handle_width(app_name: String, width: f32) -> Result<(), String> {
+    if !(0.0..=2.0).contains(&width) {
+        return Err("WIDTH must be between 0.0 (mono) and 2.0".to_string());
+    }
+    let response = send_request(&CommandRequest::Width {
+        app_name: app_name.clone(),
+        width,
+    })?;
+    let parsed: RpcResponse<Vec<RoutingUpdateAck>> = parse_response(&response)?;
+    let (message, results): (Option<String>, Vec<RoutingUpdateAck>) = extract_success(parsed)?;
+
+    if let Some(msg) = message {
+        println!("{}", msg);
+    }
+    let pids: Vec<String> = results.iter().map(|ack| ack.pid.to_string()).collect();
+    println!(
+        "Set width={:.2} for app '{}' (pids: {})",
+        width,
+        app_name,
+        pids.join(", ")
+    );
+    Ok(())
+}
+
+fn handle_balance(
+    app_name: String,
+    balance: Option<f32>,
+    swap: Option<bool>,
+) -> Result<(), String> {
+    if balance.is_some_and(|balance| !(-1.0..=1.0).contains(&balance)) {
+        return Err("BALANCE must be between -1.0 (left) and 1.0 (right)".to_string());
+    }
+    if balance.is_none() && swap.is_none() {
+        return Err("Give a BALANCE, --swap or --no-swap".to_string());
+    }
+    let response = send_request(&CommandRequest::Balance {
+        app_name: app_name.clone(),
+        balance,
+        swap,
+    })?;
+    let parsed: RpcResponse<Vec<RoutingUpdateAck>> = parse_response(&response)?;
+    let (message, results): (Option<String>, Vec<RoutingUpdateAck>) = extract_success(parsed)?;
+
+    if let Some(msg) = message {
+        println!("{}", msg);
+    }
+    let pids: Vec<String> = results.iter().map(|ack| ack.pid.to_string()).collect();
+    let mut changes = Vec::new();
+    if let Some(balance) = balance {
+        changes.push(format!("balance={:.2}", balance));
+    }
+    if let Some(swap) = swap {
+        changes.push(
+            if swap {
+                "channels swapped"
+            } else {
+                "channels in order"
+            }
+            .to_string(),
+        );
+    }
+    println!(
+        "Set {} for app '{}' (pids: {})",
+        changes.join(", "),
+        app_name,
+        pids.join(", ")
+    );
+    Ok(())
+}
+
+fn handle_mono(app_name: String, fold: &str) -> Result<(), String> {
+    let fold = MonoFold::parse(fold)
+        .ok_or_else(|| format!("Unknown fold '{}'. Use left, right or off.", fold))?;
+    let response = send_request(&CommandRequest::Mono {
+        app_name: app_name.clone(),
+        fold,
+    })?;
+    let parsed: RpcResponse<Vec<RoutingUpdateAck>> = parse_response(&response)?;
+    let (message, results): (Option<String>, Vec<RoutingUpdateAck>) = extract_success(parsed)?;
+
+    if let Some(msg) = message {
+        println!("{}", msg);
+    }
+    let pids: Vec<String> = results.iter().map(|ack| ack.pid.to_string()).collect();
+    let what = match fold {
+        MonoFold::Off => "Stereo".to_string(),
+        side => format!("Mono on the {} channel", side.name()),
+    };
+    println!(
+        "{} for app '{}' (pids: {})",
+        what,
+        app_name,
+        pids.join(", ")
+    );
+    Ok(())
+}
+
+fn handle_mirror(app_name: String, pair: &str) -> Result<(), String> {
+    let offset = if pair.eq_ignore_ascii_case("off") {
+        None
+    } else {
+        Some(parse_offset_arg(pair)?)
+    };
+    let response = send_request(&CommandRequest::Mirror {
+        app_name: app_name.clone(),
+        offset,
+    })?;
+    let parsed: RpcResponse<Vec<RoutingUpdateAck>> = parse_response(&response)?;
+    let (message, results): (Option<String>, Vec<RoutingUpdateAck>) = extract_success(parsed)?;
+
+    if let Some(msg) = message {
+        println!("{}", msg);
+    }
+    let pids: Vec<String> = results.iter().map(|ack| ack.pid.to_string()).collect();
+    match offset {
+        Some(offset) => println!(
+            "Mirroring app '{}' to channels {}-{} (pids: {})",
+            app_name,
+            offset + 1,
+            offset + 2,
+            pids.join(", ")
+        ),
+        None => println!(
+            "Stopped mirroring app '{}' (pids: {})",
+            app_name,
+            pids.join(", ")
+        ),
+    }
+    Ok(())
+}
+
+fn handle_resync() -> Result<(), String> {
+    let response = send_request(&CommandRequest::Resync)?;
+    let parsed: RpcResponse<ResyncPayload> = parse_response(&response)?;
+    let (message, payload): (Option<String>, ResyncPayload) = extract_success(parsed)?;
+
+    println!(
+        "{} (resync #{} since the driver loaded)",
+        message.unwrap_or_else(|| "resync requested".to_string()),
+        payload.resyncs
+    );
+    Ok(())
+}
+
+fn handle_health() -> Result<(), String> {
+    let response = send_request(&CommandRequest::Health)?;
+    let parsed: RpcResponse<HealthPayload> = parse_response(&response)?;
+    let (_message, health): (Option<String>, HealthPayload) = extract_success(parsed)?;
+
+    let uptime = format!("up {}s, pid {}", health.uptime_secs, health.pid);
+    match health.device_id {
+        Some(device_id) if health.healthy => {
+            println!("healthy: attached to device {} ({})", device_id, uptime);
+            Ok(())
+        }
+        _ => Err(format!("unhealthy: Prism device not attached ({})", uptime)),
+    }
+}
+
+fn handle_status() -> Result<(), String> {
+    let response = send_request(&CommandRequest::Status)?;
+    let parsed: RpcResponse<StatusPayload> = parse_response(&response)?;
+    let (_message, status): (Option<String>, StatusPayload) = extract_success(parsed)?;
+
+    let attached = if status.healthy {
+        "attached"
+    } else {
+        "not attached"
+    };
+    println!("device:         {}", attached);
+    println!("tracked routes: {}", status.tracked_routes);
+    if status.drift_interval_secs == 0 {
+        println!("drift check:    off");
+        return Ok(());
+    }
+    let Some(check) = status.drift_check else {
+        println!(
+            "drift check:    every {}s, not run yet",
+            status.drift_interval_secs
+        );
+        return Ok(());
+    };
+    let ago = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs().saturating_sub(check.checked_at))
+        .unwrap_or(0);
+    let outcome = match (check.drifted.len(), check.corrected) {
+        (0, _) => "no drift".to_string(),
+        (count, true) => format!("{} drifted, routed back", count),
+        (count, false) => format!("{} drifted", count),
+    };
+    println!(
+        "drift check:    every {}s, last {}s ago: {} of {} tracked",
+        status.drift_interval_secs, ago, outcome, check.tracked
+    );
+    for drift in &check.drifted {
+        println!(
+            "    pid {:<6} {:<20} on {}-{}ch, routed to {}-{}ch by {}",
+            drift.pid,
+            drift.app_name.as_deref().unwrap_or("<unknown>"),
+            drift.actual_offset + 1,
+            drift.actual_offset + 2,
+            drift.intended_offset + 1,
+            drift.intended_offset + 2,
+            drift.source
+        );
+    }
+    if let Some(err) = check.error {
+        eprintln!("    {}", err);
+    }
+    Ok(())
+}
+
+fn handle_quickstart(preset: Option<String>, owner: Option<String>) -> Result<(), String> {
+    let request = match preset.as_deref() {
+        None => CommandRequest::Presets,
+        Some(arg) if arg.eq_ignore_ascii_case("off") => CommandRequest::Quickstart {
+            preset: None,
+            owner: None,
+        },
+        Some(arg) => CommandRequest::Quickstart {
+            preset: Some(arg.to_string()),
+            owner,
+        },
+    };
+    let applying = matches!(
+        request,
+        CommandRequest::Quickstart {
+            preset: Some(_),
+            ..
+        }
+    );
+    let response = send_request(&request)?;
+
+    if !applying {
+        let parsed: RpcResponse<Vec<PresetPayload>> = parse_response(&response)?;
+        let (message, presets): (Option<String>, Vec<PresetPayload>) = extract_success(parsed)?;
+        if let Some(message) = message {
+            println!("{}", message);
+            return Ok(());
+        }
+        for preset in presets {
+            let marker = if preset.active { " (active)" } else { "" };
+            println!("{}{} - {}", preset.name, marker, preset.description);
+            for route in preset.routes {
+                println!(
+                    "    {:<12} -> {}-{}ch  {:+.0}dB",
+                    route.app,
+                    route.channel_offset + 1,
+                    route.channel_offset + 2,
+                    route.gain_db
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    let parsed: RpcResponse<PresetPayload> = parse_response(&response)?;
+    let (message, preset): (Option<String>, PresetPayload) = extract_success(parsed)?;
+    for route in &preset.routes {
+        let apps = if route.matched.is_empty() {
+            "(waiting for an app to start)".to_string()
+        } else {
+            route.matched.join(", ")
+        };
+        println!(
+            "{:<12} -> {}-{}ch  {:+.0}dB  {}",
+            route.app,
+            route.channel_offset + 1,
+            route.channel_offset + 2,
+            route.gain_db,
+            apps
+        );
+        if let Some(err) = &route.error {
+            eprintln!("    {}", err);
+        }
+    }
+    if let Some(message) = message {
+        println!("{}", message);
+    }
+    Ok(())
+}
+
+fn handle_preset_check(path: &Path) -> Result<(), String> {
+    let file = presetfmt::load(path)?;
+    for preset in &file.presets {
+        println!(
+            "{} - {} ({} routes)",
+            preset.name,
+            preset.description,
+            preset.routes.len()
+        );
+    }
+    println!(
+        "{}: ok, schema version {}",
+        path.display(),
+        presetfmt::SCHEMA_VERSION
+    );
+    Ok(())
+}
+
+fn handle_rules(reload: bool) -> Result<(), String> {
+    let response = send_request(&CommandRequest::Rules { reload })?;
+    let parsed: RpcResponse<RulesPayload> = parse_response(&response)?;
+    let (message, payload): (Option<String>, RulesPayload) = extract_success(parsed)?;
+
+    if let Some(message) = message {
+        println!("{}", message);
+    }
+    match &payload.path {
+        Some(path) => println!("Rules file: {}", path),
+        None => println!("Rules file: none"),
+    }
+    if payload.rules.is_empty() {
+        println!("No rules.");
+        return Ok(());
+    }
+    for (index, rule) in payload.rules.iter().enumerate() {
+        let matches = if rule.default {
+            "(any other app)".to_string()
+        } else {
+            let patterns: Vec<&str> = rule
+                .bundle_ids
+                .iter()
+                .chain(&rule.names)
+                .map(String::as_str)
+                .collect();
+            patterns.join(", ")
+        };
+        let priority = if rule.priority != 0 {
+            format!("  priority {}", rule.priority)
+        } else {
+            String::new()
+        };
+        println!(
+            "{:>2}. {:<40} -> {}-{}ch  {:+.0}dB{}",
+            index + 1,
+            matches,
+            rule.channel_offset + 1,
+            rule.channel_offset + 2,
+            rule.gain_db,
+            priority
+        );
+    }
+    Ok(())
+}
+
+fn handle_driver_info() -> Result<(), String> {
+    let response = send_request(&CommandRequest::DriverInfo)?;
+    let parsed: RpcResponse<DriverInfoPayload> = parse_response(&response)?;
+    let (_message, info): (Option<String>, DriverInfoPayload) = extract_success(parsed)?;
+
+    let mut build = format!("Prism driver {} ({}", info.version, info.build);
+    if info.debug {
+        build.push_str(", debug");
+    }
+    for feature in &info.features {
+        build.push_str(", ");
+        build.push_str(feature);
+    }
+    println!("{})", build);
+    for device in &info.devices {
+        println!(
+            "  device {} {} ({}): {} channels, {} clients, IO {}",
+            device.device_id,
+            device.name,
+            device.uid,
+            device.channels,
+            device.clients,
+            if device.io_running {
+                "running"
+            } else {
+                "stopped"
+            }
+        );
+    }
+    println!("  {} clients in total", info.total_clients);
+    Ok(())
+}
+
+fn handle_clear(target: Option<String>) -> Result<(), String> {
+    let request = match target.as_deref() {
+        None => CommandRequest::Buffers,
+        Some(arg) if arg.eq_ignore_ascii_case("all") => CommandRequest::Clear { offset: None },
+        Some(arg) => CommandRequest::Clear {
+            offset: Some(parse_offset_arg(arg)?),
+        },
+    };
+    let response = send_request(&request)?;
+    let parsed: RpcResponse<Vec<BufferRegionPayload>> = parse_response(&response)?;
+    let (message, regions): (Option<String>, Vec<BufferRegionPayload>) = extract_success(parsed)?;
+
+    if let Some(message) = message {
+        println!("{}", message);
+        return Ok(());
+    }
+    if regions.is_empty() {
+        println!("No loopback rings are allocated.");
+        return Ok(());
+    }
+    println!("{:<8} | {:>7} | {:>8} | Clients", "Bus", "Frames", "Peak");
+    println!(
+        "{}-+-{}-+-{}-+-{}",
+        "-".repeat(8),
+        "-".repeat(7),
+        "-".repeat(8),
+        "-".repeat(7)
+    );
+    for region in regions {
+        let bus = format!(
+            "{}-{}ch",
+            region.channel_offset + 1,
+            region.channel_offset + 2
+        );
+        let peak = if region.peak > 0.0 {
+            format!("{:.1}dB", linear_to_db(region.peak))
+        } else {
+            "silent".to_string()
+        };
+        println!(
+            "{:<8} | {:>7} | {:>8} | {}",
+            bus, region.frames, peak, region.clients
+        );
+    }
+    Ok(())
+}
+
+fn handle_log_level(level: Option<String>) -> Result<(), String> {
+    let level = level.map(|name| name.to_ascii_lowercase());
+    let response = send_request(&CommandRequest::LogLevel { level })?;
+    let parsed: RpcResponse<LogLevelPayload> = parse_response(&response)?;
+    let (message, payload): (Option<String>, LogLevelPayload) = extract_success(parsed)?;
+
+    match message {
+        Some(message) => println!("{}", message),
+        None => println!("Driver log level: {}", payload.level),
+    }
+    Ok(())
+}
+
+fn handle_latency(request: CommandRequest) -> Result<(), String> {
+    let response = send_request(&request)?;
+    let parsed: RpcResponse<LatencyPayload> = parse_response(&response)?;
+    let (message, timing): (Option<String>, LatencyPayload) = extract_success(parsed)?;
+
+    if let Some(message) = message {
+        println!("{}", message);
+    }
+    let ms = |frames: u32| frames as f64 / 48.0;
+    println!("{:<7} | {:>14} | {:>14}", "", "Latency", "Safety offset");
+    for (label, latency, safety) in [
+        ("Input", timing.input_latency, timing.input_safety_offset),
+        ("Output", timing.output_latency, timing.output_safety_offset),
+    ] {
+        println!(
+            "{:<7} | {:>5} ({:>5.1}ms) | {:>5} ({:>5.1}ms)",
+            label,
+            latency,
+            ms(latency),
+            safety,
+            ms(safety)
+        );
+    }
+    Ok(())
+}
+
+fn handle_clock_rate(ppm: Option<f64>) -> Result<(), String> {
+    let response = send_request(&CommandRequest::ClockRate { ppm })?;
+    let parsed: RpcResponse<ClockRatePayload> = parse_response(&response)?;
+    let (message, payload): (Option<String>, ClockRatePayload) = extract_success(parsed)?;
+
+    if let Some(message) = message {
+        println!("{}", message);
+    }
+    println!(
+        "Driver clock rate: {:+.1} ppm (scalar now {:.9})",
+        payload.ppm, payload.scalar
+    );
+    Ok(())
+}
+
+fn handle_bridge(
+    offset: Option<String>,
+    target: Option<String>,
+    to: Option<String>,
+    from: Option<String>,
+    remove: Option<u32>,
+) -> Result<(), String> {
+    let request = match (remove, offset, target) {
+        (Some(id), _, _) => CommandRequest::BridgeRemove { id },
+        (None, None, None) => CommandRequest::Bridges,
+        (None, Some(offset), Some(target)) => CommandRequest::BridgeAdd {
+            source: from,
+            source_offset: parse_offset_arg(&offset)?,
+            target,
+            target_offset: to
+                .as_deref()
+                .map(parse_offset_arg)
+                .transpose()?
+                .unwrap_or(0),
+        },
+        _ => return Err(
+            "Usage: prism bridge <OFFSET|CH1-CH2> <TARGET> [--to <OFFSET|CH1-CH2>] [--from DEVICE]"
+                .to_string(),
+        ),
+    };
+    let response = send_request(&request)?;
+    if matches!(request, CommandRequest::BridgeAdd { .. }) {
+        let parsed: RpcResponse<BridgePayload> = parse_response(&response)?;
+        let (message, bridge): (Option<String>, BridgePayload) = extract_success(parsed)?;
+        if let Some(message) = message {
+            println!("{}", message);
+        }
+        println!(
+            "Latency: {} frames ({:.1}ms)",
+            bridge.latency.total_frames, bridge.latency.total_ms
+        );
+        return Ok(());
+    }
+
+    let parsed: RpcResponse<Vec<BridgePayload>> = parse_response(&response)?;
+    let (message, bridges): (Option<String>, Vec<BridgePayload>) = extract_success(parsed)?;
+    if let Some(message) = message {
+        println!("{}", message);
+    }
+    if bridges.is_empty() {
+        println!("No bridges running.");
+        return Ok(());
+    }
+    println!(
+        "{:>3} | {:<28} | {:<28} | {:>24} | Xruns",
+        "ID", "From", "To", "Latency (src+ring+dst)"
+    );
+    println!(
+        "{}-+-{}-+-{}-+-{}-+-{}",
+        "-".repeat(3),
+        "-".repeat(28),
+        "-".repeat(28),
+        "-".repeat(24),
+        "-".repeat(5)
+    );
+    for bridge in bridges {
+        let end = |name: &str, offset: u32| format!("{} {}-{}ch", name, offset + 1, offset + 2);
+        let latency = format!(
+            "{}+{}+{} = {:.1}ms",
+            bridge.latency.source_frames,
+            bridge.latency.ring_frames,
+            bridge.latency.target_frames,
+            bridge.latency.total_ms
+        );
+        let state = if bridge.alive { "" } else { " (device gone)" };
+        println!(
+            "{:>3} | {:<28} | {:<28} | {:>24} | {}/{}{}",
+            bridge.id,
+            end(&bridge.source, bridge.source_offset),
+            end(&bridge.target, bridge.target_offset),
+            latency,
+            bridge.underruns,
+            bridge.overruns,
+            state
+        );
+    }
+    Ok(())
+}
+
+fn handle_clock_domain(target: Option<String>) -> Result<(), String> {
+    let request = match target {
+        None => CommandRequest::ClockDomain {
+            domain: None,
+            device: None,
+        },
+        Some(target) => match target.parse::<u32>() {
+            Ok(domain) => CommandRequest::ClockDomain {
+                domain: Some(domain),
+                device: None,
+            },
+            Err(_) => CommandRequest::ClockDomain {
+                domain: None,
+                device: Some(target),
+            },
+        },
+    };
+    let response = send_request(&request)?;
+    let parsed: RpcResponse<ClockDomainPayload> = parse_response(&response)?;
+    let (message, payload): (Option<String>, ClockDomainPayload) = extract_success(parsed)?;
+
+    if let Some(message) = message {
+        println!("{}", message);
+        return Ok(());
+    }
+    let shared: Vec<&str> = payload
+        .devices
+        .iter()
+        .filter(|device| payload.domain != 0 && device.clock_domain == payload.domain)
+        .map(|device| device.name.as_str())
+        .collect();
+    match (payload.domain, shared.is_empty()) {
+        (0, _) => println!("Prism clock domain: none"),
+        (domain, true) => println!("Prism clock domain: {} (no other device)", domain),
+        (domain, false) => println!(
+            "Prism clock domain: {} (shared with {})",
+            domain,
+            shared.join(", ")
+        ),
+    }
+    if payload.devices.is_empty() {
+        return Ok(());
+    }
+    println!();
+    println!("{:<32} | {:>12} | UID", "Device", "Clock domain");
+    println!(
+        "{}-+-{}-+-{}",
+        "-".repeat(32),
+        "-".repeat(12),
+        "-".repeat(24)
+    );
+    for device in &payload.devices {
+        let domain = match device.clock_domain {
+            0 => "-".to_string(),
+            domain => domain.to_string(),
+        };
+        println!("{:<32} | {:>12} | {}", device.name, domain, device.uid);
+    }
+    Ok(())
+}
+
+fn handle_output_mode(mode: Option<String>) -> Result<(), String> {
+    let full_bus = match mode.as_deref().map(str::to_ascii_lowercase).as_deref() {
+        None => None,
+        Some("stereo") => Some(false),
+        Some("bus") => Some(true),
+        Some(other) => {
+            return Err(format!(
+                "unknown output mode '{}' (expected stereo or bus)",
+                other
+            ))
+        }
+    };
+    let response = send_request(&CommandRequest::OutputMode { full_bus })?;
+    let parsed: RpcResponse<OutputModePayload> = parse_response(&response)?;
+    let (message, payload): (Option<String>, OutputModePayload) = extract_success(parsed)?;
+
+    if let Some(message) = message {
+        println!("{}", message);
+    }
+    if payload.full_bus {
+        println!(
+            "Output mode: bus ({} channels, mapped 1:1)",
+            payload.bus_channels
+        );
+    } else {
+        println!("Output mode: stereo (routed per client)");
+    }
+    Ok(())
+}
+
+fn handle_system_mix(pair: Option<String>) -> Result<(), String> {
+    // on/off switch capture of the mix; anything else names the pair it moves to
+    let (offset, enabled) = match pair.as_deref() {
+        None => (None, None),
+        Some(state) if state.eq_ignore_ascii_case("on") => (None, Some(true)),
+        Some(state) if state.eq_ignore_ascii_case("off") => (None, Some(false)),
+        Some(pair) => (Some(parse_offset_arg(pair)?), None),
+    };
+    let response = send_request(&CommandRequest::SystemMix { offset, enabled })?;
+    let parsed: RpcResponse<SystemMixPayload> = parse_response(&response)?;
+    let (message, payload): (Option<String>, SystemMixPayload) = extract_success(parsed)?;
+
+    if let Some(message) = message {
+        println!("{}", message);
+    }
+    if payload.enabled {
+        println!(
+            "System mix: {}-{}",
+            payload.channel_offset + 1,
+            payload.channel_offset + 2
+        );
+    } else {
+        println!(
+            "System mix: off (would land on {}-{}); only routed apps reach the bus",
+            payload.channel_offset + 1,
+            payload.channel_offset + 2
+        );
+    }
+    Ok(())
+}
+
+fn handle_auto_assign(policy: Option<String>) -> Result<(), String> {
+    let policy = match policy {
+        None => None,
+        Some(name) => Some(AssignPolicy::parse(&name).ok_or_else(|| {
+            format!(
+                "unknown policy '{}' (expected disabled, first-free, round-robin or sticky-bundle)",
+                name
+            )
+        })?),
+    };
+    let response = send_request(&CommandRequest::AutoAssign { policy })?;
+    let parsed: RpcResponse<AutoAssignPayload> = parse_response(&response)?;
+    let (message, payload): (Option<String>, AutoAssignPayload) = extract_success(parsed)?;
+
+    if let Some(message) = message {
+        println!("{}", message);
+    }
+    match payload.saved {
+        Some(saved) if saved == payload.policy => {
+            println!("Auto-assign: {} (kept by prismd)", payload.policy.name())
+        }
+        _ => println!("Auto-assign: {} (from Driver.plist)", payload.policy.name()),
+    }
+    Ok(())
+}
+
+fn handle_stats() -> Result<(), String> {
+    let response = send_request(&CommandRequest::Stats)?;
+    let parsed: RpcResponse<IoStatsPayload> = parse_response(&response)?;
+    let (_message, stats): (Option<String>, IoStatsPayload) = extract_success(parsed)?;
+
+    println!("IO statistics:");
+    println!("  cycles served : {}", stats.cycles_served);
+    println!("  output cycles : {}", stats.output_cycles);
+    println!("  wrap-arounds  : {}", stats.wrap_arounds);
+    println!("  stale reads   : {}", stats.stale_reads);
+    println!("  overloads     : {}", stats.overloads);
+    println!(
+        "  late cycles   : {} ({} missed write{})",
+        stats.late_cycles,
+        stats.missed_writes,
+        if stats.missed_writes == 1 { "" } else { "s" }
+    );
+    println!(
+        "  watchdog      : {} stalled ring(s) cleared",
+        stats.watchdog_clears
+    );
+    println!(
+        "  client slots  : {}/{} occupied, {} collision{}, grown {} time{}",
+        stats.slots_occupied,
+        stats.slot_table_size,
+        stats.slot_collisions,
+        if stats.slot_collisions == 1 { "" } else { "s" },
+        stats.slot_table_growths,
+        if stats.slot_table_growths == 1 {
+            ""
+        } else {
+            "s"
+        }
+    );
+
+    let active: Vec<(usize, u64)> = stats
+        .frames_written
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|(_, frames)| *frames > 0)
+        .collect();
+    if active.is_empty() {
+        println!("  No frames written to any bus yet.");
+    } else {
+        println!("  Frames written per bus:");
+        for (pair, frames) in active {
+            println!("    {:>2}-{:<2}ch  {}", pair * 2 + 1, pair * 2 + 2, frames);
+        }
+    }
+    Ok(())
+}
+
+fn handle_diag() -> Result<(), String> {
+    let response = send_request(&CommandRequest::Diag)?;
+    let parsed: RpcResponse<DiagPayload> = parse_response(&response)?;
+    let (_message, diag): (Option<String>, DiagPayload) = extract_success(parsed)?;
+
+    println!(
+        "Capture fan-out: {} active reader{} ({} seen)",
+        diag.active_capture_readers,
+        if diag.active_capture_readers == 1 {
+            ""
+        } else {
+            "s"
+        },
+        diag.capture_readers.len()
+    );
+    if diag.capture_readers.is_empty() {
+        return Ok(());
+    }
+    println!(
+        "{:<9} | {:<7} | {:<20} | {:>12} | {:>9} | State",
+        "Client", "PID", "Process", "Read time", "Cycles"
+    );
+    println!(
+        "{}-+-{}-+-{}-+-{}-+-{}-+-{}",
+        "-".repeat(9),
+        "-".repeat(7),
+        "-".repeat(20),
+        "-".repeat(12),
+        "-".repeat(9),
+        "-".repeat(6)
+    );
+    for reader in &diag.capture_readers {
+        println!(
+            "{:<9} | {:<7} | {:<20} | {:>12.0} | {:>9} | {}",
+            reader.client_id,
+            reader.pid,
+            reader.process_name.as_deref().unwrap_or("<unknown>"),
+            reader.last_read_sample_time,
+            reader.reads,
+            if reader.active { "active" } else { "idle" }
+        );
+    }
+    Ok(())
+}
+
+fn handle_meters(watch: bool, history: Option<String>) -> Result<(), String> {
+    if let Some(duration) = history {
+        let seconds = parse_duration_secs(&duration)
+            .ok_or_else(|| format!("invalid duration '{}' (expected e.g. 5m)", duration))?;
+        return execute_meter_history(seconds);
+    }
+    if !watch {
+        return execute_meters();
+    }
+
+    loop {
+        // Clear the screen and home the cursor before each frame
+        print!("\x1b[2J\x1b[H");
+        execute_meters()?;
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+}
+
+fn handle_trim(channels: Option<String>, gain: Option<String>) -> Result<(), String> {
+    let request = match (channels, gain) {
+        (None, _) => CommandRequest::Trims,
+        (Some(_), None) => {
+            return Err(
+                "Usage: prism trim <CH|CH1-CH2> <GAIN> (e.g. prism trim 3-4 -3dB)".to_string(),
+            )
+        }
+        (Some(channels), Some(gain)) => {
+            let channels = match parse_channel_range(&channels) {
+                Some((first, last)) if (1..=last).contains(&first) => (first..=last).collect(),
+                Some(_) => {
+                    return Err(
+                        "Channel range must be ascending and start at 1 or above".to_string()
+                    )
+                }
+                None => match channels.parse::<u32>() {
+                    Ok(ch) if ch >= 1 => vec![ch],
+                    _ => {
+                        return Err(
+                            "CH must be a channel number >= 1 or a range like 3-4".to_string()
+                        )
+                    }
+                },
+            };
+            let gain_db = parse_gain_db(&gain)
+                .ok_or_else(|| format!("invalid gain '{}' (expected e.g. -3dB)", gain))?;
+            CommandRequest::Trim { channels, gain_db }
+        }
+    };
+    let listing = matches!(request, CommandRequest::Trims);
+
+    let response = send_request(&request)?;
+    let parsed: RpcResponse<Vec<ChannelTrimPayload>> = parse_response(&response)?;
+    let (_message, trims): (Option<String>, Vec<ChannelTrimPayload>) = extract_success(parsed)?;
+    if trims.is_empty() && listing {
+        println!("All input channels are at 0 dB.");
+        return Ok(());
+    }
+    for trim in trims {
+        println!("  ch {:>2}  {}", trim.channel, format_trim(trim.gain_db));
+    }
+    Ok(())
+}
+
+fn format_trim(db: f32) -> String {
+    // The driver mutes the channel at the bottom of its range
+    if db <= -64.0 {
+        "muted".to_string()
+    } else {
+        format!("{:+.1} dB", db)
+    }
+}
+
+const AGC_USAGE: &str = "Usage: prism agc <APP_NAME> --target <LUFS> [--range <dB>] (or --off)";
+
+fn handle_agc(
+    app_name: Option<String>,
+    target: Option<String>,
+    range: Option<String>,
+    off: bool,
+) -> Result<(), String> {
+    let request = match app_name {
+        None => CommandRequest::AgcStatus,
+        Some(app_name) => {
+            let target_lufs = match target {
+                Some(raw) => Some(parse_lufs(&raw)?),
+                None if off => None,
+                None => return Err(AGC_USAGE.to_string()),
+            };
+            let range_db = match range {
+                Some(raw) => Some(
+                    parse_gain_db(&raw)
+                        .filter(|db| *db >= 0.0)
+                        .ok_or_else(|| format!("invalid range '{}' (expected e.g. 12dB)", raw))?,
+                ),
+                None => None,
+            };
+            CommandRequest::Agc {
+                app_name,
+                target_lufs,
+                range_db,
+            }
+        }
+    };
+
+    let response = send_request(&request)?;
+    let parsed: RpcResponse<Vec<AgcPayload>> = parse_response(&response)?;
+    let (message, entries): (Option<String>, Vec<AgcPayload>) = extract_success(parsed)?;
+    if let Some(msg) = message {
+        println!("{}", msg);
+    }
+    if entries.is_empty() {
+        if matches!(request, CommandRequest::AgcStatus) {
+            println!("AGC is not enabled for any app.");
+        }
+        return Ok(());
+    }
+
+    println!(
+        "{:<24} | {:>8} | {:>7} | {:>8} | {:>10}",
+        "App", "Target", "Range", "Gain", "Loudness"
+    );
+    println!(
+        "{}-+-{}-+-{}-+-{}-+-{}",
+        "-".repeat(24),
+        "-".repeat(8),
+        "-".repeat(7),
+        "-".repeat(8),
+        "-".repeat(10)
+    );
+    for entry in entries {
+        let loudness = entry
+            .loudness_lufs
+            .map(|lufs| format!("{:.1} LUFS", lufs))
+            .unwrap_or_else(|| "-".to_string());
+        println!(
+            "{:<24} | {:>8} | {:>7} | {:>8} | {:>10}",
+            entry.app_name,
+            format!("{:.1}", entry.target_lufs),
+            format!("±{:.0}dB", entry.range_db),
+            format!("{:+.1}dB", entry.gain_db),
+            loudness
+        );
+    }
+    Ok(())
+}
+
+const CLASSIFY_USAGE: &str =
+    "Usage: prism classify <BUNDLE_ID> <music|voice|game|system> (or --clear)";
+
+fn handle_classify(
+    bundle_id: Option<String>,
+    content_type: Option<String>,
+    clear: bool,
+) -> Result<(), String> {
+    let request = match (bundle_id, content_type) {
+        (None, _) => CommandRequest::Classifications,
+        (Some(bundle_id), None) if clear => CommandRequest::Classify {
+            bundle_id,
+            content_type: None,
+        },
+        (Some(_), None) => return Err(CLASSIFY_USAGE.to_string()),
+        (Some(bundle_id), Some(name)) => CommandRequest::Classify {
+            bundle_id,
+            content_type: Some(ContentType::parse(&name).ok_or_else(|| {
+                format!(
+                    "unknown content type '{}' (expected music, voice, game or system)",
+                    name
+                )
+            })?),
+        },
+    };
+
+    let response = send_request(&request)?;
+    let parsed: RpcResponse<Vec<ClassificationPayload>> = parse_response(&response)?;
+    let (message, entries): (Option<String>, Vec<ClassificationPayload>) = extract_success(parsed)?;
+    if let Some(msg) = message {
+        println!("{}", msg);
+    }
+    if entries.is_empty() {
+        if matches!(request, CommandRequest::Classifications) {
+            println!("No apps are playing and no content types are set.");
+        }
+        return Ok(());
+    }
+
+    println!("{:<24} | {:<32} | {:<8}", "App", "Bundle ID", "Type");
+    println!(
+        "{}-+-{}-+-{}",
+        "-".repeat(24),
+        "-".repeat(32),
+        "-".repeat(8)
+    );
+    for entry in entries {
+        let content_type = match entry.content_type {
+            Some(content_type) if entry.overridden => format!("{} (set)", content_type.name()),
+            Some(content_type) => content_type.name().to_string(),
+            None => "-".to_string(),
+        };
+        println!(
+            "{:<24} | {:<32} | {:<8}",
+            entry.app_name.as_deref().unwrap_or("(not running)"),
+            entry.bundle_id.as_deref().unwrap_or("-"),
+            content_type
+        );
+    }
+    Ok(())
+}
+
+const MATRIX_USAGE: &str = "Usage: prism matrix <APP_NAME> <L|R:CH[@GAIN]>... (or --clear)";
+
+fn handle_matrix(app_name: Option<String>, routes: Vec<String>, clear: bool) -> Result<(), String> {
+    let request = match app_name {
+        None => CommandRequest::Matrices,
+        Some(_) if routes.is_empty() && !clear => return Err(MATRIX_USAGE.to_string()),
+        Some(app_name) => CommandRequest::Matrix {
+            app_name,
+            routes: routes
+                .iter()
+                .map(|route| parse_matrix_route(route))
+                .collect::<Result<_, _>>()?,
+        },
+    };
+
+    let response = send_request(&request)?;
+    let parsed: RpcResponse<Vec<ClientMatrixPayload>> = parse_response(&response)?;
+    let (message, matrices): (Option<String>, Vec<ClientMatrixPayload>) = extract_success(parsed)?;
+    if let Some(msg) = message {
+        println!("{}", msg);
+    }
+    if matrices.is_empty() {
+        if matches!(request, CommandRequest::Matrices) {
+            println!("No app is routed by a matrix.");
+        }
+        return Ok(());
+    }
+
+    println!("{:<24} | {:>7} | Routes", "App", "PID");
+    println!(
+        "{}-+-{}-+-{}",
+        "-".repeat(24),
+        "-".repeat(7),
+        "-".repeat(30)
+    );
+    for matrix in matrices {
+        let routes: Vec<String> = matrix
+            .routes
+            .iter()
+            .map(|route| {
+                let side = if route.source == 0 { "L" } else { "R" };
+                if route.gain_db == 0.0 {
+                    format!("{}:{}", side, route.channel)
+                } else {
+                    format!("{}:{}@{:+.1}dB", side, route.channel, route.gain_db)
+                }
+            })
+            .collect();
+        println!(
+            "{:<24} | {:>7} | {}",
+            matrix.app_name.as_deref().unwrap_or("-"),
+            matrix.pid,
+            routes.join(" ")
+        );
+    }
+    Ok(())
+}
+
+// Parse a matrix route like "L:5", "R:12" or "L:7@-6dB" (1-based channel)
+fn parse_matrix_route(s: &str) -> Result<MatrixRoutePayload, String> {
+    let invalid = || format!("invalid route '{}' (expected e.g. L:5 or R:12@-6dB)", s);
+    let (side, rest) = s.split_once(':').ok_or_else(invalid)?;
+    let source = match side.trim() {
+        "L" | "l" => 0,
+        "R" | "r" => 1,
+        _ => return Err(invalid()),
+    };
+    let (channel, gain) = match rest.split_once('@') {
+        Some((channel, gain)) => (channel, Some(gain)),
+        None => (rest, None),
+    };
+    let channel = channel
+        .trim()
+        .parse::<u32>()
+        .ok()
+        .filter(|channel| *channel >= 1)
+        .ok_or_else(invalid)?;
+    let gain_db = match gain {
+        Some(gain) => parse_gain_db(gain).ok_or_else(invalid)?,
+        None => 0.0,
+    };
+    Ok(MatrixRoutePayload {
+        source,
+        channel,
+        gain_db,
+    })
+}
+
+fn handle_monitor(state: Option<String>, sends: Vec<String>) -> Result<(), String> {
+    // clap takes the first send for the state when on/off is left out
+    let mut sends = sends;
+    let enabled = match state.as_deref() {
+        None => None,
+        Some("on") => Some(true),
+        Some("off") => Some(false),
+        Some(_) => {
+            sends.insert(0, state.unwrap_or_default());
+            None
+        }
+    };
+    let request = CommandRequest::Monitor {
+        enabled,
+        sends: sends
+            .iter()
+            .map(|send| parse_monitor_send(send))
+            .collect::<Result<_, _>>()?,
+    };
+
+    let response = send_request(&request)?;
+    let parsed: RpcResponse<MonitorPayload> = parse_response(&response)?;
+    let (_, monitor): (Option<String>, MonitorPayload) = extract_success(parsed)?;
+    println!("Monitor: {}", if monitor.enabled { "on" } else { "off" });
+    if monitor.sends.is_empty() {
+        println!("No pair is sent to 1-2.");
+        return Ok(());
+    }
+    for send in monitor.sends {
+        let gain_db = send.gain_db.unwrap_or(0.0);
+        println!(
+            "  {}-{} -> 1-2 at {:+.1}dB",
+            send.channel_offset + 1,
+            send.channel_offset + 2,
+            gain_db
+        );
+    }
+    Ok(())
+}
+
+fn handle_solo(state: Option<String>, pairs: Vec<String>) -> Result<(), String> {
+    // clap takes the first pair for the state when off is left out
+    let mut pairs = pairs;
+    let clear = match state {
+        None => false,
+        Some(state) if state.eq_ignore_ascii_case("off") => true,
+        Some(state) => {
+            pairs.insert(0, state);
+            false
+        }
+    };
+    let (mut solo, mut unsolo) = (Vec::new(), Vec::new());
+    for arg in &pairs {
+        let (pair, release) = match arg.split_once('@') {
+            Some((pair, flag)) if flag.eq_ignore_ascii_case("off") => (pair, true),
+            Some(_) => {
+                return Err(format!(
+                    "invalid pair '{}' (expected e.g. 5-6 or 5-6@off)",
+                    arg
+                ))
+            }
+            None => (arg.as_str(), false),
+        };
+        let offset = parse_offset_arg(pair)?;
+        if release {
+            unsolo.push(offset);
+        } else {
+            solo.push(offset);
+        }
+    }
+
+    let response = send_request(&CommandRequest::Solo {
+        clear,
+        solo,
+        unsolo,
+    })?;
+    let parsed: RpcResponse<SoloPayload> = parse_response(&response)?;
+    let (_, payload): (Option<String>, SoloPayload) = extract_success(parsed)?;
+    if payload.pairs.is_empty() {
+        println!("No pair is soloed.");
+        return Ok(());
+    }
+    let pairs: Vec<String> = payload
+        .pairs
+        .iter()
+        .map(|offset| format!("{}-{}", offset + 1, offset + 2))
+        .collect();
+    println!(
+        "Soloed: {} (captures hear silence on every other pair)",
+        pairs.join(", ")
+    );
+    Ok(())
+}
+
+// Parse a monitor send like "3-4", "5-6@-6dB" or "5-6@off" (1-based pair)
+fn parse_monitor_send(s: &str) -> Result<MonitorSendPayload, String> {
+    let invalid = || {
+        format!(
+            "invalid send '{}' (expected e.g. 3-4, 5-6@-6dB or 5-6@off)",
+            s
+        )
+    };
+    let (pair, gain) = match s.split_once('@') {
+        Some((pair, gain)) => (pair, Some(gain)),
+        None => (s, None),
+    };
+    let channel_offset = match parse_channel_range(pair.trim()) {
+        Some((ch1, ch2)) if ch1 >= 1 && ch2 == ch1 + 1 && ch1 % 2 == 1 => ch1 - 1,
+        _ => return Err(invalid()),
+    };
+    let gain_db = match gain {
+        Some(gain) if gain.trim().eq_ignore_ascii_case("off") => None,
+        Some(gain) => Some(parse_gain_db(gain).ok_or_else(invalid)?),
+        None => Some(0.0),
+    };
+    Ok(MonitorSendPayload {
+        channel_offset,
+        gain_db,
+    })
+}
+
+fn handle_selftest(clients: usize, seconds: f64) -> Result<(), String> {
+    let exe = env::current_exe().map_err(|err| format!("cannot locate prism: {}", err))?;
+    let sim = exe.with_file_name("prism-sim");
+    let status = std::process::Command::new(&sim)
+        .args(["--route", "--clients", &clients.to_string()])
+        .args(["--seconds", &seconds.to_string()])
+        .status()
+        .map_err(|err| format!("cannot run {}: {}", sim.display(), err))?;
+    if status.success() {
+        println!("Self-test passed.");
+        Ok(())
+    } else {
+        Err("self-test failed".to_string())
+    }
+}
+
+fn handle_benchmark(requests: usize, concurrency: usize, request: &str) -> Result<(), String> {
+    // Read-only requests only, so a benchmark never changes routing
+    let command = match request {
+        "clients" => CommandRequest::Clients,
+        "apps" => CommandRequest::Apps,
+        "list" => CommandRequest::List,
+        "stats" => CommandRequest::Stats,
+        "meters" => CommandRequest::Meters,
+        _ => {
+            return Err(format!(
+                "Unknown request '{}'. Use clients, apps, list, stats or meters.",
+                request
+            ))
+        }
+    };
+    if requests == 0 {
+        return Err("--requests must be at least 1".to_string());
+    }
+    let concurrency = concurrency.clamp(1, requests);
+
+    // Each worker sends its share over fresh connections, like any other CLI call
+    let started = Instant::now();
+    let results: Vec<(Vec<Duration>, Vec<String>)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..concurrency)
+            .map(|worker| {
+                let command = &command;
+                let share = requests / concurrency + usize::from(worker < requests % concurrency);
+                scope.spawn(move || {
+                    let mut latencies = Vec::with_capacity(share);
+                    let mut errors = Vec::new();
+                    for _ in 0..share {
+                        let sent = Instant::now();
+                        let outcome = send_request(command).and_then(|response| {
+                            let parsed: RpcResponse<serde_json::Value> = parse_response(&response)?;
+                            extract_success(parsed).map(|_| ())
+                        });
+                        match outcome {
+                            Ok(()) => latencies.push(sent.elapsed()),
+                            Err(err) => errors.push(err),
+                        }
+                    }
+                    (latencies, errors)
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap_or_default())
+            .collect()
+    });
+    let elapsed = started.elapsed();
+
+    let mut latencies: Vec<Duration> = Vec::with_capacity(requests);
+    let mut errors: Vec<String> = Vec::new();
+    for (worker_latencies, worker_errors) in results {
+        latencies.extend(worker_latencies);
+        errors.extend(worker_errors);
+    }
+    if latencies.is_empty() {
+        return Err(format!(
+            "all {} requests failed: {}",
+            requests,
+            errors
+                .first()
+                .map(String::as_str)
+                .unwrap_or("unknown error")
+        ));
+    }
+    latencies.sort_unstable();
+
+    println!(
+        "{} × {} over {} connection{} in {:.2} s ({:.1} req/s)",
+        latencies.len(),
+        request,
+        concurrency,
+        if concurrency == 1 { "" } else { "s" },
+        elapsed.as_secs_f64(),
+        latencies.len() as f64 / elapsed.as_secs_f64()
+    );
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let percentile = |p: f64| {
+        let rank = ((p / 100.0) * latencies.len() as f64).ceil() as usize;
+        ms(latencies[rank.clamp(1, latencies.len()) - 1])
+    };
+    println!(
+        "{:>8} | {:>8} | {:>8} | {:>8} | {:>8}",
+        "min ms", "p50 ms", "p90 ms", "p99 ms", "max ms"
+    );
+    println!(
+        "{:>8.2} | {:>8.2} | {:>8.2} | {:>8.2} | {:>8.2}",
+        ms(latencies[0]),
+        percentile(50.0),
+        percentile(90.0),
+        percentile(99.0),
+        ms(latencies[latencies.len() - 1])
+    );
+    if !errors.is_empty() {
+        println!(
+            "{} request(s) failed; first error: {}",
+            errors.len(),
+            errors[0]
+        );
+    }
+    Ok(())
+}
+
+fn handle_capture_allow(
+    offset: Option<String>,
+    bundle_id: Option<String>,
+    clear: bool,
+) -> Result<(), String> {
+    let Some(offset_arg) = offset else {
+        return execute_capture_rules();
+    };
+    let offset = parse_offset_arg(&offset_arg)?;
+    if bundle_id.is_none() && !clear {
+        return Err(
+            "Usage: prism capture-allow <OFFSET|CH1-CH2> <BUNDLE_ID> (or --clear)".to_string(),
+        );
+    }
+
+    let response = send_request(&CommandRequest::CaptureAllow { offset, bundle_id })?;
+    let parsed: RpcResponse<CaptureRulePayload> = parse_response(&response)?;
+    let (message, rule): (Option<String>, CaptureRulePayload) = extract_success(parsed)?;
+    let bus = format!("{}-{}ch", rule.channel_offset + 1, rule.channel_offset + 2);
+    if rule.bundle_id.is_empty() {
+        println!(
+            "{}: {} readable by every capture client",
+            message.unwrap_or_default(),
+            bus
+        );
+    } else {
+        println!(
+            "{}: {} readable only by {}",
+            message.unwrap_or_default(),
+            bus,
+            rule.bundle_id
+        );
+    }
+    Ok(())
+}
+
+fn handle_capture_list(state: Option<String>, entries: Vec<String>) -> Result<(), String> {
+    // clap takes the first entry for the state when on/off is left out
+    let mut entries = entries;
+    let enabled = match state {
+        None => None,
+        Some(state) if state.eq_ignore_ascii_case("on") => Some(true),
+        Some(state) if state.eq_ignore_ascii_case("off") => Some(false),
+        Some(state) => {
+            entries.insert(0, state);
+            None
+        }
+    };
+    let (mut allow, mut revoke) = (Vec::new(), Vec::new());
+    for entry in entries {
+        match entry.split_once('@') {
+            Some((id, flag)) if flag.eq_ignore_ascii_case("off") => revoke.push(id.to_string()),
+            Some(_) => {
+                return Err(format!(
+                    "invalid entry '{}' (expected a bundle ID or PID, e.g. 4711 or 4711@off)",
+                    entry
+                ))
+            }
+            None => allow.push(entry),
+        }
+    }
+
+    let response = send_request(&CommandRequest::CaptureList {
+        enabled,
+        allow,
+        revoke,
+    })?;
+    let parsed: RpcResponse<CaptureListPayload> = parse_response(&response)?;
+    let (_, list): (Option<String>, CaptureListPayload) = extract_success(parsed)?;
+    println!(
+        "Capture list: {}",
+        if list.enabled {
+            "on (unlisted clients read silence)"
+        } else {
+            "off (every client may capture)"
+        }
+    );
+    for bundle_id in &list.bundle_ids {
+        println!("  {}", bundle_id);
+    }
+    for pid in &list.pids {
+        println!("  PID {}", pid);
+    }
+    Ok(())
+}
+
+fn execute_capture_rules() -> Result<(), String> {
+    let response = send_request(&CommandRequest::CaptureRules)?;
+    let parsed: RpcResponse<Vec<CaptureRulePayload>> = parse_response(&response)?;
+    let (_message, rules): (Option<String>, Vec<CaptureRulePayload>) = extract_success(parsed)?;
+
+    if rules.is_empty() {
+        println!("No capture restrictions; every pair is readable by all capture clients.");
+        return Ok(());
+    }
+    println!("{:<8} | Allowed bundle", "Bus");
+    println!("{}-+-{}", "-".repeat(8), "-".repeat(14));
+    for rule in rules {
+        let bus = format!("{}-{}ch", rule.channel_offset + 1, rule.channel_offset + 2);
+        println!("{:<8} | {}", bus, rule.bundle_id);
+    }
+    Ok(())
+}
+
+fn handle_capture_mask(pid: Option<i32>, pairs: Vec<String>) -> Result<(), String> {
+    let Some(pid) = pid else {
+        return execute_capture_masks();
+    };
+    let offsets = match pairs.as_slice() {
+        [] => return Err("Usage: prism capture-mask <PID> <CH1-CH2...|all>".to_string()),
+        [all] if all.eq_ignore_ascii_case("all") => None,
+        pairs => Some(
+            pairs
+                .iter()
+                .map(|pair| parse_offset_arg(pair))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+    };
+
+    let response = send_request(&CommandRequest::CaptureMask { pid, offsets })?;
+    let parsed: RpcResponse<CaptureMaskPayload> = parse_response(&response)?;
+    let (message, mask): (Option<String>, CaptureMaskPayload) = extract_success(parsed)?;
+    println!(
+        "{}: PID {} reads {}",
+        message.unwrap_or_default(),
+        mask.pid,
+        format_mask_pairs(&mask.offsets)
+    );
+    Ok(())
+}
+
+fn execute_capture_masks() -> Result<(), String> {
+    let response = send_request(&CommandRequest::CaptureMasks)?;
+    let parsed: RpcResponse<Vec<CaptureMaskPayload>> = parse_response(&response)?;
+    let (_message, masks): (Option<String>, Vec<CaptureMaskPayload>) = extract_success(parsed)?;
+
+    if masks.is_empty() {
+        println!("No capture masks; every capture client reads every pair.");
+        return Ok(());
+    }
+    println!("{:<8} | Readable pairs", "PID");
+    println!("{}-+-{}", "-".repeat(8), "-".repeat(14));
+    for mask in masks {
+        println!("{:<8} | {}", mask.pid, format_mask_pairs(&mask.offsets));
+    }
+    Ok(())
+}
+
+fn execute_capture_exclusions() -> Result<(), String> {
+    let response = send_request(&CommandRequest::CaptureExclusions)?;
+    let parsed: RpcResponse<Vec<String>> = parse_response(&response)?;
+    let (_message, bundle_ids): (Option<String>, Vec<String>) = extract_success(parsed)?;
+
+    if bundle_ids.is_empty() {
+        println!("No capture exclusions (capture_exclude in Driver.plist).");
+        return Ok(());
+    }
+    println!("Never copied into the loopback:");
+    for bundle_id in bundle_ids {
+        println!("  {}", bundle_id);
+    }
+    Ok(())
+}
+
+fn format_mask_pairs(offsets: &[u32]) -> String {
+    if offsets.is_empty() {
+        return "nothing".to_string();
+    }
+    offsets
+        .iter()
+        .map(|offset| format!("{}-{}", offset + 1, offset + 2))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn handle_pair_policy(offset: Option<String>, policy: Option<String>) -> Result<(), String> {
+    let Some(offset_arg) = offset else {
+        return execute_pair_policies();
+    };
+    let usage = "Usage: prism pair-policy <OFFSET|CH1-CH2> <reject|mix|replace|duck>";
+    let offset = parse_offset_arg(&offset_arg)?;
+    let policy_arg = policy.ok_or_else(|| usage.to_string())?;
+    let policy = ConflictPolicy::parse(&policy_arg.to_ascii_lowercase())
+        .ok_or_else(|| format!("Unknown policy '{}'. {}", policy_arg, usage))?;
+
+    let response = send_request(&CommandRequest::PairPolicy { offset, policy })?;
+    let parsed: RpcResponse<PairPolicyPayload> = parse_response(&response)?;
+    let (message, entry): (Option<String>, PairPolicyPayload) = extract_success(parsed)?;
+    println!(
+        "{}: {}-{}ch",
+        message.unwrap_or_default(),
+        entry.channel_offset + 1,
+        entry.channel_offset + 2
+    );
+    Ok(())
+}
+
+fn execute_pair_policies() -> Result<(), String> {
+    let response = send_request(&CommandRequest::PairPolicies)?;
+    let parsed: RpcResponse<Vec<PairPolicyPayload>> = parse_response(&response)?;
+    let (_message, entries): (Option<String>, Vec<PairPolicyPayload>) = extract_success(parsed)?;
+
+    if entries.is_empty() {
+        println!("Every pair mixes apps routed to it (the default policy).");
+        return Ok(());
+    }
+    println!("{:<8} | Policy", "Bus");
+    println!("{}-+-{}", "-".repeat(8), "-".repeat(7));
+    for entry in entries {
+        let bus = format!(
+            "{}-{}ch",
+            entry.channel_offset + 1,
+            entry.channel_offset + 2
+        );
+        println!("{:<8} | {}", bus, entry.policy.name());
+    }
+    Ok(())
+}
+
+fn handle_gap_policy(
+    offset: Option<String>,
+    mode: Option<String>,
+    max_hold: Option<u32>,
+) -> Result<(), String> {
+    let usage = "Usage: prism gap-policy <OFFSET|CH1-CH2> <zero-fill|hold-last> [--max-hold MS]";
+    let request = match (offset, mode) {
+        (None, None) if max_hold.is_none() => CommandRequest::GapPolicies,
+        (Some(offset), Some(mode_arg)) => {
+            let mode = GapMode::parse(&mode_arg)
+                .ok_or_else(|| format!("Unknown gap mode '{}'. {}", mode_arg, usage))?;
+            if mode == GapMode::ZeroFill && max_hold.is_some() {
+                return Err("--max-hold applies to hold-last only".to_string());
+            }
+            CommandRequest::GapPolicy {
+                offset: parse_offset_arg(&offset)?,
+                mode,
+                max_hold_ms: max_hold,
+            }
+        }
+        _ => return Err(usage.to_string()),
+    };
+
+    let response = send_request(&request)?;
+    let parsed: RpcResponse<Vec<GapPolicyPayload>> = parse_response(&response)?;
+    let (message, entries): (Option<String>, Vec<GapPolicyPayload>) = extract_success(parsed)?;
+    if let Some(msg) = message {
+        println!("{}", msg);
+        return Ok(());
+    }
+    if entries.is_empty() {
+        println!("Every pair zero-fills gaps (the default policy).");
+        return Ok(());
+    }
+    println!("{:<8} | Policy", "Bus");
+    println!("{}-+-{}", "-".repeat(8), "-".repeat(20));
+    for entry in entries {
+        let bus = format!(
+            "{}-{}ch",
+            entry.channel_offset + 1,
+            entry.channel_offset + 2
+        );
+        println!(
+            "{:<8} | {} (up to {} ms)",
+            bus,
+            entry.mode.name(),
+            entry.max_hold_ms
+        );
+    }
+    Ok(())
+}
+
+fn handle_buses() -> Result<(), String> {
+    let response = send_request(&CommandRequest::Buses)?;
+    let parsed: RpcResponse<Vec<BusPayload>> = parse_response(&response)?;
+    let (_message, buses): (Option<String>, Vec<BusPayload>) = extract_success(parsed)?;
+
+    if buses.is_empty() {
+        println!("The device has no bus pairs beyond 1-2.");
+        return Ok(());
+    }
+    println!(
+        "{:<8} | {:<12} | {:<16} | {:>10} | Apps",
+        "Bus", "Name", "State", "Last audio"
+    );
+    println!(
+        "{}-+-{}-+-{}-+-{}-+-{}",
+        "-".repeat(8),
+        "-".repeat(12),
+        "-".repeat(16),
+        "-".repeat(10),
+        "-".repeat(4)
+    );
+    for bus in buses {
+        let name = format!("{}-{}ch", bus.channel_offset + 1, bus.channel_offset + 2);
+        let state = match (&bus.reserved_by, bus.writers.is_empty()) {
+            (Some(owner), _) => format!("reserved: {}", owner),
+            (None, true) => "free".to_string(),
+            (None, false) => "in use".to_string(),
+        };
+        let last_audio = match bus.last_audio_ms {
+            None => "never".to_string(),
+            Some(ms) if ms < 1000 => "now".to_string(),
+            Some(ms) => format_age(ms / 1000),
+        };
+        let writers = bus
+            .writers
+            .iter()
+            .map(|writer| {
+                let name = writer
+                    .app_name
+                    .as_deref()
+                    .or(writer.bundle_id.as_deref())
+                    .unwrap_or("<unknown>");
+                format!(
+                    "{} (pid {}{})",
+                    name,
+                    writer.pid,
+                    if writer.is_running { "" } else { ", idle" }
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!(
+            "{:<8} | {:<12} | {:<16} | {:>10} | {}",
+            name,
+            bus.label.as_deref().unwrap_or("-"),
+            state,
+            last_audio,
+            writers
+        );
+    }
+    Ok(())
+}
+
+fn handle_bus_label(pair: &str, name: &[String]) -> Result<(), String> {
+    let offset = parse_offset_arg(pair)?;
+    let label = (!name.is_empty()).then(|| name.join(" "));
+    let response = send_request(&CommandRequest::BusLabel { offset, label })?;
+    let parsed: RpcResponse<Vec<BusLabelPayload>> = parse_response(&response)?;
+    let (message, _labels): (Option<String>, Vec<BusLabelPayload>) = extract_success(parsed)?;
+    if let Some(msg) = message {
+        println!("{}", msg);
+    }
+    Ok(())
+}
+
+fn handle_reserve(
+    offset: Option<String>,
+    owner: Option<String>,
+    release: bool,
+) -> Result<(), String> {
+    let request = match (offset, owner) {
+        (None, None) if !release => CommandRequest::Reservations,
+        (Some(offset), Some(owner)) => CommandRequest::Reserve {
+            offset: parse_offset_arg(&offset)?,
+            owner,
+            release,
+        },
+        _ => return Err("Usage: prism reserve <OFFSET|CH1-CH2> <OWNER> [--release]".to_string()),
+    };
+
+    let response = send_request(&request)?;
+    let parsed: RpcResponse<Vec<ReservationPayload>> = parse_response(&response)?;
+    let (message, entries): (Option<String>, Vec<ReservationPayload>) = extract_success(parsed)?;
+    if let Some(msg) = message {
+        println!("{}", msg);
+        return Ok(());
+    }
+    if entries.is_empty() {
+        println!("No pair is reserved.");
+        return Ok(());
+    }
+    println!("{:<8} | Owner", "Bus");
+    println!("{}-+-{}", "-".repeat(8), "-".repeat(16));
+    for entry in entries {
+        let bus = format!(
+            "{}-{}ch",
+            entry.channel_offset + 1,
+            entry.channel_offset + 2
+        );
+        println!("{:<8} | {}", bus, entry.owner);
+    }
     Ok(())
 }
 
-fn handle_set_app(args: Vec<String>) -> Result<(), String> {
-    // set-app <APP_NAME> <OFFSET|CH1-CH2>
-    // Accept app name containing spaces by treating the last arg as the offset
-    if args.len() < 2 {
-        return Err("Usage: prism set-app <APP_NAME> <OFFSET|CH1-CH2>".to_string());
+fn execute_meters() -> Result<(), String> {
+    let response = send_request(&CommandRequest::Meters)?;
+    let parsed: RpcResponse<Vec<BusLevelPayload>> = parse_response(&response)?;
+    let (_message, levels): (Option<String>, Vec<BusLevelPayload>) = extract_success(parsed)?;
+
+    if levels.is_empty() {
+        println!("No bus activity since the last read.");
+        return Ok(());
     }
-    let offset_arg = args.last().unwrap().to_string();
-    let app_name = args[..args.len() - 1].join(" ");
-    // Accept either numeric offset or channel range like "1-2"
-    let offset: u32 = if let Some((ch1, ch2)) = parse_channel_range(&offset_arg) {
-        if ch2 != ch1 + 1 {
-            return Err("Channel range must be consecutive (e.g. 1-2, 3-4)".to_string());
-        }
-        if ch1 < 1 {
-            return Err("Channel numbers must be >= 1".to_string());
-        }
-        ch1 - 1
-    } else {
-        offset_arg.parse().map_err(|_| {
-            "OFFSET must be a non-negative integer or channel range (e.g. 1-2)".to_string()
-        })?
-    };
-    // Delegate the app-level update to prismd (daemon) and display its result.
-    let response = send_request(&CommandRequest::SetApp {
-        app_name: app_name.clone(),
-        offset,
-    })?;
-    let parsed: RpcResponse<Vec<RoutingUpdateAck>> = parse_response(&response)?;
-    let (_message, results): (Option<String>, Vec<RoutingUpdateAck>) = extract_success(parsed)?;
 
-    if results.is_empty() {
-        println!("No clients found for app '{}'.", app_name);
-    } else {
-        let pids: Vec<String> = results.iter().map(|ack| ack.pid.to_string()).collect();
+    println!(
+        "{:<8} | {:>13} | {:>13} | Apps",
+        "Bus", "Peak L/R dB", "RMS L/R dB"
+    );
+    println!(
+        "{}-+-{}-+-{}-+-{}",
+        "-".repeat(8),
+        "-".repeat(13),
+        "-".repeat(13),
+        "-".repeat(4)
+    );
+    for level in levels {
+        let bus = format!("{}-{}ch", level.pair * 2 + 1, level.pair * 2 + 2);
+        let apps = if level.pair == 0 {
+            "(system mix)".to_string()
+        } else {
+            level.apps.join(", ")
+        };
         println!(
-            "Set offset={} for app '{}' (pids: {})",
-            offset,
-            app_name,
-            pids.join(", ")
+            "{:<8} | {:>6} {:>6} | {:>6} {:>6} | {}",
+            bus,
+            format_db(level.peak[0]),
+            format_db(level.peak[1]),
+            format_db(level.rms[0]),
+            format_db(level.rms[1]),
+            apps
         );
     }
     Ok(())
 }
 
-fn handle_set(args: Vec<String>) -> Result<(), String> {
-    if args.len() < 2 {
-        return Err("Usage: prism set <PID> <OFFSET|CH1-CH2>".to_string());
+// Columns of a history sparkline; longer windows are folded by taking the max.
+const HISTORY_COLUMNS: usize = 60;
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+// Peaks below this render as silence.
+const SPARK_FLOOR_DB: f32 = -60.0;
+
+fn execute_meter_history(seconds: u64) -> Result<(), String> {
+    let response = send_request(&CommandRequest::MeterHistory { seconds })?;
+    let parsed: RpcResponse<MeterHistoryPayload> = parse_response(&response)?;
+    let (_message, history): (Option<String>, MeterHistoryPayload) = extract_success(parsed)?;
+
+    let samples = history
+        .series
+        .first()
+        .map_or(0, |series| series.peaks.len());
+    if samples == 0 {
+        println!("No meter history recorded yet.");
+        return Ok(());
     }
+    let per_column = samples.div_ceil(HISTORY_COLUMNS);
+    let span = samples as u64 * u64::from(history.interval_secs);
+    println!(
+        "Peak history {} - {} ({}s per column; '.' silent, blank not routed)",
+        format_clock(history.end_time.saturating_sub(span)),
+        format_clock(history.end_time),
+        per_column as u64 * u64::from(history.interval_secs)
+    );
 
-    let pid: i32 = args[0]
-        .parse()
-        .map_err(|_| "PID must be an integer".to_string())?;
+    let name_width = history
+        .series
+        .iter()
+        .map(|series| series.name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .min(24);
+    for series in &history.series {
+        let columns: Vec<Option<f32>> = series
+            .peaks
+            .chunks(per_column)
+            .map(|chunk| chunk.iter().flatten().copied().reduce(f32::max))
+            .collect();
+        let spark: String = columns.iter().map(|peak| spark_char(*peak)).collect();
+        let loudest = columns.iter().flatten().copied().reduce(f32::max);
+        let name: String = series.name.chars().take(name_width).collect();
+        println!(
+            "{:<width$} |{}| max {}",
+            name,
+            spark,
+            loudest.map_or("-".to_string(), |peak| format!("{} dB", format_db(peak))),
+            width = name_width
+        );
+    }
+    Ok(())
+}
 
-    // Accept either offset or CH1-CH2 format
-    let offset: u32 = if let Some((ch1, ch2)) = parse_channel_range(&args[1]) {
-        // offset = ch1 - 1
-        if ch2 != ch1 + 1 {
-            return Err("Channel range must be consecutive (e.g. 1-2, 2-3)".to_string());
-        }
-        if ch1 < 1 {
-            return Err("Channel numbers must be >= 1".to_string());
-        }
-        ch1 - 1
-    } else {
-        args[1].parse().map_err(|_| {
-            "OFFSET must be a non-negative integer or channel range (e.g. 1-2)".to_string()
-        })?
+fn spark_char(peak: Option<f32>) -> char {
+    let Some(peak) = peak else {
+        return ' ';
     };
-    execute_set(pid, offset)
+    let db = linear_to_db(peak);
+    if !db.is_finite() || db < SPARK_FLOOR_DB {
+        return '.';
+    }
+    let position = (db - SPARK_FLOOR_DB) / -SPARK_FLOOR_DB;
+    let index = (position * SPARK_LEVELS.len() as f32) as usize;
+    SPARK_LEVELS[index.min(SPARK_LEVELS.len() - 1)]
 }
 
-fn handle_list() -> Result<(), String> {
-    execute_list()
+// Local wall-clock time (HH:MM:SS) of a Unix timestamp.
+fn format_clock(unix_secs: u64) -> String {
+    let time = unix_secs as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return unix_secs.to_string();
+    }
+    format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec)
 }
 
-fn handle_clients() -> Result<(), String> {
-    execute_clients()
+// "90", "90s", "5m" or "1h" as seconds.
+fn parse_duration_secs(text: &str) -> Option<u64> {
+    let text = text.trim().to_ascii_lowercase();
+    let (number, scale) = if let Some(n) = text.strip_suffix('h') {
+        (n, 3600)
+    } else if let Some(n) = text.strip_suffix('m') {
+        (n, 60)
+    } else if let Some(n) = text.strip_suffix('s') {
+        (n, 1)
+    } else {
+        (text.as_str(), 1)
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|n| *n > 0)
+        .and_then(|n| n.checked_mul(scale))
 }
 
-fn execute_set(pid: i32, offset: u32) -> Result<(), String> {
-    let response = send_request(&CommandRequest::Set { pid, offset })?;
+fn format_db(level: f32) -> String {
+    let db = linear_to_db(level);
+    if db.is_finite() && db > -120.0 {
+        format!("{:.1}", db)
+    } else {
+        "-inf".to_string()
+    }
+}
+
+fn execute_set(
+    pid: i32,
+    offset: u32,
+    gain_db: Option<f32>,
+    pan: Option<f32>,
+    exclude: Vec<String>,
+    owner: Option<String>,
+) -> Result<(), String> {
+    let response = send_request(&CommandRequest::Set {
+        pid,
+        offset,
+        gain_db,
+        pan,
+        exclude,
+        owner,
+    })?;
     let parsed: RpcResponse<RoutingUpdateAck> = parse_response(&response)?;
     let (message, ack): (Option<String>, RoutingUpdateAck) = extract_success(parsed)?;
+    let mix = format_mix(ack.gain_db, ack.pan);
     if let Some(msg) = message {
-        println!("{} (pid={} offset={})", msg, ack.pid, ack.channel_offset);
+        println!(
+            "{} (pid={} offset={}{})",
+            msg,
+            ack.pid,
+            format_offset(ack.channel_offset),
+            mix
+        );
     } else {
         println!(
-            "Routing update sent: pid={} offset={}",
-            ack.pid, ack.channel_offset
+            "Routing update sent: pid={} offset={}{}",
+            ack.pid,
+            format_offset(ack.channel_offset),
+            mix
         );
     }
     Ok(())
@@ -286,6 +2973,10 @@ fn execute_clients() -> Result<(), String> {
         return Ok(());
     }
 
+    let pending = clients
+        .iter()
+        .filter(|client| client.identity_pending)
+        .count();
     let mut groups: BTreeMap<i32, (Option<String>, Vec<ClientInfoPayload>)> = BTreeMap::new();
     let mut ungrouped: Vec<ClientInfoPayload> = Vec::new();
 
@@ -338,8 +3029,14 @@ fn execute_clients() -> Result<(), String> {
                 "-"
             };
             println!(
-                "    {} pid={} ({}) client_id={} offset={}",
-                marker, client.pid, proc_name, client.client_id, client.channel_offset
+                "    {} pid={} ({}) client_id={} offset={} {}{}",
+                marker,
+                client.pid,
+                proc_name,
+                client.client_id,
+                format_offset(client.channel_offset),
+                format_io_state(client.is_running),
+                format_bundle(client.bundle_id.as_deref())
             );
         }
     }
@@ -348,10 +3045,22 @@ fn execute_clients() -> Result<(), String> {
         ungrouped.sort_by(|a, b| a.pid.cmp(&b.pid).then(a.client_id.cmp(&b.client_id)));
         println!("  Ungrouped clients ({}):", ungrouped.len());
         for client in ungrouped {
-            let proc_name = client.process_name.as_deref().unwrap_or("<unknown>");
+            let proc_name = client
+                .process_name
+                .as_deref()
+                .unwrap_or(if client.identity_pending {
+                    "<resolving>"
+                } else {
+                    "<unknown>"
+                });
             println!(
-                "    - pid={} ({}) client_id={} offset={}",
-                client.pid, proc_name, client.client_id, client.channel_offset
+                "    - pid={} ({}) client_id={} offset={} {}{}",
+                client.pid,
+                proc_name,
+                client.client_id,
+                format_offset(client.channel_offset),
+                format_io_state(client.is_running),
+                format_bundle(client.bundle_id.as_deref())
             );
         }
     }
@@ -359,22 +3068,157 @@ fn execute_clients() -> Result<(), String> {
     if !groups.is_empty() {
         println!("  ('*' marks the responsible process owning the group)");
     }
+    if pending > 0 {
+        println!(
+            "  ({} client{} still being identified; run again for their names)",
+            pending,
+            if pending == 1 { " is" } else { "s are" }
+        );
+    }
     Ok(())
 }
 
+fn format_bundle(bundle_id: Option<&str>) -> String {
+    bundle_id
+        .map(|id| format!(" bundle={}", id))
+        .unwrap_or_default()
+}
+
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        _ => format!("{}h ago", secs / 3600),
+    }
+}
+
+fn format_io_state(is_running: bool) -> &'static str {
+    if is_running {
+        "running"
+    } else {
+        "idle"
+    }
+}
+
 // Token-based command builder removed with REPL.
 fn send_request(request: &CommandRequest) -> Result<String, String> {
-    let payload = serde_json::to_string(request)
+    if !request.is_mutating() {
+        let payload = serde_json::to_string(request)
+            .map_err(|err| format!("failed to encode request: {}", err))?;
+        return send_raw_payload(&payload, None);
+    }
+
+    // A mutating request is retried with the same key when prismd does not answer, and
+    // prismd answers a key it has already run with the first response
+    let mut fields = serde_json::to_value(request)
         .map_err(|err| format!("failed to encode request: {}", err))?;
-    send_raw_payload(&payload)
+    if let Some(fields) = fields.as_object_mut() {
+        fields.insert(IDEMPOTENCY_KEY_FIELD.to_string(), idempotency_key().into());
+    }
+    let payload = fields.to_string();
+    let mut attempt = 1;
+    loop {
+        match send_raw_payload(&payload, Some(REQUEST_TIMEOUT)) {
+            Err(err) if attempt < REQUEST_ATTEMPTS => {
+                eprintln!(
+                    "prism: {}; retrying ({}/{})",
+                    err,
+                    attempt + 1,
+                    REQUEST_ATTEMPTS
+                );
+                thread::sleep(RETRY_BACKOFF * attempt);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Sends of one mutating request; prismd keeps its key for IDEMPOTENCY_TTL_SECS, far
+// longer than the attempts take.
+const REQUEST_ATTEMPTS: u32 = 3;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const RETRY_BACKOFF: Duration = Duration::from_millis(250);
+
+// Unique per request: the mixer sends many from one process.
+fn idempotency_key() -> String {
+    static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos());
+    format!(
+        "{}-{}-{}",
+        std::process::id(),
+        nanos,
+        SEQUENCE.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+fn send_raw_payload(payload: &str, timeout: Option<Duration>) -> Result<String, String> {
+    let raw = exchange_payload(payload, true, timeout)?;
+    // prismd always answers, so nothing at all means it went away mid-request
+    if raw.is_empty() {
+        return Err("prismd closed the connection without answering".to_string());
+    }
+    let body = match socket::unframe_response(&raw) {
+        Some(decoded) => decoded.map_err(|err| format!("failed to decode response: {}", err))?,
+        // prismd predates the handshake and took the hello line for the command
+        None => exchange_payload(payload, false, timeout)?,
+    };
+    String::from_utf8(body).map_err(|err| format!("invalid response from prismd: {}", err))
+}
+
+// First socket that accepts a connection, trying the per-user one before the shared one.
+fn connect_daemon() -> Result<UnixStream, String> {
+    let candidates = socket::client_sockets(SOCKET_OVERRIDE.get().map(PathBuf::as_path));
+    let mut last_err = None;
+    for path in &candidates {
+        if let Err(err) = socket::check_client_socket(path) {
+            last_err = Some(err);
+            continue;
+        }
+        match UnixStream::connect(path) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    let tried: Vec<String> = candidates
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    Err(format!(
+        "failed to connect to prismd ({}): {}",
+        tried.join(", "),
+        last_err.map(|err| err.to_string()).unwrap_or_default()
+    ))
+}
+
+// Send one command and read the whole response. With `hello`, large responses may
+// come back compressed.
+fn exchange_payload(
+    payload: &str,
+    hello: bool,
+    timeout: Option<Duration>,
+) -> Result<Vec<u8>, String> {
+    let stream = connect_daemon()?;
+    stream
+        .set_read_timeout(timeout)
+        .map_err(|err| format!("failed to set socket timeout: {}", err))?;
+    exchange_on(stream, payload, hello)
 }
-fn send_raw_payload(payload: &str) -> Result<String, String> {
-    let mut stream = UnixStream::connect(socket::PRISM_SOCKET_PATH)
-        .map_err(|err| format!("failed to connect to prismd: {}", err))?;
 
+fn exchange_on(mut stream: UnixStream, payload: &str, hello: bool) -> Result<Vec<u8>, String> {
+    let mut request = String::new();
+    if hello {
+        request.push_str(&socket::hello_line(&[
+            socket::Encoding::Zstd,
+            socket::Encoding::Deflate,
+        ]));
+    }
+    request.push_str(payload);
+    request.push('\n');
     stream
-        .write_all(payload.as_bytes())
-        .and_then(|_| stream.write_all(b"\n"))
+        .write_all(request.as_bytes())
         .and_then(|_| stream.flush())
         .map_err(|err| format!("failed to send command: {}", err))?;
 
@@ -382,15 +3226,56 @@ fn send_raw_payload(payload: &str) -> Result<String, String> {
         eprintln!("prism: warning: failed to half-close socket: {}", err);
     }
 
-    let mut reader = BufReader::new(stream);
-    let mut response = String::new();
-    reader
-        .read_to_string(&mut response)
+    let mut response = Vec::new();
+    stream
+        .read_to_end(&mut response)
         .map_err(|err| format!("failed to read response: {}", err))?;
 
     Ok(response)
 }
 
+// A shell waits on every <TAB>; a daemon slower than this yields no candidates.
+const COMPLETION_TIMEOUT: Duration = Duration::from_millis(300);
+
+fn complete_apps() -> Vec<CompletionCandidate> {
+    completion_candidates(CompletionKind::Apps)
+}
+
+fn complete_pairs() -> Vec<CompletionCandidate> {
+    completion_candidates(CompletionKind::Pairs)
+}
+
+fn complete_presets() -> Vec<CompletionCandidate> {
+    completion_candidates(CompletionKind::Presets)
+}
+
+// Completion has nowhere to report errors, so any failure is just no candidates.
+fn completion_candidates(kind: CompletionKind) -> Vec<CompletionCandidate> {
+    fetch_completions(kind)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|candidate| {
+            CompletionCandidate::new(candidate.value).help(candidate.description.map(Into::into))
+        })
+        .collect()
+}
+
+fn fetch_completions(kind: CompletionKind) -> Result<Vec<CompletionPayload>, String> {
+    let payload = serde_json::to_string(&CommandRequest::Complete { kind })
+        .map_err(|err| format!("failed to encode request: {}", err))?;
+    let stream = connect_daemon()?;
+    stream
+        .set_read_timeout(Some(COMPLETION_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(COMPLETION_TIMEOUT)))
+        .map_err(|err| format!("failed to set socket timeout: {}", err))?;
+    // Candidate lists are small; skip the encoding handshake
+    let raw = exchange_on(stream, &payload, false)?;
+    let body =
+        String::from_utf8(raw).map_err(|err| format!("invalid response from prismd: {}", err))?;
+    let parsed: RpcResponse<Vec<CompletionPayload>> = parse_response(&body)?;
+    extract_success(parsed).map(|(_message, candidates)| candidates)
+}
+
 #[allow(dead_code)]
 fn fetch_help_entries() -> Result<(Option<String>, Vec<HelpEntry>), String> {
     let response = send_request(&CommandRequest::Help)?;
@@ -494,18 +3379,229 @@ fn fallback_help_entries() -> Vec<HelpEntry> {
         HelpEntry::new("clients", "clients", "Show active Prism clients via prismd"),
         HelpEntry::new(
             "set",
-            "set <PID> <OFFSET|CH1-CH2>",
-            "Send routing update (relayed by prismd). OFFSET or CH1-CH2 are accepted.",
+            "set <PID> <OFFSET|CH1-CH2|null> [--exclude PID|BUNDLE_ID ...]",
+            "Send routing update (relayed by prismd). OFFSET or CH1-CH2 are accepted, null mutes for every capture; PID -1 routes every client but the excluded ones.",
+        ),
+        HelpEntry::new(
+            "unset",
+            "unset <PID>",
+            "Return a PID's clients to 1-2ch with a unity mix and clear their old pair",
         ),
         HelpEntry::new(
             "apps",
             "apps",
             "List active apps grouped by responsible process (shows channel ranges)",
         ),
+        HelpEntry::new("stats", "stats", "Show driver IO statistics"),
+        HelpEntry::new(
+            "diag",
+            "diag",
+            "Show capture clients reading the input stream: pid, read position, active or idle",
+        ),
+        HelpEntry::new(
+            "benchmark",
+            "benchmark [-n <REQUESTS>] [-j <CONNECTIONS>] [--request <clients|apps|list|stats|meters>]",
+            "Send a burst of read-only requests to prismd and report latency percentiles",
+        ),
+        HelpEntry::new(
+            "meters",
+            "meters [--watch | --history <DURATION>]",
+            "Show peak/RMS levels per bus, or a per-app peak sparkline over DURATION",
+        ),
+        HelpEntry::new(
+            "buses",
+            "buses",
+            "Show which bus pairs are free, who plays on each and when it last had audio",
+        ),
+        HelpEntry::new(
+            "bus-label",
+            "bus-label <CH1-CH2> [NAME]",
+            "Name a pair for `prism buses` and recording apps; no NAME drops the name",
+        ),
+        HelpEntry::new(
+            "trim",
+            "trim [<CH|CH1-CH2> <GAIN>]",
+            "Trim input channels; no arguments lists trimmed channels",
+        ),
+        HelpEntry::new(
+            "width",
+            "width <APP_NAME> <WIDTH>",
+            "Set an app's stereo width: 0.0 mono, 1.0 unchanged, up to 2.0 wider",
+        ),
+        HelpEntry::new(
+            "balance",
+            "balance <APP_NAME> [BALANCE] [--swap|--no-swap]",
+            "Set an app's balance (-1.0 left .. 1.0 right) or swap its left and right channels",
+        ),
+        HelpEntry::new(
+            "mono",
+            "mono <APP_NAME> <left|right|off>",
+            "Fold an app to mono at -3 dB on one channel of its pair, freeing the other",
+        ),
+        HelpEntry::new(
+            "mirror",
+            "mirror <APP_NAME> <CH1-CH2|off>",
+            "Also play an app on a second pair (e.g. a clean recording feed), or stop",
+        ),
+        HelpEntry::new(
+            "matrix",
+            "matrix [APP_NAME] [L|R:CH[@GAIN]...] [--clear]",
+            "Route an app's left and right to any bus channels (e.g. L:5 R:12); lists without arguments",
+        ),
+        HelpEntry::new(
+            "monitor",
+            "monitor [on|off] [CH1-CH2[@GAIN|@off]...]",
+            "Mix routed pairs into 1-2 so you hear what is being captured",
+        ),
+        HelpEntry::new(
+            "solo",
+            "solo [off] [CH1-CH2[@off]...]",
+            "Silence every pair but the soloed ones for all captures, leaving routing alone",
+        ),
+        HelpEntry::new(
+            "mixer",
+            "mixer",
+            "Interactive mixer: meters, faders, mute/solo and channel pairs per app",
+        ),
+        HelpEntry::new(
+            "resync",
+            "resync",
+            "Re-anchor the driver timeline and clear its buffers (fixes drift/glitch states)",
+        ),
+        HelpEntry::new(
+            "health",
+            "health",
+            "Check that prismd is up and attached to the device; exits 1 if not",
+        ),
+        HelpEntry::new(
+            "status",
+            "status",
+            "Show tracked routes and whether the driver drifted from them",
+        ),
+        HelpEntry::new(
+            "daemon",
+            "daemon run [PRISMD_ARGS...]",
+            "Run the daemon from this binary, as prismd does",
+        ),
+        HelpEntry::new(
+            "quickstart",
+            "quickstart [<PRESET>|off]",
+            "Route Discord, Spotify, browsers and games to their own pairs (e.g. quickstart streaming)",
+        ),
+        HelpEntry::new(
+            "preset-check",
+            "preset-check <FILE>",
+            "Validate a preset file before handing it to prismd",
+        ),
+        HelpEntry::new(
+            "rules",
+            "rules [--reload]",
+            "Show the per-app routing rules prismd applies as apps appear",
+        ),
+        HelpEntry::new(
+            "selftest",
+            "selftest [-n <CLIENTS>] [-t <SECONDS>]",
+            "Play test tones from simulated clients, route them and check each lands on its pair",
+        ),
+        HelpEntry::new(
+            "driver-info",
+            "driver-info",
+            "Show the loaded driver's version, build, devices and client count",
+        ),
+        HelpEntry::new(
+            "clear",
+            "clear [<OFFSET|CH1-CH2>|all]",
+            "Zero a pair's loopback ring (or all) to stop stuck audio; no arguments lists the rings",
+        ),
+        HelpEntry::new(
+            "log-level",
+            "log-level [error|info|debug]",
+            "Show or set how much the driver logs to the unified log (dev.ichigo.prism)",
+        ),
+        HelpEntry::new(
+            "latency",
+            "latency [--input N] [--output N] [--input-safety N] [--output-safety N]",
+            "Show or set the per-direction latency and safety offset in frames (trade latency for stability)",
+        ),
+        HelpEntry::new(
+            "bridge",
+            "bridge [<OFFSET|CH1-CH2> <TARGET> [--to <OFFSET|CH1-CH2>] [--from DEVICE] | --remove ID]",
+            "Forward a pair into another device; no arguments lists bridges and their latency",
+        ),
+        HelpEntry::new(
+            "clock-domain",
+            "clock-domain [DEVICE|DOMAIN]",
+            "Share a hardware device's clock domain so aggregates skip resampling; no arguments lists domains",
+        ),
+        HelpEntry::new(
+            "output-mode",
+            "output-mode [stereo|bus]",
+            "Show or set whether apps play stereo routed per client or write all bus channels 1:1",
+        ),
+        HelpEntry::new(
+            "system-mix",
+            "system-mix [CH1-CH2|on|off]",
+            "Show, move or switch off the mix of every app not routed to a pair of its own",
+        ),
+        HelpEntry::new(
+            "auto-assign",
+            "auto-assign [disabled|first-free|round-robin|sticky-bundle]",
+            "Show or set how new apps get a pair before they are routed",
+        ),
+        HelpEntry::new(
+            "clock-rate",
+            "clock-rate [PPM]",
+            "Show or set the driver clock rate offset for aggregate devices (±1000 ppm)",
+        ),
+        HelpEntry::new(
+            "agc",
+            "agc [<APP_NAME> --target <LUFS> [--range <dB>] | --off]",
+            "Keep APP_NAME near a target loudness; no arguments lists AGC state",
+        ),
+        HelpEntry::new(
+            "capture-allow",
+            "capture-allow [<OFFSET|CH1-CH2> <BUNDLE_ID> | --clear]",
+            "Only let capture clients of BUNDLE_ID read the pair; no arguments lists restrictions",
+        ),
+        HelpEntry::new(
+            "capture-mask",
+            "capture-mask [<PID> <CH1-CH2...|all>]",
+            "Let one capture process read only some pairs; no arguments lists masks",
+        ),
+        HelpEntry::new(
+            "capture-exclusions",
+            "capture-exclusions",
+            "Show the apps whose output the driver never copies into the loopback",
+        ),
+        HelpEntry::new(
+            "capture-list",
+            "capture-list [on|off] [BUNDLE_ID|PID[@off]...]",
+            "Only let the listed apps or processes capture the input stream",
+        ),
+        HelpEntry::new(
+            "pair-policy",
+            "pair-policy [<OFFSET|CH1-CH2> <reject|mix|replace|duck>]",
+            "Choose what happens when an app is routed to an occupied pair; no arguments lists policies",
+        ),
+        HelpEntry::new(
+            "gap-policy",
+            "gap-policy [<OFFSET|CH1-CH2> <zero-fill|hold-last> [--max-hold MS]]",
+            "Choose whether a pair plays silence or holds the last audio when an app misses a cycle",
+        ),
+        HelpEntry::new(
+            "reserve",
+            "reserve [OFFSET|CH1-CH2 OWNER] [--release]",
+            "Claim a pair so routing by anyone but OWNER cannot move apps onto it",
+        ),
         HelpEntry::new(
             "set-app",
-            "set-app <APP_NAME> <OFFSET|CH1-CH2>",
-            "Request prismd to set channel offset for all clients of APP_NAME",
+            "set-app <APP_NAME|BUNDLE_ID|type:TYPE> <OFFSET|CH1-CH2|null>",
+            "Request prismd to set channel offset for all clients of an app, bundle ID or content type",
+        ),
+        HelpEntry::new(
+            "classify",
+            "classify [BUNDLE_ID] [music|voice|game|system] [--clear]",
+            "Show or override the content type presets and type: targets route by",
         ),
         // repl removed; use subcommands instead
         HelpEntry::new("help", "help", "Show this help message"),
@@ -548,6 +3644,91 @@ fn format_fourcc(value: u32) -> (String, u32) {
     (text, u32::from_be_bytes(bytes))
 }
 
+// Parse "-6dB", "-6 dB" or "-6" into decibels
+fn parse_gain_db(s: &str) -> Option<f32> {
+    let trimmed = s.trim();
+    let number = trimmed
+        .strip_suffix("dB")
+        .or_else(|| trimmed.strip_suffix("db"))
+        .or_else(|| trimmed.strip_suffix("DB"))
+        .unwrap_or(trimmed)
+        .trim();
+    number.parse::<f32>().ok().filter(|db| db.is_finite())
+}
+
+// Parse a loudness like "-18LUFS", "-18 LKFS" or "-18"
+fn parse_lufs(s: &str) -> Result<f32, String> {
+    let trimmed = s.trim();
+    let number = trimmed
+        .strip_suffix("LUFS")
+        .or_else(|| trimmed.strip_suffix("lufs"))
+        .or_else(|| trimmed.strip_suffix("LKFS"))
+        .unwrap_or(trimmed)
+        .trim();
+    number
+        .parse::<f32>()
+        .ok()
+        .filter(|lufs| lufs.is_finite())
+        .ok_or_else(|| format!("invalid target '{}' (expected e.g. -18LUFS)", s))
+}
+
+// Validate optional --gain / --pan flags, returning (gain_db, pan)
+fn parse_mix_args(
+    gain: Option<&str>,
+    pan: Option<f32>,
+) -> Result<(Option<f32>, Option<f32>), String> {
+    let gain_db = match gain {
+        Some(raw) => Some(
+            parse_gain_db(raw)
+                .ok_or_else(|| format!("invalid gain '{}' (expected e.g. -6dB)", raw))?,
+        ),
+        None => None,
+    };
+    if let Some(value) = pan {
+        if !(-1.0..=1.0).contains(&value) {
+            return Err("PAN must be between -1.0 and 1.0".to_string());
+        }
+    }
+    Ok((gain_db, pan))
+}
+
+fn format_mix(gain_db: Option<f32>, pan: Option<f32>) -> String {
+    let mut out = String::new();
+    if let Some(db) = gain_db {
+        out.push_str(&format!(" gain={:+.1}dB", db));
+    }
+    if let Some(value) = pan {
+        out.push_str(&format!(" pan={:+.2}", value));
+    }
+    out
+}
+
+// A channel offset as typed on the command line: the number, or "null" for the null bus
+fn format_offset(offset: u32) -> String {
+    if offset == NULL_BUS_OFFSET {
+        "null".to_string()
+    } else {
+        offset.to_string()
+    }
+}
+
+// Parse an OFFSET or consecutive CH1-CH2 argument into a channel offset
+fn parse_offset_arg(arg: &str) -> Result<u32, String> {
+    if let Some((ch1, ch2)) = parse_channel_range(arg) {
+        if ch2 != ch1 + 1 {
+            return Err("Channel range must be consecutive (e.g. 1-2, 3-4)".to_string());
+        }
+        if ch1 < 1 {
+            return Err("Channel numbers must be >= 1".to_string());
+        }
+        Ok(ch1 - 1)
+    } else {
+        arg.parse().map_err(|_| {
+            "OFFSET must be a non-negative integer or channel range (e.g. 1-2)".to_string()
+        })
+    }
+}
+
 // Parse "1-2" or "2-3" style channel range, return (ch1, ch2) if valid, else None
 fn parse_channel_range(s: &str) -> Option<(u32, u32)> {
     let parts: Vec<&str> = s.split('-').collect();
@@ -0,0 +1,386 @@
+//! Bridges: prismd copies a stereo pair from one device's input into a pair of another
+//! device's output, so per-app buses can feed a master mix device (another Prism
+//! included). Each bridge runs an IOProc on both devices joined by a lock-free ring.
+//! The target waits until the ring holds a couple of cycles before it plays, and
+//! re-primes after an underrun. Both sides must run at the same nominal rate; bridges
+//! do not resample. A bridge can also fold other source pairs into its own at a send
+//! gain, which is how prismd plays Prism's monitor downmix without touching capture.
+
+use crate::host::{
+    channel_count, device_is_alive, device_sample_rate, device_u32_property, locate_channel,
+};
+use coreaudio_sys::*;
+use std::ffi::c_void;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+
+const RING_FRAMES: usize = 16384; // Power of two; ~340 ms at 48 kHz
+const RING_MASK: usize = RING_FRAMES - 1;
+const MAX_SEND_PAIRS: usize = 32; // Pairs a bridge can fold in; Prism's bus has 32
+
+// Single-producer single-consumer stereo ring: the source IOProc writes, the target
+// IOProc reads. Samples are stored as f32 bits so neither side needs a lock.
+struct PairRing {
+    samples: Box<[AtomicU32]>,
+    written: AtomicU64, // Frames ever written
+    read: AtomicU64,    // Frames ever read or skipped
+    primed: AtomicBool,
+    prime_frames: usize,
+    fill: AtomicU32, // Frames buffered at the target's last cycle
+    underruns: AtomicU64,
+    overruns: AtomicU64,
+}
+
+impl PairRing {
+    fn new(prime_frames: usize) -> Self {
+        Self {
+            samples: (0..RING_FRAMES * 2).map(|_| AtomicU32::new(0)).collect(),
+            written: AtomicU64::new(0),
+            read: AtomicU64::new(0),
+            primed: AtomicBool::new(false),
+            prime_frames,
+            fill: AtomicU32::new(prime_frames as u32),
+            underruns: AtomicU64::new(0),
+            overruns: AtomicU64::new(0),
+        }
+    }
+
+    // Append `frames` frames; what does not fit is dropped and counted as an overrun.
+    fn push(&self, frames: usize, frame: impl Fn(usize) -> (f32, f32)) {
+        let written = self.written.load(Ordering::Relaxed);
+        let buffered = (written - self.read.load(Ordering::Acquire)) as usize;
+        let count = frames.min(RING_FRAMES - buffered);
+        if count < frames {
+            self.overruns.fetch_add(1, Ordering::Relaxed);
+        }
+        for f in 0..count {
+            let (left, right) = frame(f);
+            let index = ((written as usize + f) & RING_MASK) * 2;
+            self.samples[index].store(left.to_bits(), Ordering::Relaxed);
+            self.samples[index + 1].store(right.to_bits(), Ordering::Relaxed);
+        }
+        self.written
+            .store(written + count as u64, Ordering::Release);
+    }
+
+    // Hand `frames` frames to `out`, or return false (the caller plays silence) while
+    // the ring is priming or after it ran dry.
+    fn pop(&self, frames: usize, mut out: impl FnMut(usize, f32, f32)) -> bool {
+        let mut read = self.read.load(Ordering::Relaxed);
+        let buffered = (self.written.load(Ordering::Acquire) - read) as usize;
+        self.fill.store(buffered as u32, Ordering::Relaxed);
+        if !self.primed.load(Ordering::Relaxed) {
+            if buffered < self.prime_frames + frames {
+                return false;
+            }
+            self.primed.store(true, Ordering::Relaxed);
+        } else if buffered < frames {
+            self.underruns.fetch_add(1, Ordering::Relaxed);
+            self.primed.store(false, Ordering::Relaxed);
+            return false;
+        }
+        // The two clocks never match exactly: once the source has pulled a full
+        // priming level ahead, drop the surplus instead of letting latency grow
+        if buffered > 2 * self.prime_frames + frames {
+            read += (buffered - self.prime_frames - frames) as u64;
+            self.overruns.fetch_add(1, Ordering::Relaxed);
+        }
+        for f in 0..frames {
+            let index = ((read as usize + f) & RING_MASK) * 2;
+            out(
+                f,
+                f32::from_bits(self.samples[index].load(Ordering::Relaxed)),
+                f32::from_bits(self.samples[index + 1].load(Ordering::Relaxed)),
+            );
+        }
+        self.read.store(read + frames as u64, Ordering::Release);
+        true
+    }
+}
+
+// State both IOProcs see; they receive it as their client data.
+struct Shared {
+    ring: PairRing,
+    source_channel: u32,
+    target_channel: u32,
+    // Per source pair (index = channel_offset / 2): f32 bits of the linear gain it is
+    // folded into the bridged pair with, 0 for none
+    sends: [AtomicU32; MAX_SEND_PAIRS],
+}
+
+unsafe extern "C" fn source_proc(
+    _device: AudioObjectID,
+    _now: *const AudioTimeStamp,
+    input: *const AudioBufferList,
+    _input_time: *const AudioTimeStamp,
+    _output: *mut AudioBufferList,
+    _output_time: *const AudioTimeStamp,
+    client_data: *mut c_void,
+) -> OSStatus {
+    let shared = &*(client_data as *const Shared);
+    if input.is_null() {
+        return 0;
+    }
+    let (Some(left), Some(right)) = (
+        locate_channel(input, shared.source_channel),
+        locate_channel(input, shared.source_channel + 1),
+    ) else {
+        return 0;
+    };
+    let mut frames = left.3.min(right.3);
+    // Locate the pairs with a send once per cycle; the IOProc cannot allocate
+    let mut folds = [(ptr::null_mut(), 0, 0, ptr::null_mut(), 0, 0, 0.0f32); MAX_SEND_PAIRS];
+    let mut fold_count = 0;
+    for (pair, send) in shared.sends.iter().enumerate() {
+        let gain = f32::from_bits(send.load(Ordering::Relaxed));
+        let channel = pair as u32 * 2;
+        if gain == 0.0 || channel == shared.source_channel {
+            continue;
+        }
+        if let (Some(l), Some(r)) = (
+            locate_channel(input, channel),
+            locate_channel(input, channel + 1),
+        ) {
+            frames = frames.min(l.3).min(r.3);
+            folds[fold_count] = (l.0, l.1, l.2, r.0, r.1, r.2, gain);
+            fold_count += 1;
+        }
+    }
+    let folds = &folds[..fold_count];
+    shared.ring.push(frames, |f| {
+        let mut frame = (
+            *left.0.add(f * left.2 + left.1),
+            *right.0.add(f * right.2 + right.1),
+        );
+        for &(l_data, l_index, l_stride, r_data, r_index, r_stride, gain) in folds {
+            frame.0 += gain * *l_data.add(f * l_stride + l_index);
+            frame.1 += gain * *r_data.add(f * r_stride + r_index);
+        }
+        frame
+    });
+    0
+}
+
+unsafe extern "C" fn target_proc(
+    _device: AudioObjectID,
+    _now: *const AudioTimeStamp,
+    _input: *const AudioBufferList,
+    _input_time: *const AudioTimeStamp,
+    output: *mut AudioBufferList,
+    _output_time: *const AudioTimeStamp,
+    client_data: *mut c_void,
+) -> OSStatus {
+    let shared = &*(client_data as *const Shared);
+    if output.is_null() {
+        return 0;
+    }
+    let (Some(left), Some(right)) = (
+        locate_channel(output, shared.target_channel),
+        locate_channel(output, shared.target_channel + 1),
+    ) else {
+        return 0;
+    };
+    // The HAL hands each IOProc a zeroed output, so silence needs no writes
+    let frames = left.3.min(right.3);
+    shared.ring.pop(frames, |f, l, r| {
+        *left.0.add(f * left.2 + left.1) = l;
+        *right.0.add(f * right.2 + right.1) = r;
+    });
+    0
+}
+
+/// One side of a bridge: a device and the first channel of its pair (0-based).
+#[derive(Debug, Clone)]
+pub struct BridgeEnd {
+    pub device_id: AudioObjectID,
+    pub name: String,
+    pub channel_offset: u32,
+}
+
+/// Frames a bridge adds between the source's input and the target's output.
+#[derive(Debug, Clone, Copy)]
+pub struct BridgeLatency {
+    /// Source input latency, safety offset and one IO buffer.
+    pub source_frames: u32,
+    /// Audio waiting in the ring at the target's last cycle.
+    pub ring_frames: u32,
+    /// Target output latency, safety offset and one IO buffer.
+    pub target_frames: u32,
+    pub sample_rate: f64,
+}
+
+impl BridgeLatency {
+    pub fn total_frames(&self) -> u32 {
+        self.source_frames + self.ring_frames + self.target_frames
+    }
+
+    pub fn total_ms(&self) -> f64 {
+        self.total_frames() as f64 * 1000.0 / self.sample_rate
+    }
+}
+
+pub struct Bridge {
+    pub id: u32,
+    pub source: BridgeEnd,
+    pub target: BridgeEnd,
+    sample_rate: f64,
+    shared: Arc<Shared>,
+    source_proc: AudioDeviceIOProcID,
+    target_proc: AudioDeviceIOProcID,
+}
+
+impl Bridge {
+    /// Validate both ends and start copying.
+    pub fn start(id: u32, source: BridgeEnd, target: BridgeEnd) -> Result<Bridge, String> {
+        if source.device_id == target.device_id {
+            return Err("a bridge needs two different devices".to_string());
+        }
+        let source_rate = device_sample_rate(source.device_id)
+            .ok_or_else(|| format!("cannot read the sample rate of '{}'", source.name))?;
+        let target_rate = device_sample_rate(target.device_id)
+            .ok_or_else(|| format!("cannot read the sample rate of '{}'", target.name))?;
+        if source_rate != target_rate {
+            return Err(format!(
+                "'{}' runs at {} Hz and '{}' at {} Hz; bridges do not resample",
+                source.name, source_rate, target.name, target_rate
+            ));
+        }
+        for (end, scope, direction) in [
+            (&source, kAudioObjectPropertyScopeInput, "input"),
+            (&target, kAudioObjectPropertyScopeOutput, "output"),
+        ] {
+            let channels = channel_count(end.device_id, scope)?;
+            if end.channel_offset + 2 > channels {
+                return Err(format!(
+                    "'{}' has {} {} channel(s); channels {}-{} do not exist",
+                    end.name,
+                    channels,
+                    direction,
+                    end.channel_offset + 1,
+                    end.channel_offset + 2
+                ));
+            }
+        }
+
+        // Two of the larger IO buffer keep either side's jitter from draining the ring
+        let buffer_frames = |end: &BridgeEnd, scope| {
+            device_u32_property(end.device_id, kAudioDevicePropertyBufferFrameSize, scope)
+                .unwrap_or(512)
+        };
+        let prime_frames = 2 * buffer_frames(&source, kAudioObjectPropertyScopeInput)
+            .max(buffer_frames(&target, kAudioObjectPropertyScopeOutput))
+            as usize;
+        if 2 * prime_frames >= RING_FRAMES {
+            return Err(format!(
+                "IO buffers of {} frames are too large to bridge",
+                prime_frames / 2
+            ));
+        }
+
+        let mut bridge = Bridge {
+            id,
+            sample_rate: source_rate,
+            shared: Arc::new(Shared {
+                ring: PairRing::new(prime_frames),
+                source_channel: source.channel_offset,
+                target_channel: target.channel_offset,
+                sends: Default::default(),
+            }),
+            source,
+            target,
+            source_proc: None,
+            target_proc: None,
+        };
+        // On error `bridge` is dropped, which stops whatever was started
+        let client_data = Arc::as_ptr(&bridge.shared) as *mut c_void;
+        let procs: [(AudioObjectID, AudioDeviceIOProc); 2] = [
+            (bridge.source.device_id, Some(source_proc)),
+            (bridge.target.device_id, Some(target_proc)),
+        ];
+        for (index, (device_id, io_proc)) in procs.into_iter().enumerate() {
+            let mut proc_id: AudioDeviceIOProcID = None;
+            let status =
+                unsafe { AudioDeviceCreateIOProcID(device_id, io_proc, client_data, &mut proc_id) };
+            if status != 0 {
+                return Err(format!(
+                    "AudioDeviceCreateIOProcID failed with status {}",
+                    status
+                ));
+            }
+            if index == 0 {
+                bridge.source_proc = proc_id;
+            } else {
+                bridge.target_proc = proc_id;
+            }
+            let status = unsafe { AudioDeviceStart(device_id, proc_id) };
+            if status != 0 {
+                return Err(format!("AudioDeviceStart failed with status {}", status));
+            }
+        }
+        Ok(bridge)
+    }
+
+    pub fn latency(&self) -> BridgeLatency {
+        let side = |end: &BridgeEnd, scope| {
+            [
+                kAudioDevicePropertyLatency,
+                kAudioDevicePropertySafetyOffset,
+                kAudioDevicePropertyBufferFrameSize,
+            ]
+            .into_iter()
+            .map(|selector| device_u32_property(end.device_id, selector, scope).unwrap_or(0))
+            .sum()
+        };
+        BridgeLatency {
+            source_frames: side(&self.source, kAudioObjectPropertyScopeInput),
+            ring_frames: self.shared.ring.fill.load(Ordering::Relaxed),
+            target_frames: side(&self.target, kAudioObjectPropertyScopeOutput),
+            sample_rate: self.sample_rate,
+        }
+    }
+
+    /// Target cycles that found the ring empty.
+    pub fn underruns(&self) -> u64 {
+        self.shared.ring.underruns.load(Ordering::Relaxed)
+    }
+
+    /// Source cycles that found the ring full, plus drift corrections.
+    pub fn overruns(&self) -> u64 {
+        self.shared.ring.overruns.load(Ordering::Relaxed)
+    }
+
+    /// Fold the source pairs in `sends` ((channel_offset, linear gain)) into the bridged
+    /// pair, replacing the previous sends. Pairs out of range are ignored.
+    pub fn set_sends(&self, sends: &[(u32, f32)]) {
+        for (pair, send) in self.shared.sends.iter().enumerate() {
+            let gain = sends
+                .iter()
+                .find(|(channel_offset, _)| *channel_offset as usize == pair * 2)
+                .map_or(0.0, |(_, gain)| *gain);
+            send.store(gain.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    /// False once either device has gone away (unplugged, coreaudiod restarted).
+    pub fn is_alive(&self) -> bool {
+        device_is_alive(self.source.device_id) && device_is_alive(self.target.device_id)
+    }
+}
+
+impl Drop for Bridge {
+    fn drop(&mut self) {
+        // Destroying an IOProc waits for a cycle in flight, so `shared` outlives both
+        for (device_id, proc_id) in [
+            (self.source.device_id, self.source_proc),
+            (self.target.device_id, self.target_proc),
+        ] {
+            if proc_id.is_some() {
+                unsafe {
+                    AudioDeviceStop(device_id, proc_id);
+                    AudioDeviceDestroyIOProcID(device_id, proc_id);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! Content type of an app's audio: music, voice, game or system sounds.
+//!
+//! An override set with `prism classify` wins. Otherwise the type comes from the
+//! LSApplicationCategoryType in the app bundle's Info.plist, and anything running out
+//! of the OS itself counts as system audio, apart from the apps Apple ships with it
+//! (Music, FaceTime, Safari), which go by their category too. Overrides are kept in a
+//! JSON file so they survive prismd restarts.
+
+use crate::ipc::ContentType;
+use crate::persist;
+use crate::process::bundle_matches;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where prismd keeps `prism classify` overrides unless told otherwise.
+pub fn default_overrides_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME")?;
+    Some(PathBuf::from(home).join("Library/Application Support/Prism/content-types.json"))
+}
+
+/// Content types set by hand, by bundle ID. An ID also covers its helpers
+/// (com.hnc.Discord covers com.hnc.Discord.helper).
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    by_bundle: BTreeMap<String, ContentType>,
+}
+
+impl Overrides {
+    pub const fn new() -> Self {
+        Self {
+            by_bundle: BTreeMap::new(),
+        }
+    }
+
+    /// Read overrides from `path`; a missing file means none.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let raw = match fs::read_to_string(path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(format!("{}: {}", path.display(), err)),
+        };
+        let by_bundle =
+            serde_json::from_str(&raw).map_err(|err| format!("{}: {}", path.display(), err))?;
+        Ok(Self { by_bundle })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let serialized =
+            serde_json::to_string_pretty(&self.by_bundle).map_err(|err| err.to_string())?;
+        persist::write_atomic(path, serialized.as_bytes())
+    }
+
+    /// Set the type of `bundle_id`, or drop its override when `content_type` is None.
+    /// Returns whether anything changed.
+    pub fn set(&mut self, bundle_id: &str, content_type: Option<ContentType>) -> bool {
+        match content_type {
+            Some(content_type) => {
+                self.by_bundle.insert(bundle_id.to_string(), content_type) != Some(content_type)
+            }
+            None => self.by_bundle.remove(bundle_id).is_some(),
+        }
+    }
+
+    /// Override for `bundle_id`, preferring the longest matching ID.
+    pub fn lookup(&self, bundle_id: &str) -> Option<ContentType> {
+        self.by_bundle
+            .iter()
+            .filter(|(id, _)| bundle_matches(bundle_id, id))
+            .max_by_key(|(id, _)| id.len())
+            .map(|(_, content_type)| *content_type)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, ContentType)> {
+        self.by_bundle
+            .iter()
+            .map(|(id, content_type)| (id.as_str(), *content_type))
+    }
+}
+
+// Where the OS keeps its own apps. They live under /System but are apps like any other.
+const SYSTEM_APP_DIRS: [&str; 2] = ["/System/Applications/", "/System/Cryptexes/App/"];
+
+/// Guess the content type of the process at `executable_path` from its bundle.
+pub fn classify_executable(executable_path: &str) -> Option<ContentType> {
+    let system_app = SYSTEM_APP_DIRS
+        .iter()
+        .any(|dir| executable_path.starts_with(dir));
+    if !system_app
+        && ["/System/", "/usr/", "/Library/Apple/"]
+            .iter()
+            .any(|prefix| executable_path.starts_with(prefix))
+    {
+        return Some(ContentType::System);
+    }
+    app_category(executable_path).and_then(|category| from_category(&category))
+}
+
+/// Map an LSApplicationCategoryType to a content type.
+pub fn from_category(category: &str) -> Option<ContentType> {
+    let kind = category.strip_prefix("public.app-category.")?;
+    match kind {
+        "music" => Some(ContentType::Music),
+        "games" => Some(ContentType::Game),
+        "social-networking" => Some(ContentType::Voice),
+        _ if kind.ends_with("-games") => Some(ContentType::Game),
+        _ => None,
+    }
+}
+
+// LSApplicationCategoryType of the outermost .app the executable lives in. Helpers
+// nested inside an app rarely declare a category, their app does.
+fn app_category(executable_path: &str) -> Option<String> {
+    let end = executable_path.find(".app/")? + ".app".len();
+    let info = Path::new(&executable_path[..end]).join("Contents/Info.plist");
+    let plist = plist::Value::from_file(info).ok()?;
+    plist
+        .as_dictionary()?
+        .get("LSApplicationCategoryType")?
+        .as_string()
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    // An app bundle declaring `category`, removed when the test ends
+    struct TempApp(PathBuf);
+
+    impl TempApp {
+        fn new(test: &str, category: &str) -> Self {
+            let root = env::temp_dir().join(format!("prism-content-{}-{}", process::id(), test));
+            let contents = root.join("Player.app/Contents");
+            fs::create_dir_all(&contents).unwrap();
+            let mut info = plist::Dictionary::new();
+            info.insert("LSApplicationCategoryType".to_string(), category.into());
+            plist::Value::Dictionary(info)
+                .to_file_xml(contents.join("Info.plist"))
+                .unwrap();
+            TempApp(root)
+        }
+
+        fn executable(&self, inner: &str) -> String {
+            self.0.join("Player.app").join(inner).display().to_string()
+        }
+    }
+
+    impl Drop for TempApp {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn os_binaries_are_system() {
+        for path in [
+            "/usr/bin/afplay",
+            "/usr/libexec/coreaudiod",
+            "/System/Library/CoreServices/NotificationCenter.app/Contents/MacOS/NotificationCenter",
+            "/Library/Apple/System/Library/CoreServices/XProtect.app/Contents/MacOS/XProtect",
+        ] {
+            assert_eq!(
+                classify_executable(path),
+                Some(ContentType::System),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn os_apps_are_not_system() {
+        // Without their bundles here the category is unknown, but they are not system
+        for path in [
+            "/System/Applications/Music.app/Contents/MacOS/Music",
+            "/System/Applications/FaceTime.app/Contents/MacOS/FaceTime",
+            "/System/Cryptexes/App/System/Applications/Safari.app/Contents/MacOS/Safari",
+        ] {
+            assert_ne!(
+                classify_executable(path),
+                Some(ContentType::System),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn category_of_the_outermost_app_applies() {
+        let app = TempApp::new("outer", "public.app-category.music");
+        assert_eq!(
+            classify_executable(&app.executable("Contents/MacOS/Player")),
+            Some(ContentType::Music)
+        );
+        // The helper app declares nothing of its own
+        assert_eq!(
+            classify_executable(
+                &app.executable(
+                    "Contents/Frameworks/Player Helper.app/Contents/MacOS/Player Helper"
+                )
+            ),
+            Some(ContentType::Music)
+        );
+    }
+
+    #[test]
+    fn unmapped_categories_classify_as_nothing() {
+        let app = TempApp::new("unmapped", "public.app-category.productivity");
+        assert_eq!(
+            classify_executable(&app.executable("Contents/MacOS/Player")),
+            None
+        );
+        assert_eq!(classify_executable("/opt/tool/bin/player"), None);
+    }
+
+    #[test]
+    fn categories_map_to_content_types() {
+        assert_eq!(
+            from_category("public.app-category.music"),
+            Some(ContentType::Music)
+        );
+        assert_eq!(
+            from_category("public.app-category.games"),
+            Some(ContentType::Game)
+        );
+        assert_eq!(
+            from_category("public.app-category.puzzle-games"),
+            Some(ContentType::Game)
+        );
+        assert_eq!(
+            from_category("public.app-category.social-networking"),
+            Some(ContentType::Voice)
+        );
+        assert_eq!(from_category("public.app-category.video"), None);
+        assert_eq!(from_category("music"), None);
+    }
+}
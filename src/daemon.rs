@@ -0,0 +1,4887 @@
+//! The prismd daemon. Lives in the library so the `prismd` binary and `prism` share one
+//! build of it; `prism` runs it as `prism daemon run` or whenever it is invoked under
+//! the name `prismd` (a symlink).
+#![allow(clippy::missing_safety_doc)]
+
+#[path = "bridge.rs"]
+mod bridge;
+
+use crate::agc::{AgcController, AGC_DEFAULT_RANGE_DB};
+use crate::agent;
+use crate::content::{self, Overrides as ContentOverrides};
+use crate::host::{
+    device_is_alive, device_name, fetch_auto_assign, fetch_buffer_regions, fetch_bus_labels,
+    fetch_bus_occupancy, fetch_capture_allowlist, fetch_capture_exclusions, fetch_capture_masks,
+    fetch_capture_rules, fetch_client_list, fetch_client_list_matching, fetch_clock_domain,
+    fetch_clock_rate, fetch_device_events, fetch_device_timing, fetch_gap_holds, fetch_io_stats,
+    fetch_log_level, fetch_matrices, fetch_meters, fetch_monitor, fetch_output_mode,
+    fetch_plugin_info, fetch_reservations, fetch_route_change, fetch_solo, fetch_system_mix,
+    fetch_telemetry_name, find_device, find_prism_device, find_prism_plugin, get_channel_trim_db,
+    input_channel_count, list_audio_devices, read_custom_property_info, send_auto_assign,
+    send_balance, send_bundle_route, send_bus_label, send_capture_allowlist, send_capture_mask,
+    send_capture_rule, send_clear_buffers, send_client_agc, send_clock_domain, send_clock_rate,
+    send_device_timing, send_gap_policy, send_log_level, send_matrix, send_mirror, send_monitor,
+    send_mono_fold, send_output_mode, send_reservation, send_resync, send_rout_batch,
+    send_rout_broadcast, send_rout_update, send_routing_freeze, send_solo, send_system_mix,
+    send_unroute, set_channel_trim_db, BusLevel, CaptureAllowlist, ClientEntry, DeviceTiming,
+    MatrixRoute, OutputMode, RoutUpdate, K_AUDIO_PRISM_PROPERTY_EVENTS, PRISM_EVENT_CLIENTS,
+    PRISM_EVENT_CONFIG, PRISM_EVENT_DIAG, PRISM_EVENT_ROUTING, TIMING_KEYS,
+};
+use crate::ipc::{
+    db_to_linear, linear_to_db, AgcPayload, AssignPolicy, AutoAssignPayload, BridgeLatencyPayload,
+    BridgePayload, BufferRegionPayload, BusLabelPayload, BusLevelPayload, BusPayload,
+    BusWriterPayload, CaptureListPayload, CaptureMaskPayload, CaptureReaderPayload,
+    CaptureRulePayload, ChannelTrimPayload, ClassificationPayload, ClientInfoPayload,
+    ClientMatrixPayload, ClockDomainDevicePayload, ClockDomainPayload, ClockRatePayload,
+    CommandRequest, CompletionKind, CompletionPayload, ConflictPolicy, ContentType,
+    CustomPropertyPayload, DiagPayload, DriftCheckPayload, DriftPayload, DriverDevicePayload,
+    DriverInfoPayload, GapMode, GapPolicyPayload, HealthPayload, IoStatsPayload, LatencyPayload,
+    LogLevelPayload, MatrixRoutePayload, MeterHistoryPayload, MeterSeriesPayload, MonitorPayload,
+    MonitorSendPayload, OutputModePayload, PairPolicyPayload, PresetPayload, PresetRoutePayload,
+    ReservationPayload, ResyncPayload, RoutingUpdateAck, RpcResponse, RulePayload, RulesPayload,
+    SoloPayload, StatusPayload, SystemMixPayload, CONTENT_TYPE_PREFIX, DEFAULT_GAP_HOLD_MS,
+    DEFAULT_ROUTING_FREEZE_MS, DUCK_DB, IDEMPOTENCY_KEY_FIELD, IDEMPOTENCY_TTL_SECS,
+    MAX_BUS_LABEL_CHARS, MAX_GAP_HOLD_MS, NULL_BUS_OFFSET, SILENCE_DB,
+};
+use crate::oslog::Level as LogLevel;
+use crate::persist;
+use crate::preset::{self, Preset};
+use crate::presetfmt;
+use crate::process as procinfo;
+use crate::rules::{self, Rule};
+use crate::socket::{self, SocketAccess, SocketSpec};
+use crate::telemetry::{TelemetryRegion, TelemetrySnapshot};
+use bridge::{Bridge, BridgeEnd};
+use clap::{Parser, Subcommand};
+use coreaudio_sys::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::env;
+use std::ffi::{c_void, OsString};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::fd::{FromRawFd, RawFd};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Parser)]
+#[command(name = "prismd", about = "Prism daemon for managing audio routing")]
+struct Opts {
+    /// Run as a background process
+    #[arg(short = 'd', long = "daemonize")]
+    daemonize: bool,
+
+    /// Internal flag used by the daemon child
+    #[arg(long = "daemon-child")]
+    daemon_child: bool,
+
+    /// Minutes of downsampled meter history to keep in memory (at most 1440)
+    #[arg(long = "meter-history", value_name = "MINUTES", default_value_t = 30)]
+    meter_history: u64,
+
+    /// Flush the meter history to this file every minute and reload it on start
+    #[arg(long = "meter-history-file", value_name = "PATH")]
+    meter_history_file: Option<PathBuf>,
+
+    /// Seconds helpers stay grouped with their app after the app's process exits
+    /// (0 regroups them under their own process right away)
+    #[arg(long = "orphan-grace", value_name = "SECONDS", default_value_t = 60)]
+    orphan_grace: u64,
+
+    /// Seconds between checks that the driver still routes clients where prismd put
+    /// them (0 turns the check off)
+    #[arg(long = "drift-interval", value_name = "SECONDS", default_value_t = 10)]
+    drift_interval: u64,
+
+    /// Milliseconds between polls of the client list on a device whose change listener
+    /// cannot be registered (0 fails to attach instead)
+    #[arg(long = "poll-interval", value_name = "MS", default_value_t = 500)]
+    poll_interval: u64,
+
+    /// Log routing drift without routing the clients back
+    #[arg(long = "drift-report-only")]
+    drift_report_only: bool,
+
+    /// Write the daemon's pid to this file once it serves requests; removed while the
+    /// Prism device is gone
+    #[arg(long = "ready-file", value_name = "PATH")]
+    ready_file: Option<PathBuf>,
+
+    /// Write "READY=1" to this inherited descriptor and close it once requests are served
+    #[arg(long = "ready-fd", value_name = "FD")]
+    ready_fd: Option<RawFd>,
+
+    /// Listen on this socket instead of the default; repeat to listen on several
+    /// (PRISM_SOCKET also sets it)
+    #[arg(long = "socket", value_name = "PATH")]
+    sockets: Vec<PathBuf>,
+
+    /// Listen on a socket only this user can open (/tmp/prismd-<uid>.sock), so each
+    /// user can run their own daemon
+    #[arg(long = "user-socket")]
+    user_socket: bool,
+
+    /// Keep `prism classify` overrides in this file (default:
+    /// ~/Library/Application Support/Prism/content-types.json)
+    #[arg(long = "content-types-file", value_name = "PATH")]
+    content_types_file: Option<PathBuf>,
+
+    /// Offer the presets in the *.json files of this directory alongside the built-in
+    /// ones (default: ~/Library/Application Support/Prism/presets)
+    #[arg(long = "presets-dir", value_name = "DIR")]
+    presets_dir: Option<PathBuf>,
+
+    /// Route apps by the rules in this file as they appear (default:
+    /// ~/Library/Application Support/Prism/rules.json)
+    #[arg(long = "rules-file", value_name = "PATH")]
+    rules_file: Option<PathBuf>,
+
+    /// Keep the routes prismd has made in this file, to put them back after prismd or
+    /// coreaudiod restarts (default: ~/Library/Application Support/Prism/routes.json)
+    #[arg(long = "routes-file", value_name = "PATH")]
+    routes_file: Option<PathBuf>,
+
+    /// Keep settings made through prismd, such as the auto-assignment policy, in this
+    /// file (default: ~/Library/Application Support/Prism/prismd.json)
+    #[arg(long = "settings-file", value_name = "PATH")]
+    settings_file: Option<PathBuf>,
+
+    /// Forward unknown args (collected)
+    #[arg(last = true)]
+    forward_args: Vec<String>,
+
+    #[command(subcommand)]
+    command: Option<AgentCommand>,
+}
+
+#[derive(Subcommand)]
+enum AgentCommand {
+    /// Start prismd at login through a LaunchAgent, and start it now
+    InstallAgent {
+        /// Write prismd's output to this file (default: ~/Library/Logs/Prism/prismd.log)
+        #[arg(long = "log-file", value_name = "PATH")]
+        log_file: Option<PathBuf>,
+
+        /// Options the agent starts prismd with, e.g. -- --user-socket
+        #[arg(last = true, value_name = "PRISMD_ARGS")]
+        args: Vec<String>,
+    },
+    /// Stop prismd's LaunchAgent and remove it
+    UninstallAgent,
+}
+
+// The Prism device prismd talks to, or kAudioObjectUnknown while it is gone (driver
+// update, coreaudiod restart). DEVICE_GENERATION moves on every rediscovery so threads
+// holding per-device state know to rebuild it.
+static DEVICE_ID: AtomicU32 = AtomicU32::new(kAudioObjectUnknown);
+static DEVICE_GENERATION: AtomicU64 = AtomicU64::new(0);
+// Serializes loss and rediscovery, which HAL listeners and IPC commands can both trigger.
+static DEVICE_DISCOVERY: Mutex<()> = Mutex::new(());
+// Set while the current device runs without its 'evnt' or alive listener; the poller
+// stands in for them. Cleared with the device.
+static EVENT_POLLING: AtomicBool = AtomicBool::new(false);
+// --poll-interval; 0 when polling is off.
+static POLL_INTERVAL_MS: AtomicU64 = AtomicU64::new(0);
+// Set by shut_down under DEVICE_DISCOVERY: the device is not adopted again and the
+// poller stops.
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+// Client data the 'evnt' listener of the current device was added with; removing the
+// listener takes the same pointer.
+static DEVICE_EVENT_CONTEXT: AtomicPtr<DeviceEventContext> = AtomicPtr::new(ptr::null_mut());
+
+// When the daemon started serving, and the --ready-file to keep in step with the device.
+static STARTED: OnceLock<Instant> = OnceLock::new();
+static READY_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+static CLIENT_LIST: Mutex<Vec<ClientEntry>> = Mutex::new(Vec::new());
+// Sequence number of the last 'revt' routing change logged; 0 until one is seen.
+static LAST_ROUTE_SEQ: AtomicU64 = AtomicU64::new(0);
+
+// Latest sample of the driver's shared-memory telemetry and when its sequence last moved.
+static TELEMETRY: Mutex<Option<(TelemetrySnapshot, Instant)>> = Mutex::new(None);
+
+const TELEMETRY_POLL_INTERVAL: Duration = Duration::from_millis(33);
+const TELEMETRY_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+// No IO for this long means the buses are silent rather than holding their last level.
+const TELEMETRY_IDLE_TIMEOUT: Duration = Duration::from_millis(500);
+
+// Last app grouping seen per client pid, kept so helpers that outlive their app do not
+// silently move to another group.
+static APP_GROUPS: Mutex<BTreeMap<i32, AppGroup>> = Mutex::new(BTreeMap::new());
+static ORPHAN_GRACE_SECS: AtomicU64 = AtomicU64::new(60);
+
+struct AppGroup {
+    responsible_pid: i32,
+    responsible_name: Option<String>,
+    orphaned_at: Option<Instant>, // When the responsible process was first seen gone
+}
+
+// Process name and app group per client pid, resolved off the request path by the
+// identity worker (the responsibility lookup is a syscall per client). `prism clients`
+// and `prism apps` answer from here and queue what is missing or stale, so a large
+// client list comes back at once and fills in on the next request.
+static CLIENT_IDENTITIES: Mutex<BTreeMap<i32, ClientIdentity>> = Mutex::new(BTreeMap::new());
+static PENDING_IDENTITIES: Mutex<BTreeSet<i32>> = Mutex::new(BTreeSet::new());
+static IDENTITY_WAKE: Condvar = Condvar::new();
+static IDENTITY_WORKER: AtomicBool = AtomicBool::new(false);
+// Identities older than this are resolved again, so helpers of an app that quit regroup.
+const IDENTITY_REFRESH: Duration = Duration::from_secs(2);
+
+#[derive(Clone)]
+struct ClientIdentity {
+    process_name: Option<String>,
+    responsible_pid: Option<i32>,
+    responsible_name: Option<String>,
+    resolved_at: Instant,
+}
+
+// Conflict policy per pair (channel offset); pairs not listed mix.
+static PAIR_POLICIES: Mutex<BTreeMap<u32, ConflictPolicy>> = Mutex::new(BTreeMap::new());
+// Clients lowered by the duck policy, by pid: the pair they were ducked on and the
+// linear gain they had before, put back once nothing else plays on that pair.
+static DUCKED: Mutex<BTreeMap<i32, (u32, f32)>> = Mutex::new(BTreeMap::new());
+
+// Preset applied by `prism quickstart`, with the apps (app_key) it has already routed.
+// Each app is routed once, so a later manual route sticks.
+static ACTIVE_PRESET: Mutex<Option<ActivePreset>> = Mutex::new(None);
+
+struct ActivePreset {
+    preset: Preset,
+    routed: HashSet<i32>,
+    owner: Option<String>, // Reservation owner its routes are sent as
+}
+
+// Where prismd last routed each client pid, and what asked for it. The drift check
+// compares the driver's client list against this and forgets processes that exited; a
+// process with no client left keeps its route for when it plays again.
+static ROUTE_INTENTS: Mutex<BTreeMap<i32, RouteIntent>> = Mutex::new(BTreeMap::new());
+static LAST_DRIFT_CHECK: Mutex<Option<DriftCheckPayload>> = Mutex::new(None);
+static DRIFT_INTERVAL_SECS: AtomicU64 = AtomicU64::new(0);
+
+struct RouteIntent {
+    offset: u32,
+    source: &'static str,
+    // Bundle the process played as when it was routed, if the driver knew it. A client
+    // the process recreates is routed back only as the same bundle
+    bundle_id: Option<String>,
+}
+
+// File ROUTE_INTENTS is saved to on every change. Loaded at startup, the routes go back
+// to the processes still running as the first client list comes in.
+static ROUTES_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedRoute {
+    pid: i32,
+    offset: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bundle_id: Option<String>,
+    // Executable when saved; a pid another program has taken since is not restored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+}
+
+// Responses to requests that carried an idempotency key, kept for
+// IDEMPOTENCY_TTL_SECS so a retried request is answered without running it again.
+// A key is entered before its command runs, with no response yet, so a retry sent
+// while the first attempt is still running waits for it instead of running it twice.
+static IDEMPOTENT_RESPONSES: Mutex<BTreeMap<String, IdempotentResponse>> =
+    Mutex::new(BTreeMap::new());
+static IDEMPOTENT_DONE: Condvar = Condvar::new();
+// How long a retry waits for the attempt still running before it gives up
+const IDEMPOTENT_WAIT: Duration = Duration::from_secs(5);
+
+struct IdempotentResponse {
+    request: serde_json::Value,
+    response: Option<String>, // None while the first attempt runs
+    at: Instant,
+}
+
+// Directory of user preset files. They are read again on every request, so a tool that
+// writes a preset can apply it straight away.
+static PRESETS_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+// Rules from the rules file, the file, and the apps (app_key) the rules have routed.
+// Each app is routed once while it runs, so a later manual route sticks.
+static RULES: Mutex<Vec<Rule>> = Mutex::new(Vec::new());
+static RULES_FILE: OnceLock<PathBuf> = OnceLock::new();
+static RULE_ROUTED: Mutex<BTreeSet<i32>> = Mutex::new(BTreeSet::new());
+
+// Content types set with `prism classify`, and the file they are saved to.
+static CONTENT_OVERRIDES: Mutex<ContentOverrides> = Mutex::new(ContentOverrides::new());
+static CONTENT_TYPES_FILE: OnceLock<PathBuf> = OnceLock::new();
+// Content type read from each executable's bundle, so Info.plist is read once per app.
+static BUNDLE_CONTENT_TYPES: Mutex<BTreeMap<String, Option<ContentType>>> =
+    Mutex::new(BTreeMap::new());
+
+// Settings changed through prismd that the driver forgets when coreaudiod restarts,
+// re-applied to every device prismd adopts, and the file they are saved to.
+static SETTINGS: Mutex<DaemonSettings> = Mutex::new(DaemonSettings {
+    auto_assign: None,
+    bus_labels: BTreeMap::new(),
+    capture_list: None,
+});
+static SETTINGS_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DaemonSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    auto_assign: Option<AssignPolicy>,
+    // Pair names by channel offset
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    bus_labels: BTreeMap<u32, String>,
+    // Last capture allowlist set through prismd, without prismd's own PID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    capture_list: Option<SavedCaptureList>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct SavedCaptureList {
+    enabled: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    bundle_ids: Vec<String>,
+    // Only those still running are restored
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pids: Vec<i32>,
+}
+
+// Bridges started with `prism bridge`; dropping one stops its IOProcs.
+static BRIDGES: Mutex<Vec<Bridge>> = Mutex::new(Vec::new());
+
+// Loudness AGC per app display name.
+static AGC: Mutex<BTreeMap<String, AgcController>> = Mutex::new(BTreeMap::new());
+
+const AGC_INTERVAL: Duration = Duration::from_millis(250);
+
+// Per-second peak history of every bus, for `prism meters --history`.
+static METER_HISTORY: Mutex<VecDeque<HistoryBucket>> = Mutex::new(VecDeque::new());
+
+const HISTORY_SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+const HISTORY_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+// Longest window a history query may ask for.
+const HISTORY_MAX_QUERY_SECS: u64 = 24 * 60 * 60;
+const HISTORY_SYSTEM_MIX: &str = "System mix";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HistoryBucket {
+    time: u64,                // Unix seconds at the start of the bucket
+    peaks: Vec<f32>,          // Per pair, max over both channels and the second
+    apps: Vec<(String, u32)>, // App display name and the pair it was routed to
+}
+
+fn json_response<T>(status: &str, message: Option<String>, data: Option<T>) -> String
+where
+    T: Serialize,
+{
+    let payload = RpcResponse {
+        status: status.to_string(),
+        message,
+        data,
+    };
+    let serialized = serde_json::to_string(&payload).unwrap_or_else(|err| {
+        serde_json::to_string(&RpcResponse::<serde_json::Value> {
+            status: "error".to_string(),
+            message: Some(format!("failed to serialize response: {}", err)),
+            data: None,
+        })
+        .unwrap()
+    });
+    format!("{}\n", serialized)
+}
+
+fn json_success_with_data<T>(data: T) -> String
+where
+    T: Serialize,
+{
+    json_response("ok", None, Some(data))
+}
+
+fn json_success_with_message_and_data<T>(message: String, data: T) -> String
+where
+    T: Serialize,
+{
+    json_response("ok", Some(message), Some(data))
+}
+
+fn json_error(message: String) -> String {
+    json_response::<serde_json::Value>("error", Some(message), None)
+}
+
+// daemon no longer provides a help payload; CLI serves local help.
+
+// clap handles parsing and help printing for prismd
+
+fn spawn_daemon_child(args: &[String]) -> Result<u32, String> {
+    let exe = env::current_exe().map_err(|err| err.to_string())?;
+
+    let mut child_args = Vec::with_capacity(args.len() + 1);
+    child_args.extend(args.iter().cloned());
+    child_args.push("--daemon-child".to_string());
+
+    // Named prismd so the child starts as the daemon whichever name launched us
+    let child = Command::new(exe)
+        .arg0(DAEMON_NAME)
+        .args(&child_args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| err.to_string())?;
+
+    Ok(child.id())
+}
+
+/// Name the binary answers to as the daemon.
+pub const DAEMON_NAME: &str = "prismd";
+
+/// Whether argv[0] names the daemon, i.e. the binary was started through the prismd link.
+pub fn invoked_as_daemon() -> bool {
+    env::args_os()
+        .next()
+        .map(PathBuf::from)
+        .and_then(|path| path.file_name().map(|name| name == DAEMON_NAME))
+        .unwrap_or(false)
+}
+
+/// Run the daemon with prismd's command line; `args` starts with the program name.
+pub fn run<I>(args: I)
+where
+    I: IntoIterator<Item = OsString>,
+{
+    let opts = Opts::parse_from(args);
+
+    if let Some(command) = &opts.command {
+        let result = match command {
+            AgentCommand::InstallAgent { log_file, args } => install_agent(log_file.clone(), args),
+            AgentCommand::UninstallAgent => match agent::uninstall() {
+                Ok(true) => {
+                    println!("prismd LaunchAgent removed");
+                    Ok(())
+                }
+                Ok(false) => {
+                    println!("No prismd LaunchAgent installed");
+                    Ok(())
+                }
+                Err(err) => Err(err),
+            },
+        };
+        if let Err(err) = result {
+            eprintln!("[prismd] {}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if opts.daemon_child {
+        run_daemon(&opts);
+        return;
+    }
+
+    if opts.daemonize {
+        let mut child_args = vec![
+            "--meter-history".to_string(),
+            opts.meter_history.to_string(),
+            "--orphan-grace".to_string(),
+            opts.orphan_grace.to_string(),
+            "--drift-interval".to_string(),
+            opts.drift_interval.to_string(),
+            "--poll-interval".to_string(),
+            opts.poll_interval.to_string(),
+        ];
+        if opts.drift_report_only {
+            child_args.push("--drift-report-only".to_string());
+        }
+        if let Some(path) = &opts.meter_history_file {
+            child_args.push("--meter-history-file".to_string());
+            child_args.push(path.display().to_string());
+        }
+        if let Some(path) = &opts.content_types_file {
+            child_args.push("--content-types-file".to_string());
+            child_args.push(path.display().to_string());
+        }
+        if let Some(path) = &opts.presets_dir {
+            child_args.push("--presets-dir".to_string());
+            child_args.push(path.display().to_string());
+        }
+        if let Some(path) = &opts.rules_file {
+            child_args.push("--rules-file".to_string());
+            child_args.push(path.display().to_string());
+        }
+        if let Some(path) = &opts.routes_file {
+            child_args.push("--routes-file".to_string());
+            child_args.push(path.display().to_string());
+        }
+        if let Some(path) = &opts.settings_file {
+            child_args.push("--settings-file".to_string());
+            child_args.push(path.display().to_string());
+        }
+        if let Some(path) = &opts.ready_file {
+            child_args.push("--ready-file".to_string());
+            child_args.push(path.display().to_string());
+        }
+        if let Some(fd) = opts.ready_fd {
+            child_args.push("--ready-fd".to_string());
+            child_args.push(fd.to_string());
+        }
+        for path in &opts.sockets {
+            child_args.push("--socket".to_string());
+            child_args.push(path.display().to_string());
+        }
+        if opts.user_socket {
+            child_args.push("--user-socket".to_string());
+        }
+        child_args.extend(opts.forward_args.iter().cloned());
+        match spawn_daemon_child(&child_args) {
+            Ok(pid) => {
+                println!("prismd started in background (pid={})", pid);
+                return;
+            }
+            Err(err) => {
+                eprintln!("[prismd] Failed to daemonize: {}", err);
+                process::exit(1);
+            }
+        }
+    }
+
+    if !opts.forward_args.is_empty() {
+        eprintln!(
+            "[prismd] Unknown arguments: {}",
+            opts.forward_args.join(" ")
+        );
+        process::exit(2);
+    }
+
+    run_daemon(&opts);
+}
+
+// launchd runs the agent's prismd in the foreground and restarts it, so it must not
+// fork itself away. The arguments are checked here, as a typo would otherwise only
+// show as launchd restarting a daemon that keeps exiting.
+fn install_agent(log_file: Option<PathBuf>, args: &[String]) -> Result<(), String> {
+    let parsed =
+        Opts::try_parse_from(std::iter::once(DAEMON_NAME.to_string()).chain(args.iter().cloned()))
+            .map_err(|err| format!("invalid prismd arguments: {}", err))?;
+    if parsed.daemonize || parsed.daemon_child || parsed.command.is_some() {
+        return Err("launchd keeps prismd in the foreground; drop --daemonize".to_string());
+    }
+    let log = match log_file {
+        // launchd resolves nothing against our working directory
+        Some(path) if path.is_relative() => env::current_dir()
+            .map_err(|err| err.to_string())?
+            .join(path),
+        Some(path) => path,
+        None => agent::default_log_path()?,
+    };
+    let exe = env::current_exe().map_err(|err| err.to_string())?;
+    // Named prismd, as spawn_daemon_child does, so either binary name works
+    let path = agent::install(&exe, DAEMON_NAME, args, &log)?;
+    println!("prismd LaunchAgent installed at {}", path.display());
+    println!("prismd starts at login; output goes to {}", log.display());
+    Ok(())
+}
+
+struct DeviceEventContext {
+    device_id: AudioObjectID,
+}
+
+unsafe extern "C" fn device_event_listener(
+    _: AudioObjectID,
+    _: UInt32,
+    _: *const AudioObjectPropertyAddress,
+    client_data: *mut c_void,
+) -> OSStatus {
+    if client_data.is_null() {
+        return 0;
+    }
+
+    let context = &*(client_data as *mut DeviceEventContext);
+    if let Err(err) = handle_device_events(context.device_id) {
+        eprintln!("[prismd] Failed to handle driver events: {}", err);
+    }
+
+    0
+}
+
+// Read the driver's pending change mask and refetch only what it names. Several
+// notifications may collapse into one mask, in which case later reads see 0.
+fn handle_device_events(device_id: AudioObjectID) -> Result<(), String> {
+    let events = fetch_device_events(device_id)?;
+    if events & PRISM_EVENT_ROUTING != 0 {
+        report_route_change(device_id);
+    }
+    if events & (PRISM_EVENT_CLIENTS | PRISM_EVENT_ROUTING) != 0 {
+        handle_client_list_update(device_id)?;
+    }
+    if events & PRISM_EVENT_CONFIG != 0 {
+        println!("[prismd] Driver configuration changed");
+    }
+    if events & PRISM_EVENT_DIAG != 0 {
+        match fetch_io_stats(device_id) {
+            Ok(stats) => println!(
+                "[prismd] Driver diagnostics changed (slots {}/{}, {} growths)",
+                stats.slots_occupied, stats.slot_table_size, stats.slot_table_growths
+            ),
+            Err(err) => eprintln!("[prismd] Failed to read IO stats: {}", err),
+        }
+    }
+    Ok(())
+}
+
+// Log the client move 'revt' describes. The driver only keeps the last one, so changes
+// that landed between two reads are counted rather than described.
+fn report_route_change(device_id: AudioObjectID) {
+    let change = match fetch_route_change(device_id) {
+        Ok(Some(change)) => change,
+        Ok(None) => return,
+        Err(err) => {
+            eprintln!("[prismd] Failed to read the last routing change: {}", err);
+            return;
+        }
+    };
+    let last = LAST_ROUTE_SEQ.swap(change.seq, Ordering::AcqRel);
+    if change.seq <= last {
+        return;
+    }
+    let process_name =
+        procinfo::process_name(change.pid).unwrap_or_else(|| "<unknown>".to_string());
+    println!(
+        "[prismd] Event: pid={} ({}) client_id={} moved {} -> {} ({})",
+        change.pid,
+        process_name,
+        change.client_id,
+        pair_label(change.old_offset),
+        pair_label(change.new_offset),
+        change.reason
+    );
+    let missed = change.seq - last - 1;
+    if last != 0 && missed > 0 {
+        println!(
+            "[prismd]     {} earlier routing change(s) since the last event",
+            missed
+        );
+    }
+}
+
+fn handle_client_list_update(device_id: AudioObjectID) -> Result<(), String> {
+    let clients = fetch_client_list(device_id)?;
+
+    let known: HashSet<u32> = {
+        let mut cache = CLIENT_LIST.lock().expect("client list mutex poisoned");
+        let known = cache.iter().map(|entry| entry.client_id).collect();
+        *cache = clients.clone();
+        known
+    };
+
+    println!("[prismd] Client list updated ({} entries)", clients.len());
+    for entry in &clients {
+        let process_name =
+            procinfo::process_name(entry.pid).unwrap_or_else(|| "<unknown>".to_string());
+        println!(
+            "    pid={} ({}) client_id={} offset={}",
+            entry.pid, process_name, entry.client_id, entry.channel_offset
+        );
+        // Resolve new clients now, so the next request finds them named
+        if !CLIENT_IDENTITIES
+            .lock()
+            .expect("client identity mutex poisoned")
+            .contains_key(&entry.pid)
+        {
+            queue_identity(entry.pid);
+        }
+    }
+
+    restore_routes(device_id, &clients, &known);
+    restore_ducked(device_id, &clients);
+    route_by_rules(device_id);
+    route_by_active_preset(device_id);
+    Ok(())
+}
+
+// Undo the duck policy for clients whose pair has emptied of everything that was not
+// ducked. A ducked client that left the pair or went away is forgotten.
+fn restore_ducked(device_id: AudioObjectID, clients: &[ClientEntry]) {
+    let mut restore = Vec::new();
+    {
+        let mut ducked = DUCKED.lock().expect("ducked mutex poisoned");
+        let before = ducked.clone();
+        let is_ducked =
+            |pid: i32, offset: u32| before.get(&pid).is_some_and(|&(pair, _)| pair == offset);
+        ducked.retain(|&pid, &mut (offset, gain)| {
+            let mut on_pair = clients
+                .iter()
+                .filter(|client| client.channel_offset == offset);
+            if !on_pair.clone().any(|client| client.pid == pid) {
+                return false;
+            }
+            if on_pair.all(|client| is_ducked(client.pid, offset)) {
+                restore.push((pid, offset, gain));
+                return false;
+            }
+            true
+        });
+    }
+    for (pid, offset, gain) in restore {
+        match send_rout_update(device_id, pid, offset, Some(gain), None, None) {
+            Ok(()) => println!(
+                "[prismd] Restored the gain of pid {} on {}",
+                pid,
+                pair_label(offset)
+            ),
+            Err(err) => eprintln!(
+                "[prismd] Failed to restore the gain of pid {}: {}",
+                pid, err
+            ),
+        }
+    }
+}
+
+fn register_device_event_listener(device_id: AudioObjectID) -> Result<(), String> {
+    let address = AudioObjectPropertyAddress {
+        mSelector: K_AUDIO_PRISM_PROPERTY_EVENTS,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+
+    let context = Box::new(DeviceEventContext { device_id });
+    let context_ptr = Box::into_raw(context);
+    let status = unsafe {
+        AudioObjectAddPropertyListener(
+            device_id,
+            &address,
+            Some(device_event_listener),
+            context_ptr as *mut _,
+        )
+    };
+
+    if status != 0 {
+        unsafe {
+            drop(Box::from_raw(context_ptr));
+        }
+        return Err(format!(
+            "AudioObjectAddPropertyListener('evnt') failed with status {}",
+            status
+        ));
+    }
+    DEVICE_EVENT_CONTEXT.store(context_ptr, Ordering::Release);
+
+    Ok(())
+}
+
+fn unregister_device_event_listener(device_id: AudioObjectID) {
+    let context_ptr = DEVICE_EVENT_CONTEXT.swap(ptr::null_mut(), Ordering::AcqRel);
+    if context_ptr.is_null() {
+        return;
+    }
+    let address = AudioObjectPropertyAddress {
+        mSelector: K_AUDIO_PRISM_PROPERTY_EVENTS,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    let status = unsafe {
+        AudioObjectRemovePropertyListener(
+            device_id,
+            &address,
+            Some(device_event_listener),
+            context_ptr as *mut _,
+        )
+    };
+    if status != 0 {
+        // The HAL may still call it; leaking the context is the safe side
+        eprintln!(
+            "[prismd] AudioObjectRemovePropertyListener('evnt') failed with status {}",
+            status
+        );
+        return;
+    }
+    unsafe {
+        drop(Box::from_raw(context_ptr));
+    }
+}
+
+unsafe extern "C" fn device_alive_listener(
+    device_id: AudioObjectID,
+    _: UInt32,
+    _: *const AudioObjectPropertyAddress,
+    _: *mut c_void,
+) -> OSStatus {
+    if !device_is_alive(device_id) {
+        mark_device_lost(device_id, "device is no longer alive");
+    }
+    0
+}
+
+unsafe extern "C" fn device_list_listener(
+    _: AudioObjectID,
+    _: UInt32,
+    _: *const AudioObjectPropertyAddress,
+    _: *mut c_void,
+) -> OSStatus {
+    if let Some(device_id) = current_device() {
+        match find_prism_device() {
+            Ok(found) if found == device_id => {
+                // The change may be a bridge's other device returning
+                restart_bridges();
+                return 0;
+            }
+            _ => mark_device_lost(device_id, "removed from the device list"),
+        }
+    }
+    rediscover_device();
+    0
+}
+
+fn add_property_listener(
+    object_id: AudioObjectID,
+    selector: AudioObjectPropertySelector,
+    listener: AudioObjectPropertyListenerProc,
+    name: &str,
+) -> Result<(), String> {
+    let address = AudioObjectPropertyAddress {
+        mSelector: selector,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+
+    let status =
+        unsafe { AudioObjectAddPropertyListener(object_id, &address, listener, ptr::null_mut()) };
+    if status != 0 {
+        return Err(format!(
+            "AudioObjectAddPropertyListener('{}') failed with status {}",
+            name, status
+        ));
+    }
+    Ok(())
+}
+
+fn remove_property_listener(
+    object_id: AudioObjectID,
+    selector: AudioObjectPropertySelector,
+    listener: AudioObjectPropertyListenerProc,
+    name: &str,
+) -> Result<(), String> {
+    let address = AudioObjectPropertyAddress {
+        mSelector: selector,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+
+    let status = unsafe {
+        AudioObjectRemovePropertyListener(object_id, &address, listener, ptr::null_mut())
+    };
+    if status != 0 {
+        return Err(format!(
+            "AudioObjectRemovePropertyListener('{}') failed with status {}",
+            name, status
+        ));
+    }
+    Ok(())
+}
+
+fn current_device() -> Option<AudioObjectID> {
+    let device_id = DEVICE_ID.load(Ordering::Acquire);
+    if device_id == kAudioObjectUnknown {
+        None
+    } else {
+        Some(device_id)
+    }
+}
+
+// The device to serve a command with. While it is gone, look for it here too in case
+// the device-list notification was missed, so commands recover without a restart.
+fn ensure_device() -> Result<AudioObjectID, String> {
+    if let Some(device_id) = current_device() {
+        return Ok(device_id);
+    }
+    rediscover_device();
+    current_device().ok_or_else(|| {
+        "Prism device is not available; prismd is waiting for the driver to return".to_string()
+    })
+}
+
+// Start listening to a (re)discovered device and make it the current one.
+fn adopt_device(device_id: AudioObjectID) -> Result<(), String> {
+    let poll_ms = POLL_INTERVAL_MS.load(Ordering::Relaxed);
+    let mut polling = false;
+    for registered in [
+        register_device_event_listener(device_id),
+        add_property_listener(
+            device_id,
+            kAudioDevicePropertyDeviceIsAlive,
+            Some(device_alive_listener),
+            "livn",
+        ),
+    ] {
+        match registered {
+            Ok(()) => {}
+            Err(err) if poll_ms > 0 => {
+                eprintln!(
+                    "[prismd] {}; polling the client list every {} ms instead",
+                    err, poll_ms
+                );
+                polling = true;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    EVENT_POLLING.store(polling, Ordering::Release);
+
+    // A fresh driver starts from Driver.plist; put back what was set through prismd
+    apply_settings(device_id);
+
+    // Drop events raised before we started listening; the full fetch covers them
+    let _ = fetch_device_events(device_id);
+    DEVICE_ID.store(device_id, Ordering::Release);
+    DEVICE_GENERATION.fetch_add(1, Ordering::Relaxed);
+    if let Err(err) = handle_client_list_update(device_id) {
+        eprintln!("[prismd] Initial client list fetch failed: {}", err);
+    }
+    restart_bridges();
+    Ok(())
+}
+
+// Stands in for the device listeners adopt_device could not register: notices the
+// device going away and handles client list changes, each a poll later than a listener
+// would. A lost device is looked for on every poll, in case the device list listener
+// is missing too.
+fn start_event_poller(interval_ms: u64) -> io::Result<()> {
+    if interval_ms == 0 {
+        return Ok(());
+    }
+    thread::Builder::new()
+        .name("prismd-poll".to_string())
+        .spawn(move || loop {
+            thread::sleep(Duration::from_millis(interval_ms));
+            if SHUTTING_DOWN.load(Ordering::Acquire) {
+                break;
+            }
+            let Some(device_id) = current_device() else {
+                rediscover_device();
+                continue;
+            };
+            if !EVENT_POLLING.load(Ordering::Acquire) {
+                continue;
+            }
+            if !device_is_alive(device_id) {
+                mark_device_lost(device_id, "device is no longer alive");
+                continue;
+            }
+            let clients = match fetch_client_list(device_id) {
+                Ok(clients) => clients,
+                Err(err) => {
+                    eprintln!("[prismd] Failed to poll the client list: {}", err);
+                    continue;
+                }
+            };
+            let key = |entry: &ClientEntry| {
+                (
+                    entry.client_id,
+                    entry.pid,
+                    entry.channel_offset,
+                    entry.bundle_id.clone(),
+                )
+            };
+            let changed = {
+                let cache = CLIENT_LIST.lock().expect("client list mutex poisoned");
+                !cache.iter().map(key).eq(clients.iter().map(key))
+            };
+            if changed {
+                if let Err(err) = handle_client_list_update(device_id) {
+                    eprintln!("[prismd] Failed to handle client list: {}", err);
+                }
+            }
+        })?;
+    Ok(())
+}
+
+// A bridge's IOProcs die with either device (unplugged, coreaudiod restarted), and a
+// device that comes back gets a new ID. Start dead bridges again on the devices now
+// carrying their names; one whose device is still missing stays listed as dead.
+fn restart_bridges() {
+    let mut bridges = BRIDGES.lock().expect("bridge mutex poisoned");
+    for bridge in bridges.iter_mut().filter(|bridge| !bridge.is_alive()) {
+        let (Ok(source_id), Ok(target_id)) = (
+            find_device(&bridge.source.name),
+            find_device(&bridge.target.name),
+        ) else {
+            continue;
+        };
+        let source = BridgeEnd {
+            device_id: source_id,
+            ..bridge.source.clone()
+        };
+        let target = BridgeEnd {
+            device_id: target_id,
+            ..bridge.target.clone()
+        };
+        match Bridge::start(bridge.id, source, target) {
+            // Replacing the old bridge drops it, which clears its stale IOProcs
+            Ok(restarted) => {
+                *bridge = restarted;
+                apply_monitor_sends(bridge);
+                println!(
+                    "[prismd] Bridge {} restarted: {} -> {}",
+                    bridge.id, bridge.source.name, bridge.target.name
+                );
+            }
+            Err(err) => eprintln!("[prismd] Failed to restart bridge {}: {}", bridge.id, err),
+        }
+    }
+}
+
+// The monitor downmix plays through the bridges carrying Prism's 1-2: they fold the
+// pairs with a send into what they copy, so capture clients keep every pair separate.
+fn apply_monitor_sends(bridge: &Bridge) {
+    let Some(device_id) = current_device() else {
+        return;
+    };
+    if bridge.source.device_id != device_id || bridge.source.channel_offset != 0 {
+        return;
+    }
+    match fetch_monitor(device_id) {
+        Ok(state) if state.enabled => bridge.set_sends(&state.sends),
+        Ok(_) => bridge.set_sends(&[]),
+        Err(err) => eprintln!(
+            "[prismd] Failed to read the monitor sends for bridge {}: {}",
+            bridge.id, err
+        ),
+    }
+}
+
+fn apply_settings(device_id: AudioObjectID) {
+    let (auto_assign, bus_labels, capture_list) = {
+        let settings = SETTINGS.lock().expect("settings mutex poisoned");
+        (
+            settings.auto_assign,
+            settings.bus_labels.clone(),
+            settings.capture_list.clone(),
+        )
+    };
+    restore_capture_list(device_id, capture_list);
+    for (offset, label) in &bus_labels {
+        if let Err(err) = send_bus_label(device_id, *offset, Some(label)) {
+            eprintln!(
+                "[prismd] Failed to restore the name of {}: {}",
+                pair_label(*offset),
+                err
+            );
+        }
+    }
+    if let Some(policy) = auto_assign {
+        if let Err(err) = send_auto_assign(device_id, policy.name()) {
+            eprintln!(
+                "[prismd] Failed to restore auto-assign policy {}: {}",
+                policy.name(),
+                err
+            );
+        }
+    }
+}
+
+// The capture allowlist must carry this prismd's PID, or its bridges read silence. A
+// driver that outlived the previous prismd still lists that one's PID, so the list is
+// rebuilt on every device adopted: from the saved list, else from the driver's own.
+fn restore_capture_list(device_id: AudioObjectID, saved: Option<SavedCaptureList>) {
+    let mut allowlist = match saved {
+        Some(saved) => CaptureAllowlist {
+            enabled: saved.enabled,
+            bundle_ids: saved.bundle_ids,
+            pids: saved.pids,
+        },
+        None => match fetch_capture_allowlist(device_id) {
+            Ok(allowlist) if allowlist.enabled || !allowlist.pids.is_empty() => allowlist,
+            Ok(_) => return,
+            Err(err) => {
+                eprintln!("[prismd] Failed to read the capture allowlist: {}", err);
+                return;
+            }
+        },
+    };
+    let own_pid = process::id() as i32;
+    allowlist
+        .pids
+        .retain(|&pid| pid != own_pid && procinfo::is_alive(pid));
+    allowlist.pids.push(own_pid);
+    if let Err(err) = send_capture_allowlist(device_id, &allowlist) {
+        eprintln!("[prismd] Failed to restore the capture allowlist: {}", err);
+    }
+}
+
+fn default_settings_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME")?;
+    Some(PathBuf::from(home).join("Library/Application Support/Prism/prismd.json"))
+}
+
+fn load_settings(path: &Path) -> Result<DaemonSettings, String> {
+    let raw = match fs::read_to_string(path) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(DaemonSettings::default()),
+        Err(err) => return Err(format!("{}: {}", path.display(), err)),
+    };
+    serde_json::from_str(&raw).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+fn save_settings(settings: &DaemonSettings, path: &Path) -> Result<(), String> {
+    write_json(settings, path)
+}
+
+fn default_routes_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME")?;
+    Some(PathBuf::from(home).join("Library/Application Support/Prism/routes.json"))
+}
+
+// Saved routes of processes that still run the executable they ran when saved.
+fn load_routes(path: &Path) -> Result<BTreeMap<i32, RouteIntent>, String> {
+    let raw = match fs::read_to_string(path) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(err) => return Err(format!("{}: {}", path.display(), err)),
+    };
+    let saved: Vec<SavedRoute> =
+        serde_json::from_str(&raw).map_err(|err| format!("{}: {}", path.display(), err))?;
+    Ok(saved
+        .into_iter()
+        .filter(|route| procinfo::is_alive(route.pid))
+        .filter(|route| route.path.is_none() || procinfo::process_path(route.pid) == route.path)
+        .map(|route| {
+            (
+                route.pid,
+                RouteIntent {
+                    offset: route.offset,
+                    source: "saved",
+                    bundle_id: route.bundle_id,
+                },
+            )
+        })
+        .collect())
+}
+
+// Called with the intents lock held after every change, so saves never interleave.
+fn save_routes(intents: &BTreeMap<i32, RouteIntent>) {
+    let Some(path) = ROUTES_FILE.get() else {
+        return;
+    };
+    let saved: Vec<SavedRoute> = intents
+        .iter()
+        .map(|(pid, intent)| SavedRoute {
+            pid: *pid,
+            offset: intent.offset,
+            bundle_id: intent.bundle_id.clone(),
+            path: procinfo::process_path(*pid),
+        })
+        .collect();
+    if let Err(err) = write_json(&saved, path) {
+        eprintln!("[prismd] Failed to save routes: {}", err);
+    }
+}
+
+fn write_json<T: Serialize>(value: &T, path: &Path) -> Result<(), String> {
+    let serialized = serde_json::to_string_pretty(value).map_err(|err| err.to_string())?;
+    persist::write_atomic(path, serialized.as_bytes())
+}
+
+// Forget everything cached about `device_id`. The HAL drops its listeners along with
+// the object, so there is nothing to unregister.
+fn mark_device_lost(device_id: AudioObjectID, reason: &str) {
+    let _guard = DEVICE_DISCOVERY
+        .lock()
+        .expect("device discovery mutex poisoned");
+    if DEVICE_ID
+        .compare_exchange(
+            device_id,
+            kAudioObjectUnknown,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        )
+        .is_err()
+    {
+        return;
+    }
+    EVENT_POLLING.store(false, Ordering::Release);
+
+    CLIENT_LIST
+        .lock()
+        .expect("client list mutex poisoned")
+        .clear();
+    // A reloaded driver counts its changes from 1 again
+    LAST_ROUTE_SEQ.store(0, Ordering::Release);
+    if let Ok(mut guard) = TELEMETRY.lock() {
+        *guard = None;
+    }
+    if let Some(path) = READY_FILE.get() {
+        let _ = fs::remove_file(path);
+    }
+    println!(
+        "[prismd] Event: Prism device {} lost ({}); waiting for it to reappear",
+        device_id, reason
+    );
+}
+
+fn rediscover_device() {
+    let _guard = DEVICE_DISCOVERY
+        .lock()
+        .expect("device discovery mutex poisoned");
+    if SHUTTING_DOWN.load(Ordering::Acquire) || current_device().is_some() {
+        return;
+    }
+    let Ok(device_id) = find_prism_device() else {
+        return;
+    };
+    if !device_is_alive(device_id) {
+        return;
+    }
+    match adopt_device(device_id) {
+        Ok(()) => {
+            println!(
+                "[prismd] Event: Prism device rediscovered (ID {})",
+                device_id
+            );
+            write_ready_file();
+        }
+        Err(err) => eprintln!(
+            "[prismd] Failed to attach to rediscovered device {}: {}",
+            device_id, err
+        ),
+    }
+}
+
+fn write_ready_file() {
+    let Some(path) = READY_FILE.get() else {
+        return;
+    };
+    if let Err(err) = fs::write(path, format!("{}\n", process::id())) {
+        eprintln!(
+            "[prismd] Failed to write ready file {}: {}",
+            path.display(),
+            err
+        );
+    }
+}
+
+// Tell a supervisor the daemon is serving: the ready file (kept only while the device
+// is attached) and/or a one-shot "READY=1" on an inherited descriptor, sd_notify style.
+fn signal_ready(opts: &Opts) {
+    STARTED.get_or_init(Instant::now);
+    if let Some(path) = &opts.ready_file {
+        let _ = READY_FILE.set(path.clone());
+        if current_device().is_some() {
+            write_ready_file();
+        }
+    }
+    if let Some(fd) = opts.ready_fd {
+        // Taking ownership closes the descriptor once the line is written
+        let mut notify = unsafe { fs::File::from_raw_fd(fd) };
+        if let Err(err) = notify.write_all(b"READY=1\n") {
+            eprintln!("[prismd] Failed to signal readiness on fd {}: {}", fd, err);
+        }
+    }
+}
+
+// Straight from the plug-in object, so it answers while the device is being rediscovered.
+fn driver_info_payload() -> Result<DriverInfoPayload, String> {
+    let info = fetch_plugin_info(find_prism_plugin()?)?;
+    Ok(DriverInfoPayload {
+        version: info.version,
+        build: info.build,
+        debug: info.debug,
+        features: info.features,
+        devices: info
+            .devices
+            .into_iter()
+            .map(|device| DriverDevicePayload {
+                device_id: device.device_id,
+                uid: device.uid,
+                name: device.name,
+                channels: device.channels,
+                clients: device.clients,
+                io_running: device.io_running,
+            })
+            .collect(),
+        total_clients: info.total_clients,
+    })
+}
+
+fn health_payload() -> HealthPayload {
+    let device_id = current_device();
+    HealthPayload {
+        healthy: device_id.is_some(),
+        device_id,
+        pid: process::id(),
+        uptime_secs: STARTED
+            .get()
+            .map(|started| started.elapsed().as_secs())
+            .unwrap_or(0),
+    }
+}
+
+fn status_payload() -> StatusPayload {
+    StatusPayload {
+        healthy: current_device().is_some(),
+        tracked_routes: ROUTE_INTENTS
+            .lock()
+            .expect("route intent mutex poisoned")
+            .len(),
+        drift_interval_secs: DRIFT_INTERVAL_SECS.load(Ordering::Relaxed),
+        drift_check: LAST_DRIFT_CHECK
+            .lock()
+            .expect("drift mutex poisoned")
+            .clone(),
+    }
+}
+
+fn remember_route(pids: impl IntoIterator<Item = i32>, offset: u32, source: &'static str) {
+    let clients = CLIENT_LIST
+        .lock()
+        .expect("client list mutex poisoned")
+        .clone();
+    let mut intents = ROUTE_INTENTS.lock().expect("route intent mutex poisoned");
+    for pid in pids {
+        let bundle_id = clients
+            .iter()
+            .find(|entry| entry.pid == pid && entry.bundle_id.is_some())
+            .and_then(|entry| entry.bundle_id.clone());
+        intents.insert(
+            pid,
+            RouteIntent {
+                offset,
+                source,
+                bundle_id,
+            },
+        );
+    }
+    save_routes(&intents);
+}
+
+// A process that tears its client down and creates another (a sample-rate change, a
+// device switch) comes back on 1-2 or an auto-assigned pair. Send the route prismd gave
+// it straight back as the new client shows up, instead of waiting for the drift check.
+// After a restart every client is new, so the saved routes go back the same way.
+fn restore_routes(device_id: AudioObjectID, clients: &[ClientEntry], known: &HashSet<u32>) {
+    let intents = ROUTE_INTENTS.lock().expect("route intent mutex poisoned");
+    let mut updates: Vec<RoutUpdate> = Vec::new();
+    for entry in clients {
+        if known.contains(&entry.client_id) || updates.iter().any(|u| u.pid == entry.pid) {
+            continue;
+        }
+        let Some(intent) = intents.get(&entry.pid) else {
+            continue;
+        };
+        if intent.offset == entry.channel_offset
+            || (intent.bundle_id.is_some() && intent.bundle_id != entry.bundle_id)
+        {
+            continue;
+        }
+        println!(
+            "[prismd] Routing pid {} back to {} ({})",
+            entry.pid,
+            pair_label(intent.offset),
+            intent.source
+        );
+        updates.push(RoutUpdate {
+            pid: entry.pid,
+            channel_offset: intent.offset,
+            gain: None,
+            pan: None,
+            width: None,
+        });
+    }
+    drop(intents);
+    if updates.is_empty() {
+        return;
+    }
+    if let Err(err) = send_rout_batch(device_id, &updates, None) {
+        eprintln!("[prismd] Failed to restore routes: {}", err);
+    }
+}
+
+// Periodically compare where the driver routes each client with where prismd last
+// routed it, to catch property writes the driver missed and driver restarts that
+// dropped every route. A mismatch has to show on two checks in a row, so a route being
+// applied while the client list is read is not taken for drift.
+fn start_drift_check(interval_secs: u64, report_only: bool) -> io::Result<()> {
+    DRIFT_INTERVAL_SECS.store(interval_secs, Ordering::Relaxed);
+    if interval_secs == 0 {
+        return Ok(());
+    }
+    thread::Builder::new()
+        .name("prismd-drift".to_string())
+        .spawn(move || {
+            let mut suspects: HashSet<(i32, u32)> = HashSet::new();
+            loop {
+                thread::sleep(Duration::from_secs(interval_secs));
+                let Some(device_id) = current_device() else {
+                    suspects.clear();
+                    continue;
+                };
+                let report = check_drift(device_id, &mut suspects, report_only);
+                *LAST_DRIFT_CHECK.lock().expect("drift mutex poisoned") = Some(report);
+            }
+        })?;
+    Ok(())
+}
+
+fn check_drift(
+    device_id: AudioObjectID,
+    suspects: &mut HashSet<(i32, u32)>,
+    report_only: bool,
+) -> DriftCheckPayload {
+    let mut report = DriftCheckPayload {
+        checked_at: unix_now(),
+        tracked: 0,
+        drifted: Vec::new(),
+        corrected: false,
+        error: None,
+    };
+    let clients = match build_clients_payload(device_id) {
+        Ok(clients) => clients,
+        Err(err) => {
+            report.error = Some(format!("failed to fetch clients: {}", err));
+            return report;
+        }
+    };
+
+    let mut intents = ROUTE_INTENTS.lock().expect("route intent mutex poisoned");
+    let before = intents.len();
+    intents.retain(|pid, _| {
+        clients.iter().any(|client| client.pid == *pid) || procinfo::is_alive(*pid)
+    });
+    if intents.len() != before {
+        save_routes(&intents);
+    }
+    report.tracked = intents.len();
+    let mut current = HashSet::new();
+    for client in &clients {
+        let Some(intent) = intents.get(&client.pid) else {
+            continue;
+        };
+        let key = (client.pid, client.channel_offset);
+        if intent.offset == client.channel_offset || !current.insert(key) {
+            continue;
+        }
+        if suspects.contains(&key) {
+            report.drifted.push(DriftPayload {
+                pid: client.pid,
+                app_name: client
+                    .responsible_name
+                    .clone()
+                    .or_else(|| client.process_name.clone()),
+                intended_offset: intent.offset,
+                actual_offset: client.channel_offset,
+                source: intent.source.to_string(),
+            });
+        }
+    }
+    drop(intents);
+    *suspects = current;
+
+    if report.drifted.is_empty() {
+        return report;
+    }
+    for drift in &report.drifted {
+        eprintln!(
+            "[prismd] Drift: pid {} ({}) is on {} but was routed to {} by {}",
+            drift.pid,
+            drift.app_name.as_deref().unwrap_or("unknown"),
+            pair_label(drift.actual_offset),
+            pair_label(drift.intended_offset),
+            drift.source
+        );
+    }
+    if report_only {
+        return report;
+    }
+    let updates: Vec<RoutUpdate> = report
+        .drifted
+        .iter()
+        .map(|drift| RoutUpdate {
+            pid: drift.pid,
+            channel_offset: drift.intended_offset,
+            gain: None,
+            pan: None,
+            width: None,
+        })
+        .collect();
+    match send_rout_batch(device_id, &updates, None) {
+        Ok(()) => {
+            report.corrected = true;
+            suspects.clear();
+        }
+        Err(err) => report.error = Some(format!("failed to route drifted clients back: {}", err)),
+    }
+    report
+}
+
+fn start_ipc_server(spec: &SocketSpec) -> io::Result<()> {
+    let path = &spec.path;
+    // An owner-only socket must not be one another user left in /tmp for us
+    if spec.access == SocketAccess::Owner {
+        socket::check_socket_owner(path)?;
+    }
+    if let Err(err) = fs::remove_file(path) {
+        if err.kind() != io::ErrorKind::NotFound {
+            eprintln!(
+                "[prismd] Warning: failed to remove existing socket {}: {}",
+                path.display(),
+                err
+            );
+        }
+    }
+
+    let listener = UnixListener::bind(path)?;
+    if let Err(err) = fs::set_permissions(path, fs::Permissions::from_mode(spec.access.mode())) {
+        eprintln!(
+            "[prismd] Warning: failed to set permissions on {}: {}",
+            path.display(),
+            err
+        );
+    }
+
+    thread::Builder::new()
+        .name("prismd-ipc".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => handle_ipc_connection(stream),
+                    Err(err) => eprintln!("[prismd] IPC accept error: {}", err),
+                }
+            }
+        })?;
+
+    Ok(())
+}
+
+fn handle_ipc_connection(stream: UnixStream) {
+    let mut reader = BufReader::new(match stream.try_clone() {
+        Ok(cloned) => cloned,
+        Err(err) => {
+            eprintln!("[prismd] Failed to clone IPC stream: {}", err);
+            return;
+        }
+    });
+
+    let mut line = String::new();
+    match reader.read_line(&mut line) {
+        Ok(0) => return,
+        Ok(_) => {}
+        Err(err) => {
+            eprintln!("[prismd] Failed to read IPC command: {}", err);
+            return;
+        }
+    }
+
+    // An optional hello line before the command negotiates response compression
+    let mut encoding = None;
+    if let Some(accepted) = socket::parse_hello(line.trim()) {
+        encoding = Some(
+            accepted
+                .first()
+                .copied()
+                .unwrap_or(socket::Encoding::Identity),
+        );
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) => return,
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("[prismd] Failed to read IPC command: {}", err);
+                return;
+            }
+        }
+    }
+
+    let response = handle_ipc_command(line.trim());
+    let bytes = match encoding {
+        Some(encoding) => match socket::frame_response(encoding, response.as_bytes()) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                eprintln!("[prismd] Failed to encode IPC response: {}", err);
+                return;
+            }
+        },
+        None => response.into_bytes(),
+    };
+
+    if let Err(err) = write_all_and_flush(stream, &bytes) {
+        eprintln!("[prismd] Failed to write IPC response: {}", err);
+    }
+}
+
+fn write_all_and_flush(mut stream: UnixStream, bytes: &[u8]) -> io::Result<()> {
+    stream.write_all(bytes)?;
+    stream.flush()
+}
+
+fn handle_ipc_command(raw: &str) -> String {
+    if raw.is_empty() {
+        return json_error("empty command".to_string());
+    }
+
+    let mut fields: serde_json::Value = match serde_json::from_str(raw) {
+        Ok(fields) => fields,
+        Err(err) => return json_error(format!("invalid request: {}", err)),
+    };
+    let key = match fields
+        .as_object_mut()
+        .and_then(|fields| fields.remove(IDEMPOTENCY_KEY_FIELD))
+    {
+        None => None,
+        Some(serde_json::Value::String(key)) if !key.is_empty() => Some(key),
+        Some(_) => {
+            return json_error(format!(
+                "{} must be a non-empty string",
+                IDEMPOTENCY_KEY_FIELD
+            ))
+        }
+    };
+    let request: CommandRequest = match serde_json::from_value(fields.clone()) {
+        Ok(req) => req,
+        Err(err) => return json_error(format!("invalid request: {}", err)),
+    };
+    let Some(key) = key else {
+        return run_command(request);
+    };
+
+    let ttl = Duration::from_secs(IDEMPOTENCY_TTL_SECS);
+    if let Ok(mut responses) = IDEMPOTENT_RESPONSES.lock() {
+        responses.retain(|_, seen| seen.at.elapsed() < ttl);
+        let seen = match responses.get(&key) {
+            Some(seen) if seen.request != fields => {
+                return json_error(format!(
+                    "idempotency key '{}' was already used for a different request",
+                    key
+                ));
+            }
+            Some(_) => true,
+            None => false,
+        };
+        if seen {
+            let (responses, _) = IDEMPOTENT_DONE
+                .wait_timeout_while(responses, IDEMPOTENT_WAIT, |responses| {
+                    responses
+                        .get(&key)
+                        .is_some_and(|seen| seen.response.is_none())
+                })
+                .unwrap_or_else(|e| e.into_inner());
+            return match responses.get(&key).and_then(|seen| seen.response.clone()) {
+                Some(response) => response,
+                None => json_error(format!(
+                    "request with idempotency key '{}' is still in progress",
+                    key
+                )),
+            };
+        }
+        responses.insert(
+            key.clone(),
+            IdempotentResponse {
+                request: fields.clone(),
+                response: None,
+                at: Instant::now(),
+            },
+        );
+    }
+    let response = run_command(request);
+    if let Ok(mut responses) = IDEMPOTENT_RESPONSES.lock() {
+        responses.insert(
+            key,
+            IdempotentResponse {
+                request: fields,
+                response: Some(response.clone()),
+                at: Instant::now(),
+            },
+        );
+    }
+    IDEMPOTENT_DONE.notify_all();
+    response
+}
+
+fn run_command(request: CommandRequest) -> String {
+    // Every command but the health probe needs the device
+    let device_id = match (&request, ensure_device()) {
+        (_, Ok(device_id)) => device_id,
+        (
+            CommandRequest::Health
+            | CommandRequest::Status
+            | CommandRequest::DriverInfo
+            | CommandRequest::Presets
+            | CommandRequest::Rules { .. }
+            | CommandRequest::Complete { .. }
+            | CommandRequest::Classify { .. }
+            | CommandRequest::Classifications
+            | CommandRequest::Bridges
+            | CommandRequest::BridgeRemove { .. },
+            Err(_),
+        ) => kAudioObjectUnknown,
+        (_, Err(err)) => return json_error(err),
+    };
+
+    match request {
+        CommandRequest::Help => {
+            json_error("help is provided by the CLI; run 'prism --help' locally".to_string())
+        }
+        CommandRequest::Health => json_success_with_data(health_payload()),
+        CommandRequest::Status => json_success_with_data(status_payload()),
+        CommandRequest::DriverInfo => match driver_info_payload() {
+            Ok(payload) => json_success_with_data(payload),
+            Err(err) => json_error(format!("failed to read driver info: {}", err)),
+        },
+        CommandRequest::Clients => match clients_payload(device_id, false) {
+            Ok(payload) => json_success_with_data(payload),
+            Err(err) => json_error(format!("failed to fetch clients: {}", err)),
+        },
+        CommandRequest::List => match build_custom_properties_payload(device_id) {
+            Ok(payload) => json_success_with_data(payload),
+            Err(err) => json_error(format!("failed to read custom properties: {}", err)),
+        },
+        CommandRequest::Set {
+            pid,
+            offset,
+            gain_db,
+            pan,
+            exclude,
+            owner,
+        } => {
+            if pid != -1 && !exclude.is_empty() {
+                return json_error("exclusions only apply to a broadcast (pid -1)".to_string());
+            }
+            // pid -1 is a broadcast to every client; pair policies do not apply
+            let mut note = None;
+            if pid > 0 {
+                let clients = match build_clients_payload(device_id) {
+                    Ok(clients) => clients,
+                    Err(err) => return json_error(format!("failed to fetch clients: {}", err)),
+                };
+                let incoming: HashSet<i32> = clients
+                    .iter()
+                    .filter(|client| client.pid == pid)
+                    .map(app_key)
+                    .chain([pid])
+                    .collect();
+                note = match resolve_route_conflict(device_id, &clients, offset, &incoming) {
+                    Ok(note) => note,
+                    Err(err) => return json_error(err),
+                };
+            }
+
+            // prismd's own clients (bridges) never follow a broadcast
+            let mut exclude_pids = vec![process::id() as i32];
+            let mut exclude_bundle_ids = Vec::new();
+            for entry in exclude {
+                match entry.parse::<i32>() {
+                    Ok(pid) => exclude_pids.push(pid),
+                    Err(_) => exclude_bundle_ids.push(entry),
+                }
+            }
+            let gain = gain_db.map(db_to_linear);
+            let sent = if pid == -1 {
+                send_rout_broadcast(
+                    device_id,
+                    offset,
+                    gain,
+                    pan,
+                    &exclude_pids,
+                    &exclude_bundle_ids,
+                    owner.as_deref(),
+                )
+            } else if owner.is_some() {
+                // Only the plist form can name an owner
+                let update = RoutUpdate {
+                    pid,
+                    channel_offset: offset,
+                    gain,
+                    pan,
+                    width: None,
+                };
+                send_rout_batch(device_id, &[update], owner.as_deref())
+            } else {
+                send_rout_update(device_id, pid, offset, gain, pan, None)
+            };
+            if sent.is_ok() && pid > 0 {
+                remember_route([pid], offset, "set");
+            } else if sent.is_ok() && pid == -1 {
+                // A broadcast replaces every route prismd was keeping but the excluded ones
+                let mut kept: HashSet<i32> = exclude_pids.into_iter().collect();
+                // The driver matches the bundles as it did for the broadcast
+                for bundle_id in &exclude_bundle_ids {
+                    if let Ok(clients) =
+                        fetch_client_list_matching(device_id, None, Some(bundle_id))
+                    {
+                        kept.extend(clients.iter().map(|entry| entry.pid));
+                    }
+                }
+                let mut intents = ROUTE_INTENTS.lock().expect("route intent mutex poisoned");
+                intents.retain(|pid, _| kept.contains(pid));
+                save_routes(&intents);
+            }
+            match sent {
+                Ok(()) => json_success_with_message_and_data(
+                    match note {
+                        Some(note) => format!("routing update sent; {}", note),
+                        None => "routing update sent".to_string(),
+                    },
+                    RoutingUpdateAck {
+                        pid,
+                        channel_offset: offset,
+                        gain_db,
+                        pan,
+                        width: None,
+                        mono: None,
+                        swap: None,
+                        mirror_offset: None,
+                    },
+                ),
+                Err(err) => json_error(format!("failed to send routing update: {}", err)),
+            }
+        }
+        CommandRequest::Unset { pid } => match send_unroute(device_id, pid) {
+            Ok(()) => {
+                // Unassigned clients have no route to hold them to
+                let mut intents = ROUTE_INTENTS.lock().expect("route intent mutex poisoned");
+                if pid == -1 {
+                    intents.clear();
+                } else {
+                    intents.remove(&pid);
+                }
+                save_routes(&intents);
+                drop(intents);
+                json_success_with_message_and_data(
+                    "client unrouted".to_string(),
+                    RoutingUpdateAck {
+                        pid,
+                        channel_offset: 0,
+                        gain_db: None,
+                        pan: None,
+                        width: None,
+                        mono: None,
+                        swap: None,
+                        mirror_offset: None,
+                    },
+                )
+            }
+            Err(err) => json_error(format!("failed to unroute pid {}: {}", pid, err)),
+        },
+        CommandRequest::Apps => match clients_payload(device_id, false) {
+            Ok(payload) => json_success_with_data(payload),
+            Err(err) => json_error(format!("failed to fetch apps: {}", err)),
+        },
+        CommandRequest::Stats => match fetch_io_stats(device_id) {
+            Ok(stats) => json_success_with_data(IoStatsPayload {
+                cycles_served: stats.cycles_served,
+                output_cycles: stats.output_cycles,
+                wrap_arounds: stats.wrap_arounds,
+                stale_reads: stats.stale_reads,
+                overloads: stats.overloads,
+                watchdog_clears: stats.watchdog_clears,
+                late_cycles: stats.late_cycles,
+                missed_writes: stats.missed_writes,
+                frames_written: stats.frames_written,
+                slot_table_size: stats.slot_table_size,
+                slots_occupied: stats.slots_occupied,
+                slot_collisions: stats.slot_collisions,
+                slot_table_growths: stats.slot_table_growths,
+            }),
+            Err(err) => json_error(format!("failed to read IO statistics: {}", err)),
+        },
+        CommandRequest::Diag => match fetch_io_stats(device_id) {
+            Ok(stats) => {
+                let capture_readers: Vec<CaptureReaderPayload> = stats
+                    .readers
+                    .into_iter()
+                    .map(|reader| CaptureReaderPayload {
+                        client_id: reader.client_id,
+                        pid: reader.pid,
+                        process_name: procinfo::process_name(reader.pid),
+                        last_read_sample_time: reader.last_read_time,
+                        reads: reader.reads,
+                        active: reader.active,
+                    })
+                    .collect();
+                json_success_with_data(DiagPayload {
+                    active_capture_readers: capture_readers.iter().filter(|r| r.active).count()
+                        as u32,
+                    capture_readers,
+                })
+            }
+            Err(err) => json_error(format!("failed to read diagnostics: {}", err)),
+        },
+        CommandRequest::Meters => match build_meters_payload(device_id) {
+            Ok(payload) => json_success_with_data(payload),
+            Err(err) => json_error(format!("failed to read meters: {}", err)),
+        },
+        CommandRequest::MeterHistory { seconds } => {
+            json_success_with_data(meter_history_payload(seconds))
+        }
+        CommandRequest::CaptureAllow { offset, bundle_id } => {
+            let bundle = bundle_id.unwrap_or_default();
+            match send_capture_rule(device_id, offset, &bundle) {
+                Ok(()) => {
+                    let message = if bundle.is_empty() {
+                        "capture restriction cleared".to_string()
+                    } else {
+                        "capture restriction set".to_string()
+                    };
+                    json_success_with_message_and_data(
+                        message,
+                        CaptureRulePayload {
+                            channel_offset: offset,
+                            bundle_id: bundle,
+                        },
+                    )
+                }
+                Err(err) => json_error(format!("failed to update capture ACL: {}", err)),
+            }
+        }
+        CommandRequest::PairPolicy { offset, policy } => {
+            if offset % 2 != 0 || offset < 2 {
+                return json_error(
+                    "pair policies apply to pairs from channels 3-4 upwards".to_string(),
+                );
+            }
+            let mut policies = PAIR_POLICIES.lock().expect("pair policy mutex poisoned");
+            if policy == ConflictPolicy::default() {
+                policies.remove(&offset);
+            } else {
+                policies.insert(offset, policy);
+            }
+            json_success_with_message_and_data(
+                format!("pair policy set to {}", policy.name()),
+                PairPolicyPayload {
+                    channel_offset: offset,
+                    policy,
+                },
+            )
+        }
+        CommandRequest::PairPolicies => {
+            let policies = PAIR_POLICIES.lock().expect("pair policy mutex poisoned");
+            json_success_with_data(
+                policies
+                    .iter()
+                    .map(|(&channel_offset, &policy)| PairPolicyPayload {
+                        channel_offset,
+                        policy,
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        }
+        CommandRequest::BusLabel { offset, label } => {
+            let channels = match input_channel_count(device_id) {
+                Ok(channels) => channels,
+                Err(err) => return json_error(format!("failed to read channel count: {}", err)),
+            };
+            if offset % 2 != 0 || offset + 1 >= channels {
+                return json_error(format!(
+                    "channel offset {} is not a pair between 1-2 and {}-{}",
+                    offset,
+                    channels - 1,
+                    channels
+                ));
+            }
+            let label = label
+                .as_deref()
+                .map(str::trim)
+                .filter(|label| !label.is_empty());
+            if label.is_some_and(|label| label.chars().count() > MAX_BUS_LABEL_CHARS) {
+                return json_error(format!(
+                    "bus names are at most {} characters",
+                    MAX_BUS_LABEL_CHARS
+                ));
+            }
+            if let Err(err) = send_bus_label(device_id, offset, label) {
+                return json_error(format!("failed to name {}: {}", pair_label(offset), err));
+            }
+            let mut settings = SETTINGS.lock().expect("settings mutex poisoned");
+            let message = match label {
+                Some(label) => {
+                    settings.bus_labels.insert(offset, label.to_string());
+                    format!("{} named '{}'", pair_label(offset), label)
+                }
+                None => {
+                    settings.bus_labels.remove(&offset);
+                    format!("{} unnamed", pair_label(offset))
+                }
+            };
+            if let Some(path) = SETTINGS_FILE.get() {
+                if let Err(err) = save_settings(&settings, path) {
+                    return json_error(format!("{} but not saved: {}", message, err));
+                }
+            }
+            drop(settings);
+            match fetch_bus_labels(device_id) {
+                Ok(labels) => json_success_with_message_and_data(
+                    message,
+                    labels
+                        .into_iter()
+                        .map(|(channel_offset, label)| BusLabelPayload {
+                            channel_offset,
+                            label,
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+                Err(err) => json_error(format!("failed to read bus names: {}", err)),
+            }
+        }
+        CommandRequest::Reserve {
+            offset,
+            owner,
+            release,
+        } => {
+            if offset % 2 != 0 || offset < 2 {
+                return json_error(
+                    "reservations apply to pairs from channels 3-4 upwards".to_string(),
+                );
+            }
+            if owner.trim().is_empty() {
+                return json_error("a reservation needs an owner name".to_string());
+            }
+            if let Err(err) = send_reservation(device_id, offset, &owner, release) {
+                // Name the holder rather than the status when someone else has the pair
+                let holder = fetch_reservations(device_id)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .find(|reservation| {
+                        reservation.channel_offset == offset && reservation.owner != owner
+                    });
+                return match holder {
+                    Some(holder) => json_error(format!(
+                        "channels {}-{} are reserved by '{}'",
+                        offset + 1,
+                        offset + 2,
+                        holder.owner
+                    )),
+                    None => json_error(format!("failed to update reservation: {}", err)),
+                };
+            }
+            let message = format!(
+                "channels {}-{} {} '{}'",
+                offset + 1,
+                offset + 2,
+                if release {
+                    "released by"
+                } else {
+                    "reserved for"
+                },
+                owner
+            );
+            match reservations_payload(device_id) {
+                Ok(payload) => json_success_with_message_and_data(message, payload),
+                Err(err) => json_error(format!("failed to read reservations: {}", err)),
+            }
+        }
+        CommandRequest::GapPolicy {
+            offset,
+            mode,
+            max_hold_ms,
+        } => {
+            if offset % 2 != 0 || offset < 2 {
+                return json_error(
+                    "gap policies apply to pairs from channels 3-4 upwards".to_string(),
+                );
+            }
+            let max_hold_ms = max_hold_ms.unwrap_or(DEFAULT_GAP_HOLD_MS);
+            if !(1..=MAX_GAP_HOLD_MS).contains(&max_hold_ms) {
+                return json_error(format!(
+                    "the hold must be 1 to {} ms, got {}",
+                    MAX_GAP_HOLD_MS, max_hold_ms
+                ));
+            }
+            if let Err(err) = send_gap_policy(device_id, offset, mode.name(), max_hold_ms) {
+                return json_error(format!("failed to set gap policy: {}", err));
+            }
+            let message = match mode {
+                GapMode::ZeroFill => {
+                    format!("channels {}-{} zero-fill gaps", offset + 1, offset + 2)
+                }
+                GapMode::HoldLast => format!(
+                    "channels {}-{} hold the last audio over gaps of up to {} ms",
+                    offset + 1,
+                    offset + 2,
+                    max_hold_ms
+                ),
+            };
+            match gap_policies_payload(device_id) {
+                Ok(payload) => json_success_with_message_and_data(message, payload),
+                Err(err) => json_error(format!("failed to read gap policies: {}", err)),
+            }
+        }
+        CommandRequest::Buses => match buses_payload(device_id) {
+            Ok(payload) => json_success_with_data(payload),
+            Err(err) => json_error(format!("failed to read bus occupancy: {}", err)),
+        },
+        CommandRequest::GapPolicies => match gap_policies_payload(device_id) {
+            Ok(payload) => json_success_with_data(payload),
+            Err(err) => json_error(format!("failed to read gap policies: {}", err)),
+        },
+        CommandRequest::Reservations => match reservations_payload(device_id) {
+            Ok(payload) => json_success_with_data(payload),
+            Err(err) => json_error(format!("failed to read reservations: {}", err)),
+        },
+        CommandRequest::CaptureRules => match fetch_capture_rules(device_id) {
+            Ok(rules) => json_success_with_data(
+                rules
+                    .into_iter()
+                    .map(|rule| CaptureRulePayload {
+                        channel_offset: rule.channel_offset,
+                        bundle_id: rule.bundle_id,
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            Err(err) => json_error(format!("failed to read capture ACL: {}", err)),
+        },
+        CommandRequest::CaptureMask { pid, offsets } => {
+            if pid <= 0 {
+                return json_error(format!("invalid PID {}", pid));
+            }
+            let channels = match input_channel_count(device_id) {
+                Ok(channels) => channels,
+                Err(err) => return json_error(format!("failed to read channel count: {}", err)),
+            };
+            let mask = match &offsets {
+                None => None,
+                Some(offsets) => {
+                    if let Some(offset) = offsets
+                        .iter()
+                        .find(|&&offset| offset % 2 != 0 || offset + 1 >= channels)
+                    {
+                        return json_error(format!(
+                            "channel offset {} is not a pair between 1-2 and {}-{}",
+                            offset,
+                            channels - 1,
+                            channels
+                        ));
+                    }
+                    Some(
+                        offsets
+                            .iter()
+                            .fold(0u64, |mask, &offset| mask | (0b11 << offset)),
+                    )
+                }
+            };
+            match send_capture_mask(device_id, pid, mask) {
+                Ok(()) => json_success_with_message_and_data(
+                    if mask.is_some() {
+                        "capture mask set".to_string()
+                    } else {
+                        "capture mask cleared".to_string()
+                    },
+                    CaptureMaskPayload {
+                        pid,
+                        offsets: mask_offsets(mask.unwrap_or(u64::MAX), channels),
+                    },
+                ),
+                Err(err) => json_error(format!("failed to update capture mask: {}", err)),
+            }
+        }
+        CommandRequest::CaptureExclusions => match fetch_capture_exclusions(device_id) {
+            Ok(bundle_ids) => json_success_with_data(bundle_ids),
+            Err(err) => json_error(format!("failed to read capture exclusions: {}", err)),
+        },
+        CommandRequest::CaptureMasks => {
+            let channels = match input_channel_count(device_id) {
+                Ok(channels) => channels,
+                Err(err) => return json_error(format!("failed to read channel count: {}", err)),
+            };
+            match fetch_capture_masks(device_id) {
+                Ok(masks) => json_success_with_data(
+                    masks
+                        .into_iter()
+                        .map(|(pid, mask)| CaptureMaskPayload {
+                            pid,
+                            offsets: mask_offsets(mask, channels),
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+                Err(err) => json_error(format!("failed to read capture masks: {}", err)),
+            }
+        }
+        CommandRequest::SetApp {
+            app_name,
+            offset,
+            gain_db,
+            pan,
+            owner,
+        } => {
+            // Find groups by the display name used by the `apps` command
+            // (responsible_name if present, otherwise process_name) or by the
+            // client's bundle ID. Match must be exact. `type:<content type>` picks
+            // every app of that type instead.
+            let content_type = match app_name.strip_prefix(CONTENT_TYPE_PREFIX) {
+                Some(name) => match ContentType::parse(name) {
+                    Some(content_type) => Some(content_type),
+                    None => {
+                        return json_error(format!(
+                            "unknown content type '{}' (expected music, voice, game or system)",
+                            name
+                        ))
+                    }
+                },
+                None => None,
+            };
+            match build_clients_payload(device_id) {
+                Ok(clients) => {
+                    // Collect target responsible_pids (groups) and individual pids where responsible_pid is None
+                    let mut target_responsible_pids: HashSet<i32> = HashSet::new();
+                    let mut direct_pids: Vec<i32> = Vec::new();
+                    for client in &clients {
+                        let display = client
+                            .responsible_name
+                            .as_ref()
+                            .or(client.process_name.as_ref())
+                            .map(|s| s.as_str());
+                        let matched = match content_type {
+                            Some(content_type) => client.content_type == Some(content_type),
+                            None => {
+                                display == Some(app_name.as_str())
+                                    || client.bundle_id.as_deref() == Some(app_name.as_str())
+                            }
+                        };
+                        if matched {
+                            if let Some(rpid) = client.responsible_pid {
+                                target_responsible_pids.insert(rpid);
+                            } else {
+                                direct_pids.push(client.pid);
+                            }
+                        }
+                    }
+
+                    if target_responsible_pids.is_empty() && direct_pids.is_empty() {
+                        return json_error(format!("no clients found for app '{}'.", app_name));
+                    }
+
+                    let incoming: HashSet<i32> = target_responsible_pids
+                        .iter()
+                        .chain(direct_pids.iter())
+                        .copied()
+                        .collect();
+                    let note = match resolve_route_conflict(device_id, &clients, offset, &incoming)
+                    {
+                        Ok(note) => note,
+                        Err(err) => return json_error(err),
+                    };
+
+                    let mut results: Vec<RoutingUpdateAck> = Vec::new();
+                    let mut errors: Vec<String> = Vec::new();
+
+                    // A bundle ID target is routed by bundle in the driver first, which
+                    // also catches clients of the app that connected after the list
+                    // above was read. Helpers with other bundle IDs still go by pid.
+                    let bundle_routed = content_type.is_none()
+                        && clients
+                            .iter()
+                            .any(|client| client.bundle_id.as_deref() == Some(app_name.as_str()))
+                        && send_bundle_route(
+                            device_id,
+                            &app_name,
+                            offset,
+                            gain_db.map(db_to_linear),
+                            pan,
+                            owner.as_deref(),
+                        )
+                        .is_ok();
+
+                    let ack = |pid: i32| RoutingUpdateAck {
+                        pid,
+                        channel_offset: offset,
+                        gain_db,
+                        pan,
+                        width: None,
+                        mono: None,
+                        swap: None,
+                        mirror_offset: None,
+                    };
+                    let mut batch: Vec<RoutUpdate> = Vec::new();
+                    for client in clients {
+                        let should_update = if let Some(rpid) = client.responsible_pid {
+                            target_responsible_pids.contains(&rpid)
+                        } else {
+                            direct_pids.contains(&client.pid)
+                        };
+                        if !should_update {
+                            continue;
+                        }
+
+                        if bundle_routed
+                            && client
+                                .bundle_id
+                                .as_deref()
+                                .is_some_and(|id| procinfo::bundle_matches(id, &app_name))
+                        {
+                            results.push(ack(client.pid));
+                        } else {
+                            batch.push(RoutUpdate {
+                                pid: client.pid,
+                                channel_offset: offset,
+                                gain: gain_db.map(db_to_linear),
+                                pan,
+                                width: None,
+                            });
+                        }
+                    }
+                    // Every helper in one 'rout' write rather than one per pid
+                    if !batch.is_empty() {
+                        match send_rout_batch(device_id, &batch, owner.as_deref()) {
+                            Ok(()) => results.extend(batch.iter().map(|update| ack(update.pid))),
+                            Err(err) => {
+                                let pids: Vec<String> =
+                                    batch.iter().map(|update| update.pid.to_string()).collect();
+                                errors.push(format!(
+                                    "failed to set pids {}: {}",
+                                    pids.join(", "),
+                                    err
+                                ));
+                            }
+                        }
+                    }
+                    remember_route(results.iter().map(|ack| ack.pid), offset, "set-app");
+
+                    if results.is_empty() {
+                        if errors.is_empty() {
+                            return json_error(format!("no clients found for app '{}'.", app_name));
+                        } else {
+                            return json_error(format!(
+                                "all matching clients failed for app '{}': {}",
+                                app_name,
+                                errors.join("; ")
+                            ));
+                        }
+                    }
+
+                    if !errors.is_empty() {
+                        let mut msg = format!("partial failures: {}", errors.join("; "));
+                        if let Some(note) = note {
+                            msg = format!("{}; {}", note, msg);
+                        }
+                        return json_success_with_message_and_data(msg, results);
+                    }
+
+                    match note {
+                        Some(note) => json_success_with_message_and_data(note, results),
+                        None => json_success_with_data(results),
+                    }
+                }
+                Err(err) => json_error(format!("failed to fetch clients: {}", err)),
+            }
+        }
+        CommandRequest::Agc {
+            app_name,
+            target_lufs,
+            range_db,
+        } => {
+            let mut agc = AGC.lock().expect("agc mutex poisoned");
+            let Some(target_lufs) = target_lufs else {
+                if agc.remove(&app_name).is_none() {
+                    return json_error(format!("AGC is not enabled for '{}'", app_name));
+                }
+                drop(agc);
+                // Drop the AGC stage; the app's own gain was never touched
+                if let Ok(clients) = build_clients_payload(device_id) {
+                    for client in clients_of_app(&clients, &app_name) {
+                        let _ = send_client_agc(device_id, client.pid, 1.0);
+                    }
+                }
+                return json_success_with_message_and_data(
+                    format!("AGC disabled for '{}'", app_name),
+                    Vec::<AgcPayload>::new(),
+                );
+            };
+            if !target_lufs.is_finite() || !(-70.0..=0.0).contains(&target_lufs) {
+                return json_error("target loudness must be between -70 and 0 LUFS".to_string());
+            }
+
+            let range_db = range_db.unwrap_or(AGC_DEFAULT_RANGE_DB);
+            let controller = agc
+                .entry(app_name.clone())
+                .and_modify(|ctl| {
+                    ctl.target_lufs = target_lufs;
+                    ctl.range_db = range_db;
+                })
+                .or_insert_with(|| AgcController::new(target_lufs, range_db));
+            let payload = agc_payload(&app_name, controller);
+            json_success_with_message_and_data(
+                format!("AGC enabled for '{}'", app_name),
+                vec![payload],
+            )
+        }
+        CommandRequest::AgcStatus => {
+            let agc = AGC.lock().expect("agc mutex poisoned");
+            json_success_with_data(
+                agc.iter()
+                    .map(|(app, ctl)| agc_payload(app, ctl))
+                    .collect::<Vec<_>>(),
+            )
+        }
+        CommandRequest::Trim { channels, gain_db } => {
+            let mut results = Vec::new();
+            for channel in channels {
+                if let Err(err) = set_channel_trim_db(device_id, channel, gain_db) {
+                    return json_error(format!("failed to set trim: {}", err));
+                }
+                // Report what the driver actually applied (it clamps to its range)
+                let applied = get_channel_trim_db(device_id, channel).unwrap_or(gain_db);
+                results.push(ChannelTrimPayload {
+                    channel,
+                    gain_db: applied,
+                });
+            }
+            json_success_with_data(results)
+        }
+        CommandRequest::Trims => {
+            let channels = match input_channel_count(device_id) {
+                Ok(channels) => channels,
+                Err(err) => return json_error(format!("failed to read trims: {}", err)),
+            };
+            let mut results = Vec::new();
+            for channel in 1..=channels {
+                match get_channel_trim_db(device_id, channel) {
+                    Ok(db) if db != 0.0 => results.push(ChannelTrimPayload {
+                        channel,
+                        gain_db: db,
+                    }),
+                    Ok(_) => {}
+                    Err(err) => return json_error(format!("failed to read trims: {}", err)),
+                }
+            }
+            json_success_with_data(results)
+        }
+        CommandRequest::Width { app_name, width } => {
+            if !(0.0..=2.0).contains(&width) {
+                return json_error(format!("width {} is outside 0.0..=2.0", width));
+            }
+            let clients = match build_clients_payload(device_id) {
+                Ok(clients) => clients,
+                Err(err) => return json_error(format!("failed to fetch clients: {}", err)),
+            };
+            let targets = clients_of_app(&clients, &app_name);
+            if targets.is_empty() {
+                return json_error(format!("no clients found for app '{}'.", app_name));
+            }
+
+            let mut results = Vec::new();
+            let mut errors = Vec::new();
+            for client in targets {
+                match send_rout_update(
+                    device_id,
+                    client.pid,
+                    client.channel_offset,
+                    None,
+                    None,
+                    Some(width),
+                ) {
+                    Ok(()) => results.push(RoutingUpdateAck {
+                        pid: client.pid,
+                        channel_offset: client.channel_offset,
+                        gain_db: None,
+                        pan: None,
+                        width: Some(width),
+                        mono: None,
+                        swap: None,
+                        mirror_offset: None,
+                    }),
+                    Err(err) => errors.push(format!("failed to set pid {}: {}", client.pid, err)),
+                }
+            }
+            if results.is_empty() {
+                return json_error(format!(
+                    "all matching clients failed for app '{}': {}",
+                    app_name,
+                    errors.join("; ")
+                ));
+            }
+            if !errors.is_empty() {
+                let msg = format!("partial failures: {}", errors.join("; "));
+                return json_success_with_message_and_data(msg, results);
+            }
+            json_success_with_data(results)
+        }
+        CommandRequest::Balance {
+            app_name,
+            balance,
+            swap,
+        } => {
+            if let Some(balance) = balance.filter(|balance| !(-1.0..=1.0).contains(balance)) {
+                return json_error(format!("balance {} is outside -1.0..=1.0", balance));
+            }
+            if balance.is_none() && swap.is_none() {
+                return json_error("nothing to change: give a balance, a swap or both".into());
+            }
+            let clients = match build_clients_payload(device_id) {
+                Ok(clients) => clients,
+                Err(err) => return json_error(format!("failed to fetch clients: {}", err)),
+            };
+            let targets = clients_of_app(&clients, &app_name);
+            if targets.is_empty() {
+                return json_error(format!("no clients found for app '{}'.", app_name));
+            }
+
+            let mut results = Vec::new();
+            let mut errors = Vec::new();
+            for client in targets {
+                match send_balance(device_id, client.pid, client.channel_offset, balance, swap) {
+                    Ok(()) => results.push(RoutingUpdateAck {
+                        pid: client.pid,
+                        channel_offset: client.channel_offset,
+                        gain_db: None,
+                        pan: balance,
+                        width: None,
+                        mono: None,
+                        swap,
+                        mirror_offset: None,
+                    }),
+                    Err(err) => errors.push(format!("failed to set pid {}: {}", client.pid, err)),
+                }
+            }
+            if results.is_empty() {
+                return json_error(format!(
+                    "all matching clients failed for app '{}': {}",
+                    app_name,
+                    errors.join("; ")
+                ));
+            }
+            if !errors.is_empty() {
+                let msg = format!("partial failures: {}", errors.join("; "));
+                return json_success_with_message_and_data(msg, results);
+            }
+            json_success_with_data(results)
+        }
+        CommandRequest::Mono { app_name, fold } => {
+            let clients = match build_clients_payload(device_id) {
+                Ok(clients) => clients,
+                Err(err) => return json_error(format!("failed to fetch clients: {}", err)),
+            };
+            let targets = clients_of_app(&clients, &app_name);
+            if targets.is_empty() {
+                return json_error(format!("no clients found for app '{}'.", app_name));
+            }
+
+            let mut results = Vec::new();
+            let mut errors = Vec::new();
+            for client in targets {
+                match send_mono_fold(device_id, client.pid, client.channel_offset, fold.name()) {
+                    Ok(()) => results.push(RoutingUpdateAck {
+                        pid: client.pid,
+                        channel_offset: client.channel_offset,
+                        gain_db: None,
+                        pan: None,
+                        width: None,
+                        mono: Some(fold),
+                        swap: None,
+                        mirror_offset: None,
+                    }),
+                    Err(err) => errors.push(format!("failed to set pid {}: {}", client.pid, err)),
+                }
+            }
+            if results.is_empty() {
+                return json_error(format!(
+                    "all matching clients failed for app '{}': {}",
+                    app_name,
+                    errors.join("; ")
+                ));
+            }
+            if !errors.is_empty() {
+                let msg = format!("partial failures: {}", errors.join("; "));
+                return json_success_with_message_and_data(msg, results);
+            }
+            json_success_with_data(results)
+        }
+        CommandRequest::Mirror { app_name, offset } => {
+            if let Some(offset) = offset {
+                let channels = match input_channel_count(device_id) {
+                    Ok(channels) => channels,
+                    Err(err) => {
+                        return json_error(format!("failed to read channel count: {}", err))
+                    }
+                };
+                if offset < 2 || offset % 2 != 0 || offset + 1 >= channels {
+                    return json_error(format!(
+                        "mirror offset {} is not a bus pair (even, 2..{})",
+                        offset,
+                        channels.saturating_sub(2)
+                    ));
+                }
+            }
+            let clients = match build_clients_payload(device_id) {
+                Ok(clients) => clients,
+                Err(err) => return json_error(format!("failed to fetch clients: {}", err)),
+            };
+            let targets = clients_of_app(&clients, &app_name);
+            if targets.is_empty() {
+                return json_error(format!("no clients found for app '{}'.", app_name));
+            }
+
+            let mirror = offset.unwrap_or(0);
+            let mut results = Vec::new();
+            let mut errors = Vec::new();
+            for client in targets {
+                if mirror != 0 && mirror == client.channel_offset {
+                    errors.push(format!(
+                        "pid {} already plays on channel_offset {}",
+                        client.pid, mirror
+                    ));
+                    continue;
+                }
+                match send_mirror(device_id, client.pid, client.channel_offset, mirror) {
+                    Ok(()) => results.push(RoutingUpdateAck {
+                        pid: client.pid,
+                        channel_offset: client.channel_offset,
+                        gain_db: None,
+                        pan: None,
+                        width: None,
+                        mono: None,
+                        swap: None,
+                        mirror_offset: Some(mirror),
+                    }),
+                    Err(err) => errors.push(format!("failed to set pid {}: {}", client.pid, err)),
+                }
+            }
+            if results.is_empty() {
+                return json_error(format!(
+                    "all matching clients failed for app '{}': {}",
+                    app_name,
+                    errors.join("; ")
+                ));
+            }
+            if !errors.is_empty() {
+                let msg = format!("partial failures: {}", errors.join("; "));
+                return json_success_with_message_and_data(msg, results);
+            }
+            json_success_with_data(results)
+        }
+        CommandRequest::Matrix { app_name, routes } => {
+            let channels = match input_channel_count(device_id) {
+                Ok(channels) => channels,
+                Err(err) => return json_error(format!("failed to read channel count: {}", err)),
+            };
+            let mut matrix = Vec::with_capacity(routes.len());
+            for route in &routes {
+                if route.source > 1 {
+                    return json_error(format!(
+                        "source {} is neither left (0) nor right (1)",
+                        route.source
+                    ));
+                }
+                if route.channel == 0 || route.channel > channels {
+                    return json_error(format!(
+                        "channel {} is outside 1..={}",
+                        route.channel, channels
+                    ));
+                }
+                matrix.push(MatrixRoute {
+                    source: route.source,
+                    channel: route.channel - 1,
+                    gain: db_to_linear(route.gain_db),
+                });
+            }
+            let clients = match build_clients_payload(device_id) {
+                Ok(clients) => clients,
+                Err(err) => return json_error(format!("failed to fetch clients: {}", err)),
+            };
+            let mut pids: Vec<i32> = clients_of_app(&clients, &app_name)
+                .iter()
+                .map(|client| client.pid)
+                .collect();
+            pids.sort_unstable();
+            pids.dedup();
+            if pids.is_empty() {
+                return json_error(format!("no clients found for app '{}'.", app_name));
+            }
+
+            let mut errors = Vec::new();
+            for &pid in &pids {
+                if let Err(err) = send_matrix(device_id, pid, &matrix) {
+                    errors.push(format!("failed to set pid {}: {}", pid, err));
+                }
+            }
+            if errors.len() == pids.len() {
+                return json_error(format!(
+                    "all matching clients failed for app '{}': {}",
+                    app_name,
+                    errors.join("; ")
+                ));
+            }
+            let message = if !errors.is_empty() {
+                format!("partial failures: {}", errors.join("; "))
+            } else if routes.is_empty() {
+                format!("{} is back on its pair", app_name)
+            } else {
+                format!("{} routed through {} matrix routes", app_name, routes.len())
+            };
+            match build_matrices_payload(device_id, &clients) {
+                Ok(payload) => json_success_with_message_and_data(message, payload),
+                Err(err) => json_error(format!("failed to read matrices: {}", err)),
+            }
+        }
+        CommandRequest::Matrices => {
+            let clients = match build_clients_payload(device_id) {
+                Ok(clients) => clients,
+                Err(err) => return json_error(format!("failed to fetch clients: {}", err)),
+            };
+            match build_matrices_payload(device_id, &clients) {
+                Ok(payload) => json_success_with_data(payload),
+                Err(err) => json_error(format!("failed to read matrices: {}", err)),
+            }
+        }
+        CommandRequest::Monitor { enabled, sends } => {
+            if enabled.is_some() || !sends.is_empty() {
+                let channels = match input_channel_count(device_id) {
+                    Ok(channels) => channels,
+                    Err(err) => {
+                        return json_error(format!("failed to read channel count: {}", err))
+                    }
+                };
+                let mut updates = Vec::with_capacity(sends.len());
+                for send in &sends {
+                    // 1-2 is where the monitor mix lands, so it cannot feed itself
+                    if send.channel_offset < 2
+                        || send.channel_offset % 2 != 0
+                        || send.channel_offset + 1 >= channels
+                    {
+                        return json_error(format!(
+                            "channel offset {} is not a pair between 3-4 and {}-{}",
+                            send.channel_offset,
+                            channels - 1,
+                            channels
+                        ));
+                    }
+                    let gain = send.gain_db.map_or(0.0, db_to_linear);
+                    updates.push((send.channel_offset, gain));
+                }
+                if let Err(err) = send_monitor(device_id, enabled, &updates) {
+                    return json_error(format!("failed to update monitor: {}", err));
+                }
+                for bridge in BRIDGES.lock().expect("bridge mutex poisoned").iter() {
+                    apply_monitor_sends(bridge);
+                }
+            }
+            match fetch_monitor(device_id) {
+                Ok(state) => json_success_with_data(MonitorPayload {
+                    enabled: state.enabled,
+                    sends: state
+                        .sends
+                        .into_iter()
+                        .map(|(channel_offset, gain)| MonitorSendPayload {
+                            channel_offset,
+                            gain_db: Some(linear_to_db(gain).max(SILENCE_DB)),
+                        })
+                        .collect(),
+                }),
+                Err(err) => json_error(format!("failed to read monitor: {}", err)),
+            }
+        }
+        CommandRequest::Solo {
+            clear,
+            solo,
+            unsolo,
+        } => {
+            if clear || !solo.is_empty() || !unsolo.is_empty() {
+                let channels = match input_channel_count(device_id) {
+                    Ok(channels) => channels,
+                    Err(err) => {
+                        return json_error(format!("failed to read channel count: {}", err))
+                    }
+                };
+                if let Some(offset) = solo
+                    .iter()
+                    .chain(&unsolo)
+                    .find(|&&offset| offset % 2 != 0 || offset + 1 >= channels)
+                {
+                    return json_error(format!(
+                        "channel offset {} is not a pair between 1-2 and {}-{}",
+                        offset,
+                        channels - 1,
+                        channels
+                    ));
+                }
+                if let Err(err) = send_solo(device_id, clear, &solo, &unsolo) {
+                    return json_error(format!("failed to update solo: {}", err));
+                }
+            }
+            match fetch_solo(device_id) {
+                Ok(pairs) => json_success_with_data(SoloPayload { pairs }),
+                Err(err) => json_error(format!("failed to read solo: {}", err)),
+            }
+        }
+        CommandRequest::CaptureList {
+            enabled,
+            allow,
+            revoke,
+        } => {
+            let mut allowlist = match fetch_capture_allowlist(device_id) {
+                Ok(allowlist) => allowlist,
+                Err(err) => {
+                    return json_error(format!("failed to read capture allowlist: {}", err))
+                }
+            };
+            if enabled.is_some() || !allow.is_empty() || !revoke.is_empty() {
+                // A number is a PID, anything else a bundle ID
+                for entry in &revoke {
+                    match entry.parse::<i32>() {
+                        Ok(pid) => allowlist.pids.retain(|&listed| listed != pid),
+                        Err(_) => allowlist.bundle_ids.retain(|listed| listed != entry),
+                    }
+                }
+                for entry in &allow {
+                    match entry.parse::<i32>() {
+                        Ok(pid) if pid <= 0 => {
+                            return json_error(format!("invalid PID {}", pid));
+                        }
+                        Ok(pid) if !allowlist.pids.contains(&pid) => allowlist.pids.push(pid),
+                        Ok(_) => {}
+                        Err(_) if entry.trim().is_empty() => {
+                            return json_error("empty bundle ID".to_string());
+                        }
+                        Err(_) if !allowlist.bundle_ids.contains(entry) => {
+                            allowlist.bundle_ids.push(entry.clone())
+                        }
+                        Err(_) => {}
+                    }
+                }
+                // Bridges read the input stream from this process
+                let own_pid = std::process::id() as i32;
+                if !allowlist.pids.contains(&own_pid) {
+                    allowlist.pids.push(own_pid);
+                }
+                if let Some(enabled) = enabled {
+                    allowlist.enabled = enabled;
+                }
+                if let Err(err) = send_capture_allowlist(device_id, &allowlist) {
+                    return json_error(format!("failed to update capture allowlist: {}", err));
+                }
+                allowlist = match fetch_capture_allowlist(device_id) {
+                    Ok(allowlist) => allowlist,
+                    Err(err) => {
+                        return json_error(format!("failed to read capture allowlist: {}", err))
+                    }
+                };
+                let mut settings = SETTINGS.lock().expect("settings mutex poisoned");
+                settings.capture_list = Some(SavedCaptureList {
+                    enabled: allowlist.enabled,
+                    bundle_ids: allowlist.bundle_ids.clone(),
+                    pids: allowlist
+                        .pids
+                        .iter()
+                        .copied()
+                        .filter(|&pid| pid != own_pid)
+                        .collect(),
+                });
+                if let Some(path) = SETTINGS_FILE.get() {
+                    if let Err(err) = save_settings(&settings, path) {
+                        return json_error(format!(
+                            "capture allowlist updated but not saved: {}",
+                            err
+                        ));
+                    }
+                }
+            }
+            json_success_with_data(CaptureListPayload {
+                enabled: allowlist.enabled,
+                bundle_ids: allowlist.bundle_ids,
+                pids: allowlist.pids,
+            })
+        }
+        CommandRequest::Resync => match send_resync(device_id) {
+            Ok(resyncs) => json_success_with_message_and_data(
+                "driver timeline re-anchored and buffers cleared".to_string(),
+                ResyncPayload { resyncs },
+            ),
+            Err(err) => json_error(format!("failed to resync driver: {}", err)),
+        },
+        CommandRequest::Buffers => match build_buffer_regions_payload(device_id) {
+            Ok(payload) => json_success_with_data(payload),
+            Err(err) => json_error(format!("failed to read buffer regions: {}", err)),
+        },
+        CommandRequest::Clear { offset } => {
+            if let Err(err) = send_clear_buffers(device_id, offset) {
+                return json_error(format!("failed to clear buffers: {}", err));
+            }
+            let message = match offset {
+                Some(offset) => format!("cleared the ring of {}-{}ch", offset + 1, offset + 2),
+                None => "cleared every ring".to_string(),
+            };
+            match build_buffer_regions_payload(device_id) {
+                Ok(payload) => json_success_with_message_and_data(message, payload),
+                Err(err) => json_error(format!("failed to read buffer regions: {}", err)),
+            }
+        }
+        CommandRequest::LogLevel { level: None } => match fetch_log_level(device_id) {
+            Ok(level) => json_success_with_data(LogLevelPayload { level }),
+            Err(err) => json_error(format!("failed to read driver log level: {}", err)),
+        },
+        CommandRequest::LogLevel { level: Some(name) } => {
+            let Some(level) = LogLevel::parse(&name) else {
+                return json_error(format!(
+                    "unknown log level '{}' (expected error, info or debug)",
+                    name
+                ));
+            };
+            match send_log_level(device_id, level.name()) {
+                Ok(()) => json_success_with_message_and_data(
+                    format!("driver log level set to {}", level.name()),
+                    LogLevelPayload {
+                        level: level.name().to_string(),
+                    },
+                ),
+                Err(err) => json_error(format!("failed to set driver log level: {}", err)),
+            }
+        }
+        CommandRequest::Latency {
+            input_latency,
+            output_latency,
+            input_safety_offset,
+            output_safety_offset,
+        } => {
+            let updates: Vec<(&str, u32)> = TIMING_KEYS
+                .into_iter()
+                .zip([
+                    input_latency,
+                    output_latency,
+                    input_safety_offset,
+                    output_safety_offset,
+                ])
+                .filter_map(|(key, frames)| Some((key, frames?)))
+                .collect();
+            let message = if updates.is_empty() {
+                None
+            } else if let Err(err) = send_device_timing(device_id, &updates) {
+                return json_error(format!(
+                    "failed to set driver timing (each value is 0 to 16384 frames): {}",
+                    err
+                ));
+            } else {
+                let changed: Vec<String> = updates
+                    .iter()
+                    .map(|(key, frames)| format!("{}={}", key, frames))
+                    .collect();
+                Some(format!("driver timing updated: {}", changed.join(", ")))
+            };
+            match fetch_device_timing(device_id) {
+                Ok(timing) => {
+                    let payload = latency_payload(&timing);
+                    match message {
+                        Some(message) => json_success_with_message_and_data(message, payload),
+                        None => json_success_with_data(payload),
+                    }
+                }
+                Err(err) => json_error(format!("failed to read driver timing: {}", err)),
+            }
+        }
+        CommandRequest::Bridges => json_success_with_data(bridges_payload()),
+        CommandRequest::BridgeAdd {
+            source,
+            source_offset,
+            target,
+            target_offset,
+        } => {
+            let source_id = match source.as_deref() {
+                None => device_id,
+                Some(query) => match find_device(query) {
+                    Ok(id) => id,
+                    Err(err) => return json_error(err),
+                },
+            };
+            let target_id = match find_device(&target) {
+                Ok(id) => id,
+                Err(err) => return json_error(err),
+            };
+            let mut bridges = BRIDGES.lock().expect("bridge mutex poisoned");
+            let id = bridges.iter().map(|bridge| bridge.id).max().unwrap_or(0) + 1;
+            let source = BridgeEnd {
+                device_id: source_id,
+                name: device_name(source_id),
+                channel_offset: source_offset,
+            };
+            let target = BridgeEnd {
+                device_id: target_id,
+                name: device_name(target_id),
+                channel_offset: target_offset,
+            };
+            match Bridge::start(id, source, target) {
+                Ok(bridge) => {
+                    apply_monitor_sends(&bridge);
+                    let message = format!(
+                        "bridge {} started: {} {} -> {} {}",
+                        id,
+                        bridge.source.name,
+                        pair_label(bridge.source.channel_offset),
+                        bridge.target.name,
+                        pair_label(bridge.target.channel_offset)
+                    );
+                    let payload = bridge_payload(&bridge);
+                    bridges.push(bridge);
+                    json_success_with_message_and_data(message, payload)
+                }
+                Err(err) => json_error(format!("failed to start bridge: {}", err)),
+            }
+        }
+        CommandRequest::BridgeRemove { id } => {
+            let mut bridges = BRIDGES.lock().expect("bridge mutex poisoned");
+            let Some(index) = bridges.iter().position(|bridge| bridge.id == id) else {
+                return json_error(format!("no bridge {}", id));
+            };
+            // Dropping the bridge stops both IOProcs
+            bridges.remove(index);
+            drop(bridges);
+            json_success_with_message_and_data(format!("bridge {} stopped", id), bridges_payload())
+        }
+        CommandRequest::ClockDomain { domain, device } => {
+            let devices = match list_audio_devices() {
+                Ok(devices) => devices,
+                Err(err) => return json_error(format!("failed to list audio devices: {}", err)),
+            };
+            let update = match (device, domain) {
+                (Some(query), _) => {
+                    let Some(target) = devices.iter().find(|entry| {
+                        entry.uid == query || entry.name.eq_ignore_ascii_case(&query)
+                    }) else {
+                        return json_error(format!("no audio device named '{}'", query));
+                    };
+                    if target.clock_domain == 0 {
+                        return json_error(format!(
+                            "'{}' reports no clock domain to share",
+                            target.name
+                        ));
+                    }
+                    Some((
+                        target.clock_domain,
+                        format!(
+                            "Prism now shares the clock domain of '{}' ({})",
+                            target.name, target.clock_domain
+                        ),
+                    ))
+                }
+                (None, Some(0)) => Some((0, "clock domain cleared".to_string())),
+                (None, Some(domain)) => Some((domain, format!("clock domain set to {}", domain))),
+                (None, None) => None,
+            };
+            let message = match update {
+                Some((domain, message)) => {
+                    if let Err(err) = send_clock_domain(device_id, domain) {
+                        return json_error(format!("failed to set clock domain: {}", err));
+                    }
+                    Some(message)
+                }
+                None => None,
+            };
+            match fetch_clock_domain(device_id) {
+                Ok(domain) => {
+                    let payload = ClockDomainPayload {
+                        domain,
+                        devices: devices
+                            .into_iter()
+                            .map(|entry| ClockDomainDevicePayload {
+                                name: entry.name,
+                                uid: entry.uid,
+                                clock_domain: entry.clock_domain,
+                            })
+                            .collect(),
+                    };
+                    match message {
+                        Some(message) => json_success_with_message_and_data(message, payload),
+                        None => json_success_with_data(payload),
+                    }
+                }
+                Err(err) => json_error(format!("failed to read clock domain: {}", err)),
+            }
+        }
+        CommandRequest::OutputMode { full_bus } => {
+            let message = match full_bus {
+                None => None,
+                Some(full_bus) => {
+                    if let Err(err) = send_output_mode(device_id, full_bus) {
+                        return json_error(format!("failed to set output mode: {}", err));
+                    }
+                    Some(if full_bus {
+                        "output stream now spans the bus; apps play onto bus channels 1:1"
+                            .to_string()
+                    } else {
+                        "output stream back to stereo; apps are routed per client".to_string()
+                    })
+                }
+            };
+            match fetch_output_mode(device_id) {
+                Ok(mode) => {
+                    let payload = output_mode_payload(&mode);
+                    match message {
+                        Some(message) => json_success_with_message_and_data(message, payload),
+                        None => json_success_with_data(payload),
+                    }
+                }
+                Err(err) => json_error(format!("failed to read output mode: {}", err)),
+            }
+        }
+        CommandRequest::SystemMix { offset, enabled } => {
+            if let Some(offset) = offset {
+                let channels = match input_channel_count(device_id) {
+                    Ok(channels) => channels,
+                    Err(err) => {
+                        return json_error(format!("failed to read channel count: {}", err))
+                    }
+                };
+                if offset % 2 != 0 || offset + 1 >= channels {
+                    return json_error(format!(
+                        "channel offset {} is not a pair between 1-2 and {}-{}",
+                        offset,
+                        channels - 1,
+                        channels
+                    ));
+                }
+            }
+            let message = match (offset, enabled) {
+                (None, None) => None,
+                _ => {
+                    if let Err(err) = send_system_mix(device_id, offset, enabled) {
+                        return json_error(format!("failed to update the system mix: {}", err));
+                    }
+                    Some("system mix updated".to_string())
+                }
+            };
+            match fetch_system_mix(device_id) {
+                Ok(mix) => {
+                    let payload = SystemMixPayload {
+                        channel_offset: mix.offset,
+                        enabled: mix.enabled,
+                    };
+                    match message {
+                        Some(message) => json_success_with_message_and_data(message, payload),
+                        None => json_success_with_data(payload),
+                    }
+                }
+                Err(err) => json_error(format!("failed to read system mix: {}", err)),
+            }
+        }
+        CommandRequest::AutoAssign { policy } => {
+            let message = match policy {
+                None => None,
+                Some(policy) => {
+                    if let Err(err) = send_auto_assign(device_id, policy.name()) {
+                        return json_error(format!("failed to set auto-assign policy: {}", err));
+                    }
+                    let mut settings = SETTINGS.lock().expect("settings mutex poisoned");
+                    settings.auto_assign = Some(policy);
+                    if let Some(path) = SETTINGS_FILE.get() {
+                        if let Err(err) = save_settings(&settings, path) {
+                            return json_error(format!(
+                                "auto-assign policy set to {} but not saved: {}",
+                                policy.name(),
+                                err
+                            ));
+                        }
+                    }
+                    Some(format!("auto-assign policy set to {}", policy.name()))
+                }
+            };
+            let policy = match fetch_auto_assign(device_id) {
+                Ok(name) => match AssignPolicy::parse(&name) {
+                    Some(policy) => policy,
+                    None => return json_error(format!("driver reports unknown policy '{}'", name)),
+                },
+                Err(err) => {
+                    return json_error(format!("failed to read auto-assign policy: {}", err))
+                }
+            };
+            let payload = AutoAssignPayload {
+                policy,
+                saved: SETTINGS
+                    .lock()
+                    .expect("settings mutex poisoned")
+                    .auto_assign,
+            };
+            match message {
+                Some(message) => json_success_with_message_and_data(message, payload),
+                None => json_success_with_data(payload),
+            }
+        }
+        CommandRequest::Presets => json_success_with_data(presets_payload()),
+        CommandRequest::Rules { reload } => {
+            if !reload {
+                return json_success_with_data(rules_payload());
+            }
+            let Some(path) = RULES_FILE.get() else {
+                return json_error("prismd has no rules file".to_string());
+            };
+            let loaded = match rules::load(path) {
+                Ok(loaded) => loaded,
+                Err(err) => return json_error(format!("rules not reloaded: {}", err)),
+            };
+            let message = format!("{} rule(s) loaded", loaded.len());
+            *RULES.lock().expect("rules mutex poisoned") = loaded;
+            // The new rules apply to the apps already playing too
+            RULE_ROUTED.lock().expect("rule mutex poisoned").clear();
+            if device_id != kAudioObjectUnknown {
+                route_by_rules(device_id);
+            }
+            json_success_with_message_and_data(message, rules_payload())
+        }
+        CommandRequest::Complete { kind } => json_success_with_data(completion_payload(kind)),
+        CommandRequest::Classify {
+            bundle_id,
+            content_type,
+        } => {
+            let changed = {
+                let mut overrides = CONTENT_OVERRIDES
+                    .lock()
+                    .expect("content type mutex poisoned");
+                let changed = overrides.set(&bundle_id, content_type);
+                if let Some(path) = CONTENT_TYPES_FILE.get().filter(|_| changed) {
+                    if let Err(err) = overrides.save(path) {
+                        return json_error(format!("failed to save content types: {}", err));
+                    }
+                }
+                changed
+            };
+
+            let message = match content_type {
+                Some(content_type) => {
+                    format!("{} classified as {}", bundle_id, content_type.name())
+                }
+                None => format!("{} is classified from its bundle again", bundle_id),
+            };
+            // A preset routing by content type may now cover the app
+            if let Some(device_id) = current_device().filter(|_| changed) {
+                route_by_active_preset(device_id);
+            }
+            let clients = current_device()
+                .and_then(|device_id| build_clients_payload(device_id).ok())
+                .unwrap_or_default();
+            json_success_with_message_and_data(message, classifications_payload(&clients))
+        }
+        CommandRequest::Classifications => {
+            let clients = match current_device() {
+                Some(device_id) => match build_clients_payload(device_id) {
+                    Ok(clients) => clients,
+                    Err(err) => return json_error(format!("failed to fetch clients: {}", err)),
+                },
+                None => Vec::new(),
+            };
+            json_success_with_data(classifications_payload(&clients))
+        }
+        CommandRequest::Quickstart { preset: None, .. } => {
+            match ACTIVE_PRESET.lock().expect("preset mutex poisoned").take() {
+                Some(active) => json_success_with_message_and_data(
+                    format!(
+                        "preset '{}' turned off; apps keep their current routes",
+                        active.preset.name
+                    ),
+                    presets_payload(),
+                ),
+                None => json_error("no preset is active".to_string()),
+            }
+        }
+        CommandRequest::Quickstart {
+            preset: Some(name),
+            owner,
+        } => {
+            let (presets, load_errors) = available_presets();
+            let Some(preset) = presets
+                .iter()
+                .find(|preset| preset.name.eq_ignore_ascii_case(&name))
+                .cloned()
+            else {
+                let names: Vec<&str> = presets.iter().map(|preset| preset.name.as_str()).collect();
+                let mut message = format!(
+                    "unknown preset '{}' (available: {})",
+                    name,
+                    names.join(", ")
+                );
+                // The preset may be in a file that was refused
+                for err in load_errors {
+                    message.push_str(&format!("\nskipped {}", err));
+                }
+                return json_error(message);
+            };
+            let (preset_name, description) = (preset.name.clone(), preset.description.clone());
+            let mut guard = ACTIVE_PRESET.lock().expect("preset mutex poisoned");
+            let active = guard.insert(ActivePreset {
+                preset,
+                routed: HashSet::new(),
+                owner,
+            });
+            match apply_preset_routes(device_id, active) {
+                Ok(routes) => json_success_with_message_and_data(
+                    format!(
+                        "preset '{}' active; apps that start later are routed as they appear",
+                        preset_name
+                    ),
+                    PresetPayload {
+                        name: preset_name,
+                        description,
+                        active: true,
+                        routes,
+                    },
+                ),
+                Err(err) => json_error(format!("failed to apply preset: {}", err)),
+            }
+        }
+        CommandRequest::ClockRate { ppm: None } => match fetch_clock_rate(device_id) {
+            Ok((ppm, scalar)) => json_success_with_data(ClockRatePayload { ppm, scalar }),
+            Err(err) => json_error(format!("failed to read driver clock rate: {}", err)),
+        },
+        CommandRequest::ClockRate { ppm: Some(ppm) } => {
+            if let Err(err) = send_clock_rate(device_id, ppm) {
+                return json_error(format!(
+                    "failed to set driver clock rate (limit is ±1000 ppm): {}",
+                    err
+                ));
+            }
+            match fetch_clock_rate(device_id) {
+                Ok((ppm, scalar)) => json_success_with_message_and_data(
+                    format!("driver clock rate set to {:+.1} ppm", ppm),
+                    ClockRatePayload { ppm, scalar },
+                ),
+                Err(err) => json_error(format!("failed to read driver clock rate: {}", err)),
+            }
+        }
+        CommandRequest::Quit | CommandRequest::Exit => {
+            json_error("terminating prismd via CLI is not supported".to_string())
+        }
+    }
+}
+
+fn build_clients_payload(device_id: AudioObjectID) -> Result<Vec<ClientInfoPayload>, String> {
+    clients_payload(device_id, true)
+}
+
+// Clients with their process and app. Routing decisions need every identity, so with
+// `wait` missing or stale ones are resolved here; without, they are queued for the
+// identity worker and the client is sent with what is cached (`identity_pending` when
+// nothing is).
+fn clients_payload(device_id: AudioObjectID, wait: bool) -> Result<Vec<ClientInfoPayload>, String> {
+    let clients = fetch_client_list(device_id)?;
+
+    {
+        let mut cache = CLIENT_LIST.lock().expect("client list mutex poisoned");
+        *cache = clients.clone();
+    }
+
+    APP_GROUPS
+        .lock()
+        .expect("app group mutex poisoned")
+        .retain(|pid, _| clients.iter().any(|entry| entry.pid == *pid));
+    CLIENT_IDENTITIES
+        .lock()
+        .expect("client identity mutex poisoned")
+        .retain(|pid, _| clients.iter().any(|entry| entry.pid == *pid));
+
+    let wait = wait || !IDENTITY_WORKER.load(Ordering::Acquire);
+    let payload = clients
+        .into_iter()
+        .map(|entry| {
+            let cached = CLIENT_IDENTITIES
+                .lock()
+                .expect("client identity mutex poisoned")
+                .get(&entry.pid)
+                .cloned();
+            let identity = match cached {
+                Some(identity) if identity.resolved_at.elapsed() < IDENTITY_REFRESH => {
+                    Some(identity)
+                }
+                _ if wait => Some(resolve_identity(entry.pid)),
+                stale => {
+                    queue_identity(entry.pid);
+                    stale
+                }
+            };
+            let identity_pending = identity.is_none();
+            let identity = identity.unwrap_or(ClientIdentity {
+                process_name: None,
+                responsible_pid: None,
+                responsible_name: None,
+                resolved_at: Instant::now(),
+            });
+            let content_type = if identity_pending {
+                None
+            } else {
+                content_type_of(
+                    identity.responsible_pid.unwrap_or(entry.pid),
+                    entry.bundle_id.as_deref(),
+                )
+            };
+
+            ClientInfoPayload {
+                pid: entry.pid,
+                client_id: entry.client_id,
+                channel_offset: entry.channel_offset,
+                process_name: identity.process_name,
+                responsible_pid: identity.responsible_pid,
+                responsible_name: identity.responsible_name,
+                bundle_id: entry.bundle_id,
+                is_running: entry.is_running,
+                gain_db: Some(linear_to_db(entry.gain).max(SILENCE_DB)),
+                content_type,
+                identity_pending,
+            }
+        })
+        .collect();
+
+    Ok(payload)
+}
+
+fn resolve_identity(pid: i32) -> ClientIdentity {
+    let process_name = procinfo::process_name(pid);
+    let (responsible_pid, responsible_name) = resolve_app_group(pid, process_name.as_deref());
+    let identity = ClientIdentity {
+        process_name,
+        responsible_pid,
+        responsible_name,
+        resolved_at: Instant::now(),
+    };
+    let previous = CLIENT_IDENTITIES
+        .lock()
+        .expect("client identity mutex poisoned")
+        .insert(pid, identity.clone());
+    if let Some(responsible_pid) = identity
+        .responsible_pid
+        .filter(|&responsible| responsible != pid)
+    {
+        if previous.and_then(|previous| previous.responsible_pid) != Some(responsible_pid) {
+            println!(
+                "[prismd] pid {} ({}) belongs to pid {} ({})",
+                pid,
+                identity.process_name.as_deref().unwrap_or("<unknown>"),
+                responsible_pid,
+                identity.responsible_name.as_deref().unwrap_or("<unknown>")
+            );
+        }
+    }
+    identity
+}
+
+fn queue_identity(pid: i32) {
+    PENDING_IDENTITIES
+        .lock()
+        .expect("identity queue mutex poisoned")
+        .insert(pid);
+    IDENTITY_WAKE.notify_one();
+}
+
+fn start_identity_worker() -> io::Result<()> {
+    thread::Builder::new()
+        .name("prismd-identity".to_string())
+        .spawn(|| loop {
+            let pids = {
+                let mut pending = PENDING_IDENTITIES
+                    .lock()
+                    .expect("identity queue mutex poisoned");
+                while pending.is_empty() {
+                    pending = IDENTITY_WAKE
+                        .wait(pending)
+                        .expect("identity queue mutex poisoned");
+                }
+                std::mem::take(&mut *pending)
+            };
+            for pid in pids {
+                resolve_identity(pid);
+            }
+        })?;
+    IDENTITY_WORKER.store(true, Ordering::Release);
+    Ok(())
+}
+
+// Content type of a client: its `prism classify` override, else what the bundle of its
+// app (the responsible process) declares.
+fn content_type_of(app_pid: i32, bundle_id: Option<&str>) -> Option<ContentType> {
+    if let Some(content_type) = bundle_id.and_then(|bundle_id| {
+        CONTENT_OVERRIDES
+            .lock()
+            .expect("content type mutex poisoned")
+            .lookup(bundle_id)
+    }) {
+        return Some(content_type);
+    }
+    let path = procinfo::process_path(app_pid)?;
+    *BUNDLE_CONTENT_TYPES
+        .lock()
+        .expect("content type mutex poisoned")
+        .entry(path)
+        .or_insert_with_key(|path| content::classify_executable(path))
+}
+
+// Running apps with their content type, then overrides for bundles not running now.
+fn classifications_payload(clients: &[ClientInfoPayload]) -> Vec<ClassificationPayload> {
+    let overrides = CONTENT_OVERRIDES
+        .lock()
+        .expect("content type mutex poisoned")
+        .clone();
+    let mut apps: BTreeMap<String, ClassificationPayload> = BTreeMap::new();
+    for client in clients {
+        let Some(name) = client
+            .responsible_name
+            .clone()
+            .or_else(|| client.process_name.clone())
+        else {
+            continue;
+        };
+        let entry = apps.entry(name.clone()).or_insert(ClassificationPayload {
+            app_name: Some(name),
+            bundle_id: None,
+            content_type: None,
+            overridden: false,
+        });
+        if entry.bundle_id.is_none() {
+            entry.bundle_id.clone_from(&client.bundle_id);
+        }
+        if entry.content_type.is_none() {
+            entry.content_type = client.content_type;
+            entry.overridden = client
+                .bundle_id
+                .as_deref()
+                .is_some_and(|bundle_id| overrides.lookup(bundle_id).is_some());
+        }
+    }
+
+    let mut payload: Vec<ClassificationPayload> = apps.into_values().collect();
+    for (bundle_id, content_type) in overrides.iter() {
+        let running = clients.iter().any(|client| {
+            client
+                .bundle_id
+                .as_deref()
+                .is_some_and(|id| procinfo::bundle_matches(id, bundle_id))
+        });
+        if !running {
+            payload.push(ClassificationPayload {
+                app_name: None,
+                bundle_id: Some(bundle_id.to_string()),
+                content_type: Some(content_type),
+                overridden: true,
+            });
+        }
+    }
+    payload
+}
+
+// Responsible pid and name a client is grouped under. When the responsible process has
+// exited (its pid no longer resolves, or the helper was reparented to launchd), the
+// helper keeps its last known group for --orphan-grace seconds, then becomes its own app.
+fn resolve_app_group(pid: i32, process_name: Option<&str>) -> (Option<i32>, Option<String>) {
+    let identity = procinfo::resolve_responsible_identity(pid);
+    let mut groups = APP_GROUPS.lock().expect("app group mutex poisoned");
+    let known = groups.get(&pid).map(|group| group.responsible_pid);
+
+    let orphaned = identity.as_ref().is_some_and(|identity| {
+        identity.pid != pid
+            && (!procinfo::is_alive(identity.pid)
+                || (identity.pid == 1 && known.is_some_and(|known| known != 1)))
+    });
+    if !orphaned {
+        let Some(identity) = identity else {
+            return (None, None);
+        };
+        let name = identity.preferred_name();
+        groups.insert(
+            pid,
+            AppGroup {
+                responsible_pid: identity.pid,
+                responsible_name: name.clone(),
+                orphaned_at: None,
+            },
+        );
+        return (Some(identity.pid), name);
+    }
+
+    let grace = Duration::from_secs(ORPHAN_GRACE_SECS.load(Ordering::Relaxed));
+    if let Some(group) = groups.get_mut(&pid) {
+        if group.responsible_pid != pid {
+            let orphaned_at = *group.orphaned_at.get_or_insert_with(|| {
+                println!(
+                    "[prismd] pid {} outlived its app {} ({}); regrouping in {} s",
+                    pid,
+                    group.responsible_name.as_deref().unwrap_or("<unknown>"),
+                    group.responsible_pid,
+                    grace.as_secs()
+                );
+                Instant::now()
+            });
+            if orphaned_at.elapsed() < grace {
+                return (Some(group.responsible_pid), group.responsible_name.clone());
+            }
+        }
+    }
+
+    let name = process_name.map(str::to_string);
+    groups.insert(
+        pid,
+        AppGroup {
+            responsible_pid: pid,
+            responsible_name: name.clone(),
+            orphaned_at: None,
+        },
+    );
+    (Some(pid), name)
+}
+
+fn build_meters_payload(device_id: AudioObjectID) -> Result<Vec<BusLevelPayload>, String> {
+    let levels = match telemetry_levels() {
+        Some(levels) => levels,
+        None => fetch_meters(device_id)?,
+    };
+    let clients = build_clients_payload(device_id)?;
+
+    let payload = levels
+        .into_iter()
+        .map(|level| {
+            let mut apps: Vec<String> = clients
+                .iter()
+                .filter(|client| client.channel_offset / 2 == level.pair)
+                .filter_map(|client| {
+                    client
+                        .responsible_name
+                        .clone()
+                        .or_else(|| client.process_name.clone())
+                })
+                .collect();
+            apps.sort();
+            apps.dedup();
+
+            BusLevelPayload {
+                pair: level.pair,
+                peak: level.peak,
+                rms: level.rms,
+                apps,
+            }
+        })
+        .collect();
+
+    Ok(payload)
+}
+
+// Bus levels from the shared-memory region, or None if it is not mapped.
+fn telemetry_levels() -> Option<Vec<BusLevel>> {
+    let guard = TELEMETRY.lock().ok()?;
+    let (snapshot, updated) = guard.as_ref()?;
+    let idle = updated.elapsed() > TELEMETRY_IDLE_TIMEOUT;
+    // A bus that has not been written for half a second is reported as silent
+    let stale_after = f64::from(snapshot.sample_rate) / 2.0;
+
+    let levels = snapshot
+        .buses
+        .iter()
+        .map(|bus| {
+            let stale =
+                idle || snapshot.last_output_sample_time - bus.last_sample_time > stale_after;
+            if stale {
+                BusLevel {
+                    pair: bus.pair,
+                    ..Default::default()
+                }
+            } else {
+                BusLevel {
+                    pair: bus.pair,
+                    peak: bus.peak,
+                    rms: bus.rms,
+                }
+            }
+        })
+        .collect();
+    Some(levels)
+}
+
+// Map the driver's telemetry region and sample it at display rate. Falls back to
+// the 'metr' property (via build_meters_payload) while the region is unavailable.
+fn start_telemetry_sampler() -> io::Result<()> {
+    thread::Builder::new()
+        .name("prismd-telemetry".to_string())
+        .spawn(move || loop {
+            let Some(device_id) = current_device() else {
+                thread::sleep(TELEMETRY_RETRY_INTERVAL);
+                continue;
+            };
+            let generation = DEVICE_GENERATION.load(Ordering::Relaxed);
+            let region = match fetch_telemetry_name(device_id)
+                .and_then(|name| TelemetryRegion::open_readonly(&name))
+            {
+                Ok(region) => region,
+                Err(err) => {
+                    eprintln!("[prismd] Telemetry region unavailable: {}", err);
+                    thread::sleep(TELEMETRY_RETRY_INTERVAL);
+                    continue;
+                }
+            };
+
+            let mut last_sequence = None;
+            let mut updated = Instant::now();
+            // A reloaded driver maps a fresh region; reopen it after rediscovery
+            while current_device().is_some()
+                && DEVICE_GENERATION.load(Ordering::Relaxed) == generation
+            {
+                let snapshot = region.snapshot();
+                if last_sequence != Some(snapshot.sequence) {
+                    last_sequence = Some(snapshot.sequence);
+                    updated = Instant::now();
+                }
+                if let Ok(mut guard) = TELEMETRY.lock() {
+                    *guard = Some((snapshot, updated));
+                }
+                thread::sleep(TELEMETRY_POLL_INTERVAL);
+            }
+        })?;
+    Ok(())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Sample bus peaks a few times a second, fold them into one bucket per second
+// together with who was routed where, and keep `minutes` worth of buckets. With a
+// file configured the history is flushed every minute and reloaded on start, so it
+// survives a daemon restart.
+fn start_meter_history(minutes: u64, file: Option<PathBuf>) -> io::Result<()> {
+    let retention = match minutes.checked_mul(60) {
+        Some(0) => return Ok(()),
+        Some(secs) if secs <= HISTORY_MAX_QUERY_SECS => secs as usize,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "--meter-history {} is out of range (at most {} minutes)",
+                    minutes,
+                    HISTORY_MAX_QUERY_SECS / 60
+                ),
+            ))
+        }
+    };
+    if let Some(path) = &file {
+        match load_meter_history(path, retention) {
+            Ok(buckets) => *METER_HISTORY.lock().expect("history mutex poisoned") = buckets,
+            Err(err) => eprintln!("[prismd] Meter history not loaded: {}", err),
+        }
+    }
+
+    thread::Builder::new()
+        .name("prismd-history".to_string())
+        .spawn(move || {
+            let mut current: Option<(u64, Vec<f32>)> = None;
+            let mut last_flush = Instant::now();
+            loop {
+                thread::sleep(HISTORY_SAMPLE_INTERVAL);
+                let device_id = current_device();
+                let levels = match telemetry_levels() {
+                    Some(levels) => levels,
+                    None => device_id
+                        .and_then(|device_id| fetch_meters(device_id).ok())
+                        .unwrap_or_default(),
+                };
+                let now = unix_now();
+
+                if let Some((time, peaks)) = current.take_if(|(time, _)| *time != now) {
+                    let apps = history_apps();
+                    let mut history = METER_HISTORY.lock().expect("history mutex poisoned");
+                    history.push_back(HistoryBucket { time, peaks, apps });
+                    while history.len() > retention {
+                        history.pop_front();
+                    }
+                }
+
+                let (_, peaks) = current.get_or_insert_with(|| (now, Vec::new()));
+                for level in &levels {
+                    let pair = level.pair as usize;
+                    if peaks.len() <= pair {
+                        peaks.resize(pair + 1, 0.0);
+                    }
+                    peaks[pair] = peaks[pair].max(level.peak[0]).max(level.peak[1]);
+                }
+
+                if let Some(path) = &file {
+                    if last_flush.elapsed() >= HISTORY_FLUSH_INTERVAL {
+                        last_flush = Instant::now();
+                        if let Err(err) = flush_meter_history(path) {
+                            eprintln!("[prismd] Failed to flush meter history: {}", err);
+                        }
+                    }
+                }
+            }
+        })?;
+    Ok(())
+}
+
+// Apps on each pair, from the cached client list and identities, so sampling never
+// re-resolves a client; one not resolved yet is named by its process.
+fn history_apps() -> Vec<(String, u32)> {
+    let clients = CLIENT_LIST
+        .lock()
+        .expect("client list mutex poisoned")
+        .clone();
+    let identities = CLIENT_IDENTITIES
+        .lock()
+        .expect("client identity mutex poisoned");
+    let mut apps: Vec<(String, u32)> = clients
+        .iter()
+        .filter_map(|entry| {
+            let name = match identities.get(&entry.pid) {
+                Some(identity) => identity
+                    .responsible_name
+                    .clone()
+                    .or_else(|| identity.process_name.clone()),
+                None => procinfo::process_name(entry.pid),
+            }?;
+            Some((name, entry.channel_offset / 2))
+        })
+        .collect();
+    apps.sort();
+    apps.dedup();
+    apps
+}
+
+fn load_meter_history(path: &Path, retention: usize) -> Result<VecDeque<HistoryBucket>, String> {
+    let raw = match fs::read_to_string(path) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(VecDeque::new()),
+        Err(err) => return Err(format!("{}: {}", path.display(), err)),
+    };
+    let buckets: VecDeque<HistoryBucket> =
+        serde_json::from_str(&raw).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let oldest = unix_now().saturating_sub(retention as u64);
+    Ok(buckets
+        .into_iter()
+        .filter(|bucket| bucket.time >= oldest)
+        .collect())
+}
+
+fn flush_meter_history(path: &Path) -> Result<(), String> {
+    let serialized = {
+        let history = METER_HISTORY.lock().map_err(|err| err.to_string())?;
+        serde_json::to_string(&*history).map_err(|err| err.to_string())?
+    };
+    persist::write_atomic(path, serialized.as_bytes())
+}
+
+fn meter_history_payload(seconds: u64) -> MeterHistoryPayload {
+    let end_time = unix_now();
+    let len = seconds.clamp(1, HISTORY_MAX_QUERY_SECS);
+    let start = end_time.saturating_sub(len);
+
+    let mut system_mix = vec![None; len as usize];
+    let mut series: BTreeMap<String, Vec<Option<f32>>> = BTreeMap::new();
+    if let Ok(history) = METER_HISTORY.lock() {
+        for bucket in history
+            .iter()
+            .filter(|bucket| bucket.time >= start && bucket.time < end_time)
+        {
+            let idx = (bucket.time - start) as usize;
+            system_mix[idx] = bucket.peaks.first().copied();
+            for (app, pair) in &bucket.apps {
+                // Apps on the system mix have no bus of their own to measure
+                if *pair == 0 {
+                    continue;
+                }
+                let Some(peak) = bucket.peaks.get(*pair as usize) else {
+                    continue;
+                };
+                let samples = series
+                    .entry(app.clone())
+                    .or_insert_with(|| vec![None; len as usize]);
+                samples[idx] = Some(samples[idx].map_or(*peak, |p: f32| p.max(*peak)));
+            }
+        }
+    }
+
+    let mut payload = vec![MeterSeriesPayload {
+        name: HISTORY_SYSTEM_MIX.to_string(),
+        peaks: system_mix,
+    }];
+    payload.extend(
+        series
+            .into_iter()
+            .map(|(name, peaks)| MeterSeriesPayload { name, peaks }),
+    );
+    MeterHistoryPayload {
+        interval_secs: 1,
+        end_time,
+        series: payload,
+    }
+}
+
+fn agc_payload(app_name: &str, controller: &AgcController) -> AgcPayload {
+    AgcPayload {
+        app_name: app_name.to_string(),
+        target_lufs: controller.target_lufs,
+        range_db: controller.range_db,
+        gain_db: controller.gain_db,
+        loudness_lufs: controller.loudness(),
+    }
+}
+
+fn reservations_payload(device_id: AudioObjectID) -> Result<Vec<ReservationPayload>, String> {
+    Ok(fetch_reservations(device_id)?
+        .into_iter()
+        .map(|reservation| ReservationPayload {
+            channel_offset: reservation.channel_offset,
+            owner: reservation.owner,
+        })
+        .collect())
+}
+
+// Bus occupancy with writers named by app. Names come from the identity cache, so
+// answering stays quick; a writer not resolved yet is named by its process.
+fn buses_payload(device_id: AudioObjectID) -> Result<Vec<BusPayload>, String> {
+    let buses = fetch_bus_occupancy(device_id)?;
+    let labels: BTreeMap<u32, String> = fetch_bus_labels(device_id)?.into_iter().collect();
+    let identities = CLIENT_IDENTITIES
+        .lock()
+        .expect("client identity mutex poisoned")
+        .clone();
+    Ok(buses
+        .into_iter()
+        .map(|bus| BusPayload {
+            channel_offset: bus.channel_offset,
+            label: labels.get(&bus.channel_offset).cloned(),
+            free: bus.writers.is_empty() && bus.reserved_by.is_none(),
+            writers: bus
+                .writers
+                .into_iter()
+                .map(|writer| BusWriterPayload {
+                    pid: writer.pid,
+                    client_id: writer.client_id,
+                    app_name: match identities.get(&writer.pid) {
+                        Some(identity) => identity
+                            .responsible_name
+                            .clone()
+                            .or_else(|| identity.process_name.clone()),
+                        None => procinfo::process_name(writer.pid),
+                    },
+                    bundle_id: writer.bundle_id,
+                    is_running: writer.is_running,
+                })
+                .collect(),
+            reserved_by: bus.reserved_by,
+            last_audio_ms: bus.last_audio_ms,
+        })
+        .collect())
+}
+
+fn gap_policies_payload(device_id: AudioObjectID) -> Result<Vec<GapPolicyPayload>, String> {
+    Ok(fetch_gap_holds(device_id)?
+        .into_iter()
+        .map(|hold| GapPolicyPayload {
+            channel_offset: hold.channel_offset,
+            mode: GapMode::HoldLast,
+            max_hold_ms: hold.max_hold_ms,
+        })
+        .collect())
+}
+
+// Matrix-routed clients with 1-based channels and dB gains, named like `prism apps` does.
+fn build_matrices_payload(
+    device_id: AudioObjectID,
+    clients: &[ClientInfoPayload],
+) -> Result<Vec<ClientMatrixPayload>, String> {
+    Ok(fetch_matrices(device_id)?
+        .into_iter()
+        .map(|matrix| ClientMatrixPayload {
+            pid: matrix.pid,
+            client_id: matrix.client_id,
+            app_name: clients
+                .iter()
+                .find(|client| client.client_id == matrix.client_id)
+                .and_then(|client| {
+                    client
+                        .responsible_name
+                        .clone()
+                        .or_else(|| client.process_name.clone())
+                }),
+            routes: matrix
+                .routes
+                .iter()
+                .map(|route| MatrixRoutePayload {
+                    source: route.source,
+                    channel: route.channel + 1,
+                    gain_db: linear_to_db(route.gain).max(SILENCE_DB),
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+// Clients whose display name (as shown by `prism apps`) or bundle ID is exactly `app_name`.
+fn clients_of_app<'a>(
+    clients: &'a [ClientInfoPayload],
+    app_name: &str,
+) -> Vec<&'a ClientInfoPayload> {
+    clients
+        .iter()
+        .filter(|client| {
+            client
+                .responsible_name
+                .as_ref()
+                .or(client.process_name.as_ref())
+                .map(|s| s.as_str())
+                == Some(app_name)
+                || client.bundle_id.as_deref() == Some(app_name)
+        })
+        .collect()
+}
+
+// Clients of the same app share a responsible process; standalone ones are their own app.
+fn app_key(client: &ClientInfoPayload) -> i32 {
+    client.responsible_pid.unwrap_or(client.pid)
+}
+
+// Enforce the conflict policy of the pair at `offset` before the apps in `incoming`
+// (app_key values) are routed there. Returns a note on what happened to the apps
+// already on the pair, or an error when the policy refuses the route.
+fn resolve_route_conflict(
+    device_id: AudioObjectID,
+    clients: &[ClientInfoPayload],
+    offset: u32,
+    incoming: &HashSet<i32>,
+) -> Result<Option<String>, String> {
+    // Channels 1/2 are the system mix every app is part of; the null bus has no
+    // listeners to conflict over
+    if offset < 2 || offset == NULL_BUS_OFFSET {
+        return Ok(None);
+    }
+    let policy = PAIR_POLICIES
+        .lock()
+        .expect("pair policy mutex poisoned")
+        .get(&offset)
+        .copied()
+        .unwrap_or_default();
+    if policy == ConflictPolicy::Mix {
+        return Ok(None);
+    }
+    // Gain/pan updates for apps already on the pair are not new arrivals
+    if clients
+        .iter()
+        .filter(|client| incoming.contains(&app_key(client)))
+        .all(|client| client.channel_offset == offset)
+    {
+        return Ok(None);
+    }
+
+    let occupants: Vec<&ClientInfoPayload> = clients
+        .iter()
+        .filter(|client| client.channel_offset == offset && !incoming.contains(&app_key(client)))
+        .collect();
+    if occupants.is_empty() {
+        return Ok(None);
+    }
+    let mut names: Vec<&str> = occupants
+        .iter()
+        .map(|client| {
+            client
+                .responsible_name
+                .as_deref()
+                .or(client.process_name.as_deref())
+                .unwrap_or("<unknown>")
+        })
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    let names = names.join(", ");
+    let bus = format!("{}-{}", offset + 1, offset + 2);
+
+    match policy {
+        ConflictPolicy::Mix => Ok(None),
+        ConflictPolicy::Reject => Err(format!(
+            "channels {} are in use by {} (pair policy: reject)",
+            bus, names
+        )),
+        ConflictPolicy::Replace => {
+            for client in &occupants {
+                send_rout_update(device_id, client.pid, 0, None, None, None).map_err(|err| {
+                    format!(
+                        "failed to move pid {} off channels {} (pair policy: replace): {}",
+                        client.pid, bus, err
+                    )
+                })?;
+                remember_route([client.pid], 0, "pair policy");
+            }
+            Ok(Some(format!(
+                "moved {} off channels {} to the system mix",
+                names, bus
+            )))
+        }
+        ConflictPolicy::Duck => {
+            let mut ducked = DUCKED.lock().expect("ducked mutex poisoned");
+            for client in &occupants {
+                // Already ducked for an earlier arrival; lowering it again would stack
+                if ducked
+                    .get(&client.pid)
+                    .is_some_and(|&(pair, _)| pair == offset)
+                {
+                    continue;
+                }
+                let gain = client.gain_db.map_or(1.0, db_to_linear);
+                send_rout_update(
+                    device_id,
+                    client.pid,
+                    offset,
+                    Some(gain * db_to_linear(DUCK_DB)),
+                    None,
+                    None,
+                )
+                .map_err(|err| {
+                    format!(
+                        "failed to duck pid {} on channels {} (pair policy: duck): {}",
+                        client.pid, bus, err
+                    )
+                })?;
+                ducked.insert(client.pid, (offset, gain));
+            }
+            Ok(Some(format!(
+                "ducked {} on channels {} by {} dB",
+                names, bus, DUCK_DB
+            )))
+        }
+    }
+}
+
+fn latency_payload(timing: &DeviceTiming) -> LatencyPayload {
+    LatencyPayload {
+        input_latency: timing.input_latency,
+        output_latency: timing.output_latency,
+        input_safety_offset: timing.input_safety_offset,
+        output_safety_offset: timing.output_safety_offset,
+    }
+}
+
+fn pair_label(offset: u32) -> String {
+    if offset == NULL_BUS_OFFSET {
+        return "null".to_string();
+    }
+    format!("{}-{}ch", offset + 1, offset + 2)
+}
+
+// Channel offsets of the pairs a 'cmsk' channel mask lets through, in part or whole.
+fn mask_offsets(mask: u64, channels: u32) -> Vec<u32> {
+    (0..channels.min(64))
+        .step_by(2)
+        .filter(|&offset| (mask >> offset) & 0b11 != 0)
+        .collect()
+}
+
+fn bridge_payload(bridge: &Bridge) -> BridgePayload {
+    let latency = bridge.latency();
+    BridgePayload {
+        id: bridge.id,
+        source: bridge.source.name.clone(),
+        source_offset: bridge.source.channel_offset,
+        target: bridge.target.name.clone(),
+        target_offset: bridge.target.channel_offset,
+        alive: bridge.is_alive(),
+        latency: BridgeLatencyPayload {
+            source_frames: latency.source_frames,
+            ring_frames: latency.ring_frames,
+            target_frames: latency.target_frames,
+            total_frames: latency.total_frames(),
+            total_ms: latency.total_ms(),
+        },
+        underruns: bridge.underruns(),
+        overruns: bridge.overruns(),
+    }
+}
+
+fn bridges_payload() -> Vec<BridgePayload> {
+    BRIDGES
+        .lock()
+        .expect("bridge mutex poisoned")
+        .iter()
+        .map(bridge_payload)
+        .collect()
+}
+
+fn output_mode_payload(mode: &OutputMode) -> OutputModePayload {
+    OutputModePayload {
+        full_bus: mode.full_bus,
+        bus_channels: mode.bus_channels,
+    }
+}
+
+// Route the apps a rule matches that no rule has routed since they appeared. Uses
+// try_lock for the same reason as route_by_active_preset.
+fn route_by_rules(device_id: AudioObjectID) {
+    let Ok(mut routed) = RULE_ROUTED.try_lock() else {
+        return;
+    };
+    let rules = RULES.lock().expect("rules mutex poisoned").clone();
+    if rules.is_empty() {
+        return;
+    }
+    let clients = match build_clients_payload(device_id) {
+        Ok(clients) => clients,
+        Err(err) => {
+            eprintln!("[prismd] Rules: failed to fetch clients: {}", err);
+            return;
+        }
+    };
+    routed.retain(|key| clients.iter().any(|client| app_key(client) == *key));
+
+    let mut apps: BTreeMap<i32, Vec<&ClientInfoPayload>> = BTreeMap::new();
+    for client in &clients {
+        apps.entry(app_key(client)).or_default().push(client);
+    }
+    // An app routed by other means keeps that route, as restore_routes puts it back; a
+    // default rule would otherwise take every recreated client. Routes the rules made
+    // themselves are fair game, so reloaded rules apply to them
+    let intents: HashSet<i32> = ROUTE_INTENTS
+        .lock()
+        .expect("route intent mutex poisoned")
+        .iter()
+        .filter(|(_, intent)| intent.source != "rule")
+        .map(|(pid, _)| *pid)
+        .collect();
+    for (key, members) in apps {
+        if routed.contains(&key) || members.iter().any(|client| intents.contains(&client.pid)) {
+            continue;
+        }
+        let display = |client: &ClientInfoPayload| {
+            client
+                .responsible_name
+                .clone()
+                .or_else(|| client.process_name.clone())
+        };
+        let Some(rule) = members.iter().find_map(|client| {
+            rules::find(
+                &rules,
+                client.bundle_id.as_deref(),
+                display(client).as_deref(),
+            )
+        }) else {
+            continue;
+        };
+        // Tried once either way: a refused route is not retried on every client change
+        routed.insert(key);
+
+        let name = display(members[0]).unwrap_or_else(|| format!("pid {}", key));
+        let incoming = HashSet::from([key]);
+        if let Err(err) =
+            resolve_route_conflict(device_id, &clients, rule.channel_offset, &incoming)
+        {
+            eprintln!("[prismd] Rules: {}: {}", name, err);
+            continue;
+        }
+        let mut failures = Vec::new();
+        for client in &members {
+            if let Err(err) = send_rout_update(
+                device_id,
+                client.pid,
+                rule.channel_offset,
+                Some(db_to_linear(rule.gain_db)),
+                None,
+                None,
+            ) {
+                failures.push(format!("pid {}: {}", client.pid, err));
+            }
+        }
+        if failures.is_empty() {
+            remember_route(
+                members.iter().map(|client| client.pid),
+                rule.channel_offset,
+                "rule",
+            );
+            println!(
+                "[prismd] Rules: routed {} to {}",
+                name,
+                pair_label(rule.channel_offset)
+            );
+        } else {
+            eprintln!("[prismd] Rules: {}: {}", name, failures.join("; "));
+        }
+    }
+}
+
+fn rules_payload() -> RulesPayload {
+    RulesPayload {
+        path: RULES_FILE.get().map(|path| path.display().to_string()),
+        rules: RULES
+            .lock()
+            .expect("rules mutex poisoned")
+            .iter()
+            .map(|rule| RulePayload {
+                bundle_ids: rule.bundle_ids.clone(),
+                names: rule.names.clone(),
+                channel_offset: rule.channel_offset,
+                gain_db: rule.gain_db,
+                priority: rule.priority,
+                default: rule.default,
+            })
+            .collect(),
+    }
+}
+
+fn presets_payload() -> Vec<PresetPayload> {
+    let active = ACTIVE_PRESET
+        .lock()
+        .expect("preset mutex poisoned")
+        .as_ref()
+        .map(|active| active.preset.name.clone());
+    available_presets()
+        .0
+        .iter()
+        .map(|preset| PresetPayload {
+            name: preset.name.clone(),
+            description: preset.description.clone(),
+            active: active.as_deref() == Some(preset.name.as_str()),
+            routes: preset
+                .routes
+                .iter()
+                .map(|route| PresetRoutePayload {
+                    app: route.app.clone(),
+                    channel_offset: route.channel_offset,
+                    gain_db: route.gain_db,
+                    matched: Vec::new(),
+                    error: None,
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+// Candidates for `prism` shell completion. Apps come from the cached client list, named
+// the way `prism apps` groups them; only pairs read the device, for its channel count.
+fn completion_payload(kind: CompletionKind) -> Vec<CompletionPayload> {
+    let clients = CLIENT_LIST
+        .lock()
+        .expect("client list mutex poisoned")
+        .clone();
+    let app_name = |entry: &ClientEntry| {
+        APP_GROUPS
+            .lock()
+            .expect("app group mutex poisoned")
+            .get(&entry.pid)
+            .and_then(|group| group.responsible_name.clone())
+            .or_else(|| procinfo::process_name(entry.pid))
+    };
+
+    match kind {
+        CompletionKind::Apps => {
+            let mut apps: BTreeMap<String, Option<String>> = BTreeMap::new();
+            for entry in &clients {
+                if let Some(name) = app_name(entry) {
+                    let bundle_id = apps.entry(name).or_default();
+                    if bundle_id.is_none() {
+                        bundle_id.clone_from(&entry.bundle_id);
+                    }
+                }
+            }
+            apps.into_iter()
+                .map(|(value, description)| CompletionPayload { value, description })
+                .collect()
+        }
+        CompletionKind::Pairs => {
+            let channels = current_device()
+                .and_then(|device_id| input_channel_count(device_id).ok())
+                .unwrap_or(0);
+            (0..channels / 2)
+                .map(|pair| {
+                    let offset = pair * 2;
+                    let description = if offset == 0 {
+                        Some("system mix".to_string())
+                    } else {
+                        let mut names: Vec<String> = clients
+                            .iter()
+                            .filter(|entry| entry.channel_offset == offset)
+                            .filter_map(app_name)
+                            .collect();
+                        names.sort();
+                        names.dedup();
+                        (!names.is_empty()).then(|| names.join(", "))
+                    };
+                    CompletionPayload {
+                        value: format!("{}-{}", offset + 1, offset + 2),
+                        description,
+                    }
+                })
+                .collect()
+        }
+        CompletionKind::Presets => available_presets()
+            .0
+            .iter()
+            .map(|preset| CompletionPayload {
+                value: preset.name.clone(),
+                description: Some(preset.description.clone()),
+            })
+            .collect(),
+    }
+}
+
+// Built-in presets followed by the user's, with why any preset file was refused. A user
+// preset replaces a built-in one of the same name.
+fn available_presets() -> (Vec<Preset>, Vec<String>) {
+    let mut presets = preset::builtin().to_vec();
+    let Some(dir) = PRESETS_DIR.get() else {
+        return (presets, Vec::new());
+    };
+    let (user, errors) = presetfmt::load_dir(dir);
+    for err in &errors {
+        eprintln!("[prismd] Skipping preset file {}", err);
+    }
+    for preset in user {
+        presets.retain(|existing| !existing.name.eq_ignore_ascii_case(&preset.name));
+        presets.push(preset);
+    }
+    (presets, errors)
+}
+
+// Route every app the active preset covers and has not routed yet. Returns one entry
+// per preset route naming the apps it moved just now, and why any of them failed.
+fn apply_preset_routes(
+    device_id: AudioObjectID,
+    active: &mut ActivePreset,
+) -> Result<Vec<PresetRoutePayload>, String> {
+    let clients = build_clients_payload(device_id)?;
+    active
+        .routed
+        .retain(|key| clients.iter().any(|client| app_key(client) == *key));
+
+    let mut results: Vec<PresetRoutePayload> = active
+        .preset
+        .routes
+        .iter()
+        .map(|route| PresetRoutePayload {
+            app: route.app.clone(),
+            channel_offset: route.channel_offset,
+            gain_db: route.gain_db,
+            matched: Vec::new(),
+            error: None,
+        })
+        .collect();
+
+    let mut apps: BTreeMap<i32, Vec<&ClientInfoPayload>> = BTreeMap::new();
+    for client in &clients {
+        apps.entry(app_key(client)).or_default().push(client);
+    }
+
+    // Routes are staged under a routing freeze and switch together once all are sent,
+    // so a preset never plays half-applied. Drivers without 'frez' switch one by one
+    let mut frozen = false;
+    for (key, members) in apps {
+        if active.routed.contains(&key) {
+            continue;
+        }
+        let display = |client: &ClientInfoPayload| {
+            client
+                .responsible_name
+                .clone()
+                .or_else(|| client.process_name.clone())
+        };
+        let Some(index) = members.iter().find_map(|client| {
+            let name = display(client);
+            active.preset.routes.iter().position(|route| {
+                route.matches(
+                    client.bundle_id.as_deref(),
+                    name.as_deref(),
+                    client.content_type,
+                )
+            })
+        }) else {
+            continue;
+        };
+        // Tried once either way: a refused route is not retried on every client change
+        active.routed.insert(key);
+
+        let route = &active.preset.routes[index];
+        let name = display(members[0]).unwrap_or_else(|| format!("pid {}", key));
+        let incoming = HashSet::from([key]);
+        if let Err(err) =
+            resolve_route_conflict(device_id, &clients, route.channel_offset, &incoming)
+        {
+            results[index].error = Some(format!("{}: {}", name, err));
+            continue;
+        }
+        if !frozen {
+            match send_routing_freeze(device_id, true, DEFAULT_ROUTING_FREEZE_MS) {
+                Ok(()) => frozen = true,
+                Err(err) => eprintln!("[prismd] Routing freeze unavailable: {}", err),
+            }
+        }
+        let updates: Vec<RoutUpdate> = members
+            .iter()
+            .map(|client| RoutUpdate {
+                pid: client.pid,
+                channel_offset: route.channel_offset,
+                gain: Some(db_to_linear(route.gain_db)),
+                pan: None,
+                width: None,
+            })
+            .collect();
+        match send_rout_batch(device_id, &updates, active.owner.as_deref()) {
+            Ok(()) => {
+                remember_route(
+                    members.iter().map(|client| client.pid),
+                    route.channel_offset,
+                    "preset",
+                );
+                results[index].matched.push(name);
+            }
+            Err(err) => results[index].error = Some(format!("{}: {}", name, err)),
+        }
+    }
+    if frozen {
+        if let Err(err) = send_routing_freeze(device_id, false, DEFAULT_ROUTING_FREEZE_MS) {
+            eprintln!("[prismd] Routing thaw failed: {}", err);
+        }
+    }
+
+    Ok(results)
+}
+
+// Called on every client list change. Uses try_lock: the change may come from a route
+// the IPC thread is applying under the lock right now, and it fetches the clients itself.
+fn route_by_active_preset(device_id: AudioObjectID) {
+    let Ok(mut guard) = ACTIVE_PRESET.try_lock() else {
+        return;
+    };
+    let Some(active) = guard.as_mut() else {
+        return;
+    };
+    match apply_preset_routes(device_id, active) {
+        Ok(routes) => {
+            for route in routes {
+                for app in &route.matched {
+                    println!(
+                        "[prismd] Preset '{}': routed {} to channels {}-{}",
+                        active.preset.name,
+                        app,
+                        route.channel_offset + 1,
+                        route.channel_offset + 2
+                    );
+                }
+                if let Some(err) = route.error {
+                    eprintln!("[prismd] Preset '{}': {}", active.preset.name, err);
+                }
+            }
+        }
+        Err(err) => eprintln!("[prismd] Failed to apply preset: {}", err),
+    }
+}
+
+// Periodically nudge the gain of every AGC-managed app toward its target. The
+// loudness comes from the app's bus in the telemetry region, so it includes any
+// other app routed to the same pair; the system mix pair is never measured. The
+// driver applies the result as a stage of its own, on top of the gain the user set.
+fn start_agc_loop() -> io::Result<()> {
+    thread::Builder::new()
+        .name("prismd-agc".to_string())
+        .spawn(move || {
+            let mut last_tick = Instant::now();
+            loop {
+                thread::sleep(AGC_INTERVAL);
+                let dt = last_tick.elapsed().as_secs_f32();
+                last_tick = Instant::now();
+
+                if AGC.lock().map(|agc| agc.is_empty()).unwrap_or(true) {
+                    continue;
+                }
+                let Some(levels) = telemetry_levels() else {
+                    continue;
+                };
+                let Some(device_id) = current_device() else {
+                    continue;
+                };
+                let clients = match build_clients_payload(device_id) {
+                    Ok(clients) => clients,
+                    Err(err) => {
+                        eprintln!("[prismd] AGC: failed to fetch clients: {}", err);
+                        continue;
+                    }
+                };
+
+                let mut agc = AGC.lock().expect("agc mutex poisoned");
+                for (app_name, controller) in agc.iter_mut() {
+                    let targets = clients_of_app(&clients, app_name);
+                    let Some(pair) = targets
+                        .iter()
+                        .map(|client| client.channel_offset / 2)
+                        .find(|pair| *pair != 0)
+                    else {
+                        continue;
+                    };
+                    let Some(level) = levels.iter().find(|level| level.pair == pair) else {
+                        continue;
+                    };
+                    let Some(gain_db) = controller.update(level.rms, dt) else {
+                        continue;
+                    };
+                    // Gain only, so a reroute since the client list was read is not undone
+                    for client in targets.iter().filter(|c| c.channel_offset / 2 == pair) {
+                        if let Err(err) =
+                            send_client_agc(device_id, client.pid, db_to_linear(gain_db))
+                        {
+                            eprintln!(
+                                "[prismd] AGC: failed to set gain for pid {}: {}",
+                                client.pid, err
+                            );
+                        }
+                    }
+                }
+            }
+        })?;
+    Ok(())
+}
+
+fn build_buffer_regions_payload(
+    device_id: AudioObjectID,
+) -> Result<Vec<BufferRegionPayload>, String> {
+    Ok(fetch_buffer_regions(device_id)?
+        .into_iter()
+        .map(|region| BufferRegionPayload {
+            channel_offset: region.channel_offset,
+            frames: region.frames,
+            peak: region.peak,
+            clients: region.clients,
+        })
+        .collect())
+}
+
+fn build_custom_properties_payload(
+    device_id: AudioObjectID,
+) -> Result<Vec<CustomPropertyPayload>, String> {
+    let entries = read_custom_property_info(device_id)?;
+
+    let payload = entries
+        .into_iter()
+        .map(|entry| CustomPropertyPayload {
+            selector: entry.selector,
+            property_data_type: entry.property_data_type,
+            qualifier_data_type: entry.qualifier_data_type,
+        })
+        .collect();
+
+    Ok(payload)
+}
+
+fn run_daemon(opts: &Opts) {
+    println!("Prism Daemon (prismd) starting...");
+    // Before any thread starts, so every thread (the HAL's included) inherits the mask
+    block_shutdown_signals();
+    ORPHAN_GRACE_SECS.store(opts.orphan_grace, Ordering::Relaxed);
+    POLL_INTERVAL_MS.store(opts.poll_interval, Ordering::Relaxed);
+    if let Some(path) = opts
+        .content_types_file
+        .clone()
+        .or_else(content::default_overrides_path)
+    {
+        match ContentOverrides::load(&path) {
+            Ok(overrides) => {
+                *CONTENT_OVERRIDES
+                    .lock()
+                    .expect("content type mutex poisoned") = overrides
+            }
+            Err(err) => eprintln!("[prismd] Failed to load content types: {}", err),
+        }
+        let _ = CONTENT_TYPES_FILE.set(path);
+    }
+    if let Some(path) = opts.settings_file.clone().or_else(default_settings_path) {
+        match load_settings(&path) {
+            Ok(settings) => *SETTINGS.lock().expect("settings mutex poisoned") = settings,
+            Err(err) => eprintln!("[prismd] Failed to load settings: {}", err),
+        }
+        let _ = SETTINGS_FILE.set(path);
+    }
+    if let Some(path) = opts.routes_file.clone().or_else(default_routes_path) {
+        match load_routes(&path) {
+            Ok(intents) => {
+                if !intents.is_empty() {
+                    println!("[prismd] Restoring {} saved route(s)", intents.len());
+                }
+                *ROUTE_INTENTS.lock().expect("route intent mutex poisoned") = intents;
+            }
+            Err(err) => eprintln!("[prismd] Failed to load routes: {}", err),
+        }
+        let _ = ROUTES_FILE.set(path);
+    }
+    if let Some(dir) = opts
+        .presets_dir
+        .clone()
+        .or_else(presetfmt::default_presets_dir)
+    {
+        let _ = PRESETS_DIR.set(dir);
+    }
+    if let Some(path) = opts.rules_file.clone().or_else(rules::default_rules_path) {
+        match rules::load(&path) {
+            Ok(loaded) => {
+                println!("[prismd] Loaded {} routing rule(s)", loaded.len());
+                *RULES.lock().expect("rules mutex poisoned") = loaded;
+            }
+            Err(err) => eprintln!("[prismd] Failed to load rules: {}", err),
+        }
+        let _ = RULES_FILE.set(path);
+    }
+    // A ready file left by a previous run must not claim this one is serving
+    if let Some(path) = &opts.ready_file {
+        let _ = fs::remove_file(path);
+    }
+
+    let device_id = match find_prism_device() {
+        Ok(id) => id,
+        Err(err) => {
+            eprintln!("Prism driver not found: {}", err);
+            return;
+        }
+    };
+
+    println!("Found Prism Device ID: {}", device_id);
+
+    if let Err(err) = adopt_device(device_id) {
+        eprintln!("[prismd] Failed to register driver listeners: {}", err);
+        return;
+    }
+
+    // Notices the device going away or coming back (driver update, coreaudiod restart)
+    if let Err(err) = add_property_listener(
+        kAudioObjectSystemObject,
+        kAudioHardwarePropertyDevices,
+        Some(device_list_listener),
+        "dev#",
+    ) {
+        eprintln!(
+            "[prismd] Failed to register device list listener: {}; a lost device is only \
+             rediscovered by the next command",
+            err
+        );
+    }
+
+    if let Err(err) = start_identity_worker() {
+        eprintln!(
+            "[prismd] Failed to start identity worker: {}; clients are resolved per request",
+            err
+        );
+    }
+
+    if let Err(err) = start_telemetry_sampler() {
+        eprintln!("[prismd] Failed to start telemetry sampler: {}", err);
+    }
+
+    if let Err(err) = start_agc_loop() {
+        eprintln!("[prismd] Failed to start AGC loop: {}", err);
+    }
+
+    if let Err(err) = start_event_poller(opts.poll_interval) {
+        eprintln!("[prismd] Failed to start client list poller: {}", err);
+    }
+
+    if let Err(err) = start_drift_check(opts.drift_interval, opts.drift_report_only) {
+        eprintln!("[prismd] Failed to start drift check: {}", err);
+    }
+
+    if let Err(err) = start_meter_history(opts.meter_history, opts.meter_history_file.clone()) {
+        eprintln!("[prismd] Failed to start meter history: {}", err);
+    }
+
+    let sockets = socket::listen_sockets(&opts.sockets, opts.user_socket);
+    for spec in &sockets {
+        if let Err(err) = start_ipc_server(spec) {
+            eprintln!(
+                "[prismd] Failed to start IPC server on {}: {}",
+                spec.path.display(),
+                err
+            );
+            return;
+        }
+    }
+    signal_ready(opts);
+
+    let paths: Vec<String> = sockets
+        .iter()
+        .map(|spec| spec.path.display().to_string())
+        .collect();
+    println!(
+        "prismd is now monitoring the Prism driver (socket: {}). Press Ctrl+C to exit.",
+        paths.join(", ")
+    );
+
+    let signal = wait_for_shutdown_signal();
+    println!("[prismd] Received signal {}; shutting down", signal);
+    shut_down(opts, &sockets);
+}
+
+fn shutdown_signals() -> libc::sigset_t {
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGINT);
+        libc::sigaddset(&mut set, libc::SIGTERM);
+        set
+    }
+}
+
+// SIGINT and SIGTERM stay pending until the main thread takes them in
+// wait_for_shutdown_signal, so shutting down never runs inside a signal handler.
+fn block_shutdown_signals() {
+    let set = shutdown_signals();
+    let err = unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &set, ptr::null_mut()) };
+    if err != 0 {
+        eprintln!(
+            "[prismd] Failed to block shutdown signals: {}",
+            io::Error::from_raw_os_error(err)
+        );
+    }
+}
+
+fn wait_for_shutdown_signal() -> libc::c_int {
+    let set = shutdown_signals();
+    let mut signal = 0;
+    while unsafe { libc::sigwait(&set, &mut signal) } != 0 {}
+    signal
+}
+
+// Leave nothing behind that claims prismd is still there: HAL listeners, bridge
+// IOProcs, the sockets and the ready file. State is saved as it changes; the meter
+// history and the routes are written once more so nothing since the last write is lost.
+fn shut_down(opts: &Opts, sockets: &[SocketSpec]) {
+    {
+        // Holding discovery keeps a listener from adopting the device again meanwhile, and
+        // SHUTTING_DOWN keeps anything from doing so afterwards
+        let _guard = DEVICE_DISCOVERY
+            .lock()
+            .expect("device discovery mutex poisoned");
+        SHUTTING_DOWN.store(true, Ordering::Release);
+        EVENT_POLLING.store(false, Ordering::Release);
+        if let Err(err) = remove_property_listener(
+            kAudioObjectSystemObject,
+            kAudioHardwarePropertyDevices,
+            Some(device_list_listener),
+            "dev#",
+        ) {
+            eprintln!("[prismd] {}", err);
+        }
+        let device_id = DEVICE_ID.swap(kAudioObjectUnknown, Ordering::AcqRel);
+        if device_id != kAudioObjectUnknown {
+            unregister_device_event_listener(device_id);
+            if let Err(err) = remove_property_listener(
+                device_id,
+                kAudioDevicePropertyDeviceIsAlive,
+                Some(device_alive_listener),
+                "livn",
+            ) {
+                eprintln!("[prismd] {}", err);
+            }
+        }
+    }
+
+    // Dropping a bridge stops its IOProcs
+    BRIDGES.lock().expect("bridge mutex poisoned").clear();
+
+    if let Some(path) = &opts.meter_history_file {
+        if let Err(err) = flush_meter_history(path) {
+            eprintln!("[prismd] Failed to flush meter history: {}", err);
+        }
+    }
+    save_routes(&ROUTE_INTENTS.lock().expect("route intent mutex poisoned"));
+
+    // The listeners close as the process exits; with the files gone no client connects
+    // in the meantime
+    for spec in sockets {
+        if let Err(err) = fs::remove_file(&spec.path) {
+            if err.kind() != io::ErrorKind::NotFound {
+                eprintln!(
+                    "[prismd] Failed to remove socket {}: {}",
+                    spec.path.display(),
+                    err
+                );
+            }
+        }
+    }
+    if let Some(path) = READY_FILE.get() {
+        let _ = fs::remove_file(path);
+    }
+    println!("[prismd] Stopped");
+}
@@ -1,10 +1,24 @@
+use crate::dsp::mix_stereo_into;
+use crate::ipc::{
+    AssignPolicy, GapMode, MonoFold, DEFAULT_GAP_HOLD_MS, DEFAULT_ROUTING_FREEZE_MS,
+    MAX_BUS_LABEL_CHARS, MAX_GAP_HOLD_MS, MAX_ROUTING_FREEZE_MS, NULL_BUS_OFFSET,
+};
+use crate::oslog::{self, Category, Level};
+use crate::process::bundle_matches;
+use crate::telemetry::{TelemetryRegion, TELEMETRY_SHM_NAME};
 use core_foundation::base::TCFType;
 use core_foundation::data::{CFData, CFDataRef};
+use core_foundation::string::CFString;
 use coreaudio_sys::*;
 use plist::{Dictionary, Value};
-use std::ffi::c_void;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ffi::{c_void, CStr, CString};
 use std::ptr;
-use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::atomic::{
+    AtomicBool, AtomicI32, AtomicPtr, AtomicU32, AtomicU64, AtomicUsize, Ordering,
+};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
 
 mod accelerate {
     #[link(name = "Accelerate", kind = "framework")]
@@ -19,6 +33,28 @@ mod accelerate {
             stride_c: isize,
             len: usize,
         );
+        fn vDSP_vrampmuladd(
+            a: *const f32,
+            stride_a: isize,
+            start: *mut f32,
+            step: *const f32,
+            c: *mut f32,
+            stride_c: isize,
+            len: usize,
+        );
+        fn vDSP_vsma(
+            a: *const f32,
+            stride_a: isize,
+            b: *const f32,
+            c: *const f32,
+            stride_c: isize,
+            d: *mut f32,
+            stride_d: isize,
+            len: usize,
+        );
+        fn vDSP_maxmgv(a: *const f32, stride_a: isize, c: *mut f32, len: usize);
+        fn cblas_scopy(n: i32, x: *const f32, inc_x: i32, y: *mut f32, inc_y: i32);
+        fn vDSP_svesq(a: *const f32, stride_a: isize, c: *mut f32, len: usize);
     }
 
     #[inline]
@@ -29,6 +65,14 @@ mod accelerate {
         vDSP_vclr(dst, 1, len);
     }
 
+    #[inline]
+    pub unsafe fn clear_strided(dst: *mut f32, stride_dst: isize, len: usize) {
+        if len == 0 {
+            return;
+        }
+        vDSP_vclr(dst, stride_dst, len);
+    }
+
     #[inline]
     pub unsafe fn add_inplace(
         src: *const f32,
@@ -42,38 +86,295 @@ mod accelerate {
         }
         vDSP_vadd(src, stride_src, dst, stride_dst, dst, stride_dst, frames);
     }
+
+    /// dst[n] += src[n] * gain.
+    #[inline]
+    pub unsafe fn add_scaled(
+        src: *const f32,
+        stride_src: isize,
+        dst: *mut f32,
+        stride_dst: isize,
+        gain: f32,
+        frames: usize,
+    ) {
+        if frames == 0 {
+            return;
+        }
+        vDSP_vsma(
+            src, stride_src, &gain, dst, stride_dst, dst, stride_dst, frames,
+        );
+    }
+
+    #[inline]
+    pub unsafe fn copy_strided(
+        src: *const f32,
+        stride_src: isize,
+        dst: *mut f32,
+        stride_dst: isize,
+        frames: usize,
+    ) {
+        if frames == 0 {
+            return;
+        }
+        cblas_scopy(
+            frames as i32,
+            src,
+            stride_src as i32,
+            dst,
+            stride_dst as i32,
+        );
+    }
+
+    /// dst[n] += src[n] * (gain + n * step). Returns the gain the ramp would continue with.
+    #[inline]
+    pub unsafe fn add_ramped(
+        src: *const f32,
+        stride_src: isize,
+        dst: *mut f32,
+        stride_dst: isize,
+        gain: f32,
+        step: f32,
+        frames: usize,
+    ) -> f32 {
+        let mut gain = gain;
+        if frames == 0 {
+            return gain;
+        }
+        vDSP_vrampmuladd(src, stride_src, &mut gain, &step, dst, stride_dst, frames);
+        gain
+    }
+
+    /// Returns (max magnitude, sum of squares) over `frames` strided samples.
+    #[inline]
+    pub unsafe fn peak_and_energy(src: *const f32, stride_src: isize, frames: usize) -> (f32, f32) {
+        if frames == 0 {
+            return (0.0, 0.0);
+        }
+        let mut peak = 0.0f32;
+        let mut energy = 0.0f32;
+        vDSP_maxmgv(src, stride_src, &mut peak, frames);
+        vDSP_svesq(src, stride_src, &mut energy, frames);
+        (peak, energy)
+    }
 }
 // use std::collections::HashMap;
 // use std::sync::RwLock;
 
+// Tracing for the realtime IO path. Formatting allocates and syslog can block, so it only
+// exists in builds with the `trace-io` feature; otherwise the arguments are not evaluated.
+macro_rules! trace_io {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "trace-io")]
+        log_msg(Category::Io, &format!($($arg)*));
+    };
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct PrismConfig {
     pub buffer_frame_size: u32,
-    pub safety_offset: u32,
-    pub zero_timestamp_period: u32,
+    /// Latency and safety offset reported for each scope, in frames. The HAL adds
+    /// both to an app's IO timing: raise them to buy headroom against glitches at the
+    /// cost of latency. Adjustable at runtime through 'cnfg'.
+    pub input_latency: u32,
+    pub output_latency: u32,
+    pub input_safety_offset: u32,
+    pub output_safety_offset: u32,
     pub num_channels: u32,
     /// Per-slot ring buffer size in frames. Larger values provide more margin
     /// against audio dropouts but use more memory. Default 16384 frames
     /// (~85ms @ 192kHz, ~340ms @ 48kHz). Memory = slots × frames × 2ch × 4bytes.
     pub slot_buffer_frame_size: u32,
+    /// Initial number of client slots (rounded up to a power of two). Client IDs
+    /// are direct-indexed into the table, so a collision grows it.
+    pub client_slots: u32,
+    /// Upper bound the slot table may grow to on collisions.
+    pub max_client_slots: u32,
+    /// IO cycles without a write after which ReadInput zeroes a bus or client ring,
+    /// so a hung writer is heard as silence instead of its last buffer looping.
+    pub watchdog_cycles: u32,
+    /// Bus mode: the output stream is as wide as the bus and the mix apps play lands on
+    /// the bus channels 1:1, for multichannel sources such as a DAW. Per-client routing
+    /// does not apply in this mode. Switchable at runtime through 'cnfg'.
+    pub full_bus_output: bool,
+    /// kAudioDevicePropertyClockDomain. 0 means no domain; set it to the domain of the
+    /// interface Prism is aggregated with to declare the two share a clock, so the HAL
+    /// skips resampling between them. Adjustable at runtime through 'cnfg'.
+    pub clock_domain: u32,
+    /// How a new client gets a pair of its own instead of staying on 1-2 until the
+    /// daemon routes it. Clients of a process that already plays share its pair.
+    /// Driver.plist and 'cnfg' take a policy name, or true (first-free) and false
+    /// (disabled). Switchable at runtime through 'cnfg'.
+    pub auto_assign: AssignPolicy,
+    /// Channel offset of the pair the system mix (every app not routed to a pair of
+    /// its own) lands on. 0 for 1-2; switchable at runtime through 'cnfg'.
+    pub system_mix_offset: u32,
+    /// Whether WriteMix content reaches the system mix pair at all. Off leaves only
+    /// apps routed to pairs of their own on the bus; switchable at runtime through
+    /// 'cnfg'. Bus mode is unaffected.
+    pub system_mix_enabled: bool,
 }
 
 impl PrismConfig {
     fn default() -> Self {
         Self {
             buffer_frame_size: 1024,
-            safety_offset: 256,
-            zero_timestamp_period: 1024,
+            input_latency: 0,
+            output_latency: 0,
+            input_safety_offset: 256,
+            output_safety_offset: 256,
             num_channels: 64, // Increased to 64 for OMNIBUS-style routing
             slot_buffer_frame_size: 16384, // ~85ms @ 192kHz, ~340ms @ 48kHz
+            client_slots: 256,
+            max_client_slots: 4096,
+            watchdog_cycles: 8,
+            full_bus_output: false,
+            clock_domain: 0,
+            auto_assign: AssignPolicy::FirstFree,
+            system_mix_offset: 0,
+            system_mix_enabled: true,
         }
     }
 
     fn load() -> Self {
-        let config = Self::default();
-        log_msg("Prism: Using default config");
+        let mut config = Self::default();
+        match plist::Value::from_file(PRISM_CONFIG_PATH) {
+            Ok(Value::Dictionary(dict)) => {
+                config.apply_overrides(&dict);
+                log_msg(
+                    Category::Lifecycle,
+                    &format!("Prism: Loaded config from {}", PRISM_CONFIG_PATH),
+                );
+            }
+            _ => log_msg(Category::Lifecycle, "Prism: Using default config"),
+        }
+        // Whole stereo pairs only; 2 turns the device into a plain loopback
+        config.num_channels = config.num_channels.clamp(2, 64) & !1;
+        if config.system_mix_offset % 2 != 0 || config.system_mix_offset + 1 >= config.num_channels
+        {
+            log_error(
+                Category::Lifecycle,
+                &format!(
+                    "Prism: system_mix_offset {} is not a pair of the device; using 1-2",
+                    config.system_mix_offset
+                ),
+            );
+            config.system_mix_offset = 0;
+        }
+        // Any cycle the host may pick must fit in a slot ring with room to spare
+        config.slot_buffer_frame_size = config
+            .slot_buffer_frame_size
+            .max((MIN_RING_CYCLES * MAX_IO_FRAMES) as u32);
+        config.client_slots = config.client_slots.clamp(16, 1 << 16).next_power_of_two();
+        config.max_client_slots = config
+            .max_client_slots
+            .clamp(config.client_slots, 1 << 16)
+            .next_power_of_two();
+        for frames in [
+            &mut config.input_latency,
+            &mut config.output_latency,
+            &mut config.input_safety_offset,
+            &mut config.output_safety_offset,
+        ] {
+            *frames = (*frames).min(MAX_TIMING_FRAMES);
+        }
         config
     }
+
+    // Override defaults with unsigned integer keys from the driver config plist.
+    fn apply_overrides(&mut self, dict: &Dictionary) {
+        let read = |key: &str| {
+            dict.get(key)
+                .and_then(|v| v.as_unsigned_integer())
+                .and_then(|v| u32::try_from(v).ok())
+                .filter(|v| *v > 0)
+        };
+        if let Some(v) = read("slot_buffer_frame_size") {
+            self.slot_buffer_frame_size = v;
+        }
+        if let Some(v) = read("client_slots") {
+            self.client_slots = v;
+        }
+        if let Some(v) = read("max_client_slots") {
+            self.max_client_slots = v;
+        }
+        if let Some(v) = read("num_channels") {
+            self.num_channels = v;
+        }
+        if let Some(v) = read("watchdog_cycles") {
+            self.watchdog_cycles = v;
+        }
+        if let Some(v) = read("clock_domain") {
+            self.clock_domain = v;
+        }
+        if let Some(v) = dict.get("full_bus_output").and_then(|v| v.as_boolean()) {
+            self.full_bus_output = v;
+        }
+        if let Some(v) = dict.get("auto_assign").and_then(parse_assign_policy) {
+            self.auto_assign = v;
+        }
+        if let Some(v) = dict.get("system_mix_enabled").and_then(|v| v.as_boolean()) {
+            self.system_mix_enabled = v;
+        }
+
+        if let Some(v) = dict
+            .get("system_mix_offset")
+            .and_then(|v| v.as_unsigned_integer())
+            .and_then(|v| u32::try_from(v).ok())
+        {
+            self.system_mix_offset = v;
+        }
+
+        // Timing keys may be 0
+        let read_frames = |key: &str| {
+            dict.get(key)
+                .and_then(|v| v.as_unsigned_integer())
+                .and_then(|v| u32::try_from(v).ok())
+        };
+        for (key, frames) in [
+            ("input_latency", &mut self.input_latency),
+            ("output_latency", &mut self.output_latency),
+            ("input_safety_offset", &mut self.input_safety_offset),
+            ("output_safety_offset", &mut self.output_safety_offset),
+        ] {
+            if let Some(v) = read_frames(key) {
+                *frames = v;
+            }
+        }
+    }
+}
+
+// Auto-assignment policy from a plist value: a policy name, or a boolean as the key
+// took before policies existed.
+fn parse_assign_policy(value: &Value) -> Option<AssignPolicy> {
+    match value {
+        Value::Boolean(true) => Some(AssignPolicy::FirstFree),
+        Value::Boolean(false) => Some(AssignPolicy::Disabled),
+        Value::String(name) => AssignPolicy::parse(name),
+        _ => None,
+    }
+}
+
+// Optional driver configuration read once at creation.
+const PRISM_CONFIG_PATH: &str = "/Library/Application Support/Prism/Driver.plist";
+
+// Bundle IDs listed under capture_exclude in Driver.plist. Their output never reaches
+// the loopback, however they are routed; only a root-owned file lifts that, never
+// prismd.
+fn load_capture_exclusions() -> Vec<String> {
+    let Ok(Value::Dictionary(dict)) = plist::Value::from_file(PRISM_CONFIG_PATH) else {
+        return Vec::new();
+    };
+    dict.get("capture_exclude")
+        .and_then(|v| v.as_array())
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|v| v.as_string())
+                .filter(|id| !id.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
 }
 
 // Define the Host Interface struct locally since coreaudio-sys seems to treat it as opaque or we are having trouble dereferencing it.
@@ -85,664 +386,3584 @@ impl PrismConfig {
 // In coreaudio-sys, this might be available as a constant, but often we need to construct it.
 // For now, we'll use the standard UUID for the driver interface.
 
-const MAX_CLIENTS: usize = 4096; // Increased for Direct Indexing
+const PAIR_RING_FRAMES: usize = 65536; // Frames per lazily allocated pair ring
+const MAX_IO_FRAMES: usize = 4096; // Upper end of kAudioDevicePropertyBufferFrameSizeRange
+const MIN_IO_FRAMES: usize = 16; // Lower end of kAudioDevicePropertyBufferFrameSizeRange
+const MIN_RING_CYCLES: usize = 4; // Every ring holds at least this many of the largest cycles
+const _: () = assert!(PAIR_RING_FRAMES >= MIN_RING_CYCLES * MAX_IO_FRAMES);
+const MAX_OUTPUT_CHANNELS: u32 = 8; // Widest output stream format a host may negotiate
+const ROUTE_RAMP_FRAMES: usize = 480; // Crossfade between pairs on reroute, 10 ms at 48 kHz
+const RAMP_PENDING: u64 = u64::MAX; // ramp_start until the first read after a reroute
+const MAX_MATRIX_ROUTES: usize = 8; // Source-to-bus-channel routes one client's 'mtrx' may hold
+const MAX_LEARNED_ROUTES: usize = 64; // Bundles whose last 'rout' pair the driver remembers
+const SAMPLE_RATE: f64 = 48000.0; // The only nominal rate the device offers
+
+// Floor for the zero timestamp period: 5 ms, so tiny buffers do not cost the HAL
+// thousands of GetZeroTimeStamp wakeups a second
+const MIN_ZERO_TIMESTAMP_PERIOD: u32 = (SAMPLE_RATE / 200.0) as u32;
+const MAX_TIMING_FRAMES: u32 = 16384; // Largest latency or safety offset a scope may report
+const MAX_RATE_PPM: f64 = 1000.0; // Largest clock rate adjustment 'rate' accepts
+const RATE_SMOOTHING: f64 = 0.05; // Share of the remaining rate step taken per zero timestamp
+const READER_IDLE_FRAMES: f64 = 24000.0; // A capture client is idle after half a second without ReadInput
+const AUDIBLE_PEAK: f32 = 1.0e-5; // -100 dBFS; quieter blocks do not count as audio for 'busy'
+const MONO_FOLD_GAIN: f32 = std::f32::consts::FRAC_1_SQRT_2; // -3 dB pan law for L+R folds
 
 pub struct ClientSlot {
     pub client_id: AtomicU32,
     pub channel_offset: AtomicUsize,
     pub pid: AtomicI32,
     pub last_write_time: AtomicU64, // Per-channel timing tracking
-    #[allow(dead_code)]
-    pub slot_active: AtomicBool,
+    pub last_write_frames: AtomicU32, // IO buffer size of the writing client's last cycle
+    pub gain: AtomicU32,              // f32 bits, linear gain applied on ProcessOutput
+    pub agc_gain: AtomicU32,          // f32 bits, prismd's AGC stage applied on top of gain
+    pub pan: AtomicU32,               // f32 bits, -1.0 (left) .. 1.0 (right)
+    pub width: AtomicU32,             // f32 bits, stereo width: 0.0 (mono) .. 2.0, 1.0 unchanged
+    pub mono: AtomicU32,              // MonoFold as an index into MonoFold::ALL
+    pub swap: AtomicBool,             // Left and right exchanged before the rest of the mix
+    pub bundle_hash: AtomicU64,       // bundle_hash() of the client's bundle ID, 0 if unknown
+    pub io_running: AtomicBool,       // Between this client's StartIO and StopIO
+    pub ramp_from: AtomicUsize,       // Channel offset being faded out after a reroute
+    pub ramp_start: AtomicU64,        // Sample time + 1 the reroute fade began; 0 if none
+    pub frozen_offset: AtomicUsize,   // channel_offset as of the routing freeze; heard while frozen
+    pub mirror_offset: AtomicUsize,   // Second pair the client also plays on; 0 if none
+    pub last_read_time: AtomicU64,    // f64 bits, input sample time of the client's last ReadInput
+    pub reads: AtomicU64,             // ReadInput cycles served to this client
+    pub capture_allowed: AtomicBool,  // On the capture allowlist ('capl'); read on the IO path
+    pub capture_mask: AtomicU64,      // Channels it may read ('cmsk'), bit n = channel n + 1
+    pub capture_excluded: AtomicBool, // Bundle excluded from capture; its output is dropped
+    // 'mtrx' routes packed by pack_matrix_route; while matrix_len is non-zero they replace
+    // the channel_offset pair in ReadInput
+    pub matrix: [AtomicU64; MAX_MATRIX_ROUTES],
+    pub matrix_len: AtomicU32,
     // Per-slot small ring buffer for stereo frames (length = buffer_frame_size * 2)
     // Preallocated at driver creation to avoid allocs in IO path.
     pub slot_buffer: Vec<f32>,
 }
 
 impl ClientSlot {
-    fn resize_and_clear_buffer(&mut self, frames_per_buffer: usize) {
-        let required_len = frames_per_buffer.saturating_mul(2);
-        if required_len == 0 {
-            self.slot_buffer.clear();
+    fn new(slot_buf_len: usize) -> Self {
+        Self {
+            client_id: AtomicU32::new(0),
+            channel_offset: AtomicUsize::new(0),
+            pid: AtomicI32::new(0),
+            last_write_time: AtomicU64::new(0),
+            last_write_frames: AtomicU32::new(0),
+            gain: AtomicU32::new(1.0f32.to_bits()),
+            agc_gain: AtomicU32::new(1.0f32.to_bits()),
+            pan: AtomicU32::new(0.0f32.to_bits()),
+            width: AtomicU32::new(1.0f32.to_bits()),
+            mono: AtomicU32::new(0),
+            swap: AtomicBool::new(false),
+            bundle_hash: AtomicU64::new(0),
+            io_running: AtomicBool::new(false),
+            ramp_from: AtomicUsize::new(0),
+            ramp_start: AtomicU64::new(0),
+            frozen_offset: AtomicUsize::new(0),
+            mirror_offset: AtomicUsize::new(0),
+            last_read_time: AtomicU64::new(0),
+            reads: AtomicU64::new(0),
+            capture_allowed: AtomicBool::new(false),
+            capture_mask: AtomicU64::new(u64::MAX),
+            capture_excluded: AtomicBool::new(false),
+            matrix: std::array::from_fn(|_| AtomicU64::new(0)),
+            matrix_len: AtomicU32::new(0),
+            slot_buffer: vec![0.0; slot_buf_len],
+        }
+    }
+
+    // Stop ReadInput mixing this slot, then clear its ring. Readers skip slots whose
+    // last_write_time is 0, so the stale audio is gone from captures before the memory
+    // is zeroed. The next ProcessOutput publishes the slot again.
+    unsafe fn silence(&self) {
+        self.last_write_time.store(0, Ordering::Release);
+        self.ramp_start.store(0, Ordering::Relaxed);
+        let buf_ptr = self.slot_buffer.as_ptr() as *mut f32;
+        ptr::write_bytes(buf_ptr, 0, self.slot_buffer.len());
+    }
+
+    // Watchdog variant of silence(): clears the ring only if no write landed since
+    // `last_write_bits` was read, and only once when several readers see the stall.
+    unsafe fn silence_if_stalled(&self, last_write_bits: u64) -> bool {
+        if self
+            .last_write_time
+            .compare_exchange(last_write_bits, 0, Ordering::AcqRel, Ordering::Relaxed)
+            .is_err()
+        {
+            return false;
+        }
+        self.ramp_start.store(0, Ordering::Relaxed);
+        let buf_ptr = self.slot_buffer.as_ptr() as *mut f32;
+        ptr::write_bytes(buf_ptr, 0, self.slot_buffer.len());
+        true
+    }
+
+    // The pair the IO path plays the slot on: the offset staged by the last reroute, or
+    // the one it had when routing was frozen.
+    #[inline]
+    fn routed_offset(&self, frozen: bool) -> usize {
+        if frozen {
+            self.frozen_offset.load(Ordering::Relaxed)
         } else {
-            if self.slot_buffer.len() != required_len {
-                self.slot_buffer.resize(required_len, 0.0);
-            }
-            for sample in &mut self.slot_buffer {
-                *sample = 0.0;
-            }
+            self.channel_offset.load(Ordering::Relaxed)
         }
     }
-}
 
-fn encode_client_list(driver: &PrismDriver) -> Vec<u8> {
-    let mut array = Vec::new();
+    // Move the slot to `offset`, crossfading from its current pair over the first
+    // ROUTE_RAMP_FRAMES frames captured afterwards. Returns the previous offset. The
+    // client's audio lives in slot_buffer, so the pair it leaves has nothing to clear;
+    // pair rings carry only WriteMix's mix.
+    fn reroute(&self, offset: usize) -> usize {
+        let prev = self.channel_offset.load(Ordering::Acquire);
+        if prev != offset {
+            self.ramp_from.store(prev, Ordering::Relaxed);
+            self.ramp_start.store(RAMP_PENDING, Ordering::Release);
+            self.channel_offset.store(offset, Ordering::Release);
+        }
+        prev
+    }
 
-    for slot in driver.client_slots.iter() {
-        let client_id = slot.client_id.load(Ordering::Acquire);
-        if client_id == 0 {
-            continue;
+    // Pair being faded out and frames into the crossfade at `sample_time`, while a
+    // reroute fade is running. The first read that sees the new offset anchors the fade.
+    #[inline]
+    fn route_ramp(&self, sample_time: f64, channel_offset: usize) -> Option<(usize, usize)> {
+        let now = sample_time as u64 + 1;
+        let mut start = self.ramp_start.load(Ordering::Acquire);
+        if start == RAMP_PENDING {
+            let from = self.ramp_from.load(Ordering::Relaxed);
+            if from == channel_offset {
+                // reroute() has not published the new offset yet
+                return None;
+            }
+            start = match self.ramp_start.compare_exchange(
+                RAMP_PENDING,
+                now,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => now,
+                Err(current) => current,
+            };
         }
-        let pid = slot.pid.load(Ordering::Acquire);
-        let offset = slot.channel_offset.load(Ordering::Acquire) as u32;
+        if start == 0 || start == RAMP_PENDING {
+            return None;
+        }
+        let elapsed = now.saturating_sub(start) as usize;
+        if elapsed >= ROUTE_RAMP_FRAMES {
+            return None;
+        }
+        Some((self.ramp_from.load(Ordering::Relaxed), elapsed))
+    }
 
-        let mut dict = Dictionary::new();
-        dict.insert("client_id".into(), Value::from(i64::from(client_id)));
-        dict.insert("pid".into(), Value::from(pid as i64));
-        dict.insert("channel_offset".into(), Value::from(i64::from(offset)));
+    // Replace the 'mtrx' routes; an empty list returns the client to its channel_offset
+    // pair. Readers see no routes while the new ones are stored, so a cycle never mixes
+    // half of the old matrix with half of the new one.
+    fn set_matrix(&self, routes: &[u64]) {
+        self.matrix_len.store(0, Ordering::Release);
+        let routes = &routes[..routes.len().min(MAX_MATRIX_ROUTES)];
+        for (slot_route, &route) in self.matrix.iter().zip(routes) {
+            slot_route.store(route, Ordering::Relaxed);
+        }
+        self.matrix_len
+            .store(routes.len() as u32, Ordering::Release);
+    }
 
-        array.push(Value::Dictionary(dict));
+    // Current 'mtrx' routes as (source side, bus channel, gain).
+    #[inline]
+    fn matrix_routes(&self) -> impl Iterator<Item = (usize, usize, f32)> + '_ {
+        let len = (self.matrix_len.load(Ordering::Acquire) as usize).min(MAX_MATRIX_ROUTES);
+        self.matrix[..len]
+            .iter()
+            .map(|route| unpack_matrix_route(route.load(Ordering::Relaxed)))
     }
 
-    let value = Value::Array(array);
-    let mut buf = Vec::new();
-    if plist::to_writer_binary(&mut buf, &value).is_err() {
-        buf.clear();
+    // Back to the state a new client starts in. Returns the previous offset.
+    fn unroute(&self) -> usize {
+        self.reset_mix();
+        self.set_matrix(&[]);
+        self.mirror_offset.store(0, Ordering::Relaxed);
+        self.reroute(0)
     }
-    buf
-}
 
-#[repr(C)]
-pub struct PrismDriver {
-    pub _vtable: *const AudioServerPlugInDriverInterface,
-    pub ref_count: AtomicU32,
-    pub host: Option<AudioServerPlugInHostRef>,
-    pub anchor_host_time: AtomicU64,
-    pub num_time_stamps: AtomicU64,
-    pub host_ticks_per_frame: f64,
-    pub client_count: AtomicU32,
-    pub phase: f64,
-    pub loopback_buffer: Vec<f32>,
-    pub config: PrismConfig,
+    fn reset_mix(&self) {
+        self.gain.store(1.0f32.to_bits(), Ordering::Relaxed);
+        self.pan.store(0.0f32.to_bits(), Ordering::Relaxed);
+        self.width.store(1.0f32.to_bits(), Ordering::Relaxed);
+        self.mono.store(0, Ordering::Relaxed);
+        self.swap.store(false, Ordering::Relaxed);
+    }
 
-    // Timing synchronization (like BlackHole)
-    pub last_output_sample_time: AtomicU64, // Tracks when data was last written
-    pub is_buffer_clear: AtomicBool,        // Tracks if buffer has valid data
+    // Apply optional gain/pan/width values from a routing update. NaN means "leave unchanged",
+    // as does a mono fold or swap of None.
+    fn apply_mix(
+        &self,
+        gain: f32,
+        pan: f32,
+        width: f32,
+        mono: Option<MonoFold>,
+        swap: Option<bool>,
+    ) {
+        if let Some(index) = mono.and_then(|mono| MonoFold::ALL.iter().position(|&f| f == mono)) {
+            self.mono.store(index as u32, Ordering::Relaxed);
+        }
+        if let Some(swap) = swap {
+            self.swap.store(swap, Ordering::Relaxed);
+        }
+        if !gain.is_nan() {
+            self.gain.store(gain.to_bits(), Ordering::Relaxed);
+        }
+        if !pan.is_nan() {
+            self.pan.store(pan.to_bits(), Ordering::Relaxed);
+        }
+        if !width.is_nan() {
+            self.width.store(width.to_bits(), Ordering::Relaxed);
+        }
+    }
 
-    // Actual buffer frame size (may differ from config if host uses different size)
-    pub buffer_frame_size_actual: AtomicU32,
+    fn mono_fold(&self) -> MonoFold {
+        MonoFold::ALL
+            .get(self.mono.load(Ordering::Relaxed) as usize)
+            .copied()
+            .unwrap_or_default()
+    }
 
-    // Padding to prevent false sharing between write_pos and read_pos
-    // Cache line size is typically 64 bytes.
-    pub _pad1: [u8; 64],
-    pub write_pos: AtomicUsize,
-    pub _pad2: [u8; 64],
-    pub read_pos: AtomicUsize,
+    // 2x2 matrix [L<-L, L<-R, R<-L, R<-R] combining stereo width with gain and pan.
+    // Width scales the side signal of a mid/side split: 0 folds to mono, 1 passes the
+    // input through, above 1 widens. A swap exchanges the input columns, so it applies
+    // before everything else, and a mono fold then sums both rows at -3 dB into one side
+    // and leaves the other silent.
+    #[inline]
+    fn mix_matrix(&self) -> [f32; 4] {
+        let (gain_l, gain_r) = self.mix_gains();
+        let width = f32::from_bits(self.width.load(Ordering::Relaxed));
+        let direct = (1.0 + width) * 0.5;
+        let cross = (1.0 - width) * 0.5;
+        let mut mix = [
+            gain_l * direct,
+            gain_l * cross,
+            gain_r * cross,
+            gain_r * direct,
+        ];
+        if self.swap.load(Ordering::Relaxed) {
+            mix = [mix[1], mix[0], mix[3], mix[2]];
+        }
+        let folded = [
+            (mix[0] + mix[2]) * MONO_FOLD_GAIN,
+            (mix[1] + mix[3]) * MONO_FOLD_GAIN,
+        ];
+        match self.mono_fold() {
+            MonoFold::Off => mix,
+            MonoFold::Left => [folded[0], folded[1], 0.0, 0.0],
+            MonoFold::Right => [0.0, 0.0, folded[0], folded[1]],
+        }
+    }
 
-    // Fixed size array of client slots for lock-free access in IO path
-    pub client_slots: Vec<ClientSlot>,
-} // The singleton instance of our driver
-static mut DRIVER_INSTANCE: *mut PrismDriver = ptr::null_mut();
+    // Per-channel multipliers derived from gain, the AGC stage and balance-style pan.
+    #[inline]
+    fn mix_gains(&self) -> (f32, f32) {
+        let agc = f32::from_bits(self.agc_gain.load(Ordering::Relaxed));
+        let (left, right) = self.side_gains();
+        (left * agc, right * agc)
+    }
 
-#[allow(deprecated)]
-fn get_host_ticks_per_second() -> f64 {
-    let mut info = libc::mach_timebase_info_data_t { numer: 0, denom: 0 };
-    unsafe {
-        libc::mach_timebase_info(&mut info);
+    // Left and right gains the client was given: gain with balance-style pan, without
+    // the AGC stage.
+    #[inline]
+    fn side_gains(&self) -> (f32, f32) {
+        let gain = f32::from_bits(self.gain.load(Ordering::Relaxed));
+        let pan = f32::from_bits(self.pan.load(Ordering::Relaxed));
+        let left = if pan > 0.0 { 1.0 - pan } else { 1.0 };
+        let right = if pan < 0.0 { 1.0 + pan } else { 1.0 };
+        (gain * left, gain * right)
     }
-    if info.numer == 0 || info.denom == 0 {
-        return 1_000_000_000.0;
+
+    // Set one side's gain (0 left, 1 right) and keep the other, by rewriting gain and pan
+    // so that side_gains() returns both.
+    fn set_side_gain(&self, side: usize, value: f32) {
+        let (mut left, mut right) = self.side_gains();
+        if side == 0 {
+            left = value;
+        } else {
+            right = value;
+        }
+        let gain = left.max(right);
+        let pan = if gain <= 0.0 {
+            0.0
+        } else if left < right {
+            1.0 - left / right
+        } else {
+            right / left - 1.0
+        };
+        self.gain.store(gain.to_bits(), Ordering::Relaxed);
+        self.pan.store(pan.to_bits(), Ordering::Relaxed);
     }
-    // ticks * numer / denom = nanoseconds
-    // 1 sec = 1e9 ns
-    // ticks_per_sec * numer / denom = 1e9
-    // ticks_per_sec = 1e9 * denom / numer
-    1_000_000_000.0 * (info.denom as f64) / (info.numer as f64)
 }
 
-// --- IUnknown Implementation ---
-
-unsafe extern "C" fn query_interface(
-    _self: *mut c_void,
-    _uuid: CFUUIDBytes,
-    _out_interface: *mut *mut c_void,
-) -> HRESULT {
-    // Minimal implementation: We only support IUnknown and the Driver Interface.
-    // For now, just return S_OK and self, assuming the caller asks for the right thing.
-    // UUID check may be required.
-    log_msg(&format!("Prism: QueryInterface called. UUID: {:02X}{:02X}{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
-        _uuid.byte0, _uuid.byte1, _uuid.byte2, _uuid.byte3,
-        _uuid.byte4, _uuid.byte5,
-        _uuid.byte6, _uuid.byte7,
-        _uuid.byte8, _uuid.byte9,
-        _uuid.byte10, _uuid.byte11, _uuid.byte12, _uuid.byte13, _uuid.byte14, _uuid.byte15
-    ));
-    *_out_interface = _self;
-    add_ref(_self);
-    0 // S_OK
+// One 'mtrx' route in a u64: source side (0 = left, 1 = right) in bits 48.., 0-based bus
+// channel in bits 32..48 and the linear gain's f32 bits below.
+fn pack_matrix_route(source: u32, channel: u32, gain: f32) -> u64 {
+    (u64::from(source) << 48) | (u64::from(channel & 0xFFFF) << 32) | u64::from(gain.to_bits())
 }
 
-unsafe extern "C" fn add_ref(_self: *mut c_void) -> ULONG {
-    let driver = _self as *mut PrismDriver;
-    (*driver).ref_count.fetch_add(1, Ordering::Relaxed) + 1
+#[inline]
+fn unpack_matrix_route(route: u64) -> (usize, usize, f32) {
+    (
+        (route >> 48) as usize,
+        ((route >> 32) & 0xFFFF) as usize,
+        f32::from_bits(route as u32),
+    )
 }
 
-unsafe extern "C" fn release(_self: *mut c_void) -> ULONG {
-    let driver = _self as *mut PrismDriver;
-    let count = (*driver).ref_count.fetch_sub(1, Ordering::Relaxed) - 1;
-    if count == 0 {
-        // In a real scenario, we might drop the Box here.
-        // But for a driver that lives as long as the server, we might keep it.
-    }
-    count
+// Longest probe sequence a client may be placed along; bounds the loads the IO
+// path does per lookup.
+const SLOT_PROBE_LIMIT: usize = 8;
+
+// Open-addressed client slot table. A client lives in the first free slot of its
+// probe window, starting at client_id & mask. The driver publishes the table through
+// an AtomicPtr; a larger table is built off the RT path and swapped in when a window
+// is full. Replaced tables are retired, not freed, so an IO
+// cycle that loaded the old pointer keeps working until it finishes.
+// Slots are shared, not copied, between a table and the one that replaces it: a
+// client's slot is the same object in both, so whatever a writer still holding the
+// old table stores lands in the new one too, and a retired table owns no ring of its own.
+pub struct SlotTable {
+    pub mask: usize,
+    pub slots: Vec<Arc<ClientSlot>>,
 }
 
-// --- Driver Interface Implementation (Stubs) ---
+impl SlotTable {
+    fn new(size: usize, slot_buf_len: usize) -> Self {
+        Self {
+            mask: size - 1,
+            slots: (0..size)
+                .map(|_| Arc::new(ClientSlot::new(slot_buf_len)))
+                .collect(),
+        }
+    }
 
-unsafe extern "C" fn initialize(
-    _self: AudioServerPlugInDriverRef,
-    host: AudioServerPlugInHostRef,
-) -> OSStatus {
-    log_msg(&format!(
-        "Prism: Initialize called!!! - ver {} (cust_any=true, rout_any=true)",
-        env!("CARGO_PKG_VERSION")
-    ));
-    let driver = _self as *mut PrismDriver;
-    (*driver).host = Some(host);
+    // A table of `size` slots taking over every slot of `old`: live clients in the first
+    // free slot of their windows, free slots anywhere left. Only the slots added by
+    // growing get a ring of their own.
+    fn regrown(old: &SlotTable, size: usize) -> Self {
+        let mask = size - 1;
+        let mut slots: Vec<Option<Arc<ClientSlot>>> = vec![None; size];
+        let mut spare = Vec::new();
+        for slot in old.slots.iter() {
+            let id = slot.client_id.load(Ordering::Acquire);
+            let idx = (0..SLOT_PROBE_LIMIT.min(size))
+                .map(|step| (id as usize + step) & mask)
+                .find(|idx| slots[*idx].is_none());
+            match idx {
+                Some(idx) if id != 0 => slots[idx] = Some(Arc::clone(slot)),
+                _ => spare.push(Arc::clone(slot)),
+            }
+        }
+        let slot_buf_len = old.slots.first().map_or(0, |slot| slot.slot_buffer.len());
+        let mut spare = spare.into_iter();
+        Self {
+            mask,
+            slots: slots
+                .into_iter()
+                .map(|slot| {
+                    slot.or_else(|| spare.next())
+                        .unwrap_or_else(|| Arc::new(ClientSlot::new(slot_buf_len)))
+                })
+                .collect(),
+        }
+    }
 
-    if let Some(prop_changed) = (*host).PropertiesChanged {
-        // 1. Device List (plugin-level)
-        let addr_dev_list = AudioObjectPropertyAddress {
-            mSelector: kAudioPlugInPropertyDeviceList,
-            mScope: kAudioObjectPropertyScopeGlobal,
-            mElement: kAudioObjectPropertyElementMaster,
-        };
-        prop_changed(host, kAudioObjectPlugInObject, 1, &addr_dev_list);
+    #[inline]
+    fn probe(&self, client_id: u32) -> impl Iterator<Item = usize> + '_ {
+        let home = client_id as usize;
+        (0..SLOT_PROBE_LIMIT.min(self.slots.len())).map(move |step| (home + step) & self.mask)
+    }
 
-        // 2. Custom Property Info (catalog update)
-        let addr_cust = AudioObjectPropertyAddress {
-            mSelector: kAudioObjectPropertyCustomPropertyInfoList,
-            mScope: kAudioObjectPropertyScopeGlobal,
-            mElement: kAudioObjectPropertyElementMaster,
-        };
-        prop_changed(host, kAudioObjectPlugInObject, 1, &addr_cust);
-        prop_changed(host, DEVICE_ID, 1, &addr_cust);
+    // Slot currently owned by `client_id`. Scans the whole window rather than
+    // stopping at an empty slot, so removals need no tombstones.
+    #[inline]
+    fn find(&self, client_id: u32) -> Option<&ClientSlot> {
+        if client_id == 0 {
+            return None;
+        }
+        self.probe(client_id)
+            .map(|idx| &*self.slots[idx])
+            .find(|slot| slot.client_id.load(Ordering::Acquire) == client_id)
+    }
 
-        // 3. Device Name
-        let addr_name = AudioObjectPropertyAddress {
-            mSelector: kAudioDevicePropertyDeviceName,
-            mScope: kAudioObjectPropertyScopeGlobal,
-            mElement: kAudioObjectPropertyElementMaster,
-        };
-        prop_changed(host, DEVICE_ID, 1, &addr_name);
-
-        // 4. Routing Table (include 'rout' as well, just in case)
-        let addr_rout = AudioObjectPropertyAddress {
-            mSelector: kAudioPrismPropertyRoutingTable,
-            mScope: kAudioObjectPropertyScopeGlobal,
-            mElement: kAudioObjectPropertyElementMaster,
-        };
-        prop_changed(host, DEVICE_ID, 1, &addr_rout);
+    // Index to place `client_id` at: its existing slot, else the first free one in
+    // its window. None when the window is full.
+    fn place(&self, client_id: u32) -> Option<usize> {
+        let mut free = None;
+        for idx in self.probe(client_id) {
+            let occupant = self.slots[idx].client_id.load(Ordering::Acquire);
+            if occupant == client_id {
+                return Some(idx);
+            }
+            if occupant == 0 && free.is_none() {
+                free = Some(idx);
+            }
+        }
+        free
+    }
 
-        let addr_clnt = AudioObjectPropertyAddress {
-            mSelector: kAudioPrismPropertyClientList,
-            mScope: kAudioObjectPropertyScopeGlobal,
-            mElement: kAudioObjectPropertyElementMaster,
-        };
-        prop_changed(host, DEVICE_ID, 1, &addr_clnt);
+    fn occupied(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| slot.client_id.load(Ordering::Relaxed) != 0)
+            .count()
+    }
+}
 
-        let addr_owned = AudioObjectPropertyAddress {
-            mSelector: kAudioObjectPropertyOwnedObjects,
-            mScope: kAudioObjectPropertyScopeGlobal,
-            mElement: kAudioObjectPropertyElementMaster,
-        };
-        prop_changed(host, DEVICE_ID, 1, &addr_owned);
+// Counters updated from the IO path and exposed through the 'stat' property.
+// All updates are Relaxed fetch_adds so they stay cheap on the realtime thread.
+pub struct IoStats {
+    pub cycles_served: AtomicU64, // ReadInput cycles delivered to capture clients
+    pub output_cycles: AtomicU64, // ProcessOutput/WriteMix cycles accepted
+    pub wrap_arounds: AtomicU64,  // Ring reads/writes that wrapped past the end
+    pub stale_reads: AtomicU64,   // Slot reads skipped because the ring held only stale data
+    pub overloads: AtomicU64,     // Cycles dropped because frames exceeded the ring capacity
+    pub watchdog_clears: AtomicU64, // Rings zeroed because their writer stopped writing
+    pub late_cycles: AtomicU64,   // IO operations ending over a period after their cycle began
+    pub missed_writes: AtomicU64, // Output writes that ended after their cycle's output time
+    pub frames_written: Vec<AtomicU64>, // Per stereo pair (index = channel_offset / 2)
+    // Slot table metrics (updated off the RT path)
+    pub slot_collisions: AtomicU64,
+    pub slot_table_growths: AtomicU64,
+}
 
-        let addr_streams = AudioObjectPropertyAddress {
-            mSelector: kAudioDevicePropertyStreams,
-            mScope: kAudioObjectPropertyScopeGlobal,
-            mElement: kAudioObjectPropertyElementMaster,
-        };
-        prop_changed(host, DEVICE_ID, 1, &addr_streams);
+impl IoStats {
+    fn new(num_channels: u32) -> Self {
+        let pairs = (num_channels as usize) / 2;
+        Self {
+            cycles_served: AtomicU64::new(0),
+            output_cycles: AtomicU64::new(0),
+            wrap_arounds: AtomicU64::new(0),
+            stale_reads: AtomicU64::new(0),
+            overloads: AtomicU64::new(0),
+            watchdog_clears: AtomicU64::new(0),
+            late_cycles: AtomicU64::new(0),
+            missed_writes: AtomicU64::new(0),
+            frames_written: (0..pairs).map(|_| AtomicU64::new(0)).collect(),
+            slot_collisions: AtomicU64::new(0),
+            slot_table_growths: AtomicU64::new(0),
+        }
     }
 
-    0
+    #[inline]
+    fn add_frames(&self, channel_offset: usize, frames: usize) {
+        if let Some(counter) = self.frames_written.get(channel_offset / 2) {
+            counter.fetch_add(frames as u64, Ordering::Relaxed);
+        }
+    }
 }
 
-unsafe extern "C" fn create_device(
-    _self: AudioServerPlugInDriverRef,
-    _description: CFDictionaryRef,
-    _client_id: *const AudioServerPlugInClientInfo,
-    _out_device_id: *mut AudioObjectID,
-) -> OSStatus {
-    kAudioHardwareUnsupportedOperationError as OSStatus
+// Peak/energy accumulators for one stereo bus. Written only from the IO thread;
+// 'metr' reads take and reset them, so each read covers the interval since the last.
+// Peaks are stored as f32 bits: for non-negative floats the bit patterns order like
+// the values, which lets fetch_max work directly.
+pub struct BusMeter {
+    pub peak: [AtomicU32; 2],
+    pub energy: [AtomicU64; 2], // f64 bits, running sum of squares
+    pub frames: AtomicU64,
+    // Sample time (f64 bits) at the end of the newest block above AUDIBLE_PEAK; 0 if
+    // the pair never carried audio. Not reset by reads, unlike the accumulators
+    pub last_audio: AtomicU64,
 }
 
-unsafe extern "C" fn destroy_device(
-    _self: AudioServerPlugInDriverRef,
-    _device_id: AudioObjectID,
-) -> OSStatus {
-    kAudioHardwareUnsupportedOperationError as OSStatus
-}
+impl BusMeter {
+    fn new() -> Self {
+        Self {
+            peak: [AtomicU32::new(0), AtomicU32::new(0)],
+            energy: [AtomicU64::new(0), AtomicU64::new(0)],
+            frames: AtomicU64::new(0),
+            last_audio: AtomicU64::new(0),
+        }
+    }
 
-unsafe extern "C" fn add_device_client(
-    _self: AudioServerPlugInDriverRef,
-    _device_id: AudioObjectID,
-    _client_id: *const AudioServerPlugInClientInfo,
-) -> OSStatus {
-    let driver = _self as *mut PrismDriver;
-    if !_client_id.is_null() {
-        // Cast to our custom struct to access mBundleID
-        let client_info = &*(_client_id as *const PrismClientInfo);
-        let client_id = client_info.mClientID;
-        let pid = client_info.mProcessID;
+    // Meter an interleaved stereo block, scaled by the per-channel gains applied to it.
+    // Returns the block's own ([peak_l, peak_r], [rms_l, rms_r]) for telemetry.
+    #[inline]
+    unsafe fn accumulate(
+        &self,
+        input: *const f32,
+        frames: usize,
+        gains: (f32, f32),
+    ) -> ([f32; 2], [f32; 2]) {
+        let mut block_peak = [0.0f32; 2];
+        let mut block_rms = [0.0f32; 2];
+        for (ch, gain) in [gains.0, gains.1].into_iter().enumerate() {
+            let (peak, energy) = accelerate::peak_and_energy(input.add(ch), 2, frames);
+            let gain = gain.abs();
+            let energy = energy * gain * gain;
+            block_peak[ch] = peak * gain;
+            if frames > 0 {
+                block_rms[ch] = (energy / frames as f32).sqrt();
+            }
+            self.peak[ch].fetch_max(block_peak[ch].to_bits(), Ordering::Relaxed);
+            // One read-modify-write, so a take() swapping the sum out in between can
+            // neither lose this block nor see it twice
+            let _ = self.energy[ch].fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + f64::from(energy)).to_bits())
+            });
+        }
+        self.frames.fetch_add(frames as u64, Ordering::Relaxed);
+        (block_peak, block_rms)
+    }
 
-        // Direct Indexing for slot
-        let idx = (client_id as usize) & (MAX_CLIENTS - 1);
-        let slots = &(*driver).client_slots;
-        let slot = &slots[idx];
+    // Returns ([peak_l, peak_r], [rms_l, rms_r]) and resets the accumulators.
+    fn take(&self) -> Option<([f32; 2], [f32; 2])> {
+        let frames = self.frames.swap(0, Ordering::Relaxed);
+        let mut peak = [0.0f32; 2];
+        let mut rms = [0.0f32; 2];
+        for ch in 0..2 {
+            peak[ch] = f32::from_bits(self.peak[ch].swap(0, Ordering::Relaxed));
+            let energy = f64::from_bits(self.energy[ch].swap(0, Ordering::Relaxed));
+            if frames > 0 {
+                rms[ch] = (energy / frames as f64).sqrt() as f32;
+            }
+        }
+        if frames == 0 {
+            None
+        } else {
+            Some((peak, rms))
+        }
+    }
+}
 
-        // We default to channel 0 (passthrough) or an explicit unassigned state.
-        // The daemon updates this via SetProperty('rout').
-        let channel_offset = 0;
+fn encode_meters(driver: &PrismDriver) -> Vec<u8> {
+    let mut array = Vec::new();
 
-        log_msg(&format!(
-            "Prism: Client Added. ID={}, PID={}, Slot={}, Default Offset={}",
-            client_id, pid, idx, channel_offset
-        ));
+    for (pair, meter) in driver.bus_meters.iter().enumerate() {
+        let Some((peak, rms)) = meter.take() else {
+            continue;
+        };
 
-        slot.channel_offset.store(channel_offset, Ordering::SeqCst);
-        slot.pid.store(pid, Ordering::SeqCst);
-        slot.client_id.store(client_id, Ordering::Release);
+        let mut dict = Dictionary::new();
+        dict.insert("pair".into(), Value::from(pair as u64));
+        dict.insert("peak_l".into(), Value::from(f64::from(peak[0])));
+        dict.insert("peak_r".into(), Value::from(f64::from(peak[1])));
+        dict.insert("rms_l".into(), Value::from(f64::from(rms[0])));
+        dict.insert("rms_r".into(), Value::from(f64::from(rms[1])));
+        array.push(Value::Dictionary(dict));
+    }
 
-        notify_device_property_changed(driver, kAudioPrismPropertyClientList);
+    let value = Value::Array(array);
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &value).is_err() {
+        buf.clear();
     }
-    0
+    buf
 }
 
-unsafe extern "C" fn remove_device_client(
-    _self: AudioServerPlugInDriverRef,
-    _device_id: AudioObjectID,
-    _client_id: *const AudioServerPlugInClientInfo,
-) -> OSStatus {
-    let driver = _self as *mut PrismDriver;
-    if !_client_id.is_null() {
-        let client_info = &*_client_id;
-        let client_id = client_info.mClientID;
-        let pid = client_info.mProcessID;
+// Capture ACL entry for one stereo pair. When set, only capture clients whose
+// bundle ID matches read the pair; everyone else gets silence on its channels.
+pub struct CaptureRule {
+    pub bundle_hash: AtomicU64,   // 0 = unrestricted; read on the IO path
+    pub bundle_id: Mutex<String>, // For reporting only
+}
 
-        log_msg(&format!(
-            "Prism: Client Removed. ID={}, PID={}",
-            client_id, pid
-        ));
+impl CaptureRule {
+    fn new() -> Self {
+        Self {
+            bundle_hash: AtomicU64::new(0),
+            bundle_id: Mutex::new(String::new()),
+        }
+    }
+}
 
-        let idx = (client_id as usize) & (MAX_CLIENTS - 1);
-        let slots = &(*driver).client_slots;
-        let slot = &slots[idx];
-        let id = slot.client_id.load(Ordering::SeqCst);
+// Device-wide capture allowlist set through 'capl'. While enabled, only clients whose
+// PID or bundle ID is listed read the input stream; everyone else gets silence. Slots
+// carry the verdict in capture_allowed so the IO path never takes the lock.
+// SetPropertyData does not say who is writing, so any process that can set the device's
+// properties may rewrite the list: it keeps cooperating recorders off the bus, and is no
+// defence against a local process that wants to capture.
+#[derive(Default)]
+pub struct CaptureAllowlist {
+    pub bundle_ids: Vec<String>,
+    pub pids: Vec<i32>,
+}
 
-        if id == client_id {
-            // Before clearing the slot, zero any stale audio left in the slot buffer
-            let prev_offset = slot.channel_offset.load(Ordering::Acquire);
-            // Zero per-slot buffer
-            {
-                let slots_ref = &(*driver).client_slots;
-                let idx = (client_id as usize) & (MAX_CLIENTS - 1);
-                let buf_ptr = slots_ref[idx].slot_buffer.as_ptr() as *mut f32;
-                let buf_len = slots_ref[idx].slot_buffer.len();
-                for i in 0..buf_len {
-                    unsafe {
-                        std::ptr::write(buf_ptr.add(i), 0.0);
-                    }
-                }
-            }
-            // Also zero the ring pair if necessary
-            zero_channel_pair(driver, prev_offset);
+impl CaptureAllowlist {
+    // A listed bundle ID also covers its helpers
+    fn allows(&self, pid: i32, bundle_id: &str) -> bool {
+        self.pids.contains(&pid)
+            || (!bundle_id.is_empty()
+                && self
+                    .bundle_ids
+                    .iter()
+                    .any(|allowed| bundle_matches(bundle_id, allowed)))
+    }
+}
 
-            slot.client_id.store(0, Ordering::Release); // Reset to 0
-            slot.channel_offset.store(0, Ordering::Relaxed);
-            slot.pid.store(0, Ordering::Relaxed);
+// FNV-1a over the bundle ID, never 0 so that 0 can mean "no bundle".
+fn bundle_hash(bundle_id: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bundle_id.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash.max(1)
+}
 
-            notify_device_property_changed(driver, kAudioPrismPropertyClientList);
+fn encode_capture_rules(driver: &PrismDriver) -> Vec<u8> {
+    let mut array = Vec::new();
+    for (pair, rule) in driver.capture_acl.iter().enumerate() {
+        if rule.bundle_hash.load(Ordering::Acquire) == 0 {
+            continue;
         }
+        let bundle_id = match rule.bundle_id.lock() {
+            Ok(guard) => guard.clone(),
+            Err(_) => continue,
+        };
+        let mut dict = Dictionary::new();
+        dict.insert("channel_offset".into(), Value::from((pair * 2) as u64));
+        dict.insert("bundle_id".into(), Value::from(bundle_id));
+        array.push(Value::Dictionary(dict));
     }
-    0
+
+    let value = Value::Array(array);
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &value).is_err() {
+        buf.clear();
+    }
+    buf
 }
 
-unsafe extern "C" fn perform_device_configuration_change(
-    _self: AudioServerPlugInDriverRef,
-    _device_id: AudioObjectID,
-    _action: u64,
-    _change_info: *mut c_void,
-) -> OSStatus {
-    0
+// Monitor downmix: { enabled, sends: [{ channel_offset, gain }] } listing non-zero sends.
+fn encode_monitor(driver: &PrismDriver) -> Vec<u8> {
+    let sends = driver
+        .monitor_sends
+        .iter()
+        .enumerate()
+        .filter_map(|(pair, send)| {
+            let gain = f32::from_bits(send.load(Ordering::Relaxed));
+            if gain == 0.0 {
+                return None;
+            }
+            let mut dict = Dictionary::new();
+            dict.insert("channel_offset".into(), Value::from((pair * 2) as u64));
+            dict.insert("gain".into(), Value::from(f64::from(gain)));
+            Some(Value::Dictionary(dict))
+        })
+        .collect();
+    let mut dict = Dictionary::new();
+    dict.insert(
+        "enabled".into(),
+        Value::from(driver.monitor_enabled.load(Ordering::Relaxed)),
+    );
+    dict.insert("sends".into(), Value::Array(sends));
+
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Dictionary(dict)).is_err() {
+        buf.clear();
+    }
+    buf
 }
 
-unsafe extern "C" fn abort_device_configuration_change(
-    _self: AudioServerPlugInDriverRef,
-    _device_id: AudioObjectID,
-    _action: u64,
-    _change_info: *mut c_void,
-) -> OSStatus {
-    0
+// Capture allowlist: { enabled, bundle_ids: [bundle_id], pids: [pid] }.
+fn encode_capture_allowlist(driver: &PrismDriver) -> Vec<u8> {
+    let mut dict = Dictionary::new();
+    dict.insert(
+        "enabled".into(),
+        Value::from(driver.capture_allowlist_enabled.load(Ordering::Relaxed)),
+    );
+    if let Ok(allowlist) = driver.capture_allowlist.lock() {
+        dict.insert(
+            "bundle_ids".into(),
+            Value::Array(
+                allowlist
+                    .bundle_ids
+                    .iter()
+                    .map(|id| Value::from(id.as_str()))
+                    .collect(),
+            ),
+        );
+        dict.insert(
+            "pids".into(),
+            Value::Array(
+                allowlist
+                    .pids
+                    .iter()
+                    .map(|&pid| Value::from(i64::from(pid)))
+                    .collect(),
+            ),
+        );
+    }
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Dictionary(dict)).is_err() {
+        buf.clear();
+    }
+    buf
 }
 
-// Constants
-const DEVICE_ID: AudioObjectID = 2;
-const INPUT_STREAM_ID: AudioObjectID = 3;
-const OUTPUT_STREAM_ID: AudioObjectID = 4;
+// Capture channel masks: [{ pid, mask }], bit n of mask standing for channel n + 1.
+fn encode_capture_masks(driver: &PrismDriver) -> Vec<u8> {
+    let masks = match driver.capture_masks.lock() {
+        Ok(masks) => masks
+            .iter()
+            .map(|(&pid, &mask)| {
+                let mut dict = Dictionary::new();
+                dict.insert("pid".into(), Value::from(i64::from(pid)));
+                dict.insert("mask".into(), Value::from(mask));
+                Value::Dictionary(dict)
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Array(masks)).is_err() {
+        buf.clear();
+    }
+    buf
+}
 
-#[allow(non_upper_case_globals)]
-const kAudioPlugInPropertyDeviceList: AudioObjectPropertySelector = 0x64657623; // 'dev#'
-#[allow(non_upper_case_globals)]
-const kAudioPlugInPropertyResourceBundle: AudioObjectPropertySelector = 0x72737263; // 'rsrc'
-#[allow(non_upper_case_globals)]
-const kAudioPlugInPropertyTranslateUIDToDevice: AudioObjectPropertySelector = 0x75696464; // 'uidd'
-#[allow(non_upper_case_globals)]
-const kAudioObjectPropertyScope: AudioObjectPropertySelector = 0x73636F70; // 'scop'
-#[allow(non_upper_case_globals)]
-const kAudioObjectPropertyElement: AudioObjectPropertySelector = 0x656C656D; // 'elem'
-#[allow(non_upper_case_globals)]
-const kAudioDevicePropertyBufferFrameSize: AudioObjectPropertySelector = 0x6673697A; // 'fsiz'
-#[allow(non_upper_case_globals)]
-const kAudioDevicePropertyBufferFrameSizeRange: AudioObjectPropertySelector = 0x66737A72; // 'fszr'
-#[allow(non_upper_case_globals)]
-const kAudioObjectPropertyControlList: AudioObjectPropertySelector = 0x6374726C; // 'ctrl'
-#[allow(non_upper_case_globals)]
-const kAudioObjectPropertyCustomPropertyInfoList: AudioObjectPropertySelector = 0x63757374; // 'cust'
-#[allow(non_upper_case_globals)]
-const kAudioDevicePropertyStreamsIsSettable: AudioObjectPropertySelector = 0x7369736F; // 'siso'
-#[allow(non_upper_case_globals)]
-const kAudioDevicePropertyClockDomain: AudioObjectPropertySelector = 0x636C6B64; // 'clkd'
-#[allow(non_upper_case_globals)]
-const kAudioDevicePropertyClockSource: AudioObjectPropertySelector = 0x63737263; // 'csrc'
-#[allow(non_upper_case_globals)]
-const kAudioDevicePropertyIsHidden: AudioObjectPropertySelector = 0x6869646E; // 'hidn'
-#[allow(non_upper_case_globals)]
-const kAudioObjectPropertyName: AudioObjectPropertySelector = 0x6C6E616D; // 'lnam'
-#[allow(non_upper_case_globals)]
-const kAudioDevicePropertyRingBufferFrameSize: AudioObjectPropertySelector = 0x72696E67; // 'ring'
-#[allow(non_upper_case_globals)]
-const kAudioPrismPropertyRoutingTable: AudioObjectPropertySelector = 0x726F7574; // 'rout'
-#[allow(non_upper_case_globals)]
-const kAudioPrismPropertyClientList: AudioObjectPropertySelector = 0x636C6E74; // 'clnt'
+// A client moving from one pair to another, as 'revt' reports it. `seq` counts every
+// change since the driver loaded, so a reader can tell whether it missed some.
+#[derive(Clone, Copy)]
+pub struct RouteChange {
+    seq: u64,
+    pid: pid_t,
+    client_id: u32,
+    old_offset: usize,
+    new_offset: usize,
+    // What moved it: route, broadcast, bundle, unroute, assign (joined on an
+    // auto-assigned pair) or learned (joined on the pair its bundle was last routed to)
+    reason: &'static str,
+}
 
-#[repr(C)]
-#[derive(Debug, Clone, Copy)]
-#[allow(non_snake_case)]
-struct PrismRoutingUpdate {
-    pid: i32,
-    channel_offset: u32,
+// Note a change of `slot`'s pair for 'revt'; nothing when it stayed put. Off the RT path
+// only.
+fn record_route_change(
+    driver: &PrismDriver,
+    slot: &ClientSlot,
+    old_offset: usize,
+    new_offset: usize,
+    reason: &'static str,
+) {
+    if old_offset == new_offset {
+        return;
+    }
+    let Ok(mut last) = driver.last_route_change.lock() else {
+        return;
+    };
+    *last = Some(RouteChange {
+        seq: last.map_or(1, |change| change.seq + 1),
+        pid: slot.pid.load(Ordering::Acquire),
+        client_id: slot.client_id.load(Ordering::Acquire),
+        old_offset,
+        new_offset,
+        reason,
+    });
 }
 
-#[repr(C)]
-#[allow(non_snake_case)]
-struct AudioServerPlugInCustomPropertyInfo {
-    mSelector: AudioObjectPropertySelector,
-    mPropertyDataType: AudioObjectPropertySelector,
-    mQualifierDataType: AudioObjectPropertySelector,
+// 'revt': { seq, pid, client_id, old_offset, new_offset, reason } of the last change, or
+// { seq: 0 } before any.
+fn encode_route_change(driver: &PrismDriver) -> Vec<u8> {
+    let change = *driver
+        .last_route_change
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    let mut dict = Dictionary::new();
+    dict.insert(
+        "seq".into(),
+        Value::from(change.map_or(0, |change| change.seq)),
+    );
+    if let Some(change) = change {
+        dict.insert("pid".into(), Value::from(i64::from(change.pid)));
+        dict.insert("client_id".into(), Value::from(u64::from(change.client_id)));
+        dict.insert("old_offset".into(), Value::from(change.old_offset as u64));
+        dict.insert("new_offset".into(), Value::from(change.new_offset as u64));
+        dict.insert("reason".into(), Value::from(change.reason));
+    }
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Dictionary(dict)).is_err() {
+        buf.clear();
+    }
+    buf
 }
 
-#[allow(non_upper_case_globals)]
-unsafe extern "C" fn has_property(
-    _self: AudioServerPlugInDriverRef,
-    object_id: AudioObjectID,
-    _client_process_id: pid_t,
-    _address: *const AudioObjectPropertyAddress,
-) -> Boolean {
-    let address = *_address;
-    let selector = address.mSelector;
+// Excluded bundles from Driver.plist: [bundle_id].
+fn encode_capture_exclusions(driver: &PrismDriver) -> Vec<u8> {
+    let ids = driver
+        .capture_exclusions
+        .iter()
+        .map(|id| Value::from(id.as_str()))
+        .collect();
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Array(ids)).is_err() {
+        buf.clear();
+    }
+    buf
+}
 
-    // Legacy forced-true behavior is not applied.
-    // Evaluate property presence strictly within the match arms.
+// Recompute every client's capture_allowed after the allowlist changed.
+fn refresh_capture_allowed(driver: &PrismDriver) {
+    let Ok(allowlist) = driver.capture_allowlist.lock() else {
+        return;
+    };
+    let bundle_ids = driver.bundle_ids.lock().ok();
+    for slot in driver.slot_table().slots.iter() {
+        if slot.client_id.load(Ordering::Acquire) == 0 {
+            continue;
+        }
+        let hash = slot.bundle_hash.load(Ordering::Relaxed);
+        let bundle_id = bundle_ids
+            .as_ref()
+            .and_then(|ids| ids.get(&hash))
+            .map_or("", String::as_str);
+        slot.capture_allowed.store(
+            allowlist.allows(slot.pid.load(Ordering::Relaxed), bundle_id),
+            Ordering::Release,
+        );
+    }
+}
 
-    let res = match object_id {
-        // --------------------------------------------------------
-        // 1. Plugin Object
-        // --------------------------------------------------------
-        id if id == kAudioObjectPlugInObject => {
-            if selector == kAudioObjectPropertyBaseClass
-                || selector == kAudioObjectPropertyClass
-                || selector == kAudioObjectPropertyOwner
-                || selector == kAudioObjectPropertyManufacturer
-                || selector == kAudioObjectPropertyOwnedObjects
-                || selector == kAudioPlugInPropertyDeviceList
-                || selector == kAudioPlugInPropertyTranslateUIDToDevice
-                || selector == kAudioPlugInPropertyResourceBundle
-                || selector == kAudioObjectPropertyCustomPropertyInfoList
-            {
-                log_msg(&format!(
-                    "Prism: HasProperty Plugin Known. Object: {}, Selector: {}",
-                    object_id, selector
-                ));
-                true
-            } else {
-                log_msg(&format!(
-                    "Prism: HasProperty Plugin Unknown. Object: {}, Selector: {}",
-                    object_id, selector
-                ));
-                false
-            }
-        }
+// Soloed pairs: [channel_offset].
+fn encode_solo(driver: &PrismDriver) -> Vec<u8> {
+    let pairs = driver
+        .bus_solo
+        .iter()
+        .enumerate()
+        .filter(|(_, soloed)| soloed.load(Ordering::Relaxed))
+        .map(|(pair, _)| Value::from((pair * 2) as u64))
+        .collect();
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Array(pairs)).is_err() {
+        buf.clear();
+    }
+    buf
+}
 
-        // --------------------------------------------------------
-        // 2. Device Object (only here allow 'rout' / 'cust')
-        // --------------------------------------------------------
-        DEVICE_ID => {
-            if selector == kAudioObjectPropertyBaseClass ||
-               selector == kAudioObjectPropertyClass ||
-               selector == kAudioObjectPropertyOwner ||
-               selector == kAudioObjectPropertyManufacturer ||
-               selector == kAudioObjectPropertyOwnedObjects ||
-               selector == kAudioObjectPropertyControlList ||
-               selector == kAudioObjectPropertyCustomPropertyInfoList || // 'cust' OK
-               selector == kAudioDevicePropertyStreams ||
-               selector == kAudioDevicePropertyStreamsIsSettable ||
-               selector == kAudioDevicePropertyDeviceUID ||
-               selector == kAudioDevicePropertyModelUID ||
-               selector == kAudioDevicePropertyDeviceName ||
-               selector == kAudioObjectPropertyName ||
-               selector == kAudioDevicePropertyDeviceIsRunning ||
-               selector == kAudioDevicePropertyIsHidden ||
-               selector == kAudioDevicePropertyDeviceCanBeDefaultDevice ||
-               selector == kAudioDevicePropertyDeviceCanBeDefaultSystemDevice ||
-               selector == kAudioDevicePropertySafetyOffset ||
-               selector == kAudioDevicePropertyLatency ||
-               selector == kAudioDevicePropertyDeviceIsAlive ||
-               selector == kAudioDevicePropertyNominalSampleRate ||
-               selector == kAudioDevicePropertyAvailableNominalSampleRates ||
-               selector == kAudioDevicePropertyBufferFrameSize ||
-               selector == kAudioDevicePropertyBufferFrameSizeRange ||
-               selector == kAudioDevicePropertyRingBufferFrameSize ||
-               selector == kAudioDevicePropertyZeroTimeStampPeriod ||
-               selector == kAudioDevicePropertyClockDomain ||
-               selector == kAudioDevicePropertyClockSource ||
-               selector == kAudioDevicePropertyDataSource ||
-               selector == kAudioObjectPropertyScope ||
-               selector == kAudioObjectPropertyElement ||
-               selector == kAudioDevicePropertyBufferFrameSize ||
-               selector == kAudioPrismPropertyRoutingTable ||
-               selector == kAudioPrismPropertyClientList
-            {
-                log_msg(&format!(
-                    "Prism: HasProperty Device Known. Object: {}, Selector: {}",
-                    object_id, selector
-                ));
-                true
-            } else {
-                log_msg(&format!(
-                    "Prism: HasProperty Device Unknown. Object: {}, Selector: {}",
-                    object_id, selector
-                ));
-                false
-            }
-        }
+// Reserved pairs: [{ channel_offset, owner }].
+fn encode_reservations(driver: &PrismDriver) -> Vec<u8> {
+    let reservations = match driver.reservations.lock() {
+        Ok(reservations) => reservations
+            .iter()
+            .map(|(offset, owner)| {
+                let mut dict = Dictionary::new();
+                dict.insert("channel_offset".into(), Value::from(*offset as u64));
+                dict.insert("owner".into(), Value::from(owner.as_str()));
+                Value::Dictionary(dict)
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
 
-        // --------------------------------------------------------
-        // 3. Stream Object (do NOT include 'cust' / 'rout' here)
-        // --------------------------------------------------------
-        INPUT_STREAM_ID | OUTPUT_STREAM_ID => {
-            if selector == kAudioObjectPropertyBaseClass ||
-               selector == kAudioObjectPropertyClass ||
-               selector == kAudioObjectPropertyOwner ||
-               selector == kAudioObjectPropertyControlList ||
-               // (do not include kAudioObjectPropertyCustomPropertyInfoList here)
-               selector == kAudioStreamPropertyDirection ||
-               selector == kAudioStreamPropertyTerminalType ||
-               selector == kAudioStreamPropertyStartingChannel ||
-               selector == kAudioObjectPropertyScope ||
-               selector == kAudioObjectPropertyElement ||
-               // stream formats
-               selector == kAudioStreamPropertyVirtualFormat ||
-               selector == kAudioStreamPropertyPhysicalFormat ||
-               selector == kAudioStreamPropertyPhysicalFormats ||
-               selector == kAudioStreamPropertyAvailableVirtualFormats ||
-               selector == kAudioStreamPropertyAvailablePhysicalFormats
-            {
-                log_msg(&format!(
-                    "Prism: HasProperty Stream Known. Object: {}, Selector: {}",
-                    object_id, selector
-                ));
-                true
-            } else {
-                log_msg(&format!(
-                    "Prism: HasProperty Stream Unknown. Object: {}, Selector: {}",
-                    object_id, selector
-                ));
-                false
-            }
-        }
-        _ => {
-            log_msg(&format!(
-                "Prism: HasProperty Unknown. Object: {}, Selector: {}",
-                object_id, selector
-            ));
-            false
-        }
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Array(reservations)).is_err() {
+        buf.clear();
+    }
+    buf
+}
+
+// Named pairs: [{ channel_offset, label }].
+fn encode_bus_labels(driver: &PrismDriver) -> Vec<u8> {
+    let labels = match driver.bus_labels.lock() {
+        Ok(labels) => labels
+            .iter()
+            .map(|(offset, label)| {
+                let mut dict = Dictionary::new();
+                dict.insert("channel_offset".into(), Value::from(*offset as u64));
+                dict.insert("label".into(), Value::from(label.as_str()));
+                Value::Dictionary(dict)
+            })
+            .collect(),
+        Err(_) => Vec::new(),
     };
 
-    if res {
-        1
-    } else {
-        0
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Array(labels)).is_err() {
+        buf.clear();
     }
+    buf
 }
 
-#[allow(non_upper_case_globals)]
-unsafe extern "C" fn is_property_settable(
-    _self: AudioServerPlugInDriverRef,
-    _object_id: AudioObjectID,
-    _client_process_id: pid_t,
-    _address: *const AudioObjectPropertyAddress,
-    _out_is_settable: *mut Boolean,
-) -> OSStatus {
-    let address = *_address;
-    let selector = address.mSelector;
+// Pairs that hold over gaps: [{ channel_offset, mode, max_hold_ms }]. Pairs not listed
+// zero-fill.
+fn encode_gap_policies(driver: &PrismDriver) -> Vec<u8> {
+    let policies = driver
+        .gap_holds
+        .iter()
+        .enumerate()
+        .filter_map(|(pair, hold)| {
+            let frames = hold.load(Ordering::Relaxed);
+            if frames == 0 {
+                return None;
+            }
+            let mut dict = Dictionary::new();
+            dict.insert("channel_offset".into(), Value::from((pair * 2) as u64));
+            dict.insert("mode".into(), Value::from(GapMode::HoldLast.name()));
+            dict.insert(
+                "max_hold_ms".into(),
+                Value::from((f64::from(frames) * 1000.0 / SAMPLE_RATE).round() as u64),
+            );
+            Some(Value::Dictionary(dict))
+        })
+        .collect();
 
-    log_msg(&format!(
-        "Prism: IsPropertySettable called. Object: {}, Selector: {}",
-        _object_id, selector
-    ));
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Array(policies)).is_err() {
+        buf.clear();
+    }
+    buf
+}
 
-    // Short-circuit: 'rout' is settable everywhere
-    if selector == kAudioPrismPropertyRoutingTable {
-        *_out_is_settable = 1;
-        log_msg("Prism: IsPropertySettable('rout') -> true");
-        return 0;
+// Routing freeze state: { frozen, remaining_ms? }.
+fn encode_routing_freeze(driver: &PrismDriver) -> Vec<u8> {
+    let until = driver.routing_frozen_until.load(Ordering::Acquire);
+    let now = unsafe { libc::mach_absolute_time() };
+    let mut dict = Dictionary::new();
+    dict.insert("frozen".into(), Value::from(until > now));
+    if until > now {
+        let ms = (until - now) as f64 / driver.host_ticks_per_frame * 1000.0 / SAMPLE_RATE;
+        dict.insert("remaining_ms".into(), Value::from(ms.ceil() as u64));
+    }
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Dictionary(dict)).is_err() {
+        buf.clear();
     }
+    buf
+}
 
-    let res = if selector == kAudioPrismPropertyRoutingTable
-        || selector == kAudioDevicePropertyDeviceName
-        || selector == kAudioObjectPropertyName
-        || selector == kAudioDevicePropertyDataSource
-        || selector == kAudioDevicePropertyNominalSampleRate
-        || selector == kAudioDevicePropertyBufferFrameSize
-    {
-        *_out_is_settable = 1;
-        true
-    } else {
-        *_out_is_settable = 0;
-        false
-    };
+// Per-client gain applied in ProcessOutput: [{ pid, client_id, gain, agc }], both linear.
+fn encode_client_gains(driver: &PrismDriver) -> Vec<u8> {
+    let array = driver
+        .slot_table()
+        .slots
+        .iter()
+        .filter(|slot| slot.client_id.load(Ordering::Acquire) != 0)
+        .map(|slot| {
+            let mut dict = Dictionary::new();
+            dict.insert(
+                "pid".into(),
+                Value::from(i64::from(slot.pid.load(Ordering::Relaxed))),
+            );
+            dict.insert(
+                "client_id".into(),
+                Value::from(u64::from(slot.client_id.load(Ordering::Relaxed))),
+            );
+            let gain = f32::from_bits(slot.gain.load(Ordering::Relaxed));
+            dict.insert("gain".into(), Value::from(f64::from(gain)));
+            let agc = f32::from_bits(slot.agc_gain.load(Ordering::Relaxed));
+            dict.insert("agc".into(), Value::from(f64::from(agc)));
+            Value::Dictionary(dict)
+        })
+        .collect();
 
-    log_msg(&format!(
-        "Prism: IsPropertySettable called. Object: {}, Selector: {} -> {}",
-        _object_id, selector, res
-    ));
-    0
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Array(array)).is_err() {
+        buf.clear();
+    }
+    buf
 }
 
-#[allow(non_upper_case_globals)]
-unsafe extern "C" fn get_property_data_size(
-    _self: AudioServerPlugInDriverRef,
-    object_id: AudioObjectID,
-    _client_process_id: pid_t,
-    _address: *const AudioObjectPropertyAddress,
-    _qualifier_data_size: UInt32,
-    _qualifier_data: *const c_void,
-    _out_data_size: *mut UInt32,
-) -> OSStatus {
-    // let driver = _self as *mut PrismDriver; // can be commented out if config access is not required
-    let address = *_address;
-    let selector = address.mSelector;
+// Clients routed by 'mtrx': [{ pid, client_id, routes: [{ source, channel, gain }] }].
+fn encode_matrices(driver: &PrismDriver) -> Vec<u8> {
+    let mut array = Vec::new();
+    for slot in driver.slot_table().slots.iter() {
+        let client_id = slot.client_id.load(Ordering::Acquire);
+        if client_id == 0 || slot.matrix_len.load(Ordering::Acquire) == 0 {
+            continue;
+        }
+        let routes = slot
+            .matrix_routes()
+            .map(|(source, channel, gain)| {
+                let mut route = Dictionary::new();
+                route.insert("source".into(), Value::from(source as u64));
+                route.insert("channel".into(), Value::from(channel as u64));
+                route.insert("gain".into(), Value::from(f64::from(gain)));
+                Value::Dictionary(route)
+            })
+            .collect();
+        let mut dict = Dictionary::new();
+        dict.insert(
+            "pid".into(),
+            Value::from(i64::from(slot.pid.load(Ordering::Relaxed))),
+        );
+        dict.insert("client_id".into(), Value::from(u64::from(client_id)));
+        dict.insert("routes".into(), Value::Array(routes));
+        array.push(Value::Dictionary(dict));
+    }
 
-    // Debug logs: comment out if too verbose
-    // log_msg(&format!("Prism: GetPropertyDataSize called. Object: {}, Selector: {}", object_id, selector));
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Array(array)).is_err() {
+        buf.clear();
+    }
+    buf
+}
 
-    match object_id {
-        // ---------------------------------------------------------------------
-        // 1. Plugin object
-        // ---------------------------------------------------------------------
-        id if id == kAudioObjectPlugInObject => {
-            match selector {
-                kAudioObjectPropertyCustomPropertyInfoList => {
-                    // The plugin itself does not have custom properties
-                    *_out_data_size = 0;
-                }
-                kAudioObjectPropertyBaseClass
-                | kAudioObjectPropertyClass
-                | kAudioObjectPropertyOwner
-                | kAudioPlugInPropertyTranslateUIDToDevice => {
-                    *_out_data_size = std::mem::size_of::<AudioClassID>() as UInt32;
-                }
-                kAudioObjectPropertyManufacturer | kAudioPlugInPropertyResourceBundle => {
-                    *_out_data_size = std::mem::size_of::<CFStringRef>() as UInt32;
-                }
-                kAudioPlugInPropertyDeviceList | kAudioObjectPropertyOwnedObjects => {
-                    *_out_data_size = std::mem::size_of::<AudioObjectID>() as UInt32;
-                }
-                _ => {
-                    return kAudioHardwareUnknownPropertyError as OSStatus;
-                }
-            }
+// Allocated pair rings: which pair, how long, how loud the buffered audio is and how
+// many clients are routed there. Lets a user spot a ring stuck replaying old audio.
+fn encode_buffer_regions(driver: &PrismDriver) -> Vec<u8> {
+    let mut clients = vec![0u64; driver.pair_buffers.len()];
+    for slot in driver.slot_table().slots.iter() {
+        if slot.client_id.load(Ordering::Acquire) == 0 {
+            continue;
         }
+        let pair = slot.channel_offset.load(Ordering::Relaxed) / 2;
+        if let Some(count) = clients.get_mut(pair) {
+            *count += 1;
+        }
+    }
 
-        // ---------------------------------------------------------------------
-        // 2. Device object
-        // ---------------------------------------------------------------------
-        DEVICE_ID => {
-            // Custom property (catalog)
-            if selector == kAudioObjectPropertyCustomPropertyInfoList {
-                // Only the Device has a "custom property list"
-                let size =
-                    (2 * std::mem::size_of::<AudioServerPlugInCustomPropertyInfo>()) as UInt32;
-                *_out_data_size = size;
-                log_msg(&format!("Prism: Device has 'cust', size={}", size));
-                return 0;
-            }
+    let mut array = Vec::new();
+    for (pair, entry) in driver.pair_buffers.iter().enumerate() {
+        let ring = entry.load(Ordering::Acquire);
+        if ring.is_null() {
+            continue;
+        }
+        let (peak, _) =
+            unsafe { accelerate::peak_and_energy(ring, 1, driver.pair_buffer_frames * 2) };
+        let mut dict = Dictionary::new();
+        dict.insert("channel_offset".into(), Value::from((pair * 2) as u64));
+        dict.insert(
+            "frames".into(),
+            Value::from(driver.pair_buffer_frames as u64),
+        );
+        dict.insert("peak".into(), Value::from(f64::from(peak)));
+        dict.insert("clients".into(), Value::from(clients[pair]));
+        array.push(Value::Dictionary(dict));
+    }
 
-            // Custom property (actual data: 'rout')
-            if selector == kAudioPrismPropertyRoutingTable {
-                let size = std::mem::size_of::<PrismRoutingUpdate>() as UInt32;
-                *_out_data_size = size;
-                log_msg(&format!("Prism: Device has 'rout', size={}", size));
-                return 0;
-            } else if selector == kAudioPrismPropertyClientList {
-                *_out_data_size = std::mem::size_of::<CFDataRef>() as UInt32;
-                log_msg("Prism: Device has 'clnt' (CFDataRef)");
-                return 0;
-            }
+    let value = Value::Array(array);
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &value).is_err() {
+        buf.clear();
+    }
+    buf
+}
 
-            // --- Standard properties ---
-            if selector == kAudioObjectPropertyControlList {
-                *_out_data_size = 0;
-            } else if selector == kAudioDevicePropertyStreamsIsSettable
-                || selector == kAudioDevicePropertyClockDomain
-                || selector == kAudioDevicePropertyClockSource
-                || selector == kAudioDevicePropertyDataSource
-                || selector == kAudioObjectPropertyBaseClass
-                || selector == kAudioObjectPropertyClass
-                || selector == kAudioObjectPropertyOwner
+// Reading 'evnt' consumes the pending mask, so only prismd should read it.
+fn encode_pending_events(driver: &PrismDriver) -> Vec<u8> {
+    let events = driver.pending_events.swap(0, Ordering::AcqRel);
+    let mut dict = Dictionary::new();
+    dict.insert("events".into(), Value::from(u64::from(events)));
+
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Dictionary(dict)).is_err() {
+        buf.clear();
+    }
+    buf
+}
+
+fn encode_resync_state(driver: &PrismDriver) -> Vec<u8> {
+    let mut dict = Dictionary::new();
+    dict.insert(
+        "resyncs".into(),
+        Value::from(driver.resyncs.load(Ordering::Relaxed)),
+    );
+    dict.insert(
+        "seed".into(),
+        Value::from(driver.timestamp_seed.load(Ordering::Relaxed)),
+    );
+
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Dictionary(dict)).is_err() {
+        buf.clear();
+    }
+    buf
+}
+
+fn encode_log_level() -> Vec<u8> {
+    let mut dict = Dictionary::new();
+    dict.insert("level".into(), Value::from(oslog::level().name()));
+
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Dictionary(dict)).is_err() {
+        buf.clear();
+    }
+    buf
+}
+
+// Plug-in wide summary for 'pinf': build info, every device and the clients across them.
+fn encode_plugin_info(driver: &PrismDriver) -> Vec<u8> {
+    let clients = driver.client_count.load(Ordering::Relaxed);
+
+    let mut device = Dictionary::new();
+    device.insert("device_id".into(), Value::from(u64::from(DEVICE_ID)));
+    device.insert("uid".into(), Value::from("dev.ichigo.driver.Prism.Device"));
+    device.insert("name".into(), Value::from("Prism"));
+    device.insert(
+        "channels".into(),
+        Value::from(u64::from(driver.config.num_channels)),
+    );
+    device.insert("clients".into(), Value::from(u64::from(clients)));
+    device.insert(
+        "io_running".into(),
+        Value::from(driver.anchor_host_time.load(Ordering::Relaxed) != 0),
+    );
+
+    let mut features = Vec::new();
+    if cfg!(feature = "trace-io") {
+        features.push(Value::from("trace-io"));
+    }
+
+    let mut dict = Dictionary::new();
+    dict.insert("version".into(), Value::from(env!("CARGO_PKG_VERSION")));
+    dict.insert(
+        "build".into(),
+        Value::from(option_env!("PRISM_GIT_HASH").unwrap_or("unknown")),
+    );
+    dict.insert("debug".into(), Value::from(cfg!(debug_assertions)));
+    dict.insert("features".into(), Value::Array(features));
+    dict.insert(
+        "devices".into(),
+        Value::Array(vec![Value::Dictionary(device)]),
+    );
+    dict.insert("total_clients".into(), Value::from(u64::from(clients)));
+
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Dictionary(dict)).is_err() {
+        buf.clear();
+    }
+    buf
+}
+
+// The driver configuration as loaded, with the timing values currently in effect.
+fn encode_config(driver: &PrismDriver) -> Vec<u8> {
+    let config = &driver.config;
+    let load = |frames: &AtomicU32| Value::from(u64::from(frames.load(Ordering::Relaxed)));
+
+    let mut dict = Dictionary::new();
+    dict.insert("buffer_frame_size".into(), load(&driver.buffer_frame_size));
+    dict.insert(
+        "num_channels".into(),
+        Value::from(u64::from(config.num_channels)),
+    );
+    dict.insert(
+        "slot_buffer_frame_size".into(),
+        Value::from(u64::from(config.slot_buffer_frame_size)),
+    );
+    dict.insert(
+        "client_slots".into(),
+        Value::from(u64::from(config.client_slots)),
+    );
+    dict.insert(
+        "max_client_slots".into(),
+        Value::from(u64::from(config.max_client_slots)),
+    );
+    dict.insert(
+        "watchdog_cycles".into(),
+        Value::from(u64::from(config.watchdog_cycles)),
+    );
+    dict.insert(
+        "full_bus_output".into(),
+        Value::from(driver.full_bus_output.load(Ordering::Relaxed)),
+    );
+    dict.insert("clock_domain".into(), load(&driver.clock_domain));
+    dict.insert(
+        "auto_assign".into(),
+        Value::from(driver.assign_policy().name()),
+    );
+    dict.insert(
+        "system_mix_offset".into(),
+        Value::from(driver.system_mix_offset.load(Ordering::Relaxed) as u64),
+    );
+    dict.insert(
+        "system_mix_enabled".into(),
+        Value::from(driver.system_mix_enabled.load(Ordering::Relaxed)),
+    );
+    dict.insert("input_latency".into(), load(&driver.input_latency));
+    dict.insert("output_latency".into(), load(&driver.output_latency));
+    dict.insert(
+        "input_safety_offset".into(),
+        load(&driver.input_safety_offset),
+    );
+    dict.insert(
+        "output_safety_offset".into(),
+        load(&driver.output_safety_offset),
+    );
+
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Dictionary(dict)).is_err() {
+        buf.clear();
+    }
+    buf
+}
+
+fn encode_clock_rate(driver: &PrismDriver) -> Vec<u8> {
+    let target = f64::from_bits(driver.rate_scalar_target.load(Ordering::Relaxed));
+    let scalar = f64::from_bits(driver.rate_scalar.load(Ordering::Relaxed));
+
+    let mut dict = Dictionary::new();
+    dict.insert("ppm".into(), Value::from((target - 1.0) * 1e6));
+    dict.insert("scalar".into(), Value::from(scalar));
+
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Dictionary(dict)).is_err() {
+        buf.clear();
+    }
+    buf
+}
+
+fn encode_io_stats(driver: &PrismDriver) -> Vec<u8> {
+    let stats = &driver.io_stats;
+    let load = |counter: &AtomicU64| Value::from(counter.load(Ordering::Relaxed));
+
+    let mut dict = Dictionary::new();
+    dict.insert("cycles_served".into(), load(&stats.cycles_served));
+    dict.insert("output_cycles".into(), load(&stats.output_cycles));
+    dict.insert("wrap_arounds".into(), load(&stats.wrap_arounds));
+    dict.insert("stale_reads".into(), load(&stats.stale_reads));
+    dict.insert("overloads".into(), load(&stats.overloads));
+    dict.insert("watchdog_clears".into(), load(&stats.watchdog_clears));
+    dict.insert("late_cycles".into(), load(&stats.late_cycles));
+    dict.insert("missed_writes".into(), load(&stats.missed_writes));
+    // The next late cycle is reported to listeners again
+    driver.overload_reported.store(false, Ordering::Release);
+    dict.insert(
+        "frames_written".into(),
+        Value::Array(stats.frames_written.iter().map(load).collect()),
+    );
+
+    let table = driver.slot_table();
+    dict.insert(
+        "slot_table_size".into(),
+        Value::from(table.slots.len() as u64),
+    );
+    dict.insert(
+        "slots_occupied".into(),
+        Value::from(table.occupied() as u64),
+    );
+    dict.insert("slot_collisions".into(), load(&stats.slot_collisions));
+    dict.insert("slot_table_growths".into(), load(&stats.slot_table_growths));
+    dict.insert(
+        "readers".into(),
+        Value::Array(capture_readers(driver, table)),
+    );
+
+    let value = Value::Dictionary(dict);
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &value).is_err() {
+        buf.clear();
+    }
+    buf
+}
+
+// Every bus pair (from 3-4) with who writes to it: [{ channel_offset, writers: [{ pid,
+// client_id, bundle_id?, is_running, mirror? }], reserved_by?, last_audio_ms? }]. mirror
+// marks a client heard here through its mirror_offset. last_audio_ms
+// is how long ago the pair last carried audio, absent if it never did. Matrix-routed
+// clients write to channels rather than a pair and are not listed.
+fn encode_bus_occupancy(driver: &PrismDriver) -> Vec<u8> {
+    let anchor = driver.anchor_host_time.load(Ordering::Acquire);
+    let now = if anchor == 0 {
+        None
+    } else {
+        let elapsed = unsafe { libc::mach_absolute_time() }.saturating_sub(anchor);
+        Some(elapsed as f64 / driver.host_ticks_per_frame)
+    };
+    let reservations = match driver.reservations.lock() {
+        Ok(reservations) => reservations.clone(),
+        Err(_) => BTreeMap::new(),
+    };
+    let bundle_ids = driver.bundle_ids.lock().unwrap_or_else(|e| e.into_inner());
+    let table = driver.slot_table();
+
+    let mut array = Vec::new();
+    for pair in 1..driver.bus_meters.len() {
+        let offset = pair * 2;
+        let writers: Vec<Value> = table
+            .slots
+            .iter()
+            .filter(|slot| {
+                slot.client_id.load(Ordering::Acquire) != 0
+                    && (slot.channel_offset.load(Ordering::Relaxed) == offset
+                        || slot.mirror_offset.load(Ordering::Relaxed) == offset)
+                    && slot.matrix_len.load(Ordering::Acquire) == 0
+            })
+            .map(|slot| {
+                let mut dict = Dictionary::new();
+                dict.insert(
+                    "pid".into(),
+                    Value::from(i64::from(slot.pid.load(Ordering::Relaxed))),
+                );
+                dict.insert(
+                    "client_id".into(),
+                    Value::from(u64::from(slot.client_id.load(Ordering::Relaxed))),
+                );
+                if let Some(bundle_id) = bundle_ids.get(&slot.bundle_hash.load(Ordering::Relaxed)) {
+                    dict.insert("bundle_id".into(), Value::from(bundle_id.as_str()));
+                }
+                dict.insert(
+                    "is_running".into(),
+                    Value::Boolean(slot.io_running.load(Ordering::Relaxed)),
+                );
+                if slot.channel_offset.load(Ordering::Relaxed) != offset {
+                    dict.insert("mirror".into(), Value::Boolean(true));
+                }
+                Value::Dictionary(dict)
+            })
+            .collect();
+
+        let mut dict = Dictionary::new();
+        dict.insert("channel_offset".into(), Value::from(offset as u64));
+        dict.insert("writers".into(), Value::Array(writers));
+        if let Some(owner) = reservations.get(&offset) {
+            dict.insert("reserved_by".into(), Value::from(owner.as_str()));
+        }
+        let last_audio = f64::from_bits(driver.bus_meters[pair].last_audio.load(Ordering::Relaxed));
+        if let Some(now) = now.filter(|_| last_audio > 0.0) {
+            let ago_ms = (now - last_audio).max(0.0) * 1000.0 / SAMPLE_RATE;
+            dict.insert("last_audio_ms".into(), Value::from(ago_ms as u64));
+        }
+        array.push(Value::Dictionary(dict));
+    }
+
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Array(array)).is_err() {
+        buf.clear();
+    }
+    buf
+}
+
+// Clients that have done ReadInput, for 'stat'. A reader counts as active while its
+// IO is running and its last read is within READER_IDLE_FRAMES of the device timeline.
+fn capture_readers(driver: &PrismDriver, table: &SlotTable) -> Vec<Value> {
+    let anchor = driver.anchor_host_time.load(Ordering::Acquire);
+    let now = if anchor == 0 {
+        None
+    } else {
+        let elapsed = unsafe { libc::mach_absolute_time() }.saturating_sub(anchor);
+        Some(elapsed as f64 / driver.host_ticks_per_frame)
+    };
+
+    let mut readers = Vec::new();
+    for slot in table.slots.iter() {
+        let client_id = slot.client_id.load(Ordering::Acquire);
+        let reads = slot.reads.load(Ordering::Relaxed);
+        if client_id == 0 || reads == 0 {
+            continue;
+        }
+        let last_read_time = f64::from_bits(slot.last_read_time.load(Ordering::Relaxed));
+        let active = slot.io_running.load(Ordering::Relaxed)
+            && now.is_some_and(|now| now - last_read_time <= READER_IDLE_FRAMES);
+
+        let mut dict = Dictionary::new();
+        dict.insert("client_id".into(), Value::from(u64::from(client_id)));
+        dict.insert(
+            "pid".into(),
+            Value::from(i64::from(slot.pid.load(Ordering::Relaxed))),
+        );
+        dict.insert("last_read_time".into(), Value::from(last_read_time));
+        dict.insert("reads".into(), Value::from(reads));
+        dict.insert("active".into(), Value::Boolean(active));
+        readers.push(Value::Dictionary(dict));
+    }
+    readers
+}
+
+// Clients a qualified 'clnt' read asks for: those of `pid` and of `bundle_id` (an ID
+// covers its helpers). With both, an entry has to match both.
+#[derive(Default)]
+struct ClientFilter {
+    pid: Option<pid_t>,
+    bundle_id: Option<String>,
+}
+
+impl ClientFilter {
+    // The qualifier of a 'clnt' read: CFData holding a binary plist { pid?, bundle_id? }.
+    // None when it is malformed or names neither.
+    unsafe fn from_qualifier(size: UInt32, data: *const c_void) -> Option<Self> {
+        if size != std::mem::size_of::<CFDataRef>() as UInt32 || data.is_null() {
+            return None;
+        }
+        let data_ref = *(data as *const CFDataRef);
+        if data_ref.is_null() {
+            return None;
+        }
+        let data = CFData::wrap_under_get_rule(data_ref);
+        let dict = Value::from_reader(std::io::Cursor::new(data.bytes()))
+            .ok()?
+            .into_dictionary()?;
+        let pid = match dict.get("pid") {
+            Some(pid) => Some(
+                pid.as_signed_integer()
+                    .and_then(|pid| pid_t::try_from(pid).ok())?,
+            ),
+            None => None,
+        };
+        let bundle_id = match dict.get("bundle_id") {
+            Some(bundle_id) => Some(bundle_id.as_string().filter(|id| !id.is_empty())?),
+            None => None,
+        };
+        (pid.is_some() || bundle_id.is_some()).then(|| Self {
+            pid,
+            bundle_id: bundle_id.map(str::to_string),
+        })
+    }
+
+    fn matches(&self, pid: pid_t, bundle_id: Option<&str>) -> bool {
+        if self.pid.is_some_and(|wanted| wanted != pid) {
+            return false;
+        }
+        match &self.bundle_id {
+            Some(wanted) => bundle_id.is_some_and(|bundle_id| bundle_matches(bundle_id, wanted)),
+            None => true,
+        }
+    }
+}
+
+fn encode_client_list(driver: &PrismDriver, filter: &ClientFilter) -> Vec<u8> {
+    let mut array = Vec::new();
+    let bundle_ids = driver.bundle_ids.lock().unwrap_or_else(|e| e.into_inner());
+
+    for slot in driver.slot_table().slots.iter() {
+        let client_id = slot.client_id.load(Ordering::Acquire);
+        if client_id == 0 {
+            continue;
+        }
+        let pid = slot.pid.load(Ordering::Acquire);
+        let bundle_id = bundle_ids.get(&slot.bundle_hash.load(Ordering::Relaxed));
+        if !filter.matches(pid, bundle_id.map(String::as_str)) {
+            continue;
+        }
+        let offset = slot.channel_offset.load(Ordering::Acquire) as u32;
+        let gain = f32::from_bits(slot.gain.load(Ordering::Relaxed));
+        let pan = f32::from_bits(slot.pan.load(Ordering::Relaxed));
+        let width = f32::from_bits(slot.width.load(Ordering::Relaxed));
+        let is_running = slot.io_running.load(Ordering::Relaxed);
+
+        let mut dict = Dictionary::new();
+        dict.insert("client_id".into(), Value::from(i64::from(client_id)));
+        dict.insert("pid".into(), Value::from(pid as i64));
+        dict.insert("channel_offset".into(), Value::from(i64::from(offset)));
+        dict.insert("gain".into(), Value::from(f64::from(gain)));
+        dict.insert("pan".into(), Value::from(f64::from(pan)));
+        dict.insert("width".into(), Value::from(f64::from(width)));
+        if slot.mono_fold() != MonoFold::Off {
+            dict.insert("mono".into(), Value::from(slot.mono_fold().name()));
+        }
+        if slot.swap.load(Ordering::Relaxed) {
+            dict.insert("swap".into(), Value::Boolean(true));
+        }
+        let mirror = slot.mirror_offset.load(Ordering::Relaxed);
+        if mirror != 0 {
+            dict.insert("mirror_offset".into(), Value::from(mirror as u64));
+        }
+        if slot.capture_excluded.load(Ordering::Relaxed) {
+            dict.insert("capture_excluded".into(), Value::Boolean(true));
+        }
+        dict.insert("is_running".into(), Value::Boolean(is_running));
+        if let Some(bundle_id) = bundle_id {
+            dict.insert("bundle_id".into(), Value::from(bundle_id.as_str()));
+        }
+
+        array.push(Value::Dictionary(dict));
+    }
+
+    let value = Value::Array(array);
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &value).is_err() {
+        buf.clear();
+    }
+    buf
+}
+
+// Connected bundles and where they play: [{ bundle_id, channel_offset, clients }], one
+// entry per bundle and pair.
+fn encode_bundle_routes(driver: &PrismDriver) -> Vec<u8> {
+    let bundle_ids = driver.bundle_ids.lock().unwrap_or_else(|e| e.into_inner());
+    let mut routes: Vec<(&str, u64, u64)> = Vec::new();
+    for slot in driver.slot_table().slots.iter() {
+        if slot.client_id.load(Ordering::Acquire) == 0 {
+            continue;
+        }
+        let Some(bundle_id) = bundle_ids.get(&slot.bundle_hash.load(Ordering::Relaxed)) else {
+            continue;
+        };
+        let offset = slot.channel_offset.load(Ordering::Acquire) as u64;
+        match routes
+            .iter_mut()
+            .find(|(id, route_offset, _)| *id == bundle_id.as_str() && *route_offset == offset)
+        {
+            Some((_, _, clients)) => *clients += 1,
+            None => routes.push((bundle_id.as_str(), offset, 1)),
+        }
+    }
+
+    let array = routes
+        .into_iter()
+        .map(|(bundle_id, offset, clients)| {
+            let mut dict = Dictionary::new();
+            dict.insert("bundle_id".into(), Value::from(bundle_id));
+            dict.insert("channel_offset".into(), Value::from(offset));
+            dict.insert("clients".into(), Value::from(clients));
+            Value::Dictionary(dict)
+        })
+        .collect();
+    let mut buf = Vec::new();
+    if plist::to_writer_binary(&mut buf, &Value::Array(array)).is_err() {
+        buf.clear();
+    }
+    buf
+}
+
+// 'clnt' as CFData, re-encoded only when the client list generation has moved since the
+// last read. The generation is sampled before encoding, so a change that races the
+// encode bumps it again and the next read rebuilds.
+fn client_list_cfdata(driver: &PrismDriver) -> CFData {
+    let generation = driver.client_list_generation.load(Ordering::Acquire);
+    let mut cache = driver
+        .client_list_cache
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if cache.0 != generation {
+        *cache = (
+            generation,
+            encode_client_list(driver, &ClientFilter::default()),
+        );
+    }
+    CFData::from_buffer(&cache.1)
+}
+
+// CF values returned from GetPropertyData follow the Create rule: the HAL owns the
+// reference and releases it. Everything the driver creates is held as an owned
+// core_foundation wrapper until it is handed over here, so an early return can never
+// leak it and nothing is released twice.
+fn into_host_ref<T: TCFType>(value: T) -> T::Ref {
+    let value_ref = value.as_concrete_TypeRef();
+    std::mem::forget(value);
+    value_ref
+}
+
+// CFStringCreateWithCString returns null when it cannot allocate or the bytes are not
+// valid UTF-8; callers turn that into an error status instead of handing the HAL null.
+fn create_cfstring(value: &CStr) -> Option<CFString> {
+    let string_ref =
+        unsafe { CFStringCreateWithCString(ptr::null(), value.as_ptr(), kCFStringEncodingUTF8) };
+    if string_ref.is_null() {
+        log_error(
+            Category::Properties,
+            &format!("Prism: CFStringCreateWithCString failed for {:?}", value),
+        );
+        return None;
+    }
+    Some(unsafe { CFString::wrap_under_create_rule(string_ref as _) })
+}
+
+// Write an owned CFString to a CFStringRef out-pointer, transferring ownership to the HAL.
+unsafe fn write_cfstring(
+    out_data: *mut c_void,
+    out_data_size: *mut UInt32,
+    value: &CStr,
+) -> OSStatus {
+    let Some(string) = create_cfstring(value) else {
+        return kAudioHardwareUnspecifiedError as OSStatus;
+    };
+    *(out_data as *mut CFStringRef) = into_host_ref(string) as CFStringRef;
+    *out_data_size = std::mem::size_of::<CFStringRef>() as UInt32;
+    0
+}
+
+unsafe fn write_cfdata(out_data: *mut c_void, out_data_size: *mut UInt32, data: CFData) {
+    *(out_data as *mut CFDataRef) = into_host_ref(data);
+    *out_data_size = std::mem::size_of::<CFDataRef>() as UInt32;
+}
+
+#[repr(C)]
+pub struct PrismDriver {
+    pub _vtable: *const AudioServerPlugInDriverInterface,
+    pub ref_count: AtomicU32,
+    pub host: Option<AudioServerPlugInHostRef>,
+    pub anchor_host_time: AtomicU64,
+    pub num_time_stamps: AtomicU64,
+    pub timestamp_seed: AtomicU64, // Bumped whenever the timeline is re-anchored
+    // Clock rate relative to the host clock (f64 bits). GetZeroTimeStamp eases
+    // rate_scalar toward rate_scalar_target and restarts the timeline line at
+    // (rate_base_host, rate_base_sample) on each step, so a rate change never moves
+    // timestamps already handed out.
+    pub rate_scalar: AtomicU64,
+    pub rate_scalar_target: AtomicU64,
+    pub rate_base_host: AtomicU64,
+    pub rate_base_sample: AtomicU64, // f64 bits
+    pub resyncs: AtomicU64,
+    pub pending_events: AtomicU32, // PRISM_EVENT_* bits not yet read through 'evnt'
+    pub overload_reported: AtomicBool, // An overload was notified and 'stat' not read since
+    // The IO thread noticed an overload and left notifying it to the notifier thread:
+    // PropertiesChanged may block inside the HAL, so the IO thread never calls it
+    pub overload_pending: AtomicBool,
+    // Thread posting what the IO thread raises, from Initialize until the driver drops
+    notifier: OnceLock<JoinHandle<()>>,
+    notifier_stop: AtomicBool,
+    pub host_ticks_per_frame: f64,
+    pub client_count: AtomicU32,
+    pub phase: f64,
+    // Per-pair stereo rings (index = channel_offset / 2) that WriteMix fills. Entries
+    // start null and are allocated off the RT path: the system mix pair at creation or
+    // when it moves, every pair when bus mode is turned on. Once published a ring is
+    // never freed while the driver is alive, so RT readers only need an Acquire load.
+    pub pair_buffers: Vec<AtomicPtr<f32>>,
+    pub pair_buffer_frames: usize,
+    pub config: PrismConfig,
+
+    // Timing synchronization (like BlackHole)
+    pub last_output_sample_time: AtomicU64, // Tracks when data was last written
+    pub is_buffer_clear: AtomicBool,        // Tracks if buffer has valid data
+
+    // Buffer frame size the device reports, seeded from the config. A requested size waits
+    // in pending_buffer_frame_size (0 = none) until the HAL performs the config change.
+    pub buffer_frame_size: AtomicU32,
+    pub pending_buffer_frame_size: AtomicU32,
+    // Actual buffer frame size (may differ from config if host uses different size)
+    pub buffer_frame_size_actual: AtomicU32,
+    // Frames between zero timestamps; follows the buffer frame size
+    pub zero_timestamp_period: AtomicU32,
+
+    // Negotiated stream layouts: set when a host picks the kAudioFormatFlagIsNonInterleaved
+    // format, in which case the IO buffer holds one channel after another. A requested
+    // format waits in the stream's pending format (channels << 1 | non-interleaved,
+    // 0 = none) until the HAL performs the config change.
+    pub input_non_interleaved: AtomicBool,
+    pub output_non_interleaved: AtomicBool,
+    pub pending_input_format: AtomicU32,
+    pub pending_output_format: AtomicU32,
+    // Channels per frame of the output stream; apps play stereo unless a host asks otherwise
+    pub output_channels: AtomicU32,
+    // Bus mode (PrismConfig::full_bus_output); output_channels is then num_channels.
+    // A switch waits in pending_full_bus_output (0 = none, 1 = off, 2 = on) until the
+    // HAL performs the config change.
+    pub full_bus_output: AtomicBool,
+    pub pending_full_bus_output: AtomicU32,
+    // kAudioDevicePropertyClockDomain, seeded from the config; 'cnfg' changes it
+    pub clock_domain: AtomicU32,
+    // PrismConfig::auto_assign as an index into AssignPolicy::ALL; 'cnfg' changes it
+    pub auto_assign: AtomicU32,
+    // Pair round-robin assignment handed out last
+    pub assign_cursor: AtomicUsize,
+    // PrismConfig::system_mix_offset: the pair WriteMix fills outside bus mode; 'cnfg'
+    // changes it
+    pub system_mix_offset: AtomicUsize,
+    // PrismConfig::system_mix_enabled: off, WriteMix drops the mix; 'cnfg' changes it
+    pub system_mix_enabled: AtomicBool,
+    // Pair each bundle (by bundle_hash) last played on, for sticky-bundle assignment.
+    // Kept after its clients leave so the app finds its pair when it comes back
+    pub bundle_pairs: Mutex<HashMap<u64, usize>>,
+    // Pair the daemon last routed each bundle (by bundle_hash) to via 'rout', least recent
+    // first. A client of a listed bundle joins on that pair, whatever the assignment
+    // policy, so an app that restarts keeps its bus until the daemon routes it again
+    pub learned_routes: Mutex<VecDeque<(u64, usize)>>,
+    // The client that moved pairs last, for 'revt'
+    pub last_route_change: Mutex<Option<RouteChange>>,
+    // kAudioDevicePropertyHogMode owner, -1 when free. Bookkeeping only: IO from other
+    // processes is never refused
+    pub hog_pid: AtomicI32,
+    // Reported latency and safety offset per scope, seeded from the config; 'cnfg' changes them
+    pub input_latency: AtomicU32,
+    pub output_latency: AtomicU32,
+    pub input_safety_offset: AtomicU32,
+    pub output_safety_offset: AtomicU32,
+    // Interleaved staging for IO in another layout (num_channels × MAX_IO_FRAMES). Only
+    // touched from DoIOOperation, which the HAL serializes on the device's IO thread.
+    pub io_scratch: Vec<f32>,
+
+    // Padding to prevent false sharing between write_pos and read_pos
+    // Cache line size is typically 64 bytes.
+    pub _pad1: [u8; 64],
+    pub write_pos: AtomicUsize,
+    pub _pad2: [u8; 64],
+    pub read_pos: AtomicUsize,
+
+    // Current client slot table for lock-free access in IO path
+    pub client_slots: AtomicPtr<SlotTable>,
+    // Tables replaced by growth; kept alive until the driver goes away
+    pub retired_slot_tables: Mutex<Vec<Box<SlotTable>>>,
+
+    pub io_stats: IoStats,
+    pub bus_meters: Vec<BusMeter>, // Per stereo pair (index = channel_offset / 2)
+    // Shared-memory telemetry, absent if the region could not be mapped
+    pub telemetry: Option<&'static TelemetryRegion>,
+    pub capture_acl: Vec<CaptureRule>, // Per stereo pair (index = channel_offset / 2)
+    // Interned bundle IDs keyed by bundle_hash(); slots only carry the hash
+    pub bundle_ids: Mutex<HashMap<u64, String>>,
+    // Bumped on every 'clnt' change; the cache holds the generation it was encoded for
+    pub client_list_generation: AtomicU64,
+    pub client_list_cache: Mutex<(u64, Vec<u8>)>,
+    // Monitor downmix set through 'mnsd': every pair's send (f32 bits, linear) into 1-2.
+    // The driver only keeps it; prismd's bridges from 1-2 fold it into what they play,
+    // so capture clients still read each pair on its own
+    pub monitor_enabled: AtomicBool,
+    pub monitor_sends: Vec<AtomicU32>, // Per stereo pair (index = channel_offset / 2)
+    // Pairs claimed through 'resv', by channel_offset, with the owner's name. Only
+    // property writes read it, never the IO path
+    pub reservations: Mutex<BTreeMap<usize, String>>,
+    // Names given to pairs through 'busn', by channel_offset; channel element names use
+    // them in place of "Bus 3-4"
+    pub bus_labels: Mutex<BTreeMap<usize, String>>,
+    // Gap policy set through 'gapp', per stereo pair (index = channel_offset / 2): the
+    // longest gap in frames ReadInput covers by repeating a client's newest audio, or 0
+    // to zero-fill
+    pub gap_holds: Vec<AtomicU32>,
+    // Pairs soloed through 'solo', per stereo pair (index = channel_offset / 2). While any
+    // is set, ReadInput silences every other pair; routing is left as it is
+    pub bus_solo: Vec<AtomicBool>,
+    // Capture allowlist set through 'capl'. Off by default: every client may capture
+    pub capture_allowlist_enabled: AtomicBool,
+    pub capture_allowlist: Mutex<CaptureAllowlist>,
+    // Channel masks set through 'cmsk', by capture PID: bit n set lets the client read
+    // channel n + 1. Slots copy their process's mask into capture_mask for the IO path
+    pub capture_masks: Mutex<BTreeMap<i32, u64>>,
+    // Bundles whose output never reaches the loopback (Driver.plist capture_exclude).
+    // Fixed once the driver is created
+    pub capture_exclusions: Vec<String>,
+    // Host time a 'frez' routing freeze lapses at, 0 when routing is live. While frozen,
+    // reroutes only stage channel_offset and IO keeps playing each slot's frozen_offset
+    pub routing_frozen_until: AtomicU64,
+}
+
+impl PrismDriver {
+    /// Build a standalone driver instance from `config`. It is not registered as
+    /// the plug-in singleton, has no host until Initialize and maps no telemetry
+    /// region, so it can be constructed and exercised without coreaudiod.
+    pub(crate) fn new(config: PrismConfig) -> Box<PrismDriver> {
+        let host_ticks_per_second = get_host_ticks_per_second();
+        let sample_rate = SAMPLE_RATE; // Must match what we report in GetPropertyData
+        let host_ticks_per_frame = host_ticks_per_second / sample_rate;
+
+        let pair_count = (config.num_channels as usize) / 2;
+
+        // Per-slot stereo ring buffer (configurable size)
+        let slot_buf_len = (config.slot_buffer_frame_size as usize) * 2;
+        let client_slots = Box::new(SlotTable::new(config.client_slots as usize, slot_buf_len));
+
+        let mut driver = Box::new(PrismDriver {
+            _vtable: &raw const DRIVER_VTABLE,
+            ref_count: AtomicU32::new(1),
+            host: None,
+            anchor_host_time: AtomicU64::new(0),
+            num_time_stamps: AtomicU64::new(0),
+            timestamp_seed: AtomicU64::new(1),
+            rate_scalar: AtomicU64::new(1.0f64.to_bits()),
+            rate_scalar_target: AtomicU64::new(1.0f64.to_bits()),
+            rate_base_host: AtomicU64::new(0),
+            rate_base_sample: AtomicU64::new(0.0f64.to_bits()),
+            resyncs: AtomicU64::new(0),
+            pending_events: AtomicU32::new(0),
+            overload_reported: AtomicBool::new(false),
+            overload_pending: AtomicBool::new(false),
+            notifier: OnceLock::new(),
+            notifier_stop: AtomicBool::new(false),
+            host_ticks_per_frame,
+            client_count: AtomicU32::new(0),
+            phase: 0.0,
+            pair_buffers: (0..pair_count)
+                .map(|_| AtomicPtr::new(ptr::null_mut()))
+                .collect(),
+            pair_buffer_frames: PAIR_RING_FRAMES,
+            config,
+            last_output_sample_time: AtomicU64::new(0),
+            is_buffer_clear: AtomicBool::new(true),
+            buffer_frame_size: AtomicU32::new(config.buffer_frame_size),
+            pending_buffer_frame_size: AtomicU32::new(0),
+            buffer_frame_size_actual: AtomicU32::new(config.buffer_frame_size),
+            zero_timestamp_period: AtomicU32::new(zero_timestamp_period_for(
+                config.buffer_frame_size,
+            )),
+            input_non_interleaved: AtomicBool::new(false),
+            output_non_interleaved: AtomicBool::new(false),
+            pending_input_format: AtomicU32::new(0),
+            pending_output_format: AtomicU32::new(0),
+            output_channels: AtomicU32::new(if config.full_bus_output {
+                config.num_channels
+            } else {
+                2
+            }),
+            full_bus_output: AtomicBool::new(config.full_bus_output),
+            pending_full_bus_output: AtomicU32::new(0),
+            clock_domain: AtomicU32::new(config.clock_domain),
+            auto_assign: AtomicU32::new(config.auto_assign as u32),
+            assign_cursor: AtomicUsize::new(0),
+            system_mix_offset: AtomicUsize::new(config.system_mix_offset as usize),
+            system_mix_enabled: AtomicBool::new(config.system_mix_enabled),
+            bundle_pairs: Mutex::new(HashMap::new()),
+            learned_routes: Mutex::new(VecDeque::new()),
+            last_route_change: Mutex::new(None),
+            hog_pid: AtomicI32::new(-1),
+            input_latency: AtomicU32::new(config.input_latency),
+            output_latency: AtomicU32::new(config.output_latency),
+            input_safety_offset: AtomicU32::new(config.input_safety_offset),
+            output_safety_offset: AtomicU32::new(config.output_safety_offset),
+            io_scratch: vec![0.0; config.num_channels.max(2) as usize * MAX_IO_FRAMES],
+            _pad1: [0; 64],
+            write_pos: AtomicUsize::new(0),
+            _pad2: [0; 64],
+            read_pos: AtomicUsize::new(0),
+            client_slots: AtomicPtr::new(Box::into_raw(client_slots)),
+            retired_slot_tables: Mutex::new(Vec::new()),
+            io_stats: IoStats::new(config.num_channels),
+            bus_meters: (0..pair_count).map(|_| BusMeter::new()).collect(),
+            telemetry: None,
+            capture_acl: (0..pair_count).map(|_| CaptureRule::new()).collect(),
+            bundle_ids: Mutex::new(HashMap::new()),
+            client_list_generation: AtomicU64::new(1),
+            client_list_cache: Mutex::new((0, Vec::new())),
+            monitor_enabled: AtomicBool::new(false),
+            monitor_sends: (0..pair_count).map(|_| AtomicU32::new(0)).collect(),
+            gap_holds: (0..pair_count).map(|_| AtomicU32::new(0)).collect(),
+            bus_solo: (0..pair_count).map(|_| AtomicBool::new(false)).collect(),
+            capture_allowlist_enabled: AtomicBool::new(false),
+            capture_allowlist: Mutex::new(CaptureAllowlist::default()),
+            capture_masks: Mutex::new(BTreeMap::new()),
+            capture_exclusions: Vec::new(),
+            routing_frozen_until: AtomicU64::new(0),
+            reservations: Mutex::new(BTreeMap::new()),
+            bus_labels: Mutex::new(BTreeMap::new()),
+        });
+        // Rings only for the pairs WriteMix fills: the system mix pair, and in bus mode
+        // every pair. Client audio lives in the slot rings.
+        unsafe {
+            if config.full_bus_output {
+                for pair in 0..pair_count {
+                    ensure_pair_buffer(&mut *driver, pair * 2);
+                }
+            }
+            ensure_pair_buffer(&mut *driver, config.system_mix_offset as usize);
+        }
+        driver
+    }
+
+    #[inline]
+    fn slot_table(&self) -> &SlotTable {
+        unsafe { &*self.client_slots.load(Ordering::Acquire) }
+    }
+
+    fn assign_policy(&self) -> AssignPolicy {
+        AssignPolicy::ALL
+            .get(self.auto_assign.load(Ordering::Relaxed) as usize)
+            .copied()
+            .unwrap_or(AssignPolicy::FirstFree)
+    }
+
+    // Whether a 'frez' routing freeze is in force. A freeze nobody thaws lapses by itself.
+    #[inline]
+    fn routing_frozen(&self) -> bool {
+        let until = self.routing_frozen_until.load(Ordering::Acquire);
+        until != 0 && unsafe { libc::mach_absolute_time() } < until
+    }
+
+    // Latency or safety offset (by selector) of a scope. The global and wildcard scopes
+    // report the output side, which is what a playback-only app sees.
+    fn timing_frames(
+        &self,
+        selector: AudioObjectPropertySelector,
+        scope: AudioObjectPropertyScope,
+    ) -> &AtomicU32 {
+        let input = scope == kAudioObjectPropertyScopeInput;
+        match (selector == kAudioDevicePropertyLatency, input) {
+            (true, true) => &self.input_latency,
+            (true, false) => &self.output_latency,
+            (false, true) => &self.input_safety_offset,
+            (false, false) => &self.output_safety_offset,
+        }
+    }
+
+    // Layout flag of a stream object; None for any other object.
+    fn non_interleaved_flag(&self, stream_id: AudioObjectID) -> Option<&AtomicBool> {
+        match stream_id {
+            INPUT_STREAM_ID => Some(&self.input_non_interleaved),
+            OUTPUT_STREAM_ID => Some(&self.output_non_interleaved),
+            _ => None,
+        }
+    }
+
+    // Format change waiting for the HAL on a stream object; None for any other object.
+    fn pending_format(&self, stream_id: AudioObjectID) -> Option<&AtomicU32> {
+        match stream_id {
+            INPUT_STREAM_ID => Some(&self.pending_input_format),
+            OUTPUT_STREAM_ID => Some(&self.pending_output_format),
+            _ => None,
+        }
+    }
+
+    fn stream_channels(&self, stream_id: AudioObjectID) -> u32 {
+        if stream_id == OUTPUT_STREAM_ID {
+            self.output_channels.load(Ordering::Relaxed)
+        } else {
+            self.config.num_channels
+        }
+    }
+}
+
+// 48 kHz float32 with `channels` channels, either packed interleaved or one buffer per
+// channel. These are the only formats the streams offer.
+fn stream_format(channels: u32, non_interleaved: bool) -> AudioStreamBasicDescription {
+    let (flags, bytes_per_frame) = if non_interleaved {
+        (
+            kAudioFormatFlagIsFloat | kAudioFormatFlagIsPacked | kAudioFormatFlagIsNonInterleaved,
+            4,
+        )
+    } else {
+        (
+            kAudioFormatFlagIsFloat | kAudioFormatFlagIsPacked,
+            4 * channels,
+        )
+    };
+    AudioStreamBasicDescription {
+        mSampleRate: 48000.0,
+        mFormatID: kAudioFormatLinearPCM,
+        mFormatFlags: flags,
+        mBytesPerPacket: bytes_per_frame,
+        mFramesPerPacket: 1,
+        mBytesPerFrame: bytes_per_frame,
+        mChannelsPerFrame: channels,
+        mBitsPerChannel: 32,
+        mReserved: 0,
+    }
+}
+
+// ProcessOutput and WriteMix work on stereo interleaved frames. Returns the host buffer
+// itself when the output stream is negotiated that way; otherwise stages channels 1 and
+// 2 (channel 1 twice for mono, further channels dropped) in io_scratch. None if `frames`
+// exceeds MAX_IO_FRAMES.
+unsafe fn stereo_output_frames(
+    driver: *mut PrismDriver,
+    buffer: *const f32,
+    frames: usize,
+) -> Option<*const f32> {
+    let channels = (*driver).output_channels.load(Ordering::Relaxed).max(1) as usize;
+    let non_interleaved = (*driver).output_non_interleaved.load(Ordering::Relaxed);
+    if channels == 2 && !non_interleaved {
+        return Some(buffer);
+    }
+    if frames > MAX_IO_FRAMES {
+        return None;
+    }
+    // Distance between consecutive frames of one channel, and between channels
+    let (frame_stride, channel_stride) = if non_interleaved {
+        (1, frames)
+    } else {
+        (channels, 1)
+    };
+    let scratch = (*driver).io_scratch.as_ptr() as *mut f32;
+    for (dst, src) in [0, 1.min(channels - 1)].into_iter().enumerate() {
+        accelerate::copy_strided(
+            buffer.add(src * channel_stride),
+            frame_stride as isize,
+            scratch.add(dst),
+            2,
+            frames,
+        );
+    }
+    Some(scratch)
+}
+
+// Spread interleaved `src` into the per-channel runs of a non-interleaved buffer.
+unsafe fn deinterleave(src: *const f32, dst: *mut f32, channels: usize, frames: usize) {
+    for ch in 0..channels {
+        accelerate::copy_strided(
+            src.add(ch),
+            channels as isize,
+            dst.add(ch * frames),
+            1,
+            frames,
+        );
+    }
+}
+
+impl Drop for PrismDriver {
+    // Only runs from release() once the host holds no more references, so no IO
+    // or property call can still be looking at the rings or the slot table.
+    fn drop(&mut self) {
+        if let Some(notifier) = self.notifier.take() {
+            self.notifier_stop.store(true, Ordering::Release);
+            notifier.thread().unpark();
+            let _ = notifier.join();
+        }
+
+        let ring_len = self.pair_buffer_frames * 2;
+        for entry in self.pair_buffers.iter() {
+            let ring = entry.swap(ptr::null_mut(), Ordering::AcqRel);
+            if !ring.is_null() {
+                unsafe {
+                    drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ring, ring_len)));
+                }
+            }
+        }
+
+        let table = self.client_slots.swap(ptr::null_mut(), Ordering::AcqRel);
+        if !table.is_null() {
+            unsafe {
+                drop(Box::from_raw(table));
+            }
+        }
+
+        if let Some(region) = self.telemetry.take() {
+            unsafe {
+                region.unmap();
+            }
+        }
+        self.host = None;
+    }
+}
+
+// The singleton instance handed to coreaudiod; null until create_driver() runs
+// and again after the last reference is released.
+static DRIVER_INSTANCE: AtomicPtr<PrismDriver> = AtomicPtr::new(ptr::null_mut());
+// Held across create_driver() and destroy_driver(), so a create can neither take a
+// reference on an instance being torn down nor map telemetry while one is unmapping.
+static DRIVER_LIFECYCLE: Mutex<()> = Mutex::new(());
+
+#[allow(deprecated)]
+fn get_host_ticks_per_second() -> f64 {
+    let mut info = libc::mach_timebase_info_data_t { numer: 0, denom: 0 };
+    unsafe {
+        libc::mach_timebase_info(&mut info);
+    }
+    if info.numer == 0 || info.denom == 0 {
+        return 1_000_000_000.0;
+    }
+    // ticks * numer / denom = nanoseconds
+    // 1 sec = 1e9 ns
+    // ticks_per_sec * numer / denom = 1e9
+    // ticks_per_sec = 1e9 * denom / numer
+    1_000_000_000.0 * (info.denom as f64) / (info.numer as f64)
+}
+
+// --- IUnknown Implementation ---
+
+unsafe extern "C" fn query_interface(
+    _self: *mut c_void,
+    _uuid: CFUUIDBytes,
+    _out_interface: *mut *mut c_void,
+) -> HRESULT {
+    // Minimal implementation: We only support IUnknown and the Driver Interface.
+    // For now, just return S_OK and self, assuming the caller asks for the right thing.
+    // UUID check may be required.
+    log_msg(Category::Lifecycle, &format!("Prism: QueryInterface called. UUID: {:02X}{:02X}{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
+        _uuid.byte0, _uuid.byte1, _uuid.byte2, _uuid.byte3,
+        _uuid.byte4, _uuid.byte5,
+        _uuid.byte6, _uuid.byte7,
+        _uuid.byte8, _uuid.byte9,
+        _uuid.byte10, _uuid.byte11, _uuid.byte12, _uuid.byte13, _uuid.byte14, _uuid.byte15
+    ));
+    *_out_interface = _self;
+    add_ref(_self);
+    0 // S_OK
+}
+
+unsafe extern "C" fn add_ref(_self: *mut c_void) -> ULONG {
+    let driver = _self as *mut PrismDriver;
+    (*driver).ref_count.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+unsafe extern "C" fn release(_self: *mut c_void) -> ULONG {
+    let driver = _self as *mut PrismDriver;
+    let prev = (*driver)
+        .ref_count
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+            count.checked_sub(1)
+        });
+    match prev {
+        Ok(1) => {
+            destroy_driver(driver);
+            0
+        }
+        Ok(count) => count - 1,
+        Err(_) => {
+            log_msg(
+                Category::Lifecycle,
+                "Prism: Release called with no outstanding references",
+            );
+            0
+        }
+    }
+}
+
+// Tear down the singleton after the last reference is gone so a later
+// create_driver() (coreaudiod reloading the plug-in) starts from a clean slate.
+unsafe fn destroy_driver(driver: *mut PrismDriver) {
+    let _lifecycle = DRIVER_LIFECYCLE.lock().unwrap_or_else(|e| e.into_inner());
+    // A create_driver() that got the lock first may have handed the instance out
+    // again, and a second release to zero then finds it already destroyed
+    if DRIVER_INSTANCE.load(Ordering::Acquire) != driver
+        || (*driver).ref_count.load(Ordering::Acquire) != 0
+    {
+        return;
+    }
+    log_msg(
+        Category::Lifecycle,
+        "Prism: Last reference released, destroying driver",
+    );
+    DRIVER_INSTANCE.store(ptr::null_mut(), Ordering::Release);
+    drop(Box::from_raw(driver));
+}
+
+// --- Driver Interface Implementation (Stubs) ---
+
+unsafe extern "C" fn initialize(
+    _self: AudioServerPlugInDriverRef,
+    host: AudioServerPlugInHostRef,
+) -> OSStatus {
+    log_msg(
+        Category::Lifecycle,
+        &format!(
+            "Prism: Initialize called!!! - ver {} (cust_any=true, rout_any=true)",
+            env!("CARGO_PKG_VERSION")
+        ),
+    );
+    let driver = _self as *mut PrismDriver;
+    (*driver).host = Some(host);
+    start_notifier(driver);
+
+    if let Some(prop_changed) = (*host).PropertiesChanged {
+        // 1. Device List (plugin-level)
+        let addr_dev_list = AudioObjectPropertyAddress {
+            mSelector: kAudioPlugInPropertyDeviceList,
+            mScope: kAudioObjectPropertyScopeGlobal,
+            mElement: kAudioObjectPropertyElementMain,
+        };
+        prop_changed(host, kAudioObjectPlugInObject, 1, &addr_dev_list);
+
+        // 2. Custom Property Info (catalog update)
+        let addr_cust = AudioObjectPropertyAddress {
+            mSelector: kAudioObjectPropertyCustomPropertyInfoList,
+            mScope: kAudioObjectPropertyScopeGlobal,
+            mElement: kAudioObjectPropertyElementMain,
+        };
+        prop_changed(host, kAudioObjectPlugInObject, 1, &addr_cust);
+        prop_changed(host, DEVICE_ID, 1, &addr_cust);
+
+        // 3. Device Name
+        let addr_name = AudioObjectPropertyAddress {
+            mSelector: kAudioDevicePropertyDeviceName,
+            mScope: kAudioObjectPropertyScopeGlobal,
+            mElement: kAudioObjectPropertyElementMain,
+        };
+        prop_changed(host, DEVICE_ID, 1, &addr_name);
+
+        // 4. Routing Table (include 'rout' as well, just in case)
+        let addr_rout = AudioObjectPropertyAddress {
+            mSelector: kAudioPrismPropertyRoutingTable,
+            mScope: kAudioObjectPropertyScopeGlobal,
+            mElement: kAudioObjectPropertyElementMain,
+        };
+        prop_changed(host, DEVICE_ID, 1, &addr_rout);
+
+        let addr_clnt = AudioObjectPropertyAddress {
+            mSelector: kAudioPrismPropertyClientList,
+            mScope: kAudioObjectPropertyScopeGlobal,
+            mElement: kAudioObjectPropertyElementMain,
+        };
+        prop_changed(host, DEVICE_ID, 1, &addr_clnt);
+
+        let addr_owned = AudioObjectPropertyAddress {
+            mSelector: kAudioObjectPropertyOwnedObjects,
+            mScope: kAudioObjectPropertyScopeGlobal,
+            mElement: kAudioObjectPropertyElementMain,
+        };
+        prop_changed(host, DEVICE_ID, 1, &addr_owned);
+
+        let addr_streams = AudioObjectPropertyAddress {
+            mSelector: kAudioDevicePropertyStreams,
+            mScope: kAudioObjectPropertyScopeGlobal,
+            mElement: kAudioObjectPropertyElementMain,
+        };
+        prop_changed(host, DEVICE_ID, 1, &addr_streams);
+    }
+
+    0
+}
+
+unsafe extern "C" fn create_device(
+    _self: AudioServerPlugInDriverRef,
+    _description: CFDictionaryRef,
+    _client_id: *const AudioServerPlugInClientInfo,
+    _out_device_id: *mut AudioObjectID,
+) -> OSStatus {
+    kAudioHardwareUnsupportedOperationError as OSStatus
+}
+
+unsafe extern "C" fn destroy_device(
+    _self: AudioServerPlugInDriverRef,
+    _device_id: AudioObjectID,
+) -> OSStatus {
+    kAudioHardwareUnsupportedOperationError as OSStatus
+}
+
+unsafe extern "C" fn add_device_client(
+    _self: AudioServerPlugInDriverRef,
+    _device_id: AudioObjectID,
+    _client_id: *const AudioServerPlugInClientInfo,
+) -> OSStatus {
+    let driver = _self as *mut PrismDriver;
+    if !_client_id.is_null() {
+        // Cast to our custom struct to access mBundleID
+        let client_info = &*(_client_id as *const PrismClientInfo);
+        let client_id = client_info.mClientID;
+        let pid = client_info.mProcessID;
+        let bundle_id = if client_info.mBundleID.is_null() {
+            String::new()
+        } else {
+            CFString::wrap_under_get_rule(client_info.mBundleID as _).to_string()
+        };
+
+        // Probe for a slot; grow the table if the client's whole window is taken
+        let claims = SLOT_CLAIMS.lock().unwrap_or_else(|e| e.into_inner());
+        let mut table = (*driver).slot_table();
+        let home = table.probe(client_id).next().unwrap_or(0);
+        let occupant = table.slots[home].client_id.load(Ordering::Acquire);
+        if occupant != 0 && occupant != client_id {
+            (*driver)
+                .io_stats
+                .slot_collisions
+                .fetch_add(1, Ordering::Relaxed);
+        }
+        let mut placed = table.place(client_id);
+        if placed.is_none() && grow_slot_table(driver, client_id) {
+            table = (*driver).slot_table();
+            placed = table.place(client_id);
+        }
+        let Some(idx) = placed else {
+            // Leave the client unrouted rather than overwrite another client's slot
+            log_msg(
+                Category::Lifecycle,
+                &format!(
+                    "Prism: No free slot for client {} (PID {}), table at max size {}",
+                    client_id,
+                    pid,
+                    table.slots.len()
+                ),
+            );
+            return 0;
+        };
+        let slot = &table.slots[idx];
+
+        let hash = if bundle_id.is_empty() {
+            0
+        } else {
+            let hash = bundle_hash(&bundle_id);
+            if let Ok(mut bundle_ids) = (*driver).bundle_ids.lock() {
+                bundle_ids.entry(hash).or_insert(bundle_id.clone());
+            }
+            hash
+        };
+
+        let excluded = !bundle_id.is_empty()
+            && (*driver)
+                .capture_exclusions
+                .iter()
+                .any(|excluded| bundle_matches(&bundle_id, excluded));
+        if excluded {
+            log_msg(
+                Category::Lifecycle,
+                &format!(
+                    "Prism: Client {} ({}) is excluded from capture; its output is dropped",
+                    client_id, bundle_id
+                ),
+            );
+        }
+
+        // The pair the daemon last routed the bundle to, else a pair of its own when
+        // auto-assignment is on, else channel 0 (passthrough) until the daemon routes it
+        // via SetProperty('rout')
+        let learned = learned_route(driver, hash);
+        let channel_offset =
+            learned.unwrap_or_else(|| auto_assign_offset(driver, table, pid, hash));
+
+        log_msg(
+            Category::Lifecycle,
+            &format!(
+                "Prism: Client Added. ID={}, PID={}, Bundle={}, Slot={}, Default Offset={}",
+                client_id, pid, bundle_id, idx, channel_offset
+            ),
+        );
+
+        slot.channel_offset.store(channel_offset, Ordering::SeqCst);
+        slot.frozen_offset.store(channel_offset, Ordering::Relaxed);
+        slot.mirror_offset.store(0, Ordering::Relaxed);
+        slot.reset_mix();
+        slot.agc_gain.store(1.0f32.to_bits(), Ordering::Relaxed);
+        slot.set_matrix(&[]);
+        slot.bundle_hash.store(hash, Ordering::Relaxed);
+        slot.io_running.store(false, Ordering::Relaxed);
+        slot.ramp_start.store(0, Ordering::Relaxed);
+        slot.last_read_time.store(0, Ordering::Relaxed);
+        slot.reads.store(0, Ordering::Relaxed);
+        slot.capture_allowed.store(
+            (*driver)
+                .capture_allowlist
+                .lock()
+                .is_ok_and(|allowlist| allowlist.allows(pid, &bundle_id)),
+            Ordering::Relaxed,
+        );
+        slot.capture_excluded.store(excluded, Ordering::Relaxed);
+        slot.capture_mask.store(
+            (*driver)
+                .capture_masks
+                .lock()
+                .ok()
+                .and_then(|masks| masks.get(&pid).copied())
+                .unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+        slot.pid.store(pid, Ordering::SeqCst);
+        slot.client_id.store(client_id, Ordering::Release);
+        drop(claims);
+        let reason = if learned.is_some() {
+            "learned"
+        } else {
+            "assign"
+        };
+        record_route_change(&*driver, slot, 0, channel_offset, reason);
+
+        let events = if channel_offset != 0 {
+            PRISM_EVENT_CLIENTS | PRISM_EVENT_ROUTING
+        } else {
+            PRISM_EVENT_CLIENTS
+        };
+        notify_device_event(driver, kAudioPrismPropertyClientList, events);
+        if channel_offset != 0 {
+            notify_channel_map_changed(driver, false);
+        }
+    }
+    0
+}
+
+// Freeze routing for `timeout_ms`, or thaw it with None. Freezing snapshots each slot's
+// offset for the IO path to keep playing; thawing starts a reroute crossfade from the
+// snapshot for every slot whose staged offset moved, so the whole batch switches in the
+// same cycle.
+fn freeze_routing(driver: &PrismDriver, timeout_ms: Option<u64>) {
+    let table = driver.slot_table();
+    match timeout_ms {
+        Some(timeout_ms) => {
+            if !driver.routing_frozen() {
+                for slot in table.slots.iter() {
+                    slot.frozen_offset.store(
+                        slot.channel_offset.load(Ordering::Acquire),
+                        Ordering::Relaxed,
+                    );
+                }
+            }
+            let ticks = timeout_ms as f64 / 1000.0 * SAMPLE_RATE * driver.host_ticks_per_frame;
+            let until = unsafe { libc::mach_absolute_time() } + ticks as u64;
+            driver.routing_frozen_until.store(until, Ordering::Release);
+            log_msg(
+                Category::Routing,
+                &format!("Prism: FREZ routing frozen for {} ms", timeout_ms),
+            );
+        }
+        None => {
+            if driver.routing_frozen() {
+                let mut moved = 0;
+                for slot in table.slots.iter() {
+                    let from = slot.frozen_offset.load(Ordering::Relaxed);
+                    if slot.client_id.load(Ordering::Acquire) == 0 {
+                        continue;
+                    }
+                    if slot.channel_offset.load(Ordering::Acquire) != from {
+                        slot.ramp_from.store(from, Ordering::Relaxed);
+                        slot.ramp_start.store(RAMP_PENDING, Ordering::Release);
+                        moved += 1;
+                    } else {
+                        // Staged away and back: drop the fade from a pair it never played
+                        let _ = slot.ramp_start.compare_exchange(
+                            RAMP_PENDING,
+                            0,
+                            Ordering::AcqRel,
+                            Ordering::Relaxed,
+                        );
+                    }
+                }
+                log_msg(
+                    Category::Routing,
+                    &format!("Prism: FREZ routing thawed, {} slot(s) switch", moved),
+                );
+            }
+            driver.routing_frozen_until.store(0, Ordering::Release);
+        }
+    }
+}
+
+// Pair a joining client of the bundle starts on because the daemon routed the bundle
+// there before. None for unknown bundles and for pairs reserved since.
+unsafe fn learned_route(driver: *mut PrismDriver, bundle_hash: u64) -> Option<usize> {
+    if bundle_hash == 0 {
+        return None;
+    }
+    let offset = (*driver)
+        .learned_routes
+        .lock()
+        .ok()?
+        .iter()
+        .find(|(hash, _)| *hash == bundle_hash)
+        .map(|&(_, offset)| offset)?;
+    let reserved = (*driver)
+        .reservations
+        .lock()
+        .is_ok_and(|reservations| reservations.contains_key(&offset));
+    (!reserved).then_some(offset)
+}
+
+// Record that the daemon routed `slot`'s bundle to `offset`; 0 forgets the bundle, as
+// it is back on the system mix.
+fn learn_route(driver: &PrismDriver, slot: &ClientSlot, offset: usize) {
+    let hash = slot.bundle_hash.load(Ordering::Relaxed);
+    if hash == 0 {
+        return;
+    }
+    let Ok(mut routes) = driver.learned_routes.lock() else {
+        return;
+    };
+    routes.retain(|(learned, _)| *learned != hash);
+    if offset == 0 {
+        return;
+    }
+    if routes.len() >= MAX_LEARNED_ROUTES {
+        routes.pop_front();
+    }
+    routes.push_back((hash, offset));
+}
+
+// Channel offset for a client of `pid` that is joining: the pair the process already
+// plays on, else a pair no client is routed to and nobody reserved, picked by the
+// assignment policy. Sticky-bundle returns the bundle to its last pair even when
+// another app shares it now. 0 when auto-assignment is disabled, in bus mode (which
+// maps the bus 1:1) or when every pair is taken.
+unsafe fn auto_assign_offset(
+    driver: *mut PrismDriver,
+    table: &SlotTable,
+    pid: pid_t,
+    bundle_hash: u64,
+) -> usize {
+    let policy = (*driver).assign_policy();
+    if policy == AssignPolicy::Disabled || (*driver).full_bus_output.load(Ordering::Relaxed) {
+        return 0;
+    }
+    let active = || {
+        table
+            .slots
+            .iter()
+            .filter(|slot| slot.client_id.load(Ordering::Acquire) != 0)
+    };
+    if let Some(offset) = active()
+        .filter(|slot| slot.pid.load(Ordering::Acquire) == pid)
+        .map(|slot| slot.channel_offset.load(Ordering::Relaxed))
+        .find(|&offset| offset != 0)
+    {
+        return offset;
+    }
+    let reserved: Vec<usize> = match (*driver).reservations.lock() {
+        Ok(reservations) => reservations.keys().copied().collect(),
+        Err(_) => Vec::new(),
+    };
+    if policy == AssignPolicy::StickyBundle && bundle_hash != 0 {
+        let remembered = match (*driver).bundle_pairs.lock() {
+            Ok(pairs) => pairs.get(&bundle_hash).copied(),
+            Err(_) => None,
+        };
+        if let Some(offset) = remembered.filter(|offset| !reserved.contains(offset)) {
+            return offset;
+        }
+    }
+    let pair_count = (*driver).config.num_channels as usize / 2;
+    let system_mix = (*driver).system_mix_offset.load(Ordering::Relaxed);
+    let mut free = (1..pair_count).map(|pair| pair * 2).filter(|offset| {
+        *offset != system_mix
+            && !reserved.contains(offset)
+            && !active().any(|slot| slot.channel_offset.load(Ordering::Relaxed) == *offset)
+    });
+    if policy != AssignPolicy::RoundRobin {
+        return free.next().unwrap_or(0);
+    }
+    // The first free pair after the last one handed out, wrapping to 3-4
+    let last = (*driver).assign_cursor.load(Ordering::Relaxed);
+    let free: Vec<usize> = free.collect();
+    let Some(offset) = free
+        .iter()
+        .find(|&&offset| offset > last)
+        .or(free.first())
+        .copied()
+    else {
+        return 0;
+    };
+    (*driver).assign_cursor.store(offset, Ordering::Relaxed);
+    offset
+}
+
+unsafe extern "C" fn remove_device_client(
+    _self: AudioServerPlugInDriverRef,
+    _device_id: AudioObjectID,
+    _client_id: *const AudioServerPlugInClientInfo,
+) -> OSStatus {
+    let driver = _self as *mut PrismDriver;
+    if !_client_id.is_null() {
+        let client_info = &*_client_id;
+        let client_id = client_info.mClientID;
+        let pid = client_info.mProcessID;
+
+        log_msg(
+            Category::Lifecycle,
+            &format!("Prism: Client Removed. ID={}, PID={}", client_id, pid),
+        );
+
+        // The slot reads as free once client_id is 0, so it is reset under SLOT_CLAIMS
+        // and released last: a client claiming it next never has its fields zeroed here
+        let claims = SLOT_CLAIMS.lock().unwrap_or_else(|e| e.into_inner());
+        let slot = (*driver).slot_table().find(client_id);
+        if let Some(slot) = slot {
+            // Before clearing the slot, drop any stale audio left in the slot buffer
+            let prev_offset = slot.channel_offset.load(Ordering::Acquire);
+            // Remember where the app played, routed or assigned, for sticky-bundle
+            let hash = slot.bundle_hash.load(Ordering::Relaxed);
+            if prev_offset != 0 && hash != 0 {
+                if let Ok(mut pairs) = (*driver).bundle_pairs.lock() {
+                    pairs.insert(hash, prev_offset);
+                }
+            }
+            slot.silence();
+            slot.channel_offset.store(0, Ordering::Relaxed);
+            slot.mirror_offset.store(0, Ordering::Relaxed);
+            slot.reset_mix();
+            slot.set_matrix(&[]);
+            slot.pid.store(0, Ordering::Relaxed);
+            slot.io_running.store(false, Ordering::Relaxed);
+            slot.client_id.store(0, Ordering::Release);
+        }
+        drop(claims);
+        if slot.is_some() {
+            notify_device_event(driver, kAudioPrismPropertyClientList, PRISM_EVENT_CLIENTS);
+        }
+
+        // An owner whose last client is gone can no longer release hog mode itself
+        let table = (*driver).slot_table();
+        if !table
+            .slots
+            .iter()
+            .any(|slot| slot.pid.load(Ordering::Acquire) == pid)
+        {
+            release_hog_mode(driver, pid);
+        }
+    }
+    0
+}
+
+// Free hog mode if `pid` holds it.
+unsafe fn release_hog_mode(driver: *mut PrismDriver, pid: pid_t) {
+    if pid == -1
+        || (*driver)
+            .hog_pid
+            .compare_exchange(pid, -1, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+    {
+        return;
+    }
+    log_msg(
+        Category::Properties,
+        &format!("Prism: HogMode released by PID {}", pid),
+    );
+    notify_device_property_changed(driver, kAudioDevicePropertyHogMode);
+}
+
+unsafe extern "C" fn perform_device_configuration_change(
+    _self: AudioServerPlugInDriverRef,
+    _device_id: AudioObjectID,
+    _action: u64,
+    _change_info: *mut c_void,
+) -> OSStatus {
+    let driver = _self as *mut PrismDriver;
+    if driver.is_null() || _device_id != DEVICE_ID {
+        return kAudioHardwareBadObjectError as OSStatus;
+    }
+    apply_config_change(driver, _action);
+    0
+}
+
+unsafe extern "C" fn abort_device_configuration_change(
+    _self: AudioServerPlugInDriverRef,
+    _device_id: AudioObjectID,
+    _action: u64,
+    _change_info: *mut c_void,
+) -> OSStatus {
+    let driver = _self as *mut PrismDriver;
+    if driver.is_null() || _device_id != DEVICE_ID {
+        return kAudioHardwareBadObjectError as OSStatus;
+    }
+    if _action == CONFIG_CHANGE_BUFFER_FRAME_SIZE {
+        let dropped = (*driver)
+            .pending_buffer_frame_size
+            .swap(0, Ordering::AcqRel);
+        log_msg(
+            Category::Properties,
+            &format!("Prism: BufferFrameSize {} change aborted", dropped),
+        );
+    }
+    if _action == CONFIG_CHANGE_FULL_BUS_OUTPUT {
+        (*driver)
+            .pending_full_bus_output
+            .store(0, Ordering::Release);
+        log_msg(Category::Properties, "Prism: bus mode change aborted");
+    }
+    if _action == CONFIG_CHANGE_STREAM_FORMAT {
+        (*driver).pending_input_format.store(0, Ordering::Release);
+        (*driver).pending_output_format.store(0, Ordering::Release);
+        log_msg(Category::Properties, "Prism: stream format change aborted");
+    }
+    0
+}
+
+// Ask the host to stop IO and call PerformDeviceConfigurationChange with `action`, which
+// applies what is pending for it. Without a host nothing runs yet, so it applies now.
+unsafe fn request_config_change(driver: *mut PrismDriver, action: u64) -> OSStatus {
+    let request = (*driver)
+        .host
+        .and_then(|host| (*host).RequestDeviceConfigurationChange.map(|f| (host, f)));
+    match request {
+        Some((host, request)) => request(host, DEVICE_ID, action, ptr::null_mut()),
+        None => {
+            apply_config_change(driver, action);
+            0
+        }
+    }
+}
+
+unsafe fn apply_config_change(driver: *mut PrismDriver, action: u64) {
+    match action {
+        CONFIG_CHANGE_BUFFER_FRAME_SIZE => apply_buffer_frame_size(driver),
+        CONFIG_CHANGE_FULL_BUS_OUTPUT => apply_full_bus_output(driver),
+        CONFIG_CHANGE_STREAM_FORMAT => apply_stream_formats(driver),
+        _ => {}
+    }
+}
+
+// Switch to the pending buffer frame size. The HAL has stopped IO for the change, so the
+// rings can be dropped and the zero timestamp period moved to the new cycle.
+unsafe fn apply_buffer_frame_size(driver: *mut PrismDriver) {
+    let frames = (*driver)
+        .pending_buffer_frame_size
+        .swap(0, Ordering::AcqRel);
+    if frames == 0 {
+        return;
+    }
+    let previous = (*driver).buffer_frame_size.swap(frames, Ordering::AcqRel);
+    if previous == frames {
+        return;
+    }
+    log_msg(
+        Category::Properties,
+        &format!(
+            "Prism: BufferFrameSize updated from {} to {}",
+            previous, frames
+        ),
+    );
+
+    (*driver)
+        .buffer_frame_size_actual
+        .store(frames, Ordering::Relaxed);
+    // Rings are sized for the largest cycle already; only drop what they hold
+    for slot in (*driver).slot_table().slots.iter() {
+        slot.silence();
+    }
+    (*driver)
+        .last_output_sample_time
+        .store(0, Ordering::Release);
+    (*driver).is_buffer_clear.store(true, Ordering::Release);
+
+    update_zero_timestamp_period(driver, frames);
+    notify_device_property_changed(driver, kAudioDevicePropertyRingBufferFrameSize);
+    notify_device_event(
+        driver,
+        kAudioDevicePropertyBufferFrameSize,
+        PRISM_EVENT_CONFIG,
+    );
+}
+
+// Constants
+const DEVICE_ID: AudioObjectID = 2;
+// Actions passed through RequestDeviceConfigurationChange: a new buffer frame size, a
+// bus mode switch, a stream format a host picked
+const CONFIG_CHANGE_BUFFER_FRAME_SIZE: u64 = 1;
+const CONFIG_CHANGE_FULL_BUS_OUTPUT: u64 = 2;
+const CONFIG_CHANGE_STREAM_FORMAT: u64 = 3;
+// Devices sharing the main device's clock, published as kAudioDevicePropertyRelatedDevices
+// so aggregates built from them skip resampling. The HAL expects a device to list itself.
+// The plug-in publishes no per-app tap devices yet; when it does, they belong here and
+// each must report this same list.
+const RELATED_DEVICES: [AudioObjectID; 1] = [DEVICE_ID];
+const INPUT_STREAM_ID: AudioObjectID = 3;
+const OUTPUT_STREAM_ID: AudioObjectID = 4;
+// Input volume controls, one per channel: object ID = base + element - 1
+const VOLUME_CONTROL_BASE_ID: AudioObjectID = 16;
+
+// kAudioObjectPropertyElementMain is the macOS 12 name for the deprecated ElementMaster;
+// both are 0. Defined here so the driver builds against SDKs that only have one of them.
+#[allow(non_upper_case_globals)]
+const kAudioObjectPropertyElementMain: AudioObjectPropertyElement = 0;
+
+// Every property the driver publishes lives on the main element, except the channel
+// names of addresses_channel_element (input volumes are separate control objects). A
+// wildcard element matches it, as the AudioServerPlugIn contract requires.
+fn addresses_main_element(address: &AudioObjectPropertyAddress) -> bool {
+    address.mElement == kAudioObjectPropertyElementMain
+        || address.mElement == kAudioObjectPropertyElementWildcard
+}
+
+// Device element names are the one per-channel property: element N of the input or
+// output scope names channel N of that side.
+unsafe fn addresses_channel_element(
+    driver: *mut PrismDriver,
+    object_id: AudioObjectID,
+    address: &AudioObjectPropertyAddress,
+) -> bool {
+    object_id == DEVICE_ID
+        && address.mSelector == kAudioObjectPropertyElementName
+        && channel_element_name(driver, address.mScope, address.mElement).is_some()
+}
+
+// Name of channel `element` (1-based) in `scope`. Input channels and bus-mode output
+// channels are named after their bus pair; otherwise the output stream folds onto each
+// client's routed pair, so its channels only carry a side.
+unsafe fn channel_element_name(
+    driver: *mut PrismDriver,
+    scope: AudioObjectPropertyScope,
+    element: AudioObjectPropertyElement,
+) -> Option<String> {
+    let channel = element.checked_sub(1)?;
+    let side = if channel % 2 == 0 { "L" } else { "R" };
+    let first = channel - channel % 2 + 1;
+    let label = (*driver)
+        .bus_labels
+        .lock()
+        .ok()
+        .and_then(|labels| labels.get(&((first - 1) as usize)).cloned());
+    let bus = match label {
+        Some(label) => format!("{} {}", label, side),
+        None => format!("Bus {}-{} {}", first, first + 1, side),
+    };
+    match scope {
+        kAudioObjectPropertyScopeInput if element <= (*driver).config.num_channels => Some(bus),
+        kAudioObjectPropertyScopeOutput
+            if element <= (*driver).output_channels.load(Ordering::Relaxed) =>
+        {
+            if (*driver).full_bus_output.load(Ordering::Relaxed) {
+                Some(bus)
+            } else {
+                Some(format!("Routed {}", side))
+            }
+        }
+        _ => None,
+    }
+}
+
+// Whether a query in `scope` covers `wanted`: the global and wildcard scopes cover
+// input and output alike.
+fn scope_includes(scope: AudioObjectPropertyScope, wanted: AudioObjectPropertyScope) -> bool {
+    scope == wanted
+        || scope == kAudioObjectPropertyScopeGlobal
+        || scope == kAudioObjectPropertyScopeWildcard
+}
+
+const VOLUME_MIN_DB: f32 = -64.0;
+const VOLUME_MAX_DB: f32 = 12.0;
+
+#[allow(non_upper_case_globals)]
+const kAudioLevelControlClassID: AudioClassID = 0x6C65766C; // 'levl'
+#[allow(non_upper_case_globals)]
+const kAudioVolumeControlClassID: AudioClassID = 0x766C6D65; // 'vlme'
+#[allow(non_upper_case_globals)]
+const kAudioControlPropertyScope: AudioObjectPropertySelector = 0x63736370; // 'cscp'
+#[allow(non_upper_case_globals)]
+const kAudioControlPropertyElement: AudioObjectPropertySelector = 0x63656C6D; // 'celm'
+#[allow(non_upper_case_globals)]
+const kAudioLevelControlPropertyScalarValue: AudioObjectPropertySelector = 0x6C637376; // 'lcsv'
+#[allow(non_upper_case_globals)]
+const kAudioLevelControlPropertyDecibelValue: AudioObjectPropertySelector = 0x6C636476; // 'lcdv'
+#[allow(non_upper_case_globals)]
+const kAudioLevelControlPropertyDecibelRange: AudioObjectPropertySelector = 0x6C636472; // 'lcdr'
+#[allow(non_upper_case_globals)]
+const kAudioLevelControlPropertyConvertScalarToDecibels: AudioObjectPropertySelector = 0x6C637364; // 'lcsd'
+#[allow(non_upper_case_globals)]
+const kAudioLevelControlPropertyConvertDecibelsToScalar: AudioObjectPropertySelector = 0x6C636473; // 'lcds'
+
+// Input channel (1-based element) whose volume control is `object_id`, if any.
+unsafe fn volume_control_channel(
+    driver: *const PrismDriver,
+    object_id: AudioObjectID,
+) -> Option<u32> {
+    let index = object_id.checked_sub(VOLUME_CONTROL_BASE_ID)?;
+    (index < (*driver).config.num_channels).then_some(index + 1)
+}
+
+// Sliders map linearly onto the dB range.
+fn volume_db_to_scalar(db: f32) -> f32 {
+    ((db - VOLUME_MIN_DB) / (VOLUME_MAX_DB - VOLUME_MIN_DB)).clamp(0.0, 1.0)
+}
+
+fn volume_scalar_to_db(scalar: f32) -> f32 {
+    VOLUME_MIN_DB + scalar.clamp(0.0, 1.0) * (VOLUME_MAX_DB - VOLUME_MIN_DB)
+}
+
+// Clients whose gain input `channel` (1-based) trims: those routed to its pair on the
+// bus, with the side of their stereo pair the channel carries. A trim is no store of its
+// own but their gain and pan, the state 'rout', 'cgan' and prismd set, so the sliders
+// and the daemon always agree. The system mix has no per-client gain to trim.
+fn trimmed_clients(
+    driver: &PrismDriver,
+    channel: u32,
+) -> (impl Iterator<Item = &ClientSlot> + '_, usize) {
+    let index = channel.saturating_sub(1) as usize;
+    let offset = index / 2 * 2;
+    let slots = driver
+        .slot_table()
+        .slots
+        .iter()
+        .map(Arc::as_ref)
+        .filter(move |slot| {
+            offset >= 2
+                && slot.client_id.load(Ordering::Acquire) != 0
+                && slot.channel_offset.load(Ordering::Acquire) == offset
+        });
+    (slots, index % 2)
+}
+
+// Trim of input `channel`: its side's gain on the first client routed there, 0 dB when
+// there is none.
+fn channel_trim_db(driver: &PrismDriver, channel: u32) -> f32 {
+    let (mut slots, side) = trimmed_clients(driver, channel);
+    let Some(slot) = slots.next() else {
+        return 0.0;
+    };
+    let (left, right) = slot.side_gains();
+    let gain = if side == 0 { left } else { right };
+    if gain <= 0.0 {
+        VOLUME_MIN_DB
+    } else {
+        (20.0 * gain.log10()).clamp(VOLUME_MIN_DB, VOLUME_MAX_DB)
+    }
+}
+
+// Set the trim of input `channel` on every client routed to its pair. Returns how many
+// clients it reached.
+fn set_channel_trim_db(driver: &PrismDriver, channel: u32, db: f32) -> usize {
+    let db = db.clamp(VOLUME_MIN_DB, VOLUME_MAX_DB);
+    // The bottom of the range mutes the channel
+    let gain = if db <= VOLUME_MIN_DB {
+        0.0
+    } else {
+        10f32.powf(db / 20.0)
+    };
+    let (slots, side) = trimmed_clients(driver, channel);
+    let mut clients = 0;
+    for slot in slots {
+        slot.set_side_gain(side, gain);
+        clients += 1;
+    }
+    clients
+}
+
+// Tell the HAL every input volume control may have moved, after client gains changed.
+fn notify_trims_changed(driver: *mut PrismDriver) {
+    if driver.is_null() {
+        return;
+    }
+    let channels = unsafe { (*driver).config.num_channels };
+    for index in 0..channels {
+        notify_properties_changed(
+            driver,
+            VOLUME_CONTROL_BASE_ID + index,
+            &[
+                kAudioLevelControlPropertyScalarValue,
+                kAudioLevelControlPropertyDecibelValue,
+            ],
+        );
+    }
+}
+
+#[allow(non_upper_case_globals)]
+const kAudioPlugInPropertyDeviceList: AudioObjectPropertySelector = 0x64657623; // 'dev#'
+#[allow(non_upper_case_globals)]
+const kAudioPlugInPropertyResourceBundle: AudioObjectPropertySelector = 0x72737263; // 'rsrc'
+#[allow(non_upper_case_globals)]
+const kAudioPlugInPropertyTranslateUIDToDevice: AudioObjectPropertySelector = 0x75696464; // 'uidd'
+#[allow(non_upper_case_globals)]
+const kAudioObjectPropertyScope: AudioObjectPropertySelector = 0x73636F70; // 'scop'
+#[allow(non_upper_case_globals)]
+const kAudioObjectPropertyElement: AudioObjectPropertySelector = 0x656C656D; // 'elem'
+#[allow(non_upper_case_globals)]
+const kAudioDevicePropertyBufferFrameSize: AudioObjectPropertySelector = 0x6673697A; // 'fsiz'
+#[allow(non_upper_case_globals)]
+const kAudioDevicePropertyBufferFrameSizeRange: AudioObjectPropertySelector = 0x66737A72; // 'fszr'
+#[allow(non_upper_case_globals)]
+const kAudioObjectPropertyControlList: AudioObjectPropertySelector = 0x6374726C; // 'ctrl'
+#[allow(non_upper_case_globals)]
+const kAudioObjectPropertyCustomPropertyInfoList: AudioObjectPropertySelector = 0x63757374; // 'cust'
+#[allow(non_upper_case_globals)]
+const kAudioDevicePropertyStreamsIsSettable: AudioObjectPropertySelector = 0x7369736F; // 'siso'
+#[allow(non_upper_case_globals)]
+const kAudioDevicePropertyClockDomain: AudioObjectPropertySelector = 0x636C6B64; // 'clkd'
+#[allow(non_upper_case_globals)]
+const kAudioDevicePropertyClockIsStable: AudioObjectPropertySelector = 0x63737462; // 'cstb'
+#[allow(non_upper_case_globals)]
+const kAudioDevicePropertyClockAlgorithm: AudioObjectPropertySelector = 0x636C6F6B; // 'clok'
+#[allow(non_upper_case_globals)]
+const kAudioDeviceClockAlgorithmSimpleIIR: UInt32 = 0x69697266; // 'iirf'
+#[allow(non_upper_case_globals)]
+const kAudioDevicePropertyRelatedDevices: AudioObjectPropertySelector = 0x616B696E; // 'akin'
+#[allow(non_upper_case_globals)]
+const kAudioDeviceProcessorOverload: AudioObjectPropertySelector = 0x6F766572; // 'over'
+#[allow(non_upper_case_globals)]
+const kAudioDevicePropertyHogMode: AudioObjectPropertySelector = 0x6F696E6B; // 'oink'
+#[allow(non_upper_case_globals)]
+const kAudioDevicePermissionsError: OSStatus = 0x21686F67; // '!hog'
+#[allow(non_upper_case_globals)]
+const kAudioObjectPropertyElementName: AudioObjectPropertySelector = 0x6C63686E; // 'lchn'
+#[allow(non_upper_case_globals)]
+const kAudioDevicePropertyPreferredChannelsForStereo: AudioObjectPropertySelector = 0x64636832; // 'dch2'
+#[allow(non_upper_case_globals)]
+const kAudioDevicePropertyClockSource: AudioObjectPropertySelector = 0x63737263; // 'csrc'
+#[allow(non_upper_case_globals)]
+const kAudioDevicePropertyIsHidden: AudioObjectPropertySelector = 0x6869646E; // 'hidn'
+#[allow(non_upper_case_globals)]
+const kAudioObjectPropertyName: AudioObjectPropertySelector = 0x6C6E616D; // 'lnam'
+#[allow(non_upper_case_globals)]
+const kAudioDevicePropertyRingBufferFrameSize: AudioObjectPropertySelector = 0x72696E67; // 'ring'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyRoutingTable: AudioObjectPropertySelector = 0x726F7574; // 'rout'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyClientList: AudioObjectPropertySelector = 0x636C6E74; // 'clnt'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyIoStats: AudioObjectPropertySelector = 0x73746174; // 'stat'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyMeters: AudioObjectPropertySelector = 0x6D657472; // 'metr'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyTelemetry: AudioObjectPropertySelector = 0x746C6D79; // 'tlmy'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyCaptureAcl: AudioObjectPropertySelector = 0x63616374; // 'cact'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyResync: AudioObjectPropertySelector = 0x7273796E; // 'rsyn'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyEvents: AudioObjectPropertySelector = 0x65766E74; // 'evnt'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyLogLevel: AudioObjectPropertySelector = 0x6C6F676C; // 'logl'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyClearBuffers: AudioObjectPropertySelector = 0x636C7220; // 'clr '
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyClockRate: AudioObjectPropertySelector = 0x72617465; // 'rate'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyConfig: AudioObjectPropertySelector = 0x636E6667; // 'cnfg'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyRouteBundle: AudioObjectPropertySelector = 0x72626E64; // 'rbnd'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyMatrix: AudioObjectPropertySelector = 0x6D747278; // 'mtrx'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyMonitor: AudioObjectPropertySelector = 0x6D6E7364; // 'mnsd'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyReservations: AudioObjectPropertySelector = 0x72657376; // 'resv'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyGapPolicy: AudioObjectPropertySelector = 0x67617070; // 'gapp'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyBusOccupancy: AudioObjectPropertySelector = 0x62757379; // 'busy'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyRoutingFreeze: AudioObjectPropertySelector = 0x6672657A; // 'frez'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyClientGain: AudioObjectPropertySelector = 0x6367616E; // 'cgan'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertySolo: AudioObjectPropertySelector = 0x736F6C6F; // 'solo'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyCaptureAllowlist: AudioObjectPropertySelector = 0x6361706C; // 'capl'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyCaptureMask: AudioObjectPropertySelector = 0x636D736B; // 'cmsk'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyCaptureExclusions: AudioObjectPropertySelector = 0x63657863; // 'cexc'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyRouteEvent: AudioObjectPropertySelector = 0x72657674; // 'revt'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyBusLabels: AudioObjectPropertySelector = 0x6275736E; // 'busn'
+#[allow(non_upper_case_globals)]
+const kAudioPrismPropertyPluginInfo: AudioObjectPropertySelector = 0x70696E66; // 'pinf'
+
+// Bits of the 'evnt' mask: what changed since prismd last read it.
+const PRISM_EVENT_CLIENTS: u32 = 1 << 0; // Clients added, removed, started or stopped IO
+const PRISM_EVENT_ROUTING: u32 = 1 << 1; // Channel offset or mix of a client changed
+const PRISM_EVENT_CONFIG: u32 = 1 << 2; // Buffer size, ACL, trims, clock rate or 'cnfg' changed
+const PRISM_EVENT_DIAG: u32 = 1 << 3; // Resync, ring clear or slot table growth; refetch 'stat'
+
+// Custom properties advertised by the plug-in object through 'cust'. They describe
+// the driver as a whole, so tools need not resolve a device first.
+#[allow(non_upper_case_globals)]
+const PRISM_PLUGIN_CUSTOM_PROPERTIES: &[(AudioObjectPropertySelector, u32)] = &[(
+    kAudioPrismPropertyPluginInfo,
+    kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+)];
+
+// Custom properties advertised by the Device through 'cust' (selector, property data type).
+#[allow(non_upper_case_globals)]
+const PRISM_CUSTOM_PROPERTIES: &[(AudioObjectPropertySelector, u32)] = &[
+    (
+        kAudioPrismPropertyRoutingTable,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyClientList,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyIoStats,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyMeters,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyTelemetry,
+        kAudioServerPlugInCustomPropertyDataTypeCFString,
+    ),
+    (
+        kAudioPrismPropertyCaptureAcl,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyResync,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyEvents,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyLogLevel,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyClearBuffers,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyClockRate,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyConfig,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyMatrix,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyRouteBundle,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyMonitor,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyReservations,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyGapPolicy,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyBusOccupancy,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyRoutingFreeze,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyClientGain,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertySolo,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyCaptureAllowlist,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyCaptureMask,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyCaptureExclusions,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyRouteEvent,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+    (
+        kAudioPrismPropertyBusLabels,
+        kAudioServerPlugInCustomPropertyDataTypeCFPropertyList,
+    ),
+];
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[allow(non_snake_case)]
+struct PrismRoutingUpdate {
+    pid: i32,
+    channel_offset: u32,
+}
+
+// Optional trailer appended to a 'rout' payload: linear gain and pan as
+// little-endian f32, optionally followed by stereo width, a u32 mono fold (an index into
+// MonoFold::ALL) and a u32 channel swap (0 or 1). A NaN field, or a u32 of
+// PRISM_ROUTING_KEEP, leaves the client's current value untouched.
+const PRISM_ROUTING_MIX_SIZE: usize = 8;
+const PRISM_ROUTING_WIDTH_SIZE: usize = 4;
+const PRISM_ROUTING_FLAG_SIZE: usize = 4;
+const PRISM_ROUTING_KEEP: u32 = u32::MAX;
+const PRISM_MAX_WIDTH: f32 = 2.0;
+// channel_offset of a 'rout' update that returns the client (every client for pid -1)
+// to the state it connected in: pair 0, unity mix and no matrix. NULL_BUS_OFFSET, just
+// below it, routes to the null bus instead.
+const PRISM_UNROUTE_OFFSET: u32 = u32::MAX;
+// Status of a routing write that would move clients onto a pair reserved through 'resv'
+// by another owner, or of a 'resv' claim or release on a pair someone else holds.
+const PRISM_BUS_RESERVED_ERROR: OSStatus = 0x72737664; // 'rsvd'
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct AudioServerPlugInCustomPropertyInfo {
+    mSelector: AudioObjectPropertySelector,
+    mPropertyDataType: AudioObjectPropertySelector,
+    mQualifierDataType: AudioObjectPropertySelector,
+}
+
+#[allow(non_upper_case_globals)]
+unsafe extern "C" fn has_property(
+    _self: AudioServerPlugInDriverRef,
+    object_id: AudioObjectID,
+    _client_process_id: pid_t,
+    _address: *const AudioObjectPropertyAddress,
+) -> Boolean {
+    let driver = _self as *mut PrismDriver;
+    let address = *_address;
+    let selector = address.mSelector;
+
+    if addresses_channel_element(driver, object_id, &address) {
+        return 1;
+    }
+    if !addresses_main_element(&address) {
+        return 0;
+    }
+
+    // Legacy forced-true behavior is not applied.
+    // Evaluate property presence strictly within the match arms.
+
+    let res = match object_id {
+        // --------------------------------------------------------
+        // 1. Plugin Object
+        // --------------------------------------------------------
+        id if id == kAudioObjectPlugInObject => {
+            if selector == kAudioObjectPropertyBaseClass
+                || selector == kAudioObjectPropertyClass
+                || selector == kAudioObjectPropertyOwner
+                || selector == kAudioObjectPropertyManufacturer
+                || selector == kAudioObjectPropertyOwnedObjects
+                || selector == kAudioPlugInPropertyDeviceList
+                || selector == kAudioPlugInPropertyTranslateUIDToDevice
+                || selector == kAudioPlugInPropertyResourceBundle
+                || selector == kAudioObjectPropertyCustomPropertyInfoList
+                || selector == kAudioPrismPropertyPluginInfo
+            {
+                log_msg(
+                    Category::Properties,
+                    &format!(
+                        "Prism: HasProperty Plugin Known. Object: {}, Selector: {}",
+                        object_id, selector
+                    ),
+                );
+                true
+            } else {
+                log_msg(
+                    Category::Properties,
+                    &format!(
+                        "Prism: HasProperty Plugin Unknown. Object: {}, Selector: {}",
+                        object_id, selector
+                    ),
+                );
+                false
+            }
+        }
+
+        // --------------------------------------------------------
+        // 2. Device Object (only here allow 'rout' / 'cust')
+        // --------------------------------------------------------
+        DEVICE_ID => {
+            if selector == kAudioObjectPropertyBaseClass ||
+               selector == kAudioObjectPropertyClass ||
+               selector == kAudioObjectPropertyOwner ||
+               selector == kAudioObjectPropertyManufacturer ||
+               selector == kAudioObjectPropertyOwnedObjects ||
+               selector == kAudioObjectPropertyControlList ||
+               selector == kAudioObjectPropertyCustomPropertyInfoList || // 'cust' OK
+               selector == kAudioDevicePropertyStreams ||
+               selector == kAudioDevicePropertyStreamsIsSettable ||
+               selector == kAudioDevicePropertyDeviceUID ||
+               selector == kAudioDevicePropertyModelUID ||
+               selector == kAudioDevicePropertyDeviceName ||
+               selector == kAudioObjectPropertyName ||
+               selector == kAudioDevicePropertyDeviceIsRunning ||
+               selector == kAudioDevicePropertyIsHidden ||
+               selector == kAudioDevicePropertyDeviceCanBeDefaultDevice ||
+               selector == kAudioDevicePropertyDeviceCanBeDefaultSystemDevice ||
+               selector == kAudioDevicePropertySafetyOffset ||
+               selector == kAudioDevicePropertyLatency ||
+               selector == kAudioDevicePropertyDeviceIsAlive ||
+               selector == kAudioDevicePropertyNominalSampleRate ||
+               selector == kAudioDevicePropertyAvailableNominalSampleRates ||
+               selector == kAudioDevicePropertyBufferFrameSize ||
+               selector == kAudioDevicePropertyBufferFrameSizeRange ||
+               selector == kAudioDevicePropertyRingBufferFrameSize ||
+               selector == kAudioDevicePropertyZeroTimeStampPeriod ||
+               selector == kAudioDevicePropertyClockDomain ||
+               selector == kAudioDevicePropertyClockIsStable ||
+               selector == kAudioDevicePropertyClockAlgorithm ||
+               selector == kAudioDevicePropertyRelatedDevices ||
+               selector == kAudioDevicePropertyPreferredChannelsForStereo ||
+               selector == kAudioDevicePropertyHogMode ||
+               selector == kAudioDevicePropertyClockSource ||
+               selector == kAudioDevicePropertyDataSource ||
+               selector == kAudioObjectPropertyScope ||
+               selector == kAudioObjectPropertyElement ||
+               selector == kAudioDevicePropertyBufferFrameSize ||
+               selector == kAudioPrismPropertyRoutingTable ||
+               selector == kAudioPrismPropertyClientList ||
+               selector == kAudioPrismPropertyIoStats ||
+               selector == kAudioPrismPropertyMeters ||
+               selector == kAudioPrismPropertyTelemetry ||
+               selector == kAudioPrismPropertyCaptureAcl ||
+               selector == kAudioPrismPropertyResync ||
+               selector == kAudioPrismPropertyEvents ||
+               selector == kAudioPrismPropertyLogLevel ||
+               selector == kAudioPrismPropertyClearBuffers ||
+               selector == kAudioPrismPropertyClockRate ||
+               selector == kAudioPrismPropertyConfig ||
+               selector == kAudioPrismPropertyMatrix ||
+               selector == kAudioPrismPropertyRouteBundle ||
+               selector == kAudioPrismPropertyMonitor ||
+               selector == kAudioPrismPropertyReservations ||
+               selector == kAudioPrismPropertyGapPolicy ||
+               selector == kAudioPrismPropertyBusOccupancy ||
+               selector == kAudioPrismPropertyRoutingFreeze ||
+               selector == kAudioPrismPropertyClientGain ||
+               selector == kAudioPrismPropertySolo ||
+               selector == kAudioPrismPropertyCaptureAllowlist ||
+               selector == kAudioPrismPropertyCaptureMask ||
+               selector == kAudioPrismPropertyCaptureExclusions ||
+               selector == kAudioPrismPropertyRouteEvent ||
+               selector == kAudioPrismPropertyBusLabels
+            {
+                log_msg(
+                    Category::Properties,
+                    &format!(
+                        "Prism: HasProperty Device Known. Object: {}, Selector: {}",
+                        object_id, selector
+                    ),
+                );
+                true
+            } else {
+                log_msg(
+                    Category::Properties,
+                    &format!(
+                        "Prism: HasProperty Device Unknown. Object: {}, Selector: {}",
+                        object_id, selector
+                    ),
+                );
+                false
+            }
+        }
+
+        // --------------------------------------------------------
+        // 3. Stream Object (do NOT include 'cust' / 'rout' here)
+        // --------------------------------------------------------
+        INPUT_STREAM_ID | OUTPUT_STREAM_ID => {
+            if selector == kAudioObjectPropertyBaseClass ||
+               selector == kAudioObjectPropertyClass ||
+               selector == kAudioObjectPropertyOwner ||
+               selector == kAudioObjectPropertyControlList ||
+               // (do not include kAudioObjectPropertyCustomPropertyInfoList here)
+               selector == kAudioStreamPropertyDirection ||
+               selector == kAudioStreamPropertyTerminalType ||
+               selector == kAudioStreamPropertyStartingChannel ||
+               selector == kAudioObjectPropertyScope ||
+               selector == kAudioObjectPropertyElement ||
+               // stream formats
+               selector == kAudioStreamPropertyVirtualFormat ||
+               selector == kAudioStreamPropertyPhysicalFormat ||
+               selector == kAudioStreamPropertyPhysicalFormats ||
+               selector == kAudioStreamPropertyAvailableVirtualFormats ||
+               selector == kAudioStreamPropertyAvailablePhysicalFormats
+            {
+                log_msg(
+                    Category::Properties,
+                    &format!(
+                        "Prism: HasProperty Stream Known. Object: {}, Selector: {}",
+                        object_id, selector
+                    ),
+                );
+                true
+            } else {
+                log_msg(
+                    Category::Properties,
+                    &format!(
+                        "Prism: HasProperty Stream Unknown. Object: {}, Selector: {}",
+                        object_id, selector
+                    ),
+                );
+                false
+            }
+        }
+        // --------------------------------------------------------
+        // 4. Input volume controls
+        // --------------------------------------------------------
+        id if volume_control_channel(driver, id).is_some() => {
+            selector == kAudioObjectPropertyBaseClass
+                || selector == kAudioObjectPropertyClass
+                || selector == kAudioObjectPropertyOwner
+                || selector == kAudioControlPropertyScope
+                || selector == kAudioControlPropertyElement
+                || selector == kAudioLevelControlPropertyScalarValue
+                || selector == kAudioLevelControlPropertyDecibelValue
+                || selector == kAudioLevelControlPropertyDecibelRange
+                || selector == kAudioLevelControlPropertyConvertScalarToDecibels
+                || selector == kAudioLevelControlPropertyConvertDecibelsToScalar
+        }
+        _ => {
+            log_msg(
+                Category::Properties,
+                &format!(
+                    "Prism: HasProperty Unknown. Object: {}, Selector: {}",
+                    object_id, selector
+                ),
+            );
+            false
+        }
+    };
+
+    if res {
+        1
+    } else {
+        0
+    }
+}
+
+#[allow(non_upper_case_globals)]
+unsafe extern "C" fn is_property_settable(
+    _self: AudioServerPlugInDriverRef,
+    _object_id: AudioObjectID,
+    _client_process_id: pid_t,
+    _address: *const AudioObjectPropertyAddress,
+    _out_is_settable: *mut Boolean,
+) -> OSStatus {
+    let driver = _self as *mut PrismDriver;
+    let address = *_address;
+    let selector = address.mSelector;
+
+    log_msg(
+        Category::Properties,
+        &format!(
+            "Prism: IsPropertySettable called. Object: {}, Selector: {}",
+            _object_id, selector
+        ),
+    );
+
+    if addresses_channel_element(driver, _object_id, &address) {
+        *_out_is_settable = 0;
+        return 0;
+    }
+    if !addresses_main_element(&address) {
+        return kAudioHardwareUnknownPropertyError as OSStatus;
+    }
+
+    // Short-circuit: 'rout' is settable everywhere
+    if selector == kAudioPrismPropertyRoutingTable {
+        *_out_is_settable = 1;
+        log_msg(
+            Category::Properties,
+            "Prism: IsPropertySettable('rout') -> true",
+        );
+        return 0;
+    }
+
+    let res = if selector == kAudioPrismPropertyRoutingTable
+        || selector == kAudioPrismPropertyCaptureAcl
+        || selector == kAudioPrismPropertyResync
+        || selector == kAudioPrismPropertyLogLevel
+        || selector == kAudioPrismPropertyClearBuffers
+        || selector == kAudioPrismPropertyClockRate
+        || selector == kAudioPrismPropertyConfig
+        || selector == kAudioPrismPropertyMatrix
+        || selector == kAudioPrismPropertyRouteBundle
+        || selector == kAudioPrismPropertyMonitor
+        || selector == kAudioPrismPropertyReservations
+        || selector == kAudioPrismPropertyGapPolicy
+        || selector == kAudioPrismPropertyRoutingFreeze
+        || selector == kAudioPrismPropertyClientGain
+        || selector == kAudioPrismPropertySolo
+        || selector == kAudioPrismPropertyCaptureAllowlist
+        || selector == kAudioPrismPropertyCaptureMask
+        || selector == kAudioPrismPropertyBusLabels
+        || selector == kAudioDevicePropertyDeviceName
+        || selector == kAudioObjectPropertyName
+        || selector == kAudioDevicePropertyDataSource
+        || selector == kAudioDevicePropertyNominalSampleRate
+        || selector == kAudioDevicePropertyBufferFrameSize
+        || selector == kAudioDevicePropertyHogMode
+        || selector == kAudioLevelControlPropertyScalarValue
+        || selector == kAudioLevelControlPropertyDecibelValue
+        || ((selector == kAudioStreamPropertyVirtualFormat
+            || selector == kAudioStreamPropertyPhysicalFormat)
+            && (_object_id == INPUT_STREAM_ID || _object_id == OUTPUT_STREAM_ID))
+    {
+        *_out_is_settable = 1;
+        true
+    } else {
+        *_out_is_settable = 0;
+        false
+    };
+
+    log_msg(
+        Category::Properties,
+        &format!(
+            "Prism: IsPropertySettable called. Object: {}, Selector: {} -> {}",
+            _object_id, selector, res
+        ),
+    );
+    0
+}
+
+#[allow(non_upper_case_globals)]
+unsafe extern "C" fn get_property_data_size(
+    _self: AudioServerPlugInDriverRef,
+    object_id: AudioObjectID,
+    _client_process_id: pid_t,
+    _address: *const AudioObjectPropertyAddress,
+    _qualifier_data_size: UInt32,
+    _qualifier_data: *const c_void,
+    _out_data_size: *mut UInt32,
+) -> OSStatus {
+    let driver = _self as *mut PrismDriver;
+    let address = *_address;
+    let selector = address.mSelector;
+
+    // Debug logs: comment out if too verbose
+    // log_msg(&format!("Prism: GetPropertyDataSize called. Object: {}, Selector: {}", object_id, selector));
+
+    if addresses_channel_element(driver, object_id, &address) {
+        *_out_data_size = std::mem::size_of::<CFStringRef>() as UInt32;
+        return 0;
+    }
+    if !addresses_main_element(&address) {
+        return kAudioHardwareUnknownPropertyError as OSStatus;
+    }
+
+    match object_id {
+        // ---------------------------------------------------------------------
+        // 1. Plugin object
+        // ---------------------------------------------------------------------
+        id if id == kAudioObjectPlugInObject => {
+            match selector {
+                kAudioObjectPropertyCustomPropertyInfoList => {
+                    *_out_data_size = (PRISM_PLUGIN_CUSTOM_PROPERTIES.len()
+                        * std::mem::size_of::<AudioServerPlugInCustomPropertyInfo>())
+                        as UInt32;
+                }
+                kAudioObjectPropertyBaseClass
+                | kAudioObjectPropertyClass
+                | kAudioObjectPropertyOwner
+                | kAudioPlugInPropertyTranslateUIDToDevice => {
+                    *_out_data_size = std::mem::size_of::<AudioClassID>() as UInt32;
+                }
+                kAudioObjectPropertyManufacturer | kAudioPlugInPropertyResourceBundle => {
+                    *_out_data_size = std::mem::size_of::<CFStringRef>() as UInt32;
+                }
+                kAudioPlugInPropertyDeviceList | kAudioObjectPropertyOwnedObjects => {
+                    *_out_data_size = std::mem::size_of::<AudioObjectID>() as UInt32;
+                }
+                kAudioPrismPropertyPluginInfo => {
+                    *_out_data_size = std::mem::size_of::<CFDataRef>() as UInt32;
+                }
+                _ => {
+                    return kAudioHardwareUnknownPropertyError as OSStatus;
+                }
+            }
+        }
+
+        // ---------------------------------------------------------------------
+        // 2. Device object
+        // ---------------------------------------------------------------------
+        DEVICE_ID => {
+            // Custom property (catalog)
+            if selector == kAudioObjectPropertyCustomPropertyInfoList {
+                // Only the Device has a "custom property list"
+                let size = (PRISM_CUSTOM_PROPERTIES.len()
+                    * std::mem::size_of::<AudioServerPlugInCustomPropertyInfo>())
+                    as UInt32;
+                *_out_data_size = size;
+                log_msg(
+                    Category::Properties,
+                    &format!("Prism: Device has 'cust', size={}", size),
+                );
+                return 0;
+            }
+
+            // Custom property (actual data: 'rout')
+            if selector == kAudioPrismPropertyRoutingTable {
+                let size = std::mem::size_of::<PrismRoutingUpdate>() as UInt32;
+                *_out_data_size = size;
+                log_msg(
+                    Category::Properties,
+                    &format!("Prism: Device has 'rout', size={}", size),
+                );
+                return 0;
+            } else if selector == kAudioPrismPropertyClientList
+                || selector == kAudioPrismPropertyIoStats
+                || selector == kAudioPrismPropertyMeters
+                || selector == kAudioPrismPropertyCaptureAcl
+                || selector == kAudioPrismPropertyResync
+                || selector == kAudioPrismPropertyEvents
+                || selector == kAudioPrismPropertyLogLevel
+                || selector == kAudioPrismPropertyClearBuffers
+                || selector == kAudioPrismPropertyClockRate
+                || selector == kAudioPrismPropertyConfig
+                || selector == kAudioPrismPropertyMatrix
+                || selector == kAudioPrismPropertyRouteBundle
+                || selector == kAudioPrismPropertyMonitor
+                || selector == kAudioPrismPropertyReservations
+                || selector == kAudioPrismPropertyGapPolicy
+                || selector == kAudioPrismPropertyBusOccupancy
+                || selector == kAudioPrismPropertyRoutingFreeze
+                || selector == kAudioPrismPropertyClientGain
+                || selector == kAudioPrismPropertySolo
+                || selector == kAudioPrismPropertyCaptureAllowlist
+                || selector == kAudioPrismPropertyCaptureMask
+                || selector == kAudioPrismPropertyCaptureExclusions
+                || selector == kAudioPrismPropertyRouteEvent
+                || selector == kAudioPrismPropertyBusLabels
+            {
+                *_out_data_size = std::mem::size_of::<CFDataRef>() as UInt32;
+                log_msg(
+                    Category::Properties,
+                    "Prism: Device has CFDataRef custom property",
+                );
+                return 0;
+            } else if selector == kAudioPrismPropertyTelemetry {
+                *_out_data_size = std::mem::size_of::<CFStringRef>() as UInt32;
+                return 0;
+            }
+
+            // --- Standard properties ---
+            let control_count = (*driver).config.num_channels as usize;
+            if selector == kAudioObjectPropertyControlList {
+                *_out_data_size = (control_count * std::mem::size_of::<AudioObjectID>()) as UInt32;
+            } else if selector == kAudioDevicePropertyStreamsIsSettable
+                || selector == kAudioDevicePropertyClockDomain
+                || selector == kAudioDevicePropertyClockIsStable
+                || selector == kAudioDevicePropertyClockAlgorithm
+                || selector == kAudioDevicePropertyClockSource
+                || selector == kAudioDevicePropertyDataSource
+                || selector == kAudioObjectPropertyBaseClass
+                || selector == kAudioObjectPropertyClass
+                || selector == kAudioObjectPropertyOwner
                 || selector == kAudioDevicePropertyTransportType
                 || selector == kAudioDevicePropertyDeviceIsRunning
                 || selector == kAudioDevicePropertyDeviceCanBeDefaultDevice
@@ -766,837 +3987,3272 @@ unsafe extern "C" fn get_property_data_size(
                 || selector == kAudioPrismPropertyClientList
             {
                 *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
-            } else if selector == kAudioObjectPropertyManufacturer
-                || selector == kAudioDevicePropertyDeviceUID
-                || selector == kAudioDevicePropertyModelUID
-                || selector == kAudioDevicePropertyDeviceName
-                || selector == kAudioObjectPropertyName
+            } else if selector == kAudioObjectPropertyManufacturer
+                || selector == kAudioDevicePropertyDeviceUID
+                || selector == kAudioDevicePropertyModelUID
+                || selector == kAudioDevicePropertyDeviceName
+                || selector == kAudioObjectPropertyName
+            {
+                *_out_data_size = std::mem::size_of::<CFStringRef>() as UInt32;
+            } else if selector == kAudioObjectPropertyOwnedObjects {
+                *_out_data_size =
+                    ((2 + control_count) * std::mem::size_of::<AudioObjectID>()) as UInt32;
+            } else if selector == kAudioDevicePropertyStreams {
+                let scope = address.mScope;
+                let mut count = 0;
+                if scope_includes(scope, kAudioObjectPropertyScopeInput) {
+                    count += 1;
+                }
+                if scope_includes(scope, kAudioObjectPropertyScopeOutput) {
+                    count += 1;
+                }
+                *_out_data_size = (count * std::mem::size_of::<AudioObjectID>()) as UInt32;
+            } else if selector == kAudioDevicePropertyRelatedDevices {
+                *_out_data_size = std::mem::size_of_val(&RELATED_DEVICES) as UInt32;
+            } else if selector == kAudioDevicePropertyPreferredChannelsForStereo {
+                *_out_data_size = (2 * std::mem::size_of::<UInt32>()) as UInt32;
+            } else if selector == kAudioDevicePropertyHogMode {
+                *_out_data_size = std::mem::size_of::<pid_t>() as UInt32;
+            } else if selector == kAudioDevicePropertyNominalSampleRate {
+                *_out_data_size = std::mem::size_of::<Float64>() as UInt32;
+            } else if selector == kAudioDevicePropertyAvailableNominalSampleRates
+                || selector == kAudioDevicePropertyBufferFrameSizeRange
+            {
+                *_out_data_size = std::mem::size_of::<AudioValueRange>() as UInt32;
+            } else {
+                // log_msg(&format!("Prism: GetPropertyDataSize Unknown. Object: {}, Selector: {}", object_id, selector));
+                return kAudioHardwareUnknownPropertyError as OSStatus;
+            }
+        }
+
+        // ---------------------------------------------------------------------
+        // 3. Stream object
+        // ---------------------------------------------------------------------
+        INPUT_STREAM_ID | OUTPUT_STREAM_ID => match selector {
+            kAudioObjectPropertyCustomPropertyInfoList => {
+                *_out_data_size = 0;
+                return 0;
+            }
+            kAudioObjectPropertyControlList => {
+                *_out_data_size = 0;
+            }
+            kAudioObjectPropertyBaseClass
+            | kAudioObjectPropertyClass
+            | kAudioObjectPropertyOwner
+            | kAudioStreamPropertyDirection
+            | kAudioStreamPropertyTerminalType
+            | kAudioStreamPropertyStartingChannel
+            | kAudioObjectPropertyScope
+            | kAudioObjectPropertyElement => {
+                *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+            }
+            kAudioStreamPropertyVirtualFormat | kAudioStreamPropertyPhysicalFormat => {
+                *_out_data_size = std::mem::size_of::<AudioStreamBasicDescription>() as UInt32;
+            }
+            kAudioStreamPropertyPhysicalFormats
+            | kAudioStreamPropertyAvailableVirtualFormats
+            | kAudioStreamPropertyAvailablePhysicalFormats => {
+                // Interleaved and non-interleaved variants
+                *_out_data_size =
+                    (2 * std::mem::size_of::<AudioStreamRangedDescription>()) as UInt32;
+            }
+            _ => {
+                return kAudioHardwareUnknownPropertyError as OSStatus;
+            }
+        },
+
+        // ---------------------------------------------------------------------
+        // 4. Input volume controls
+        // ---------------------------------------------------------------------
+        id if volume_control_channel(driver, id).is_some() => match selector {
+            kAudioObjectPropertyBaseClass
+            | kAudioObjectPropertyClass
+            | kAudioObjectPropertyOwner
+            | kAudioControlPropertyScope
+            | kAudioControlPropertyElement => {
+                *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+            }
+            kAudioLevelControlPropertyScalarValue
+            | kAudioLevelControlPropertyDecibelValue
+            | kAudioLevelControlPropertyConvertScalarToDecibels
+            | kAudioLevelControlPropertyConvertDecibelsToScalar => {
+                *_out_data_size = std::mem::size_of::<Float32>() as UInt32;
+            }
+            kAudioLevelControlPropertyDecibelRange => {
+                *_out_data_size = std::mem::size_of::<AudioValueRange>() as UInt32;
+            }
+            _ => {
+                return kAudioHardwareUnknownPropertyError as OSStatus;
+            }
+        },
+        _ => return kAudioHardwareBadObjectError as OSStatus,
+    }
+    0
+}
+
+#[allow(non_upper_case_globals)]
+unsafe extern "C" fn get_property_data(
+    _self: AudioServerPlugInDriverRef,
+    object_id: AudioObjectID,
+    _client_process_id: pid_t,
+    _address: *const AudioObjectPropertyAddress,
+    _qualifier_data_size: UInt32,
+    _qualifier_data: *const c_void,
+    _in_data_size: UInt32,
+    _out_data_size: *mut UInt32,
+    _out_data: *mut c_void,
+) -> OSStatus {
+    let driver = _self as *mut PrismDriver;
+    let address = *_address;
+    let selector = address.mSelector;
+
+    // log_msg(&format!("Prism: GetPropertyData called. Object: {}, Selector: {}", object_id, selector));
+
+    if _out_data.is_null() {
+        return kAudioHardwareIllegalOperationError as OSStatus;
+    }
+    if addresses_channel_element(driver, object_id, &address) {
+        let Some(name) = channel_element_name(driver, address.mScope, address.mElement)
+            .and_then(|name| CString::new(name).ok())
+        else {
+            return kAudioHardwareUnspecifiedError as OSStatus;
+        };
+        return write_cfstring(_out_data, _out_data_size, &name);
+    }
+    if !addresses_main_element(&address) {
+        return kAudioHardwareUnknownPropertyError as OSStatus;
+    }
+
+    match object_id {
+        // ---------------------------------------------------------------------
+        // 1. Plugin object (Driver PlugIn)
+        // ---------------------------------------------------------------------
+        id if id == kAudioObjectPlugInObject => {
+            match selector {
+                kAudioObjectPropertyCustomPropertyInfoList => {
+                    let need = (PRISM_PLUGIN_CUSTOM_PROPERTIES.len()
+                        * std::mem::size_of::<AudioServerPlugInCustomPropertyInfo>())
+                        as UInt32;
+                    if _in_data_size < need {
+                        return kAudioHardwareBadPropertySizeError as OSStatus;
+                    }
+                    let out = _out_data as *mut AudioServerPlugInCustomPropertyInfo;
+                    for (i, (selector, data_type)) in
+                        PRISM_PLUGIN_CUSTOM_PROPERTIES.iter().enumerate()
+                    {
+                        let entry = out.add(i);
+                        (*entry).mSelector = *selector;
+                        (*entry).mPropertyDataType = *data_type;
+                        (*entry).mQualifierDataType = kAudioServerPlugInCustomPropertyDataTypeNone;
+                    }
+                    *_out_data_size = need;
+                    return 0;
+                }
+                kAudioPrismPropertyPluginInfo => {
+                    let encoded = encode_plugin_info(&*driver);
+                    write_cfdata(_out_data, _out_data_size, CFData::from_buffer(&encoded));
+                }
+                kAudioObjectPropertyBaseClass => {
+                    let out = _out_data as *mut AudioClassID;
+                    *out = kAudioObjectClassID;
+                    *_out_data_size = std::mem::size_of::<AudioClassID>() as UInt32;
+                }
+                kAudioObjectPropertyClass => {
+                    let out = _out_data as *mut AudioClassID;
+                    *out = kAudioPlugInClassID;
+                    *_out_data_size = std::mem::size_of::<AudioClassID>() as UInt32;
+                }
+                kAudioObjectPropertyOwner => {
+                    let out = _out_data as *mut AudioObjectID;
+                    *out = kAudioObjectUnknown;
+                    *_out_data_size = std::mem::size_of::<AudioObjectID>() as UInt32;
+                }
+                kAudioObjectPropertyManufacturer => {
+                    return write_cfstring(_out_data, _out_data_size, c"PetitStrawberry");
+                }
+                kAudioPlugInPropertyResourceBundle => {
+                    return write_cfstring(_out_data, _out_data_size, c"dev.ichigo.driver.Prism");
+                }
+                kAudioPlugInPropertyDeviceList | kAudioObjectPropertyOwnedObjects => {
+                    let out = _out_data as *mut AudioObjectID;
+                    *out = DEVICE_ID;
+                    *_out_data_size = std::mem::size_of::<AudioObjectID>() as UInt32;
+
+                    // Late notification: send 'cust' after the plugin's device list has been retrieved
+                    if let Some(host) = (*driver).host {
+                        if let Some(prop_changed) = (*host).PropertiesChanged {
+                            let addr_cust = AudioObjectPropertyAddress {
+                                mSelector: kAudioObjectPropertyCustomPropertyInfoList,
+                                mScope: kAudioObjectPropertyScopeGlobal,
+                                mElement: kAudioObjectPropertyElementMain,
+                            };
+                            prop_changed(host, DEVICE_ID, 1, &addr_cust);
+                            log_msg(
+                                Category::Properties,
+                                "Prism: Late notification sent for Device 'cust' property",
+                            );
+                        }
+                    }
+                }
+                kAudioPlugInPropertyTranslateUIDToDevice => {
+                    let mut device_id = kAudioObjectUnknown;
+                    if _qualifier_data_size == std::mem::size_of::<CFStringRef>() as UInt32
+                        && !_qualifier_data.is_null()
+                    {
+                        let uid = *(_qualifier_data as *const CFStringRef);
+                        let Some(my_uid) = create_cfstring(c"dev.ichigo.driver.Prism.Device")
+                        else {
+                            return kAudioHardwareUnspecifiedError as OSStatus;
+                        };
+                        if !uid.is_null()
+                            && CFStringCompare(uid, my_uid.as_concrete_TypeRef() as _, 0) == 0
+                        {
+                            device_id = DEVICE_ID;
+                        }
+                    }
+                    let out = _out_data as *mut AudioObjectID;
+                    *out = device_id;
+                    *_out_data_size = std::mem::size_of::<AudioObjectID>() as UInt32;
+                }
+                _ => {
+                    return kAudioHardwareUnknownPropertyError as OSStatus;
+                }
+            }
+        }
+
+        // ---------------------------------------------------------------------
+        // 2. Device object (The Prism Device)
+        // ---------------------------------------------------------------------
+        DEVICE_ID => {
+            #[allow(
+                non_upper_case_globals,
+                unreachable_patterns,
+                clippy::match_overlapping_arm
+            )]
+            match selector {
+                kAudioObjectPropertyCustomPropertyInfoList => {
+                    log_msg(
+                        Category::Properties,
+                        "Prism: GetPropertyData(Device) -> CustomPropertyInfoList",
+                    );
+
+                    let need = (PRISM_CUSTOM_PROPERTIES.len()
+                        * std::mem::size_of::<AudioServerPlugInCustomPropertyInfo>())
+                        as UInt32;
+                    if *_out_data_size < need {
+                        return kAudioHardwareBadPropertySizeError as OSStatus;
+                    }
+
+                    let out = _out_data as *mut AudioServerPlugInCustomPropertyInfo;
+                    for (i, (selector, data_type)) in PRISM_CUSTOM_PROPERTIES.iter().enumerate() {
+                        let entry = out.add(i);
+                        (*entry).mSelector = *selector;
+                        (*entry).mPropertyDataType = *data_type;
+                        // 'clnt' takes an optional { pid?, bundle_id? } to narrow the list
+                        (*entry).mQualifierDataType = if *selector == kAudioPrismPropertyClientList
+                        {
+                            kAudioServerPlugInCustomPropertyDataTypeCFPropertyList
+                        } else {
+                            kAudioServerPlugInCustomPropertyDataTypeNone
+                        };
+                    }
+                    *_out_data_size = need;
+                    return 0;
+                }
+                kAudioPrismPropertyRoutingTable => {
+                    log_msg(
+                        Category::Properties,
+                        "Prism: GetPropertyData(Device) -> RoutingTable",
+                    );
+                    let size = std::mem::size_of::<PrismRoutingUpdate>() as UInt32;
+                    let out = _out_data as *mut PrismRoutingUpdate;
+                    unsafe {
+                        *out = PrismRoutingUpdate {
+                            pid: 0,
+                            channel_offset: 0,
+                        };
+                    }
+                    *_out_data_size = size;
+                    return 0;
+                }
+                kAudioPrismPropertyClientList => {
+                    log_msg(
+                        Category::Properties,
+                        "Prism: GetPropertyData(Device) -> ClientList",
+                    );
+                    if _qualifier_data_size == 0 {
+                        write_cfdata(_out_data, _out_data_size, client_list_cfdata(&*driver));
+                        return 0;
+                    }
+                    // A qualified read bypasses the cache, which holds the whole list
+                    let Some(filter) =
+                        ClientFilter::from_qualifier(_qualifier_data_size, _qualifier_data)
+                    else {
+                        log_error(
+                            Category::Properties,
+                            "Prism: CLNT rejected: qualifier is not a { pid, bundle_id } plist",
+                        );
+                        return kAudioHardwareIllegalOperationError as OSStatus;
+                    };
+                    let encoded = encode_client_list(&*driver, &filter);
+                    write_cfdata(_out_data, _out_data_size, CFData::from_buffer(&encoded));
+                    return 0;
+                }
+                kAudioPrismPropertyIoStats
+                | kAudioPrismPropertyMeters
+                | kAudioPrismPropertyCaptureAcl
+                | kAudioPrismPropertyResync
+                | kAudioPrismPropertyEvents
+                | kAudioPrismPropertyLogLevel
+                | kAudioPrismPropertyClearBuffers
+                | kAudioPrismPropertyClockRate
+                | kAudioPrismPropertyConfig
+                | kAudioPrismPropertyMatrix
+                | kAudioPrismPropertyRouteBundle
+                | kAudioPrismPropertyMonitor
+                | kAudioPrismPropertyReservations
+                | kAudioPrismPropertyGapPolicy
+                | kAudioPrismPropertyBusOccupancy
+                | kAudioPrismPropertyRoutingFreeze
+                | kAudioPrismPropertyClientGain
+                | kAudioPrismPropertySolo
+                | kAudioPrismPropertyCaptureAllowlist
+                | kAudioPrismPropertyCaptureMask
+                | kAudioPrismPropertyCaptureExclusions
+                | kAudioPrismPropertyRouteEvent
+                | kAudioPrismPropertyBusLabels => {
+                    let encoded = match selector {
+                        kAudioPrismPropertyMeters => encode_meters(&*driver),
+                        kAudioPrismPropertyCaptureAcl => encode_capture_rules(&*driver),
+                        kAudioPrismPropertyResync => encode_resync_state(&*driver),
+                        kAudioPrismPropertyEvents => encode_pending_events(&*driver),
+                        kAudioPrismPropertyLogLevel => encode_log_level(),
+                        kAudioPrismPropertyClearBuffers => encode_buffer_regions(&*driver),
+                        kAudioPrismPropertyClockRate => encode_clock_rate(&*driver),
+                        kAudioPrismPropertyConfig => encode_config(&*driver),
+                        kAudioPrismPropertyMatrix => encode_matrices(&*driver),
+                        kAudioPrismPropertyRouteBundle => encode_bundle_routes(&*driver),
+                        kAudioPrismPropertyMonitor => encode_monitor(&*driver),
+                        kAudioPrismPropertyReservations => encode_reservations(&*driver),
+                        kAudioPrismPropertyGapPolicy => encode_gap_policies(&*driver),
+                        kAudioPrismPropertyBusOccupancy => encode_bus_occupancy(&*driver),
+                        kAudioPrismPropertyRoutingFreeze => encode_routing_freeze(&*driver),
+                        kAudioPrismPropertyClientGain => encode_client_gains(&*driver),
+                        kAudioPrismPropertySolo => encode_solo(&*driver),
+                        kAudioPrismPropertyCaptureAllowlist => encode_capture_allowlist(&*driver),
+                        kAudioPrismPropertyCaptureMask => encode_capture_masks(&*driver),
+                        kAudioPrismPropertyCaptureExclusions => encode_capture_exclusions(&*driver),
+                        kAudioPrismPropertyRouteEvent => encode_route_change(&*driver),
+                        kAudioPrismPropertyBusLabels => encode_bus_labels(&*driver),
+                        _ => encode_io_stats(&*driver),
+                    };
+                    write_cfdata(_out_data, _out_data_size, CFData::from_buffer(&encoded));
+                    return 0;
+                }
+                kAudioPrismPropertyTelemetry => {
+                    // Name of the shm object; unavailable if the driver could not map it
+                    if (*driver).telemetry.is_none() {
+                        return kAudioHardwareUnsupportedOperationError as OSStatus;
+                    }
+                    let Ok(name) = CString::new(TELEMETRY_SHM_NAME) else {
+                        return kAudioHardwareUnspecifiedError as OSStatus;
+                    };
+                    return write_cfstring(_out_data, _out_data_size, &name);
+                }
+                kAudioObjectPropertyControlList => {
+                    let out = _out_data as *mut AudioObjectID;
+                    let count = (*driver).config.num_channels;
+                    for index in 0..count {
+                        *out.add(index as usize) = VOLUME_CONTROL_BASE_ID + index;
+                    }
+                    *_out_data_size =
+                        (count as usize * std::mem::size_of::<AudioObjectID>()) as UInt32;
+                }
+                kAudioObjectPropertyBaseClass => {
+                    let out = _out_data as *mut AudioClassID;
+                    *out = kAudioObjectClassID;
+                    *_out_data_size = std::mem::size_of::<AudioClassID>() as UInt32;
+                }
+                kAudioObjectPropertyClass => {
+                    let out = _out_data as *mut AudioClassID;
+                    *out = kAudioDeviceClassID;
+                    *_out_data_size = std::mem::size_of::<AudioClassID>() as UInt32;
+                }
+                kAudioObjectPropertyOwner => {
+                    let out = _out_data as *mut AudioObjectID;
+                    *out = kAudioObjectPlugInObject;
+                    *_out_data_size = std::mem::size_of::<AudioObjectID>() as UInt32;
+                }
+                kAudioObjectPropertyManufacturer => {
+                    return write_cfstring(_out_data, _out_data_size, c"PetitStrawberry");
+                }
+                kAudioDevicePropertyDeviceUID => {
+                    return write_cfstring(
+                        _out_data,
+                        _out_data_size,
+                        c"dev.ichigo.driver.Prism.Device",
+                    );
+                }
+                kAudioDevicePropertyModelUID => {
+                    return write_cfstring(
+                        _out_data,
+                        _out_data_size,
+                        c"dev.ichigo.driver.Prism.Model",
+                    );
+                }
+                kAudioDevicePropertyDeviceName | kAudioObjectPropertyName => {
+                    return write_cfstring(_out_data, _out_data_size, c"Prism");
+                }
+                kAudioDevicePropertyTransportType => {
+                    let out = _out_data as *mut UInt32;
+                    *out = kAudioDeviceTransportTypeVirtual;
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioDevicePropertyDeviceIsRunning => {
+                    let out = _out_data as *mut UInt32;
+                    *out = if (*driver).client_count.load(Ordering::SeqCst) > 0 {
+                        1
+                    } else {
+                        0
+                    };
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioDevicePropertyDeviceIsAlive => {
+                    let out = _out_data as *mut UInt32;
+                    *out = 1;
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioDevicePropertyClockDomain => {
+                    let out = _out_data as *mut UInt32;
+                    *out = (*driver).clock_domain.load(Ordering::Relaxed);
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioDevicePropertyHogMode => {
+                    let out = _out_data as *mut pid_t;
+                    *out = (*driver).hog_pid.load(Ordering::Acquire);
+                    *_out_data_size = std::mem::size_of::<pid_t>() as UInt32;
+                }
+                kAudioDevicePropertyIsHidden
+                | kAudioDevicePropertyStreamsIsSettable
+                | kAudioDevicePropertyClockSource
+                | kAudioDevicePropertyDataSource => {
+                    let out = _out_data as *mut UInt32;
+                    *out = 0;
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                // Timestamps come from the host clock, so the HAL may trust them; the
+                // IIR smooths whatever is left after a 'rate' step
+                kAudioDevicePropertyClockIsStable => {
+                    let out = _out_data as *mut UInt32;
+                    *out = 1;
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioDevicePropertyClockAlgorithm => {
+                    let out = _out_data as *mut UInt32;
+                    *out = kAudioDeviceClockAlgorithmSimpleIIR;
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioDevicePropertyDeviceCanBeDefaultDevice
+                | kAudioDevicePropertyDeviceCanBeDefaultSystemDevice => {
+                    let out = _out_data as *mut UInt32;
+                    *out = 1;
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioDevicePropertyLatency | kAudioDevicePropertySafetyOffset => {
+                    let out = _out_data as *mut UInt32;
+                    *out = (*driver)
+                        .timing_frames(selector, address.mScope)
+                        .load(Ordering::Relaxed);
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioDevicePropertyNominalSampleRate => {
+                    let out = _out_data as *mut Float64;
+                    *out = 48000.0;
+                    *_out_data_size = std::mem::size_of::<Float64>() as UInt32;
+                }
+                kAudioDevicePropertyAvailableNominalSampleRates => {
+                    let out = _out_data as *mut AudioValueRange;
+                    *out = AudioValueRange {
+                        mMinimum: 44100.0,
+                        mMaximum: 96000.0,
+                    };
+                    *_out_data_size = std::mem::size_of::<AudioValueRange>() as UInt32;
+                }
+                kAudioDevicePropertyBufferFrameSize => {
+                    let out = _out_data as *mut UInt32;
+                    *out = (*driver).buffer_frame_size.load(Ordering::Relaxed);
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioDevicePropertyZeroTimeStampPeriod => {
+                    let out = _out_data as *mut UInt32;
+                    *out = (*driver).zero_timestamp_period.load(Ordering::Relaxed);
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioDevicePropertyBufferFrameSizeRange => {
+                    let out = _out_data as *mut AudioValueRange;
+                    *out = AudioValueRange {
+                        mMinimum: MIN_IO_FRAMES as f64,
+                        mMaximum: MAX_IO_FRAMES as f64,
+                    };
+                    *_out_data_size = std::mem::size_of::<AudioValueRange>() as UInt32;
+                }
+                kAudioDevicePropertyRingBufferFrameSize => {
+                    let out = _out_data as *mut UInt32;
+                    *out = (*driver).buffer_frame_size.load(Ordering::Relaxed);
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioObjectPropertyScope => {
+                    let out = _out_data as *mut UInt32;
+                    *out = kAudioObjectPropertyScopeGlobal;
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioObjectPropertyElement => {
+                    let out = _out_data as *mut UInt32;
+                    *out = kAudioObjectPropertyElementMain;
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioObjectPropertyOwnedObjects => {
+                    let out = _out_data as *mut AudioObjectID;
+                    let count = (*driver).config.num_channels;
+                    unsafe {
+                        *out.offset(0) = INPUT_STREAM_ID;
+                        *out.offset(1) = OUTPUT_STREAM_ID;
+                        for index in 0..count {
+                            *out.add(2 + index as usize) = VOLUME_CONTROL_BASE_ID + index;
+                        }
+                    }
+                    *_out_data_size =
+                        ((2 + count as usize) * std::mem::size_of::<AudioObjectID>()) as UInt32;
+                }
+                kAudioDevicePropertyPreferredChannelsForStereo => {
+                    // The main mix on both sides: WriteMix lands on bus 1-2, and a
+                    // routed client's stereo fold starts its output stream
+                    if (_in_data_size as usize) < 2 * std::mem::size_of::<UInt32>() {
+                        return kAudioHardwareBadPropertySizeError as OSStatus;
+                    }
+                    let out = _out_data as *mut UInt32;
+                    *out = 1;
+                    *out.add(1) = 2;
+                    *_out_data_size = (2 * std::mem::size_of::<UInt32>()) as UInt32;
+                }
+                kAudioDevicePropertyRelatedDevices => {
+                    let out = _out_data as *mut AudioObjectID;
+                    let capacity = _in_data_size as usize / std::mem::size_of::<AudioObjectID>();
+                    let count = RELATED_DEVICES.len().min(capacity);
+                    ptr::copy_nonoverlapping(RELATED_DEVICES.as_ptr(), out, count);
+                    *_out_data_size = (count * std::mem::size_of::<AudioObjectID>()) as UInt32;
+                }
+                kAudioDevicePropertyStreams => {
+                    let scope = address.mScope;
+                    let out = _out_data as *mut AudioObjectID;
+                    let mut count = 0;
+                    unsafe {
+                        if scope_includes(scope, kAudioObjectPropertyScopeInput) {
+                            *out.offset(count) = INPUT_STREAM_ID;
+                            count += 1;
+                        }
+                        if scope_includes(scope, kAudioObjectPropertyScopeOutput) {
+                            *out.offset(count) = OUTPUT_STREAM_ID;
+                            count += 1;
+                        }
+                    }
+                    *_out_data_size =
+                        (count as usize * std::mem::size_of::<AudioObjectID>()) as UInt32;
+                }
+                _ => {
+                    return kAudioHardwareUnknownPropertyError as OSStatus;
+                }
+            }
+        }
+
+        // ---------------------------------------------------------------------
+        // 3. Stream object
+        // ---------------------------------------------------------------------
+        INPUT_STREAM_ID | OUTPUT_STREAM_ID => {
+            #[allow(non_upper_case_globals)]
+            match selector {
+                kAudioObjectPropertyCustomPropertyInfoList => {
+                    *_out_data_size = 0;
+                    return 0;
+                }
+                kAudioObjectPropertyControlList => {
+                    *_out_data_size = 0;
+                }
+                kAudioObjectPropertyBaseClass => {
+                    let out = _out_data as *mut AudioClassID;
+                    *out = kAudioObjectClassID;
+                    *_out_data_size = std::mem::size_of::<AudioClassID>() as UInt32;
+                }
+                kAudioObjectPropertyClass => {
+                    let out = _out_data as *mut AudioClassID;
+                    *out = kAudioStreamClassID;
+                    *_out_data_size = std::mem::size_of::<AudioClassID>() as UInt32;
+                }
+                kAudioObjectPropertyOwner => {
+                    let out = _out_data as *mut AudioObjectID;
+                    *out = DEVICE_ID;
+                    *_out_data_size = std::mem::size_of::<AudioObjectID>() as UInt32;
+                }
+                kAudioObjectPropertyScope => {
+                    let out = _out_data as *mut UInt32;
+                    *out = if object_id == INPUT_STREAM_ID {
+                        kAudioObjectPropertyScopeInput
+                    } else {
+                        kAudioObjectPropertyScopeOutput
+                    };
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioObjectPropertyElement => {
+                    let out = _out_data as *mut UInt32;
+                    *out = kAudioObjectPropertyElementMain;
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioStreamPropertyDirection => {
+                    let out = _out_data as *mut UInt32;
+                    *out = if object_id == INPUT_STREAM_ID { 1 } else { 0 };
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioStreamPropertyTerminalType => {
+                    let out = _out_data as *mut UInt32;
+                    *out = if object_id == INPUT_STREAM_ID {
+                        0x6D696320
+                    } else {
+                        0x73706B72
+                    };
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioStreamPropertyStartingChannel => {
+                    let out = _out_data as *mut UInt32;
+                    *out = 1;
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioStreamPropertyVirtualFormat | kAudioStreamPropertyPhysicalFormat => {
+                    let out = _out_data as *mut AudioStreamBasicDescription;
+                    let non_interleaved = (*driver)
+                        .non_interleaved_flag(object_id)
+                        .is_some_and(|flag| flag.load(Ordering::Relaxed));
+                    *out = stream_format((*driver).stream_channels(object_id), non_interleaved);
+                    *_out_data_size = std::mem::size_of::<AudioStreamBasicDescription>() as UInt32;
+                }
+                kAudioStreamPropertyPhysicalFormats
+                | kAudioStreamPropertyAvailableVirtualFormats
+                | kAudioStreamPropertyAvailablePhysicalFormats => {
+                    // Interleaved first, so hosts that take the first entry keep today's layout
+                    let out = _out_data as *mut AudioStreamRangedDescription;
+                    let entry_size = std::mem::size_of::<AudioStreamRangedDescription>();
+                    let count = (_in_data_size as usize / entry_size).min(2);
+                    for (i, non_interleaved) in [false, true].into_iter().take(count).enumerate() {
+                        *out.add(i) = AudioStreamRangedDescription {
+                            mFormat: stream_format(
+                                (*driver).stream_channels(object_id),
+                                non_interleaved,
+                            ),
+                            mSampleRateRange: AudioValueRange {
+                                mMinimum: 48000.0,
+                                mMaximum: 48000.0,
+                            },
+                        };
+                    }
+                    *_out_data_size = (count * entry_size) as UInt32;
+                }
+                _ => {
+                    return kAudioHardwareUnknownPropertyError as OSStatus;
+                }
+            }
+        }
+
+        // ---------------------------------------------------------------------
+        // 4. Input volume controls
+        // ---------------------------------------------------------------------
+        id if volume_control_channel(driver, id).is_some() => {
+            let channel = volume_control_channel(driver, id).unwrap_or(1);
+            match selector {
+                kAudioObjectPropertyBaseClass => {
+                    *(_out_data as *mut AudioClassID) = kAudioLevelControlClassID;
+                    *_out_data_size = std::mem::size_of::<AudioClassID>() as UInt32;
+                }
+                kAudioObjectPropertyClass => {
+                    *(_out_data as *mut AudioClassID) = kAudioVolumeControlClassID;
+                    *_out_data_size = std::mem::size_of::<AudioClassID>() as UInt32;
+                }
+                kAudioObjectPropertyOwner => {
+                    *(_out_data as *mut AudioObjectID) = DEVICE_ID;
+                    *_out_data_size = std::mem::size_of::<AudioObjectID>() as UInt32;
+                }
+                kAudioControlPropertyScope => {
+                    *(_out_data as *mut UInt32) = kAudioObjectPropertyScopeInput;
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioControlPropertyElement => {
+                    *(_out_data as *mut UInt32) = channel;
+                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
+                }
+                kAudioLevelControlPropertyScalarValue => {
+                    *(_out_data as *mut Float32) =
+                        volume_db_to_scalar(channel_trim_db(&*driver, channel));
+                    *_out_data_size = std::mem::size_of::<Float32>() as UInt32;
+                }
+                kAudioLevelControlPropertyDecibelValue => {
+                    *(_out_data as *mut Float32) = channel_trim_db(&*driver, channel);
+                    *_out_data_size = std::mem::size_of::<Float32>() as UInt32;
+                }
+                kAudioLevelControlPropertyDecibelRange => {
+                    *(_out_data as *mut AudioValueRange) = AudioValueRange {
+                        mMinimum: f64::from(VOLUME_MIN_DB),
+                        mMaximum: f64::from(VOLUME_MAX_DB),
+                    };
+                    *_out_data_size = std::mem::size_of::<AudioValueRange>() as UInt32;
+                }
+                // The conversions are in/out: the caller passes the value to convert in the buffer
+                kAudioLevelControlPropertyConvertScalarToDecibels => {
+                    let value = _out_data as *mut Float32;
+                    *value = volume_scalar_to_db(*value);
+                    *_out_data_size = std::mem::size_of::<Float32>() as UInt32;
+                }
+                kAudioLevelControlPropertyConvertDecibelsToScalar => {
+                    let value = _out_data as *mut Float32;
+                    *value = volume_db_to_scalar(*value);
+                    *_out_data_size = std::mem::size_of::<Float32>() as UInt32;
+                }
+                _ => {
+                    return kAudioHardwareUnknownPropertyError as OSStatus;
+                }
+            }
+        }
+        _ => return kAudioHardwareBadObjectError as OSStatus,
+    }
+    0
+}
+
+unsafe extern "C" fn set_property_data(
+    _self: AudioServerPlugInDriverRef,
+    _object_id: AudioObjectID,
+    _client_process_id: pid_t,
+    _address: *const AudioObjectPropertyAddress,
+    _qualifier_data_size: UInt32,
+    _qualifier_data: *const c_void,
+    _in_data_size: UInt32,
+    _in_data: *const c_void,
+) -> OSStatus {
+    let driver = _self as *mut PrismDriver;
+    let address = *_address;
+    let selector = address.mSelector;
+    log_msg(
+        Category::Properties,
+        &format!(
+            "Prism: SetPropertyData called. Object: {}, Selector: {}",
+            _object_id, selector
+        ),
+    );
+    if !addresses_main_element(&address) {
+        return kAudioHardwareUnknownPropertyError as OSStatus;
+    }
+
+    if selector == kAudioDevicePropertyBufferFrameSize {
+        if _in_data_size != std::mem::size_of::<UInt32>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+
+        let requested_frames = unsafe { *(_in_data as *const UInt32) };
+        if !(MIN_IO_FRAMES..=MAX_IO_FRAMES).contains(&(requested_frames as usize)) {
+            log_msg(
+                Category::Properties,
+                &format!(
+                    "Prism: BufferFrameSize {} outside {}..={}",
+                    requested_frames, MIN_IO_FRAMES, MAX_IO_FRAMES
+                ),
+            );
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+
+        unsafe {
+            if (*driver).buffer_frame_size.load(Ordering::Relaxed) == requested_frames {
+                (*driver)
+                    .pending_buffer_frame_size
+                    .store(0, Ordering::Release);
+                return 0;
+            }
+
+            // The size changes between IO cycles: the HAL stops IO and calls
+            // PerformDeviceConfigurationChange, which applies it
+            (*driver)
+                .pending_buffer_frame_size
+                .store(requested_frames, Ordering::Release);
+            let status = request_config_change(driver, CONFIG_CHANGE_BUFFER_FRAME_SIZE);
+            if status != 0 {
+                (*driver)
+                    .pending_buffer_frame_size
+                    .store(0, Ordering::Release);
+                log_error(
+                    Category::Properties,
+                    &format!(
+                        "Prism: BufferFrameSize {} change request failed: {}",
+                        requested_frames, status
+                    ),
+                );
+                return status;
+            }
+        }
+
+        return 0;
+    }
+
+    if selector == kAudioPrismPropertyRoutingTable {
+        // CFData-only: expect a CFDataRef containing the little-endian PrismRoutingUpdate bytes
+        extern "C" {
+            fn CFDataGetLength(theData: CFDataRef) -> isize;
+            fn CFDataGetBytePtr(theData: CFDataRef) -> *const u8;
+        }
+
+        let expected_struct_size = std::mem::size_of::<PrismRoutingUpdate>();
+        let cfdata_ref_size = std::mem::size_of::<CFDataRef>();
+
+        if _in_data_size != cfdata_ref_size as UInt32 {
+            log_error(
+                Category::Routing,
+                &format!(
+                    "Prism: SetPropertyData ROUT rejected: expected CFDataRef size={}, got={}",
+                    cfdata_ref_size, _in_data_size
+                ),
+            );
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+
+        let data_ref = *(_in_data as *const CFDataRef);
+        if data_ref.is_null() {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+
+        let len = unsafe { CFDataGetLength(data_ref) } as usize;
+        let ptr = unsafe { CFDataGetBytePtr(data_ref) };
+        // A binary plist instead of the struct is a batch of updates (an array) or a
+        // broadcast with exclusions (a dictionary)
+        if !ptr.is_null() && len >= 6 && std::slice::from_raw_parts(ptr, 6) == b"bplist" {
+            let bytes = std::slice::from_raw_parts(ptr, len);
+            return match Value::from_reader(std::io::Cursor::new(bytes)) {
+                Ok(Value::Array(items)) => apply_rout_batch(driver, &items),
+                Ok(Value::Dictionary(dict)) => apply_rout_broadcast(driver, &dict),
+                _ => {
+                    log_error(
+                        Category::Routing,
+                        "Prism: ROUT rejected: plist payload is neither an array nor a dictionary",
+                    );
+                    kAudioHardwareIllegalOperationError as OSStatus
+                }
+            };
+        }
+        if ptr.is_null() || len < expected_struct_size {
+            log_error(
+                Category::Routing,
+                &format!(
+                    "Prism: SetPropertyData ROUT rejected: CFData length {} too small",
+                    len
+                ),
+            );
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+
+        // Copy into local buffer and parse little-endian fields
+        let mut buf = [0u8; std::mem::size_of::<PrismRoutingUpdate>()];
+        unsafe {
+            ptr::copy_nonoverlapping(ptr, buf.as_mut_ptr(), buf.len());
+        }
+        let pid = i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        let offset = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
+
+        // Optional gain/pan trailer
+        let (gain, pan) = if len >= expected_struct_size + PRISM_ROUTING_MIX_SIZE {
+            let mut mix = [0u8; PRISM_ROUTING_MIX_SIZE];
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    ptr.add(expected_struct_size),
+                    mix.as_mut_ptr(),
+                    mix.len(),
+                );
+            }
+            (
+                f32::from_le_bytes([mix[0], mix[1], mix[2], mix[3]]),
+                f32::from_le_bytes([mix[4], mix[5], mix[6], mix[7]]),
+            )
+        } else {
+            (f32::NAN, f32::NAN)
+        };
+        let width_at = expected_struct_size + PRISM_ROUTING_MIX_SIZE;
+        let width = if len >= width_at + PRISM_ROUTING_WIDTH_SIZE {
+            let mut bytes = [0u8; PRISM_ROUTING_WIDTH_SIZE];
+            unsafe {
+                ptr::copy_nonoverlapping(ptr.add(width_at), bytes.as_mut_ptr(), bytes.len());
+            }
+            f32::from_le_bytes(bytes)
+        } else {
+            f32::NAN
+        };
+        let flag_at = |index: usize| {
+            let at = width_at + PRISM_ROUTING_WIDTH_SIZE + index * PRISM_ROUTING_FLAG_SIZE;
+            if len < at + PRISM_ROUTING_FLAG_SIZE {
+                return PRISM_ROUTING_KEEP;
+            }
+            let mut bytes = [0u8; PRISM_ROUTING_FLAG_SIZE];
+            unsafe {
+                ptr::copy_nonoverlapping(ptr.add(at), bytes.as_mut_ptr(), bytes.len());
+            }
+            u32::from_le_bytes(bytes)
+        };
+        let (mono_code, swap_code) = (flag_at(0), flag_at(1));
+        let mono = MonoFold::ALL.get(mono_code as usize).copied();
+        let swap = match swap_code {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        };
+
+        log_msg(
+            Category::Routing,
+            &format!(
+                "Prism: SetPropertyData ROUT (CFData) PID={}, Offset={}, Gain={}, Pan={}, Width={}, \
+                 Mono={:?}, Swap={:?}",
+                pid, offset, gain, pan, width, mono, swap
+            ),
+        );
+
+        if offset == PRISM_UNROUTE_OFFSET {
+            return unroute_clients(driver, pid);
+        }
+
+        if (!gain.is_nan() && (gain.is_infinite() || gain < 0.0))
+            || (!pan.is_nan() && !(-1.0..=1.0).contains(&pan))
+            || (!width.is_nan() && !(0.0..=PRISM_MAX_WIDTH).contains(&width))
+            || (mono_code != PRISM_ROUTING_KEEP && mono.is_none())
+            || (swap_code != PRISM_ROUTING_KEEP && swap.is_none())
+        {
+            log_error(
+                Category::Routing,
+                &format!(
+                    "Prism: ROUT rejected: invalid gain={}, pan={}, width={}, mono={} or swap={}",
+                    gain, pan, width, mono_code, swap_code
+                ),
+            );
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+
+        let driver_ref = &*driver;
+        let slots = &driver_ref.slot_table().slots;
+
+        // Validate offset for 2ch write into 64ch bus, or the null bus
+        let max_channels = (*driver).config.num_channels;
+        if offset != NULL_BUS_OFFSET && (offset % 2 != 0 || offset + 1 >= max_channels) {
+            log_error(
+                Category::Routing,
+                &format!(
+                    "Prism: ROUT rejected: invalid channel_offset={}, max_channels={}",
+                    offset, max_channels
+                ),
+            );
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+
+        // The binary form cannot name an owner, so it never moves clients onto a
+        // reserved pair
+        if let Err(status) = check_reservation(driver, offset as usize, None, |slot| {
+            pid == -1 || slot.pid.load(Ordering::Acquire) == pid
+        }) {
+            return status;
+        }
+
+        // pid == -1 => broadcast to all clients
+        if pid == -1 {
+            for slot in slots.iter() {
+                if slot.client_id.load(Ordering::Acquire) == 0 {
+                    continue;
+                }
+                slot.apply_mix(gain, pan, width, mono, swap);
+                let prev = slot.reroute(offset as usize);
+                record_route_change(driver_ref, slot, prev, offset as usize, "broadcast");
+            }
+            log_msg(
+                Category::Routing,
+                &format!("Prism: Routing Update ROUT Broadcast. Offset={}", offset),
+            );
+            notify_device_event(driver, kAudioPrismPropertyClientList, PRISM_EVENT_ROUTING);
+            notify_channel_map_changed(driver, false);
+            return 0;
+        }
+
+        if pid != 0 {
+            let mut found = false;
+            for slot in slots.iter() {
+                if slot.pid.load(Ordering::Acquire) == pid {
+                    slot.apply_mix(gain, pan, width, mono, swap);
+                    let prev = slot.reroute(offset as usize);
+                    learn_route(driver_ref, slot, offset as usize);
+                    record_route_change(driver_ref, slot, prev, offset as usize, "route");
+                    log_msg(
+                        Category::Routing,
+                        &format!(
+                            "Prism: Routing Update via ROUT. PID={}, Offset={}",
+                            pid, offset
+                        ),
+                    );
+                    found = true;
+                }
+            }
+            if !found {
+                log_error(
+                    Category::Routing,
+                    &format!(
+                        "Prism: Routing Update via ROUT Failed. PID={} not found",
+                        pid
+                    ),
+                );
+            } else {
+                notify_device_event(driver, kAudioPrismPropertyClientList, PRISM_EVENT_ROUTING);
+                notify_channel_map_changed(driver, false);
+            }
+        }
+
+        return 0;
+    }
+
+    if selector == kAudioPrismPropertyMonitor {
+        // CFData holding a binary plist: { enabled?, sends?: [{ channel_offset, gain }] }.
+        // Each listed send replaces that pair's (gain 0 removes it); pairs not listed keep
+        // theirs. Pair 0 is the system mix the sends land on and cannot have one.
+        if _in_data_size != std::mem::size_of::<CFDataRef>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+        let data_ref = *(_in_data as *const CFDataRef);
+        if data_ref.is_null() {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        let data = CFData::wrap_under_get_rule(data_ref);
+        let dict = match Value::from_reader(std::io::Cursor::new(data.bytes())) {
+            Ok(Value::Dictionary(dict)) => dict,
+            _ => {
+                log_error(
+                    Category::Routing,
+                    "Prism: MNSD rejected: payload is not a plist dictionary",
+                );
+                return kAudioHardwareIllegalOperationError as OSStatus;
+            }
+        };
+        let enabled = dict.get("enabled").and_then(|v| v.as_boolean());
+        let max_channels = u64::from((*driver).config.num_channels);
+        let mut sends = Vec::new();
+        for item in dict
+            .get("sends")
+            .and_then(|v| v.as_array())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+        {
+            let entry = item.as_dictionary();
+            let offset = entry
+                .and_then(|entry| entry.get("channel_offset"))
+                .and_then(|v| v.as_unsigned_integer())
+                .unwrap_or(u64::MAX);
+            let gain = entry
+                .and_then(|entry| entry.get("gain"))
+                .and_then(|v| v.as_real())
+                .map_or(f32::NAN, |v| v as f32);
+            if offset < 2
+                || offset % 2 != 0
+                || offset + 1 >= max_channels
+                || !gain.is_finite()
+                || gain < 0.0
             {
-                *_out_data_size = std::mem::size_of::<CFStringRef>() as UInt32;
-            } else if selector == kAudioObjectPropertyOwnedObjects {
-                *_out_data_size = (2 * std::mem::size_of::<AudioObjectID>()) as UInt32;
-            } else if selector == kAudioDevicePropertyStreams {
-                let scope = address.mScope;
-                let mut count = 0;
-                if scope == kAudioObjectPropertyScopeGlobal
-                    || scope == kAudioObjectPropertyScopeInput
-                {
-                    count += 1;
+                log_error(
+                    Category::Routing,
+                    &format!(
+                        "Prism: MNSD rejected: invalid send {:?} (max_channels={})",
+                        item, max_channels
+                    ),
+                );
+                return kAudioHardwareIllegalOperationError as OSStatus;
+            }
+            sends.push((offset as usize / 2, gain));
+        }
+
+        for (pair, gain) in &sends {
+            (*driver).monitor_sends[*pair].store(gain.to_bits(), Ordering::Relaxed);
+        }
+        if let Some(enabled) = enabled {
+            (*driver).monitor_enabled.store(enabled, Ordering::Relaxed);
+        }
+        log_msg(
+            Category::Routing,
+            &format!(
+                "Prism: Monitor downmix enabled={} sends changed={}",
+                (*driver).monitor_enabled.load(Ordering::Relaxed),
+                sends.len()
+            ),
+        );
+        notify_device_event(driver, kAudioPrismPropertyMonitor, PRISM_EVENT_CONFIG);
+        return 0;
+    }
+
+    if selector == kAudioPrismPropertyRoutingFreeze {
+        // CFData holding a binary plist: { frozen, timeout_ms? }. Freezing pins every
+        // slot to the pair it plays now; 'rout' updates made meanwhile only stage, and
+        // thawing (or the timeout lapsing) switches them all at once. Freezing again
+        // while frozen just extends the timeout.
+        if _in_data_size != std::mem::size_of::<CFDataRef>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+        let data_ref = *(_in_data as *const CFDataRef);
+        if data_ref.is_null() {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        let data = CFData::wrap_under_get_rule(data_ref);
+        let dict = match Value::from_reader(std::io::Cursor::new(data.bytes())) {
+            Ok(Value::Dictionary(dict)) => dict,
+            _ => {
+                log_error(
+                    Category::Routing,
+                    "Prism: FREZ rejected: payload is not a plist dictionary",
+                );
+                return kAudioHardwareIllegalOperationError as OSStatus;
+            }
+        };
+        let frozen = dict.get("frozen").and_then(|v| v.as_boolean());
+        let timeout_ms = dict
+            .get("timeout_ms")
+            .map(|v| v.as_unsigned_integer().unwrap_or(0))
+            .unwrap_or(u64::from(DEFAULT_ROUTING_FREEZE_MS));
+        let Some(frozen) =
+            frozen.filter(|_| (1..=u64::from(MAX_ROUTING_FREEZE_MS)).contains(&timeout_ms))
+        else {
+            log_error(
+                Category::Routing,
+                &format!(
+                    "Prism: FREZ rejected: {:?} (max timeout {} ms)",
+                    dict, MAX_ROUTING_FREEZE_MS
+                ),
+            );
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        };
+        freeze_routing(&*driver, frozen.then_some(timeout_ms));
+        notify_device_event(
+            driver,
+            kAudioPrismPropertyRoutingFreeze,
+            PRISM_EVENT_ROUTING,
+        );
+        return 0;
+    }
+
+    if selector == kAudioPrismPropertySolo {
+        // CFData holding a binary plist: { clear?, solo?: [channel_offset],
+        // unsolo?: [channel_offset] }, applied in that order. Pair 0 (the system mix)
+        // may be soloed like any other.
+        if _in_data_size != std::mem::size_of::<CFDataRef>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+        let data_ref = *(_in_data as *const CFDataRef);
+        if data_ref.is_null() {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        let data = CFData::wrap_under_get_rule(data_ref);
+        let dict = match Value::from_reader(std::io::Cursor::new(data.bytes())) {
+            Ok(Value::Dictionary(dict)) => dict,
+            _ => {
+                log_error(
+                    Category::Routing,
+                    "Prism: SOLO rejected: payload is not a plist dictionary",
+                );
+                return kAudioHardwareIllegalOperationError as OSStatus;
+            }
+        };
+        let max_channels = u64::from((*driver).config.num_channels);
+        let pairs = |key: &str| -> Option<Vec<usize>> {
+            let Some(value) = dict.get(key) else {
+                return Some(Vec::new());
+            };
+            value
+                .as_array()?
+                .iter()
+                .map(|v| {
+                    v.as_unsigned_integer()
+                        .filter(|&offset| offset % 2 == 0 && offset + 1 < max_channels)
+                        .map(|offset| offset as usize / 2)
+                })
+                .collect()
+        };
+        let clear = dict.get("clear").and_then(|v| v.as_boolean());
+        let (Some(solo), Some(unsolo)) = (pairs("solo"), pairs("unsolo")) else {
+            log_error(
+                Category::Routing,
+                &format!(
+                    "Prism: SOLO rejected: {:?} (max_channels={})",
+                    dict, max_channels
+                ),
+            );
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        };
+
+        if clear == Some(true) {
+            for soloed in &(*driver).bus_solo {
+                soloed.store(false, Ordering::Relaxed);
+            }
+        }
+        for pair in unsolo {
+            (*driver).bus_solo[pair].store(false, Ordering::Relaxed);
+        }
+        for pair in solo {
+            (*driver).bus_solo[pair].store(true, Ordering::Relaxed);
+        }
+        log_msg(
+            Category::Routing,
+            &format!(
+                "Prism: SOLO pairs={:?}",
+                (*driver)
+                    .bus_solo
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, soloed)| soloed.load(Ordering::Relaxed))
+                    .map(|(pair, _)| pair * 2)
+                    .collect::<Vec<_>>()
+            ),
+        );
+        notify_device_event(driver, kAudioPrismPropertySolo, PRISM_EVENT_CONFIG);
+        return 0;
+    }
+
+    if selector == kAudioPrismPropertyCaptureAllowlist {
+        // CFData holding a binary plist: { enabled?, bundle_ids?: [bundle_id],
+        // pids?: [pid] }. A list that is given replaces the current one.
+        if _in_data_size != std::mem::size_of::<CFDataRef>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+        let data_ref = *(_in_data as *const CFDataRef);
+        if data_ref.is_null() {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        let data = CFData::wrap_under_get_rule(data_ref);
+        let dict = match Value::from_reader(std::io::Cursor::new(data.bytes())) {
+            Ok(Value::Dictionary(dict)) => dict,
+            _ => {
+                log_error(
+                    Category::Routing,
+                    "Prism: CAPL rejected: payload is not a plist dictionary",
+                );
+                return kAudioHardwareIllegalOperationError as OSStatus;
+            }
+        };
+        let bundle_ids = dict.get("bundle_ids").map(|value| {
+            value.as_array().and_then(|items| {
+                items
+                    .iter()
+                    .map(|v| {
+                        v.as_string()
+                            .filter(|id| !id.is_empty())
+                            .map(str::to_string)
+                    })
+                    .collect::<Option<Vec<_>>>()
+            })
+        });
+        let pids = dict.get("pids").map(|value| {
+            value.as_array().and_then(|items| {
+                items
+                    .iter()
+                    .map(|v| {
+                        v.as_signed_integer()
+                            .and_then(|pid| i32::try_from(pid).ok())
+                            .filter(|&pid| pid > 0)
+                    })
+                    .collect::<Option<Vec<_>>>()
+            })
+        });
+        let enabled = dict.get("enabled").and_then(|v| v.as_boolean());
+        if matches!(bundle_ids, Some(None)) || matches!(pids, Some(None)) {
+            log_error(
+                Category::Routing,
+                &format!("Prism: CAPL rejected: {:?}", dict),
+            );
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+
+        if let Ok(mut allowlist) = (*driver).capture_allowlist.lock() {
+            if let Some(Some(bundle_ids)) = bundle_ids {
+                allowlist.bundle_ids = bundle_ids;
+            }
+            if let Some(Some(pids)) = pids {
+                allowlist.pids = pids;
+            }
+            log_msg(
+                Category::Routing,
+                &format!(
+                    "Prism: CAPL enabled={} bundles={:?} pids={:?}",
+                    enabled.unwrap_or_else(|| {
+                        (*driver).capture_allowlist_enabled.load(Ordering::Relaxed)
+                    }),
+                    allowlist.bundle_ids,
+                    allowlist.pids
+                ),
+            );
+        }
+        // Verdicts first, so enabling never silences a listed client for a cycle
+        refresh_capture_allowed(&*driver);
+        if let Some(enabled) = enabled {
+            (*driver)
+                .capture_allowlist_enabled
+                .store(enabled, Ordering::Relaxed);
+        }
+        notify_device_event(
+            driver,
+            kAudioPrismPropertyCaptureAllowlist,
+            PRISM_EVENT_CONFIG,
+        );
+        return 0;
+    }
+
+    if selector == kAudioPrismPropertyCaptureMask {
+        // CFData holding a binary plist array: [{ pid, mask? }], mask a channel bitmask
+        // (bit n for channel n + 1). Without a mask the process reads every channel again.
+        if _in_data_size != std::mem::size_of::<CFDataRef>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+        let data_ref = *(_in_data as *const CFDataRef);
+        if data_ref.is_null() {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        let data = CFData::wrap_under_get_rule(data_ref);
+        let entries = match Value::from_reader(std::io::Cursor::new(data.bytes())) {
+            Ok(Value::Array(entries)) => entries,
+            _ => {
+                log_error(
+                    Category::Routing,
+                    "Prism: CMSK rejected: payload is not a plist array",
+                );
+                return kAudioHardwareIllegalOperationError as OSStatus;
+            }
+        };
+        let mut masks = Vec::with_capacity(entries.len());
+        for entry in &entries {
+            let dict = entry.as_dictionary();
+            let pid = dict
+                .and_then(|dict| dict.get("pid"))
+                .and_then(|v| v.as_signed_integer())
+                .and_then(|pid| i32::try_from(pid).ok())
+                .filter(|&pid| pid > 0);
+            let mask = dict.and_then(|dict| dict.get("mask"));
+            let Some(pid) = pid else {
+                log_error(
+                    Category::Routing,
+                    &format!("Prism: CMSK rejected: invalid entry {:?}", entry),
+                );
+                return kAudioHardwareIllegalOperationError as OSStatus;
+            };
+            match mask.map(|v| v.as_unsigned_integer()) {
+                None => masks.push((pid, None)),
+                Some(Some(mask)) => masks.push((pid, Some(mask))),
+                Some(None) => {
+                    log_error(
+                        Category::Routing,
+                        &format!("Prism: CMSK rejected: invalid mask in {:?}", entry),
+                    );
+                    return kAudioHardwareIllegalOperationError as OSStatus;
                 }
-                if scope == kAudioObjectPropertyScopeGlobal
-                    || scope == kAudioObjectPropertyScopeOutput
+            }
+        }
+
+        if let Ok(mut stored) = (*driver).capture_masks.lock() {
+            for &(pid, mask) in &masks {
+                match mask {
+                    Some(mask) => stored.insert(pid, mask),
+                    None => stored.remove(&pid),
+                };
+            }
+        }
+        let table = (*driver).slot_table();
+        for (pid, mask) in masks {
+            log_msg(
+                Category::Routing,
+                &format!(
+                    "Prism: CMSK PID={} Mask={:?}",
+                    pid,
+                    mask.map(|m| format!("{:#x}", m))
+                ),
+            );
+            for slot in table.slots.iter() {
+                if slot.client_id.load(Ordering::Acquire) != 0
+                    && slot.pid.load(Ordering::Relaxed) == pid
                 {
-                    count += 1;
+                    slot.capture_mask
+                        .store(mask.unwrap_or(u64::MAX), Ordering::Relaxed);
                 }
-                *_out_data_size = (count * std::mem::size_of::<AudioObjectID>()) as UInt32;
-            } else if selector == kAudioDevicePropertyNominalSampleRate {
-                *_out_data_size = std::mem::size_of::<Float64>() as UInt32;
-            } else if selector == kAudioDevicePropertyAvailableNominalSampleRates
-                || selector == kAudioDevicePropertyBufferFrameSizeRange
+            }
+        }
+        notify_device_event(driver, kAudioPrismPropertyCaptureMask, PRISM_EVENT_CONFIG);
+        return 0;
+    }
+
+    if selector == kAudioPrismPropertyClientGain {
+        // CFData holding a binary plist array: [{ pid, gain?, agc? }], pid -1 for every
+        // client. gain is the client's own (0 mutes), agc prismd's AGC stage multiplied
+        // on top of it, both linear; an entry sets at least one. Only gains change:
+        // unlike 'rout' the write names no pair, so it cannot undo a reroute made since
+        // the writer last looked.
+        if _in_data_size != std::mem::size_of::<CFDataRef>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+        let data_ref = *(_in_data as *const CFDataRef);
+        if data_ref.is_null() {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        let data = CFData::wrap_under_get_rule(data_ref);
+        let entries = match Value::from_reader(std::io::Cursor::new(data.bytes())) {
+            Ok(Value::Array(entries)) => entries,
+            _ => {
+                log_error(
+                    Category::Routing,
+                    "Prism: CGAN rejected: payload is not a plist array",
+                );
+                return kAudioHardwareIllegalOperationError as OSStatus;
+            }
+        };
+        let mut gains = Vec::with_capacity(entries.len());
+        for entry in &entries {
+            let dict = entry.as_dictionary();
+            let pid = dict
+                .and_then(|dict| dict.get("pid"))
+                .and_then(|v| v.as_signed_integer())
+                .and_then(|pid| i32::try_from(pid).ok())
+                .filter(|&pid| pid > 0 || pid == -1);
+            // None when absent, Some(None) when not a finite, non-negative real
+            let linear = |key: &str| {
+                dict.and_then(|dict| dict.get(key)).map(|v| {
+                    v.as_real()
+                        .map(|v| v as f32)
+                        .filter(|gain| gain.is_finite() && *gain >= 0.0)
+                })
+            };
+            let (gain, agc) = (linear("gain"), linear("agc"));
+            let (Some(pid), Some(gain), Some(agc)) = (
+                pid,
+                gain.unwrap_or(Some(f32::NAN)),
+                agc.unwrap_or(Some(f32::NAN)),
+            ) else {
+                log_error(
+                    Category::Routing,
+                    &format!("Prism: CGAN rejected: invalid entry {:?}", entry),
+                );
+                return kAudioHardwareIllegalOperationError as OSStatus;
+            };
+            if gain.is_nan() && agc.is_nan() {
+                log_error(
+                    Category::Routing,
+                    &format!("Prism: CGAN rejected: entry sets no gain {:?}", entry),
+                );
+                return kAudioHardwareIllegalOperationError as OSStatus;
+            }
+            // NaN leaves that stage unchanged, as in apply_mix
+            gains.push((pid, gain, agc));
+        }
+
+        let mut changed = 0;
+        for slot in (*driver).slot_table().slots.iter() {
+            if slot.client_id.load(Ordering::Acquire) == 0 {
+                continue;
+            }
+            let pid = slot.pid.load(Ordering::Acquire);
+            // Applied in order, so the last entry for a pid wins, as in a 'rout' batch
+            let mut matched = false;
+            for &(_, gain, agc) in gains
+                .iter()
+                .filter(|(target, _, _)| *target == pid || *target == -1)
             {
-                *_out_data_size = std::mem::size_of::<AudioValueRange>() as UInt32;
-            } else {
-                // log_msg(&format!("Prism: GetPropertyDataSize Unknown. Object: {}, Selector: {}", object_id, selector));
-                return kAudioHardwareUnknownPropertyError as OSStatus;
+                if !gain.is_nan() {
+                    slot.gain.store(gain.to_bits(), Ordering::Relaxed);
+                }
+                if !agc.is_nan() {
+                    slot.agc_gain.store(agc.to_bits(), Ordering::Relaxed);
+                }
+                matched = true;
+            }
+            if matched {
+                changed += 1;
+            }
+        }
+        log_msg(
+            Category::Routing,
+            &format!("Prism: CGAN Entries={}, Clients={}", gains.len(), changed),
+        );
+        if changed > 0 {
+            notify_device_event(driver, kAudioPrismPropertyClientList, PRISM_EVENT_ROUTING);
+        }
+        return 0;
+    }
+
+    if selector == kAudioPrismPropertyGapPolicy {
+        // CFData holding a binary plist: { channel_offset, mode, max_hold_ms? }. mode is
+        // zero-fill or hold-last; max_hold_ms (1..=MAX_GAP_HOLD_MS) bounds the hold.
+        if _in_data_size != std::mem::size_of::<CFDataRef>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+        let data_ref = *(_in_data as *const CFDataRef);
+        if data_ref.is_null() {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        let data = CFData::wrap_under_get_rule(data_ref);
+        let dict = match Value::from_reader(std::io::Cursor::new(data.bytes())) {
+            Ok(Value::Dictionary(dict)) => dict,
+            _ => {
+                log_error(
+                    Category::Routing,
+                    "Prism: GAPP rejected: payload is not a plist dictionary",
+                );
+                return kAudioHardwareIllegalOperationError as OSStatus;
+            }
+        };
+        let offset = dict
+            .get("channel_offset")
+            .and_then(|v| v.as_unsigned_integer())
+            .unwrap_or(u64::MAX);
+        let mode = dict
+            .get("mode")
+            .and_then(|v| v.as_string())
+            .and_then(GapMode::parse);
+        let max_hold_ms = dict
+            .get("max_hold_ms")
+            .map(|v| v.as_unsigned_integer().unwrap_or(0))
+            .unwrap_or(u64::from(DEFAULT_GAP_HOLD_MS));
+        let max_channels = u64::from((*driver).config.num_channels);
+        if offset < 2
+            || offset % 2 != 0
+            || offset + 1 >= max_channels
+            || mode.is_none()
+            || !(1..=u64::from(MAX_GAP_HOLD_MS)).contains(&max_hold_ms)
+        {
+            log_error(
+                Category::Routing,
+                &format!(
+                    "Prism: GAPP rejected: {:?} (max_channels={}, max hold {} ms)",
+                    dict, max_channels, MAX_GAP_HOLD_MS
+                ),
+            );
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        let frames = match mode {
+            Some(GapMode::HoldLast) => (max_hold_ms as f64 * SAMPLE_RATE / 1000.0) as u32,
+            _ => 0,
+        };
+        let pair = offset as usize / 2;
+        if (*driver).gap_holds[pair].swap(frames, Ordering::Relaxed) != frames {
+            log_msg(
+                Category::Routing,
+                &format!(
+                    "Prism: GAPP channel_offset={} holds up to {} frames",
+                    offset, frames
+                ),
+            );
+            notify_device_event(driver, kAudioPrismPropertyGapPolicy, PRISM_EVENT_CONFIG);
+        }
+        return 0;
+    }
+
+    if selector == kAudioPrismPropertyReservations {
+        // CFData holding a binary plist: { channel_offset, owner, release? }. Claims the
+        // pair for `owner`, or with release drops the claim. A pair held by another owner
+        // is neither claimed nor released. Pair 0 carries the system mix and cannot be held.
+        if _in_data_size != std::mem::size_of::<CFDataRef>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+        let data_ref = *(_in_data as *const CFDataRef);
+        if data_ref.is_null() {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        let data = CFData::wrap_under_get_rule(data_ref);
+        let dict = match Value::from_reader(std::io::Cursor::new(data.bytes())) {
+            Ok(Value::Dictionary(dict)) => dict,
+            _ => {
+                log_error(
+                    Category::Routing,
+                    "Prism: RESV rejected: payload is not a plist dictionary",
+                );
+                return kAudioHardwareIllegalOperationError as OSStatus;
             }
+        };
+        let offset = dict
+            .get("channel_offset")
+            .and_then(|v| v.as_unsigned_integer())
+            .unwrap_or(u64::MAX);
+        let owner = dict
+            .get("owner")
+            .and_then(|v| v.as_string())
+            .unwrap_or_default();
+        let release = dict
+            .get("release")
+            .and_then(|v| v.as_boolean())
+            .unwrap_or(false);
+        let max_channels = u64::from((*driver).config.num_channels);
+        if owner.is_empty() || offset < 2 || offset % 2 != 0 || offset + 1 >= max_channels {
+            log_error(
+                Category::Routing,
+                &format!(
+                    "Prism: RESV rejected: channel_offset={} owner={:?} (max_channels={})",
+                    offset, owner, max_channels
+                ),
+            );
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+
+        let Ok(mut reservations) = (*driver).reservations.lock() else {
+            return kAudioHardwareUnspecifiedError as OSStatus;
+        };
+        let offset = offset as usize;
+        if let Some(holder) = reservations.get(&offset).filter(|holder| *holder != owner) {
+            log_error(
+                Category::Routing,
+                &format!(
+                    "Prism: RESV refused: channel_offset={} is held by {:?}, not {:?}",
+                    offset, holder, owner
+                ),
+            );
+            return PRISM_BUS_RESERVED_ERROR;
+        }
+        let changed = if release {
+            reservations.remove(&offset).is_some()
+        } else {
+            reservations.insert(offset, owner.to_string()).is_none()
+        };
+        drop(reservations);
+        log_msg(
+            Category::Routing,
+            &format!(
+                "Prism: RESV channel_offset={} {} by {:?}",
+                offset,
+                if release { "released" } else { "reserved" },
+                owner
+            ),
+        );
+        if changed {
+            notify_device_event(driver, kAudioPrismPropertyReservations, PRISM_EVENT_CONFIG);
         }
+        return 0;
+    }
 
-        // ---------------------------------------------------------------------
-        // 3. Stream object
-        // ---------------------------------------------------------------------
-        INPUT_STREAM_ID | OUTPUT_STREAM_ID => match selector {
-            kAudioObjectPropertyCustomPropertyInfoList => {
-                *_out_data_size = 0;
-                return 0;
-            }
-            kAudioObjectPropertyControlList => {
-                *_out_data_size = 0;
-            }
-            kAudioObjectPropertyBaseClass
-            | kAudioObjectPropertyClass
-            | kAudioObjectPropertyOwner
-            | kAudioStreamPropertyDirection
-            | kAudioStreamPropertyTerminalType
-            | kAudioStreamPropertyStartingChannel
-            | kAudioObjectPropertyScope
-            | kAudioObjectPropertyElement => {
-                *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
-            }
-            kAudioStreamPropertyVirtualFormat | kAudioStreamPropertyPhysicalFormat => {
-                *_out_data_size = std::mem::size_of::<AudioStreamBasicDescription>() as UInt32;
-            }
-            kAudioStreamPropertyPhysicalFormats
-            | kAudioStreamPropertyAvailableVirtualFormats
-            | kAudioStreamPropertyAvailablePhysicalFormats => {
-                *_out_data_size = std::mem::size_of::<AudioStreamRangedDescription>() as UInt32;
-            }
+    if selector == kAudioPrismPropertyBusLabels {
+        // CFData holding a binary plist: { channel_offset, label? }. Names the pair, or
+        // without a label (or with an empty one) drops its name.
+        if _in_data_size != std::mem::size_of::<CFDataRef>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+        let data_ref = *(_in_data as *const CFDataRef);
+        if data_ref.is_null() {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        let data = CFData::wrap_under_get_rule(data_ref);
+        let dict = match Value::from_reader(std::io::Cursor::new(data.bytes())) {
+            Ok(Value::Dictionary(dict)) => dict,
             _ => {
-                return kAudioHardwareUnknownPropertyError as OSStatus;
+                log_error(
+                    Category::Routing,
+                    "Prism: BUSN rejected: payload is not a plist dictionary",
+                );
+                return kAudioHardwareIllegalOperationError as OSStatus;
             }
-        },
-        _ => return kAudioHardwareBadObjectError as OSStatus,
+        };
+        let offset = dict
+            .get("channel_offset")
+            .and_then(|v| v.as_unsigned_integer())
+            .unwrap_or(u64::MAX);
+        let label = dict
+            .get("label")
+            .and_then(|v| v.as_string())
+            .map(str::trim)
+            .unwrap_or_default();
+        let max_channels = u64::from((*driver).config.num_channels);
+        if offset % 2 != 0
+            || offset + 1 >= max_channels
+            || label.chars().count() > MAX_BUS_LABEL_CHARS
+            || label.chars().any(char::is_control)
+        {
+            log_error(
+                Category::Routing,
+                &format!(
+                    "Prism: BUSN rejected: channel_offset={} label={:?} (max_channels={})",
+                    offset, label, max_channels
+                ),
+            );
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+
+        let Ok(mut labels) = (*driver).bus_labels.lock() else {
+            return kAudioHardwareUnspecifiedError as OSStatus;
+        };
+        let offset = offset as usize;
+        let changed = if label.is_empty() {
+            labels.remove(&offset).is_some()
+        } else {
+            labels.insert(offset, label.to_string()).as_deref() != Some(label)
+        };
+        drop(labels);
+        log_msg(
+            Category::Routing,
+            &format!("Prism: BUSN channel_offset={} label={:?}", offset, label),
+        );
+        if changed {
+            notify_device_event(driver, kAudioPrismPropertyBusLabels, PRISM_EVENT_CONFIG);
+            notify_channel_map_changed(driver, false);
+        }
+        return 0;
     }
-    0
-}
 
-#[allow(non_upper_case_globals)]
-unsafe extern "C" fn get_property_data(
-    _self: AudioServerPlugInDriverRef,
-    object_id: AudioObjectID,
-    _client_process_id: pid_t,
-    _address: *const AudioObjectPropertyAddress,
-    _qualifier_data_size: UInt32,
-    _qualifier_data: *const c_void,
-    _in_data_size: UInt32,
-    _out_data_size: *mut UInt32,
-    _out_data: *mut c_void,
-) -> OSStatus {
-    let driver = _self as *mut PrismDriver;
-    let address = *_address;
-    let selector = address.mSelector;
+    if selector == kAudioPrismPropertyRouteBundle {
+        // CFData holding a binary plist: { bundle_id, channel_offset, gain?, pan?, width? }.
+        // Routes every client of the bundle and its helpers, so the daemon can route an
+        // app by the ID it was configured with before it has resolved any pid.
+        if _in_data_size != std::mem::size_of::<CFDataRef>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+        let data_ref = *(_in_data as *const CFDataRef);
+        if data_ref.is_null() {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        let data = CFData::wrap_under_get_rule(data_ref);
+        let dict = match Value::from_reader(std::io::Cursor::new(data.bytes())) {
+            Ok(Value::Dictionary(dict)) => dict,
+            _ => {
+                log_error(
+                    Category::Routing,
+                    "Prism: RBND rejected: payload is not a plist dictionary",
+                );
+                return kAudioHardwareIllegalOperationError as OSStatus;
+            }
+        };
+        let bundle_id = dict
+            .get("bundle_id")
+            .and_then(|v| v.as_string())
+            .unwrap_or_default()
+            .to_string();
+        let offset = dict
+            .get("channel_offset")
+            .and_then(|v| v.as_unsigned_integer())
+            .unwrap_or(u64::MAX);
+        let field = |key: &str| {
+            dict.get(key)
+                .and_then(|v| v.as_real())
+                .map_or(f32::NAN, |v| v as f32)
+        };
+        let (gain, pan, width) = (field("gain"), field("pan"), field("width"));
+
+        let max_channels = u64::from((*driver).config.num_channels);
+        if bundle_id.is_empty()
+            || offset % 2 != 0
+            || offset + 1 >= max_channels
+            || (!gain.is_nan() && (gain.is_infinite() || gain < 0.0))
+            || (!pan.is_nan() && !(-1.0..=1.0).contains(&pan))
+            || (!width.is_nan() && !(0.0..=PRISM_MAX_WIDTH).contains(&width))
+        {
+            log_error(
+                Category::Routing,
+                &format!(
+                    "Prism: RBND rejected: bundle={:?} channel_offset={} gain={} pan={} width={} \
+                     (max_channels={})",
+                    bundle_id, offset, gain, pan, width, max_channels
+                ),
+            );
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
 
-    // log_msg(&format!("Prism: GetPropertyData called. Object: {}, Selector: {}", object_id, selector));
+        // Hashes of the bundle and its helpers among the clients seen so far
+        let hashes: Vec<u64> = match (*driver).bundle_ids.lock() {
+            Ok(bundle_ids) => bundle_ids
+                .iter()
+                .filter(|(_, candidate)| bundle_matches(candidate, &bundle_id))
+                .map(|(hash, _)| *hash)
+                .collect(),
+            Err(_) => Vec::new(),
+        };
 
-    if _out_data.is_null() {
-        return kAudioHardwareIllegalOperationError as OSStatus;
+        let owner = dict.get("owner").and_then(|v| v.as_string());
+        if let Err(status) = check_reservation(driver, offset as usize, owner, |slot| {
+            hashes.contains(&slot.bundle_hash.load(Ordering::Relaxed))
+        }) {
+            return status;
+        }
+
+        let mut routed = 0;
+        for slot in (*driver).slot_table().slots.iter() {
+            if slot.client_id.load(Ordering::Acquire) == 0
+                || !hashes.contains(&slot.bundle_hash.load(Ordering::Relaxed))
+            {
+                continue;
+            }
+            slot.apply_mix(gain, pan, width, None, None);
+            let prev = slot.reroute(offset as usize);
+            record_route_change(&*driver, slot, prev, offset as usize, "bundle");
+            routed += 1;
+        }
+        if routed == 0 {
+            log_error(
+                Category::Routing,
+                &format!("Prism: RBND Failed. No client of {:?}", bundle_id),
+            );
+            return kAudioHardwareBadObjectError as OSStatus;
+        }
+        log_msg(
+            Category::Routing,
+            &format!(
+                "Prism: Routing Update via RBND. Bundle={:?}, Offset={}, Clients={}",
+                bundle_id, offset, routed
+            ),
+        );
+        notify_device_event(driver, kAudioPrismPropertyClientList, PRISM_EVENT_ROUTING);
+        notify_channel_map_changed(driver, false);
+        return 0;
     }
 
-    match object_id {
-        // ---------------------------------------------------------------------
-        // 1. Plugin object (Driver PlugIn)
-        // ---------------------------------------------------------------------
-        id if id == kAudioObjectPlugInObject => {
-            match selector {
-                kAudioObjectPropertyCustomPropertyInfoList => {
-                    // The plugin does not own custom properties (they belong to the Device)
-                    *_out_data_size = 0;
-                    return 0;
-                }
-                kAudioObjectPropertyBaseClass => {
-                    let out = _out_data as *mut AudioClassID;
-                    *out = kAudioObjectClassID;
-                    *_out_data_size = std::mem::size_of::<AudioClassID>() as UInt32;
-                }
-                kAudioObjectPropertyClass => {
-                    let out = _out_data as *mut AudioClassID;
-                    *out = kAudioPlugInClassID;
-                    *_out_data_size = std::mem::size_of::<AudioClassID>() as UInt32;
-                }
-                kAudioObjectPropertyOwner => {
-                    let out = _out_data as *mut AudioObjectID;
-                    *out = kAudioObjectUnknown;
-                    *_out_data_size = std::mem::size_of::<AudioObjectID>() as UInt32;
-                }
-                kAudioObjectPropertyManufacturer => {
-                    let out = _out_data as *mut CFStringRef;
-                    *out = CFStringCreateWithCString(
-                        ptr::null(),
-                        c"PetitStrawberry".as_ptr(),
-                        kCFStringEncodingUTF8,
-                    );
-                    *_out_data_size = std::mem::size_of::<CFStringRef>() as UInt32;
-                }
-                kAudioPlugInPropertyResourceBundle => {
-                    let out = _out_data as *mut CFStringRef;
-                    *out = CFStringCreateWithCString(
-                        ptr::null(),
-                        c"dev.ichigo.driver.Prism".as_ptr(),
-                        kCFStringEncodingUTF8,
-                    );
-                    *_out_data_size = std::mem::size_of::<CFStringRef>() as UInt32;
-                }
-                kAudioPlugInPropertyDeviceList | kAudioObjectPropertyOwnedObjects => {
-                    let out = _out_data as *mut AudioObjectID;
-                    *out = DEVICE_ID;
-                    *_out_data_size = std::mem::size_of::<AudioObjectID>() as UInt32;
+    if selector == kAudioPrismPropertyResync {
+        // Any CFData triggers a resync; the payload is ignored
+        if _in_data_size != std::mem::size_of::<CFDataRef>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+        if (*(_in_data as *const CFDataRef)).is_null() {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        resync(driver);
+        return 0;
+    }
 
-                    // Late notification: send 'cust' after the plugin's device list has been retrieved
-                    if let Some(host) = (*driver).host {
-                        if let Some(prop_changed) = (*host).PropertiesChanged {
-                            let addr_cust = AudioObjectPropertyAddress {
-                                mSelector: kAudioObjectPropertyCustomPropertyInfoList,
-                                mScope: kAudioObjectPropertyScopeGlobal,
-                                mElement: kAudioObjectPropertyElementMaster,
-                            };
-                            prop_changed(host, DEVICE_ID, 1, &addr_cust);
-                            log_msg("Prism: Late notification sent for Device 'cust' property");
-                        }
-                    }
-                }
-                kAudioPlugInPropertyTranslateUIDToDevice => {
-                    let mut device_id = kAudioObjectUnknown;
-                    if _qualifier_data_size == std::mem::size_of::<CFStringRef>() as UInt32
-                        && !_qualifier_data.is_null()
-                    {
-                        let uid = *(_qualifier_data as *const CFStringRef);
-                        let my_uid = CFStringCreateWithCString(
-                            ptr::null(),
-                            c"dev.ichigo.driver.Prism.Device".as_ptr(),
-                            kCFStringEncodingUTF8,
-                        );
-                        if CFStringCompare(uid, my_uid, 0) == 0 {
-                            device_id = DEVICE_ID;
-                        }
-                        CFRelease(my_uid as *const c_void);
-                    }
-                    let out = _out_data as *mut AudioObjectID;
-                    *out = device_id;
-                    *_out_data_size = std::mem::size_of::<AudioObjectID>() as UInt32;
-                }
-                _ => {
-                    return kAudioHardwareUnknownPropertyError as OSStatus;
-                }
-            }
+    if selector == kAudioPrismPropertyLogLevel {
+        // CFData holding a binary plist: { level } ("error", "info" or "debug")
+        if _in_data_size != std::mem::size_of::<CFDataRef>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+        let data_ref = *(_in_data as *const CFDataRef);
+        if data_ref.is_null() {
+            return kAudioHardwareIllegalOperationError as OSStatus;
         }
+        let data = CFData::wrap_under_get_rule(data_ref);
+        let level = match Value::from_reader(std::io::Cursor::new(data.bytes())) {
+            Ok(Value::Dictionary(dict)) => dict
+                .get("level")
+                .and_then(|v| v.as_string())
+                .and_then(Level::parse),
+            _ => None,
+        };
+        let Some(level) = level else {
+            log_error(
+                Category::Properties,
+                "Prism: LOGL rejected: expected { level }",
+            );
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        };
+        oslog::set_level(level);
+        log_msg(
+            Category::Lifecycle,
+            &format!("Prism: Log level set to {}", level.name()),
+        );
+        notify_device_property_changed(driver, kAudioPrismPropertyLogLevel);
+        return 0;
+    }
 
-        // ---------------------------------------------------------------------
-        // 2. Device object (The Prism Device)
-        // ---------------------------------------------------------------------
-        DEVICE_ID => {
-            #[allow(
-                non_upper_case_globals,
-                unreachable_patterns,
-                clippy::match_overlapping_arm
-            )]
-            match selector {
-                kAudioObjectPropertyCustomPropertyInfoList => {
-                    log_msg("Prism: GetPropertyData(Device) -> CustomPropertyInfoList");
+    if selector == kAudioPrismPropertyClearBuffers {
+        // CFData holding a binary plist: { channel_offset } clears one pair, { all: true }
+        // every allocated ring
+        if _in_data_size != std::mem::size_of::<CFDataRef>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+        let data_ref = *(_in_data as *const CFDataRef);
+        if data_ref.is_null() {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        let data = CFData::wrap_under_get_rule(data_ref);
+        let Ok(Value::Dictionary(dict)) = Value::from_reader(std::io::Cursor::new(data.bytes()))
+        else {
+            log_error(
+                Category::Routing,
+                "Prism: CLR rejected: payload is not a plist dictionary",
+            );
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        };
 
-                    let need =
-                        (2 * std::mem::size_of::<AudioServerPlugInCustomPropertyInfo>()) as UInt32;
-                    if *_out_data_size < need {
-                        return kAudioHardwareBadPropertySizeError as OSStatus;
-                    }
+        if dict.get("all").and_then(|v| v.as_boolean()) == Some(true) {
+            for pair in 0..(*driver).pair_buffers.len() {
+                clear_pair(driver, pair);
+            }
+            log_msg(Category::Routing, "Prism: Cleared every pair ring");
+        } else {
+            let offset = dict
+                .get("channel_offset")
+                .and_then(|v| v.as_unsigned_integer())
+                .unwrap_or(u64::MAX);
+            let max_channels = u64::from((*driver).config.num_channels);
+            if offset % 2 != 0 || offset + 1 >= max_channels {
+                log_error(
+                    Category::Routing,
+                    &format!(
+                        "Prism: CLR rejected: invalid channel_offset={}, max_channels={}",
+                        offset, max_channels
+                    ),
+                );
+                return kAudioHardwareIllegalOperationError as OSStatus;
+            }
+            clear_pair(driver, (offset / 2) as usize);
+            log_msg(
+                Category::Routing,
+                &format!(
+                    "Prism: Cleared ring of channels {}-{}",
+                    offset + 1,
+                    offset + 2
+                ),
+            );
+        }
+        notify_device_event(driver, kAudioPrismPropertyClearBuffers, PRISM_EVENT_DIAG);
+        return 0;
+    }
 
-                    let out = _out_data as *mut AudioServerPlugInCustomPropertyInfo;
-                    unsafe {
-                        // Entry 0: 'rout' property definition
-                        (*out).mSelector = kAudioPrismPropertyRoutingTable;
-                        (*out).mPropertyDataType =
-                            kAudioServerPlugInCustomPropertyDataTypeCFPropertyList;
-                        (*out).mQualifierDataType = kAudioServerPlugInCustomPropertyDataTypeNone;
-
-                        // Entry 1: 'clnt' property definition
-                        let next = out.add(1);
-                        (*next).mSelector = kAudioPrismPropertyClientList;
-                        (*next).mPropertyDataType =
-                            kAudioServerPlugInCustomPropertyDataTypeCFPropertyList;
-                        (*next).mQualifierDataType = kAudioServerPlugInCustomPropertyDataTypeNone;
-                    }
-                    *_out_data_size = need;
-                    return 0;
-                }
-                kAudioPrismPropertyRoutingTable => {
-                    log_msg("Prism: GetPropertyData(Device) -> RoutingTable");
-                    let size = std::mem::size_of::<PrismRoutingUpdate>() as UInt32;
-                    let out = _out_data as *mut PrismRoutingUpdate;
-                    unsafe {
-                        *out = PrismRoutingUpdate {
-                            pid: 0,
-                            channel_offset: 0,
-                        };
-                    }
-                    *_out_data_size = size;
-                    return 0;
-                }
-                kAudioPrismPropertyClientList => {
-                    log_msg("Prism: GetPropertyData(Device) -> ClientList");
-                    let encoded = encode_client_list(&*driver);
-                    let cfdata = CFData::from_buffer(&encoded);
-                    let cfdata_ref = cfdata.as_concrete_TypeRef();
-                    let out = _out_data as *mut CFDataRef;
-                    unsafe {
-                        *out = cfdata_ref;
-                    }
-                    std::mem::forget(cfdata);
-                    *_out_data_size = std::mem::size_of::<CFDataRef>() as UInt32;
-                    return 0;
-                }
-                kAudioObjectPropertyControlList => {
-                    *_out_data_size = 0;
-                }
-                kAudioObjectPropertyBaseClass => {
-                    let out = _out_data as *mut AudioClassID;
-                    *out = kAudioObjectClassID;
-                    *_out_data_size = std::mem::size_of::<AudioClassID>() as UInt32;
-                }
-                kAudioObjectPropertyClass => {
-                    let out = _out_data as *mut AudioClassID;
-                    *out = kAudioDeviceClassID;
-                    *_out_data_size = std::mem::size_of::<AudioClassID>() as UInt32;
-                }
-                kAudioObjectPropertyOwner => {
-                    let out = _out_data as *mut AudioObjectID;
-                    *out = kAudioObjectPlugInObject;
-                    *_out_data_size = std::mem::size_of::<AudioObjectID>() as UInt32;
+    if selector == kAudioPrismPropertyConfig {
+        // CFData holding a binary plist with any of input_latency, output_latency,
+        // input_safety_offset and output_safety_offset (frames), full_bus_output
+        // (boolean), auto_assign (policy name or boolean), clock_domain and
+        // system_mix_offset and system_mix_enabled (boolean). The other keys are fixed
+        // once the device exists.
+        if _in_data_size != std::mem::size_of::<CFDataRef>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+        let data_ref = *(_in_data as *const CFDataRef);
+        if data_ref.is_null() {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        let data = CFData::wrap_under_get_rule(data_ref);
+        let Ok(Value::Dictionary(dict)) = Value::from_reader(std::io::Cursor::new(data.bytes()))
+        else {
+            log_error(
+                Category::Properties,
+                "Prism: CNFG rejected: payload is not a plist dictionary",
+            );
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        };
+
+        let fields = [
+            ("input_latency", &(*driver).input_latency),
+            ("output_latency", &(*driver).output_latency),
+            ("input_safety_offset", &(*driver).input_safety_offset),
+            ("output_safety_offset", &(*driver).output_safety_offset),
+        ];
+        let mut updates = Vec::new();
+        for (key, target) in fields {
+            let Some(value) = dict.get(key) else {
+                continue;
+            };
+            match value.as_unsigned_integer() {
+                Some(frames) if frames <= u64::from(MAX_TIMING_FRAMES) => {
+                    updates.push((key, target, frames as u32))
                 }
-                kAudioObjectPropertyManufacturer => {
-                    let out = _out_data as *mut CFStringRef;
-                    *out = CFStringCreateWithCString(
-                        ptr::null(),
-                        c"PetitStrawberry".as_ptr(),
-                        kCFStringEncodingUTF8,
+                _ => {
+                    log_error(
+                        Category::Properties,
+                        &format!(
+                            "Prism: CNFG rejected: {} must be 0..={} frames",
+                            key, MAX_TIMING_FRAMES
+                        ),
                     );
-                    *_out_data_size = std::mem::size_of::<CFStringRef>() as UInt32;
+                    return kAudioHardwareIllegalOperationError as OSStatus;
                 }
-                kAudioDevicePropertyDeviceUID => {
-                    let out = _out_data as *mut CFStringRef;
-                    *out = CFStringCreateWithCString(
-                        ptr::null(),
-                        c"dev.ichigo.driver.Prism.Device".as_ptr(),
-                        kCFStringEncodingUTF8,
+            }
+        }
+        let full_bus = match dict.get("full_bus_output") {
+            None => None,
+            Some(value) => match value.as_boolean() {
+                Some(enabled) => Some(enabled),
+                None => {
+                    log_error(
+                        Category::Properties,
+                        "Prism: CNFG rejected: full_bus_output must be a boolean",
                     );
-                    *_out_data_size = std::mem::size_of::<CFStringRef>() as UInt32;
+                    return kAudioHardwareIllegalOperationError as OSStatus;
                 }
-                kAudioDevicePropertyModelUID => {
-                    let out = _out_data as *mut CFStringRef;
-                    *out = CFStringCreateWithCString(
-                        ptr::null(),
-                        c"dev.ichigo.driver.Prism.Model".as_ptr(),
-                        kCFStringEncodingUTF8,
+            },
+        };
+        let auto_assign = match dict.get("auto_assign") {
+            None => None,
+            Some(value) => match parse_assign_policy(value) {
+                Some(policy) => Some(policy),
+                None => {
+                    log_error(
+                        Category::Properties,
+                        "Prism: CNFG rejected: auto_assign must be disabled, first-free, \
+                         round-robin, sticky-bundle or a boolean",
                     );
-                    *_out_data_size = std::mem::size_of::<CFStringRef>() as UInt32;
+                    return kAudioHardwareIllegalOperationError as OSStatus;
                 }
-                kAudioDevicePropertyDeviceName | kAudioObjectPropertyName => {
-                    let out = _out_data as *mut CFStringRef;
-                    *out = CFStringCreateWithCString(
-                        ptr::null(),
-                        c"Prism".as_ptr(),
-                        kCFStringEncodingUTF8,
+            },
+        };
+        let clock_domain = match dict.get("clock_domain") {
+            None => None,
+            Some(value) => match value.as_unsigned_integer().map(u32::try_from) {
+                Some(Ok(domain)) => Some(domain),
+                _ => {
+                    log_error(
+                        Category::Properties,
+                        "Prism: CNFG rejected: clock_domain must be a 32-bit unsigned integer",
                     );
-                    *_out_data_size = std::mem::size_of::<CFStringRef>() as UInt32;
-                }
-                kAudioDevicePropertyTransportType => {
-                    let out = _out_data as *mut UInt32;
-                    *out = kAudioDeviceTransportTypeVirtual;
-                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
-                }
-                kAudioDevicePropertyDeviceIsRunning => {
-                    let out = _out_data as *mut UInt32;
-                    *out = if (*driver).client_count.load(Ordering::SeqCst) > 0 {
-                        1
-                    } else {
-                        0
-                    };
-                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
-                }
-                kAudioDevicePropertyDeviceIsAlive => {
-                    let out = _out_data as *mut UInt32;
-                    *out = 1;
-                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
-                }
-                kAudioDevicePropertyIsHidden
-                | kAudioDevicePropertyStreamsIsSettable
-                | kAudioDevicePropertyClockDomain
-                | kAudioDevicePropertyClockSource
-                | kAudioDevicePropertyDataSource
-                | kAudioDevicePropertyLatency => {
-                    let out = _out_data as *mut UInt32;
-                    *out = 0;
-                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
-                }
-                kAudioDevicePropertyDeviceCanBeDefaultDevice
-                | kAudioDevicePropertyDeviceCanBeDefaultSystemDevice => {
-                    let out = _out_data as *mut UInt32;
-                    *out = 1;
-                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
-                }
-                kAudioDevicePropertySafetyOffset => {
-                    let out = _out_data as *mut UInt32;
-                    *out = (*driver).config.safety_offset;
-                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
-                }
-                kAudioDevicePropertyNominalSampleRate => {
-                    let out = _out_data as *mut Float64;
-                    *out = 48000.0;
-                    *_out_data_size = std::mem::size_of::<Float64>() as UInt32;
-                }
-                kAudioDevicePropertyAvailableNominalSampleRates => {
-                    let out = _out_data as *mut AudioValueRange;
-                    *out = AudioValueRange {
-                        mMinimum: 44100.0,
-                        mMaximum: 96000.0,
-                    };
-                    *_out_data_size = std::mem::size_of::<AudioValueRange>() as UInt32;
-                }
-                kAudioDevicePropertyBufferFrameSize => {
-                    let out = _out_data as *mut UInt32;
-                    *out = (*driver).config.buffer_frame_size;
-                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
-                }
-                kAudioDevicePropertyZeroTimeStampPeriod => {
-                    let out = _out_data as *mut UInt32;
-                    *out = (*driver).config.zero_timestamp_period;
-                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
-                }
-                kAudioDevicePropertyBufferFrameSizeRange => {
-                    let out = _out_data as *mut AudioValueRange;
-                    *out = AudioValueRange {
-                        mMinimum: 16.0,
-                        mMaximum: 4096.0,
-                    };
-                    *_out_data_size = std::mem::size_of::<AudioValueRange>() as UInt32;
-                }
-                kAudioDevicePropertyRingBufferFrameSize => {
-                    let out = _out_data as *mut UInt32;
-                    *out = (*driver).config.buffer_frame_size;
-                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
-                }
-                kAudioObjectPropertyScope => {
-                    let out = _out_data as *mut UInt32;
-                    *out = kAudioObjectPropertyScopeGlobal;
-                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
-                }
-                kAudioObjectPropertyElement => {
-                    let out = _out_data as *mut UInt32;
-                    *out = kAudioObjectPropertyElementMaster;
-                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
-                }
-                kAudioObjectPropertyOwnedObjects => {
-                    let out = _out_data as *mut AudioObjectID;
-                    unsafe {
-                        *out.offset(0) = INPUT_STREAM_ID;
-                        *out.offset(1) = OUTPUT_STREAM_ID;
-                    }
-                    *_out_data_size = (2 * std::mem::size_of::<AudioObjectID>()) as UInt32;
+                    return kAudioHardwareIllegalOperationError as OSStatus;
                 }
-                kAudioDevicePropertyStreams => {
-                    let scope = address.mScope;
-                    let out = _out_data as *mut AudioObjectID;
-                    let mut count = 0;
-                    unsafe {
-                        if scope == kAudioObjectPropertyScopeGlobal
-                            || scope == kAudioObjectPropertyScopeInput
-                        {
-                            *out.offset(count) = INPUT_STREAM_ID;
-                            count += 1;
-                        }
-                        if scope == kAudioObjectPropertyScopeGlobal
-                            || scope == kAudioObjectPropertyScopeOutput
-                        {
-                            *out.offset(count) = OUTPUT_STREAM_ID;
-                            count += 1;
-                        }
-                    }
-                    *_out_data_size =
-                        (count as usize * std::mem::size_of::<AudioObjectID>()) as UInt32;
+            },
+        };
+        let max_channels = u64::from((*driver).config.num_channels);
+        let system_mix = match dict.get("system_mix_offset") {
+            None => None,
+            Some(value) => match value.as_unsigned_integer() {
+                Some(offset) if offset % 2 == 0 && offset + 1 < max_channels => {
+                    Some(offset as usize)
                 }
                 _ => {
-                    return kAudioHardwareUnknownPropertyError as OSStatus;
+                    log_error(
+                        Category::Properties,
+                        &format!(
+                            "Prism: CNFG rejected: system_mix_offset must be an even offset \
+                             below {}",
+                            max_channels
+                        ),
+                    );
+                    return kAudioHardwareIllegalOperationError as OSStatus;
+                }
+            },
+        };
+        let system_mix_enabled = match dict.get("system_mix_enabled") {
+            None => None,
+            Some(value) => match value.as_boolean() {
+                Some(enabled) => Some(enabled),
+                None => {
+                    log_error(
+                        Category::Properties,
+                        "Prism: CNFG rejected: system_mix_enabled must be a boolean",
+                    );
+                    return kAudioHardwareIllegalOperationError as OSStatus;
                 }
+            },
+        };
+        if updates.is_empty()
+            && full_bus.is_none()
+            && auto_assign.is_none()
+            && clock_domain.is_none()
+            && system_mix.is_none()
+            && system_mix_enabled.is_none()
+        {
+            log_error(
+                Category::Properties,
+                "Prism: CNFG rejected: no adjustable key in payload",
+            );
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        if !updates.is_empty() {
+            for (key, target, frames) in updates {
+                target.store(frames, Ordering::Relaxed);
+                log_msg(
+                    Category::Lifecycle,
+                    &format!("Prism: {} set to {} frames", key, frames),
+                );
             }
+            notify_timing_changed(driver);
         }
+        if let Some(enabled) = full_bus {
+            set_full_bus_output(driver, enabled);
+        }
+        if let Some(policy) = auto_assign {
+            if (*driver).auto_assign.swap(policy as u32, Ordering::Relaxed) != policy as u32 {
+                log_msg(
+                    Category::Lifecycle,
+                    &format!("Prism: auto_assign set to {}", policy.name()),
+                );
+            }
+        }
+        if let Some(domain) = clock_domain {
+            if (*driver).clock_domain.swap(domain, Ordering::Relaxed) != domain {
+                log_msg(
+                    Category::Lifecycle,
+                    &format!("Prism: clock_domain set to {}", domain),
+                );
+                notify_properties_changed(driver, DEVICE_ID, &[kAudioDevicePropertyClockDomain]);
+            }
+        }
+        if let Some(offset) = system_mix {
+            set_system_mix_offset(driver, offset);
+        }
+        if let Some(enabled) = system_mix_enabled {
+            set_system_mix_enabled(driver, enabled);
+        }
+        notify_device_event(driver, kAudioPrismPropertyConfig, PRISM_EVENT_CONFIG);
+        return 0;
+    }
 
-        // ---------------------------------------------------------------------
-        // 3. Stream object
-        // ---------------------------------------------------------------------
-        INPUT_STREAM_ID | OUTPUT_STREAM_ID =>
-        {
-            #[allow(non_upper_case_globals)]
-            match selector {
-                kAudioObjectPropertyCustomPropertyInfoList => {
-                    *_out_data_size = 0;
-                    return 0;
-                }
-                kAudioObjectPropertyControlList => {
-                    *_out_data_size = 0;
-                }
-                kAudioObjectPropertyBaseClass => {
-                    let out = _out_data as *mut AudioClassID;
-                    *out = kAudioObjectClassID;
-                    *_out_data_size = std::mem::size_of::<AudioClassID>() as UInt32;
-                }
-                kAudioObjectPropertyClass => {
-                    let out = _out_data as *mut AudioClassID;
-                    *out = kAudioStreamClassID;
-                    *_out_data_size = std::mem::size_of::<AudioClassID>() as UInt32;
-                }
-                kAudioObjectPropertyOwner => {
-                    let out = _out_data as *mut AudioObjectID;
-                    *out = DEVICE_ID;
-                    *_out_data_size = std::mem::size_of::<AudioObjectID>() as UInt32;
-                }
-                kAudioObjectPropertyScope => {
-                    let out = _out_data as *mut UInt32;
-                    *out = if object_id == INPUT_STREAM_ID {
-                        kAudioObjectPropertyScopeInput
-                    } else {
-                        kAudioObjectPropertyScopeOutput
-                    };
-                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
-                }
-                kAudioObjectPropertyElement => {
-                    let out = _out_data as *mut UInt32;
-                    *out = kAudioObjectPropertyElementMaster;
-                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
-                }
-                kAudioStreamPropertyDirection => {
-                    let out = _out_data as *mut UInt32;
-                    *out = if object_id == INPUT_STREAM_ID { 1 } else { 0 };
-                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
-                }
-                kAudioStreamPropertyTerminalType => {
-                    let out = _out_data as *mut UInt32;
-                    *out = if object_id == INPUT_STREAM_ID {
-                        0x6D696320
-                    } else {
-                        0x73706B72
-                    };
-                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
-                }
-                kAudioStreamPropertyStartingChannel => {
-                    let out = _out_data as *mut UInt32;
-                    *out = 1;
-                    *_out_data_size = std::mem::size_of::<UInt32>() as UInt32;
-                }
-                kAudioStreamPropertyVirtualFormat | kAudioStreamPropertyPhysicalFormat => {
-                    let out = _out_data as *mut AudioStreamBasicDescription;
-                    let channels_per_frame: u32 = if object_id == OUTPUT_STREAM_ID {
-                        2
-                    } else {
-                        (*driver).config.num_channels
-                    };
-                    *out = AudioStreamBasicDescription {
-                        mSampleRate: 48000.0,
-                        mFormatID: kAudioFormatLinearPCM,
-                        mFormatFlags: kAudioFormatFlagIsFloat | kAudioFormatFlagIsPacked,
-                        mBytesPerPacket: 4 * channels_per_frame,
-                        mFramesPerPacket: 1,
-                        mBytesPerFrame: 4 * channels_per_frame,
-                        mChannelsPerFrame: channels_per_frame,
-                        mBitsPerChannel: 32,
-                        mReserved: 0,
-                    };
-                    *_out_data_size = std::mem::size_of::<AudioStreamBasicDescription>() as UInt32;
-                }
-                kAudioStreamPropertyPhysicalFormats
-                | kAudioStreamPropertyAvailableVirtualFormats
-                | kAudioStreamPropertyAvailablePhysicalFormats => {
-                    let out = _out_data as *mut AudioStreamRangedDescription;
-                    let channels_per_frame: u32 = if object_id == OUTPUT_STREAM_ID {
-                        2
-                    } else {
-                        (*driver).config.num_channels
-                    };
-                    *out = AudioStreamRangedDescription {
-                        mFormat: AudioStreamBasicDescription {
-                            mSampleRate: 48000.0,
-                            mFormatID: kAudioFormatLinearPCM,
-                            mFormatFlags: kAudioFormatFlagIsFloat | kAudioFormatFlagIsPacked,
-                            mBytesPerPacket: 4 * channels_per_frame,
-                            mFramesPerPacket: 1,
-                            mBytesPerFrame: 4 * channels_per_frame,
-                            mChannelsPerFrame: channels_per_frame,
-                            mBitsPerChannel: 32,
-                            mReserved: 0,
-                        },
-                        mSampleRateRange: AudioValueRange {
-                            mMinimum: 48000.0,
-                            mMaximum: 48000.0,
-                        },
-                    };
-                    *_out_data_size = std::mem::size_of::<AudioStreamRangedDescription>() as UInt32;
+    if selector == kAudioDevicePropertyHogMode {
+        if _in_data_size != std::mem::size_of::<pid_t>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+        // -1 releases, any other pid claims. Only the owner may release or hand it on;
+        // a claim on a device hogged by someone else fails the way the HAL's own does
+        let requested = *(_in_data as *const pid_t);
+        let owner = (*driver).hog_pid.load(Ordering::Acquire);
+        if owner != -1 && owner != requested && owner != _client_process_id {
+            log_msg(
+                Category::Properties,
+                &format!(
+                    "Prism: HogMode {} refused for PID {}, held by {}",
+                    requested, _client_process_id, owner
+                ),
+            );
+            return kAudioDevicePermissionsError;
+        }
+        if requested == -1 {
+            release_hog_mode(driver, owner);
+        } else if owner != requested {
+            if (*driver)
+                .hog_pid
+                .compare_exchange(owner, requested, Ordering::AcqRel, Ordering::Acquire)
+                .is_err()
+            {
+                return kAudioDevicePermissionsError;
+            }
+            log_msg(
+                Category::Properties,
+                &format!("Prism: HogMode claimed by PID {}", requested),
+            );
+            notify_device_property_changed(driver, kAudioDevicePropertyHogMode);
+        }
+        return 0;
+    }
+
+    if selector == kAudioPrismPropertyClockRate {
+        // CFData holding a binary plist: { ppm }, the clock rate offset from the host clock
+        if _in_data_size != std::mem::size_of::<CFDataRef>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+        let data_ref = *(_in_data as *const CFDataRef);
+        if data_ref.is_null() {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        let data = CFData::wrap_under_get_rule(data_ref);
+        let ppm = match Value::from_reader(std::io::Cursor::new(data.bytes())) {
+            Ok(Value::Dictionary(dict)) => dict.get("ppm").and_then(|v| {
+                v.as_real()
+                    .or_else(|| v.as_signed_integer().map(|ppm| ppm as f64))
+            }),
+            _ => None,
+        };
+        let Some(ppm) = ppm.filter(|ppm| ppm.abs() <= MAX_RATE_PPM) else {
+            log_error(
+                Category::Properties,
+                &format!(
+                    "Prism: RATE rejected: expected {{ ppm }} within ±{}",
+                    MAX_RATE_PPM
+                ),
+            );
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        };
+        (*driver)
+            .rate_scalar_target
+            .store((1.0 + ppm / 1e6).to_bits(), Ordering::Relaxed);
+        log_msg(
+            Category::Lifecycle,
+            &format!("Prism: Clock rate set to {:+.1} ppm", ppm),
+        );
+        notify_device_event(driver, kAudioPrismPropertyClockRate, PRISM_EVENT_CONFIG);
+        return 0;
+    }
+
+    if selector == kAudioPrismPropertyMatrix {
+        // CFData holding a binary plist: { pid, routes: [{ source, channel, gain }] }.
+        // source is 0 (left) or 1 (right) of the client's stereo output, channel a 0-based
+        // bus channel, gain linear. No routes returns the pid to its channel_offset pair.
+        if _in_data_size != std::mem::size_of::<CFDataRef>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+        let data_ref = *(_in_data as *const CFDataRef);
+        if data_ref.is_null() {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        let data = CFData::wrap_under_get_rule(data_ref);
+        let dict = match Value::from_reader(std::io::Cursor::new(data.bytes())) {
+            Ok(Value::Dictionary(dict)) => dict,
+            _ => {
+                log_error(
+                    Category::Routing,
+                    "Prism: MTRX rejected: payload is not a plist dictionary",
+                );
+                return kAudioHardwareIllegalOperationError as OSStatus;
+            }
+        };
+        let Some(pid) = dict
+            .get("pid")
+            .and_then(|v| v.as_signed_integer())
+            .and_then(|pid| i32::try_from(pid).ok())
+            .filter(|&pid| pid > 0)
+        else {
+            log_error(Category::Routing, "Prism: MTRX rejected: missing pid");
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        };
+        let items = match dict.get("routes") {
+            None => Vec::new(),
+            Some(Value::Array(items)) => items.clone(),
+            Some(_) => {
+                log_error(
+                    Category::Routing,
+                    "Prism: MTRX rejected: routes must be an array",
+                );
+                return kAudioHardwareIllegalOperationError as OSStatus;
+            }
+        };
+        if items.len() > MAX_MATRIX_ROUTES {
+            log_error(
+                Category::Routing,
+                &format!(
+                    "Prism: MTRX rejected: {} routes, at most {}",
+                    items.len(),
+                    MAX_MATRIX_ROUTES
+                ),
+            );
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+
+        let num_channels = u64::from((*driver).config.num_channels);
+        let mut routes = Vec::with_capacity(items.len());
+        let mut pairs = Vec::with_capacity(items.len());
+        for item in &items {
+            let route = item.as_dictionary();
+            let field = |key: &str| route.and_then(|route| route.get(key));
+            let source = field("source").and_then(|v| v.as_unsigned_integer());
+            let channel = field("channel").and_then(|v| v.as_unsigned_integer());
+            let gain = field("gain")
+                .and_then(|v| {
+                    v.as_real()
+                        .or_else(|| v.as_signed_integer().map(|g| g as f64))
+                })
+                .unwrap_or(1.0);
+            match (source, channel) {
+                (Some(source @ 0..=1), Some(channel))
+                    if channel < num_channels && gain.is_finite() && gain >= 0.0 =>
+                {
+                    routes.push(pack_matrix_route(
+                        source as u32,
+                        channel as u32,
+                        gain as f32,
+                    ));
+                    pairs.push(channel as usize / 2 * 2);
                 }
                 _ => {
-                    return kAudioHardwareUnknownPropertyError as OSStatus;
+                    log_error(
+                        Category::Routing,
+                        &format!(
+                            "Prism: MTRX rejected: invalid route {:?} (max_channels={})",
+                            item, num_channels
+                        ),
+                    );
+                    return kAudioHardwareIllegalOperationError as OSStatus;
                 }
             }
         }
-        _ => return kAudioHardwareBadObjectError as OSStatus,
+
+        let owner = dict.get("owner").and_then(|v| v.as_string());
+        for &pair in &pairs {
+            if let Err(status) = check_reservation(driver, pair, owner, |slot| {
+                slot.pid.load(Ordering::Acquire) == pid
+            }) {
+                return status;
+            }
+        }
+
+        let mut found = false;
+        for slot in (*driver).slot_table().slots.iter() {
+            if slot.client_id.load(Ordering::Acquire) != 0
+                && slot.pid.load(Ordering::Acquire) == pid
+            {
+                slot.set_matrix(&routes);
+                found = true;
+            }
+        }
+        if !found {
+            log_error(
+                Category::Routing,
+                &format!("Prism: MTRX Failed. PID={} not found", pid),
+            );
+            return kAudioHardwareBadObjectError as OSStatus;
+        }
+        log_msg(
+            Category::Routing,
+            &format!("Prism: Matrix Update PID={}, Routes={}", pid, routes.len()),
+        );
+        notify_device_event(driver, kAudioPrismPropertyMatrix, PRISM_EVENT_ROUTING);
+        notify_channel_map_changed(driver, false);
+        return 0;
     }
-    0
-}
 
-unsafe extern "C" fn set_property_data(
-    _self: AudioServerPlugInDriverRef,
-    _object_id: AudioObjectID,
-    _client_process_id: pid_t,
-    _address: *const AudioObjectPropertyAddress,
-    _qualifier_data_size: UInt32,
-    _qualifier_data: *const c_void,
-    _in_data_size: UInt32,
-    _in_data: *const c_void,
-) -> OSStatus {
-    let driver = _self as *mut PrismDriver;
-    let address = *_address;
-    let selector = address.mSelector;
-    log_msg(&format!(
-        "Prism: SetPropertyData called. Object: {}, Selector: {}",
-        _object_id, selector
-    ));
+    if selector == kAudioPrismPropertyCaptureAcl {
+        // CFData holding a binary plist: { channel_offset, bundle_id } ("" clears the rule)
+        if _in_data_size != std::mem::size_of::<CFDataRef>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+        let data_ref = *(_in_data as *const CFDataRef);
+        if data_ref.is_null() {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        let data = CFData::wrap_under_get_rule(data_ref);
+        let dict = match Value::from_reader(std::io::Cursor::new(data.bytes())) {
+            Ok(Value::Dictionary(dict)) => dict,
+            _ => {
+                log_error(
+                    Category::Routing,
+                    "Prism: CACT rejected: payload is not a plist dictionary",
+                );
+                return kAudioHardwareIllegalOperationError as OSStatus;
+            }
+        };
+        let offset = dict
+            .get("channel_offset")
+            .and_then(|v| v.as_unsigned_integer())
+            .unwrap_or(u64::MAX);
+        let bundle_id = dict
+            .get("bundle_id")
+            .and_then(|v| v.as_string())
+            .unwrap_or_default()
+            .to_string();
+
+        let max_channels = u64::from((*driver).config.num_channels);
+        if offset % 2 != 0 || offset + 1 >= max_channels {
+            log_error(
+                Category::Routing,
+                &format!(
+                    "Prism: CACT rejected: invalid channel_offset={}, max_channels={}",
+                    offset, max_channels
+                ),
+            );
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        let Some(rule) = (*driver).capture_acl.get(offset as usize / 2) else {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        };
 
-    if selector == kAudioDevicePropertyBufferFrameSize {
-        if _in_data_size != std::mem::size_of::<UInt32>() as UInt32 {
+        let hash = if bundle_id.is_empty() {
+            0
+        } else {
+            bundle_hash(&bundle_id)
+        };
+        log_msg(
+            Category::Routing,
+            &format!(
+                "Prism: Capture ACL Offset={} Bundle={:?}",
+                offset, bundle_id
+            ),
+        );
+        if let Ok(mut guard) = rule.bundle_id.lock() {
+            *guard = bundle_id;
+        }
+        rule.bundle_hash.store(hash, Ordering::Release);
+        notify_device_event(driver, kAudioPrismPropertyCaptureAcl, PRISM_EVENT_CONFIG);
+        return 0;
+    }
+
+    if selector == kAudioStreamPropertyVirtualFormat
+        || selector == kAudioStreamPropertyPhysicalFormat
+    {
+        let Some(flag) = (*driver).non_interleaved_flag(_object_id) else {
+            return kAudioHardwareBadObjectError as OSStatus;
+        };
+        if _in_data_size != std::mem::size_of::<AudioStreamBasicDescription>() as UInt32 {
             return kAudioHardwareBadPropertySizeError as OSStatus;
         }
+        let requested = *(_in_data as *const AudioStreamBasicDescription);
+        let non_interleaved = requested.mFormatFlags & kAudioFormatFlagIsNonInterleaved != 0;
+        // The output stream may also change width, except in bus mode; the bus side
+        // always has num_channels
+        let channels = if _object_id == OUTPUT_STREAM_ID
+            && !(*driver).full_bus_output.load(Ordering::Relaxed)
+            && (1..=MAX_OUTPUT_CHANNELS).contains(&requested.mChannelsPerFrame)
+        {
+            requested.mChannelsPerFrame
+        } else {
+            (*driver).stream_channels(_object_id)
+        };
+        let expected = stream_format(channels, non_interleaved);
+        // Only the layout and output width are negotiable; the rest must match what we offer
+        if requested.mFormatID != expected.mFormatID
+            || requested.mSampleRate != expected.mSampleRate
+            || requested.mFormatFlags != expected.mFormatFlags
+            || requested.mChannelsPerFrame != expected.mChannelsPerFrame
+            || requested.mBitsPerChannel != expected.mBitsPerChannel
+            || requested.mBytesPerFrame != expected.mBytesPerFrame
+        {
+            return kAudioDeviceUnsupportedFormatError as OSStatus;
+        }
+        // A new layout or width rearranges the IO buffers, so it waits for the HAL to stop
+        // IO (apply_stream_formats). Asking for the current format drops a pending one.
+        let Some(pending) = (*driver).pending_format(_object_id) else {
+            return kAudioHardwareBadObjectError as OSStatus;
+        };
+        if flag.load(Ordering::Acquire) == non_interleaved
+            && (*driver).stream_channels(_object_id) == channels
+        {
+            pending.store(0, Ordering::Release);
+            return 0;
+        }
+        pending.store(
+            (channels << 1) | u32::from(non_interleaved),
+            Ordering::Release,
+        );
+        let status = request_config_change(driver, CONFIG_CHANGE_STREAM_FORMAT);
+        if status != 0 {
+            pending.store(0, Ordering::Release);
+            log_error(
+                Category::Properties,
+                &format!("Prism: stream format change request failed: {}", status),
+            );
+        }
+        return status;
+    }
 
-        let requested_frames = unsafe { *(_in_data as *const UInt32) };
-        if requested_frames == 0 {
+    if selector == kAudioLevelControlPropertyScalarValue
+        || selector == kAudioLevelControlPropertyDecibelValue
+    {
+        let Some(channel) = volume_control_channel(driver, _object_id) else {
+            return kAudioHardwareBadObjectError as OSStatus;
+        };
+        if _in_data_size != std::mem::size_of::<Float32>() as UInt32 {
+            return kAudioHardwareBadPropertySizeError as OSStatus;
+        }
+        let value = *(_in_data as *const Float32);
+        if !value.is_finite() {
+            return kAudioHardwareIllegalOperationError as OSStatus;
+        }
+        let db = if selector == kAudioLevelControlPropertyScalarValue {
+            volume_scalar_to_db(value)
+        } else {
+            value
+        };
+        let clients = set_channel_trim_db(&*driver, channel, db);
+        if clients == 0 {
+            log_error(
+                Category::Properties,
+                &format!(
+                    "Prism: Input channel {} trim rejected: no client is routed there",
+                    channel
+                ),
+            );
             return kAudioHardwareIllegalOperationError as OSStatus;
         }
+        log_msg(
+            Category::Properties,
+            &format!(
+                "Prism: Input channel {} trim set to {:.1} dB, Clients={}",
+                channel, db, clients
+            ),
+        );
+        // Reported as a gain change, which also refreshes every control
+        notify_device_event(driver, kAudioPrismPropertyClientList, PRISM_EVENT_ROUTING);
+        return 0;
+    }
 
-        let mut changed = false;
-        {
-            let driver_mut = unsafe { &mut *driver };
-            if driver_mut.config.buffer_frame_size != requested_frames {
-                log_msg(&format!(
-                    "Prism: BufferFrameSize updated from {} to {}",
-                    driver_mut.config.buffer_frame_size, requested_frames
-                ));
+    kAudioHardwareUnknownPropertyError as OSStatus
+}
 
-                driver_mut.config.buffer_frame_size = requested_frames;
-                driver_mut.config.zero_timestamp_period = requested_frames;
+// --- Driver Callbacks ---
 
-                let frames_usize = requested_frames as usize;
-                for slot in driver_mut.client_slots.iter_mut() {
-                    slot.resize_and_clear_buffer(frames_usize);
-                    slot.last_write_time.store(0, Ordering::Release);
-                }
+// Track which clients are actually doing IO, for the 'clnt' is_running flag.
+unsafe fn set_client_io_running(driver: *mut PrismDriver, client_id: u32, running: bool) {
+    let Some(slot) = (*driver).slot_table().find(client_id) else {
+        return;
+    };
+    if slot.io_running.swap(running, Ordering::AcqRel) != running {
+        notify_device_event(driver, kAudioPrismPropertyClientList, PRISM_EVENT_CLIENTS);
+    }
+}
 
-                driver_mut
-                    .last_output_sample_time
-                    .store(0, Ordering::Release);
-                driver_mut.is_buffer_clear.store(true, Ordering::Release);
-                changed = true;
+#[allow(deprecated)]
+unsafe extern "C" fn start_io(
+    _self: AudioServerPlugInDriverRef,
+    _device_id: AudioObjectID,
+    _client_id: UInt32,
+) -> OSStatus {
+    log_msg(Category::Lifecycle, "Prism: StartIO called");
+    let driver = _self as *mut PrismDriver;
+    set_client_io_running(driver, _client_id, true);
+
+    let prev_count = (*driver).client_count.fetch_add(1, Ordering::SeqCst);
+    if prev_count == 0 {
+        reanchor(driver, libc::mach_absolute_time());
+        (*driver).num_time_stamps.store(0, Ordering::SeqCst);
+        (*driver).write_pos.store(0, Ordering::SeqCst);
+        (*driver).read_pos.store(0, Ordering::SeqCst);
+
+        if let Some(host) = (*driver).host {
+            let address = AudioObjectPropertyAddress {
+                mSelector: kAudioDevicePropertyDeviceIsRunning,
+                mScope: kAudioObjectPropertyScopeGlobal,
+                mElement: kAudioObjectPropertyElementMain,
+            };
+            if let Some(prop_changed) = (*host).PropertiesChanged {
+                prop_changed(host, _device_id, 1, &address);
+            }
+
+            // Also notify about CustomPropertyInfoList to force refresh
+            let cust_address = AudioObjectPropertyAddress {
+                mSelector: kAudioObjectPropertyCustomPropertyInfoList,
+                mScope: kAudioObjectPropertyScopeGlobal,
+                mElement: kAudioObjectPropertyElementMain,
+            };
+            if let Some(prop_changed) = (*host).PropertiesChanged {
+                prop_changed(host, _device_id, 1, &cust_address);
+                log_msg(
+                    Category::Lifecycle,
+                    "Prism: Notified PropertiesChanged for CustomPropertyInfoList",
+                );
             }
         }
+    }
+    0
+}
 
-        if changed {
-            notify_device_property_changed(driver, kAudioDevicePropertyBufferFrameSize);
-            notify_device_property_changed(driver, kAudioDevicePropertyRingBufferFrameSize);
-            notify_device_property_changed(driver, kAudioDevicePropertyZeroTimeStampPeriod);
+#[allow(deprecated)]
+unsafe extern "C" fn stop_io(
+    _self: AudioServerPlugInDriverRef,
+    _device_id: AudioObjectID,
+    _client_id: UInt32,
+) -> OSStatus {
+    // log_msg("Prism: StopIO called");
+    let driver = _self as *mut PrismDriver;
+    set_client_io_running(driver, _client_id, false);
+    // The client's last cycles would otherwise keep reaching capture apps until the
+    // slot ring ages out
+    if let Some(slot) = (*driver).slot_table().find(_client_id) {
+        slot.silence();
+    }
+
+    let prev_count = (*driver).client_count.fetch_sub(1, Ordering::SeqCst);
+    if prev_count == 1 {
+        (*driver).anchor_host_time.store(0, Ordering::SeqCst);
+
+        if let Some(host) = (*driver).host {
+            let address = AudioObjectPropertyAddress {
+                mSelector: kAudioDevicePropertyDeviceIsRunning,
+                mScope: kAudioObjectPropertyScopeGlobal,
+                mElement: kAudioObjectPropertyElementMain,
+            };
+            if let Some(prop_changed) = (*host).PropertiesChanged {
+                prop_changed(host, _device_id, 1, &address);
+            }
         }
+    }
+    0
+}
+
+#[allow(deprecated)]
+unsafe extern "C" fn get_zero_timestamp(
+    _self: AudioServerPlugInDriverRef,
+    _device_id: AudioObjectID,
+    _client_id: UInt32,
+    out_sample_time: *mut Float64,
+    out_host_time: *mut UInt64,
+    out_seed: *mut UInt64,
+) -> OSStatus {
+    let driver = _self as *mut PrismDriver;
+    let anchor = (*driver).anchor_host_time.load(Ordering::SeqCst);
 
+    if anchor == 0 {
+        *out_sample_time = 0.0;
+        *out_host_time = 0;
+        *out_seed = (*driver).timestamp_seed.load(Ordering::Acquire);
         return 0;
     }
 
-    if selector == kAudioPrismPropertyRoutingTable {
-        // CFData-only: expect a CFDataRef containing the little-endian PrismRoutingUpdate bytes
-        extern "C" {
-            fn CFDataGetLength(theData: CFDataRef) -> isize;
-            fn CFDataGetBytePtr(theData: CFDataRef) -> *const u8;
-        }
+    let current_host_time = libc::mach_absolute_time();
+    let period_frames = (*driver).zero_timestamp_period.load(Ordering::Relaxed) as f64;
+    let (base_host, base_sample, scalar) =
+        advance_rate_scalar(driver, current_host_time, period_frames);
+    let host_ticks_per_period = (*driver).host_ticks_per_frame * scalar * period_frames;
 
-        let expected_struct_size = std::mem::size_of::<PrismRoutingUpdate>();
-        let cfdata_ref_size = std::mem::size_of::<CFDataRef>();
+    // Calculate the next zero crossing on the current rate line
+    // We want the smallest N such that base + N * period > current_time
+    let elapsed_ticks = current_host_time.saturating_sub(base_host);
 
-        if _in_data_size != cfdata_ref_size as UInt32 {
-            log_msg(&format!(
-                "Prism: SetPropertyData ROUT rejected: expected CFDataRef size={}, got={}",
-                cfdata_ref_size, _in_data_size
-            ));
-            return kAudioHardwareBadPropertySizeError as OSStatus;
+    let num_periods = (elapsed_ticks as f64 / host_ticks_per_period).floor() as u64;
+    let next_period = num_periods + 1;
+
+    *out_sample_time = base_sample + next_period as f64 * period_frames;
+    *out_host_time = base_host + (next_period as f64 * host_ticks_per_period) as u64;
+    *out_seed = (*driver).timestamp_seed.load(Ordering::Acquire);
+
+    0
+}
+unsafe extern "C" fn will_do_io_operation(
+    _self: AudioServerPlugInDriverRef,
+    _device_id: AudioObjectID,
+    _client_id: UInt32,
+    _operation_id: UInt32,
+    _out_will_do: *mut Boolean,
+    _out_will_do_in_place: *mut Boolean,
+) -> OSStatus {
+    *_out_will_do = 1;
+    *_out_will_do_in_place = 1;
+    0
+}
+
+unsafe extern "C" fn begin_io_operation(
+    _self: AudioServerPlugInDriverRef,
+    _device_id: AudioObjectID,
+    _client_id: UInt32,
+    _operation_id: UInt32,
+    _io_buffer_frame_size: UInt32,
+    _io_cycle_info: *const AudioServerPlugInIOCycleInfo,
+) -> OSStatus {
+    0
+}
+
+unsafe extern "C" fn do_io_operation(
+    _self: AudioServerPlugInDriverRef,
+    _device_id: AudioObjectID,
+    _stream_id: AudioObjectID,
+    _client_id: UInt32,
+    _operation_id: UInt32,
+    _io_buffer_frame_size: UInt32,
+    _io_cycle_info: *const AudioServerPlugInIOCycleInfo,
+    _io_main_buffer: *mut c_void,
+    _io_secondary_buffer: *mut c_void,
+) -> OSStatus {
+    let driver = _self as *mut PrismDriver;
+    let frames = _io_buffer_frame_size as usize;
+    let channels = (*driver).config.num_channels as usize; // device bus channels (64)
+    let buffer_frames = (*driver).pair_buffer_frames; // Frames per pair ring
+
+    // ここで呼び出し状況を可視化
+    trace_io!(
+        "[do_io_operation] operation_id={} stream_id={} client_id={}",
+        _operation_id, _stream_id, _client_id
+    );
+
+    if _io_cycle_info.is_null() {
+        return kAudioHardwareIllegalOperationError as OSStatus;
+    }
+    #[allow(unused_variables)]
+    let cycle_info = &*_io_cycle_info;
+
+    // BufferFrameSizeRange tops out at MAX_IO_FRAMES and every ring holds several such
+    // cycles. A larger cycle would lap the rings, so it is refused; captures read silence.
+    if frames > MAX_IO_FRAMES {
+        if _operation_id == kAudioServerPlugInIOOperationReadInput && !_io_main_buffer.is_null() {
+            let stream_channels = (*driver).stream_channels(_stream_id) as usize;
+            accelerate::clear(_io_main_buffer as *mut f32, frames * stream_channels);
         }
+        (*driver).io_stats.overloads.fetch_add(1, Ordering::Relaxed);
+        trace_io!(
+            "[do_io_operation] {} frames exceeds the {} frame maximum",
+            frames,
+            MAX_IO_FRAMES
+        );
+        return kAudioHardwareIllegalOperationError as OSStatus;
+    }
 
-        let data_ref = *(_in_data as *const CFDataRef);
-        if data_ref.is_null() {
-            return kAudioHardwareIllegalOperationError as OSStatus;
+    // Enforce expected direction:
+    //  - OUTPUT_STREAM_ID receives WriteMix (app playback into 64ch bus at a 2ch slot)
+    //  - INPUT_STREAM_ID serves ReadInput (64ch bus exposed to capture clients)
+    if _operation_id == kAudioServerPlugInIOOperationProcessOutput {
+        trace_io!("[ProcessOutput] stream_id={}", _stream_id);
+
+        if _stream_id != OUTPUT_STREAM_ID {
+            return 0;
+        }
+        // Use actual frame size; update if mismatch detected
+        let current_actual = (*driver).buffer_frame_size_actual.load(Ordering::Relaxed) as usize;
+        if frames != current_actual && frames > 0 {
+            trace_io!(
+                "[ProcessOutput] adapting buffer_frame_size {} -> {}",
+                current_actual, frames
+            );
+            (*driver).buffer_frame_size_actual.store(frames as u32, Ordering::Relaxed);
         }
+        if !_io_main_buffer.is_null() {
+            let Some(slot) = (*driver).slot_table().find(_client_id) else {
+                return 0;
+            };
+            // The null bus: the client keeps playing, but its samples are dropped here,
+            // before the HAL mixes them, so neither WriteMix nor any ring sees them.
+            // Excluded bundles always take this path, whatever their route.
+            if slot.capture_excluded.load(Ordering::Relaxed)
+                || slot.routed_offset((*driver).routing_frozen()) == NULL_BUS_OFFSET as usize
+            {
+                let stream_channels = (*driver).stream_channels(_stream_id) as usize;
+                accelerate::clear(_io_main_buffer as *mut f32, frames * stream_channels);
+                return 0;
+            }
+            // Stereo-only install: there is no pair to route to, apps are heard via
+            // WriteMix. Bus mode likewise: apps address the bus channels themselves.
+            if channels == 2 || (*driver).full_bus_output.load(Ordering::Relaxed) {
+                return 0;
+            }
+
+            // While routing is frozen ReadInput plays the frozen offset, but the ring
+            // must also be current for the staged one it switches to on thaw
+            let channel_offset = slot.routed_offset((*driver).routing_frozen());
+            let staged_offset = slot.channel_offset.load(Ordering::Relaxed);
+            let mirror_offset = slot.mirror_offset.load(Ordering::Relaxed);
+            let on_bus = |offset: usize| offset >= 2 && offset + 1 < channels;
+            // A matrix-routed client fills its ring whatever its offset
+            let matrix_routed = slot.matrix_len.load(Ordering::Acquire) > 0;
+            if !matrix_routed
+                && !on_bus(channel_offset)
+                && !on_bus(staged_offset)
+                && !on_bus(mirror_offset)
+            {
+                return 0;
+            }
+
 
-        let len = unsafe { CFDataGetLength(data_ref) } as usize;
-        let ptr = unsafe { CFDataGetBytePtr(data_ref) };
-        if ptr.is_null() || len < expected_struct_size {
-            log_msg(&format!(
-                "Prism: SetPropertyData ROUT rejected: CFData length {} too small",
-                len
-            ));
-            return kAudioHardwareBadPropertySizeError as OSStatus;
-        }
 
-        // Copy into local buffer and parse little-endian fields
-        let mut buf = [0u8; std::mem::size_of::<PrismRoutingUpdate>()];
-        unsafe {
-            ptr::copy_nonoverlapping(ptr, buf.as_mut_ptr(), buf.len());
-        }
-        let pid = i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
-        let offset = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
+            trace_io!(
+                "[ProcessOutput] sample_time={:.0} frames={}",
+                cycle_info.mOutputTime.mSampleTime,
+                frames
+            );
 
-        log_msg(&format!(
-            "Prism: SetPropertyData ROUT (CFData) PID={}, Offset={}",
-            pid, offset
-        ));
+            // Write into the per-slot ring buffer (stereo: left/right interleaved)
+            if !_io_main_buffer.is_null() {
+                let Some(input) =
+                    stereo_output_frames(driver, _io_main_buffer as *const f32, frames)
+                else {
+                    (*driver).io_stats.overloads.fetch_add(1, Ordering::Relaxed);
+                    return 0;
+                };
+                let slot_buf_ptr = slot.slot_buffer.as_ptr() as *mut f32;
+                let slot_buf_frames = slot.slot_buffer.len() / 2; // stereo frames
+                let mix = slot.mix_matrix();
+                let (gain_l, gain_r) = slot.mix_gains();
 
-        let driver_ref = &*driver;
-        let slots = &driver_ref.client_slots;
+                if frames > slot_buf_frames {
+                    (*driver).io_stats.overloads.fetch_add(1, Ordering::Relaxed);
+                    return 0;
+                }
 
-        // Validate offset for 2ch write into 64ch bus
-        let max_channels = (*driver).config.num_channels;
-        if offset % 2 != 0 || offset + 1 >= max_channels {
-            log_msg(&format!(
-                "Prism: ROUT rejected: invalid channel_offset={}, max_channels={}",
-                offset, max_channels
-            ));
-            return kAudioHardwareIllegalOperationError as OSStatus;
-        }
+                // Ring buffer write: use sample_time to determine position
+                let sample_time = cycle_info.mOutputTime.mSampleTime as usize;
+                let w_pos = sample_time % slot_buf_frames;
+                let frames_until_wrap = slot_buf_frames - w_pos;
 
-        // pid == -1 => broadcast to all clients
-        if pid == -1 {
-            for slot in slots.iter() {
-                let prev = slot.channel_offset.swap(offset as usize, Ordering::AcqRel);
-                if prev != offset as usize {
-                    zero_channel_pair(driver, prev);
+                // stereo_output_frames normalizes the layout to interleaved stereo. The
+                // ring is read by ReadInput meanwhile, so it is written through raw
+                // pointers and never borrowed as a slice
+                if frames <= frames_until_wrap {
+                    // No wrapping needed
+                    mix_stereo_into(input, slot_buf_ptr.add(w_pos * 2), frames, mix);
+                } else {
+                    // Wrapping needed
+                    (*driver)
+                        .io_stats
+                        .wrap_arounds
+                        .fetch_add(1, Ordering::Relaxed);
+                    mix_stereo_into(input, slot_buf_ptr.add(w_pos * 2), frames_until_wrap, mix);
+                    mix_stereo_into(
+                        input.add(frames_until_wrap * 2),
+                        slot_buf_ptr,
+                        frames - frames_until_wrap,
+                        mix,
+                    );
+                }
+
+                let output_sample_time = cycle_info.mOutputTime.mSampleTime + (frames as f64);
+                // Published before last_write_time so readers never pair a new time with an old size
+                slot.last_write_frames
+                    .store(frames as u32, Ordering::Relaxed);
+                slot.last_write_time
+                    .store(output_sample_time.to_bits(), Ordering::Release);
+                (*driver).is_buffer_clear.store(false, Ordering::Release);
+                (*driver)
+                    .io_stats
+                    .output_cycles
+                    .fetch_add(1, Ordering::Relaxed);
+                // Pair counters and meters follow the offset route and its mirror; a
+                // matrix spans channels
+                let mirror = (mirror_offset != channel_offset).then_some(mirror_offset);
+                for offset in std::iter::once(channel_offset).chain(mirror) {
+                    if matrix_routed || !on_bus(offset) {
+                        continue;
+                    }
+                    (*driver).io_stats.add_frames(offset, frames);
+                    let Some(meter) = (*driver).bus_meters.get(offset / 2) else {
+                        continue;
+                    };
+                    let (peak, rms) = meter.accumulate(input, frames, (gain_l, gain_r));
+                    if peak[0].max(peak[1]) > AUDIBLE_PEAK {
+                        meter
+                            .last_audio
+                            .store(output_sample_time.to_bits(), Ordering::Relaxed);
+                    }
+                    if let Some(telemetry) = (*driver).telemetry {
+                        telemetry.update_bus(offset / 2, peak, rms, output_sample_time, frames);
+                    }
+                }
+
+                #[cfg(feature = "trace-io")]
+                if frames > 0 {
+                    let sample_l = *input;
+                    let sample_r = *input.add(1);
+                    trace_io!(
+                        "[ProcessOutput] client_id={} pid={} ch_offset={} output_time={:.0} data[0]={:.4} data[1]={:.4}",
+                        _client_id,
+                        slot.pid.load(Ordering::Relaxed),
+                        channel_offset,
+                        cycle_info.mOutputTime.mSampleTime,
+                        sample_l,
+                        sample_r
+                    );
                 }
             }
-            log_msg(&format!(
-                "Prism: Routing Update ROUT Broadcast. Offset={}",
-                offset
-            ));
-            notify_device_property_changed(driver, kAudioPrismPropertyClientList);
+        }
+    } else if _operation_id == kAudioServerPlugInIOOperationWriteMix {
+        if _stream_id != OUTPUT_STREAM_ID {
+            // Unexpected combination; ignore safely.
             return 0;
         }
+        if !_io_main_buffer.is_null() {
+            if frames > buffer_frames {
+                (*driver).io_stats.overloads.fetch_add(1, Ordering::Relaxed);
+                return 0;
+            }
+            let sample_time = cycle_info.mOutputTime.mSampleTime as usize;
+            let w_pos = sample_time % buffer_frames;
+            if frames > buffer_frames - w_pos {
+                (*driver)
+                    .io_stats
+                    .wrap_arounds
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+            let output_sample_time = cycle_info.mOutputTime.mSampleTime + (frames as f64);
 
-        if pid != 0 {
-            let mut found = false;
-            for slot in slots.iter() {
-                if slot.pid.load(Ordering::Acquire) == pid {
-                    let prev = slot.channel_offset.swap(offset as usize, Ordering::AcqRel);
-                    if prev != offset as usize {
-                        zero_channel_pair(driver, prev);
+            if (*driver).full_bus_output.load(Ordering::Relaxed) {
+                // Bus mode: the mix is as wide as the bus; each pair goes to its own ring,
+                // gathered into interleaved stereo in io_scratch first
+                let width = (*driver).output_channels.load(Ordering::Relaxed) as usize;
+                let non_interleaved = (*driver).output_non_interleaved.load(Ordering::Relaxed);
+                let (frame_stride, channel_stride) = if non_interleaved {
+                    (1, frames)
+                } else {
+                    (width, 1)
+                };
+                let mix = _io_main_buffer as *const f32;
+                let scratch = (*driver).io_scratch.as_ptr() as *mut f32;
+                for pair in 0..width.min(channels) / 2 {
+                    for side in 0..2 {
+                        accelerate::copy_strided(
+                            mix.add((pair * 2 + side) * channel_stride),
+                            frame_stride as isize,
+                            scratch.add(side),
+                            2,
+                            frames,
+                        );
                     }
-                    log_msg(&format!(
-                        "Prism: Routing Update via ROUT. PID={}, Offset={}",
-                        pid, offset
-                    ));
-                    found = true;
+                    write_mix_pair(driver, pair, scratch, frames, w_pos, output_sample_time);
                 }
-            }
-            if !found {
-                log_msg(&format!(
-                    "Prism: Routing Update via ROUT Failed. PID={} not found",
-                    pid
-                ));
+            } else if !(*driver).system_mix_enabled.load(Ordering::Relaxed) {
+                // System mix capture is off: the mix is accepted and dropped, so only
+                // apps routed to pairs of their own reach the bus
             } else {
-                notify_device_property_changed(driver, kAudioPrismPropertyClientList);
+                let Some(input) =
+                    stereo_output_frames(driver, _io_main_buffer as *const f32, frames)
+                else {
+                    (*driver).io_stats.overloads.fetch_add(1, Ordering::Relaxed);
+                    return 0;
+                };
+                // Treat the mix as the stereo system bus, on whichever pair it was moved to
+                let system_pair = (*driver).system_mix_offset.load(Ordering::Acquire) / 2;
+                write_mix_pair(
+                    driver,
+                    system_pair,
+                    input,
+                    frames,
+                    w_pos,
+                    output_sample_time,
+                );
+
+                #[cfg(feature = "trace-io")]
+                if frames > 0 {
+                    let sample_l = *input;
+                    let sample_r = *input.add(1);
+                    trace_io!(
+                        "[WriteMix] system_mix w_pos={} output_time={:.0} data[0]={:.4} data[1]={:.4}",
+                        w_pos, cycle_info.mOutputTime.mSampleTime, sample_l, sample_r
+                    );
+                }
             }
+
+            (*driver)
+                .last_output_sample_time
+                .store(output_sample_time.to_bits(), Ordering::Release);
+            (*driver).is_buffer_clear.store(false, Ordering::Release);
+            (*driver)
+                .io_stats
+                .output_cycles
+                .fetch_add(1, Ordering::Relaxed);
         }
+    } else if _operation_id == kAudioServerPlugInIOOperationReadInput {
+        if _stream_id != INPUT_STREAM_ID {
+            return 0;
+        }
+        if !_io_main_buffer.is_null() {
+            // Non-interleaved captures are rendered interleaved in io_scratch and
+            // spread into the host's per-channel buffers at the end of the cycle
+            let non_interleaved = (*driver).input_non_interleaved.load(Ordering::Relaxed);
+            let output = if non_interleaved {
+                (*driver).io_scratch.as_ptr() as *mut f32
+            } else {
+                _io_main_buffer as *mut f32
+            };
+            let input_sample_time = cycle_info.mInputTime.mSampleTime;
+            let sample_time = input_sample_time as usize;
+            let r_pos = sample_time % buffer_frames;
+            let frames_until_wrap = buffer_frames - r_pos;
 
-        return 0;
-    }
+            // Use actual frame size; update if mismatch detected
+            let current_actual =
+                (*driver).buffer_frame_size_actual.load(Ordering::Relaxed) as usize;
+            if frames != current_actual && frames > 0 {
+                trace_io!(
+                    "[ReadInput] adapting buffer_frame_size {} -> {}",
+                    current_actual,
+                    frames
+                );
+                (*driver)
+                    .buffer_frame_size_actual
+                    .store(frames as u32, Ordering::Relaxed);
+            }
 
-    kAudioHardwareUnknownPropertyError as OSStatus
-}
+            let last_output_bits = (*driver).last_output_sample_time.load(Ordering::Acquire);
+            let last_output_time = f64::from_bits(last_output_bits);
+            trace_io!(
+                "[ReadInput] sample_time={:.0} frames={} last_output_time={:.0} delta={:.0}",
+                input_sample_time,
+                frames,
+                last_output_time,
+                input_sample_time - last_output_time
+            );
+
+            let table = (*driver).slot_table();
+            let reader = table.find(_client_id);
+            // Bundle of the capture client, for the ACL; 0 if it has no slot
+            let reader_bundle = reader.map_or(0, |slot| slot.bundle_hash.load(Ordering::Relaxed));
+            // Channels the capture client may read; all of them if it has no slot
+            let reader_mask =
+                reader.map_or(u64::MAX, |slot| slot.capture_mask.load(Ordering::Relaxed));
+            if let Some(slot) = reader {
+                slot.last_read_time
+                    .store(input_sample_time.to_bits(), Ordering::Relaxed);
+                slot.reads.fetch_add(1, Ordering::Relaxed);
+            }
 
-// --- Driver Callbacks ---
+            // Initialize output buffer to zero using vectorized clear
+            unsafe {
+                accelerate::clear(output, frames * channels);
+            }
 
-#[allow(deprecated)]
-unsafe extern "C" fn start_io(
-    _self: AudioServerPlugInDriverRef,
-    _device_id: AudioObjectID,
-    _client_id: UInt32,
-) -> OSStatus {
-    log_msg("Prism: StartIO called");
-    let driver = _self as *mut PrismDriver;
+            // Capture allowlist: a client not on it reads silence. Cleared in the host's
+            // buffer, as a non-interleaved cycle renders into io_scratch.
+            if (*driver).capture_allowlist_enabled.load(Ordering::Relaxed)
+                && !reader.is_some_and(|slot| slot.capture_allowed.load(Ordering::Acquire))
+            {
+                accelerate::clear(_io_main_buffer as *mut f32, frames * channels);
+                return 0;
+            }
 
-    let prev_count = (*driver).client_count.fetch_add(1, Ordering::SeqCst);
-    if prev_count == 0 {
-        let now = libc::mach_absolute_time();
-        (*driver).anchor_host_time.store(now, Ordering::SeqCst);
-        (*driver).num_time_stamps.store(0, Ordering::SeqCst);
-        (*driver).write_pos.store(0, Ordering::SeqCst);
-        (*driver).read_pos.store(0, Ordering::SeqCst);
+            if frames > buffer_frames {
+                (*driver).io_stats.overloads.fetch_add(1, Ordering::Relaxed);
+                return 0;
+            }
+            (*driver)
+                .io_stats
+                .cycles_served
+                .fetch_add(1, Ordering::Relaxed);
+            if let Some(telemetry) = (*driver).telemetry {
+                telemetry.record_input_cycle(libc::mach_absolute_time(), input_sample_time);
+            }
 
-        if let Some(host) = (*driver).host {
-            let address = AudioObjectPropertyAddress {
-                mSelector: kAudioDevicePropertyDeviceIsRunning,
-                mScope: kAudioObjectPropertyScopeGlobal,
-                mElement: kAudioObjectPropertyElementMaster,
-            };
-            if let Some(prop_changed) = (*host).PropertiesChanged {
-                prop_changed(host, _device_id, 1, &address);
+            // Watchdog: a writer that stopped without StopIO leaves its last buffers in
+            // the rings. Zero them once it has missed watchdog_cycles cycles; this is
+            // separate from the per-read timing check, which only skips the stale part.
+            let mix_watchdog_frames = (*driver).config.watchdog_cycles as f64 * frames as f64;
+            if last_output_time > 0.0
+                && input_sample_time - last_output_time > mix_watchdog_frames
+                && (*driver)
+                    .is_buffer_clear
+                    .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+            {
+                // WriteMix fills only the system pair, or every pair in bus mode
+                let full_bus = (*driver).full_bus_output.load(Ordering::Relaxed);
+                let system_pair = (*driver).system_mix_offset.load(Ordering::Relaxed) / 2;
+                for (pair, entry) in (*driver).pair_buffers.iter().enumerate() {
+                    if !full_bus && pair != system_pair {
+                        continue;
+                    }
+                    let ring = entry.load(Ordering::Acquire);
+                    if !ring.is_null() {
+                        ptr::write_bytes(ring, 0, buffer_frames * 2);
+                    }
+                }
+                (*driver)
+                    .io_stats
+                    .watchdog_clears
+                    .fetch_add(1, Ordering::Relaxed);
             }
 
-            // Also notify about CustomPropertyInfoList to force refresh
-            let cust_address = AudioObjectPropertyAddress {
-                mSelector: kAudioObjectPropertyCustomPropertyInfoList,
-                mScope: kAudioObjectPropertyScopeGlobal,
-                mElement: kAudioObjectPropertyElementMaster,
-            };
-            if let Some(prop_changed) = (*host).PropertiesChanged {
-                prop_changed(host, _device_id, 1, &cust_address);
-                log_msg("Prism: Notified PropertiesChanged for CustomPropertyInfoList");
+            if channels == 2 {
+                read_input_stereo(driver, output, r_pos, frames, reader_bundle);
+                clear_masked_channels(output, channels, frames, reader_mask);
+                if non_interleaved {
+                    deinterleave(output, _io_main_buffer as *mut f32, channels, frames);
+                }
+                return 0;
             }
-        }
-    }
-    0
-}
 
-#[allow(deprecated)]
-unsafe extern "C" fn stop_io(
-    _self: AudioServerPlugInDriverRef,
-    _device_id: AudioObjectID,
-    _client_id: UInt32,
-) -> OSStatus {
-    // log_msg("Prism: StopIO called");
-    let driver = _self as *mut PrismDriver;
+            // Copy the pair rings WriteMix fills: the system mix pair, or every pair in bus
+            // mode; rings left over from an earlier mode or system mix pair are skipped.
+            // The output was cleared above, so adding is equivalent to copying.
+            if frames > frames_until_wrap {
+                (*driver)
+                    .io_stats
+                    .wrap_arounds
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+            let full_bus = (*driver).full_bus_output.load(Ordering::Relaxed);
+            let system_pair = (*driver).system_mix_offset.load(Ordering::Relaxed) / 2;
+            for (pair, entry) in (*driver).pair_buffers.iter().enumerate() {
+                let ring = entry.load(Ordering::Acquire);
+                if ring.is_null() || (!full_bus && pair != system_pair) {
+                    continue;
+                }
+                mix_stereo_ring(
+                    ring,
+                    buffer_frames,
+                    r_pos,
+                    output,
+                    pair * 2,
+                    channels,
+                    frames,
+                );
+            }
 
-    let prev_count = (*driver).client_count.fetch_sub(1, Ordering::SeqCst);
-    if prev_count == 1 {
-        (*driver).anchor_host_time.store(0, Ordering::SeqCst);
+            // Mix per-slot buffers into output for active clients. While routing is
+            // frozen every slot plays where it was at the freeze, without crossfades;
+            // the thaw starts the fades to the staged offsets
+            let slots_ref = &table.slots;
+            let on_bus = |offset: usize| offset >= 2 && offset + 1 < channels;
+            let frozen = (*driver).routing_frozen();
+            for slot in slots_ref.iter() {
+                let client_id = slot.client_id.load(Ordering::Acquire);
+                if client_id == 0 {
+                    continue;
+                }
 
-        if let Some(host) = (*driver).host {
-            let address = AudioObjectPropertyAddress {
-                mSelector: kAudioDevicePropertyDeviceIsRunning,
-                mScope: kAudioObjectPropertyScopeGlobal,
-                mElement: kAudioObjectPropertyElementMaster,
-            };
-            if let Some(prop_changed) = (*host).PropertiesChanged {
-                prop_changed(host, _device_id, 1, &address);
-            }
-        }
-    }
-    0
-}
+                let channel_offset = slot.routed_offset(frozen);
+                // A mirror plays the same ring on a second pair, at unity and without fades
+                let mirror_offset = slot.mirror_offset.load(Ordering::Relaxed);
+                let mirrored = on_bus(mirror_offset) && mirror_offset != channel_offset;
+                let matrix_routed = slot.matrix_len.load(Ordering::Acquire) > 0;
+                let ramp = if frozen {
+                    None
+                } else {
+                    slot.route_ramp(input_sample_time, channel_offset)
+                };
+                if !matrix_routed
+                    && !on_bus(channel_offset)
+                    && !mirrored
+                    && !ramp.is_some_and(|(prev, _)| on_bus(prev))
+                {
+                    continue;
+                }
 
-#[allow(deprecated)]
-unsafe extern "C" fn get_zero_timestamp(
-    _self: AudioServerPlugInDriverRef,
-    _device_id: AudioObjectID,
-    _client_id: UInt32,
-    out_sample_time: *mut Float64,
-    out_host_time: *mut UInt64,
-    out_seed: *mut UInt64,
-) -> OSStatus {
-    let driver = _self as *mut PrismDriver;
-    let anchor = (*driver).anchor_host_time.load(Ordering::SeqCst);
+                let last_write_bits = slot.last_write_time.load(Ordering::Acquire);
+                let last_write_time = f64::from_bits(last_write_bits);
+                // Timing is judged in the writer's cycles: a client running 4096-frame
+                // buffers legitimately leaves longer gaps than the device default
+                let write_frames = match slot.last_write_frames.load(Ordering::Relaxed) {
+                    0 => frames,
+                    n => n as usize,
+                };
+                let watchdog_frames = (*driver).config.watchdog_cycles as f64 * write_frames as f64;
+                if last_write_time > 0.0 && input_sample_time - last_write_time > watchdog_frames {
+                    if slot.silence_if_stalled(last_write_bits) {
+                        (*driver)
+                            .io_stats
+                            .watchdog_clears
+                            .fetch_add(1, Ordering::Relaxed);
+                    }
+                    continue;
+                }
 
-    if anchor == 0 {
-        *out_sample_time = 0.0;
-        *out_host_time = 0;
-        *out_seed = 0;
-        return 0;
-    }
+                // Mix if slot has been written to (ring buffer always has valid data after first write)
+                if last_write_time > 0.0 {
+                    let slot_buf_ptr = slot.slot_buffer.as_ptr();
+                    let slot_buf_frames = slot.slot_buffer.len() / 2; // stereo frames
 
-    let current_host_time = libc::mach_absolute_time();
-    let period_frames = (*driver).config.zero_timestamp_period as f64; // kZeroTimeStampPeriod
-    let host_ticks_per_period = (*driver).host_ticks_per_frame * period_frames;
+                    // The read window runs more than one writer cycle past the newest
+                    // write: what the ring holds there is from a lap ago, not current audio.
+                    // A hold-last pair repeats the newest cycle's worth of audio instead,
+                    // while the gap stays within its hold; others skip the slot (silence).
+                    let written_end = last_write_time + (write_frames as f64);
+                    let mut read_time = input_sample_time;
+                    if input_sample_time + (frames as f64) > written_end || frames > slot_buf_frames
+                    {
+                        let hold = (*driver)
+                            .gap_holds
+                            .get(channel_offset / 2)
+                            .map_or(0, |hold| hold.load(Ordering::Relaxed));
+                        let gap = input_sample_time + (frames as f64) - written_end;
+                        if hold == 0
+                            || frames > slot_buf_frames
+                            || gap > f64::from(hold)
+                            || written_end < frames as f64
+                        {
+                            (*driver)
+                                .io_stats
+                                .stale_reads
+                                .fetch_add(1, Ordering::Relaxed);
+                            continue;
+                        }
+                        read_time = written_end - frames as f64;
+                    }
+                    let slot_r_pos = (read_time as usize) % slot_buf_frames;
+                    if frames > slot_buf_frames - slot_r_pos {
+                        (*driver)
+                            .io_stats
+                            .wrap_arounds
+                            .fetch_add(1, Ordering::Relaxed);
+                    }
 
-    // Calculate the next zero crossing based on anchor time
-    // We want the smallest N such that anchor + N * period > current_time
-    let elapsed_ticks = current_host_time.saturating_sub(anchor);
+                    if matrix_routed {
+                        for (source, channel, gain) in slot.matrix_routes() {
+                            if channel < channels {
+                                mix_ring_channel(
+                                    slot_buf_ptr,
+                                    slot_buf_frames,
+                                    slot_r_pos,
+                                    source,
+                                    output,
+                                    channel,
+                                    channels,
+                                    frames,
+                                    gain,
+                                );
+                            }
+                        }
+                        continue;
+                    }
+
+                    match ramp {
+                        // Rerouted moments ago: fade out on the old pair while fading in
+                        // on the new one, then continue at unity on the new pair
+                        Some((prev_offset, elapsed)) => {
+                            let ramp_frames = (ROUTE_RAMP_FRAMES - elapsed).min(frames);
+                            let step = 1.0 / ROUTE_RAMP_FRAMES as f32;
+                            let fade_in = elapsed as f32 * step;
+                            if on_bus(prev_offset) {
+                                mix_stereo_ring_ramped(
+                                    slot_buf_ptr,
+                                    slot_buf_frames,
+                                    slot_r_pos,
+                                    output,
+                                    prev_offset,
+                                    channels,
+                                    ramp_frames,
+                                    1.0 - fade_in,
+                                    -step,
+                                );
+                            }
+                            if on_bus(channel_offset) {
+                                mix_stereo_ring_ramped(
+                                    slot_buf_ptr,
+                                    slot_buf_frames,
+                                    slot_r_pos,
+                                    output,
+                                    channel_offset,
+                                    channels,
+                                    ramp_frames,
+                                    fade_in,
+                                    step,
+                                );
+                                mix_stereo_ring(
+                                    slot_buf_ptr,
+                                    slot_buf_frames,
+                                    (slot_r_pos + ramp_frames) % slot_buf_frames,
+                                    output.add(ramp_frames * channels),
+                                    channel_offset,
+                                    channels,
+                                    frames - ramp_frames,
+                                );
+                            }
+                        }
+                        None if on_bus(channel_offset) => mix_stereo_ring(
+                            slot_buf_ptr,
+                            slot_buf_frames,
+                            slot_r_pos,
+                            output,
+                            channel_offset,
+                            channels,
+                            frames,
+                        ),
+                        None => {}
+                    }
+                    if mirrored {
+                        mix_stereo_ring(
+                            slot_buf_ptr,
+                            slot_buf_frames,
+                            slot_r_pos,
+                            output,
+                            mirror_offset,
+                            channels,
+                            frames,
+                        );
+                    }
+                }
+            }
+
+            // Solo: while any pair is soloed the others read as silence
+            let solo = &(*driver).bus_solo;
+            if solo.iter().any(|soloed| soloed.load(Ordering::Relaxed)) {
+                for (pair, soloed) in solo.iter().enumerate() {
+                    if !soloed.load(Ordering::Relaxed) {
+                        accelerate::clear_strided(output.add(pair * 2), channels as isize, frames);
+                        accelerate::clear_strided(
+                            output.add(pair * 2 + 1),
+                            channels as isize,
+                            frames,
+                        );
+                    }
+                }
+            }
+
+            // Restricted pairs read as silence for capture clients of other bundles
+            for (pair, rule) in (*driver).capture_acl.iter().enumerate() {
+                let allowed = rule.bundle_hash.load(Ordering::Acquire);
+                if allowed != 0 && allowed != reader_bundle {
+                    accelerate::clear_strided(output.add(pair * 2), channels as isize, frames);
+                    accelerate::clear_strided(output.add(pair * 2 + 1), channels as isize, frames);
+                }
+            }
 
-    let num_periods = (elapsed_ticks as f64 / host_ticks_per_period).floor() as u64;
-    let next_period = num_periods + 1;
+            clear_masked_channels(output, channels, frames, reader_mask);
 
-    *out_sample_time = next_period as f64 * period_frames;
-    *out_host_time = anchor + (next_period as f64 * host_ticks_per_period) as u64;
-    *out_seed = 1;
+            if non_interleaved {
+                deinterleave(output, _io_main_buffer as *mut f32, channels, frames);
+            }
 
+            // Debug: Log buffer info after timing check
+            #[cfg(feature = "trace-io")]
+            {
+                static mut READ_COUNT: u32 = 0;
+                READ_COUNT += 1;
+                if READ_COUNT.is_multiple_of(100) {
+                    let pid = reader.map_or(0, |slot| slot.pid.load(Ordering::Relaxed));
+                    // Sample first few channels from the output buffer (after timing check)
+                    let sample_ch0 = *output;
+                    let sample_ch1 = *output.add(1);
+                    let sample_ch2 = *output.add(2);
+                    let sample_ch3 = *output.add(3);
+                    trace_io!("[ReadInput] client_id={} pid={} r_pos={} input_time={:.0} frames={} ch[0]={:.4} ch[1]={:.4} ch[2]={:.4} ch[3]={:.4}",
+                        _client_id, pid, r_pos, input_sample_time, frames, sample_ch0, sample_ch1, sample_ch2, sample_ch3);
+                }
+            }
+        }
+    }
     0
 }
-unsafe extern "C" fn will_do_io_operation(
-    _self: AudioServerPlugInDriverRef,
-    _device_id: AudioObjectID,
-    _client_id: UInt32,
-    _operation_id: UInt32,
-    _out_will_do: *mut Boolean,
-    _out_will_do_in_place: *mut Boolean,
-) -> OSStatus {
-    *_out_will_do = 1;
-    *_out_will_do_in_place = 1;
-    0
-}
-
-unsafe extern "C" fn begin_io_operation(
+unsafe extern "C" fn end_io_operation(
     _self: AudioServerPlugInDriverRef,
     _device_id: AudioObjectID,
     _client_id: UInt32,
@@ -1604,450 +7260,1177 @@ unsafe extern "C" fn begin_io_operation(
     _io_buffer_frame_size: UInt32,
     _io_cycle_info: *const AudioServerPlugInIOCycleInfo,
 ) -> OSStatus {
+    let driver = _self as *mut PrismDriver;
+    if driver.is_null() || _io_cycle_info.is_null() {
+        return 0;
+    }
+    check_io_deadline(
+        driver,
+        _operation_id,
+        _io_buffer_frame_size,
+        &*_io_cycle_info,
+    );
     0
 }
 
-unsafe extern "C" fn do_io_operation(
-    _self: AudioServerPlugInDriverRef,
-    _device_id: AudioObjectID,
-    _stream_id: AudioObjectID,
-    _client_id: UInt32,
-    _operation_id: UInt32,
-    _io_buffer_frame_size: UInt32,
-    _io_cycle_info: *const AudioServerPlugInIOCycleInfo,
-    _io_main_buffer: *mut c_void,
-    _io_secondary_buffer: *mut c_void,
-) -> OSStatus {
-    let driver = _self as *mut PrismDriver;
-    let loopback_buffer = &mut (*driver).loopback_buffer;
-    let frames = _io_buffer_frame_size as usize;
-    let channels = (*driver).config.num_channels as usize; // device bus channels (64)
-    let buffer_len = loopback_buffer.len(); // Total samples in buffer
-    let buffer_frames = buffer_len / channels; // Total frames in buffer
+// Hold an IO operation that just ended against its cycle: ending more than a period
+// after the cycle began means it ran long, and output written after the cycle's output
+// time missed it. Both count in 'stat'. The first overload since 'stat' was last read is
+// also raised as kAudioDeviceProcessorOverload and a diag event, so a starved machine
+// cannot flood the HAL with notifications; the notifier thread sends them.
+#[inline]
+unsafe fn check_io_deadline(
+    driver: *mut PrismDriver,
+    operation_id: UInt32,
+    frames: UInt32,
+    cycle_info: &AudioServerPlugInIOCycleInfo,
+) {
+    let now = libc::mach_absolute_time();
+    let stats = &(*driver).io_stats;
+    let period_ticks = frames as f64 * (*driver).host_ticks_per_frame;
+    let mut overloaded = false;
+
+    let elapsed = now.saturating_sub(cycle_info.mCurrentTime.mHostTime);
+    if elapsed as f64 > period_ticks {
+        stats.late_cycles.fetch_add(1, Ordering::Relaxed);
+        overloaded = true;
+    }
+    let output_time = &cycle_info.mOutputTime;
+    if (operation_id == kAudioServerPlugInIOOperationProcessOutput
+        || operation_id == kAudioServerPlugInIOOperationWriteMix)
+        && output_time.mFlags & kAudioTimeStampHostTimeValid != 0
+        && now > output_time.mHostTime
+    {
+        stats.missed_writes.fetch_add(1, Ordering::Relaxed);
+        overloaded = true;
+    }
 
-    // ここで呼び出し状況を可視化
-    log_msg(&format!(
-        "[do_io_operation] operation_id={} stream_id={} client_id={}",
-        _operation_id, _stream_id, _client_id
-    ));
+    if overloaded && !(*driver).overload_reported.swap(true, Ordering::AcqRel) {
+        (*driver)
+            .pending_events
+            .fetch_or(PRISM_EVENT_DIAG, Ordering::AcqRel);
+        (*driver).overload_pending.store(true, Ordering::Release);
+        if let Some(notifier) = (*driver).notifier.get() {
+            notifier.thread().unpark();
+        }
+    }
+}
 
-    if _io_cycle_info.is_null() {
-        return kAudioHardwareIllegalOperationError as OSStatus;
+// Start the thread that sends the notifications the IO thread only flags. It sleeps
+// until unparked, so it costs nothing while the IO path is healthy.
+unsafe fn start_notifier(driver: *mut PrismDriver) {
+    if (*driver).notifier.get().is_some() {
+        return;
     }
-    #[allow(unused_variables)]
-    let cycle_info = &*_io_cycle_info;
+    // Drop joins the thread before the driver goes away
+    let address = driver as usize;
+    let spawned = thread::Builder::new()
+        .name("prism-notifier".to_string())
+        .spawn(move || {
+            let driver = address as *mut PrismDriver;
+            loop {
+                thread::park();
+                if (*driver).notifier_stop.load(Ordering::Acquire) {
+                    break;
+                }
+                if (*driver).overload_pending.swap(false, Ordering::AcqRel) {
+                    notify_properties_changed(
+                        driver,
+                        DEVICE_ID,
+                        &[kAudioDeviceProcessorOverload, kAudioPrismPropertyEvents],
+                    );
+                }
+            }
+        });
+    match spawned {
+        Ok(handle) => {
+            let _ = (*driver).notifier.set(handle);
+        }
+        Err(err) => log_error(
+            Category::Lifecycle,
+            &format!(
+                "Prism: Notifier thread failed to start ({}); overloads show in 'stat' only",
+                err
+            ),
+        ),
+    }
+}
 
-    // Enforce expected direction:
-    //  - OUTPUT_STREAM_ID receives WriteMix (app playback into 64ch bus at a 2ch slot)
-    //  - INPUT_STREAM_ID serves ReadInput (64ch bus exposed to capture clients)
-    if _operation_id == kAudioServerPlugInIOOperationProcessOutput {
-        log_msg(&format!("[ProcessOutput] stream_id={}", _stream_id));
+// Helper for logging
+// Logged at the category's level; prismd raises the runtime level via 'logl'.
+fn log_msg(category: Category, msg: &str) {
+    oslog::log(category, category.level(), msg);
+}
 
-        if _stream_id != OUTPUT_STREAM_ID {
-            return 0;
+fn log_error(category: Category, msg: &str) {
+    oslog::log(category, Level::Error, msg);
+}
+
+fn notify_properties_changed(
+    driver: *mut PrismDriver,
+    object_id: AudioObjectID,
+    selectors: &[AudioObjectPropertySelector],
+) {
+    let addresses: Vec<AudioObjectPropertyAddress> = selectors
+        .iter()
+        .map(|&selector| AudioObjectPropertyAddress {
+            mSelector: selector,
+            mScope: kAudioObjectPropertyScopeGlobal,
+            mElement: kAudioObjectPropertyElementMain,
+        })
+        .collect();
+    notify_addresses_changed(driver, object_id, &addresses);
+}
+
+fn notify_addresses_changed(
+    driver: *mut PrismDriver,
+    object_id: AudioObjectID,
+    addresses: &[AudioObjectPropertyAddress],
+) {
+    unsafe {
+        if driver.is_null() {
+            return;
         }
-        // Use actual frame size; update if mismatch detected
-        let current_actual = (*driver).buffer_frame_size_actual.load(Ordering::Relaxed) as usize;
-        if frames != current_actual && frames > 0 {
-            log_msg(&format!(
-                "[ProcessOutput] adapting buffer_frame_size {} -> {}",
-                current_actual, frames
-            ));
-            (*driver).buffer_frame_size_actual.store(frames as u32, Ordering::Relaxed);
+        let Some(host) = (*driver).host else {
+            return;
+        };
+        let Some(prop_changed) = (*host).PropertiesChanged else {
+            return;
+        };
+        prop_changed(
+            host,
+            object_id,
+            addresses.len() as UInt32,
+            addresses.as_ptr(),
+        );
+    }
+}
+
+// The channel map changed: push every channel's element name and the preferred stereo
+// pair on both scopes and, when the output width or its mapping onto the bus moved, the
+// output stream formats. Routing, bus output mode and anything else that renames
+// channels notifies through here rather than picking selectors itself.
+fn notify_channel_map_changed(driver: *mut PrismDriver, output_mapping_changed: bool) {
+    if driver.is_null() {
+        return;
+    }
+    let channels = unsafe { (*driver).config.num_channels }.max(MAX_OUTPUT_CHANNELS);
+    let mut addresses = Vec::with_capacity(2 * (channels as usize + 1));
+    for scope in [
+        kAudioObjectPropertyScopeInput,
+        kAudioObjectPropertyScopeOutput,
+    ] {
+        addresses.push(AudioObjectPropertyAddress {
+            mSelector: kAudioDevicePropertyPreferredChannelsForStereo,
+            mScope: scope,
+            mElement: kAudioObjectPropertyElementMain,
+        });
+        // Names past the current width are gone; listeners re-query and find them missing
+        addresses.extend((1..=channels).map(|element| AudioObjectPropertyAddress {
+            mSelector: kAudioObjectPropertyElementName,
+            mScope: scope,
+            mElement: element,
+        }));
+    }
+    notify_addresses_changed(driver, DEVICE_ID, &addresses);
+
+    if output_mapping_changed {
+        notify_properties_changed(
+            driver,
+            OUTPUT_STREAM_ID,
+            &[
+                kAudioStreamPropertyVirtualFormat,
+                kAudioStreamPropertyPhysicalFormat,
+            ],
+        );
+    }
+}
+
+fn notify_device_property_changed(driver: *mut PrismDriver, selector: AudioObjectPropertySelector) {
+    unsafe {
+        if driver.is_null() {
+            return;
         }
-        if !_io_main_buffer.is_null() {
-            let idx = (_client_id as usize) & (MAX_CLIENTS - 1);
-            let slots = &(*driver).client_slots;
-            let slot = &slots[idx];
+        if let Some(host) = (*driver).host {
+            if let Some(prop_changed) = (*host).PropertiesChanged {
+                let address = AudioObjectPropertyAddress {
+                    mSelector: selector,
+                    mScope: kAudioObjectPropertyScopeGlobal,
+                    mElement: kAudioObjectPropertyElementMain,
+                };
+                prop_changed(host, DEVICE_ID, 1, &address);
+            }
+        }
+    }
+}
 
-            if slot.client_id.load(Ordering::Acquire) != _client_id {
-                return 0;
+// Latency and safety offset of both scopes, for the HAL to pick up new 'cnfg' values.
+fn notify_timing_changed(driver: *mut PrismDriver) {
+    unsafe {
+        if driver.is_null() {
+            return;
+        }
+        if let Some(host) = (*driver).host {
+            if let Some(prop_changed) = (*host).PropertiesChanged {
+                let mut addresses = Vec::with_capacity(4);
+                for selector in [
+                    kAudioDevicePropertyLatency,
+                    kAudioDevicePropertySafetyOffset,
+                ] {
+                    for scope in [
+                        kAudioObjectPropertyScopeInput,
+                        kAudioObjectPropertyScopeOutput,
+                    ] {
+                        addresses.push(AudioObjectPropertyAddress {
+                            mSelector: selector,
+                            mScope: scope,
+                            mElement: kAudioObjectPropertyElementMain,
+                        });
+                    }
+                }
+                prop_changed(
+                    host,
+                    DEVICE_ID,
+                    addresses.len() as UInt32,
+                    addresses.as_ptr(),
+                );
             }
+        }
+    }
+}
 
-            let channel_offset = slot.channel_offset.load(Ordering::Relaxed);
-            if channel_offset < 2 || channel_offset + 1 >= channels {
-                return 0;
+// Notify `selector` and fold `events` into the 'evnt' mask in a single PropertiesChanged.
+// Events accumulate until prismd reads 'evnt', so bursts of changes cost it one read each.
+fn notify_device_event(
+    driver: *mut PrismDriver,
+    selector: AudioObjectPropertySelector,
+    events: u32,
+) {
+    if driver.is_null() {
+        return;
+    }
+    unsafe {
+        if selector == kAudioPrismPropertyClientList {
+            (*driver)
+                .client_list_generation
+                .fetch_add(1, Ordering::AcqRel);
+        }
+        (*driver).pending_events.fetch_or(events, Ordering::AcqRel);
+    }
+    notify_properties_changed(driver, DEVICE_ID, &[selector, kAudioPrismPropertyEvents]);
+    // Clients coming and going, routing and gain changes move the input volume controls,
+    // which read client gains
+    if events & (PRISM_EVENT_CLIENTS | PRISM_EVENT_ROUTING) != 0 {
+        notify_trims_changed(driver);
+    }
+}
+
+// Held from probing for a free slot until the client is stored in it, while a removed
+// client's slot is reset, and by grow_slot_table, so a client can never be claimed into
+// a table that is being replaced or have its fields cleared by the client it replaced.
+static SLOT_CLAIMS: Mutex<()> = Mutex::new(());
+
+// Build a larger slot table in which every live client and `incoming` find a slot
+// within their probe windows, move live clients into it and publish it. The caller
+// holds SLOT_CLAIMS. Runs off the RT path; returns false when no such table fits
+// within `max_client_slots`.
+unsafe fn grow_slot_table(driver: *mut PrismDriver, incoming: u32) -> bool {
+    let old = (*driver).slot_table();
+    let mut ids: Vec<u32> = old
+        .slots
+        .iter()
+        .map(|slot| slot.client_id.load(Ordering::Acquire))
+        .filter(|id| *id != 0)
+        .collect();
+    ids.push(incoming);
+
+    let max_size = (*driver).config.max_client_slots as usize;
+    let mut size = old.slots.len() * 2;
+    loop {
+        if size > max_size {
+            return false;
+        }
+        // Dry run of the placements the move below will make, in the same order
+        let mask = size - 1;
+        let window = SLOT_PROBE_LIMIT.min(size);
+        let mut taken = vec![false; size];
+        let fits = ids.iter().all(|id| {
+            let free = (0..window)
+                .map(|step| (*id as usize + step) & mask)
+                .find(|idx| !taken[*idx]);
+            if let Some(idx) = free {
+                taken[idx] = true;
             }
+            free.is_some()
+        });
+        if fits {
+            break;
+        }
+        size *= 2;
+    }
 
+    let table = Box::new(SlotTable::regrown(old, size));
+    let old_ptr = (*driver)
+        .client_slots
+        .swap(Box::into_raw(table), Ordering::AcqRel);
+    if let Ok(mut retired) = (*driver).retired_slot_tables.lock() {
+        retired.push(Box::from_raw(old_ptr));
+    }
+    (*driver)
+        .io_stats
+        .slot_table_growths
+        .fetch_add(1, Ordering::Relaxed);
+    log_msg(
+        Category::Lifecycle,
+        &format!(
+            "Prism: Slot table grown from {} to {} slots",
+            old.slots.len(),
+            size
+        ),
+    );
+    notify_device_event(driver, kAudioPrismPropertyIoStats, PRISM_EVENT_DIAG);
+    true
+}
 
+// mix_stereo_ring with a linear gain ramp starting at `gain` and moving by `step` per frame.
+#[allow(clippy::too_many_arguments)]
+unsafe fn mix_stereo_ring_ramped(
+    ring: *const f32,
+    ring_frames: usize,
+    r_pos: usize,
+    output: *mut f32,
+    channel: usize,
+    channels: usize,
+    frames: usize,
+    gain: f32,
+    step: f32,
+) {
+    let first = frames.min(ring_frames - r_pos);
+    let mut next_gain = gain;
+    for ch in 0..2 {
+        next_gain = accelerate::add_ramped(
+            ring.add(r_pos * 2 + ch),
+            2,
+            output.add(channel + ch),
+            channels as isize,
+            gain,
+            step,
+            first,
+        );
+    }
 
-            log_msg(&format!(
-                "[ProcessOutput] sample_time={:.0} frames={}",
-                cycle_info.mOutputTime.mSampleTime,
-                frames
-            ));
+    let remainder = frames - first;
+    if remainder > 0 {
+        let out_offset = first * channels;
+        for ch in 0..2 {
+            accelerate::add_ramped(
+                ring.add(ch),
+                2,
+                output.add(channel + ch + out_offset),
+                channels as isize,
+                next_gain,
+                step,
+                remainder,
+            );
+        }
+    }
+}
 
-            // Write into the per-slot ring buffer (stereo: left/right interleaved)
-            if !_io_main_buffer.is_null() {
-                let input = _io_main_buffer as *const f32;
-                let input_channels = 2;
-                let slots_ref = &(*driver).client_slots;
-                let idx = (_client_id as usize) & (MAX_CLIENTS - 1);
-                let slot_buf_ptr = slots_ref[idx].slot_buffer.as_ptr() as *mut f32;
-                let slot_buf_frames = slots_ref[idx].slot_buffer.len() / 2; // stereo frames
+// ReadInput for stereo-only installs (num_channels = 2), where the device is a plain
+// loopback of the system mix. Nothing can be routed off pair 0, so the slot table is
+// never walked and the ring is copied straight into the output.
+#[inline]
+unsafe fn read_input_stereo(
+    driver: *mut PrismDriver,
+    output: *mut f32,
+    r_pos: usize,
+    frames: usize,
+    reader_bundle: u64,
+) {
+    let ring = (*driver).pair_buffers[0].load(Ordering::Acquire);
+    let allowed = (*driver).capture_acl[0].bundle_hash.load(Ordering::Acquire);
+    if ring.is_null() || (allowed != 0 && allowed != reader_bundle) {
+        accelerate::clear(output, frames * 2);
+        return;
+    }
+    let first = frames.min((*driver).pair_buffer_frames - r_pos);
+    ptr::copy_nonoverlapping(ring.add(r_pos * 2), output, first * 2);
+    ptr::copy_nonoverlapping(ring, output.add(first * 2), (frames - first) * 2);
+}
 
-                // Ring buffer write: use sample_time to determine position
-                let sample_time = cycle_info.mOutputTime.mSampleTime as usize;
-                let w_pos = sample_time % slot_buf_frames;
-                let frames_until_wrap = slot_buf_frames - w_pos;
+// Silence the channels of an interleaved buffer that `mask` leaves out (bit n for
+// channel n + 1).
+unsafe fn clear_masked_channels(output: *mut f32, channels: usize, frames: usize, mask: u64) {
+    if mask == u64::MAX {
+        return;
+    }
+    for ch in (0..channels.min(64)).filter(|ch| mask & (1 << ch) == 0) {
+        accelerate::clear_strided(output.add(ch), channels as isize, frames);
+    }
+}
 
-                if frames <= frames_until_wrap {
-                    // No wrapping needed
-                    for i in 0..frames {
-                        let in_l = *input.add(i * input_channels);
-                        let in_r = *input.add(i * input_channels + 1);
-                        let dst = (w_pos + i) * 2;
-                        std::ptr::write(slot_buf_ptr.add(dst), in_l);
-                        std::ptr::write(slot_buf_ptr.add(dst + 1), in_r);
-                    }
-                } else {
-                    // Wrapping needed
-                    for i in 0..frames_until_wrap {
-                        let in_l = *input.add(i * input_channels);
-                        let in_r = *input.add(i * input_channels + 1);
-                        let dst = (w_pos + i) * 2;
-                        std::ptr::write(slot_buf_ptr.add(dst), in_l);
-                        std::ptr::write(slot_buf_ptr.add(dst + 1), in_r);
-                    }
-                    let remainder = frames - frames_until_wrap;
-                    for i in 0..remainder {
-                        let src_idx = frames_until_wrap + i;
-                        let in_l = *input.add(src_idx * input_channels);
-                        let in_r = *input.add(src_idx * input_channels + 1);
-                        let dst = i * 2;
-                        std::ptr::write(slot_buf_ptr.add(dst), in_l);
-                        std::ptr::write(slot_buf_ptr.add(dst + 1), in_r);
-                    }
-                }
+// Optional "swap" of a 'rout' plist entry: None when absent, Err when not a boolean.
+fn parse_swap_field(value: Option<&Value>) -> Result<Option<bool>, ()> {
+    match value {
+        None => Ok(None),
+        Some(value) => value.as_boolean().map(Some).ok_or(()),
+    }
+}
 
-                let output_sample_time = cycle_info.mOutputTime.mSampleTime + (frames as f64);
-                slot.last_write_time
-                    .store(output_sample_time.to_bits(), Ordering::Release);
-                (*driver).is_buffer_clear.store(false, Ordering::Release);
+// Optional "mono" of a 'rout' plist entry: None when absent, Err when not a fold name.
+fn parse_mono_field(value: Option<&Value>) -> Result<Option<MonoFold>, ()> {
+    match value {
+        None => Ok(None),
+        Some(value) => value
+            .as_string()
+            .and_then(MonoFold::parse)
+            .map(Some)
+            .ok_or(()),
+    }
+}
 
-                if frames > 0 {
-                    let sample_l = *input;
-                    let sample_r = *input.add(1);
-                    log_msg(&format!(
-                        "[ProcessOutput] client_id={} pid={} ch_offset={} output_time={:.0} data[0]={:.4} data[1]={:.4}",
-                        _client_id,
-                        slot.pid.load(Ordering::Relaxed),
-                        channel_offset,
-                        cycle_info.mOutputTime.mSampleTime,
-                        sample_l,
-                        sample_r
-                    ));
+// 'rout' batch: a plist array of { pid, channel_offset, gain?, pan?, width?, mono?, swap?,
+// mirror_offset?, owner? } applied in one write; a mirror_offset of 0 stops mirroring.
+// Every entry is checked before any is applied, so a bad entry (or one stealing a pair
+// reserved by someone other than its owner) leaves routing as it was, and listeners get
+// one notification for the whole batch. A batch none of whose pids has a client fails.
+unsafe fn apply_rout_batch(driver: *mut PrismDriver, items: &[Value]) -> OSStatus {
+    let max_channels = u64::from((*driver).config.num_channels);
+    let mut updates = Vec::with_capacity(items.len());
+    for item in items {
+        let entry = item.as_dictionary();
+        let field = |key: &str| entry.and_then(|entry| entry.get(key));
+        let real = |key: &str| {
+            field(key)
+                .and_then(|v| v.as_real())
+                .map_or(f32::NAN, |v| v as f32)
+        };
+        let pid = field("pid")
+            .and_then(|v| v.as_signed_integer())
+            .and_then(|pid| i32::try_from(pid).ok())
+            .filter(|&pid| pid > 0);
+        let offset = field("channel_offset")
+            .and_then(|v| v.as_unsigned_integer())
+            .filter(|&offset| {
+                offset == u64::from(NULL_BUS_OFFSET)
+                    || (offset % 2 == 0 && offset + 1 < max_channels)
+            });
+        let (gain, pan, width) = (real("gain"), real("pan"), real("width"));
+        let mono = parse_mono_field(field("mono"));
+        let swap = parse_swap_field(field("swap"));
+        let mirror = match field("mirror_offset") {
+            None => Ok(None),
+            Some(value) => value
+                .as_unsigned_integer()
+                .filter(|&mirror| {
+                    mirror == 0 || (mirror % 2 == 0 && mirror >= 2 && mirror + 1 < max_channels)
+                })
+                .map(|mirror| Some(mirror as usize))
+                .ok_or(()),
+        };
+        match (pid, offset, mono, swap, mirror) {
+            (Some(pid), Some(offset), Ok(mono), Ok(swap), Ok(mirror))
+                if (gain.is_nan() || (gain.is_finite() && gain >= 0.0))
+                    && (pan.is_nan() || (-1.0..=1.0).contains(&pan))
+                    && (width.is_nan() || (0.0..=PRISM_MAX_WIDTH).contains(&width))
+                    && mirror != Some(offset as usize) =>
+            {
+                let owner = field("owner").and_then(|v| v.as_string());
+                let moving = |slot: &ClientSlot| slot.pid.load(Ordering::Acquire) == pid;
+                if let Err(status) = check_reservation(driver, offset as usize, owner, moving) {
+                    return status;
                 }
+                if let Some(mirror) = mirror.filter(|&mirror| mirror != 0) {
+                    if let Err(status) = check_reservation(driver, mirror, owner, moving) {
+                        return status;
+                    }
+                }
+                updates.push((pid, offset as usize, gain, pan, width, mono, swap, mirror));
+            }
+            _ => {
+                log_error(
+                    Category::Routing,
+                    &format!(
+                        "Prism: ROUT batch rejected: invalid entry {:?} (max_channels={})",
+                        item, max_channels
+                    ),
+                );
+                return kAudioHardwareIllegalOperationError as OSStatus;
             }
         }
-    } else if _operation_id == kAudioServerPlugInIOOperationWriteMix {
-        if _stream_id != OUTPUT_STREAM_ID {
-            // Unexpected combination; ignore safely.
-            return 0;
+    }
+
+    let mut routed = 0;
+    for slot in (*driver).slot_table().slots.iter() {
+        if slot.client_id.load(Ordering::Acquire) == 0 {
+            continue;
         }
-        if !_io_main_buffer.is_null() {
-            let input = _io_main_buffer as *const f32;
-            let sample_time = cycle_info.mOutputTime.mSampleTime as usize;
-            let w_pos = sample_time % buffer_frames;
-            let frames_until_wrap = buffer_frames - w_pos;
-            let input_channels = 2; // Treat mix as stereo system bus
-
-            if frames <= frames_until_wrap {
-                // No wrapping needed
-                for i in 0..frames {
-                    let in_l = *input.add(i * input_channels);
-                    let in_r = *input.add(i * input_channels + 1);
-
-                    let dst_idx = (w_pos + i) * channels;
-                    if dst_idx + 1 < buffer_len {
-                        loopback_buffer[dst_idx] = in_l;
-                        loopback_buffer[dst_idx + 1] = in_r;
-                    }
-                }
-            } else {
-                // Wrapping needed
-                for i in 0..frames_until_wrap {
-                    let in_l = *input.add(i * input_channels);
-                    let in_r = *input.add(i * input_channels + 1);
-                    let dst_idx = (w_pos + i) * channels;
-                    if dst_idx + 1 < buffer_len {
-                        loopback_buffer[dst_idx] = in_l;
-                        loopback_buffer[dst_idx + 1] = in_r;
-                    }
-                }
+        let pid = slot.pid.load(Ordering::Acquire);
+        // The last entry for a pid wins, as if the writes had been sent one by one
+        let Some(&(_, offset, gain, pan, width, mono, swap, mirror)) =
+            updates.iter().rev().find(|update| update.0 == pid)
+        else {
+            continue;
+        };
+        slot.apply_mix(gain, pan, width, mono, swap);
+        if let Some(mirror) = mirror {
+            slot.mirror_offset.store(mirror, Ordering::Relaxed);
+        }
+        let prev = slot.reroute(offset);
+        learn_route(&*driver, slot, offset);
+        record_route_change(&*driver, slot, prev, offset, "route");
+        routed += 1;
+    }
+    if routed == 0 {
+        log_error(
+            Category::Routing,
+            &format!(
+                "Prism: ROUT batch Failed. No client of the {} entries",
+                updates.len()
+            ),
+        );
+        return kAudioHardwareIllegalOperationError as OSStatus;
+    }
+    log_msg(
+        Category::Routing,
+        &format!(
+            "Prism: Routing Update via ROUT batch. Entries={}, Clients={}",
+            updates.len(),
+            routed
+        ),
+    );
+    notify_device_event(driver, kAudioPrismPropertyClientList, PRISM_EVENT_ROUTING);
+    notify_channel_map_changed(driver, false);
+    0
+}
 
-                let remainder = frames - frames_until_wrap;
-                for i in 0..remainder {
-                    let src_idx = frames_until_wrap + i;
-                    let in_l = *input.add(src_idx * input_channels);
-                    let in_r = *input.add(src_idx * input_channels + 1);
-                    let dst_idx = i * channels;
-                    if dst_idx + 1 < buffer_len {
-                        loopback_buffer[dst_idx] = in_l;
-                        loopback_buffer[dst_idx + 1] = in_r;
-                    }
-                }
-            }
+// 'rout' broadcast with exclusions: { pid: -1, channel_offset, gain?, pan?, width?, mono?,
+// swap?, exclude_pids?, exclude_bundle_ids?, owner? }. Every client moves except the excluded
+// ones (a bundle ID covers its helpers), which keep their offset and mix.
+unsafe fn apply_rout_broadcast(driver: *mut PrismDriver, dict: &Dictionary) -> OSStatus {
+    let pid = dict.get("pid").and_then(|v| v.as_signed_integer());
+    let offset = dict
+        .get("channel_offset")
+        .and_then(|v| v.as_unsigned_integer())
+        .unwrap_or(u64::MAX);
+    let real = |key: &str| {
+        dict.get(key)
+            .and_then(|v| v.as_real())
+            .map_or(f32::NAN, |v| v as f32)
+    };
+    let (gain, pan, width) = (real("gain"), real("pan"), real("width"));
+    let mono = parse_mono_field(dict.get("mono"));
+    let swap = parse_swap_field(dict.get("swap"));
+    let list = |key: &str| {
+        dict.get(key)
+            .and_then(|v| v.as_array())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    };
+    let exclude_pids: Vec<i64> = list("exclude_pids")
+        .iter()
+        .filter_map(|v| v.as_signed_integer())
+        .collect();
+    let exclude_bundle_ids: Vec<&str> = list("exclude_bundle_ids")
+        .iter()
+        .filter_map(|v| v.as_string())
+        .collect();
+
+    let max_channels = u64::from((*driver).config.num_channels);
+    if pid != Some(-1)
+        || (offset != u64::from(NULL_BUS_OFFSET) && (offset % 2 != 0 || offset + 1 >= max_channels))
+        || (!gain.is_nan() && (gain.is_infinite() || gain < 0.0))
+        || (!pan.is_nan() && !(-1.0..=1.0).contains(&pan))
+        || (!width.is_nan() && !(0.0..=PRISM_MAX_WIDTH).contains(&width))
+    {
+        log_error(
+            Category::Routing,
+            &format!(
+                "Prism: ROUT broadcast rejected: pid={:?} channel_offset={} gain={} pan={} \
+                 width={} (max_channels={})",
+                pid, offset, gain, pan, width, max_channels
+            ),
+        );
+        return kAudioHardwareIllegalOperationError as OSStatus;
+    }
+    let (Ok(mono), Ok(swap)) = (mono, swap) else {
+        log_error(
+            Category::Routing,
+            &format!(
+                "Prism: ROUT broadcast rejected: mono={:?} swap={:?}",
+                dict.get("mono"),
+                dict.get("swap")
+            ),
+        );
+        return kAudioHardwareIllegalOperationError as OSStatus;
+    };
 
-            let output_sample_time = cycle_info.mOutputTime.mSampleTime + (frames as f64);
-            (*driver)
-                .last_output_sample_time
-                .store(output_sample_time.to_bits(), Ordering::Release);
-            (*driver).is_buffer_clear.store(false, Ordering::Release);
+    let excluded_hashes: Vec<u64> = match (*driver).bundle_ids.lock() {
+        Ok(bundle_ids) => bundle_ids
+            .iter()
+            .filter(|(_, candidate)| {
+                exclude_bundle_ids
+                    .iter()
+                    .any(|bundle_id| bundle_matches(candidate, bundle_id))
+            })
+            .map(|(hash, _)| *hash)
+            .collect(),
+        Err(_) => Vec::new(),
+    };
 
-            if frames > 0 {
-                let sample_l = *input;
-                let sample_r = *input.add(1);
-                log_msg(&format!(
-                    "[WriteMix] system_mix w_pos={} output_time={:.0} data[0]={:.4} data[1]={:.4}",
-                    w_pos, cycle_info.mOutputTime.mSampleTime, sample_l, sample_r
-                ));
-            }
-        }
-    } else if _operation_id == kAudioServerPlugInIOOperationReadInput {
-        if _stream_id != INPUT_STREAM_ID {
-            return 0;
+    let excluded = |slot: &ClientSlot| {
+        exclude_pids.contains(&i64::from(slot.pid.load(Ordering::Acquire)))
+            || excluded_hashes.contains(&slot.bundle_hash.load(Ordering::Relaxed))
+    };
+    let owner = dict.get("owner").and_then(|v| v.as_string());
+    if let Err(status) = check_reservation(driver, offset as usize, owner, |slot| !excluded(slot)) {
+        return status;
+    }
+
+    let (mut routed, mut kept) = (0, 0);
+    for slot in (*driver).slot_table().slots.iter() {
+        if slot.client_id.load(Ordering::Acquire) == 0 {
+            continue;
         }
-        if !_io_main_buffer.is_null() {
-            let output = _io_main_buffer as *mut f32;
-            let input_sample_time = cycle_info.mInputTime.mSampleTime;
-            let sample_time = input_sample_time as usize;
-            let r_pos = sample_time % buffer_frames;
-            let frames_until_wrap = buffer_frames - r_pos;
+        if excluded(slot) {
+            kept += 1;
+            continue;
+        }
+        slot.apply_mix(gain, pan, width, mono, swap);
+        let prev = slot.reroute(offset as usize);
+        record_route_change(&*driver, slot, prev, offset as usize, "broadcast");
+        routed += 1;
+    }
+    log_msg(
+        Category::Routing,
+        &format!(
+            "Prism: Routing Update ROUT Broadcast. Offset={}, Clients={}, Excluded={}",
+            offset, routed, kept
+        ),
+    );
+    if routed > 0 {
+        notify_device_event(driver, kAudioPrismPropertyClientList, PRISM_EVENT_ROUTING);
+        notify_channel_map_changed(driver, false);
+    }
+    0
+}
 
-            // Use actual frame size; update if mismatch detected
-            let current_actual = (*driver).buffer_frame_size_actual.load(Ordering::Relaxed) as usize;
-            if frames != current_actual && frames > 0 {
-                log_msg(&format!(
-                    "[ReadInput] adapting buffer_frame_size {} -> {}",
-                    current_actual, frames
-                ));
-                (*driver).buffer_frame_size_actual.store(frames as u32, Ordering::Relaxed);
-            }
+// Refuse a routing write by `owner` (None when the write cannot name one) that would move
+// the clients `moving` picks onto `offset` while someone else reserves it. Clients
+// already on the pair may still have their mix changed.
+unsafe fn check_reservation(
+    driver: *mut PrismDriver,
+    offset: usize,
+    owner: Option<&str>,
+    moving: impl Fn(&ClientSlot) -> bool,
+) -> Result<(), OSStatus> {
+    let holder = match (*driver).reservations.lock() {
+        Ok(reservations) => reservations.get(&offset).cloned(),
+        Err(_) => None,
+    };
+    let Some(holder) = holder.filter(|holder| Some(holder.as_str()) != owner) else {
+        return Ok(());
+    };
+    let steals = (*driver).slot_table().slots.iter().any(|slot| {
+        slot.client_id.load(Ordering::Acquire) != 0
+            && slot.channel_offset.load(Ordering::Relaxed) != offset
+            && moving(slot)
+    });
+    if !steals {
+        return Ok(());
+    }
+    log_error(
+        Category::Routing,
+        &format!(
+            "Prism: routing to channel_offset={} refused: reserved by {:?}",
+            offset, holder
+        ),
+    );
+    Err(PRISM_BUS_RESERVED_ERROR)
+}
 
-            let last_output_bits = (*driver).last_output_sample_time.load(Ordering::Acquire);
-            let last_output_time = f64::from_bits(last_output_bits);
-            log_msg(&format!(
-                "[ReadInput] sample_time={:.0} frames={} last_output_time={:.0} delta={:.0}",
-                input_sample_time,
-                frames,
-                last_output_time,
-                input_sample_time - last_output_time
-            ));
+// Return the clients of `pid` (every client for -1) to the unassigned state and clear the
+// pairs they leave.
+unsafe fn unroute_clients(driver: *mut PrismDriver, pid: pid_t) -> OSStatus {
+    let mut cleared = 0;
+    for slot in (*driver).slot_table().slots.iter() {
+        if slot.client_id.load(Ordering::Acquire) == 0
+            || (pid != -1 && slot.pid.load(Ordering::Acquire) != pid)
+        {
+            continue;
+        }
+        let prev = slot.unroute();
+        learn_route(&*driver, slot, 0);
+        record_route_change(&*driver, slot, prev, 0, "unroute");
+        cleared += 1;
+    }
+    if cleared == 0 {
+        log_error(
+            Category::Routing,
+            &format!("Prism: Unroute via ROUT Failed. PID={} not found", pid),
+        );
+        return kAudioHardwareBadObjectError as OSStatus;
+    }
+    log_msg(
+        Category::Routing,
+        &format!("Prism: Unroute via ROUT. PID={}, Clients={}", pid, cleared),
+    );
+    notify_device_event(driver, kAudioPrismPropertyClientList, PRISM_EVENT_ROUTING);
+    notify_channel_map_changed(driver, false);
+    0
+}
 
-            // Log every ReadInput call (unconditionally)
-            let slots = &(*driver).client_slots;
-            let slot_idx = (_client_id as usize) & (MAX_CLIENTS - 1);
-            let slot = &slots[slot_idx];
-            let pid = slot.pid.load(Ordering::Relaxed);
+// Drop everything buffered for pair `pair`: the slots routed there are silenced first so
+// ReadInput stops mixing them, then the pair ring is zeroed. Off the RT path only
+// ('clr '); a cycle in flight may still read a partly cleared ring once.
+unsafe fn clear_pair(driver: *mut PrismDriver, pair: usize) {
+    for slot in (*driver).slot_table().slots.iter() {
+        // A mirror_offset of 0 means no mirror, not a mirror on pair 0
+        let mirror = slot.mirror_offset.load(Ordering::Relaxed);
+        if slot.client_id.load(Ordering::Acquire) != 0
+            && (slot.channel_offset.load(Ordering::Relaxed) / 2 == pair
+                || (mirror != 0 && mirror / 2 == pair))
+        {
+            slot.silence();
+        }
+    }
+    let Some(entry) = (*driver).pair_buffers.get(pair) else {
+        return;
+    };
+    let ring = entry.load(Ordering::Acquire);
+    if !ring.is_null() {
+        ptr::write_bytes(ring, 0, (*driver).pair_buffer_frames * 2);
+    }
+}
 
-            // Initialize output buffer to zero using vectorized clear
-            unsafe {
-                accelerate::clear(output, frames * channels);
-            }
+// One zero timestamp per IO cycle: a fixed period would wake the HAL several times a
+// cycle at large buffers. Floored at MIN_ZERO_TIMESTAMP_PERIOD.
+fn zero_timestamp_period_for(buffer_frames: u32) -> u32 {
+    buffer_frames.max(MIN_ZERO_TIMESTAMP_PERIOD)
+}
 
-            // Copy system mix (written by WriteMix) from loopback_buffer channels 0/1 into output
-            if frames <= frames_until_wrap {
-                let src_ptr = loopback_buffer.as_ptr().add(r_pos * channels);
-                for i in 0..frames {
-                    let src_idx = i * channels;
-                    let dst_idx = i * channels;
-                    unsafe {
-                        *output.add(dst_idx) = *src_ptr.add(src_idx);
-                        *output.add(dst_idx + 1) = *src_ptr.add(src_idx + 1);
-                    }
-                }
-            } else {
-                let src_ptr1 = loopback_buffer.as_ptr().add(r_pos * channels);
-                for i in 0..frames_until_wrap {
-                    let src_idx = i * channels;
-                    let dst_idx = i * channels;
-                    unsafe {
-                        *output.add(dst_idx) = *src_ptr1.add(src_idx);
-                        *output.add(dst_idx + 1) = *src_ptr1.add(src_idx + 1);
-                    }
-                }
-                let remainder = frames - frames_until_wrap;
-                let src_ptr2 = loopback_buffer.as_ptr();
-                for i in 0..remainder {
-                    let src_idx = i * channels;
-                    let dst_idx = (frames_until_wrap + i) * channels;
-                    unsafe {
-                        *output.add(dst_idx) = *src_ptr2.add(src_idx);
-                        *output.add(dst_idx + 1) = *src_ptr2.add(src_idx + 1);
-                    }
-                }
-            }
+// Follow a new buffer frame size. Timestamps stay on the same anchor, so only the
+// spacing changes and the seed does not need to move.
+unsafe fn update_zero_timestamp_period(driver: *mut PrismDriver, buffer_frames: u32) {
+    let period = zero_timestamp_period_for(buffer_frames);
+    let previous = (*driver)
+        .zero_timestamp_period
+        .swap(period, Ordering::Relaxed);
+    if previous != period {
+        log_msg(
+            Category::Properties,
+            &format!("Prism: ZeroTimeStampPeriod {} -> {}", previous, period),
+        );
+        notify_device_property_changed(driver, kAudioDevicePropertyZeroTimeStampPeriod);
+    }
+}
 
-            // Mix per-slot buffers into output for active clients
-            let slots_ref = &(*driver).client_slots;
-            let _input_end = input_sample_time + (frames as f64);
-            for slot in slots_ref.iter() {
-                let client_id = slot.client_id.load(Ordering::Acquire);
-                if client_id == 0 {
-                    continue;
-                }
+// Step the rate scalar toward its target and return the line GetZeroTimeStamp should
+// use: (base host time, base sample time, scalar). Before a step the base moves up to
+// the last zero timestamp at the old rate, so the new rate only stretches periods that
+// have not been reported yet and the timeline stays continuous. The exponential ease
+// keeps the HAL's rate estimate from seeing a jump. Only GetZeroTimeStamp moves the
+// base between re-anchors, and the HAL calls it from the device's IO thread.
+unsafe fn advance_rate_scalar(
+    driver: *mut PrismDriver,
+    host_time: u64,
+    period_frames: f64,
+) -> (u64, f64, f64) {
+    let base_host = (*driver).rate_base_host.load(Ordering::Acquire);
+    let base_sample = f64::from_bits((*driver).rate_base_sample.load(Ordering::Acquire));
+    let scalar = f64::from_bits((*driver).rate_scalar.load(Ordering::Relaxed));
+    let target = f64::from_bits((*driver).rate_scalar_target.load(Ordering::Relaxed));
+    if scalar == target {
+        return (base_host, base_sample, scalar);
+    }
 
-                let channel_offset = slot.channel_offset.load(Ordering::Relaxed);
-                if channel_offset < 2 || channel_offset + 1 >= channels {
-                    continue;
-                }
+    let ticks_per_period = (*driver).host_ticks_per_frame * scalar * period_frames;
+    let periods = (host_time.saturating_sub(base_host) as f64 / ticks_per_period).floor();
+    let base_host = base_host + (periods * ticks_per_period) as u64;
+    let base_sample = base_sample + periods * period_frames;
 
-                let last_write_bits = slot.last_write_time.load(Ordering::Acquire);
-                let last_write_time = f64::from_bits(last_write_bits);
+    let mut next = scalar + (target - scalar) * RATE_SMOOTHING;
+    if (target - next).abs() < 1e-9 {
+        next = target;
+    }
+    (*driver).rate_base_host.store(base_host, Ordering::Release);
+    (*driver)
+        .rate_base_sample
+        .store(base_sample.to_bits(), Ordering::Release);
+    (*driver)
+        .rate_scalar
+        .store(next.to_bits(), Ordering::Relaxed);
+    (base_host, base_sample, next)
+}
 
-                // Mix if slot has been written to (ring buffer always has valid data after first write)
-                if last_write_time > 0.0 {
-                    let slot_buf_ptr = slot.slot_buffer.as_ptr();
-                    let slot_buf_frames = slot.slot_buffer.len() / 2; // stereo frames
-                    let slot_r_pos = (input_sample_time as usize) % slot_buf_frames;
-                    let slot_frames_until_wrap = slot_buf_frames - slot_r_pos;
-
-                    if frames <= slot_frames_until_wrap {
-                        // No wrapping: single contiguous read
-                        unsafe {
-                            accelerate::add_inplace(
-                                slot_buf_ptr.add(slot_r_pos * 2),
-                                2,
-                                output.add(channel_offset),
-                                channels as isize,
-                                frames,
-                            );
-                            accelerate::add_inplace(
-                                slot_buf_ptr.add(slot_r_pos * 2 + 1),
-                                2,
-                                output.add(channel_offset + 1),
-                                channels as isize,
-                                frames,
-                            );
-                        }
-                    } else {
-                        // Wrapping: read in two parts
-                        unsafe {
-                            // First part: from slot_r_pos to end
-                            accelerate::add_inplace(
-                                slot_buf_ptr.add(slot_r_pos * 2),
-                                2,
-                                output.add(channel_offset),
-                                channels as isize,
-                                slot_frames_until_wrap,
-                            );
-                            accelerate::add_inplace(
-                                slot_buf_ptr.add(slot_r_pos * 2 + 1),
-                                2,
-                                output.add(channel_offset + 1),
-                                channels as isize,
-                                slot_frames_until_wrap,
-                            );
-                            // Second part: from start
-                            let remainder = frames - slot_frames_until_wrap;
-                            let out_offset = slot_frames_until_wrap * channels;
-                            accelerate::add_inplace(
-                                slot_buf_ptr,
-                                2,
-                                output.add(channel_offset + out_offset),
-                                channels as isize,
-                                remainder,
-                            );
-                            accelerate::add_inplace(
-                                slot_buf_ptr.add(1),
-                                2,
-                                output.add(channel_offset + 1 + out_offset),
-                                channels as isize,
-                                remainder,
-                            );
-                        }
-                    }
-                }
-            }
+// Move the zero timestamp anchor and bump the seed, so GetZeroTimeStamp tells the HAL
+// the timeline is discontinuous. The seed moves after the anchor: a reader that pairs
+// the new anchor with the old seed reports the change on its next call instead of
+// hiding it.
+unsafe fn reanchor(driver: *mut PrismDriver, host_time: u64) {
+    (*driver)
+        .rate_base_sample
+        .store(0.0f64.to_bits(), Ordering::Release);
+    (*driver).rate_base_host.store(host_time, Ordering::Release);
+    (*driver)
+        .anchor_host_time
+        .store(host_time, Ordering::SeqCst);
+    (*driver).timestamp_seed.fetch_add(1, Ordering::AcqRel);
+}
 
-            // Debug: Log buffer info after timing check
-            static mut READ_COUNT: u32 = 0;
-            READ_COUNT += 1;
-            if READ_COUNT.is_multiple_of(100) {
-                // Sample first few channels from the output buffer (after timing check)
-                let sample_ch0 = *output;
-                let sample_ch1 = *output.add(1);
-                let sample_ch2 = *output.add(2);
-                let sample_ch3 = *output.add(3);
-                log_msg(&format!("[ReadInput] client_id={} pid={} r_pos={} input_time={:.0} frames={} ch[0]={:.4} ch[1]={:.4} ch[2]={:.4} ch[3]={:.4}",
-                    _client_id, pid, r_pos, input_sample_time, frames, sample_ch0, sample_ch1, sample_ch2, sample_ch3));
-            }
+// Re-anchor the timeline at the current host time and drop everything buffered, the
+// same state StartIO sets up for the first client. The new timestamp seed tells the
+// HAL the timeline jumped. Runs off the RT path from SetPropertyData('rsyn').
+unsafe fn resync(driver: *mut PrismDriver) {
+    if (*driver).anchor_host_time.load(Ordering::SeqCst) != 0 {
+        reanchor(driver, libc::mach_absolute_time());
+    } else {
+        (*driver).timestamp_seed.fetch_add(1, Ordering::AcqRel);
+    }
+    (*driver).num_time_stamps.store(0, Ordering::SeqCst);
+    (*driver).write_pos.store(0, Ordering::SeqCst);
+    (*driver).read_pos.store(0, Ordering::SeqCst);
+
+    for entry in (*driver).pair_buffers.iter() {
+        let ring = entry.load(Ordering::Acquire);
+        if !ring.is_null() {
+            ptr::write_bytes(ring, 0, (*driver).pair_buffer_frames * 2);
         }
     }
-    0
-}
-unsafe extern "C" fn end_io_operation(
-    _self: AudioServerPlugInDriverRef,
-    _device_id: AudioObjectID,
-    _client_id: UInt32,
-    _operation_id: UInt32,
-    _io_buffer_frame_size: UInt32,
-    _io_cycle_info: *const AudioServerPlugInIOCycleInfo,
-) -> OSStatus {
-    0
+    for slot in (*driver).slot_table().slots.iter() {
+        slot.silence();
+    }
+    (*driver)
+        .last_output_sample_time
+        .store(0, Ordering::Release);
+    (*driver).is_buffer_clear.store(true, Ordering::Release);
+
+    let count = (*driver).resyncs.fetch_add(1, Ordering::Relaxed) + 1;
+    log_msg(
+        Category::Routing,
+        &format!("Prism: Resync #{} requested; timeline re-anchored", count),
+    );
+    notify_device_event(driver, kAudioPrismPropertyResync, PRISM_EVENT_DIAG);
 }
 
-// Helper for logging
-fn log_msg(_msg: &str) {
-    #[cfg(debug_assertions)]
+// Allocate the ring for the pair containing `channel_offset` if it does not exist yet.
+// Must only be called off the RT path (property calls, driver creation).
+unsafe fn ensure_pair_buffer(driver: *mut PrismDriver, channel_offset: usize) {
+    if driver.is_null() {
+        return;
+    }
+    let Some(entry) = (*driver).pair_buffers.get(channel_offset / 2) else {
+        return;
+    };
+    if !entry.load(Ordering::Acquire).is_null() {
+        return;
+    }
+
+    let samples = vec![0.0f32; (*driver).pair_buffer_frames * 2].into_boxed_slice();
+    let ring = Box::into_raw(samples) as *mut f32;
+    if entry
+        .compare_exchange(ptr::null_mut(), ring, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
     {
-        use std::ffi::CString;
-        unsafe {
-            // syslog(LOG_USER, ...)
-            let c_msg =
-                CString::new(_msg).unwrap_or_else(|_| CString::new("prism: log error").unwrap());
-            libc::syslog(libc::LOG_USER | libc::LOG_INFO, c_msg.as_ptr());
+        // Lost a race with another property call; release our copy.
+        let len = (*driver).pair_buffer_frames * 2;
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ring, len)));
+    } else {
+        log_msg(
+            Category::Routing,
+            &format!(
+                "Prism: Allocated ring for channels {}-{}",
+                (channel_offset / 2) * 2 + 1,
+                (channel_offset / 2) * 2 + 2
+            ),
+        );
+    }
+}
+
+// Switch bus mode. The output stream changes width, so the switch waits for the HAL to
+// stop IO (apply_full_bus_output). Every pair ring is allocated first, so WriteMix never
+// meets a missing ring. Off the RT path ('cnfg') only.
+unsafe fn set_full_bus_output(driver: *mut PrismDriver, enabled: bool) {
+    if enabled {
+        for pair in 0..(*driver).pair_buffers.len() {
+            ensure_pair_buffer(driver, pair * 2);
         }
     }
+    if (*driver).full_bus_output.load(Ordering::Acquire) == enabled {
+        (*driver)
+            .pending_full_bus_output
+            .store(0, Ordering::Release);
+        return;
+    }
+    (*driver)
+        .pending_full_bus_output
+        .store(if enabled { 2 } else { 1 }, Ordering::Release);
+    let status = request_config_change(driver, CONFIG_CHANGE_FULL_BUS_OUTPUT);
+    if status != 0 {
+        (*driver)
+            .pending_full_bus_output
+            .store(0, Ordering::Release);
+        log_error(
+            Category::Properties,
+            &format!("Prism: bus mode change request failed: {}", status),
+        );
+    }
 }
 
-fn notify_device_property_changed(driver: *mut PrismDriver, selector: AudioObjectPropertySelector) {
-    unsafe {
-        if driver.is_null() {
-            return;
+// Apply the pending bus mode switch with IO stopped. Leaving the mode zeroes the pairs
+// it filled other than the system mix pair, so none replays old audio if bus mode
+// comes back.
+unsafe fn apply_full_bus_output(driver: *mut PrismDriver) {
+    let enabled = match (*driver).pending_full_bus_output.swap(0, Ordering::AcqRel) {
+        0 => return,
+        pending => pending == 2,
+    };
+    if (*driver).full_bus_output.swap(enabled, Ordering::AcqRel) == enabled {
+        return;
+    }
+    let pair_count = (*driver).pair_buffers.len();
+    let channels = if enabled {
+        (*driver).config.num_channels
+    } else {
+        2
+    };
+    (*driver).output_channels.store(channels, Ordering::Release);
+    if !enabled {
+        let system_pair = (*driver).system_mix_offset.load(Ordering::Acquire) / 2;
+        for pair in (0..pair_count).filter(|&pair| pair != system_pair) {
+            let ring = (*driver).pair_buffers[pair].load(Ordering::Acquire);
+            if !ring.is_null() {
+                ptr::write_bytes(ring, 0, (*driver).pair_buffer_frames * 2);
+            }
         }
-        if let Some(host) = (*driver).host {
-            if let Some(prop_changed) = (*host).PropertiesChanged {
-                let address = AudioObjectPropertyAddress {
-                    mSelector: selector,
-                    mScope: kAudioObjectPropertyScopeGlobal,
-                    mElement: kAudioObjectPropertyElementMaster,
-                };
-                prop_changed(host, DEVICE_ID, 1, &address);
+    }
+    log_msg(
+        Category::Routing,
+        &format!(
+            "Prism: Output stream now {} channel(s), {}",
+            channels,
+            if enabled {
+                "mapped 1:1 onto the bus"
+            } else {
+                "routed per client"
             }
+        ),
+    );
+    notify_channel_map_changed(driver, true);
+}
+
+// Apply the stream formats hosts picked, with IO stopped. Only the output stream changes
+// width; a new width also renames its channels.
+unsafe fn apply_stream_formats(driver: *mut PrismDriver) {
+    for stream_id in [INPUT_STREAM_ID, OUTPUT_STREAM_ID] {
+        let (Some(flag), Some(pending)) = (
+            (*driver).non_interleaved_flag(stream_id),
+            (*driver).pending_format(stream_id),
+        ) else {
+            continue;
+        };
+        let (channels, non_interleaved) = match pending.swap(0, Ordering::AcqRel) {
+            0 => continue,
+            pending => (pending >> 1, pending & 1 != 0),
+        };
+        let layout_changed = flag.swap(non_interleaved, Ordering::AcqRel) != non_interleaved;
+        // Bus mode may have been switched in the meantime; its width is fixed
+        let width_changed = stream_id == OUTPUT_STREAM_ID
+            && !(*driver).full_bus_output.load(Ordering::Acquire)
+            && (*driver).output_channels.swap(channels, Ordering::AcqRel) != channels;
+        if !layout_changed && !width_changed {
+            continue;
+        }
+        log_msg(
+            Category::Properties,
+            &format!(
+                "Prism: Stream {} now {} channel(s), {}",
+                stream_id,
+                (*driver).stream_channels(stream_id),
+                if non_interleaved {
+                    "non-interleaved"
+                } else {
+                    "interleaved"
+                }
+            ),
+        );
+        notify_properties_changed(
+            driver,
+            stream_id,
+            &[
+                kAudioStreamPropertyVirtualFormat,
+                kAudioStreamPropertyPhysicalFormat,
+            ],
+        );
+        if width_changed {
+            notify_channel_map_changed(driver, false);
         }
     }
 }
 
-// Zero an entire stereo pair across the loopback buffer for the given channel offset.
-// This is used when a client is removed or re-routed so stale audio does not remain in the ring.
-unsafe fn zero_channel_pair(driver: *mut PrismDriver, channel_offset: usize) {
-    if driver.is_null() {
+// Move the system mix to the pair at `offset`. The ring is allocated before WriteMix
+// switches to it, and the pair it leaves is zeroed, so it holds no stale mix should the
+// system mix come back. Off the RT path ('cnfg') only.
+unsafe fn set_system_mix_offset(driver: *mut PrismDriver, offset: usize) {
+    ensure_pair_buffer(driver, offset);
+    let previous = (*driver).system_mix_offset.swap(offset, Ordering::AcqRel);
+    if previous == offset {
         return;
     }
-    let channels = (*driver).config.num_channels as usize;
-    if channel_offset < 2 || channel_offset + 1 >= channels {
-        return;
+    if !(*driver).full_bus_output.load(Ordering::Relaxed) {
+        let ring = (*driver).pair_buffers[previous / 2].load(Ordering::Acquire);
+        if !ring.is_null() {
+            ptr::write_bytes(ring, 0, (*driver).pair_buffer_frames * 2);
+        }
     }
+    log_msg(
+        Category::Routing,
+        &format!(
+            "Prism: System mix moved from {}-{} to {}-{}",
+            previous + 1,
+            previous + 2,
+            offset + 1,
+            offset + 2
+        ),
+    );
+    notify_channel_map_changed(driver, false);
+}
 
-    let buf = &mut (*driver).loopback_buffer;
-    let buffer_len = buf.len();
-    if buffer_len == 0 {
+// Turn system mix capture on or off. Turning it off zeroes the system mix pair, since
+// ReadInput keeps mixing its ring. Off the RT path ('cnfg') only.
+unsafe fn set_system_mix_enabled(driver: *mut PrismDriver, enabled: bool) {
+    if (*driver).system_mix_enabled.swap(enabled, Ordering::AcqRel) == enabled {
         return;
     }
-    let frames = buffer_len / channels;
+    if !enabled && !(*driver).full_bus_output.load(Ordering::Relaxed) {
+        let pair = (*driver).system_mix_offset.load(Ordering::Relaxed) / 2;
+        let ring = (*driver).pair_buffers[pair].load(Ordering::Acquire);
+        if !ring.is_null() {
+            ptr::write_bytes(ring, 0, (*driver).pair_buffer_frames * 2);
+        }
+    }
+    log_msg(
+        Category::Routing,
+        &format!(
+            "Prism: System mix capture {}",
+            if enabled { "on" } else { "off" }
+        ),
+    );
+}
 
-    for f in 0..frames {
-        let idx = f * channels + channel_offset;
-        // bounds should hold, but be defensive
-        if idx + 1 < buffer_len {
-            buf[idx] = 0.0;
-            buf[idx + 1] = 0.0;
+// Copy `frames` interleaved stereo frames of the mix into pair `pair`'s ring at `w_pos`,
+// splitting the write when it wraps, and meter them. Rings never allocated are skipped.
+#[inline]
+unsafe fn write_mix_pair(
+    driver: *mut PrismDriver,
+    pair: usize,
+    input: *const f32,
+    frames: usize,
+    w_pos: usize,
+    output_sample_time: f64,
+) {
+    let Some(entry) = (*driver).pair_buffers.get(pair) else {
+        return;
+    };
+    let ring = entry.load(Ordering::Acquire);
+    if ring.is_null() {
+        return;
+    }
+    let first = frames.min((*driver).pair_buffer_frames - w_pos);
+    ptr::copy_nonoverlapping(input, ring.add(w_pos * 2), first * 2);
+    ptr::copy_nonoverlapping(input.add(first * 2), ring, (frames - first) * 2);
+
+    (*driver).io_stats.add_frames(pair * 2, frames);
+    if let Some(meter) = (*driver).bus_meters.get(pair) {
+        let (peak, rms) = meter.accumulate(input, frames, (1.0, 1.0));
+        if let Some(telemetry) = (*driver).telemetry {
+            telemetry.update_bus(pair, peak, rms, output_sample_time, frames);
         }
     }
 }
 
+// Add one side (`source`: 0 left, 1 right) of a stereo ring, scaled by `gain`, into
+// `channel` of the interleaved output, splitting the read where the ring wraps.
+#[allow(clippy::too_many_arguments)]
+#[inline]
+unsafe fn mix_ring_channel(
+    ring: *const f32,
+    ring_frames: usize,
+    r_pos: usize,
+    source: usize,
+    output: *mut f32,
+    channel: usize,
+    channels: usize,
+    frames: usize,
+    gain: f32,
+) {
+    let first = frames.min(ring_frames - r_pos);
+    accelerate::add_scaled(
+        ring.add(r_pos * 2 + source),
+        2,
+        output.add(channel),
+        channels as isize,
+        gain,
+        first,
+    );
+    accelerate::add_scaled(
+        ring.add(source),
+        2,
+        output.add(channel + first * channels),
+        channels as isize,
+        gain,
+        frames - first,
+    );
+}
+
+// Add `frames` stereo frames from a pair ring starting at `r_pos` into an interleaved
+// `channels`-wide output at `channel`, splitting the read when it wraps.
+#[inline]
+unsafe fn mix_stereo_ring(
+    ring: *const f32,
+    ring_frames: usize,
+    r_pos: usize,
+    output: *mut f32,
+    channel: usize,
+    channels: usize,
+    frames: usize,
+) {
+    let first = frames.min(ring_frames - r_pos);
+    accelerate::add_inplace(
+        ring.add(r_pos * 2),
+        2,
+        output.add(channel),
+        channels as isize,
+        first,
+    );
+    accelerate::add_inplace(
+        ring.add(r_pos * 2 + 1),
+        2,
+        output.add(channel + 1),
+        channels as isize,
+        first,
+    );
+
+    let remainder = frames - first;
+    if remainder > 0 {
+        let out_offset = first * channels;
+        accelerate::add_inplace(
+            ring,
+            2,
+            output.add(channel + out_offset),
+            channels as isize,
+            remainder,
+        );
+        accelerate::add_inplace(
+            ring.add(1),
+            2,
+            output.add(channel + 1 + out_offset),
+            channels as isize,
+            remainder,
+        );
+    }
+}
+
 // V-Table storage
 static mut DRIVER_VTABLE: AudioServerPlugInDriverInterface = AudioServerPlugInDriverInterface {
     _reserved: ptr::null_mut(),
@@ -2076,57 +8459,39 @@ static mut DRIVER_VTABLE: AudioServerPlugInDriverInterface = AudioServerPlugInDr
 };
 
 pub fn create_driver() -> *mut PrismDriver {
-    unsafe {
-        if DRIVER_INSTANCE.is_null() {
-            let host_ticks_per_second = get_host_ticks_per_second();
-            let sample_rate = 48000.0; // Must match what we report in GetPropertyData
-            let host_ticks_per_frame = host_ticks_per_second / sample_rate;
-
-            let config = PrismConfig::load();
-            let buffer_size = 65536 * config.num_channels as usize; // 65536 frames * channels
-
-            let mut client_slots = Vec::with_capacity(MAX_CLIENTS);
-            // Per-slot stereo ring buffer (configurable size)
-            let slot_buf_len = (config.slot_buffer_frame_size as usize) * 2;
-            for _ in 0..MAX_CLIENTS {
-                client_slots.push(ClientSlot {
-                    client_id: AtomicU32::new(0),
-                    channel_offset: AtomicUsize::new(0),
-                    pid: AtomicI32::new(0),
-                    last_write_time: AtomicU64::new(0),
-                    slot_active: AtomicBool::new(false),
-                    slot_buffer: vec![0.0; slot_buf_len],
-                });
-            }
-
-            let driver = Box::new(PrismDriver {
-                _vtable: &raw const DRIVER_VTABLE,
-                ref_count: AtomicU32::new(1),
-                host: None,
-                anchor_host_time: AtomicU64::new(0),
-                num_time_stamps: AtomicU64::new(0),
-                host_ticks_per_frame,
-                client_count: AtomicU32::new(0),
-                phase: 0.0,
-                loopback_buffer: vec![0.0; buffer_size],
-                config,
-                last_output_sample_time: AtomicU64::new(0),
-                is_buffer_clear: AtomicBool::new(true),
-                buffer_frame_size_actual: AtomicU32::new(config.buffer_frame_size),
-                _pad1: [0; 64],
-                write_pos: AtomicUsize::new(0),
-                _pad2: [0; 64],
-                read_pos: AtomicUsize::new(0),
-                client_slots,
-            });
-            DRIVER_INSTANCE = Box::into_raw(driver);
-        } else {
-            // Increment ref count if we were doing real ref counting,
-            // but for a singleton driver, we usually just return the instance.
-            (*DRIVER_INSTANCE).ref_count.fetch_add(1, Ordering::Relaxed);
+    create_driver_with(|| {
+        let mut driver = PrismDriver::new(PrismConfig::load());
+        driver.capture_exclusions = load_capture_exclusions();
+        // Only the instance that gets published maps telemetry: create() resets the
+        // shared region, and readers follow whichever driver wrote it last
+        driver.telemetry =
+            TelemetryRegion::create(driver.bus_meters.len() as u32, SAMPLE_RATE as u32);
+        if driver.telemetry.is_none() {
+            log_error(
+                Category::Lifecycle,
+                "Prism: Telemetry region unavailable; meters only via 'metr'",
+            );
         }
-        DRIVER_INSTANCE
+        driver
+    })
+}
+
+// create_driver() with the instance built by `build`, which only runs when there is
+// no instance to hand back yet.
+fn create_driver_with(build: impl FnOnce() -> Box<PrismDriver>) -> *mut PrismDriver {
+    let _lifecycle = DRIVER_LIFECYCLE.lock().unwrap_or_else(|e| e.into_inner());
+    let existing = DRIVER_INSTANCE.load(Ordering::Acquire);
+    if !existing.is_null() {
+        // coreaudiod may ask for the factory more than once; hand back the same instance
+        unsafe {
+            (*existing).ref_count.fetch_add(1, Ordering::Relaxed);
+        }
+        return existing;
     }
+
+    let driver = Box::into_raw(build());
+    DRIVER_INSTANCE.store(driver, Ordering::Release);
+    driver
 }
 
 #[repr(C)]
@@ -2137,3 +8502,345 @@ struct PrismClientInfo {
     mIsNativeEndian: Boolean,
     mBundleID: CFStringRef,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core_foundation::base::{
+        CFAllocatorContext, CFAllocatorCreate, CFAllocatorGetDefault, CFAllocatorRef,
+        CFAllocatorSetDefault, CFGetRetainCount, CFIndex, CFOptionFlags, CFRelease, CFTypeRef,
+    };
+    use std::collections::HashSet;
+    use std::sync::atomic::AtomicIsize;
+    use std::thread;
+
+    #[test]
+    fn standalone_driver_maps_no_telemetry() {
+        let driver = PrismDriver::new(PrismConfig::default());
+        assert!(driver.telemetry.is_none());
+    }
+
+    #[test]
+    fn side_gain_keeps_the_other_side() {
+        let slot = ClientSlot::new(2);
+        slot.set_side_gain(0, 0.5);
+        assert_eq!(slot.side_gains(), (0.5, 1.0));
+        slot.set_side_gain(1, 0.25);
+        let (left, right) = slot.side_gains();
+        assert!((left - 0.5).abs() < 1e-6 && (right - 0.25).abs() < 1e-6);
+        slot.set_side_gain(0, 0.0);
+        slot.set_side_gain(1, 0.0);
+        assert_eq!(slot.side_gains(), (0.0, 0.0));
+    }
+
+    // A writer that loaded the table before it grew, like an IO cycle in flight, keeps
+    // writing to it after the new table is published; the client must not lose them.
+    #[test]
+    fn slot_table_growth_keeps_writes_to_the_old_table() {
+        let driver = Box::into_raw(PrismDriver::new(PrismConfig::default())) as usize;
+        let client_id = 7;
+        unsafe {
+            let table = (*(driver as *mut PrismDriver)).slot_table();
+            let idx = table.place(client_id).unwrap();
+            table.slots[idx]
+                .client_id
+                .store(client_id, Ordering::Release);
+        }
+
+        let started = std::sync::Barrier::new(2);
+        let grown = AtomicBool::new(false);
+        let last = 100_000u32;
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let table = unsafe { (*(driver as *mut PrismDriver)).slot_table() };
+                let slot = table.find(client_id).unwrap();
+                let ring = slot.slot_buffer.as_ptr() as *mut f32;
+                started.wait();
+                for written in 1..=last {
+                    if written == last / 2 + 1 {
+                        // The second half lands after the table has been replaced
+                        while !grown.load(Ordering::Acquire) {
+                            thread::yield_now();
+                        }
+                    }
+                    slot.last_write_frames.store(written, Ordering::Relaxed);
+                    slot.gain
+                        .store((written as f32).to_bits(), Ordering::Relaxed);
+                    unsafe { ring.write(written as f32) };
+                }
+            });
+
+            started.wait();
+            let mut growths = 0;
+            loop {
+                let claims = SLOT_CLAIMS.lock().unwrap();
+                let grew = unsafe { grow_slot_table(driver as *mut PrismDriver, 1000 + growths) };
+                drop(claims);
+                if !grew {
+                    break;
+                }
+                growths += 1;
+            }
+            assert!(growths > 0);
+            grown.store(true, Ordering::Release);
+        });
+
+        let driver = unsafe { Box::from_raw(driver as *mut PrismDriver) };
+        let table = driver.slot_table();
+        assert_eq!(table.slots.len(), driver.config.max_client_slots as usize);
+        let slot = table.find(client_id).unwrap();
+        assert_eq!(slot.last_write_frames.load(Ordering::Relaxed), last);
+        assert_eq!(
+            f32::from_bits(slot.gain.load(Ordering::Relaxed)),
+            last as f32
+        );
+        assert_eq!(slot.slot_buffer[0], last as f32);
+        assert_eq!(table.occupied(), 1);
+
+        // Retired tables hold nothing the current one does not: no ring is kept twice
+        let current: HashSet<*const ClientSlot> = table.slots.iter().map(Arc::as_ptr).collect();
+        let retired = driver.retired_slot_tables.lock().unwrap();
+        assert!(retired
+            .iter()
+            .flat_map(|old| old.slots.iter())
+            .all(|slot| current.contains(&Arc::as_ptr(slot))));
+    }
+
+    // Built from the default config, so nothing depends on the Driver.plist or
+    // exclusions of the machine running the tests
+    fn create_test_driver() -> *mut PrismDriver {
+        create_driver_with(|| PrismDriver::new(PrismConfig::default()))
+    }
+
+    // The only test that touches the singleton, so the steps below cannot interleave
+    // with another test's create or release
+    #[test]
+    fn singleton_create_and_destroy_are_serialized() {
+        let drivers: Vec<usize> = (0..8)
+            .map(|_| thread::spawn(|| create_test_driver() as usize))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect();
+        let driver = drivers[0] as *mut PrismDriver;
+        assert!(drivers.iter().all(|&other| other == drivers[0]));
+        unsafe {
+            assert_eq!((*driver).ref_count.load(Ordering::Acquire), 8);
+            for _ in 1..8 {
+                release(driver.cast());
+            }
+
+            // A release that reached zero but lost the lock to a create must leave the
+            // instance it handed out again alone
+            (*driver).ref_count.store(0, Ordering::Release);
+            assert_eq!(create_test_driver(), driver);
+            destroy_driver(driver);
+            assert_eq!(DRIVER_INSTANCE.load(Ordering::Acquire), driver);
+            assert_eq!((*driver).ref_count.load(Ordering::Acquire), 1);
+
+            assert_eq!(release(driver.cast()), 0);
+        }
+        assert!(DRIVER_INSTANCE.load(Ordering::Acquire).is_null());
+    }
+
+    // Mock HAL for the CF handover tests. While installed, the thread's default
+    // allocator counts live allocations, so every CF value the helpers create with a
+    // null allocator shows up until whoever owns the last reference releases it.
+    struct CountedAllocations {
+        live: Box<AtomicIsize>,
+        previous: CFAllocatorRef,
+    }
+
+    extern "C" fn counted_allocate(
+        size: CFIndex,
+        _hint: CFOptionFlags,
+        info: *mut c_void,
+    ) -> *mut c_void {
+        let block = unsafe { libc::malloc(size as usize) };
+        if !block.is_null() {
+            unsafe { &*(info as *const AtomicIsize) }.fetch_add(1, Ordering::Relaxed);
+        }
+        block
+    }
+
+    extern "C" fn counted_reallocate(
+        block: *mut c_void,
+        size: CFIndex,
+        _hint: CFOptionFlags,
+        _info: *mut c_void,
+    ) -> *mut c_void {
+        unsafe { libc::realloc(block, size as usize) }
+    }
+
+    extern "C" fn counted_deallocate(block: *mut c_void, info: *mut c_void) {
+        unsafe { &*(info as *const AtomicIsize) }.fetch_sub(1, Ordering::Relaxed);
+        unsafe { libc::free(block) };
+    }
+
+    impl CountedAllocations {
+        fn install() -> Self {
+            let live = Box::new(AtomicIsize::new(0));
+            let mut context = CFAllocatorContext {
+                version: 0,
+                info: &*live as *const AtomicIsize as *mut c_void,
+                retain: None,
+                release: None,
+                copyDescription: None,
+                allocate: Some(counted_allocate),
+                reallocate: Some(counted_reallocate),
+                deallocate: Some(counted_deallocate),
+                preferredSize: None,
+            };
+            unsafe {
+                let previous = CFAllocatorGetDefault();
+                let allocator = CFAllocatorCreate(ptr::null(), &mut context);
+                assert!(!allocator.is_null());
+                // The default slot keeps its own reference until it is restored
+                CFAllocatorSetDefault(allocator);
+                CFRelease(allocator as CFTypeRef);
+                CountedAllocations { live, previous }
+            }
+        }
+
+        fn live(&self) -> isize {
+            self.live.load(Ordering::Relaxed)
+        }
+
+        // What the HAL does with a value it got from GetPropertyData
+        fn host_release(&self, value: CFTypeRef) {
+            assert!(!value.is_null());
+            unsafe { CFRelease(value) };
+        }
+    }
+
+    impl Drop for CountedAllocations {
+        fn drop(&mut self) {
+            unsafe { CFAllocatorSetDefault(self.previous) };
+        }
+    }
+
+    // Long enough that CF cannot store it as a tagged pointer, so it really allocates
+    const HANDOVER_TEXT: &CStr = c"Prism handover test string that needs a heap allocation";
+
+    #[test]
+    fn create_cfstring_releases_its_value_on_drop() {
+        let allocations = CountedAllocations::install();
+        let string = create_cfstring(HANDOVER_TEXT).unwrap();
+        assert!(allocations.live() > 0);
+        assert_eq!(string.retain_count(), 1);
+        drop(string);
+        assert_eq!(allocations.live(), 0);
+
+        assert!(create_cfstring(c"\xff\xfe").is_none());
+        assert_eq!(allocations.live(), 0);
+    }
+
+    #[test]
+    fn into_host_ref_hands_over_the_create_reference() {
+        let allocations = CountedAllocations::install();
+        let string = create_cfstring(HANDOVER_TEXT).unwrap();
+        let live = allocations.live();
+        let string_ref = into_host_ref(string);
+        assert_eq!(allocations.live(), live);
+        assert_eq!(unsafe { CFGetRetainCount(string_ref as CFTypeRef) }, 1);
+        allocations.host_release(string_ref as CFTypeRef);
+        assert_eq!(allocations.live(), 0);
+    }
+
+    #[test]
+    fn write_cfstring_leaves_the_host_one_reference() {
+        let allocations = CountedAllocations::install();
+        let mut value: CFStringRef = ptr::null();
+        let mut size: UInt32 = 0;
+        let status = unsafe {
+            write_cfstring(
+                &mut value as *mut CFStringRef as *mut c_void,
+                &mut size,
+                HANDOVER_TEXT,
+            )
+        };
+        assert_eq!(status, 0);
+        assert_eq!(size as usize, std::mem::size_of::<CFStringRef>());
+        assert!(allocations.live() > 0);
+        assert_eq!(unsafe { CFGetRetainCount(value as CFTypeRef) }, 1);
+        allocations.host_release(value as CFTypeRef);
+        assert_eq!(allocations.live(), 0);
+
+        // A string CF refuses fails the read without handing anything over
+        let mut value: CFStringRef = ptr::null();
+        let status = unsafe {
+            write_cfstring(
+                &mut value as *mut CFStringRef as *mut c_void,
+                &mut size,
+                c"\xff",
+            )
+        };
+        assert_eq!(status, kAudioHardwareUnspecifiedError as OSStatus);
+        assert!(value.is_null());
+        assert_eq!(allocations.live(), 0);
+    }
+
+    #[test]
+    fn write_cfdata_leaves_the_host_one_reference() {
+        let allocations = CountedAllocations::install();
+        let mut value: CFDataRef = ptr::null();
+        let mut size: UInt32 = 0;
+        let payload = vec![0x5au8; 4096];
+        unsafe {
+            write_cfdata(
+                &mut value as *mut CFDataRef as *mut c_void,
+                &mut size,
+                CFData::from_buffer(&payload),
+            )
+        };
+        assert_eq!(size as usize, std::mem::size_of::<CFDataRef>());
+        assert!(allocations.live() > 0);
+        assert_eq!(unsafe { CFGetRetainCount(value as CFTypeRef) }, 1);
+        allocations.host_release(value as CFTypeRef);
+        assert_eq!(allocations.live(), 0);
+    }
+
+    // The getters that go through the helpers, read the way the HAL reads them: nothing
+    // the driver hands over may outlive the host's single release.
+    #[test]
+    fn property_getters_leak_nothing_after_the_host_releases() {
+        let driver = Box::into_raw(PrismDriver::new(PrismConfig::default()));
+        let reads = [
+            (kAudioObjectPlugInObject, kAudioObjectPropertyManufacturer),
+            (kAudioObjectPlugInObject, kAudioPlugInPropertyResourceBundle),
+            (kAudioObjectPlugInObject, kAudioPrismPropertyPluginInfo),
+            (DEVICE_ID, kAudioObjectPropertyName),
+            (DEVICE_ID, kAudioPrismPropertyClientList),
+        ];
+        {
+            let allocations = CountedAllocations::install();
+            for (object_id, selector) in reads {
+                let address = AudioObjectPropertyAddress {
+                    mSelector: selector,
+                    mScope: kAudioObjectPropertyScopeGlobal,
+                    mElement: kAudioObjectPropertyElementMain,
+                };
+                let mut value: CFTypeRef = ptr::null();
+                let mut size: UInt32 = 0;
+                let status = unsafe {
+                    get_property_data(
+                        driver as AudioServerPlugInDriverRef,
+                        object_id,
+                        0,
+                        &address,
+                        0,
+                        ptr::null(),
+                        std::mem::size_of::<CFTypeRef>() as UInt32,
+                        &mut size,
+                        &mut value as *mut CFTypeRef as *mut c_void,
+                    )
+                };
+                assert_eq!(status, 0, "selector {selector:#x}");
+                assert_eq!(size as usize, std::mem::size_of::<CFTypeRef>());
+                allocations.host_release(value);
+                assert_eq!(allocations.live(), 0, "selector {selector:#x}");
+            }
+        }
+        drop(unsafe { Box::from_raw(driver) });
+    }
+}
@@ -0,0 +1,64 @@
+//! Sample loops of the driver's IO path that do not need Accelerate. They live outside
+//! the driver so the benchmarks in benches/ can reach them.
+
+use std::ptr;
+
+const IDENTITY_MIX: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+
+/// dst = mix * src for `frames` interleaved stereo frames, as
+/// [l', r'] = [m0 l + m1 r, m2 l + m3 r]. Unity gain at full width is a plain copy. The
+/// loop is left plain on purpose: the compiler vectorizes it, and hand-unrolled blocks
+/// measured slower (see benches/mix.rs).
+///
+/// # Safety
+///
+/// `src` must be readable and `dst` writable for `frames * 2` floats, and the two must
+/// not overlap. `dst` is typically a ring that ReadInput reads on another IO thread, so
+/// it is only ever written through the pointer, never borrowed as a slice.
+#[inline]
+pub unsafe fn mix_stereo_into(src: *const f32, dst: *mut f32, frames: usize, mix: [f32; 4]) {
+    if mix == IDENTITY_MIX {
+        ptr::copy_nonoverlapping(src, dst, frames * 2);
+        return;
+    }
+    let [m0, m1, m2, m3] = mix;
+    for f in 0..frames {
+        let (l, r) = (*src.add(2 * f), *src.add(2 * f + 1));
+        dst.add(2 * f).write(m0 * l + m1 * r);
+        dst.add(2 * f + 1).write(m2 * l + m3 * r);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mixed(src: &[f32], mix: [f32; 4]) -> Vec<f32> {
+        let mut dst = vec![f32::NAN; src.len()];
+        unsafe { mix_stereo_into(src.as_ptr(), dst.as_mut_ptr(), src.len() / 2, mix) };
+        dst
+    }
+
+    #[test]
+    fn identity_copies() {
+        let src: Vec<f32> = (0..14).map(|n| n as f32).collect();
+        assert_eq!(mixed(&src, IDENTITY_MIX), src);
+    }
+
+    #[test]
+    fn swap_covers_blocks_and_tail() {
+        // Seven frames: one block of four and a tail of three
+        let src: Vec<f32> = (0..14).map(|n| n as f32).collect();
+        let expected: Vec<f32> = src
+            .chunks_exact(2)
+            .flat_map(|frame| [frame[1], frame[0]])
+            .collect();
+        assert_eq!(mixed(&src, [0.0, 1.0, 1.0, 0.0]), expected);
+    }
+
+    #[test]
+    fn mono_fold_halves_both_sides() {
+        let src = [1.0, 3.0, -2.0, 2.0];
+        assert_eq!(mixed(&src, [0.5, 0.5, 0.5, 0.5]), [2.0, 2.0, 0.0, 0.0]);
+    }
+}
@@ -11,49 +11,1558 @@ use std::ptr;
 #[allow(dead_code)]
 pub const K_AUDIO_PRISM_PROPERTY_ROUTING_TABLE: AudioObjectPropertySelector = 0x726F7574; // 'rout'
 pub const K_AUDIO_PRISM_PROPERTY_CLIENT_LIST: AudioObjectPropertySelector = 0x636C6E74; // 'clnt'
+pub const K_AUDIO_PRISM_PROPERTY_IO_STATS: AudioObjectPropertySelector = 0x73746174; // 'stat'
+pub const K_AUDIO_PRISM_PROPERTY_METERS: AudioObjectPropertySelector = 0x6D657472; // 'metr'
+pub const K_AUDIO_PRISM_PROPERTY_TELEMETRY: AudioObjectPropertySelector = 0x746C6D79; // 'tlmy'
+pub const K_AUDIO_PRISM_PROPERTY_CAPTURE_ACL: AudioObjectPropertySelector = 0x63616374; // 'cact'
+pub const K_AUDIO_PRISM_PROPERTY_RESYNC: AudioObjectPropertySelector = 0x7273796E; // 'rsyn'
+pub const K_AUDIO_PRISM_PROPERTY_EVENTS: AudioObjectPropertySelector = 0x65766E74; // 'evnt'
+pub const K_AUDIO_PRISM_PROPERTY_LOG_LEVEL: AudioObjectPropertySelector = 0x6C6F676C; // 'logl'
+pub const K_AUDIO_PRISM_PROPERTY_CLEAR_BUFFERS: AudioObjectPropertySelector = 0x636C7220; // 'clr '
+pub const K_AUDIO_PRISM_PROPERTY_CLOCK_RATE: AudioObjectPropertySelector = 0x72617465; // 'rate'
+pub const K_AUDIO_PRISM_PROPERTY_CONFIG: AudioObjectPropertySelector = 0x636E6667; // 'cnfg'
+pub const K_AUDIO_PRISM_PROPERTY_MATRIX: AudioObjectPropertySelector = 0x6D747278; // 'mtrx'
+pub const K_AUDIO_PRISM_PROPERTY_MONITOR: AudioObjectPropertySelector = 0x6D6E7364; // 'mnsd'
+pub const K_AUDIO_PRISM_PROPERTY_RESERVATIONS: AudioObjectPropertySelector = 0x72657376; // 'resv'
+pub const K_AUDIO_PRISM_PROPERTY_GAP_POLICY: AudioObjectPropertySelector = 0x67617070; // 'gapp'
+pub const K_AUDIO_PRISM_PROPERTY_BUS_OCCUPANCY: AudioObjectPropertySelector = 0x62757379; // 'busy'
+pub const K_AUDIO_PRISM_PROPERTY_ROUTING_FREEZE: AudioObjectPropertySelector = 0x6672657A; // 'frez'
+pub const K_AUDIO_PRISM_PROPERTY_CLIENT_GAIN: AudioObjectPropertySelector = 0x6367616E; // 'cgan'
+pub const K_AUDIO_PRISM_PROPERTY_SOLO: AudioObjectPropertySelector = 0x736F6C6F; // 'solo'
+pub const K_AUDIO_PRISM_PROPERTY_CAPTURE_ALLOWLIST: AudioObjectPropertySelector = 0x6361706C; // 'capl'
+pub const K_AUDIO_PRISM_PROPERTY_CAPTURE_MASK: AudioObjectPropertySelector = 0x636D736B; // 'cmsk'
+pub const K_AUDIO_PRISM_PROPERTY_CAPTURE_EXCLUSIONS: AudioObjectPropertySelector = 0x63657863; // 'cexc'
+pub const K_AUDIO_PRISM_PROPERTY_ROUTE_EVENT: AudioObjectPropertySelector = 0x72657674; // 'revt'
+pub const K_AUDIO_PRISM_PROPERTY_BUS_LABELS: AudioObjectPropertySelector = 0x6275736E; // 'busn'
+pub const K_AUDIO_PRISM_PROPERTY_ROUTE_BUNDLE: AudioObjectPropertySelector = 0x72626E64; // 'rbnd'
+                                                                                         // On the plug-in object rather than the device
+pub const K_AUDIO_PRISM_PROPERTY_PLUGIN_INFO: AudioObjectPropertySelector = 0x70696E66; // 'pinf'
+pub const PRISM_PLUGIN_BUNDLE_ID: &str = "dev.ichigo.driver.Prism";
+const PRISM_DEVICE_UID: &str = "dev.ichigo.driver.Prism.Device";
+const K_AUDIO_DEVICE_PROPERTY_CLOCK_DOMAIN: AudioObjectPropertySelector = 0x636C6B64; // 'clkd'
+const K_AUDIO_HARDWARE_PROPERTY_TRANSLATE_BUNDLE_ID_TO_PLUGIN: AudioObjectPropertySelector =
+    0x62696470; // 'bidp'
+
+// Keys of 'cnfg' that can change while the device exists, in DeviceTiming order.
+pub const TIMING_KEYS: [&str; 4] = [
+    "input_latency",
+    "output_latency",
+    "input_safety_offset",
+    "output_safety_offset",
+];
+
+// Bits of the 'evnt' mask, matching the driver's PRISM_EVENT_* constants.
+pub const PRISM_EVENT_CLIENTS: u32 = 1 << 0;
+pub const PRISM_EVENT_ROUTING: u32 = 1 << 1;
+pub const PRISM_EVENT_CONFIG: u32 = 1 << 2;
+pub const PRISM_EVENT_DIAG: u32 = 1 << 3;
 
 #[derive(Clone, Debug, Default)]
 pub struct ClientEntry {
     pub pid: i32,
     pub client_id: u32,
     pub channel_offset: u32,
+    pub bundle_id: Option<String>,
+    pub is_running: bool,
+    pub gain: f32, // Linear
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct IoStats {
+    pub cycles_served: u64,
+    pub output_cycles: u64,
+    pub wrap_arounds: u64,
+    pub stale_reads: u64,
+    pub overloads: u64,
+    pub watchdog_clears: u64,
+    pub late_cycles: u64,
+    pub missed_writes: u64,
+    pub frames_written: Vec<u64>,
+    pub slot_table_size: u64,
+    pub slots_occupied: u64,
+    pub slot_collisions: u64,
+    pub slot_table_growths: u64,
+    pub readers: Vec<CaptureReader>,
+}
+
+// A client that has read the input stream (a capture app).
+#[derive(Clone, Debug, Default)]
+pub struct CaptureReader {
+    pub client_id: u32,
+    pub pid: i32,
+    pub last_read_time: f64, // Input sample time of its last ReadInput
+    pub reads: u64,
+    pub active: bool,
+}
+
+/// Driver-wide summary from the plug-in's 'pinf' property.
+#[derive(Clone, Debug, Default)]
+pub struct PluginInfo {
+    pub version: String,
+    pub build: String, // Git revision the driver was built from, or "unknown"
+    pub debug: bool,
+    pub features: Vec<String>,
+    pub devices: Vec<PluginDevice>,
+    pub total_clients: u32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PluginDevice {
+    pub device_id: u32,
+    pub uid: String,
+    pub name: String,
+    pub channels: u32,
+    pub clients: u32,
+    pub io_running: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BusLevel {
+    pub pair: u32,
+    pub peak: [f32; 2],
+    pub rms: [f32; 2],
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BufferRegion {
+    pub channel_offset: u32,
+    pub frames: u64,
+    pub peak: f32, // Linear, over everything currently buffered
+    pub clients: u32,
+}
+
+/// An audio device published by the HAL.
+#[derive(Clone, Debug, Default)]
+pub struct AudioDeviceEntry {
+    pub device_id: AudioObjectID,
+    pub uid: String,
+    pub name: String,
+    /// kAudioDevicePropertyClockDomain; 0 when the device reports none.
+    pub clock_domain: u32,
+}
+
+/// How the output stream maps onto the bus ('cnfg' full_bus_output).
+#[derive(Clone, Debug, Default)]
+pub struct OutputMode {
+    pub full_bus: bool,
+    pub bus_channels: u32,
+}
+
+/// Latency and safety offset per scope, in frames.
+#[derive(Clone, Debug, Default)]
+pub struct DeviceTiming {
+    pub input_latency: u32,
+    pub output_latency: u32,
+    pub input_safety_offset: u32,
+    pub output_safety_offset: u32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CaptureRuleEntry {
+    pub channel_offset: u32,
+    pub bundle_id: String,
+}
+
+/// One 'mtrx' route: `source` side (0 left, 1 right) of the client's stereo
+/// output into 0-based bus `channel`, scaled by linear `gain`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MatrixRoute {
+    pub source: u32,
+    pub channel: u32,
+    pub gain: f32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ClientMatrix {
+    pub pid: i32,
+    pub client_id: u32,
+    pub routes: Vec<MatrixRoute>,
+}
+
+/// A pair claimed through 'resv'.
+#[derive(Clone, Debug, Default)]
+pub struct Reservation {
+    pub channel_offset: u32,
+    pub owner: String,
+}
+
+/// Who plays on a bus pair, as reported by the driver's 'busy' property.
+#[derive(Debug, Clone)]
+pub struct BusOccupancy {
+    pub channel_offset: u32,
+    pub writers: Vec<BusWriter>,
+    pub reserved_by: Option<String>,
+    /// Milliseconds since the pair last carried audio; None if it never did.
+    pub last_audio_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BusWriter {
+    pub pid: i32,
+    pub client_id: u32,
+    pub bundle_id: Option<String>,
+    pub is_running: bool,
+}
+
+/// A pair that repeats its clients' newest audio over gaps of up to `max_hold_ms`
+/// ('gapp'); pairs without one zero-fill.
+#[derive(Debug, Clone)]
+pub struct GapHold {
+    pub channel_offset: u32,
+    pub max_hold_ms: u32,
+}
+
+/// The driver's monitor downmix ('mnsd'): while enabled, prismd's bridges from 1-2 add
+/// each pair with a send at linear `gain`.
+#[derive(Clone, Debug, Default)]
+pub struct MonitorState {
+    pub enabled: bool,
+    /// (channel_offset, gain) for every pair with a non-zero send.
+    pub sends: Vec<(u32, f32)>,
+}
+
+/// The driver's capture allowlist ('capl'): while enabled, only clients of the listed
+/// bundle IDs (and their helpers) or PIDs read the input stream.
+#[derive(Clone, Debug, Default)]
+pub struct CaptureAllowlist {
+    pub enabled: bool,
+    pub bundle_ids: Vec<String>,
+    pub pids: Vec<i32>,
+}
+
+/// The last client the driver moved between pairs ('revt'). `seq` counts every change
+/// since the driver loaded.
+#[derive(Clone, Debug)]
+pub struct RouteChange {
+    pub seq: u64,
+    pub pid: i32,
+    pub client_id: u32,
+    pub old_offset: u32,
+    pub new_offset: u32,
+    /// route, broadcast, bundle, unroute, assign or learned
+    pub reason: String,
+}
+
+/// The driver's system mix: the pair WriteMix fills and whether it fills it at all.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemMix {
+    pub offset: u32,
+    pub enabled: bool,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug, Default)]
+pub struct CustomPropertyInfo {
+    pub selector: u32,
+    pub property_data_type: u32,
+    pub qualifier_data_type: u32,
+}
+
+/// Send a routing update for `pid`. `gain` (linear), `pan` (-1.0..=1.0) and
+/// `width` (0.0..=2.0) are optional; when any is present the mix trailer is
+/// appended and the driver applies offset and mix together. Missing values are
+/// sent as NaN so the driver keeps the client's current setting.
+#[allow(dead_code)]
+pub fn send_rout_update(
+    device_id: AudioObjectID,
+    pid: i32,
+    offset: u32,
+    gain: Option<f32>,
+    pan: Option<f32>,
+    width: Option<f32>,
+) -> Result<(), String> {
+    let update = PrismRoutingUpdate {
+        pid,
+        channel_offset: offset,
+    };
+
+    let address = AudioObjectPropertyAddress {
+        mSelector: K_AUDIO_PRISM_PROPERTY_ROUTING_TABLE,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+
+    let mut buf: Vec<u8> = Vec::with_capacity(mem::size_of::<PrismRoutingUpdate>());
+    buf.extend_from_slice(&update.pid.to_le_bytes());
+    buf.extend_from_slice(&update.channel_offset.to_le_bytes());
+    if gain.is_some() || pan.is_some() || width.is_some() {
+        buf.extend_from_slice(&gain.unwrap_or(f32::NAN).to_le_bytes());
+        buf.extend_from_slice(&pan.unwrap_or(f32::NAN).to_le_bytes());
+    }
+    if let Some(width) = width {
+        buf.extend_from_slice(&width.to_le_bytes());
+    }
+
+    let cfdata = CFData::from_buffer(&buf);
+    let cfdata_ref = cfdata.as_concrete_TypeRef();
+    let status = unsafe {
+        AudioObjectSetPropertyData(
+            device_id,
+            &address,
+            0,
+            ptr::null(),
+            mem::size_of::<CFDataRef>() as u32,
+            &cfdata_ref as *const _ as *const c_void,
+        )
+    };
+
+    if status == 0 {
+        Ok(())
+    } else {
+        Err(set_property_error("rout", status))
+    }
+}
+
+/// One entry of a batched routing update; `None` fields keep the client's setting.
+#[derive(Clone, Copy, Debug)]
+pub struct RoutUpdate {
+    pub pid: i32,
+    pub channel_offset: u32,
+    pub gain: Option<f32>,
+    pub pan: Option<f32>,
+    pub width: Option<f32>,
+}
+
+/// Send several routing updates in one 'rout' write. The driver checks every entry
+/// before applying any and notifies once for the whole batch, and fails when none of
+/// the pids has a client. With `owner`, pairs it reserved accept the entries.
+pub fn send_rout_batch(
+    device_id: AudioObjectID,
+    updates: &[RoutUpdate],
+    owner: Option<&str>,
+) -> Result<(), String> {
+    let entries = updates
+        .iter()
+        .map(|update| {
+            let mut dict = plist::Dictionary::new();
+            dict.insert("pid".into(), Value::from(i64::from(update.pid)));
+            dict.insert(
+                "channel_offset".into(),
+                Value::from(u64::from(update.channel_offset)),
+            );
+            for (key, value) in [
+                ("gain", update.gain),
+                ("pan", update.pan),
+                ("width", update.width),
+            ] {
+                if let Some(value) = value {
+                    dict.insert(key.into(), Value::from(f64::from(value)));
+                }
+            }
+            if let Some(owner) = owner {
+                dict.insert("owner".into(), Value::from(owner));
+            }
+            Value::Dictionary(dict)
+        })
+        .collect();
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_ROUTING_TABLE,
+        "rout",
+        Value::Array(entries),
+    )
+}
+
+/// Set the balance (`pan`, -1.0..=1.0) of the clients of `pid`, exchange their left and
+/// right channels, or both, keeping them on `offset`. None leaves a setting as it is.
+pub fn send_balance(
+    device_id: AudioObjectID,
+    pid: i32,
+    offset: u32,
+    balance: Option<f32>,
+    swap: Option<bool>,
+) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    dict.insert("pid".into(), Value::from(i64::from(pid)));
+    dict.insert("channel_offset".into(), Value::from(u64::from(offset)));
+    if let Some(balance) = balance {
+        dict.insert("pan".into(), Value::from(f64::from(balance)));
+    }
+    if let Some(swap) = swap {
+        dict.insert("swap".into(), Value::Boolean(swap));
+    }
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_ROUTING_TABLE,
+        "rout",
+        Value::Array(vec![Value::Dictionary(dict)]),
+    )
+}
+
+/// Fold the clients of `pid` down to mono ("left" or "right", the channel of their pair
+/// the sum goes to) or back to stereo ("off"), keeping them on `offset`.
+pub fn send_mono_fold(
+    device_id: AudioObjectID,
+    pid: i32,
+    offset: u32,
+    fold: &str,
+) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    dict.insert("pid".into(), Value::from(i64::from(pid)));
+    dict.insert("channel_offset".into(), Value::from(u64::from(offset)));
+    dict.insert("mono".into(), Value::from(fold));
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_ROUTING_TABLE,
+        "rout",
+        Value::Array(vec![Value::Dictionary(dict)]),
+    )
+}
+
+/// Also play the clients of `pid` on the pair at `mirror`, keeping them on `offset`.
+/// A `mirror` of 0 stops mirroring.
+pub fn send_mirror(
+    device_id: AudioObjectID,
+    pid: i32,
+    offset: u32,
+    mirror: u32,
+) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    dict.insert("pid".into(), Value::from(i64::from(pid)));
+    dict.insert("channel_offset".into(), Value::from(u64::from(offset)));
+    dict.insert("mirror_offset".into(), Value::from(u64::from(mirror)));
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_ROUTING_TABLE,
+        "rout",
+        Value::Array(vec![Value::Dictionary(dict)]),
+    )
+}
+
+/// Return the clients of `pid` (every client for -1) to the state they connected in:
+/// pair 0, unity gain, centered, normal width and no matrix. Their old pair is cleared.
+pub fn send_unroute(device_id: AudioObjectID, pid: i32) -> Result<(), String> {
+    send_rout_update(device_id, pid, PRISM_UNROUTE_OFFSET, None, None, None)
+}
+
+/// Route every client to `offset` except the excluded pids and bundle IDs (helpers of an
+/// excluded bundle included), which keep their route. `gain` and `pan` are optional as in
+/// `send_rout_update`, `owner` as in `send_rout_batch`.
+pub fn send_rout_broadcast(
+    device_id: AudioObjectID,
+    offset: u32,
+    gain: Option<f32>,
+    pan: Option<f32>,
+    exclude_pids: &[i32],
+    exclude_bundle_ids: &[String],
+    owner: Option<&str>,
+) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    dict.insert("pid".into(), Value::from(-1i64));
+    if let Some(owner) = owner {
+        dict.insert("owner".into(), Value::from(owner));
+    }
+    dict.insert("channel_offset".into(), Value::from(u64::from(offset)));
+    if let Some(gain) = gain {
+        dict.insert("gain".into(), Value::from(f64::from(gain)));
+    }
+    if let Some(pan) = pan {
+        dict.insert("pan".into(), Value::from(f64::from(pan)));
+    }
+    dict.insert(
+        "exclude_pids".into(),
+        Value::Array(
+            exclude_pids
+                .iter()
+                .map(|&pid| Value::from(i64::from(pid)))
+                .collect(),
+        ),
+    );
+    dict.insert(
+        "exclude_bundle_ids".into(),
+        Value::Array(
+            exclude_bundle_ids
+                .iter()
+                .map(|id| Value::from(id.as_str()))
+                .collect(),
+        ),
+    );
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_ROUTING_TABLE,
+        "rout",
+        Value::Dictionary(dict),
+    )
+}
+
+/// Route every client of `bundle_id` and its helpers to `offset` in the driver,
+/// without resolving their pids first. Fails when no client of the bundle is connected.
+/// `gain` and `pan` are optional as in `send_rout_update`; `owner` as in `send_rout_batch`.
+pub fn send_bundle_route(
+    device_id: AudioObjectID,
+    bundle_id: &str,
+    offset: u32,
+    gain: Option<f32>,
+    pan: Option<f32>,
+    owner: Option<&str>,
+) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    dict.insert("bundle_id".into(), Value::from(bundle_id));
+    if let Some(owner) = owner {
+        dict.insert("owner".into(), Value::from(owner));
+    }
+    dict.insert("channel_offset".into(), Value::from(u64::from(offset)));
+    if let Some(gain) = gain {
+        dict.insert("gain".into(), Value::from(f64::from(gain)));
+    }
+    if let Some(pan) = pan {
+        dict.insert("pan".into(), Value::from(f64::from(pan)));
+    }
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_ROUTE_BUNDLE,
+        "rbnd",
+        Value::Dictionary(dict),
+    )
+}
+
+/// Restrict capture of the pair at `offset` to clients of `bundle_id`; an empty
+/// bundle ID lifts the restriction.
+pub fn send_capture_rule(
+    device_id: AudioObjectID,
+    offset: u32,
+    bundle_id: &str,
+) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    dict.insert("channel_offset".into(), Value::from(u64::from(offset)));
+    dict.insert("bundle_id".into(), Value::from(bundle_id));
+    let mut buf = Vec::new();
+    plist::to_writer_binary(&mut buf, &Value::Dictionary(dict))
+        .map_err(|err| format!("Failed to encode capture rule: {}", err))?;
+
+    let address = AudioObjectPropertyAddress {
+        mSelector: K_AUDIO_PRISM_PROPERTY_CAPTURE_ACL,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+
+    let cfdata = CFData::from_buffer(&buf);
+    let cfdata_ref = cfdata.as_concrete_TypeRef();
+    let status = unsafe {
+        AudioObjectSetPropertyData(
+            device_id,
+            &address,
+            0,
+            ptr::null(),
+            mem::size_of::<CFDataRef>() as u32,
+            &cfdata_ref as *const _ as *const c_void,
+        )
+    };
+
+    if status == 0 {
+        Ok(())
+    } else {
+        Err(format!(
+            "AudioObjectSetPropertyData('cact') failed with status {}",
+            status
+        ))
+    }
+}
+
+/// Ask the driver to re-anchor its timeline and clear its buffers. Returns how many
+/// resyncs the driver has performed since it was loaded.
+pub fn send_resync(device_id: AudioObjectID) -> Result<u64, String> {
+    let address = AudioObjectPropertyAddress {
+        mSelector: K_AUDIO_PRISM_PROPERTY_RESYNC,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+
+    let cfdata = CFData::from_buffer(&[]);
+    let cfdata_ref = cfdata.as_concrete_TypeRef();
+    let status = unsafe {
+        AudioObjectSetPropertyData(
+            device_id,
+            &address,
+            0,
+            ptr::null(),
+            mem::size_of::<CFDataRef>() as u32,
+            &cfdata_ref as *const _ as *const c_void,
+        )
+    };
+    if status != 0 {
+        return Err(format!(
+            "AudioObjectSetPropertyData('rsyn') failed with status {}",
+            status
+        ));
+    }
+
+    let count = fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_RESYNC, "rsyn")?
+        .and_then(|value| value.into_dictionary())
+        .and_then(|dict| dict.get("resyncs")?.as_unsigned_integer())
+        .unwrap_or(0);
+    Ok(count)
+}
+
+/// The driver's runtime os_log level ("error", "info" or "debug").
+pub fn fetch_log_level(device_id: AudioObjectID) -> Result<String, String> {
+    let level = fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_LOG_LEVEL, "logl")?
+        .and_then(|value| value.into_dictionary())
+        .and_then(|dict| Some(dict.get("level")?.as_string()?.to_string()))
+        .unwrap_or_else(|| "info".to_string());
+    Ok(level)
+}
+
+pub fn send_log_level(device_id: AudioObjectID, level: &str) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    dict.insert("level".into(), Value::from(level));
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_LOG_LEVEL,
+        "logl",
+        Value::Dictionary(dict),
+    )
+}
+
+/// The driver's clock rate as (requested ppm, rate scalar in effect). The scalar eases
+/// toward 1 + ppm / 1e6 over a few zero timestamps after a change.
+pub fn fetch_clock_rate(device_id: AudioObjectID) -> Result<(f64, f64), String> {
+    let rate = fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_CLOCK_RATE, "rate")?
+        .and_then(|value| value.into_dictionary())
+        .and_then(|dict| Some((dict.get("ppm")?.as_real()?, dict.get("scalar")?.as_real()?)))
+        .unwrap_or((0.0, 1.0));
+    Ok(rate)
+}
+
+pub fn send_clock_rate(device_id: AudioObjectID, ppm: f64) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    dict.insert("ppm".into(), Value::from(ppm));
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_CLOCK_RATE,
+        "rate",
+        Value::Dictionary(dict),
+    )
+}
+
+pub fn fetch_device_timing(device_id: AudioObjectID) -> Result<DeviceTiming, String> {
+    let Some(dict) = fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_CONFIG, "cnfg")?
+        .and_then(|value| value.into_dictionary())
+    else {
+        return Err("driver returned no 'cnfg' dictionary".to_string());
+    };
+    let frames = |key: &str| {
+        dict.get(key)
+            .and_then(|v| v.as_unsigned_integer())
+            .map(|v| v as u32)
+            .unwrap_or(0)
+    };
+    Ok(DeviceTiming {
+        input_latency: frames(TIMING_KEYS[0]),
+        output_latency: frames(TIMING_KEYS[1]),
+        input_safety_offset: frames(TIMING_KEYS[2]),
+        output_safety_offset: frames(TIMING_KEYS[3]),
+    })
+}
+
+/// Change some of the TIMING_KEYS; the rest keep their values.
+pub fn send_device_timing(device_id: AudioObjectID, updates: &[(&str, u32)]) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    for (key, frames) in updates {
+        dict.insert((*key).into(), Value::from(u64::from(*frames)));
+    }
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_CONFIG,
+        "cnfg",
+        Value::Dictionary(dict),
+    )
+}
+
+pub fn fetch_output_mode(device_id: AudioObjectID) -> Result<OutputMode, String> {
+    let Some(dict) = fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_CONFIG, "cnfg")?
+        .and_then(|value| value.into_dictionary())
+    else {
+        return Err("driver returned no 'cnfg' dictionary".to_string());
+    };
+    Ok(OutputMode {
+        full_bus: dict
+            .get("full_bus_output")
+            .and_then(|v| v.as_boolean())
+            .unwrap_or(false),
+        bus_channels: dict
+            .get("num_channels")
+            .and_then(|v| v.as_unsigned_integer())
+            .map(|v| v as u32)
+            .unwrap_or(0),
+    })
+}
+
+pub fn fetch_clock_domain(device_id: AudioObjectID) -> Result<u32, String> {
+    let Some(dict) = fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_CONFIG, "cnfg")?
+        .and_then(|value| value.into_dictionary())
+    else {
+        return Err("driver returned no 'cnfg' dictionary".to_string());
+    };
+    Ok(dict
+        .get("clock_domain")
+        .and_then(|v| v.as_unsigned_integer())
+        .unwrap_or(0) as u32)
+}
+
+pub fn send_clock_domain(device_id: AudioObjectID, domain: u32) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    dict.insert("clock_domain".into(), Value::from(u64::from(domain)));
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_CONFIG,
+        "cnfg",
+        Value::Dictionary(dict),
+    )
+}
+
+/// Where the driver's system mix lands, and whether WriteMix content is captured at all.
+pub fn fetch_system_mix(device_id: AudioObjectID) -> Result<SystemMix, String> {
+    let Some(dict) = fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_CONFIG, "cnfg")?
+        .and_then(|value| value.into_dictionary())
+    else {
+        return Err("driver returned no 'cnfg' dictionary".to_string());
+    };
+    Ok(SystemMix {
+        offset: dict
+            .get("system_mix_offset")
+            .and_then(|v| v.as_unsigned_integer())
+            .unwrap_or(0) as u32,
+        // Drivers from before the switch always capture it
+        enabled: dict
+            .get("system_mix_enabled")
+            .and_then(|v| v.as_boolean())
+            .unwrap_or(true),
+    })
+}
+
+pub fn send_system_mix(
+    device_id: AudioObjectID,
+    offset: Option<u32>,
+    enabled: Option<bool>,
+) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    if let Some(offset) = offset {
+        dict.insert("system_mix_offset".into(), Value::from(u64::from(offset)));
+    }
+    if let Some(enabled) = enabled {
+        dict.insert("system_mix_enabled".into(), Value::from(enabled));
+    }
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_CONFIG,
+        "cnfg",
+        Value::Dictionary(dict),
+    )
+}
+
+/// The driver's auto-assignment policy by name (first-free, round-robin, ...).
+pub fn fetch_auto_assign(device_id: AudioObjectID) -> Result<String, String> {
+    let Some(dict) = fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_CONFIG, "cnfg")?
+        .and_then(|value| value.into_dictionary())
+    else {
+        return Err("driver returned no 'cnfg' dictionary".to_string());
+    };
+    match dict.get("auto_assign") {
+        Some(Value::String(name)) => Ok(name.clone()),
+        // Drivers from before assignment policies report a boolean
+        Some(Value::Boolean(true)) => Ok("first-free".to_string()),
+        Some(Value::Boolean(false)) => Ok("disabled".to_string()),
+        _ => Err("driver reports no auto_assign policy".to_string()),
+    }
+}
+
+pub fn send_auto_assign(device_id: AudioObjectID, policy: &str) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    dict.insert("auto_assign".into(), Value::from(policy));
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_CONFIG,
+        "cnfg",
+        Value::Dictionary(dict),
+    )
+}
+
+pub fn send_output_mode(device_id: AudioObjectID, full_bus: bool) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    dict.insert("full_bus_output".into(), Value::from(full_bus));
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_CONFIG,
+        "cnfg",
+        Value::Dictionary(dict),
+    )
+}
+
+/// Allocated pair rings, as reported by the driver's 'clr ' property.
+pub fn fetch_buffer_regions(device_id: AudioObjectID) -> Result<Vec<BufferRegion>, String> {
+    let Some(Value::Array(items)) =
+        fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_CLEAR_BUFFERS, "clr ")?
+    else {
+        return Ok(Vec::new());
+    };
+
+    Ok(items
+        .into_iter()
+        .filter_map(|item| {
+            let dict = item.into_dictionary()?;
+            Some(BufferRegion {
+                channel_offset: dict.get("channel_offset")?.as_unsigned_integer()? as u32,
+                frames: dict.get("frames")?.as_unsigned_integer()?,
+                peak: dict.get("peak")?.as_real()? as f32,
+                clients: dict.get("clients")?.as_unsigned_integer()? as u32,
+            })
+        })
+        .collect())
+}
+
+/// Zero the ring of the pair at `offset`, or every ring when `offset` is None.
+pub fn send_clear_buffers(device_id: AudioObjectID, offset: Option<u32>) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    match offset {
+        Some(offset) => dict.insert("channel_offset".into(), Value::from(u64::from(offset))),
+        None => dict.insert("all".into(), Value::Boolean(true)),
+    };
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_CLEAR_BUFFERS,
+        "clr ",
+        Value::Dictionary(dict),
+    )
+}
+
+/// Route `pid` through `routes` instead of its channel pair; no routes clears the matrix.
+pub fn send_matrix(
+    device_id: AudioObjectID,
+    pid: i32,
+    routes: &[MatrixRoute],
+) -> Result<(), String> {
+    let routes = routes
+        .iter()
+        .map(|route| {
+            let mut dict = plist::Dictionary::new();
+            dict.insert("source".into(), Value::from(u64::from(route.source)));
+            dict.insert("channel".into(), Value::from(u64::from(route.channel)));
+            dict.insert("gain".into(), Value::from(f64::from(route.gain)));
+            Value::Dictionary(dict)
+        })
+        .collect();
+    let mut dict = plist::Dictionary::new();
+    dict.insert("pid".into(), Value::from(i64::from(pid)));
+    dict.insert("routes".into(), Value::Array(routes));
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_MATRIX,
+        "mtrx",
+        Value::Dictionary(dict),
+    )
+}
+
+/// Clients currently routed by a matrix, as reported by the driver's 'mtrx' property.
+pub fn fetch_matrices(device_id: AudioObjectID) -> Result<Vec<ClientMatrix>, String> {
+    let Some(Value::Array(items)) =
+        fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_MATRIX, "mtrx")?
+    else {
+        return Ok(Vec::new());
+    };
+
+    Ok(items
+        .into_iter()
+        .filter_map(|item| {
+            let dict = item.into_dictionary()?;
+            let routes = dict
+                .get("routes")?
+                .as_array()?
+                .iter()
+                .filter_map(|route| {
+                    let route = route.as_dictionary()?;
+                    Some(MatrixRoute {
+                        source: route.get("source")?.as_unsigned_integer()? as u32,
+                        channel: route.get("channel")?.as_unsigned_integer()? as u32,
+                        gain: route.get("gain")?.as_real()? as f32,
+                    })
+                })
+                .collect();
+            Some(ClientMatrix {
+                pid: dict.get("pid")?.as_signed_integer()? as i32,
+                client_id: dict.get("client_id")?.as_unsigned_integer()? as u32,
+                routes,
+            })
+        })
+        .collect())
+}
+
+/// Update the monitor downmix. `enabled` None leaves it as it is; a send with gain 0
+/// removes it.
+pub fn send_monitor(
+    device_id: AudioObjectID,
+    enabled: Option<bool>,
+    sends: &[(u32, f32)],
+) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    if let Some(enabled) = enabled {
+        dict.insert("enabled".into(), Value::from(enabled));
+    }
+    if !sends.is_empty() {
+        let sends = sends
+            .iter()
+            .map(|&(channel_offset, gain)| {
+                let mut send = plist::Dictionary::new();
+                send.insert(
+                    "channel_offset".into(),
+                    Value::from(u64::from(channel_offset)),
+                );
+                send.insert("gain".into(), Value::from(f64::from(gain)));
+                Value::Dictionary(send)
+            })
+            .collect();
+        dict.insert("sends".into(), Value::Array(sends));
+    }
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_MONITOR,
+        "mnsd",
+        Value::Dictionary(dict),
+    )
+}
+
+/// Claim the pair at `offset` for `owner`, or with `release` drop the claim. Fails with
+/// the reserved message when another owner holds the pair.
+pub fn send_reservation(
+    device_id: AudioObjectID,
+    offset: u32,
+    owner: &str,
+    release: bool,
+) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    dict.insert("channel_offset".into(), Value::from(u64::from(offset)));
+    dict.insert("owner".into(), Value::from(owner));
+    if release {
+        dict.insert("release".into(), Value::from(true));
+    }
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_RESERVATIONS,
+        "resv",
+        Value::Dictionary(dict),
+    )
+}
+
+pub fn fetch_reservations(device_id: AudioObjectID) -> Result<Vec<Reservation>, String> {
+    let Some(Value::Array(items)) =
+        fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_RESERVATIONS, "resv")?
+    else {
+        return Ok(Vec::new());
+    };
+    Ok(items
+        .into_iter()
+        .filter_map(|item| {
+            let dict = item.into_dictionary()?;
+            Some(Reservation {
+                channel_offset: dict.get("channel_offset")?.as_unsigned_integer()? as u32,
+                owner: dict.get("owner")?.as_string()?.to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Name the pair at `offset`, or drop its name with None.
+pub fn send_bus_label(
+    device_id: AudioObjectID,
+    offset: u32,
+    label: Option<&str>,
+) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    dict.insert("channel_offset".into(), Value::from(u64::from(offset)));
+    if let Some(label) = label {
+        dict.insert("label".into(), Value::from(label));
+    }
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_BUS_LABELS,
+        "busn",
+        Value::Dictionary(dict),
+    )
+}
+
+/// (channel offset, name) of every named pair.
+pub fn fetch_bus_labels(device_id: AudioObjectID) -> Result<Vec<(u32, String)>, String> {
+    let Some(Value::Array(items)) =
+        fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_BUS_LABELS, "busn")?
+    else {
+        return Ok(Vec::new());
+    };
+    Ok(items
+        .into_iter()
+        .filter_map(|item| {
+            let dict = item.into_dictionary()?;
+            Some((
+                dict.get("channel_offset")?.as_unsigned_integer()? as u32,
+                dict.get("label")?.as_string()?.to_string(),
+            ))
+        })
+        .collect())
+}
+
+/// Set the gap mode of the pair at `offset` ("zero-fill" or "hold-last").
+pub fn send_gap_policy(
+    device_id: AudioObjectID,
+    offset: u32,
+    mode: &str,
+    max_hold_ms: u32,
+) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    dict.insert("channel_offset".into(), Value::from(u64::from(offset)));
+    dict.insert("mode".into(), Value::from(mode));
+    dict.insert("max_hold_ms".into(), Value::from(u64::from(max_hold_ms)));
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_GAP_POLICY,
+        "gapp",
+        Value::Dictionary(dict),
+    )
+}
+
+/// Set the linear AGC stage of the clients of `pid` (every client for -1), which the
+/// driver applies on top of their own gain, without touching their routing.
+pub fn send_client_agc(device_id: AudioObjectID, pid: i32, gain: f32) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    dict.insert("pid".into(), Value::from(i64::from(pid)));
+    dict.insert("agc".into(), Value::from(f64::from(gain)));
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_CLIENT_GAIN,
+        "cgan",
+        Value::Array(vec![Value::Dictionary(dict)]),
+    )
+}
+
+/// Freeze routing so the following 'rout' updates take effect together when it is
+/// thawed, or after `timeout_ms` at the latest.
+pub fn send_routing_freeze(
+    device_id: AudioObjectID,
+    frozen: bool,
+    timeout_ms: u32,
+) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    dict.insert("frozen".into(), Value::from(frozen));
+    dict.insert("timeout_ms".into(), Value::from(u64::from(timeout_ms)));
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_ROUTING_FREEZE,
+        "frez",
+        Value::Dictionary(dict),
+    )
+}
+
+pub fn fetch_gap_holds(device_id: AudioObjectID) -> Result<Vec<GapHold>, String> {
+    let Some(Value::Array(items)) =
+        fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_GAP_POLICY, "gapp")?
+    else {
+        return Ok(Vec::new());
+    };
+    Ok(items
+        .into_iter()
+        .filter_map(|item| {
+            let dict = item.into_dictionary()?;
+            Some(GapHold {
+                channel_offset: dict.get("channel_offset")?.as_unsigned_integer()? as u32,
+                max_hold_ms: dict.get("max_hold_ms")?.as_unsigned_integer()? as u32,
+            })
+        })
+        .collect())
+}
+
+pub fn fetch_bus_occupancy(device_id: AudioObjectID) -> Result<Vec<BusOccupancy>, String> {
+    let Some(Value::Array(items)) =
+        fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_BUS_OCCUPANCY, "busy")?
+    else {
+        return Ok(Vec::new());
+    };
+    Ok(items
+        .into_iter()
+        .filter_map(|item| {
+            let dict = item.into_dictionary()?;
+            let writers = dict
+                .get("writers")
+                .and_then(|v| v.as_array())
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|writer| {
+                    let writer = writer.as_dictionary()?;
+                    Some(BusWriter {
+                        pid: writer.get("pid")?.as_signed_integer()? as i32,
+                        client_id: writer.get("client_id")?.as_unsigned_integer()? as u32,
+                        bundle_id: writer
+                            .get("bundle_id")
+                            .and_then(|v| v.as_string())
+                            .map(str::to_string),
+                        is_running: writer
+                            .get("is_running")
+                            .and_then(|v| v.as_boolean())
+                            .unwrap_or(false),
+                    })
+                })
+                .collect();
+            Some(BusOccupancy {
+                channel_offset: dict.get("channel_offset")?.as_unsigned_integer()? as u32,
+                writers,
+                reserved_by: dict
+                    .get("reserved_by")
+                    .and_then(|v| v.as_string())
+                    .map(str::to_string),
+                last_audio_ms: dict
+                    .get("last_audio_ms")
+                    .and_then(|v| v.as_unsigned_integer()),
+            })
+        })
+        .collect())
+}
+
+/// Solo the pairs at `solo` and release those at `unsolo`, after releasing every solo
+/// when `clear` is set.
+pub fn send_solo(
+    device_id: AudioObjectID,
+    clear: bool,
+    solo: &[u32],
+    unsolo: &[u32],
+) -> Result<(), String> {
+    let offsets = |offsets: &[u32]| {
+        Value::Array(
+            offsets
+                .iter()
+                .map(|&offset| Value::from(u64::from(offset)))
+                .collect(),
+        )
+    };
+    let mut dict = plist::Dictionary::new();
+    if clear {
+        dict.insert("clear".into(), Value::from(true));
+    }
+    dict.insert("solo".into(), offsets(solo));
+    dict.insert("unsolo".into(), offsets(unsolo));
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_SOLO,
+        "solo",
+        Value::Dictionary(dict),
+    )
+}
+
+/// Replace the capture allowlist and switch it on or off.
+pub fn send_capture_allowlist(
+    device_id: AudioObjectID,
+    allowlist: &CaptureAllowlist,
+) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    dict.insert("enabled".into(), Value::from(allowlist.enabled));
+    dict.insert(
+        "bundle_ids".into(),
+        Value::Array(
+            allowlist
+                .bundle_ids
+                .iter()
+                .map(|id| Value::from(id.as_str()))
+                .collect(),
+        ),
+    );
+    dict.insert(
+        "pids".into(),
+        Value::Array(
+            allowlist
+                .pids
+                .iter()
+                .map(|&pid| Value::from(i64::from(pid)))
+                .collect(),
+        ),
+    );
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_CAPTURE_ALLOWLIST,
+        "capl",
+        Value::Dictionary(dict),
+    )
+}
+
+pub fn fetch_capture_allowlist(device_id: AudioObjectID) -> Result<CaptureAllowlist, String> {
+    let Some(Value::Dictionary(dict)) =
+        fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_CAPTURE_ALLOWLIST, "capl")?
+    else {
+        return Ok(CaptureAllowlist::default());
+    };
+    let items = |key: &str| {
+        dict.get(key)
+            .and_then(|v| v.as_array())
+            .cloned()
+            .unwrap_or_default()
+    };
+    Ok(CaptureAllowlist {
+        enabled: dict
+            .get("enabled")
+            .and_then(|v| v.as_boolean())
+            .unwrap_or(false),
+        bundle_ids: items("bundle_ids")
+            .iter()
+            .filter_map(|v| v.as_string().map(str::to_string))
+            .collect(),
+        pids: items("pids")
+            .iter()
+            .filter_map(|v| v.as_signed_integer())
+            .map(|pid| pid as i32)
+            .collect(),
+    })
+}
+
+/// Let capture clients of `pid` read only the channels in `mask` (bit n for channel
+/// n + 1); None lifts the mask.
+pub fn send_capture_mask(
+    device_id: AudioObjectID,
+    pid: i32,
+    mask: Option<u64>,
+) -> Result<(), String> {
+    let mut dict = plist::Dictionary::new();
+    dict.insert("pid".into(), Value::from(i64::from(pid)));
+    if let Some(mask) = mask {
+        dict.insert("mask".into(), Value::from(mask));
+    }
+    send_plist_property(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_CAPTURE_MASK,
+        "cmsk",
+        Value::Array(vec![Value::Dictionary(dict)]),
+    )
+}
+
+/// (pid, channel mask) of every capture process with a mask.
+pub fn fetch_capture_masks(device_id: AudioObjectID) -> Result<Vec<(i32, u64)>, String> {
+    let Some(Value::Array(items)) =
+        fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_CAPTURE_MASK, "cmsk")?
+    else {
+        return Ok(Vec::new());
+    };
+    Ok(items
+        .iter()
+        .filter_map(|item| {
+            let dict = item.as_dictionary()?;
+            let pid = dict.get("pid")?.as_signed_integer()? as i32;
+            let mask = dict.get("mask")?.as_unsigned_integer()?;
+            Some((pid, mask))
+        })
+        .collect())
+}
+
+/// The driver's last routing change; None before any client moved.
+pub fn fetch_route_change(device_id: AudioObjectID) -> Result<Option<RouteChange>, String> {
+    let Some(Value::Dictionary(dict)) =
+        fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_ROUTE_EVENT, "revt")?
+    else {
+        return Ok(None);
+    };
+    let number = |key: &str| dict.get(key).and_then(|v| v.as_unsigned_integer());
+    let seq = number("seq").unwrap_or(0);
+    if seq == 0 {
+        return Ok(None);
+    }
+    Ok(Some(RouteChange {
+        seq,
+        pid: dict
+            .get("pid")
+            .and_then(|v| v.as_signed_integer())
+            .unwrap_or(0) as i32,
+        client_id: number("client_id").unwrap_or(0) as u32,
+        old_offset: number("old_offset").unwrap_or(0) as u32,
+        new_offset: number("new_offset").unwrap_or(0) as u32,
+        reason: dict
+            .get("reason")
+            .and_then(|v| v.as_string())
+            .unwrap_or_default()
+            .to_string(),
+    }))
+}
+
+/// Bundle IDs the driver keeps out of the loopback (Driver.plist capture_exclude).
+pub fn fetch_capture_exclusions(device_id: AudioObjectID) -> Result<Vec<String>, String> {
+    let Some(Value::Array(items)) =
+        fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_CAPTURE_EXCLUSIONS, "cexc")?
+    else {
+        return Ok(Vec::new());
+    };
+    Ok(items
+        .iter()
+        .filter_map(|item| item.as_string().map(str::to_string))
+        .collect())
+}
+
+/// Channel offsets of the soloed pairs.
+pub fn fetch_solo(device_id: AudioObjectID) -> Result<Vec<u32>, String> {
+    let Some(Value::Array(items)) =
+        fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_SOLO, "solo")?
+    else {
+        return Ok(Vec::new());
+    };
+    Ok(items
+        .iter()
+        .filter_map(|item| item.as_unsigned_integer())
+        .map(|offset| offset as u32)
+        .collect())
+}
+
+pub fn fetch_monitor(device_id: AudioObjectID) -> Result<MonitorState, String> {
+    let Some(Value::Dictionary(dict)) =
+        fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_MONITOR, "mnsd")?
+    else {
+        return Ok(MonitorState::default());
+    };
+    let sends = dict
+        .get("sends")
+        .and_then(Value::as_array)
+        .map(|sends| {
+            sends
+                .iter()
+                .filter_map(|send| {
+                    let send = send.as_dictionary()?;
+                    Some((
+                        send.get("channel_offset")?.as_unsigned_integer()? as u32,
+                        send.get("gain")?.as_real()? as f32,
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(MonitorState {
+        enabled: dict
+            .get("enabled")
+            .and_then(Value::as_boolean)
+            .unwrap_or(false),
+        sends,
+    })
+}
+
+fn send_plist_property(
+    device_id: AudioObjectID,
+    selector: AudioObjectPropertySelector,
+    name: &str,
+    value: Value,
+) -> Result<(), String> {
+    let mut buf = Vec::new();
+    plist::to_writer_binary(&mut buf, &value)
+        .map_err(|err| format!("Failed to encode '{}' payload: {}", name, err))?;
+
+    let address = AudioObjectPropertyAddress {
+        mSelector: selector,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+
+    let cfdata = CFData::from_buffer(&buf);
+    let cfdata_ref = cfdata.as_concrete_TypeRef();
+    let status = unsafe {
+        AudioObjectSetPropertyData(
+            device_id,
+            &address,
+            0,
+            ptr::null(),
+            mem::size_of::<CFDataRef>() as u32,
+            &cfdata_ref as *const _ as *const c_void,
+        )
+    };
+
+    if status == 0 {
+        Ok(())
+    } else {
+        Err(set_property_error(name, status))
+    }
+}
+
+// A routing write refused because of a reservation gets its own message, so callers can
+// tell it from a malformed request.
+fn set_property_error(name: &str, status: i32) -> String {
+    if status == PRISM_BUS_RESERVED_STATUS {
+        return format!(
+            "'{}' refused: the pair is reserved by another owner (see `prism reserve`)",
+            name
+        );
+    }
+    format!(
+        "AudioObjectSetPropertyData('{}') failed with status {}",
+        name, status
+    )
 }
 
-#[allow(dead_code)]
-#[derive(Clone, Debug, Default)]
-pub struct CustomPropertyInfo {
-    pub selector: u32,
-    pub property_data_type: u32,
-    pub qualifier_data_type: u32,
+pub fn fetch_capture_rules(device_id: AudioObjectID) -> Result<Vec<CaptureRuleEntry>, String> {
+    let Some(Value::Array(items)) =
+        fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_CAPTURE_ACL, "cact")?
+    else {
+        return Ok(Vec::new());
+    };
+
+    Ok(items
+        .into_iter()
+        .filter_map(|item| {
+            let dict = item.into_dictionary()?;
+            Some(CaptureRuleEntry {
+                channel_offset: dict.get("channel_offset")?.as_unsigned_integer()? as u32,
+                bundle_id: dict.get("bundle_id")?.as_string()?.to_string(),
+            })
+        })
+        .collect())
 }
 
-#[allow(dead_code)]
-pub fn send_rout_update(device_id: AudioObjectID, pid: i32, offset: u32) -> Result<(), String> {
-    let update = PrismRoutingUpdate {
-        pid,
-        channel_offset: offset,
+// Per-channel input trim, served by the driver's volume control for `channel` (1-based).
+// Goes through the HAL's device-level volume property so it behaves exactly like
+// the channel sliders in Audio MIDI Setup.
+pub fn get_channel_trim_db(device_id: AudioObjectID, channel: u32) -> Result<f32, String> {
+    let address = AudioObjectPropertyAddress {
+        mSelector: kAudioDevicePropertyVolumeDecibels,
+        mScope: kAudioObjectPropertyScopeInput,
+        mElement: channel,
+    };
+
+    let mut data_size = mem::size_of::<f32>() as u32;
+    let mut db: f32 = 0.0;
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            device_id,
+            &address,
+            0,
+            ptr::null(),
+            &mut data_size,
+            &mut db as *mut _ as *mut _,
+        )
     };
 
+    if status == 0 {
+        Ok(db)
+    } else {
+        Err(format!(
+            "reading volume of input channel {} failed with status {}",
+            channel, status
+        ))
+    }
+}
+
+/// Number of input channels on the device, from its input stream configuration.
+/// False once the HAL has torn the device down (driver unloaded, coreaudiod restarted).
+pub fn device_is_alive(device_id: AudioObjectID) -> bool {
     let address = AudioObjectPropertyAddress {
-        mSelector: K_AUDIO_PRISM_PROPERTY_ROUTING_TABLE,
+        mSelector: kAudioDevicePropertyDeviceIsAlive,
         mScope: kAudioObjectPropertyScopeGlobal,
         mElement: kAudioObjectPropertyElementMaster,
     };
 
-    let mut buf: Vec<u8> = Vec::with_capacity(mem::size_of::<PrismRoutingUpdate>());
-    buf.extend_from_slice(&update.pid.to_le_bytes());
-    buf.extend_from_slice(&update.channel_offset.to_le_bytes());
+    let mut alive: u32 = 0;
+    let mut data_size = mem::size_of::<u32>() as u32;
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            device_id,
+            &address,
+            0,
+            ptr::null(),
+            &mut data_size,
+            &mut alive as *mut _ as *mut c_void,
+        )
+    };
+    status == 0 && alive != 0
+}
+
+pub fn input_channel_count(device_id: AudioObjectID) -> Result<u32, String> {
+    channel_count(device_id, kAudioObjectPropertyScopeInput)
+}
+
+/// Channels across all of a device's streams in `scope` (input or output).
+pub fn channel_count(
+    device_id: AudioObjectID,
+    scope: AudioObjectPropertyScope,
+) -> Result<u32, String> {
+    let address = AudioObjectPropertyAddress {
+        mSelector: kAudioDevicePropertyStreamConfiguration,
+        mScope: scope,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+
+    let mut data_size: u32 = 0;
+    let status = unsafe {
+        AudioObjectGetPropertyDataSize(device_id, &address, 0, ptr::null(), &mut data_size)
+    };
+    if status != 0 {
+        return Err(format!(
+            "AudioObjectGetPropertyDataSize(StreamConfiguration) failed with status {}",
+            status
+        ));
+    }
+
+    // AudioBufferList is variable length; back it with u64s for alignment
+    let mut storage = vec![0u64; (data_size as usize).div_ceil(mem::size_of::<u64>())];
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            device_id,
+            &address,
+            0,
+            ptr::null(),
+            &mut data_size,
+            storage.as_mut_ptr() as *mut c_void,
+        )
+    };
+    if status != 0 {
+        return Err(format!(
+            "AudioObjectGetPropertyData(StreamConfiguration) failed with status {}",
+            status
+        ));
+    }
+
+    let list = storage.as_ptr() as *const AudioBufferList;
+    let channels = unsafe {
+        let buffers = ptr::addr_of!((*list).mBuffers) as *const AudioBuffer;
+        (0..(*list).mNumberBuffers as usize)
+            .map(|i| (*buffers.add(i)).mNumberChannels)
+            .sum()
+    };
+    Ok(channels)
+}
+
+/// Where channel `channel` of an IOProc's buffer list lives: the buffer's samples, the
+/// channel's index within a frame, the frame stride and the frame count.
+///
+/// # Safety
+/// `list` must point to a valid AudioBufferList, as the HAL passes to an IOProc.
+pub unsafe fn locate_channel(
+    list: *const AudioBufferList,
+    channel: u32,
+) -> Option<(*mut f32, usize, usize, usize)> {
+    let buffers = ptr::addr_of!((*list).mBuffers) as *const AudioBuffer;
+    let mut first = 0;
+    for i in 0..(*list).mNumberBuffers as usize {
+        let buffer = &*buffers.add(i);
+        let channels = buffer.mNumberChannels;
+        if channel < first + channels {
+            if buffer.mData.is_null() {
+                return None;
+            }
+            let stride = channels as usize;
+            let frames = buffer.mDataByteSize as usize / (4 * stride);
+            return Some((
+                buffer.mData as *mut f32,
+                (channel - first) as usize,
+                stride,
+                frames,
+            ));
+        }
+        first += channels;
+    }
+    None
+}
+
+pub fn set_channel_trim_db(device_id: AudioObjectID, channel: u32, db: f32) -> Result<(), String> {
+    let address = AudioObjectPropertyAddress {
+        mSelector: kAudioDevicePropertyVolumeDecibels,
+        mScope: kAudioObjectPropertyScopeInput,
+        mElement: channel,
+    };
 
-    let cfdata = CFData::from_buffer(&buf);
-    let cfdata_ref = cfdata.as_concrete_TypeRef();
     let status = unsafe {
         AudioObjectSetPropertyData(
             device_id,
             &address,
             0,
             ptr::null(),
-            mem::size_of::<CFDataRef>() as u32,
-            &cfdata_ref as *const _ as *const c_void,
+            mem::size_of::<f32>() as u32,
+            &db as *const f32 as *const c_void,
         )
     };
 
@@ -61,21 +1570,77 @@ pub fn send_rout_update(device_id: AudioObjectID, pid: i32, offset: u32) -> Resu
         Ok(())
     } else {
         Err(format!(
-            "AudioObjectSetPropertyData failed with status {}",
-            status
+            "setting volume of input channel {} failed with status {}",
+            channel, status
         ))
     }
 }
 
 pub fn fetch_client_list(device_id: AudioObjectID) -> Result<Vec<ClientEntry>, String> {
+    match fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_CLIENT_LIST, "clnt")? {
+        Some(value) => Ok(parse_client_list_value(value)),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Fetch only the clients of `pid` and/or `bundle_id` (an ID covers its helpers); the
+/// driver filters the list, so the reply stays small however many clients there are.
+pub fn fetch_client_list_matching(
+    device_id: AudioObjectID,
+    pid: Option<i32>,
+    bundle_id: Option<&str>,
+) -> Result<Vec<ClientEntry>, String> {
+    let mut qualifier = plist::Dictionary::new();
+    if let Some(pid) = pid {
+        qualifier.insert("pid".into(), Value::from(i64::from(pid)));
+    }
+    if let Some(bundle_id) = bundle_id {
+        qualifier.insert("bundle_id".into(), Value::from(bundle_id));
+    }
+    let value = fetch_plist_property_qualified(
+        device_id,
+        K_AUDIO_PRISM_PROPERTY_CLIENT_LIST,
+        "clnt",
+        Some(Value::Dictionary(qualifier)),
+    )?;
+    Ok(value.map(parse_client_list_value).unwrap_or_default())
+}
+
+/// Fetch and clear the driver's pending change mask (`PRISM_EVENT_*` bits).
+pub fn fetch_device_events(device_id: AudioObjectID) -> Result<u32, String> {
+    let events = fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_EVENTS, "evnt")?
+        .and_then(|value| value.into_dictionary())
+        .and_then(|dict| dict.get("events")?.as_unsigned_integer())
+        .unwrap_or(0);
+    Ok(events as u32)
+}
+
+pub fn fetch_io_stats(device_id: AudioObjectID) -> Result<IoStats, String> {
+    match fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_IO_STATS, "stat")? {
+        Some(value) => Ok(parse_io_stats_value(value)),
+        None => Ok(IoStats::default()),
+    }
+}
+
+/// Fetch per-bus levels accumulated since the previous read. Buses without
+/// audio in that interval are omitted.
+pub fn fetch_meters(device_id: AudioObjectID) -> Result<Vec<BusLevel>, String> {
+    match fetch_plist_property(device_id, K_AUDIO_PRISM_PROPERTY_METERS, "metr")? {
+        Some(value) => Ok(parse_meters_value(value)),
+        None => Ok(Vec::new()),
+    }
+}
+
+// Name of the driver's shared-memory telemetry region, for shm_open.
+pub fn fetch_telemetry_name(device_id: AudioObjectID) -> Result<String, String> {
     let address = AudioObjectPropertyAddress {
-        mSelector: K_AUDIO_PRISM_PROPERTY_CLIENT_LIST,
+        mSelector: K_AUDIO_PRISM_PROPERTY_TELEMETRY,
         mScope: kAudioObjectPropertyScopeGlobal,
         mElement: kAudioObjectPropertyElementMaster,
     };
 
-    let mut data_size = mem::size_of::<CFDataRef>() as u32;
-    let mut cfdata_ref: CFDataRef = ptr::null();
+    let mut data_size = mem::size_of::<CFStringRef>() as u32;
+    let mut name_ref: CFStringRef = ptr::null();
     let status = unsafe {
         AudioObjectGetPropertyData(
             device_id,
@@ -83,28 +1648,96 @@ pub fn fetch_client_list(device_id: AudioObjectID) -> Result<Vec<ClientEntry>, S
             0,
             ptr::null(),
             &mut data_size,
-            &mut cfdata_ref as *mut _ as *mut _,
+            &mut name_ref as *mut _ as *mut _,
         )
     };
 
     if status != 0 {
         return Err(format!(
-            "AudioObjectGetPropertyData('clnt') failed with status {}",
+            "AudioObjectGetPropertyData('tlmy') failed with status {}",
             status
         ));
     }
+    if name_ref.is_null() {
+        return Err("driver returned no telemetry region name".to_string());
+    }
+
+    let name = unsafe { CFString::wrap_under_create_rule(name_ref) };
+    Ok(name.to_string())
+}
+
+// Read a CFData-wrapped binary plist custom property from the device.
+fn fetch_plist_property(
+    device_id: AudioObjectID,
+    selector: AudioObjectPropertySelector,
+    label: &str,
+) -> Result<Option<Value>, String> {
+    fetch_plist_property_qualified(device_id, selector, label, None)
+}
+
+// As fetch_plist_property, passing `qualifier` to the driver as a CFData-wrapped binary
+// plist.
+fn fetch_plist_property_qualified(
+    device_id: AudioObjectID,
+    selector: AudioObjectPropertySelector,
+    label: &str,
+    qualifier: Option<Value>,
+) -> Result<Option<Value>, String> {
+    let qualifier = match qualifier {
+        Some(value) => {
+            let mut buf = Vec::new();
+            plist::to_writer_binary(&mut buf, &value)
+                .map_err(|err| format!("Failed to encode '{}' qualifier: {}", label, err))?;
+            Some(CFData::from_buffer(&buf))
+        }
+        None => None,
+    };
+    let qualifier_ref = qualifier.as_ref().map(|data| data.as_concrete_TypeRef());
+    let (qualifier_size, qualifier_ptr) = match &qualifier_ref {
+        Some(data_ref) => (
+            mem::size_of::<CFDataRef>() as u32,
+            data_ref as *const CFDataRef as *const c_void,
+        ),
+        None => (0, ptr::null()),
+    };
+
+    let address = AudioObjectPropertyAddress {
+        mSelector: selector,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+
+    let mut data_size = mem::size_of::<CFDataRef>() as u32;
+    let mut cfdata_ref: CFDataRef = ptr::null();
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            device_id,
+            &address,
+            qualifier_size,
+            qualifier_ptr,
+            &mut data_size,
+            &mut cfdata_ref as *mut _ as *mut _,
+        )
+    };
+
+    if status != 0 {
+        return Err(format!(
+            "AudioObjectGetPropertyData('{}') failed with status {}",
+            label, status
+        ));
+    }
 
     if cfdata_ref.is_null() {
-        return Ok(Vec::new());
+        return Ok(None);
     }
 
     let cfdata = unsafe { CFData::wrap_under_create_rule(cfdata_ref) };
     let bytes = cfdata.bytes();
     let mut cursor = Cursor::new(bytes);
     let value = Value::from_reader(&mut cursor)
-        .map_err(|err| format!("Failed to parse client list plist: {}", err))?;
+        .map_err(|err| format!("Failed to parse '{}' plist: {}", label, err))?;
 
-    Ok(parse_client_list_value(value))
+    Ok(Some(value))
 }
 
 pub fn parse_client_list_value(value: Value) -> Vec<ClientEntry> {
@@ -125,10 +1758,22 @@ pub fn parse_client_list_value(value: Value) -> Vec<ClientEntry> {
                         .get("channel_offset")
                         .and_then(|v| v.as_unsigned_integer())
                         .unwrap_or(0) as u32;
+                    let bundle_id = dict
+                        .get("bundle_id")
+                        .and_then(|v| v.as_string())
+                        .map(str::to_string);
+                    let is_running = dict
+                        .get("is_running")
+                        .and_then(|v| v.as_boolean())
+                        .unwrap_or(false);
+                    let gain = dict.get("gain").and_then(|v| v.as_real()).unwrap_or(1.0) as f32;
                     Some(ClientEntry {
                         pid,
                         client_id,
                         channel_offset,
+                        bundle_id,
+                        is_running,
+                        gain,
                     })
                 }
                 _ => None,
@@ -138,6 +1783,77 @@ pub fn parse_client_list_value(value: Value) -> Vec<ClientEntry> {
     }
 }
 
+pub fn parse_meters_value(value: Value) -> Vec<BusLevel> {
+    let Value::Array(items) = value else {
+        return Vec::new();
+    };
+
+    items
+        .into_iter()
+        .filter_map(|item| {
+            let dict = item.into_dictionary()?;
+            let level = |key: &str| dict.get(key).and_then(|v| v.as_real()).unwrap_or(0.0) as f32;
+            Some(BusLevel {
+                pair: dict.get("pair")?.as_unsigned_integer()? as u32,
+                peak: [level("peak_l"), level("peak_r")],
+                rms: [level("rms_l"), level("rms_r")],
+            })
+        })
+        .collect()
+}
+
+pub fn parse_io_stats_value(value: Value) -> IoStats {
+    let Value::Dictionary(dict) = value else {
+        return IoStats::default();
+    };
+    let counter = |key: &str| {
+        dict.get(key)
+            .and_then(|v| v.as_unsigned_integer())
+            .unwrap_or(0)
+    };
+
+    IoStats {
+        cycles_served: counter("cycles_served"),
+        output_cycles: counter("output_cycles"),
+        wrap_arounds: counter("wrap_arounds"),
+        stale_reads: counter("stale_reads"),
+        overloads: counter("overloads"),
+        watchdog_clears: counter("watchdog_clears"),
+        late_cycles: counter("late_cycles"),
+        missed_writes: counter("missed_writes"),
+        frames_written: dict
+            .get("frames_written")
+            .and_then(|v| v.as_array())
+            .map(|items| {
+                items
+                    .iter()
+                    .map(|v| v.as_unsigned_integer().unwrap_or(0))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        slot_table_size: counter("slot_table_size"),
+        slots_occupied: counter("slots_occupied"),
+        slot_collisions: counter("slot_collisions"),
+        slot_table_growths: counter("slot_table_growths"),
+        readers: dict
+            .get("readers")
+            .and_then(|v| v.as_array())
+            .map(|items| items.iter().filter_map(parse_capture_reader).collect())
+            .unwrap_or_default(),
+    }
+}
+
+fn parse_capture_reader(value: &Value) -> Option<CaptureReader> {
+    let dict = value.as_dictionary()?;
+    Some(CaptureReader {
+        client_id: dict.get("client_id")?.as_unsigned_integer()? as u32,
+        pid: dict.get("pid")?.as_signed_integer()? as i32,
+        last_read_time: dict.get("last_read_time")?.as_real()?,
+        reads: dict.get("reads")?.as_unsigned_integer()?,
+        active: dict.get("active")?.as_boolean()?,
+    })
+}
+
 #[allow(dead_code)]
 pub fn read_custom_property_info(
     device_id: AudioObjectID,
@@ -223,7 +1939,97 @@ pub fn fourcc_to_string_from_le(value: u32) -> String {
     std::str::from_utf8(&bytes).unwrap_or("????").to_string()
 }
 
-pub fn find_prism_device() -> Result<AudioObjectID, String> {
+/// The Prism plug-in object, resolved by bundle ID so it works without the device.
+pub fn find_prism_plugin() -> Result<AudioObjectID, String> {
+    let address = AudioObjectPropertyAddress {
+        mSelector: K_AUDIO_HARDWARE_PROPERTY_TRANSLATE_BUNDLE_ID_TO_PLUGIN,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    let bundle_id = CFString::new(PRISM_PLUGIN_BUNDLE_ID);
+    let bundle_ref = bundle_id.as_concrete_TypeRef();
+    let mut plugin_id: AudioObjectID = kAudioObjectUnknown;
+    let mut data_size = mem::size_of::<AudioObjectID>() as u32;
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            kAudioObjectSystemObject,
+            &address,
+            mem::size_of::<CFStringRef>() as u32,
+            &bundle_ref as *const _ as *const c_void,
+            &mut data_size,
+            &mut plugin_id as *mut _ as *mut c_void,
+        )
+    };
+
+    if status != 0 {
+        return Err(format!("Error resolving the Prism plug-in: {}", status));
+    }
+    if plugin_id == kAudioObjectUnknown {
+        return Err("Prism plug-in is not loaded".to_string());
+    }
+    Ok(plugin_id)
+}
+
+pub fn fetch_plugin_info(plugin_id: AudioObjectID) -> Result<PluginInfo, String> {
+    let Some(dict) = fetch_plist_property(plugin_id, K_AUDIO_PRISM_PROPERTY_PLUGIN_INFO, "pinf")?
+        .and_then(|value| value.into_dictionary())
+    else {
+        return Err("driver returned no 'pinf' dictionary".to_string());
+    };
+    let string = |key: &str| {
+        dict.get(key)
+            .and_then(|v| v.as_string())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let devices = dict
+        .get("devices")
+        .and_then(|v| v.as_array())
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| {
+                    let device = item.as_dictionary()?;
+                    Some(PluginDevice {
+                        device_id: device.get("device_id")?.as_unsigned_integer()? as u32,
+                        uid: device.get("uid")?.as_string()?.to_string(),
+                        name: device.get("name")?.as_string()?.to_string(),
+                        channels: device.get("channels")?.as_unsigned_integer()? as u32,
+                        clients: device.get("clients")?.as_unsigned_integer()? as u32,
+                        io_running: device.get("io_running")?.as_boolean()?,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(PluginInfo {
+        version: string("version"),
+        build: string("build"),
+        debug: dict
+            .get("debug")
+            .and_then(|v| v.as_boolean())
+            .unwrap_or(false),
+        features: dict
+            .get("features")
+            .and_then(|v| v.as_array())
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| item.as_string().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        devices,
+        total_clients: dict
+            .get("total_clients")
+            .and_then(|v| v.as_unsigned_integer())
+            .unwrap_or(0) as u32,
+    })
+}
+
+// Every device the HAL publishes.
+fn device_ids() -> Result<Vec<AudioObjectID>, String> {
     let address = AudioObjectPropertyAddress {
         mSelector: kAudioHardwarePropertyDevices,
         mScope: kAudioObjectPropertyScopeGlobal,
@@ -266,10 +2072,32 @@ pub fn find_prism_device() -> Result<AudioObjectID, String> {
     if status != 0 {
         return Err(format!("Error getting device list: {}", status));
     }
+    Ok(device_ids)
+}
+
+/// A device by UID or (case-insensitive) name, Prism included.
+pub fn find_device(query: &str) -> Result<AudioObjectID, String> {
+    for device_id in device_ids()? {
+        let matches = get_device_uid(device_id).is_some_and(|uid| uid == query)
+            || get_device_name(device_id).is_some_and(|name| name.eq_ignore_ascii_case(query));
+        if matches {
+            return Ok(device_id);
+        }
+    }
+    Err(format!("no audio device named '{}'", query))
+}
+
+/// Display name of a device, falling back to its UID and then its ID.
+pub fn device_name(device_id: AudioObjectID) -> String {
+    get_device_name(device_id)
+        .or_else(|| get_device_uid(device_id))
+        .unwrap_or_else(|| format!("device {}", device_id))
+}
 
-    for device_id in device_ids {
+pub fn find_prism_device() -> Result<AudioObjectID, String> {
+    for device_id in device_ids()? {
         if let Some(uid) = get_device_uid(device_id) {
-            if uid == "dev.ichigo.driver.Prism.Device" {
+            if uid == PRISM_DEVICE_UID {
                 return Ok(device_id);
             }
         }
@@ -278,6 +2106,111 @@ pub fn find_prism_device() -> Result<AudioObjectID, String> {
     Err("Prism device not found".to_string())
 }
 
+/// Every audio device other than Prism, with the clock domain it reports.
+pub fn list_audio_devices() -> Result<Vec<AudioDeviceEntry>, String> {
+    let mut devices = Vec::new();
+    for device_id in device_ids()? {
+        let Some(uid) = get_device_uid(device_id) else {
+            continue;
+        };
+        if uid == PRISM_DEVICE_UID {
+            continue;
+        }
+        devices.push(AudioDeviceEntry {
+            device_id,
+            name: get_device_name(device_id).unwrap_or_else(|| uid.clone()),
+            uid,
+            clock_domain: get_clock_domain(device_id),
+        });
+    }
+    Ok(devices)
+}
+
+fn get_device_name(device_id: AudioObjectID) -> Option<String> {
+    let address = AudioObjectPropertyAddress {
+        mSelector: kAudioObjectPropertyName,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+
+    let mut data_size = mem::size_of::<CFStringRef>() as u32;
+    let mut name_ref: CFStringRef = ptr::null();
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            device_id,
+            &address,
+            0,
+            ptr::null(),
+            &mut data_size,
+            &mut name_ref as *mut _ as *mut _,
+        )
+    };
+    if status != 0 || name_ref.is_null() {
+        return None;
+    }
+    unsafe { Some(CFString::wrap_under_create_rule(name_ref).to_string()) }
+}
+
+// 0 if the device reports no clock domain.
+fn get_clock_domain(device_id: AudioObjectID) -> u32 {
+    device_u32_property(
+        device_id,
+        K_AUDIO_DEVICE_PROPERTY_CLOCK_DOMAIN,
+        kAudioObjectPropertyScopeGlobal,
+    )
+    .unwrap_or(0)
+}
+
+/// A UInt32 device property such as kAudioDevicePropertyLatency, or None if the
+/// device does not have it in `scope`.
+pub fn device_u32_property(
+    device_id: AudioObjectID,
+    selector: AudioObjectPropertySelector,
+    scope: AudioObjectPropertyScope,
+) -> Option<u32> {
+    let address = AudioObjectPropertyAddress {
+        mSelector: selector,
+        mScope: scope,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+
+    let mut value: u32 = 0;
+    let mut data_size = mem::size_of::<u32>() as u32;
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            device_id,
+            &address,
+            0,
+            ptr::null(),
+            &mut data_size,
+            &mut value as *mut _ as *mut c_void,
+        )
+    };
+    (status == 0).then_some(value)
+}
+
+pub fn device_sample_rate(device_id: AudioObjectID) -> Option<f64> {
+    let address = AudioObjectPropertyAddress {
+        mSelector: kAudioDevicePropertyNominalSampleRate,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+
+    let mut rate: f64 = 0.0;
+    let mut data_size = mem::size_of::<f64>() as u32;
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            device_id,
+            &address,
+            0,
+            ptr::null(),
+            &mut data_size,
+            &mut rate as *mut _ as *mut c_void,
+        )
+    };
+    (status == 0 && rate > 0.0).then_some(rate)
+}
+
 fn get_device_uid(device_id: AudioObjectID) -> Option<String> {
     let address = AudioObjectPropertyAddress {
         mSelector: kAudioDevicePropertyDeviceUID,
@@ -316,3 +2249,8 @@ struct PrismRoutingUpdate {
     pid: i32,
     channel_offset: u32,
 }
+
+/// 'rout' channel_offset that unroutes instead of routing.
+pub const PRISM_UNROUTE_OFFSET: u32 = u32::MAX;
+/// Status of a write refused because another owner holds the pair through 'resv'.
+pub const PRISM_BUS_RESERVED_STATUS: i32 = 0x72737664; // 'rsvd'
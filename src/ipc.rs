@@ -4,22 +4,579 @@ use serde::{Deserialize, Serialize};
 #[serde(tag = "command", rename_all = "snake_case")]
 pub enum CommandRequest {
     Help,
+    /// Liveness probe for supervisors; answered even while the device is gone.
+    Health,
+    /// What prismd is keeping track of, including its last routing drift check; needs
+    /// no device.
+    Status,
+    /// Driver build and device summary from the plug-in object; needs no device.
+    DriverInfo,
     Clients,
     List,
+    /// Route one pid, or every client when `pid` is -1. A broadcast leaves the pids and
+    /// bundle IDs in `exclude` (and prismd's own clients) where they are.
     Set {
         pid: i32,
         #[serde(alias = "channel_offset")]
         offset: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        gain_db: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pan: Option<f32>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        exclude: Vec<String>,
+        /// Reservation owner to route as, so a pair it reserved ('resv') accepts the route.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        owner: Option<String>,
+    },
+    /// Return the clients of `pid` (every client for -1) to the unassigned state: pair 0,
+    /// unity mix, no matrix, with their old pair cleared.
+    Unset {
+        pid: i32,
     },
     Apps,
+    Stats,
+    /// Structured diagnostics: which capture clients are reading and where.
+    Diag,
+    Meters,
+    /// Downsampled peak history of the last `seconds`, per app.
+    MeterHistory {
+        seconds: u64,
+    },
+    /// Route every client of an app. `app_name` may also be `type:<content type>` to
+    /// route every app of that type.
     SetApp {
         app_name: String,
         offset: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        gain_db: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pan: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        owner: Option<String>,
+    },
+    /// Restrict who may capture a pair. `bundle_id: None` lifts the restriction.
+    CaptureAllow {
+        offset: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bundle_id: Option<String>,
+    },
+    CaptureRules,
+    /// Enable loudness AGC for an app. `target_lufs: None` turns it off.
+    Agc {
+        app_name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        target_lufs: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        range_db: Option<f32>,
+    },
+    AgcStatus,
+    /// Set the input trim of one or more channels (1-based).
+    Trim {
+        channels: Vec<u32>,
+        gain_db: f32,
+    },
+    /// Channels whose input trim is not 0 dB.
+    Trims,
+    /// Re-anchor the driver timeline and clear its buffers.
+    Resync,
+    /// Allocated loopback rings and what they currently hold.
+    Buffers,
+    /// Zero the ring of one pair, or of every pair when `offset` is None.
+    Clear {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        offset: Option<u32>,
+    },
+    /// Set the stereo width of every client of an app (0.0 mono, 1.0 unchanged, up to 2.0).
+    Width {
+        app_name: String,
+        width: f32,
+    },
+    /// Set the balance (-1.0 left .. 1.0 right) of every client of an app, exchange its
+    /// left and right channels, or both. None leaves a setting as it is.
+    Balance {
+        app_name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        balance: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        swap: Option<bool>,
+    },
+    /// Fold every client of an app down to mono, summed into one channel of its pair,
+    /// or back to stereo with `MonoFold::Off`.
+    Mono {
+        app_name: String,
+        fold: MonoFold,
+    },
+    /// Also play every client of an app on a second pair, at `offset`, besides its own.
+    /// None stops mirroring.
+    Mirror {
+        app_name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        offset: Option<u32>,
+    },
+    /// Route each side of an app's stereo output to any bus channels instead of one
+    /// pair. No routes returns the app to its pair.
+    Matrix {
+        app_name: String,
+        #[serde(default)]
+        routes: Vec<MatrixRoutePayload>,
+    },
+    /// Clients routed by a matrix.
+    Matrices,
+    /// Claim a pair for `owner` in the driver, so routing writes from anyone else cannot
+    /// move clients onto it; `release` drops the claim. Only the owner may release.
+    Reserve {
+        offset: u32,
+        owner: String,
+        #[serde(default)]
+        release: bool,
+    },
+    /// Pairs claimed with `Reserve`.
+    Reservations,
+    /// Every bus pair: whether it is free, who plays on it and when it last had audio.
+    Buses,
+    /// Name the pair at `offset` ("Music", "Stream Mix"), or drop its name with None.
+    /// prismd saves the names and gives them back to the driver whenever it attaches.
+    BusLabel {
+        offset: u32,
+        #[serde(default)]
+        label: Option<String>,
+    },
+    /// Change the monitor downmix into 1-2. Without changes, reports it.
+    Monitor {
+        #[serde(default)]
+        enabled: Option<bool>,
+        #[serde(default)]
+        sends: Vec<MonitorSendPayload>,
+    },
+    /// Solo pairs in the driver: while any is soloed, captures hear silence on every other
+    /// pair. `clear` releases every solo before `solo` and `unsolo` apply. Answers with
+    /// the soloed pairs either way.
+    Solo {
+        #[serde(default)]
+        clear: bool,
+        #[serde(default)]
+        solo: Vec<u32>,
+        #[serde(default)]
+        unsolo: Vec<u32>,
+    },
+    /// Let capture clients of `pid` read only the pairs at `offsets`; the other channels
+    /// read as silence for them. `offsets: None` lifts the mask.
+    CaptureMask {
+        pid: i32,
+        #[serde(default)]
+        offsets: Option<Vec<u32>>,
+    },
+    /// Capture processes with a channel mask.
+    CaptureMasks,
+    /// Bundle IDs whose output the driver never copies into the loopback. Set in the
+    /// driver's Driver.plist only, so prismd can list them but not change them.
+    CaptureExclusions,
+    /// Change the capture allowlist: while enabled, only the listed processes read the
+    /// input stream. Entries are bundle IDs or PIDs; `revoke` applies before `allow`.
+    /// prismd keeps its own PID on the list for its bridges. Answers with the list.
+    CaptureList {
+        #[serde(default)]
+        enabled: Option<bool>,
+        #[serde(default)]
+        allow: Vec<String>,
+        #[serde(default)]
+        revoke: Vec<String>,
+    },
+    /// Choose what happens when another app is routed to an occupied pair.
+    PairPolicy {
+        offset: u32,
+        policy: ConflictPolicy,
+    },
+    /// Pairs whose conflict policy is not the default.
+    PairPolicies,
+    /// Choose how the driver covers a gap when a client on the pair misses a cycle.
+    /// `max_hold_ms` applies to `HoldLast` (DEFAULT_GAP_HOLD_MS when None).
+    GapPolicy {
+        offset: u32,
+        mode: GapMode,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_hold_ms: Option<u32>,
+    },
+    /// Pairs that hold the last audio over gaps instead of zero-filling.
+    GapPolicies,
+    /// Driver os_log level ("error", "info" or "debug"). `level: None` only reads it.
+    LogLevel {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        level: Option<String>,
+    },
+    /// Driver clock rate offset from the host clock in ppm. `ppm: None` only reads it.
+    ClockRate {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ppm: Option<f64>,
+    },
+    /// Per-scope latency and safety offset in frames. Fields left out keep their value.
+    Latency {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        input_latency: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        output_latency: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        input_safety_offset: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        output_safety_offset: Option<u32>,
+    },
+    /// Clock domain the Prism device reports. `device` (name or UID) copies that
+    /// device's domain, `domain` sets one directly (0 clears it); neither only reads it.
+    ClockDomain {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        domain: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        device: Option<String>,
+    },
+    /// Running bridges with the latency each adds.
+    Bridges,
+    /// Copy a pair of `source`'s input (Prism when None) into a pair of `target`'s
+    /// output. Devices are named by name or UID.
+    BridgeAdd {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        source: Option<String>,
+        source_offset: u32,
+        target: String,
+        #[serde(default)]
+        target_offset: u32,
+    },
+    BridgeRemove {
+        id: u32,
+    },
+    /// Output stream mode: stereo routed per client, or as wide as the bus and mapped
+    /// 1:1. `full_bus: None` only reads it.
+    OutputMode {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        full_bus: Option<bool>,
+    },
+    /// Pair the system mix (every app without a pair of its own) lands on, and whether
+    /// it is captured at all. Fields left as None are only read.
+    SystemMix {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        offset: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        enabled: Option<bool>,
+    },
+    /// Pair the driver gives clients before they are routed. `policy: None` only
+    /// reads it; a policy set here is saved by prismd and restored when it starts.
+    AutoAssign {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        policy: Option<AssignPolicy>,
+    },
+    /// Built-in routing presets and which one is active.
+    Presets,
+    /// Routing rules prismd applies to apps as they appear; `reload` reads the rules
+    /// file again first.
+    Rules {
+        #[serde(default)]
+        reload: bool,
+    },
+    /// Apply a built-in preset and keep routing its apps as they start.
+    /// `preset: None` turns the active preset off. The preset routes as `owner`, also
+    /// for apps that start later.
+    Quickstart {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        preset: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        owner: Option<String>,
+    },
+    /// Set the content type of a bundle ID (and its helpers), persisted by prismd.
+    /// `content_type: None` drops the override.
+    Classify {
+        bundle_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        content_type: Option<ContentType>,
+    },
+    /// Content types of the running apps, and the overrides set with `Classify`.
+    Classifications,
+    /// Shell completion candidates. Answered from the cached client list instead of a
+    /// fresh driver read, so completing stays fast.
+    Complete {
+        kind: CompletionKind,
     },
     Quit,
     Exit,
 }
 
+/// Field a mutating request may carry next to `command`. prismd answers a key it has
+/// seen within `IDEMPOTENCY_TTL_SECS` with the response it gave the first time, so a
+/// client can retry a request that timed out without applying it twice.
+pub const IDEMPOTENCY_KEY_FIELD: &str = "idempotency_key";
+pub const IDEMPOTENCY_TTL_SECS: u64 = 120;
+
+impl CommandRequest {
+    /// Whether the request may change driver or prismd state. Requests that only read
+    /// with some fields left out (`ClockRate`, `OutputMode`, ...) count as mutating.
+    pub fn is_mutating(&self) -> bool {
+        !matches!(
+            self,
+            CommandRequest::Help
+                | CommandRequest::Health
+                | CommandRequest::Status
+                | CommandRequest::DriverInfo
+                | CommandRequest::Clients
+                | CommandRequest::List
+                | CommandRequest::Apps
+                | CommandRequest::Stats
+                | CommandRequest::Diag
+                | CommandRequest::Meters
+                | CommandRequest::MeterHistory { .. }
+                | CommandRequest::CaptureRules
+                | CommandRequest::CaptureMasks
+                | CommandRequest::CaptureExclusions
+                | CommandRequest::AgcStatus
+                | CommandRequest::Trims
+                | CommandRequest::Buffers
+                | CommandRequest::Matrices
+                | CommandRequest::Reservations
+                | CommandRequest::Buses
+                | CommandRequest::PairPolicies
+                | CommandRequest::GapPolicies
+                | CommandRequest::Bridges
+                | CommandRequest::Presets
+                | CommandRequest::Rules { reload: false }
+                | CommandRequest::Classifications
+                | CommandRequest::Complete { .. }
+                | CommandRequest::Quit
+                | CommandRequest::Exit
+        )
+    }
+}
+
+/// What a `Complete` request lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionKind {
+    /// App names as `prism apps` shows them.
+    Apps,
+    /// Channel pairs (`1-2`, `3-4`, ...), described by the apps routed to them.
+    Pairs,
+    /// Built-in preset names.
+    Presets,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionPayload {
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// What prismd does when an app is routed to a pair another app already uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Refuse the new route.
+    Reject,
+    /// Both apps play on the pair (the driver's behaviour).
+    #[default]
+    Mix,
+    /// Move the apps already there to the system mix only.
+    Replace,
+    /// Keep the apps already there, lowered by `DUCK_DB` until the newcomers leave.
+    Duck,
+}
+
+/// What a pair plays when a client on it has not written the audio a cycle reads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GapMode {
+    /// Silence for the missing part (the default); what music capture expects.
+    #[default]
+    ZeroFill,
+    /// Repeat the newest audio the client wrote, for up to a maximum hold, so a short
+    /// hiccup does not cut out of a voice stream (and trip voice activity detection).
+    HoldLast,
+}
+
+impl GapMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            GapMode::ZeroFill => "zero-fill",
+            GapMode::HoldLast => "hold-last",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        [GapMode::ZeroFill, GapMode::HoldLast]
+            .into_iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Hold a hold-last pair applies when none is given, and the most it accepts.
+pub const DEFAULT_GAP_HOLD_MS: u32 = 20;
+pub const MAX_GAP_HOLD_MS: u32 = 200;
+
+/// Longest name a bus pair may be given.
+pub const MAX_BUS_LABEL_CHARS: usize = 32;
+
+/// How long a routing freeze lasts unless thawed sooner, and the longest one the driver
+/// accepts. The timeout keeps a writer that dies mid-batch from leaving routing frozen.
+pub const DEFAULT_ROUTING_FREEZE_MS: u32 = 2_000;
+pub const MAX_ROUTING_FREEZE_MS: u32 = 10_000;
+
+/// Channel offset of the null bus. A client routed there keeps playing but is heard in
+/// no capture and not in the system mix; `prism set <PID> null` sends it.
+pub const NULL_BUS_OFFSET: u32 = u32::MAX - 1;
+
+/// What kind of audio an app plays, for routing whole kinds of apps at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentType {
+    Music,
+    Voice,
+    Game,
+    System,
+}
+
+impl ContentType {
+    pub const ALL: [ContentType; 4] = [
+        ContentType::Music,
+        ContentType::Voice,
+        ContentType::Game,
+        ContentType::System,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ContentType::Music => "music",
+            ContentType::Voice => "voice",
+            ContentType::Game => "game",
+            ContentType::System => "system",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|content_type| content_type.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Which pair the driver gives a client that joins before prismd routes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AssignPolicy {
+    /// New clients stay on 1-2 until they are routed (passthrough).
+    Disabled,
+    /// The lowest free pair from 3-4 up.
+    FirstFree,
+    /// The next free pair after the one handed out last, so consecutive apps spread
+    /// across the bus instead of reusing the pair just vacated.
+    RoundRobin,
+    /// The pair the app's bundle ID last played on, else the lowest free pair.
+    StickyBundle,
+}
+
+impl AssignPolicy {
+    pub const ALL: [AssignPolicy; 4] = [
+        AssignPolicy::Disabled,
+        AssignPolicy::FirstFree,
+        AssignPolicy::RoundRobin,
+        AssignPolicy::StickyBundle,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AssignPolicy::Disabled => "disabled",
+            AssignPolicy::FirstFree => "first-free",
+            AssignPolicy::RoundRobin => "round-robin",
+            AssignPolicy::StickyBundle => "sticky-bundle",
+        }
+    }
+
+    /// A policy by name; "off" and "sticky" are accepted as short forms.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "off" => Some(AssignPolicy::Disabled),
+            "sticky" => Some(AssignPolicy::StickyBundle),
+            name => Self::ALL.into_iter().find(|policy| policy.name() == name),
+        }
+    }
+}
+
+/// Mono fold-down of a client: left and right summed at -3 dB into one channel of its
+/// pair, which leaves the other channel free for another client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MonoFold {
+    #[default]
+    Off,
+    Left,
+    Right,
+}
+
+impl MonoFold {
+    pub const ALL: [MonoFold; 3] = [MonoFold::Off, MonoFold::Left, MonoFold::Right];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MonoFold::Off => "off",
+            MonoFold::Left => "left",
+            MonoFold::Right => "right",
+        }
+    }
+
+    /// A fold by name; "stereo" is accepted for off, "l" and "r" for the sides.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "stereo" => Some(MonoFold::Off),
+            "l" => Some(MonoFold::Left),
+            "r" => Some(MonoFold::Right),
+            name => Self::ALL.into_iter().find(|fold| fold.name() == name),
+        }
+    }
+}
+
+/// Prefix of a `SetApp` target naming a content type instead of an app.
+pub const CONTENT_TYPE_PREFIX: &str = "type:";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClassificationPayload {
+    /// App display name; None for an override whose app is not running.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bundle_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<ContentType>,
+    /// Whether the type comes from `prism classify` rather than the app bundle.
+    #[serde(default)]
+    pub overridden: bool,
+}
+
+/// Gain applied to the previous occupants of a pair with `ConflictPolicy::Duck`.
+pub const DUCK_DB: f32 = -12.0;
+
+impl ConflictPolicy {
+    pub fn name(self) -> &'static str {
+        match self {
+            ConflictPolicy::Reject => "reject",
+            ConflictPolicy::Mix => "mix",
+            ConflictPolicy::Replace => "replace",
+            ConflictPolicy::Duck => "duck",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "reject" => Some(ConflictPolicy::Reject),
+            "mix" => Some(ConflictPolicy::Mix),
+            "replace" => Some(ConflictPolicy::Replace),
+            "duck" => Some(ConflictPolicy::Duck),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairPolicyPayload {
+    pub channel_offset: u32,
+    pub policy: ConflictPolicy,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RpcResponse<T> {
     pub status: String,
@@ -40,12 +597,475 @@ pub struct ClientInfoPayload {
     pub responsible_pid: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub responsible_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bundle_id: Option<String>,
+    /// Whether the client is between StartIO and StopIO.
+    #[serde(default)]
+    pub is_running: bool,
+    /// Current gain; a muted client reports `SILENCE_DB`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gain_db: Option<f32>,
+    /// What the client's app plays, when it is known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<ContentType>,
+    /// prismd has not resolved the client's process and app yet, so the names and
+    /// content type are missing; asking again shortly fills them in.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub identity_pending: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoutingUpdateAck {
     pub pid: i32,
     pub channel_offset: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gain_db: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pan: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mono: Option<MonoFold>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub swap: Option<bool>,
+    /// Second pair the client plays on; 0 once mirroring stopped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirror_offset: Option<u32>,
+}
+
+/// Gains at or below this are silence. JSON has no -inf, so muting is requested and
+/// reported with this value instead.
+pub const SILENCE_DB: f32 = -144.0;
+
+/// Convert a decibel gain to the linear factor the driver expects.
+pub fn db_to_linear(db: f32) -> f32 {
+    if db <= SILENCE_DB {
+        return 0.0;
+    }
+    10f32.powf(db / 20.0)
+}
+
+/// Convert a linear level to decibels full scale; silence maps to negative infinity.
+pub fn linear_to_db(level: f32) -> f32 {
+    20.0 * level.log10()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IoStatsPayload {
+    pub cycles_served: u64,
+    pub output_cycles: u64,
+    pub wrap_arounds: u64,
+    pub stale_reads: u64,
+    pub overloads: u64,
+    #[serde(default)]
+    pub watchdog_clears: u64,
+    /// IO operations that ended more than a period after their cycle began.
+    #[serde(default)]
+    pub late_cycles: u64,
+    /// Output writes that ended after their cycle's output time.
+    #[serde(default)]
+    pub missed_writes: u64,
+    /// Frames written per stereo pair; index 0 is channels 1-2.
+    pub frames_written: Vec<u64>,
+    #[serde(default)]
+    pub slot_table_size: u64,
+    #[serde(default)]
+    pub slots_occupied: u64,
+    #[serde(default)]
+    pub slot_collisions: u64,
+    #[serde(default)]
+    pub slot_table_growths: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagPayload {
+    /// Capture clients that read the input stream within the last half second.
+    pub active_capture_readers: u32,
+    pub capture_readers: Vec<CaptureReaderPayload>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureReaderPayload {
+    pub client_id: u32,
+    pub pid: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub process_name: Option<String>,
+    /// Input sample time of the client's last read.
+    pub last_read_sample_time: f64,
+    /// Input cycles served to the client.
+    pub reads: u64,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthPayload {
+    /// The Prism device is attached and commands can be served.
+    pub healthy: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<u32>,
+    pub pid: u32,
+    pub uptime_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusPayload {
+    pub healthy: bool,
+    /// Clients prismd routed and checks the driver still routes that way.
+    pub tracked_routes: usize,
+    /// Seconds between drift checks; 0 when they are off.
+    pub drift_interval_secs: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drift_check: Option<DriftCheckPayload>,
+}
+
+/// Outcome of comparing the driver's client list with the routes prismd set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriftCheckPayload {
+    /// Unix seconds.
+    pub checked_at: u64,
+    pub tracked: usize,
+    pub drifted: Vec<DriftPayload>,
+    /// The drifted clients were routed back (never with --drift-report-only).
+    pub corrected: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriftPayload {
+    pub pid: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_name: Option<String>,
+    pub intended_offset: u32,
+    pub actual_offset: u32,
+    /// What set the intended route: set, set-app, preset or pair policy.
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriverInfoPayload {
+    pub version: String,
+    /// Git revision the driver was built from, or "unknown".
+    pub build: String,
+    pub debug: bool,
+    #[serde(default)]
+    pub features: Vec<String>,
+    pub devices: Vec<DriverDevicePayload>,
+    pub total_clients: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriverDevicePayload {
+    pub device_id: u32,
+    pub uid: String,
+    pub name: String,
+    pub channels: u32,
+    pub clients: u32,
+    pub io_running: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResyncPayload {
+    /// Resyncs performed since the driver was loaded, including this one.
+    pub resyncs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BufferRegionPayload {
+    pub channel_offset: u32,
+    /// Ring length in frames.
+    pub frames: u64,
+    /// Linear peak over everything currently buffered.
+    pub peak: f32,
+    /// Clients routed to the pair.
+    pub clients: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogLevelPayload {
+    pub level: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RulesPayload {
+    /// The rules file; None when prismd has none (no HOME).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// In the order they are tried.
+    pub rules: Vec<RulePayload>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RulePayload {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bundle_ids: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub names: Vec<String>,
+    pub channel_offset: u32,
+    pub gain_db: f32,
+    #[serde(default)]
+    pub priority: i32,
+    /// Takes the apps no other rule matches.
+    #[serde(default)]
+    pub default: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PresetPayload {
+    pub name: String,
+    pub description: String,
+    /// Whether prismd is currently routing new apps by this preset.
+    pub active: bool,
+    pub routes: Vec<PresetRoutePayload>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PresetRoutePayload {
+    /// Kind of app, e.g. "Voice chat".
+    pub app: String,
+    pub channel_offset: u32,
+    pub gain_db: f32,
+    /// Running apps routed by this entry when the preset was applied.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub matched: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Frames the HAL adds to each side's IO timing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyPayload {
+    pub input_latency: u32,
+    pub output_latency: u32,
+    pub input_safety_offset: u32,
+    pub output_safety_offset: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClockDomainPayload {
+    /// Domain Prism reports; 0 when it claims none.
+    pub domain: u32,
+    /// The other audio devices and their domains, to pick one to align with.
+    pub devices: Vec<ClockDomainDevicePayload>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClockDomainDevicePayload {
+    pub name: String,
+    pub uid: String,
+    pub clock_domain: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BridgePayload {
+    pub id: u32,
+    pub source: String,
+    pub source_offset: u32,
+    pub target: String,
+    pub target_offset: u32,
+    /// False once either device has gone away.
+    pub alive: bool,
+    pub latency: BridgeLatencyPayload,
+    pub underruns: u64,
+    pub overruns: u64,
+}
+
+/// Frames one bridge hop adds, by stage.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BridgeLatencyPayload {
+    pub source_frames: u32,
+    pub ring_frames: u32,
+    pub target_frames: u32,
+    pub total_frames: u32,
+    pub total_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputModePayload {
+    /// The output stream spans the whole bus and apps play onto bus channels directly.
+    pub full_bus: bool,
+    pub bus_channels: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemMixPayload {
+    pub channel_offset: u32,
+    /// Off: the driver drops the system mix, so only routed apps reach the bus.
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoAssignPayload {
+    pub policy: AssignPolicy,
+    /// The policy prismd restores on start, if one was set through it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub saved: Option<AssignPolicy>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClockRatePayload {
+    /// Requested offset from the host clock.
+    pub ppm: f64,
+    /// Rate scalar GetZeroTimeStamp currently applies; eases toward 1 + ppm / 1e6.
+    pub scalar: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeterHistoryPayload {
+    /// Seconds covered by each sample.
+    pub interval_secs: u32,
+    /// Unix time (seconds) just past the newest sample.
+    pub end_time: u64,
+    pub series: Vec<MeterSeriesPayload>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeterSeriesPayload {
+    /// App display name, or "System mix" for channels 1/2.
+    pub name: String,
+    /// Linear peak per sample, oldest first; None while the app was not routed
+    /// or prismd was not sampling.
+    pub peaks: Vec<Option<f32>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BusLevelPayload {
+    /// Stereo pair index; pair N covers channels 2N+1 and 2N+2.
+    pub pair: u32,
+    /// Linear peak level per channel since the previous read.
+    pub peak: [f32; 2],
+    /// Linear RMS level per channel since the previous read.
+    pub rms: [f32; 2],
+    /// Display names of the apps currently routed to this bus.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub apps: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureRulePayload {
+    pub channel_offset: u32,
+    pub bundle_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatrixRoutePayload {
+    /// Side of the app's stereo output: 0 left, 1 right.
+    pub source: u32,
+    /// 1-based bus channel.
+    pub channel: u32,
+    #[serde(default)]
+    pub gain_db: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BusPayload {
+    pub channel_offset: u32,
+    /// Name given with `BusLabel`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// No client plays on the pair and nobody reserved it.
+    pub free: bool,
+    pub writers: Vec<BusWriterPayload>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserved_by: Option<String>,
+    /// Milliseconds since the pair last carried audio; None if it never did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_audio_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BusWriterPayload {
+    pub pid: i32,
+    pub client_id: u32,
+    /// App display name, when prismd has resolved it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bundle_id: Option<String>,
+    pub is_running: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GapPolicyPayload {
+    pub channel_offset: u32,
+    pub mode: GapMode,
+    /// Longest gap covered by repeating audio; 0 for zero-fill.
+    pub max_hold_ms: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BusLabelPayload {
+    pub channel_offset: u32,
+    pub label: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReservationPayload {
+    pub channel_offset: u32,
+    pub owner: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonitorSendPayload {
+    pub channel_offset: u32,
+    /// None removes the pair's send.
+    #[serde(default)]
+    pub gain_db: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonitorPayload {
+    pub enabled: bool,
+    pub sends: Vec<MonitorSendPayload>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureMaskPayload {
+    pub pid: i32,
+    /// Channel offsets of the pairs the process may read; every pair when unmasked.
+    pub offsets: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureListPayload {
+    pub enabled: bool,
+    pub bundle_ids: Vec<String>,
+    pub pids: Vec<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SoloPayload {
+    /// Channel offsets of the soloed pairs; empty when nothing is soloed.
+    pub pairs: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientMatrixPayload {
+    pub pid: i32,
+    pub client_id: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_name: Option<String>,
+    pub routes: Vec<MatrixRoutePayload>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgcPayload {
+    pub app_name: String,
+    pub target_lufs: f32,
+    /// Maximum boost or cut the AGC may apply, in dB.
+    pub range_db: f32,
+    /// Gain the AGC currently applies, in dB.
+    pub gain_db: f32,
+    /// Averaged loudness of the app's bus, if it has produced audio.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loudness_lufs: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelTrimPayload {
+    /// 1-based input channel.
+    pub channel: u32,
+    pub gain_db: f32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
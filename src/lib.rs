@@ -1,6 +1,19 @@
+pub mod agc;
+pub mod agent;
+pub mod content;
+pub mod daemon;
 mod driver;
+pub mod dsp;
+pub mod host;
 pub mod ipc;
+pub mod oslog;
+pub mod persist;
+pub mod preset;
+pub mod presetfmt;
 pub mod process;
+pub mod rules;
+pub mod socket;
+pub mod telemetry;
 
 use coreaudio_sys::*;
 
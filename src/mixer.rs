@@ -0,0 +1,426 @@
+//! `prism mixer`: an interactive terminal mixer on top of the prismd socket.
+//!
+//! One strip per app, grouped like `prism apps`, with the level of its bus, a gain
+//! fader, mute/solo and its channel pair. Everything goes through the same `clients`,
+//! `meters` and `set` requests as the rest of the CLI. Mute and solo are sent as gain
+//! changes; solo is only known to the mixer and is released when it exits.
+
+use super::{extract_success, parse_response, send_request};
+use prism::ipc::{
+    linear_to_db, BusLevelPayload, ClientInfoPayload, CommandRequest, RoutingUpdateAck,
+    RpcResponse, NULL_BUS_OFFSET, SILENCE_DB,
+};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+const FADER_MIN_DB: f32 = -60.0;
+const FADER_MAX_DB: f32 = 12.0;
+const FADER_STEP_DB: f32 = 1.0;
+const FADER_WIDTH: usize = 19;
+const METER_FLOOR_DB: f32 = -60.0;
+const METER_WIDTH: usize = 24;
+const NAME_WIDTH: usize = 20;
+const MAX_CHANNEL_OFFSET: u32 = 62; // Last pair of the 64-channel bus
+const FRAME_INTERVAL: Duration = Duration::from_millis(50);
+const CLIENTS_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    Fader,
+    Channels,
+}
+
+struct Strip {
+    name: String,
+    pids: Vec<i32>,
+    channel_offset: u32,
+    gain_db: f32, // Fader position, kept while the strip is muted or soloed out
+    muted: bool,
+    soloed: bool,
+    running: bool,
+    sent_db: f32, // Gain the driver currently has for the app
+}
+
+struct Mixer {
+    strips: Vec<Strip>,
+    selected: usize,
+    field: Field,
+    peaks: BTreeMap<u32, f32>, // Pair -> linear peak of the louder channel
+    status: Option<String>,
+}
+
+pub fn run() -> Result<(), String> {
+    let mut mixer = Mixer {
+        strips: Vec::new(),
+        selected: 0,
+        field: Field::Fader,
+        peaks: BTreeMap::new(),
+        status: None,
+    };
+    // Fail before taking over the terminal if prismd is not reachable
+    mixer.refresh_clients()?;
+
+    let mut terminal = ratatui::init();
+    let result = mixer.event_loop(&mut terminal);
+    ratatui::restore();
+    mixer.release_solo();
+    result
+}
+
+impl Mixer {
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<(), String> {
+        let mut last_refresh = Instant::now();
+        loop {
+            if last_refresh.elapsed() >= CLIENTS_REFRESH_INTERVAL {
+                if let Err(err) = self.refresh_clients() {
+                    self.status = Some(err);
+                }
+                last_refresh = Instant::now();
+            }
+            if let Err(err) = self.refresh_levels() {
+                self.status = Some(err);
+            }
+            terminal
+                .draw(|frame| self.draw(frame))
+                .map_err(|err| err.to_string())?;
+
+            if !event::poll(FRAME_INTERVAL).map_err(|err| err.to_string())? {
+                continue;
+            }
+            let Event::Key(key) = event::read().map_err(|err| err.to_string())? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(())
+                }
+                KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+                KeyCode::Down => {
+                    self.selected = (self.selected + 1).min(self.strips.len().saturating_sub(1))
+                }
+                KeyCode::Tab => {
+                    self.field = match self.field {
+                        Field::Fader => Field::Channels,
+                        Field::Channels => Field::Fader,
+                    }
+                }
+                KeyCode::Left => self.adjust(-1),
+                KeyCode::Right => self.adjust(1),
+                KeyCode::Char('m') => {
+                    if let Some(strip) = self.strips.get_mut(self.selected) {
+                        strip.muted = !strip.muted;
+                    }
+                    self.push_gains();
+                }
+                KeyCode::Char('s') => {
+                    if let Some(strip) = self.strips.get_mut(self.selected) {
+                        strip.soloed = !strip.soloed;
+                    }
+                    self.push_gains();
+                }
+                KeyCode::Char('0') => {
+                    if let Some(strip) = self.strips.get_mut(self.selected) {
+                        strip.gain_db = 0.0;
+                    }
+                    self.push_gains();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Regroup clients into strips, keeping mixer-only state (solo, and the fader
+    // position of silenced strips) for apps that are still present.
+    fn refresh_clients(&mut self) -> Result<(), String> {
+        let response = send_request(&CommandRequest::Clients)?;
+        let parsed: RpcResponse<Vec<ClientInfoPayload>> = parse_response(&response)?;
+        let (_message, clients) = extract_success(parsed)?;
+
+        let mut groups: BTreeMap<String, Vec<ClientInfoPayload>> = BTreeMap::new();
+        for client in clients {
+            let name = client
+                .responsible_name
+                .clone()
+                .or_else(|| client.process_name.clone())
+                .unwrap_or_else(|| format!("pid {}", client.pid));
+            groups.entry(name).or_default().push(client);
+        }
+
+        let selected_name = self
+            .strips
+            .get(self.selected)
+            .map(|strip| strip.name.clone());
+        let mut previous: BTreeMap<String, Strip> = self
+            .strips
+            .drain(..)
+            .map(|strip| (strip.name.clone(), strip))
+            .collect();
+
+        for (name, clients) in groups {
+            let reported_db = clients[0].gain_db.unwrap_or(0.0);
+            let mut strip = previous.remove(&name).unwrap_or_else(|| Strip {
+                name: name.clone(),
+                pids: Vec::new(),
+                channel_offset: 0,
+                gain_db: if reported_db <= SILENCE_DB {
+                    0.0
+                } else {
+                    reported_db
+                },
+                muted: reported_db <= SILENCE_DB,
+                soloed: false,
+                running: false,
+                sent_db: reported_db,
+            });
+            // Follow gain changes made elsewhere (set-app, AGC) unless we silenced it
+            if strip.sent_db > SILENCE_DB {
+                if reported_db > SILENCE_DB {
+                    strip.gain_db = reported_db;
+                } else {
+                    strip.muted = true;
+                }
+            }
+            strip.sent_db = reported_db;
+            strip.pids = clients.iter().map(|client| client.pid).collect();
+            strip.channel_offset = clients[0].channel_offset;
+            strip.running = clients.iter().any(|client| client.is_running);
+            self.strips.push(strip);
+        }
+
+        self.selected = selected_name
+            .and_then(|name| self.strips.iter().position(|strip| strip.name == name))
+            .unwrap_or(self.selected)
+            .min(self.strips.len().saturating_sub(1));
+        // Newly appeared apps must be silenced while something is soloed
+        self.push_gains();
+        Ok(())
+    }
+
+    fn refresh_levels(&mut self) -> Result<(), String> {
+        let response = send_request(&CommandRequest::Meters)?;
+        let parsed: RpcResponse<Vec<BusLevelPayload>> = parse_response(&response)?;
+        let (_message, levels) = extract_success(parsed)?;
+        self.peaks = levels
+            .into_iter()
+            .map(|level| (level.pair, level.peak[0].max(level.peak[1])))
+            .collect();
+        Ok(())
+    }
+
+    fn adjust(&mut self, direction: i32) {
+        let field = self.field;
+        let Some(strip) = self.strips.get_mut(self.selected) else {
+            return;
+        };
+        match field {
+            Field::Fader => {
+                strip.gain_db = (strip.gain_db + direction as f32 * FADER_STEP_DB)
+                    .clamp(FADER_MIN_DB, FADER_MAX_DB);
+                self.push_gains();
+            }
+            Field::Channels => {
+                let offset = (strip.channel_offset as i64 + 2 * direction as i64)
+                    .clamp(0, MAX_CHANNEL_OFFSET as i64) as u32;
+                if offset == strip.channel_offset {
+                    return;
+                }
+                for pid in strip.pids.clone() {
+                    if let Err(err) = send_set(pid, offset, None) {
+                        self.status = Some(err);
+                        return;
+                    }
+                }
+                strip.channel_offset = offset;
+                self.status = Some(format!("{} -> {}-{}ch", strip.name, offset + 1, offset + 2));
+            }
+        }
+    }
+
+    fn release_solo(&mut self) {
+        for strip in &mut self.strips {
+            strip.soloed = false;
+        }
+        self.push_gains();
+    }
+
+    fn effective_db(&self, strip: &Strip) -> f32 {
+        let any_solo = self.strips.iter().any(|strip| strip.soloed);
+        if strip.muted || (any_solo && !strip.soloed) {
+            SILENCE_DB
+        } else {
+            strip.gain_db
+        }
+    }
+
+    // Send the gain of every strip whose effective gain differs from the driver's.
+    fn push_gains(&mut self) {
+        for index in 0..self.strips.len() {
+            let target = self.effective_db(&self.strips[index]);
+            let strip = &mut self.strips[index];
+            if target == strip.sent_db {
+                continue;
+            }
+            for &pid in &strip.pids {
+                if let Err(err) = send_set(pid, strip.channel_offset, Some(target)) {
+                    self.status = Some(err);
+                    return;
+                }
+            }
+            strip.sent_db = target;
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [body, footer] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+
+        let mut lines = vec![Line::styled(
+            format!(
+                "  {:<name$} {:<meter$} {:<fader$} {:>7}  M S  {:>6}",
+                "App",
+                "Level",
+                "Gain",
+                "",
+                "Ch",
+                name = NAME_WIDTH,
+                meter = METER_WIDTH,
+                fader = FADER_WIDTH
+            ),
+            Style::new().add_modifier(Modifier::BOLD),
+        )];
+        if self.strips.is_empty() {
+            lines.push(Line::raw("  No clients connected to Prism."));
+        }
+        for (index, strip) in self.strips.iter().enumerate() {
+            lines.push(self.strip_line(strip, index == self.selected));
+        }
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" Prism mixer ")),
+            body,
+        );
+
+        let help = "↑/↓ app  Tab fader/channels  ←/→ adjust  m mute  s solo  0 unity  q quit";
+        let footer_text = match &self.status {
+            Some(status) => format!("{}  |  {}", help, status),
+            None => help.to_string(),
+        };
+        frame.render_widget(Paragraph::new(footer_text), footer);
+    }
+
+    fn strip_line(&self, strip: &Strip, selected: bool) -> Line<'static> {
+        let focus = |field: Field| {
+            if selected && self.field == field {
+                Style::new().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::new()
+            }
+        };
+        let effective_db = self.effective_db(strip);
+        let peak = self
+            .peaks
+            .get(&(strip.channel_offset / 2))
+            .copied()
+            .unwrap_or(0.0);
+
+        let mut spans = vec![
+            Span::raw(if selected { "▶ " } else { "  " }),
+            Span::styled(
+                format!("{:<width$.width$}", strip.name, width = NAME_WIDTH),
+                if strip.running {
+                    Style::new()
+                } else {
+                    Style::new().fg(Color::DarkGray)
+                },
+            ),
+            Span::raw(" "),
+        ];
+        spans.extend(meter_spans(peak));
+        spans.push(Span::raw(" "));
+        spans.push(Span::styled(fader_bar(strip.gain_db), focus(Field::Fader)));
+        spans.push(Span::raw(if effective_db <= SILENCE_DB {
+            format!(" {:>7}", "-inf")
+        } else {
+            format!(" {:>+5.1}dB", effective_db)
+        }));
+        spans.push(Span::raw("  "));
+        spans.push(flag_span(strip.muted, 'M', Color::Red));
+        spans.push(Span::raw(" "));
+        spans.push(flag_span(strip.soloed, 'S', Color::Yellow));
+        spans.push(Span::raw("  "));
+        spans.push(Span::styled(
+            format!(
+                "{:>6}",
+                if strip.channel_offset == NULL_BUS_OFFSET {
+                    "null".to_string()
+                } else {
+                    format!("{}-{}", strip.channel_offset + 1, strip.channel_offset + 2)
+                }
+            ),
+            focus(Field::Channels),
+        ));
+        Line::from(spans)
+    }
+}
+
+fn send_set(pid: i32, offset: u32, gain_db: Option<f32>) -> Result<(), String> {
+    let response = send_request(&CommandRequest::Set {
+        pid,
+        offset,
+        gain_db,
+        pan: None,
+        exclude: Vec::new(),
+        owner: None,
+    })?;
+    let parsed: RpcResponse<RoutingUpdateAck> = parse_response(&response)?;
+    extract_success(parsed).map(|_| ())
+}
+
+// Peak meter from METER_FLOOR_DB to 0 dBFS, green below -18, yellow to -6, red above.
+fn meter_spans(peak: f32) -> Vec<Span<'static>> {
+    let db = linear_to_db(peak).max(METER_FLOOR_DB);
+    let lit = (((db - METER_FLOOR_DB) / -METER_FLOOR_DB) * METER_WIDTH as f32).round() as usize;
+    let cell_db = |cell: usize| METER_FLOOR_DB * (1.0 - (cell + 1) as f32 / METER_WIDTH as f32);
+
+    let mut spans = Vec::new();
+    let mut cell = 0;
+    for (ceiling, color) in [
+        (-18.0, Color::Green),
+        (-6.0, Color::Yellow),
+        (0.0, Color::Red),
+    ] {
+        let mut segment = String::new();
+        while cell < METER_WIDTH && cell_db(cell) <= ceiling {
+            segment.push(if cell < lit { '█' } else { '·' });
+            cell += 1;
+        }
+        spans.push(Span::styled(segment, Style::new().fg(color)));
+    }
+    spans
+}
+
+fn fader_bar(gain_db: f32) -> String {
+    let position = ((gain_db - FADER_MIN_DB) / (FADER_MAX_DB - FADER_MIN_DB)
+        * (FADER_WIDTH - 1) as f32)
+        .round() as usize;
+    (0..FADER_WIDTH)
+        .map(|cell| if cell == position { '●' } else { '─' })
+        .collect()
+}
+
+fn flag_span(on: bool, label: char, color: Color) -> Span<'static> {
+    if on {
+        Span::styled(label.to_string(), Style::new().fg(Color::Black).bg(color))
+    } else {
+        Span::styled(label.to_string(), Style::new().fg(Color::DarkGray))
+    }
+}
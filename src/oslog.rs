@@ -0,0 +1,170 @@
+// Unified logging for the driver. Messages go to os_log under the dev.ichigo.prism
+// subsystem, one category per area, so Console.app and `log stream --predicate` can
+// filter them. A runtime level (set by prismd through the 'logl' property) decides
+// which messages are emitted at all.
+
+use std::ffi::{c_char, c_void, CStr};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::OnceLock;
+
+pub const SUBSYSTEM: &CStr = c"dev.ichigo.prism";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Lifecycle,  // Plug-in, device and client setup and teardown
+    Properties, // HAL property traffic
+    Io,         // DoIOOperation tracing (trace-io builds only)
+    Routing,    // Slot and pair routing changes
+}
+
+impl Category {
+    const ALL: [Category; 4] = [
+        Category::Lifecycle,
+        Category::Properties,
+        Category::Io,
+        Category::Routing,
+    ];
+
+    fn name(self) -> &'static CStr {
+        match self {
+            Category::Lifecycle => c"lifecycle",
+            Category::Properties => c"properties",
+            Category::Io => c"io",
+            Category::Routing => c"routing",
+        }
+    }
+
+    // Level a category's messages are logged at. Property and IO traffic is chatty
+    // enough that it only shows up once the level is raised to debug.
+    pub fn level(self) -> Level {
+        match self {
+            Category::Lifecycle | Category::Routing => Level::Info,
+            Category::Properties | Category::Io => Level::Debug,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Error = 0,
+    Info = 1,
+    Debug = 2,
+}
+
+impl Level {
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "error" => Some(Level::Error),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            _ => None,
+        }
+    }
+
+    // os_log_type_t; info messages use the default type so they are persisted
+    fn os_log_type(self) -> u8 {
+        match self {
+            Level::Error => 0x10,
+            Level::Info => 0x00,
+            Level::Debug => 0x02,
+        }
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+pub fn level() -> Level {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => Level::Error,
+        1 => Level::Info,
+        _ => Level::Debug,
+    }
+}
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+#[allow(non_upper_case_globals)]
+extern "C" {
+    fn os_log_create(subsystem: *const c_char, category: *const c_char) -> *mut c_void;
+    fn os_log_type_enabled(log: *mut c_void, log_type: u8) -> bool;
+    fn _os_log_impl(
+        dso: *mut c_void,
+        log: *mut c_void,
+        log_type: u8,
+        format: *const c_char,
+        buf: *mut u8,
+        size: u32,
+    );
+    static __dso_handle: u8;
+}
+
+// os_log_t handles are immutable once created and safe to share between threads.
+struct Handle(*mut c_void);
+unsafe impl Send for Handle {}
+unsafe impl Sync for Handle {}
+
+static HANDLES: [OnceLock<Handle>; 4] = [
+    OnceLock::new(),
+    OnceLock::new(),
+    OnceLock::new(),
+    OnceLock::new(),
+];
+
+// The log tools resolve format strings from this section of the image, as they do for
+// the os_log() macro. The precision lets the message be a &str without a trailing NUL.
+#[link_section = "__TEXT,__oslogstring,cstring_literals"]
+static FORMAT: [u8; 13] = *b"%{public}.*s\0";
+
+pub fn log(category: Category, level: Level, message: &str) {
+    if level > self::level() {
+        return;
+    }
+    let index = Category::ALL
+        .iter()
+        .position(|&c| c == category)
+        .unwrap_or(0);
+    let handle = HANDLES[index].get_or_init(|| {
+        Handle(unsafe { os_log_create(SUBSYSTEM.as_ptr(), category.name().as_ptr()) })
+    });
+    let log_type = level.os_log_type();
+
+    unsafe {
+        if handle.0.is_null() || !os_log_type_enabled(handle.0, log_type) {
+            return;
+        }
+
+        // The argument buffer os_log() would encode for "%{public}.*s": a summary byte
+        // (has non-scalar arguments), the argument count, then descriptor, size and
+        // value of the precision and of the string pointer.
+        let len = message.len().min(i32::MAX as usize) as i32;
+        let mut buf = [0u8; 18];
+        buf[0] = 0x02;
+        buf[1] = 2;
+        buf[2] = 0x10; // count
+        buf[3] = 4;
+        buf[4..8].copy_from_slice(&len.to_ne_bytes());
+        buf[8] = 0x22; // string, public
+        buf[9] = 8;
+        buf[10..18].copy_from_slice(&(message.as_ptr() as u64).to_ne_bytes());
+
+        _os_log_impl(
+            &__dso_handle as *const u8 as *mut c_void,
+            handle.0,
+            log_type,
+            FORMAT.as_ptr() as *const c_char,
+            buf.as_mut_ptr(),
+            buf.len() as u32,
+        );
+    }
+}
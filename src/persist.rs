@@ -0,0 +1,59 @@
+//! Files prismd keeps its state in: saved routes, content type overrides and the
+//! meter history.
+//!
+//! Each is replaced whole. The new contents go to a file beside the target, are synced
+//! to disk and then renamed over it, so a crash or power loss leaves either the old
+//! file or the new one, never a torn mix of both.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+/// Replace the file at `path` with `contents`, creating its directory if needed.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+    }
+    let tmp = path.with_extension("tmp");
+    let write = || {
+        let mut file = File::create(&tmp)?;
+        file.write_all(contents)?;
+        // Without this the rename can reach the disk before the data it points to
+        file.sync_all()
+    };
+    write().map_err(|err| format!("{}: {}", tmp.display(), err))?;
+    fs::rename(&tmp, path).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+    use std::process;
+
+    // A directory of the test's own, removed when the test ends
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(test: &str) -> Self {
+            TempDir(env::temp_dir().join(format!("prism-persist-{}-{}", process::id(), test)))
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn replaces_the_file_and_leaves_no_temp_behind() {
+        let dir = TempDir::new("replace");
+        let path = dir.0.join("state/routes.json");
+        write_atomic(&path, b"first").unwrap();
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert!(!path.with_extension("tmp").exists());
+    }
+}
@@ -0,0 +1,81 @@
+//! Built-in routing presets for common apps.
+//!
+//! The data lives in presets/builtin.json, in the format described in
+//! [`crate::presetfmt`], and is compiled into the binaries. prismd
+//! applies a preset to the apps already playing and keeps it active, so apps that
+//! start later are routed as they appear. An app the user reroutes afterwards is left
+//! alone.
+
+use crate::ipc::ContentType;
+use crate::presetfmt;
+use crate::process::bundle_matches;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+const BUILTIN_PRESETS: &str = include_str!("../presets/builtin.json");
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Preset {
+    pub name: String,
+    pub description: String,
+    pub routes: Vec<PresetRoute>,
+}
+
+/// Where one kind of app goes. An app matches by bundle ID (helpers such as
+/// com.google.Chrome.helper match their app's ID), by the display name `prism apps`
+/// shows, or by its content type, so apps the preset does not name still land on a
+/// fitting pair.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PresetRoute {
+    /// Label shown to the user, e.g. "Voice chat".
+    pub app: String,
+    #[serde(default)]
+    pub bundle_ids: Vec<String>,
+    #[serde(default)]
+    pub names: Vec<String>,
+    #[serde(default)]
+    pub content_types: Vec<ContentType>,
+    pub channel_offset: u32,
+    #[serde(default)]
+    pub gain_db: f32,
+}
+
+impl PresetRoute {
+    pub fn matches(
+        &self,
+        bundle_id: Option<&str>,
+        name: Option<&str>,
+        content_type: Option<ContentType>,
+    ) -> bool {
+        let by_bundle = bundle_id.is_some_and(|bundle_id| {
+            self.bundle_ids
+                .iter()
+                .any(|id| bundle_matches(bundle_id, id))
+        });
+        let by_name = name.is_some_and(|name| {
+            self.names
+                .iter()
+                .any(|candidate| candidate.eq_ignore_ascii_case(name))
+        });
+        let by_type =
+            content_type.is_some_and(|content_type| self.content_types.contains(&content_type));
+        by_bundle || by_name || by_type
+    }
+}
+
+/// Presets shipped with Prism.
+pub fn builtin() -> &'static [Preset] {
+    static PRESETS: OnceLock<Vec<Preset>> = OnceLock::new();
+    PRESETS.get_or_init(|| match presetfmt::parse(BUILTIN_PRESETS) {
+        Ok(file) => file.presets,
+        Err(err) => panic!("presets/builtin.json is malformed: {}", err),
+    })
+}
+
+pub fn find(name: &str) -> Option<&'static Preset> {
+    builtin()
+        .iter()
+        .find(|preset| preset.name.eq_ignore_ascii_case(name))
+}
@@ -0,0 +1,378 @@
+//! On-disk preset format, for presets written by hand or by other tools (a Stream Deck
+//! plugin, a GUI).
+//!
+//! A preset file is a JSON object with the schema version and a list of presets:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "presets": [
+//!     {
+//!       "name": "podcast",
+//!       "description": "Guests on 3-4, music bed on 5-6",
+//!       "routes": [
+//!         { "app": "Guests", "bundle_ids": ["com.hnc.Discord"], "channel_offset": 2 },
+//!         { "app": "Music", "content_types": ["music"], "channel_offset": 4, "gain_db": -12.0 }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Each route needs at least one of `bundle_ids`, `names` or `content_types`, and an even
+//! `channel_offset` (0 for 1-2ch, 2 for 3-4ch, ...). `gain_db` defaults to 0 and must lie
+//! within -96 to +24 dB. Unknown fields are rejected rather than ignored, so a typo does not
+//! silently drop a setting. A file with a newer `version` than this build reads is refused.
+//!
+//! Errors carry the line and column of the offending value and the path to it
+//! (`presets[0].routes[1].channel_offset`), so a generator can point at its own mistake.
+
+use crate::preset::{Preset, PresetRoute};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Newest schema version this build reads and writes.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Range a route's `gain_db` must lie in.
+pub const MIN_GAIN_DB: f32 = -96.0;
+pub const MAX_GAIN_DB: f32 = 24.0;
+
+/// Where prismd looks for preset files (*.json) unless told otherwise.
+pub fn default_presets_dir() -> Option<PathBuf> {
+    let home = env::var_os("HOME")?;
+    Some(PathBuf::from(home).join("Library/Application Support/Prism/presets"))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PresetFile {
+    pub version: u32,
+    pub presets: Vec<Preset>,
+}
+
+impl PresetFile {
+    pub fn new(presets: Vec<Preset>) -> Self {
+        Self {
+            version: SCHEMA_VERSION,
+            presets,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("preset file serializes")
+    }
+}
+
+/// Why a preset file was refused, and where.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaError {
+    /// 1-based; 0 when the error has no position (an empty file).
+    pub line: usize,
+    pub column: usize,
+    /// Path to the offending value, e.g. `presets[0].routes[1].channel_offset`; empty
+    /// for syntax errors.
+    pub path: String,
+    pub message: String,
+}
+
+impl SchemaError {
+    /// The error followed by the offending source line with a caret under the column.
+    pub fn render(&self, source: &str) -> String {
+        let mut out = self.to_string();
+        if let Some(text) = self.line.checked_sub(1).and_then(|n| source.lines().nth(n)) {
+            let gutter = self.line.to_string();
+            out.push_str(&format!(
+                "\n{} | {}\n{} | {}^",
+                gutter,
+                text,
+                " ".repeat(gutter.len()),
+                " ".repeat(self.column.saturating_sub(1))
+            ));
+        }
+        out
+    }
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line > 0 {
+            write!(f, "line {}, column {}: ", self.line, self.column)?;
+        }
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+/// Parse and validate a preset file.
+pub fn parse(source: &str) -> Result<PresetFile, SchemaError> {
+    // Check the version on its own first, so a newer file is refused for being newer
+    // rather than for whatever fields it added
+    #[derive(Deserialize)]
+    struct VersionOnly {
+        version: Option<serde_json::Value>,
+    }
+    let probe: VersionOnly = serde_json::from_str(source).map_err(syntax_error)?;
+    match probe.version.as_ref().map(|v| v.as_u64()) {
+        None => return Err(error_at(source, &[], "missing \"version\"")),
+        Some(Some(version)) if version == u64::from(SCHEMA_VERSION) => {}
+        Some(Some(version)) => {
+            return Err(error_at(
+                source,
+                &[Step::Key("version")],
+                &format!(
+                    "unsupported version {}; this prism reads version {}",
+                    version, SCHEMA_VERSION
+                ),
+            ))
+        }
+        Some(None) => {
+            return Err(error_at(
+                source,
+                &[Step::Key("version")],
+                "version must be a whole number",
+            ))
+        }
+    }
+
+    let file: PresetFile = serde_json::from_str(source).map_err(syntax_error)?;
+    validate(&file).map_err(|(path, message)| error_at(source, &path, &message))?;
+    Ok(file)
+}
+
+/// Read and parse the preset file at `path`.
+pub fn load(path: &Path) -> Result<PresetFile, String> {
+    let source = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    parse(&source).map_err(|err| format!("{}: {}", path.display(), err.render(&source)))
+}
+
+/// Every preset in the *.json files of `dir`, in file name order, with an error per
+/// file that was refused. A missing directory holds no presets.
+pub fn load_dir(dir: &Path) -> (Vec<Preset>, Vec<String>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return (Vec::new(), Vec::new());
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+
+    let mut presets = Vec::new();
+    let mut errors = Vec::new();
+    for path in paths {
+        match load(&path) {
+            Ok(file) => presets.extend(file.presets),
+            Err(err) => errors.push(err),
+        }
+    }
+    (presets, errors)
+}
+
+fn syntax_error(err: serde_json::Error) -> SchemaError {
+    SchemaError {
+        line: err.line(),
+        column: err.column(),
+        path: String::new(),
+        // serde_json appends " at line L column C", which the error already carries
+        message: err
+            .to_string()
+            .split(" at line ")
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+    }
+}
+
+fn validate(file: &PresetFile) -> Result<(), (Vec<Step<'static>>, String)> {
+    if file.presets.is_empty() {
+        return Err((vec![Step::Key("presets")], "no presets".to_string()));
+    }
+    for (index, preset) in file.presets.iter().enumerate() {
+        let at = |key| vec![Step::Key("presets"), Step::Index(index), Step::Key(key)];
+        if preset.name.is_empty() || preset.name.contains(char::is_whitespace) {
+            return Err((
+                at("name"),
+                "name must be a single word, as it is typed on the command line".to_string(),
+            ));
+        }
+        if preset.name.eq_ignore_ascii_case("off") {
+            return Err((
+                at("name"),
+                "\"off\" is reserved by `prism quickstart`".to_string(),
+            ));
+        }
+        if file.presets[..index]
+            .iter()
+            .any(|other| other.name.eq_ignore_ascii_case(&preset.name))
+        {
+            return Err((at("name"), format!("duplicate preset '{}'", preset.name)));
+        }
+        if preset.routes.is_empty() {
+            return Err((
+                at("routes"),
+                "a preset needs at least one route".to_string(),
+            ));
+        }
+        for (route_index, route) in preset.routes.iter().enumerate() {
+            validate_route(route).map_err(|(key, message)| {
+                let mut path = at("routes");
+                path.push(Step::Index(route_index));
+                path.extend(key.map(Step::Key));
+                (path, message)
+            })?;
+        }
+    }
+    Ok(())
+}
+
+fn validate_route(route: &PresetRoute) -> Result<(), (Option<&'static str>, String)> {
+    if route.bundle_ids.is_empty() && route.names.is_empty() && route.content_types.is_empty() {
+        return Err((
+            None,
+            "route matches nothing; give bundle_ids, names or content_types".to_string(),
+        ));
+    }
+    if route.channel_offset % 2 != 0 {
+        return Err((
+            Some("channel_offset"),
+            format!(
+                "channel_offset must be even (the left channel of a pair, 0-based), got {}",
+                route.channel_offset
+            ),
+        ));
+    }
+    if !route.gain_db.is_finite() || !(MIN_GAIN_DB..=MAX_GAIN_DB).contains(&route.gain_db) {
+        return Err((
+            Some("gain_db"),
+            format!(
+                "gain_db must be between {} and +{} dB, got {}",
+                MIN_GAIN_DB, MAX_GAIN_DB, route.gain_db
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Step<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+fn format_path(path: &[Step]) -> String {
+    let mut out = String::new();
+    for step in path {
+        match step {
+            Step::Key(key) if out.is_empty() => out.push_str(key),
+            Step::Key(key) => {
+                out.push('.');
+                out.push_str(key);
+            }
+            Step::Index(index) => out.push_str(&format!("[{}]", index)),
+        }
+    }
+    out
+}
+
+fn error_at(source: &str, path: &[Step], message: &str) -> SchemaError {
+    // The file already parsed, so the value is there; fall back to the start if not
+    let offset = locate(source.as_bytes(), skip_ws(source.as_bytes(), 0), path).unwrap_or(0);
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |newline| newline + 1) + 1;
+    SchemaError {
+        line,
+        column,
+        path: format_path(path),
+        message: message.to_string(),
+    }
+}
+
+// Byte offset of the value at `path` in well-formed JSON, starting from the value at `pos`.
+// serde_json does not keep positions for values it parsed, so errors found after
+// deserializing walk the source to find theirs.
+fn locate(src: &[u8], pos: usize, path: &[Step]) -> Option<usize> {
+    let Some((step, rest)) = path.split_first() else {
+        return Some(pos);
+    };
+    let (open, close) = match step {
+        Step::Key(_) => (b'{', b'}'),
+        Step::Index(_) => (b'[', b']'),
+    };
+    if src.get(pos) != Some(&open) {
+        return None;
+    }
+    let mut pos = skip_ws(src, pos + 1);
+    let mut index = 0;
+    while src.get(pos).is_some_and(|&b| b != close) {
+        let found = match step {
+            Step::Key(key) => {
+                let end = skip_value(src, pos)?;
+                let name = &src[pos + 1..end - 1];
+                pos = skip_ws(src, end);
+                pos = skip_ws(src, pos + 1); // ':'
+                name == key.as_bytes()
+            }
+            Step::Index(wanted) => index == *wanted,
+        };
+        if found {
+            return locate(src, pos, rest);
+        }
+        pos = skip_ws(src, skip_value(src, pos)?);
+        if src.get(pos) == Some(&b',') {
+            pos = skip_ws(src, pos + 1);
+        }
+        index += 1;
+    }
+    None
+}
+
+fn skip_ws(src: &[u8], mut pos: usize) -> usize {
+    while src.get(pos).is_some_and(|b| b.is_ascii_whitespace()) {
+        pos += 1;
+    }
+    pos
+}
+
+// End offset of the value starting at `pos`.
+fn skip_value(src: &[u8], pos: usize) -> Option<usize> {
+    match *src.get(pos)? {
+        b'"' => {
+            let mut i = pos + 1;
+            loop {
+                match *src.get(i)? {
+                    b'\\' => i += 2,
+                    b'"' => return Some(i + 1),
+                    _ => i += 1,
+                }
+            }
+        }
+        b'{' | b'[' => {
+            let close = if src[pos] == b'{' { b'}' } else { b']' };
+            let mut i = skip_ws(src, pos + 1);
+            while *src.get(i)? != close {
+                i = skip_ws(src, skip_value(src, i)?);
+                if matches!(src.get(i), Some(b',') | Some(b':')) {
+                    i = skip_ws(src, i + 1);
+                }
+            }
+            Some(i + 1)
+        }
+        _ => {
+            let mut i = pos;
+            while src
+                .get(i)
+                .is_some_and(|&b| !matches!(b, b',' | b']' | b'}') && !b.is_ascii_whitespace())
+            {
+                i += 1;
+            }
+            Some(i)
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::HashSet;
 use std::ffi::CStr;
+use std::io;
 use std::mem;
 
 #[derive(Debug, Clone)]
@@ -62,6 +63,24 @@ pub fn process_path(pid: i32) -> Option<String> {
     Some(cstr.to_string_lossy().into_owned())
 }
 
+/// Whether a client reporting `candidate` belongs to the app `bundle_id`: the app
+/// itself or one of its helpers (com.google.Chrome.helper for com.google.Chrome).
+pub fn bundle_matches(candidate: &str, bundle_id: &str) -> bool {
+    candidate
+        .strip_prefix(bundle_id)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+pub fn is_alive(pid: i32) -> bool {
+    if pid <= 0 {
+        return false;
+    }
+
+    // Signal 0 only checks existence; EPERM means the process exists under another user
+    unsafe { libc::kill(pid, 0) == 0 }
+    || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
 pub fn resolve_responsible_identity(pid: i32) -> Option<ProcessIdentity> {
     if pid <= 0 {
         return None;
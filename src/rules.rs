@@ -0,0 +1,268 @@
+//! Routing rules: per-app routes prismd applies on its own, kept in a file so they hold
+//! across restarts.
+//!
+//! The rules file is a JSON object with the schema version and the rules in order:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "rules": [
+//!     { "bundle_ids": ["com.spotify.client"], "channel_offset": 4 },
+//!     { "names": ["Discord"], "channel_offset": 2, "gain_db": -3.0 },
+//!     { "bundle_ids": ["com.google.Chrome*"], "names": ["*Helper*"], "channel_offset": 6 },
+//!     { "default": true, "channel_offset": 8 }
+//!   ]
+//! }
+//! ```
+//!
+//! A rule matches an app by bundle ID (an ID covers its helpers) or by the display name
+//! `prism apps` shows. Entries may be globs: `*` matches any run of characters and `?`
+//! any one, over the whole ID or name; names match regardless of case. The matching rule
+//! with the highest `priority` (default 0) wins, the earliest in the file among equals,
+//! and the `default` rule, which lists no IDs or names, takes apps no other rule matches.
+//! prismd routes each app once as it appears, so a route set by hand afterwards sticks
+//! until the app quits. Fields follow preset routes: an even `channel_offset`, `gain_db`
+//! within -96 to +24 dB.
+
+use crate::presetfmt::{MAX_GAIN_DB, MIN_GAIN_DB};
+use crate::process::bundle_matches;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Newest schema version this build reads.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Where prismd reads its rules unless told otherwise.
+pub fn default_rules_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME")?;
+    Some(PathBuf::from(home).join("Library/Application Support/Prism/rules.json"))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RulesFile {
+    pub version: u32,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bundle_ids: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub names: Vec<String>,
+    pub channel_offset: u32,
+    #[serde(default)]
+    pub gain_db: f32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub priority: i32,
+    /// Takes the apps no other rule matches.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub default: bool,
+}
+
+impl Rule {
+    pub fn matches(&self, bundle_id: Option<&str>, name: Option<&str>) -> bool {
+        let by_bundle = bundle_id.is_some_and(|bundle_id| {
+            self.bundle_ids.iter().any(|id| {
+                if is_glob(id) {
+                    glob_matches(id, bundle_id, false)
+                } else {
+                    bundle_matches(bundle_id, id)
+                }
+            })
+        });
+        let by_name = name.is_some_and(|name| {
+            self.names
+                .iter()
+                .any(|candidate| glob_matches(candidate, name, true))
+        });
+        by_bundle || by_name
+    }
+}
+
+/// The rule for the app: the highest-priority rule that matches, the earliest in the file
+/// among equals, else the default rule.
+pub fn find<'a>(
+    rules: &'a [Rule],
+    bundle_id: Option<&str>,
+    name: Option<&str>,
+) -> Option<&'a Rule> {
+    rules
+        .iter()
+        .filter(|rule| !rule.default && rule.matches(bundle_id, name))
+        .rev()
+        .max_by_key(|rule| rule.priority)
+        .or_else(|| rules.iter().find(|rule| rule.default))
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
+}
+
+// Whether all of `text` matches `pattern`, where `*` matches any run of characters and
+// `?` any one. Backtracks to the last `*` only, so it stays linear in practice.
+fn glob_matches(pattern: &str, text: &str, ignore_case: bool) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let same = |a: char, b: char| a == b || (ignore_case && a.eq_ignore_ascii_case(&b));
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || same(c, text[t]) => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the last `*` swallow one more character and retry from there
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, t));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn is_zero(value: &i32) -> bool {
+    *value == 0
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Parse and validate a rules file.
+pub fn parse(source: &str) -> Result<Vec<Rule>, String> {
+    let file: RulesFile = serde_json::from_str(source).map_err(|err| err.to_string())?;
+    if file.version != SCHEMA_VERSION {
+        return Err(format!(
+            "unsupported version {}; this prism reads version {}",
+            file.version, SCHEMA_VERSION
+        ));
+    }
+    for (index, rule) in file.rules.iter().enumerate() {
+        validate(rule).map_err(|err| format!("rules[{}]: {}", index, err))?;
+        if rule.default && file.rules[..index].iter().any(|other| other.default) {
+            return Err(format!(
+                "rules[{}]: only one rule can be the default",
+                index
+            ));
+        }
+    }
+    Ok(file.rules)
+}
+
+/// Read the rules at `path`; a missing file holds none.
+pub fn load(path: &Path) -> Result<Vec<Rule>, String> {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(format!("{}: {}", path.display(), err)),
+    };
+    parse(&source).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+fn validate(rule: &Rule) -> Result<(), String> {
+    if rule.default {
+        if !rule.bundle_ids.is_empty() || !rule.names.is_empty() {
+            return Err(
+                "the default rule takes every app; drop its bundle_ids and names".to_string(),
+            );
+        }
+    } else if rule.bundle_ids.is_empty() && rule.names.is_empty() {
+        return Err(
+            "rule matches nothing; give bundle_ids or names, or make it the default".to_string(),
+        );
+    }
+    if rule
+        .bundle_ids
+        .iter()
+        .chain(&rule.names)
+        .any(|pattern| pattern.is_empty() || pattern.chars().all(|c| c == '*'))
+    {
+        return Err("a pattern that matches every app belongs in the default rule".to_string());
+    }
+    if rule.channel_offset % 2 != 0 {
+        return Err(format!(
+            "channel_offset must be even (the left channel of a pair, 0-based), got {}",
+            rule.channel_offset
+        ));
+    }
+    if !rule.gain_db.is_finite() || !(MIN_GAIN_DB..=MAX_GAIN_DB).contains(&rule.gain_db) {
+        return Err(format!(
+            "gain_db must be between {} and +{} dB, got {}",
+            MIN_GAIN_DB, MAX_GAIN_DB, rule.gain_db
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_star_matches_any_run() {
+        assert!(glob_matches(
+            "com.google.Chrome*",
+            "com.google.Chrome",
+            false
+        ));
+        assert!(glob_matches(
+            "com.google.Chrome*",
+            "com.google.Chrome.helper",
+            false
+        ));
+        assert!(glob_matches(
+            "*Helper*",
+            "Google Chrome Helper (Renderer)",
+            false
+        ));
+        assert!(glob_matches("**", "", false));
+        assert!(!glob_matches(
+            "com.google.Chrome*",
+            "com.google.Chrom",
+            false
+        ));
+    }
+
+    #[test]
+    fn glob_question_mark_matches_one_character() {
+        assert!(glob_matches("Zoom?", "Zoom1", false));
+        assert!(!glob_matches("Zoom?", "Zoom", false));
+        assert!(!glob_matches("Zoom?", "Zoom12", false));
+        assert!(glob_matches("?*?", "ab", false));
+        assert!(!glob_matches("?*?", "a", false));
+    }
+
+    #[test]
+    fn glob_backtracks_to_the_last_star() {
+        // The first `b` and `c` seen are not the ones the pattern ends on
+        assert!(glob_matches("a*b*c", "aXbYcZbc", false));
+        assert!(glob_matches("*aab", "aaaab", false));
+        assert!(glob_matches("*a?c", "abcabc", false));
+        assert!(!glob_matches("a*b*c", "aXbYcZb", false));
+        assert!(!glob_matches("*x*", "abc", false));
+    }
+
+    #[test]
+    fn glob_folds_case_only_when_asked() {
+        assert!(glob_matches("discord", "Discord", true));
+        assert!(glob_matches("*HELPER?", "Chrome Helper2", true));
+        assert!(!glob_matches("discord", "Discord", false));
+        assert!(!glob_matches("com.Spotify.*", "com.spotify.client", false));
+    }
+}
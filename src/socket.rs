@@ -1 +1,374 @@
+//! The prismd socket: where it lives, who may connect, and the compression handshake.
+//!
+//! prismd listens on the shared socket unless told otherwise; with --user-socket it
+//! listens on a socket only its user can open, so several users can each run their
+//! own daemon. Clients try the caller's per-user socket first and fall back to the
+//! shared one. PRISM_SOCKET overrides both sides.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+/// Socket shared by every user of the machine.
 pub const PRISM_SOCKET_PATH: &str = "/tmp/prismd.sock";
+/// Environment variable naming the socket to use instead of the defaults.
+pub const SOCKET_PATH_ENV: &str = "PRISM_SOCKET";
+
+/// Who may connect to a socket prismd listens on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketAccess {
+    /// Only the user prismd runs as (per-user sockets).
+    Owner,
+    /// The owner and its group, e.g. admin users reaching a shared daemon.
+    Group,
+}
+
+impl SocketAccess {
+    pub fn mode(self) -> u32 {
+        match self {
+            SocketAccess::Owner => 0o600,
+            SocketAccess::Group => 0o660,
+        }
+    }
+}
+
+/// A socket for prismd to listen on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SocketSpec {
+    pub path: PathBuf,
+    pub access: SocketAccess,
+}
+
+pub fn user_socket_path(uid: u32) -> PathBuf {
+    PathBuf::from(format!("/tmp/prismd-{}.sock", uid))
+}
+
+fn current_uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+fn env_socket_path() -> Option<PathBuf> {
+    std::env::var_os(SOCKET_PATH_ENV)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Sockets prismd listens on: every `explicit` path (--socket), else PRISM_SOCKET,
+/// else the shared socket, or the caller's own socket when `per_user`. Per-user
+/// daemons keep their sockets owner-only.
+pub fn listen_sockets(explicit: &[PathBuf], per_user: bool) -> Vec<SocketSpec> {
+    resolve_listen_sockets(explicit, env_socket_path(), per_user, current_uid())
+}
+
+fn resolve_listen_sockets(
+    explicit: &[PathBuf],
+    env: Option<PathBuf>,
+    per_user: bool,
+    uid: u32,
+) -> Vec<SocketSpec> {
+    let access = if per_user {
+        SocketAccess::Owner
+    } else {
+        SocketAccess::Group
+    };
+    let paths = if !explicit.is_empty() {
+        explicit.to_vec()
+    } else if let Some(path) = env {
+        vec![path]
+    } else if per_user {
+        vec![user_socket_path(uid)]
+    } else {
+        vec![PathBuf::from(PRISM_SOCKET_PATH)]
+    };
+    paths
+        .into_iter()
+        .map(|path| SocketSpec { path, access })
+        .collect()
+}
+
+/// Sockets a client tries, in order: `explicit` (--socket) or PRISM_SOCKET alone,
+/// otherwise the caller's per-user socket and then the shared one.
+pub fn client_sockets(explicit: Option<&Path>) -> Vec<PathBuf> {
+    resolve_client_sockets(explicit, env_socket_path(), current_uid())
+}
+
+fn resolve_client_sockets(explicit: Option<&Path>, env: Option<PathBuf>, uid: u32) -> Vec<PathBuf> {
+    if let Some(path) = explicit {
+        return vec![path.to_path_buf()];
+    }
+    if let Some(path) = env {
+        return vec![path];
+    }
+    vec![user_socket_path(uid), PathBuf::from(PRISM_SOCKET_PATH)]
+}
+
+/// Refuse a per-user socket someone else planted. /tmp is shared, so another user can
+/// create /tmp/prismd-<uid>.sock first: prismd would then fail to replace it and clients
+/// would talk to the impostor. The path must be absent, or a socket (not a symlink to
+/// one) owned by the caller.
+pub fn check_socket_owner(path: &Path) -> io::Result<()> {
+    verify_owner(path, current_uid())
+}
+
+fn verify_owner(path: &Path, uid: u32) -> io::Result<()> {
+    // lstat, so a symlink is judged itself rather than by what it points at
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not a socket", path.display()),
+        ));
+    }
+    if metadata.uid() != uid {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is owned by uid {}, not {}",
+                path.display(),
+                metadata.uid(),
+                uid
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Check a socket a client is about to connect to. Only the caller's per-user socket is
+/// checked: the shared one belongs to whoever runs the shared daemon, and a path given
+/// explicitly is trusted as given.
+pub fn check_client_socket(path: &Path) -> io::Result<()> {
+    let uid = current_uid();
+    if path == user_socket_path(uid) {
+        verify_owner(path, uid)
+    } else {
+        Ok(())
+    }
+}
+
+// A client may send this line before its command to negotiate compression:
+//   PRISM/1 accept-encoding=zstd,deflate
+// prismd then prefixes its response with one header line naming the encoding used:
+//   PRISM/1 encoding=zstd
+// Without the hello line requests and responses are plain JSON, as before.
+pub const PROTOCOL_VERSION: &str = "PRISM/1";
+
+// Responses smaller than this are sent uncompressed; polling meters stays cheap.
+const COMPRESSION_MIN_BYTES: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Identity,
+    Zstd,
+    Deflate,
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Identity => "identity",
+            Encoding::Zstd => "zstd",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "identity" => Some(Encoding::Identity),
+            "zstd" => Some(Encoding::Zstd),
+            "deflate" => Some(Encoding::Deflate),
+            _ => None,
+        }
+    }
+}
+
+pub fn hello_line(accept: &[Encoding]) -> String {
+    let names: Vec<&str> = accept.iter().map(|encoding| encoding.name()).collect();
+    format!("{} accept-encoding={}\n", PROTOCOL_VERSION, names.join(","))
+}
+
+/// Encodings offered by a hello line, in the client's order of preference, or None
+/// if `line` is not a hello (i.e. it is already the command).
+pub fn parse_hello(line: &str) -> Option<Vec<Encoding>> {
+    let rest = line.strip_prefix(PROTOCOL_VERSION)?;
+    let accept = rest
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("accept-encoding="))
+        .unwrap_or("");
+    Some(accept.split(',').filter_map(Encoding::parse).collect())
+}
+
+/// Header line plus `body`, compressed with `encoding` unless the body is small.
+pub fn frame_response(encoding: Encoding, body: &[u8]) -> io::Result<Vec<u8>> {
+    let encoding = if body.len() < COMPRESSION_MIN_BYTES {
+        Encoding::Identity
+    } else {
+        encoding
+    };
+    let mut framed = format!("{} encoding={}\n", PROTOCOL_VERSION, encoding.name()).into_bytes();
+    match encoding {
+        Encoding::Identity => framed.extend_from_slice(body),
+        // Level 1: these are short-lived local transfers, speed matters more than ratio
+        Encoding::Zstd => framed.extend(zstd::bulk::compress(body, 1)?),
+        Encoding::Deflate => {
+            let mut encoder =
+                flate2::write::DeflateEncoder::new(framed, flate2::Compression::fast());
+            encoder.write_all(body)?;
+            framed = encoder.finish()?;
+        }
+    }
+    Ok(framed)
+}
+
+/// Decode a response framed by `frame_response`. Returns None if `raw` has no header,
+/// i.e. the daemon predates the handshake.
+pub fn unframe_response(raw: &[u8]) -> Option<io::Result<Vec<u8>>> {
+    let newline = raw.iter().position(|&b| b == b'\n')?;
+    let header = std::str::from_utf8(&raw[..newline]).ok()?;
+    let rest = header.strip_prefix(PROTOCOL_VERSION)?;
+    let name = rest
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("encoding="))?;
+    let body = &raw[newline + 1..];
+
+    let decoded = match Encoding::parse(name) {
+        Some(Encoding::Identity) => Ok(body.to_vec()),
+        Some(Encoding::Zstd) => zstd::stream::decode_all(body),
+        Some(Encoding::Deflate) => {
+            let mut out = Vec::new();
+            flate2::read::DeflateDecoder::new(body)
+                .read_to_end(&mut out)
+                .map(|_| out)
+        }
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unknown response encoding '{}'", name),
+        )),
+    };
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // A fresh directory under the system temp dir, removed by the caller's test.
+    fn scratch_dir() -> PathBuf {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "prism-socket-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn listen_defaults_to_shared_socket() {
+        let specs = resolve_listen_sockets(&[], None, false, 501);
+        assert_eq!(
+            specs,
+            vec![SocketSpec {
+                path: PathBuf::from(PRISM_SOCKET_PATH),
+                access: SocketAccess::Group,
+            }]
+        );
+    }
+
+    #[test]
+    fn per_user_listens_owner_only() {
+        let specs = resolve_listen_sockets(&[], None, true, 501);
+        assert_eq!(
+            specs,
+            vec![SocketSpec {
+                path: PathBuf::from("/tmp/prismd-501.sock"),
+                access: SocketAccess::Owner,
+            }]
+        );
+        assert_eq!(specs[0].access.mode(), 0o600);
+    }
+
+    #[test]
+    fn explicit_sockets_win_over_env() {
+        let explicit = [PathBuf::from("/a.sock"), PathBuf::from("/b.sock")];
+        let specs = resolve_listen_sockets(&explicit, Some(PathBuf::from("/env.sock")), false, 0);
+        let paths: Vec<&Path> = specs.iter().map(|spec| spec.path.as_path()).collect();
+        assert_eq!(paths, [Path::new("/a.sock"), Path::new("/b.sock")]);
+
+        let specs = resolve_listen_sockets(&[], Some(PathBuf::from("/env.sock")), true, 0);
+        assert_eq!(specs[0].path, PathBuf::from("/env.sock"));
+    }
+
+    #[test]
+    fn clients_try_user_socket_then_shared() {
+        assert_eq!(
+            resolve_client_sockets(None, None, 501),
+            [
+                PathBuf::from("/tmp/prismd-501.sock"),
+                PathBuf::from(PRISM_SOCKET_PATH)
+            ]
+        );
+        assert_eq!(
+            resolve_client_sockets(None, Some(PathBuf::from("/env.sock")), 501),
+            [PathBuf::from("/env.sock")]
+        );
+        assert_eq!(
+            resolve_client_sockets(
+                Some(Path::new("/given.sock")),
+                Some(PathBuf::from("/env.sock")),
+                501
+            ),
+            [PathBuf::from("/given.sock")]
+        );
+    }
+
+    #[test]
+    fn missing_socket_passes_owner_check() {
+        let dir = scratch_dir();
+        assert!(verify_owner(&dir.join("absent.sock"), current_uid()).is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn owner_check_accepts_own_socket_only() {
+        let dir = scratch_dir();
+        let path = dir.join("prismd.sock");
+        let _listener = UnixListener::bind(&path).unwrap();
+        let uid = current_uid();
+        assert!(verify_owner(&path, uid).is_ok());
+        let err = verify_owner(&path, uid.wrapping_add(1)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn owner_check_refuses_symlinks_and_files() {
+        let dir = scratch_dir();
+        let target = dir.join("prismd.sock");
+        let _listener = UnixListener::bind(&target).unwrap();
+        let link = dir.join("link.sock");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        assert!(verify_owner(&link, current_uid()).is_err());
+
+        let file = dir.join("file.sock");
+        fs::write(&file, b"").unwrap();
+        assert!(verify_owner(&file, current_uid()).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn client_check_ignores_other_paths() {
+        let dir = scratch_dir();
+        let file = dir.join("not-a-socket");
+        fs::write(&file, b"").unwrap();
+        assert!(check_client_socket(&file).is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,366 @@
+//! Shared-memory telemetry region.
+//!
+//! The driver creates a POSIX shared memory object, advertises its name through the
+//! 'tlmy' custom property and updates it lock-free from the IO path. prismd maps it
+//! read-only and samples it at display rate instead of polling HAL properties.
+
+use std::ffi::{CStr, CString};
+use std::ptr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+#[cfg(not(test))]
+pub const TELEMETRY_SHM_NAME: &str = "/prism.telemetry";
+// Unit tests create drivers too; they must not reset the region of one that is running
+#[cfg(test)]
+pub const TELEMETRY_SHM_NAME: &str = "/prism.telemetry.test";
+pub const TELEMETRY_MAGIC: u32 = 0x5052534D; // 'PRSM'
+pub const TELEMETRY_VERSION: u32 = 1;
+pub const TELEMETRY_MAX_PAIRS: usize = 32;
+
+// Per-cycle decay applied to the held peak before the next block is folded in.
+const PEAK_DECAY: f32 = 0.93;
+
+#[repr(C)]
+pub struct TelemetryBus {
+    pub peak: [AtomicU32; 2],        // f32 bits, decaying peak hold
+    pub rms: [AtomicU32; 2],         // f32 bits, RMS of the last block
+    pub last_sample_time: AtomicU64, // f64 bits, end of the last block written
+    pub frames: AtomicU64,
+}
+
+#[repr(C)]
+pub struct TelemetryRegion {
+    pub magic: u32,
+    pub version: u32,
+    pub pair_count: u32,
+    pub sample_rate: u32,
+    pub sequence: AtomicU64, // Bumped after every update
+    pub cycles: AtomicU64,   // ReadInput cycles
+    pub last_host_time: AtomicU64,
+    pub last_input_sample_time: AtomicU64,  // f64 bits
+    pub last_output_sample_time: AtomicU64, // f64 bits
+    pub buses: [TelemetryBus; TELEMETRY_MAX_PAIRS],
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BusTelemetry {
+    pub pair: u32,
+    pub peak: [f32; 2],
+    pub rms: [f32; 2],
+    pub last_sample_time: f64,
+    pub frames: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TelemetrySnapshot {
+    pub sequence: u64,
+    pub cycles: u64,
+    pub sample_rate: u32,
+    pub last_host_time: u64,
+    pub last_input_sample_time: f64,
+    pub last_output_sample_time: f64,
+    pub buses: Vec<BusTelemetry>,
+}
+
+impl TelemetryRegion {
+    pub const SIZE: usize = std::mem::size_of::<TelemetryRegion>();
+
+    /// Create (or reuse) the shared memory object and map it read-write.
+    /// Called once by the driver at creation; returns None if the sandbox or
+    /// the system refuses the mapping.
+    pub fn create(pair_count: u32, sample_rate: u32) -> Option<&'static TelemetryRegion> {
+        Self::create_named(TELEMETRY_SHM_NAME, pair_count, sample_rate)
+    }
+
+    fn create_named(
+        name: &str,
+        pair_count: u32,
+        sample_rate: u32,
+    ) -> Option<&'static TelemetryRegion> {
+        let name = CString::new(name).ok()?;
+        unsafe {
+            let fd = Self::open_sized(&name)?;
+            let addr = libc::mmap(
+                ptr::null_mut(),
+                Self::SIZE,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            );
+            libc::close(fd);
+            if addr == libc::MAP_FAILED {
+                return None;
+            }
+
+            ptr::write_bytes(addr as *mut u8, 0, Self::SIZE);
+            let region = addr as *mut TelemetryRegion;
+            (*region).version = TELEMETRY_VERSION;
+            (*region).pair_count = pair_count.min(TELEMETRY_MAX_PAIRS as u32);
+            (*region).sample_rate = sample_rate;
+            // Publish the magic last so readers never see a half-initialised header
+            std::sync::atomic::fence(Ordering::Release);
+            (*region).magic = TELEMETRY_MAGIC;
+            Some(&*region)
+        }
+    }
+
+    // Open the object read-write, at least Self::SIZE bytes long. Touching a mapping past
+    // the end of the object faults, so one that is too small (left by a build with
+    // another layout, or created empty by someone else) is unlinked and created afresh.
+    unsafe fn open_sized(name: &CStr) -> Option<libc::c_int> {
+        for flags in [libc::O_CREAT, libc::O_CREAT | libc::O_EXCL] {
+            let fd = libc::shm_open(name.as_ptr(), flags | libc::O_RDWR, 0o644 as libc::c_uint);
+            if fd < 0 {
+                return None;
+            }
+            // ftruncate fails on an already sized object on macOS; the size check decides
+            let _ = libc::ftruncate(fd, Self::SIZE as libc::off_t);
+            if object_size(fd).is_some_and(|size| size >= Self::SIZE) {
+                return Some(fd);
+            }
+            libc::close(fd);
+            libc::shm_unlink(name.as_ptr());
+        }
+        None
+    }
+
+    /// Map an existing region read-only (prismd side).
+    pub fn open_readonly(name: &str) -> Result<&'static TelemetryRegion, String> {
+        let c_name = CString::new(name).map_err(|err| err.to_string())?;
+        unsafe {
+            let fd = libc::shm_open(c_name.as_ptr(), libc::O_RDONLY, 0 as libc::c_uint);
+            if fd < 0 {
+                return Err(format!(
+                    "shm_open({}) failed: {}",
+                    name,
+                    std::io::Error::last_os_error()
+                ));
+            }
+            // Reading past the end of a shorter object would fault instead of failing
+            if object_size(fd).is_none_or(|size| size < Self::SIZE) {
+                libc::close(fd);
+                return Err(format!("{} is not a Prism telemetry region", name));
+            }
+            let addr = libc::mmap(
+                ptr::null_mut(),
+                Self::SIZE,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            );
+            libc::close(fd);
+            if addr == libc::MAP_FAILED {
+                return Err(format!(
+                    "mmap({}) failed: {}",
+                    name,
+                    std::io::Error::last_os_error()
+                ));
+            }
+
+            let region = &*(addr as *const TelemetryRegion);
+            if region.magic != TELEMETRY_MAGIC || region.version != TELEMETRY_VERSION {
+                libc::munmap(addr, Self::SIZE);
+                return Err(format!("{} is not a Prism telemetry region", name));
+            }
+            Ok(region)
+        }
+    }
+
+    /// Unmap a region obtained from `create` or `open_readonly`. The shared memory
+    /// object itself stays so that readers and a reloaded driver can keep using it.
+    ///
+    /// # Safety
+    /// No reference to the region may be used afterwards.
+    pub unsafe fn unmap(&'static self) {
+        libc::munmap(self as *const Self as *mut libc::c_void, Self::SIZE);
+    }
+
+    /// Fold one written block into a bus entry. `peak` and `rms` are linear levels
+    /// of the block; `end_sample_time` is the sample time just past its last frame.
+    #[inline]
+    pub fn update_bus(
+        &self,
+        pair: usize,
+        peak: [f32; 2],
+        rms: [f32; 2],
+        end_sample_time: f64,
+        frames: usize,
+    ) {
+        let Some(bus) = self.buses.get(pair) else {
+            return;
+        };
+        for ch in 0..2 {
+            let held = f32::from_bits(bus.peak[ch].load(Ordering::Relaxed)) * PEAK_DECAY;
+            bus.peak[ch].store(held.max(peak[ch]).to_bits(), Ordering::Relaxed);
+            bus.rms[ch].store(rms[ch].to_bits(), Ordering::Relaxed);
+        }
+        bus.last_sample_time
+            .store(end_sample_time.to_bits(), Ordering::Relaxed);
+        bus.frames.fetch_add(frames as u64, Ordering::Relaxed);
+        self.last_output_sample_time
+            .store(end_sample_time.to_bits(), Ordering::Relaxed);
+        self.sequence.fetch_add(1, Ordering::Release);
+    }
+
+    /// Record a served capture cycle.
+    #[inline]
+    pub fn record_input_cycle(&self, host_time: u64, sample_time: f64) {
+        self.last_host_time.store(host_time, Ordering::Relaxed);
+        self.last_input_sample_time
+            .store(sample_time.to_bits(), Ordering::Relaxed);
+        self.cycles.fetch_add(1, Ordering::Relaxed);
+        self.sequence.fetch_add(1, Ordering::Release);
+    }
+
+    pub fn snapshot(&self) -> TelemetrySnapshot {
+        let sequence = self.sequence.load(Ordering::Acquire);
+        let pair_count = (self.pair_count as usize).min(TELEMETRY_MAX_PAIRS);
+        let buses = self.buses[..pair_count]
+            .iter()
+            .enumerate()
+            .map(|(pair, bus)| BusTelemetry {
+                pair: pair as u32,
+                peak: [0, 1].map(|ch| f32::from_bits(bus.peak[ch].load(Ordering::Relaxed))),
+                rms: [0, 1].map(|ch| f32::from_bits(bus.rms[ch].load(Ordering::Relaxed))),
+                last_sample_time: f64::from_bits(bus.last_sample_time.load(Ordering::Relaxed)),
+                frames: bus.frames.load(Ordering::Relaxed),
+            })
+            .collect();
+
+        TelemetrySnapshot {
+            sequence,
+            cycles: self.cycles.load(Ordering::Relaxed),
+            sample_rate: self.sample_rate,
+            last_host_time: self.last_host_time.load(Ordering::Relaxed),
+            last_input_sample_time: f64::from_bits(
+                self.last_input_sample_time.load(Ordering::Relaxed),
+            ),
+            last_output_sample_time: f64::from_bits(
+                self.last_output_sample_time.load(Ordering::Relaxed),
+            ),
+            buses,
+        }
+    }
+}
+
+// Size in bytes of the shared memory object behind `fd`.
+unsafe fn object_size(fd: libc::c_int) -> Option<usize> {
+    let mut stat: libc::stat = std::mem::zeroed();
+    if libc::fstat(fd, &mut stat) != 0 {
+        return None;
+    }
+    usize::try_from(stat.st_size).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A name of the test's own, removed again when the test ends
+    struct ShmName(String);
+
+    impl ShmName {
+        fn new(test: &str) -> Self {
+            ShmName(format!("/prism.t.{}.{}", std::process::id(), test))
+        }
+    }
+
+    impl Drop for ShmName {
+        fn drop(&mut self) {
+            let name = CString::new(self.0.as_str()).unwrap();
+            unsafe {
+                libc::shm_unlink(name.as_ptr());
+            }
+        }
+    }
+
+    #[test]
+    fn reader_sees_writer_updates() {
+        let name = ShmName::new("rw");
+        let writer = TelemetryRegion::create_named(&name.0, 4, 48000).unwrap();
+        let reader = TelemetryRegion::open_readonly(&name.0).unwrap();
+        assert_eq!(reader.pair_count, 4);
+        assert_eq!(reader.sample_rate, 48000);
+
+        writer.update_bus(1, [0.5, 0.25], [0.1, 0.2], 512.0, 512);
+        writer.record_input_cycle(1000, 256.0);
+        let snapshot = reader.snapshot();
+        assert_eq!(snapshot.buses.len(), 4);
+        assert_eq!(snapshot.buses[1].peak, [0.5, 0.25]);
+        assert_eq!(snapshot.buses[1].frames, 512);
+        assert_eq!(snapshot.cycles, 1);
+        assert_eq!(snapshot.last_input_sample_time, 256.0);
+        assert_eq!(snapshot.sequence, 2);
+
+        unsafe {
+            reader.unmap();
+            writer.unmap();
+        }
+    }
+
+    #[test]
+    fn create_resets_the_region_and_clamps_pairs() {
+        let name = ShmName::new("reset");
+        let first = TelemetryRegion::create_named(&name.0, 2, 48000).unwrap();
+        first.update_bus(0, [1.0, 1.0], [1.0, 1.0], 64.0, 64);
+        unsafe {
+            first.unmap();
+        }
+
+        let second = TelemetryRegion::create_named(&name.0, 1000, 44100).unwrap();
+        let snapshot = second.snapshot();
+        assert_eq!(snapshot.sequence, 0);
+        assert_eq!(snapshot.sample_rate, 44100);
+        assert_eq!(snapshot.buses.len(), TELEMETRY_MAX_PAIRS);
+        assert!(snapshot.buses.iter().all(|bus| bus.frames == 0));
+        unsafe {
+            second.unmap();
+        }
+    }
+
+    #[test]
+    fn open_readonly_rejects_foreign_objects() {
+        let name = ShmName::new("foreign");
+        let c_name = CString::new(name.0.as_str()).unwrap();
+        unsafe {
+            let fd = libc::shm_open(
+                c_name.as_ptr(),
+                libc::O_CREAT | libc::O_RDWR,
+                0o600 as libc::c_uint,
+            );
+            assert!(fd >= 0);
+            assert_eq!(libc::ftruncate(fd, TelemetryRegion::SIZE as libc::off_t), 0);
+            libc::close(fd);
+        }
+        assert!(TelemetryRegion::open_readonly(&name.0).is_err());
+        assert!(TelemetryRegion::open_readonly("/prism.t.missing").is_err());
+    }
+
+    #[test]
+    fn undersized_objects_are_rejected_or_replaced() {
+        let name = ShmName::new("short");
+        let c_name = CString::new(name.0.as_str()).unwrap();
+        unsafe {
+            let fd = libc::shm_open(
+                c_name.as_ptr(),
+                libc::O_CREAT | libc::O_RDWR,
+                0o600 as libc::c_uint,
+            );
+            assert!(fd >= 0);
+            libc::close(fd);
+        }
+        // An empty object must not be mapped as if it held a region
+        assert!(TelemetryRegion::open_readonly(&name.0).is_err());
+
+        let writer = TelemetryRegion::create_named(&name.0, 2, 48000).unwrap();
+        writer.update_bus(1, [0.5, 0.5], [0.1, 0.1], 64.0, 64);
+        let reader = TelemetryRegion::open_readonly(&name.0).unwrap();
+        assert_eq!(reader.snapshot().buses[1].frames, 64);
+        unsafe {
+            reader.unmap();
+            writer.unmap();
+        }
+    }
+}